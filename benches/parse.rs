@@ -0,0 +1,141 @@
+// Benchmarks of a generated parser, over long streams of tokens.  Run them with "cargo bench".
+//
+// Every reduction goes through the generated reduce function, which dispatches on the rule with
+// a single match whose arms contain the actions of the grammar.  The parser calls reduce through
+// the function pointer in ParserTables, so the call itself is not inlined into the parser.  The
+// push_* benchmarks measure the throughput of that, together with the table lookups of the
+// parser.  The table_push_* benchmarks parse the same tokens with the same tables, but their
+// reduce function calls through a table of pointers to one function for each rule; the
+// difference between the two is the cost of that second indirect call.
+
+#![feature(phase)]
+
+extern crate test;
+#[phase(plugin, link)] extern crate racc;
+
+use std::any::Any;
+use test::Bencher;
+use racc::runtime::{ParserState, ParserTables, Location, PushTokenResult, FinishParseResult};
+
+grammar! {
+    uint ctx;
+    i64;
+
+    %left PLUS MINUS;
+    %left TIMES;
+
+    NUM; LPAREN; RPAREN;
+
+    Expr : Expr=a PLUS Expr=b { a + b }
+         | Expr=a MINUS Expr=b { a - b }
+         | Expr=a TIMES Expr=b { a * b }
+         | LPAREN Expr=a RPAREN { a }
+         | NUM=n { n };
+}
+
+// Returns the tokens of "(1 + 2 * 3) - 4 + ..." with `n` groups.
+fn make_tokens(n: uint) -> Vec<(u32, i64)> {
+    let mut tokens: Vec<(u32, i64)> = Vec::with_capacity(n * 9);
+    for i in range(0, n) {
+        if i != 0 {
+            tokens.push((PLUS, 0));
+        }
+        tokens.push_all(&[(LPAREN, 0), (NUM, 1), (PLUS, 0), (NUM, 2), (TIMES, 0), (NUM, 3),
+                          (RPAREN, 0), (MINUS, 0), (NUM, 4)]);
+    }
+    tokens
+}
+
+// The value of the tokens of make_tokens(n).
+fn expected_value(n: uint) -> i64 {
+    3 * (n as i64)
+}
+
+// The actions of the rules of Expr, in order, as functions of their own.  Each pops the values of
+// its right-hand side, as the arms of the generated reduce function do.
+fn reduce_plus(value_stack: &mut Vec<i64>) -> i64 {
+    let b = value_stack.pop().unwrap();
+    drop(value_stack.pop());
+    let a = value_stack.pop().unwrap();
+    a + b
+}
+
+fn reduce_minus(value_stack: &mut Vec<i64>) -> i64 {
+    let b = value_stack.pop().unwrap();
+    drop(value_stack.pop());
+    let a = value_stack.pop().unwrap();
+    a - b
+}
+
+fn reduce_times(value_stack: &mut Vec<i64>) -> i64 {
+    let b = value_stack.pop().unwrap();
+    drop(value_stack.pop());
+    let a = value_stack.pop().unwrap();
+    a * b
+}
+
+fn reduce_parens(value_stack: &mut Vec<i64>) -> i64 {
+    drop(value_stack.pop());
+    let a = value_stack.pop().unwrap();
+    drop(value_stack.pop());
+    a
+}
+
+fn reduce_num(value_stack: &mut Vec<i64>) -> i64 {
+    value_stack.pop().unwrap()
+}
+
+// Indexed by the reduction number that the parser passes to reduce, minus one.
+static RULE_FNS: [fn(&mut Vec<i64>) -> i64, ..5] = [reduce_plus, reduce_minus, reduce_times, reduce_parens, reduce_num];
+
+fn reduce_by_table(value_stack: &mut Vec<i64>, _yylocs: &[Location], _yyloc: Location, reduction: uint, _ctx: &mut uint)
+    -> Result<i64, Box<Any + 'static>> {
+    Ok((RULE_FNS[reduction - 1])(value_stack))
+}
+
+fn bench_push(b: &mut Bencher, tables: ParserTables<i64, uint>, n: uint) {
+    let tokens = make_tokens(n);
+    let mut parser = ParserState::new(tables);
+    let mut ctx = 0u;
+    b.bytes = tokens.len() as u64;
+    b.iter(|| {
+        parser.reset();
+        for &(token, value) in tokens.iter() {
+            match parser.push_token(&mut ctx, token, value) {
+                PushTokenResult::Ok => {}
+                result => panic!("push_token: {}", result)
+            }
+        }
+        match parser.finish(&mut ctx) {
+            FinishParseResult::Accepted(value) => assert_eq!(value, expected_value(n)),
+            FinishParseResult::SyntaxError => panic!("syntax error"),
+            FinishParseResult::ActionFailed(error) => panic!("{}", error)
+        }
+    });
+}
+
+// The generated tables, with the reductions dispatched through RULE_FNS.
+fn table_dispatch() -> ParserTables<i64, uint> {
+    ParserTables { reduce: reduce_by_table, .. get_parser_tables() }
+}
+
+// The "bytes" of these benchmarks are tokens, so MB/s is millions of tokens per second.
+#[bench]
+fn push_1k_groups(b: &mut Bencher) {
+    bench_push(b, get_parser_tables(), 1000);
+}
+
+#[bench]
+fn push_100k_groups(b: &mut Bencher) {
+    bench_push(b, get_parser_tables(), 100_000);
+}
+
+#[bench]
+fn table_push_1k_groups(b: &mut Bencher) {
+    bench_push(b, table_dispatch(), 1000);
+}
+
+#[bench]
+fn table_push_100k_groups(b: &mut Bencher) {
+    bench_push(b, table_dispatch(), 100_000);
+}
@@ -0,0 +1,87 @@
+// Benchmarks of the analysis, over the grammars in the Berkeley YACC test suite (orig/test).
+// Run them with "cargo bench" in racc-core.  To see which phase a change affects, use
+// racc_core::analyze_profiled, or set RACC_PROFILE when compiling a grammar.
+
+extern crate test;
+extern crate racc_core;
+
+use std::io::File;
+use test::Bencher;
+
+use racc_core::{Grammar, LrType, analyze, analyze_with};
+use racc_core::tables::make_tables;
+use racc_core::yacc::read_yacc;
+
+fn load(name: &str) -> Grammar {
+    let path = Path::new("../orig/test").join(name);
+    let text = File::open(&path).read_to_string().unwrap();
+    match read_yacc(text.as_slice()) {
+        Ok(y) => y.grammar,
+        Err(_) => panic!("cannot read {}", path.display())
+    }
+}
+
+// A grammar with one non-terminal for each of `levels` levels of binary operators, written out
+// as rules rather than with precedence.  With 200 levels it has tens of thousands of gotos, so
+// with the "parallel" feature the lookahead computation is split across threads; compare
+// "cargo bench operator_levels" with and without "--features parallel".
+fn operator_levels(levels: uint) -> Grammar {
+    let mut text = String::from_str("%token NUM LPAREN RPAREN\n");
+    for i in range(0, levels) {
+        text.push_str(format!("%token OP{}\n", i).as_slice());
+    }
+    text.push_str("%%\n");
+    for i in range(0, levels) {
+        text.push_str(format!("e{} : e{} OP{} e{} | e{} ;\n", i, i, i, i + 1, i + 1).as_slice());
+    }
+    text.push_str(format!("e{} : NUM | LPAREN e0 RPAREN ;\n", levels).as_slice());
+    match read_yacc(text.as_slice()) {
+        Ok(y) => y.grammar,
+        Err(_) => panic!("cannot read the operator grammar")
+    }
+}
+
+fn bench_lalr(b: &mut Bencher, name: &str) {
+    let gram = load(name);
+    b.iter(|| analyze(&gram).unwrap());
+}
+
+#[bench]
+fn calc(b: &mut Bencher) {
+    bench_lalr(b, "calc.y");
+}
+
+#[bench]
+fn quote_calc(b: &mut Bencher) {
+    bench_lalr(b, "quote_calc.y");
+}
+
+#[bench]
+fn btyacc_demo(b: &mut Bencher) {
+    bench_lalr(b, "btyacc_demo.y");
+}
+
+// The C grammar: the largest in the suite.
+#[bench]
+fn c_grammar(b: &mut Bencher) {
+    bench_lalr(b, "grammar.y");
+}
+
+#[bench]
+fn c_grammar_ielr(b: &mut Bencher) {
+    let gram = load("grammar.y");
+    b.iter(|| analyze_with(&gram, LrType::Ielr).unwrap());
+}
+
+#[bench]
+fn c_grammar_packing(b: &mut Bencher) {
+    let gram = load("grammar.y");
+    let analysis = analyze(&gram).unwrap();
+    b.iter(|| make_tables(&gram, &analysis.lalr.gotos, &analysis.parser).ok().unwrap());
+}
+
+#[bench]
+fn operator_levels_200(b: &mut Bencher) {
+    let gram = operator_levels(200);
+    b.iter(|| analyze(&gram).unwrap());
+}
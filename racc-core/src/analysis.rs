@@ -0,0 +1,89 @@
+// The item-set operations of LR(0) construction, CLOSURE and GOTO, on their own: for course
+// material which works through the construction of an automaton by hand, and for tools which
+// ask what items a set of items leads to, without building the whole automaton.
+//
+// An item is an index into Grammar.ritem: the position of the dot in the right-hand side of a
+// rule.  An item whose ritem entry is negative has the dot at the end of its rule.  These are the
+// same items that the rest of the analysis uses (see lr0.rs and closure.rs); ItemSets only
+// keeps the first_derives relation, which CLOSURE needs, so that it is computed once.
+
+use grammar::Grammar;
+use lr0::set_derives;
+use closure::{closure, set_first_derives};
+use conflicts::item_to_str;
+use util::{Bitmat, Bitv32};
+use ids::{ItemId, SymbolId};
+
+/// Computes the closures of sets of items, and the sets that they go to on a symbol.
+///
+/// ```ignore
+///     let sets = ItemSets::new(&gram);
+///     let start = sets.closure(&[sets.initial_item()]);
+///     let after_expr = sets.goto(start.as_slice(), expr);
+///     for &item in sets.closure(after_expr.as_slice()).iter() {
+///         println!("{}", sets.item_to_str(item));
+///     }
+/// ```
+pub struct ItemSets<'a> {
+    gram: &'a Grammar,
+    first_derives: Bitmat,
+}
+
+impl<'a> ItemSets<'a> {
+    pub fn new(gram: &'a Grammar) -> ItemSets<'a> {
+        let derives = set_derives(gram);
+        ItemSets {
+            gram: gram,
+            first_derives: set_first_derives(gram, &derives),
+        }
+    }
+
+    /// Returns the item `$accept : . goal $end`, the kernel of the initial state.
+    pub fn initial_item(&self) -> ItemId {
+        ItemId(self.gram.rrhs[2] as uint)       // rule 2 is $accept : goal $end
+    }
+
+    /// Returns the symbol after the dot of `item`, or None if the dot is at the end of its rule.
+    pub fn next_symbol(&self, item: ItemId) -> Option<SymbolId> {
+        match self.gram.ritem[item.index()] {
+            s if s >= 0 => Some(SymbolId(s as uint)),
+            _ => None
+        }
+    }
+
+    /// Returns CLOSURE(items): the items, and every item `B : . w` for each non-terminal B which
+    /// comes right after the dot in an item of the closure, in ascending order.
+    pub fn closure(&self, items: &[ItemId]) -> Vec<ItemId> {
+        let mut kernel: Vec<ItemId> = items.to_vec();
+        kernel.sort();
+        kernel.dedup();
+        let mut rule_set = Bitv32::from_elem(self.gram.nrules, false);
+        let mut item_set: Vec<ItemId> = Vec::new();
+        closure(self.gram, kernel.as_slice(), &self.first_derives, self.gram.nrules, &mut rule_set, &mut item_set);
+        item_set
+    }
+
+    /// Returns GOTO(items, symbol): the items of CLOSURE(items) which have `symbol` after the
+    /// dot, with the dot moved past it, in ascending order.  This is the kernel of the state
+    /// that the automaton goes to; it is empty if no item has `symbol` after the dot.
+    pub fn goto(&self, items: &[ItemId], symbol: SymbolId) -> Vec<ItemId> {
+        self.closure(items).iter()
+            .filter(|&&item| self.next_symbol(item) == Some(symbol))
+            .map(|&item| ItemId(item.index() + 1))
+            .collect()
+    }
+
+    /// Returns the symbols which some item of CLOSURE(items) has after the dot: the symbols that
+    /// the state with these items has transitions on.  They are in ascending order.
+    pub fn transition_symbols(&self, items: &[ItemId]) -> Vec<SymbolId> {
+        let mut symbols: Vec<SymbolId> = self.closure(items).iter().filter_map(|&item| self.next_symbol(item)).collect();
+        symbols.sort();
+        symbols.dedup();
+        symbols
+    }
+
+    /// Writes an item as its rule, with a dot, such as `Expr : Expr . PLUS Term`.
+    pub fn item_to_str(&self, item: ItemId) -> String {
+        item_to_str(self.gram, item)
+    }
+}
@@ -0,0 +1,145 @@
+// A read-only view of the automaton that was built for a grammar, for tools which draw, check, or
+// explain parsers.
+//
+// The structures which the analysis produces (LR0Output, LALROutput, YaccParser) are shaped for
+// building tables, and keep their data in several parallel vectors.  Automaton answers the
+// questions that a tool asks about a state, in terms of the typed ids: which items it holds,
+// where it goes on each symbol, and what the final parser does in it on each token.
+
+use grammar::Grammar;
+use mkpar::ActionCode;
+use closure::{closure, set_first_derives};
+use util::{Bitmat, Bitv32};
+use ids::{StateId, SymbolId, RuleId, ItemId};
+use export;
+use Analysis;
+
+/// What the parser does in a state when it sees a token.
+#[deriving(Copy,Clone,PartialEq,Show)]
+pub enum Action {
+    /// Shift the token, and go to the state.
+    Shift(StateId),
+    /// Reduce by the rule.
+    Reduce(RuleId),
+    /// Accept the input.  This is the action of the final state on `$end`.
+    Accept,
+}
+
+/// A transition of the automaton: the symbol (a token or a non-terminal) and the state that it
+/// leads to.
+#[deriving(Copy,Clone,PartialEq,Show)]
+pub struct Transition {
+    pub symbol: SymbolId,
+    pub target: StateId,
+}
+
+/// The automaton of a grammar, as built by `analyze` or `analyze_with`.
+pub struct Automaton<'a> {
+    gram: &'a Grammar,
+    analysis: &'a Analysis,
+    first_derives: Bitmat,
+}
+
+impl<'a> Automaton<'a> {
+    /// Creates a view of the automaton in `analysis`, which must have been built for `gram`.
+    pub fn new(gram: &'a Grammar, analysis: &'a Analysis) -> Automaton<'a> {
+        let lr0 = &analysis.lr0;
+        Automaton {
+            gram: gram,
+            analysis: analysis,
+            first_derives: set_first_derives(gram, &lr0.derives),
+        }
+    }
+
+    /// Returns the grammar of the automaton.
+    pub fn grammar(&self) -> &'a Grammar {
+        self.gram
+    }
+
+    /// Returns the number of states.  States are numbered from 0, which is the initial state.
+    pub fn nstates(&self) -> uint {
+        self.analysis.lr0.nstates()
+    }
+
+    /// Returns the state which accepts the input on `$end`.
+    pub fn final_state(&self) -> StateId {
+        StateId(self.analysis.parser.final_state)
+    }
+
+    /// Returns the symbol which is shifted to enter `state`.  For state 0, this is `$end`.
+    pub fn accessing_symbol(&self, state: StateId) -> SymbolId {
+        self.analysis.lr0.states[state.index()].accessing_symbol
+    }
+
+    /// Returns the kernel items of `state`, in ascending order.
+    pub fn kernel(&self, state: StateId) -> &'a [ItemId] {
+        self.analysis.lr0.states[state.index()].items.as_slice()
+    }
+
+    /// Returns all of the items of `state`: its kernel items and the items added by closure, in
+    /// ascending order.
+    pub fn items(&self, state: StateId) -> Vec<ItemId> {
+        let mut rule_set = Bitv32::from_elem(self.gram.nrules, false);
+        let mut item_set: Vec<ItemId> = Vec::new();
+        closure(self.gram, self.kernel(state), &self.first_derives, self.gram.nrules, &mut rule_set, &mut item_set);
+        item_set
+    }
+
+    /// Returns the transitions out of `state`, on tokens and on non-terminals, in ascending order
+    /// of symbol.
+    pub fn transitions(&self, state: StateId) -> Vec<Transition> {
+        self.analysis.lr0.shifts_of(state).iter().map(|&target| Transition {
+            symbol: self.accessing_symbol(target),
+            target: target,
+        }).collect()
+    }
+
+    /// Returns the state that `state` goes to on `symbol`, if any.
+    pub fn transition(&self, state: StateId, symbol: SymbolId) -> Option<StateId> {
+        self.analysis.lr0.shift_target(state, symbol)
+    }
+
+    /// Returns the gotos (the transitions on non-terminals) out of `state`, in ascending order of
+    /// symbol.
+    pub fn gotos(&self, state: StateId) -> Vec<Transition> {
+        self.transitions(state).into_iter().filter(|t| self.gram.is_var(t.symbol.index())).collect()
+    }
+
+    /// Returns the rules which can be reduced in `state`, each with its lookahead set: the tokens
+    /// for which the reduction applies, in ascending order.
+    pub fn lookaheads(&self, state: StateId) -> Vec<(RuleId, Vec<SymbolId>)> {
+        self.analysis.lalr.lookaheads_of(self.gram, state)
+    }
+
+    /// Returns the actions of the final parser in `state`, in ascending order of token.  Actions
+    /// which lost a conflict, or which were removed by precedence, are not included; the default
+    /// reduction is not included either (see `default_reduction`).
+    pub fn actions(&self, state: StateId) -> Vec<(SymbolId, Action)> {
+        let mut actions: Vec<(SymbolId, Action)> = Vec::new();
+        if state.index() == self.analysis.parser.final_state {
+            actions.push((SymbolId(0), Action::Accept));
+        }
+        for p in self.analysis.parser.actions_of(state).iter() {
+            if !p.is_active() {
+                continue;
+            }
+            let action = match p.action_code {
+                ActionCode::Shift => Action::Shift(StateId::from_i32(p.number)),
+                ActionCode::Reduce => Action::Reduce(RuleId::from_i32(p.number))
+            };
+            actions.push((SymbolId::from_i16(p.symbol), action));
+        }
+        actions
+    }
+
+    /// Returns the rule that `state` reduces on any token for which it has no action, if any.
+    pub fn default_reduction(&self, state: StateId) -> Option<RuleId> {
+        self.analysis.parser.default_reduction(state)
+    }
+
+    /// Returns the automaton as JSON: its states, with their items, transitions, and actions, and
+    /// its conflicts.  The schema is described in `export`.
+    pub fn to_json(&self) -> String {
+        export::automaton_to_json(self.gram, self.analysis)
+    }
+}
@@ -0,0 +1,743 @@
+// Builds a Grammar from a description given by method calls, rather than from the body of a
+// grammar! macro invocation.  The macro front end (reader.rs) is itself a client of this
+// builder; it is responsible only for parsing Rust tokens and for reporting errors with spans.
+//
+// The builder collects symbols by name, in the order that they are first mentioned, and rules
+// in the order that they are added.  Nothing is checked until build() is called.  build() then
+// "packs" the grammar into the form used by the analysis code: tokens first, then variables,
+// with the three predefined rules at the start of the rule table, and the right-hand sides of
+// all rules stored in the single ritem table.  See Grammar for a description of that form.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use grammar::{Grammar, TOKEN, LEFT, RIGHT, NONASSOC};
+
+#[deriving(Copy,PartialEq,Show)]
+enum SymClass {
+    Unknown,
+    Terminal,
+    NonTerminal
+}
+
+struct BuilderSymbol {
+    name: String,
+    is_token: bool,     // declared with token() or precedence()
+    is_lhs: bool,       // appears on the left-hand side of at least one rule
+    prec: i16,          // precedence level, or 0 if none was declared
+    assoc: u8,          // associativity (see grammar.rs), or TOKEN if none was declared
+    alias: Option<String>,  // the literal text of the token, given by alias()
+    value: Option<i16>,     // the value of the token, given by token_value()
+}
+
+/// The associativity of the tokens in a precedence declaration.
+#[deriving(Copy,Clone,PartialEq,Show)]
+pub enum Assoc {
+    /// `%left`: in `a OP b OP c`, the first `OP` is reduced first.
+    Left,
+    /// `%right`: in `a OP b OP c`, the second `OP` is reduced first.
+    Right,
+    /// `%nonassoc`: `a OP b OP c` is a syntax error.
+    NonAssoc,
+}
+
+/// How `%resolve` settles a conflict between reducing by a rule and another action on a token.
+#[deriving(Copy,Clone,PartialEq,Show)]
+pub enum Resolution {
+    /// `%resolve shift`: shift the token, rather than reduce by the rule.
+    Shift,
+    /// `%resolve reduce`: reduce by the rule, rather than shift the token or reduce by another
+    /// rule.
+    Reduce,
+}
+
+/// One side of a `%prefer` policy: shifting, or reducing by any rule of a non-terminal.  In a
+/// `Grammar`, the non-terminal is its symbol index; in a `GrammarBuilder`, its name was given.
+#[deriving(Copy,Clone,PartialEq,Show)]
+pub enum Choice {
+    Shift,
+    Reduce(uint),
+}
+
+struct BuilderRule {
+    lhs: uint,          // index into GrammarBuilder.symbols
+    rhs: Vec<uint>,     // indices into GrammarBuilder.symbols
+    prec: Option<uint>, // the token given by prec(), if any; index into GrammarBuilder.symbols
+    resolve: Vec<(uint, Resolution)>,   // the tokens given by resolve(); indices into GrammarBuilder.symbols
+}
+
+/// An error found while building a grammar.
+#[deriving(Clone,PartialEq,Show)]
+pub enum GrammarError {
+    /// The grammar does not contain any rules.
+    NoRules,
+    /// A symbol was used in a rule, but was never declared as a token and never appears on the
+    /// left-hand side of a rule.
+    UndefinedSymbol(String),
+    /// A name was declared as a token, but also appears on the left-hand side of a rule.
+    TokenOnLeftSide(String),
+    /// A token was declared more than once.
+    TokenRedefined(String),
+    /// The start symbol is not a non-terminal.
+    BadStartSymbol(String),
+    /// A token was given a precedence more than once.
+    PrecedenceRedefined(String),
+    /// A token was given a value which is reserved (0, for the end of the input, or 256, for
+    /// `error`), or which is negative.
+    BadTokenValue(String, i16),
+    /// Two tokens were given the same value.
+    TokenValueReused(String, String, i16),
+    /// A rule was given a resolution, by `resolve`, for a symbol which is not a token.
+    ResolveNonToken(String),
+    /// A preference, given by `prefer`, names a symbol which is not a non-terminal, or prefers a
+    /// non-terminal over itself.
+    BadPreference(String),
+}
+
+impl fmt::Show for GrammarError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            GrammarError::NoRules => write!(f, "grammar does not define any rules"),
+            GrammarError::UndefinedSymbol(ref name) => write!(f, "symbol '{}' was used but never defined", name),
+            GrammarError::TokenOnLeftSide(ref name) => write!(f, "'{}' has been defined as a token, and so cannot be on the left-hand side of a rule", name),
+            GrammarError::TokenRedefined(ref name) => write!(f, "token '{}' is defined more than once", name),
+            GrammarError::BadStartSymbol(ref name) => write!(f, "start symbol '{}' is not a non-terminal", name),
+            GrammarError::PrecedenceRedefined(ref name) => write!(f, "token '{}' is given a precedence more than once", name),
+            GrammarError::BadTokenValue(ref name, value) => write!(f, "token '{}' cannot have the value {}; 0 and 256 are reserved, and values cannot be negative", name, value),
+            GrammarError::TokenValueReused(ref first, ref second, value) => write!(f, "tokens '{}' and '{}' both have the value {}", first, second, value),
+            GrammarError::ResolveNonToken(ref name) => write!(f, "'{}' is not a token, so a rule cannot be given a resolution for it", name),
+            GrammarError::BadPreference(ref name) => write!(f, "a preference cannot name '{}'; it must name a non-terminal, and prefer it over something else", name),
+        }
+    }
+}
+
+/// Constructs a `Grammar` programmatically.
+///
+/// ```ignore
+///     let gram = Grammar::builder()
+///         .token("NUM").token("PLUS").token("TIMES")
+///         .rule("expr", &["expr", "PLUS", "term"])
+///         .rule("expr", &["term"])
+///         .rule("term", &["term", "TIMES", "NUM"])
+///         .rule("term", &["NUM"])
+///         .build()
+///         .unwrap();
+/// ```
+///
+/// Symbols are referred to by name.  Tokens must be declared with `token`; any symbol which
+/// appears on the left-hand side of a rule is a non-terminal.  The start symbol is the left-hand
+/// side of the first rule, unless `start` is called.  The well-known `error` token is always
+/// defined.
+///
+/// Rules are numbered in the order in which they are added, starting at 3.  (Rules 0 through 2
+/// are reserved for the rules that the generator adds, such as `$accept : start $end`.)
+pub struct GrammarBuilder {
+    symbols: Vec<BuilderSymbol>,
+    symbol_table: HashMap<String, uint>,
+    rules: Vec<BuilderRule>,
+    start: Option<uint>,
+    errors: Vec<GrammarError>,
+    nprec: i16,         // the number of precedence levels declared so far
+    preferences: Vec<(Choice, Choice)>,     // (winner, loser); Reduce holds an index into symbols
+}
+
+/// The number of rules which are predefined in every grammar.  The first rule added to a
+/// `GrammarBuilder` is rule number `PREDEFINED_RULES`.
+pub const PREDEFINED_RULES: uint = 3;
+
+// The value of the error token.
+const ERROR_VALUE: i16 = 256;
+
+impl GrammarBuilder {
+    pub fn new() -> GrammarBuilder {
+        let mut b = GrammarBuilder {
+            symbols: Vec::new(),
+            symbol_table: HashMap::new(),
+            rules: Vec::new(),
+            start: None,
+            errors: Vec::new(),
+            nprec: 0,
+            preferences: Vec::new(),
+        };
+        // The well-known "error" symbol is always the first token.
+        let error = b.intern("error");
+        b.symbols[error].is_token = true;
+        b
+    }
+
+    // Looks up a symbol by name, adding it if it is not already known.
+    fn intern(&mut self, name: &str) -> uint {
+        if let Some(ii) = self.symbol_table.get(name) {
+            return *ii;
+        }
+        let index = self.symbols.len();
+        self.symbols.push(BuilderSymbol {
+            name: name.to_string(),
+            is_token: false,
+            is_lhs: false,
+            prec: 0,
+            assoc: TOKEN,
+            alias: None,
+            value: None
+        });
+        self.symbol_table.insert(name.to_string(), index);
+        index
+    }
+
+    /// Declares a token (a terminal symbol).
+    pub fn token(&mut self, name: &str) -> &mut GrammarBuilder {
+        let index = self.intern(name);
+        if self.symbols[index].is_token {
+            self.errors.push(GrammarError::TokenRedefined(name.to_string()));
+        }
+        self.symbols[index].is_token = true;
+        self
+    }
+
+    /// Gives a token an alias: the literal text that it stands for, such as `+` for `PLUS`.  The
+    /// token is declared, if it has not been already.  Reports, conflict descriptions, and the
+    /// names of tokens in the parser tables use the alias, quoted, rather than the name.
+    pub fn alias(&mut self, token: &str, text: &str) -> &mut GrammarBuilder {
+        let index = self.intern(token);
+        self.symbols[index].is_token = true;
+        self.symbols[index].alias = Some(text.to_string());
+        self
+    }
+
+    /// Gives a token the value that the lexer returns for it, rather than letting `build` choose
+    /// one.  The token is declared, if it has not been already.  Tokens without a value are
+    /// numbered from 257, in order, skipping the values which have been given; so giving every
+    /// token a value keeps the values the same when tokens are added or reordered.
+    pub fn token_value(&mut self, token: &str, value: i16) -> &mut GrammarBuilder {
+        let index = self.intern(token);
+        if value <= 0 || value == ERROR_VALUE || index == 0 {
+            self.errors.push(GrammarError::BadTokenValue(token.to_string(), value));
+        }
+        self.symbols[index].is_token = true;
+        self.symbols[index].value = Some(value);
+        self
+    }
+
+    /// Declares a precedence level, with the given associativity, and puts the given tokens in
+    /// it, as `%left`, `%right`, and `%nonassoc` do in YACC.  Each call declares a level which is
+    /// higher than all of the levels declared before it.  The tokens are declared as tokens, if
+    /// they have not been already.
+    ///
+    /// Precedence resolves shift/reduce conflicts silently.  The precedence of a rule is that of
+    /// the last token in it.  When the parser can either shift a token or reduce by a rule, and
+    /// both have a precedence, it does whichever has the higher precedence; if they are equal,
+    /// the associativity decides.
+    pub fn precedence(&mut self, assoc: Assoc, tokens: &[&str]) -> &mut GrammarBuilder {
+        self.nprec += 1;
+        let level = self.nprec;
+        for name in tokens.iter() {
+            let index = self.intern(*name);
+            let sym = &mut self.symbols[index];
+            if sym.prec != 0 {
+                self.errors.push(GrammarError::PrecedenceRedefined(name.to_string()));
+            }
+            sym.is_token = true;
+            sym.prec = level;
+            sym.assoc = match assoc {
+                Assoc::Left => LEFT,
+                Assoc::Right => RIGHT,
+                Assoc::NonAssoc => NONASSOC
+            };
+        }
+        self
+    }
+
+    /// Adds a rule `lhs : rhs[0] rhs[1] ...`.  An empty `rhs` defines an empty rule.
+    pub fn rule(&mut self, lhs: &str, rhs: &[&str]) -> &mut GrammarBuilder {
+        let lhs_index = self.intern(lhs);
+        self.symbols[lhs_index].is_lhs = true;
+        let mut rhs_indices: Vec<uint> = Vec::with_capacity(rhs.len());
+        for name in rhs.iter() {
+            rhs_indices.push(self.intern(*name));
+        }
+        self.rules.push(BuilderRule {
+            lhs: lhs_index,
+            rhs: rhs_indices,
+            prec: None,
+            resolve: Vec::new(),
+        });
+        self
+    }
+
+    /// Gives the rule that was added last the precedence and associativity of `token`, rather
+    /// than those of the last token in the rule, as `%prec` does in YACC.  This is mostly used
+    /// for operators which have a different precedence in different rules, such as unary minus:
+    ///
+    /// ```ignore
+    ///     builder.precedence(Assoc::Left, &["MINUS"])
+    ///         .precedence(Assoc::Right, &["UMINUS"])
+    ///         .rule("expr", &["expr", "MINUS", "expr"])
+    ///         .rule("expr", &["MINUS", "expr"]).prec("UMINUS")
+    /// ```
+    pub fn prec(&mut self, token: &str) -> &mut GrammarBuilder {
+        assert!(self.rules.len() != 0, "prec() must follow rule()");
+        let index = self.intern(token);
+        let last = self.rules.len() - 1;
+        self.rules[last].prec = Some(index);
+        self
+    }
+
+    /// Settles the conflicts between reducing by the rule that was added last and another action
+    /// on `token`, as `%resolve` does: `Resolution::Shift` shifts the token, and
+    /// `Resolution::Reduce` reduces by this rule.  This comes before precedence, and a conflict
+    /// which it settles is not counted or reported.  The usual example is the "dangling else":
+    ///
+    /// ```ignore
+    ///     builder.rule("stmt", &["IF", "expr", "THEN", "stmt"]).resolve(Resolution::Shift, "ELSE")
+    ///         .rule("stmt", &["IF", "expr", "THEN", "stmt", "ELSE", "stmt"])
+    /// ```
+    ///
+    /// Unlike giving the rule a precedence, this affects no other token, and no other rule.
+    pub fn resolve(&mut self, resolution: Resolution, token: &str) -> &mut GrammarBuilder {
+        assert!(self.rules.len() != 0, "resolve() must follow rule()");
+        let index = self.intern(token);
+        let last = self.rules.len() - 1;
+        self.rules[last].resolve.push((index, resolution));
+        self
+    }
+
+    /// Settles every conflict between reducing by a rule of `winner` and reducing by a rule of
+    /// `loser`, on any token and in any state, by reducing by the rule of `winner`, as
+    /// `%prefer winner over loser` does.  Preferences are a policy for the whole grammar: they
+    /// apply only to the conflicts which neither `resolve` nor precedence settles, and a
+    /// conflict which they settle is not counted or reported.
+    pub fn prefer(&mut self, winner: &str, loser: &str) -> &mut GrammarBuilder {
+        let w = self.intern(winner);
+        let l = self.intern(loser);
+        self.preferences.push((Choice::Reduce(w), Choice::Reduce(l)));
+        self
+    }
+
+    /// Settles every conflict between shifting a token and reducing by a rule of `loser` by
+    /// shifting, as `%prefer shift over loser` does.  This is the "longest match" policy for
+    /// the constructs of one non-terminal.  See `prefer`.
+    pub fn prefer_shift(&mut self, loser: &str) -> &mut GrammarBuilder {
+        let l = self.intern(loser);
+        self.preferences.push((Choice::Shift, Choice::Reduce(l)));
+        self
+    }
+
+    /// Settles every conflict between shifting a token and reducing by a rule of `winner` by
+    /// reducing, as `%prefer winner over shift` does.  See `prefer`.
+    pub fn prefer_reduce(&mut self, winner: &str) -> &mut GrammarBuilder {
+        let w = self.intern(winner);
+        self.preferences.push((Choice::Reduce(w), Choice::Shift));
+        self
+    }
+
+    /// Sets the start (goal) symbol.  By default, the start symbol is the left-hand side of
+    /// the first rule.
+    pub fn start(&mut self, name: &str) -> &mut GrammarBuilder {
+        let index = self.intern(name);
+        self.start = Some(index);
+        self
+    }
+
+    /// Returns the number of rules added so far.
+    pub fn nrules(&self) -> uint {
+        self.rules.len()
+    }
+
+    fn class_of(&self, s: uint) -> SymClass {
+        let sym = &self.symbols[s];
+        if sym.is_token {
+            SymClass::Terminal
+        }
+        else if sym.is_lhs {
+            SymClass::NonTerminal
+        }
+        else {
+            SymClass::Unknown
+        }
+    }
+
+    /// Checks the grammar, and if it is valid, packs it into a `Grammar`.
+    pub fn build(&self) -> Result<Grammar, Vec<GrammarError>> {
+        let mut errors: Vec<GrammarError> = self.errors.clone();
+
+        for sym in self.symbols.iter() {
+            if sym.is_token && sym.is_lhs {
+                errors.push(GrammarError::TokenOnLeftSide(sym.name.clone()));
+            }
+            else if !sym.is_token && !sym.is_lhs {
+                errors.push(GrammarError::UndefinedSymbol(sym.name.clone()));
+            }
+        }
+
+        let goal = match self.start {
+            Some(s) => s,
+            None if self.rules.len() != 0 => self.rules[0].lhs,
+            None => {
+                errors.push(GrammarError::NoRules);
+                return Err(errors);
+            }
+        };
+        if self.class_of(goal) != SymClass::NonTerminal {
+            errors.push(GrammarError::BadStartSymbol(self.symbols[goal].name.clone()));
+        }
+
+        let mut values: HashMap<i16, uint> = HashMap::new();
+        for (i, sym) in self.symbols.iter().enumerate() {
+            if let Some(value) = sym.value {
+                if let Some(&first) = values.get(&value) {
+                    errors.push(GrammarError::TokenValueReused(self.symbols[first].name.clone(), sym.name.clone(), value));
+                    continue;
+                }
+                values.insert(value, i);
+            }
+        }
+
+        for rule in self.rules.iter() {
+            for &(s, _) in rule.resolve.iter() {
+                if !self.symbols[s].is_token {
+                    errors.push(GrammarError::ResolveNonToken(self.symbols[s].name.clone()));
+                }
+            }
+        }
+
+        for &(winner, loser) in self.preferences.iter() {
+            for &choice in [winner, loser].iter() {
+                if let Choice::Reduce(s) = choice {
+                    if self.class_of(s) != SymClass::NonTerminal || winner == loser {
+                        errors.push(GrammarError::BadPreference(self.symbols[s].name.clone()));
+                    }
+                }
+            }
+        }
+
+        if errors.len() != 0 {
+            return Err(errors);
+        }
+
+        Ok(self.pack(goal))
+    }
+
+    // Produces the packed Grammar.  This is the equivalent of pack_names() and pack_grammar()
+    // in Berkeley YACC.  The grammar has already been checked.
+    fn pack(&self, goal: uint) -> Grammar {
+        let ntokens = 1 + self.symbols.iter().filter(|s| s.is_token).count();     // + $end
+        let nvars = 1 + self.symbols.iter().filter(|s| !s.is_token).count();      // + $accept
+        let nsyms = ntokens + nvars;
+        let start_symbol = ntokens;
+
+        let mut gram = Grammar::new();
+        gram.nsyms = nsyms;
+        gram.ntokens = ntokens;
+        gram.nvars = nvars;
+        gram.start_symbol = start_symbol;
+        gram.name = Vec::from_elem(nsyms, String::new());
+        gram.pname = Vec::from_elem(nsyms, String::new());
+        gram.value = Vec::from_elem(nsyms, 0);
+        gram.prec = Vec::from_elem(nsyms, 0);
+        gram.assoc = Vec::from_elem(nsyms, TOKEN);
+
+        // map_to_packed[s] gives the packed index of builder symbol s.  Tokens are packed in
+        // the order they were first mentioned, after $end.  The goal symbol is packed right
+        // after $accept, and the rest of the variables follow, in order.
+        let mut map_to_packed: Vec<uint> = Vec::from_elem(self.symbols.len(), 0);
+        let mut next_token = 1;
+        let mut next_var = start_symbol + 2;
+        for s in range(0, self.symbols.len()) {
+            if self.symbols[s].is_token {
+                map_to_packed[s] = next_token;
+                next_token += 1;
+            }
+            else if s == goal {
+                map_to_packed[s] = start_symbol + 1;
+            }
+            else {
+                map_to_packed[s] = next_var;
+                next_var += 1;
+            }
+        }
+        assert!(next_token == ntokens);
+        assert!(next_var == nsyms);
+
+        gram.name[0] = "$end".to_string();
+        gram.pname[0] = "$end".to_string();
+        gram.value[0] = 0;
+        gram.name[start_symbol] = "$accept".to_string();
+        gram.pname[start_symbol] = "$accept".to_string();
+        gram.value[start_symbol] = -1;
+
+        // Token values: error is 256, and the rest are numbered from 257, as in yacc, except for
+        // those given by token_value(), whose values are skipped.
+        // Variable values: the goal symbol is 0, and the rest are numbered from 1.
+        let given: HashSet<i16> = self.symbols.iter().filter_map(|s| s.value).collect();
+        let mut next_token_value = ERROR_VALUE;
+        let mut next_var_value = 1;
+        for s in range(0, self.symbols.len()) {
+            let k = map_to_packed[s];
+            gram.name[k] = self.symbols[s].name.clone();
+            gram.pname[k] = match self.symbols[s].alias {
+                Some(ref text) => format!("\"{}\"", text.escape_default()),
+                None => self.symbols[s].name.clone()
+            };
+            if self.symbols[s].is_token {
+                gram.prec[k] = self.symbols[s].prec;
+                gram.assoc[k] = self.symbols[s].assoc;
+                gram.value[k] = match self.symbols[s].value {
+                    Some(value) => value,
+                    None => {
+                        while given.contains(&next_token_value) {
+                            next_token_value += 1;
+                        }
+                        next_token_value += 1;
+                        next_token_value - 1
+                    }
+                };
+            }
+            else if s == goal {
+                gram.value[k] = 0;
+            }
+            else {
+                gram.value[k] = next_var_value;
+                next_var_value += 1;
+            }
+        }
+
+        // there are three pre-defined rules:
+        //      -1 -> (nothing)
+        //      -1 ->
+        //      $accept -> start_symbol $end
+        let nrules = PREDEFINED_RULES + self.rules.len();
+        let mut ritem: Vec<i16> = vec![-1, (start_symbol + 1) as i16, 0, -2];
+        let mut rlhs: Vec<i16> = vec![0, 0, start_symbol as i16];
+        let mut rrhs: Vec<i16> = vec![0, 0, 1];
+
+        for (i, rule) in self.rules.iter().enumerate() {
+            let ruleno = PREDEFINED_RULES + i;
+            rlhs.push(map_to_packed[rule.lhs] as i16);
+            rrhs.push(ritem.len() as i16);
+
+            // The precedence and associativity of a rule are those of the last token in it,
+            // unless they were given by prec().
+            let mut prec: i16 = 0;
+            let mut assoc: u8 = TOKEN;
+            for &s in rule.rhs.iter() {
+                let k = map_to_packed[s];
+                ritem.push(k as i16);
+                if k < ntokens {
+                    prec = gram.prec[k];
+                    assoc = gram.assoc[k];
+                }
+            }
+
+            if let Some(s) = rule.prec {
+                let k = map_to_packed[s];
+                prec = gram.prec[k];
+                assoc = gram.assoc[k];
+            }
+
+            for &(s, resolution) in rule.resolve.iter() {
+                gram.resolutions.push((ruleno, map_to_packed[s], resolution));
+            }
+
+            // Terminate the item list with the negative of the rule index.  This is used by
+            // lr0::save_reductions() in order to realize when we've reached the end of a rule.
+            ritem.push(-(ruleno as i16));
+            gram.rprec.push(prec);
+            gram.rassoc.push(assoc);
+        }
+
+        // Terminate the rrhs list
+        rrhs.push(ritem.len() as i16);
+
+        for &(winner, loser) in self.preferences.iter() {
+            gram.preferences.push((pack_choice(winner, map_to_packed.as_slice()), pack_choice(loser, map_to_packed.as_slice())));
+        }
+
+        gram.nrules = nrules;
+        gram.nitems = ritem.len();
+        gram.ritem = ritem;
+        gram.rlhs = rlhs;
+        gram.rrhs = rrhs;
+
+        assert!(gram.rprec.len() == nrules);
+        assert!(gram.rassoc.len() == nrules);
+        gram
+    }
+}
+
+// Renumbers the non-terminal of a Choice::Reduce from a builder symbol to a packed symbol.
+fn pack_choice(choice: Choice, map_to_packed: &[uint]) -> Choice {
+    match choice {
+        Choice::Reduce(s) => Choice::Reduce(map_to_packed[s]),
+        Choice::Shift => Choice::Shift
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use grammar::{Grammar, TOKEN, LEFT, RIGHT, NONASSOC};
+    use lr0::compute_lr0;
+    use lalr::run_lalr;
+    use mkpar::{make_parser, ActionCode, YaccParser};
+    use super::{GrammarBuilder, GrammarError, Assoc};
+
+    fn symbol(gram: &Grammar, name: &str) -> uint {
+        range(0, gram.nsyms).find(|&s| gram.name[s].as_slice() == name).unwrap()
+    }
+
+    fn parser(gram: &Grammar) -> YaccParser {
+        let lr0 = compute_lr0(gram).unwrap();
+        let lalr = run_lalr(gram, &lr0).unwrap();
+        make_parser(gram, &lr0, &lalr)
+    }
+
+    // Describes the actions of the final parser on `token`, in the state which reduces by `rule`.
+    fn actions_on(gram: &Grammar, rule: uint, token: &str) -> Vec<String> {
+        let parser = parser(gram);
+        let t = symbol(gram, token);
+        let state = range(0, parser.nstates).find(|&s| parser.actions[s].iter().any(|a| {
+            a.action_code == ActionCode::Reduce && a.number as uint == rule
+        })).unwrap();
+        parser.actions[state].iter().filter(|a| a.symbol as uint == t && a.is_active()).map(|a| {
+            match a.action_code {
+                ActionCode::Shift => "shift".to_string(),
+                ActionCode::Reduce => format!("reduce {}", a.number)
+            }
+        }).collect()
+    }
+
+    // e : e PLUS e | e TIMES e | NUM, which is ambiguous without precedence.  Rule 3 is
+    // e : e PLUS e, and rule 4 is e : e TIMES e.
+    fn arith(b: &mut GrammarBuilder) -> Grammar {
+        b.token("NUM")
+            .rule("e", &["e", "PLUS", "e"])
+            .rule("e", &["e", "TIMES", "e"])
+            .rule("e", &["NUM"])
+            .build().unwrap()
+    }
+
+    #[test]
+    fn precedence_levels() {
+        let gram = arith(GrammarBuilder::new()
+            .precedence(Assoc::Left, &["PLUS"])
+            .precedence(Assoc::Right, &["TIMES"]));
+        let (num, plus, times) = (symbol(&gram, "NUM"), symbol(&gram, "PLUS"), symbol(&gram, "TIMES"));
+        assert_eq!((gram.prec[num], gram.assoc[num]), (0, TOKEN));
+        assert_eq!((gram.prec[plus], gram.assoc[plus]), (1, LEFT));
+        assert_eq!((gram.prec[times], gram.assoc[times]), (2, RIGHT));
+        // The precedence of a rule is that of its last token.
+        assert_eq!(gram.rprec.slice_from(3), [1, 2, 0].as_slice());
+        assert_eq!(gram.rassoc.slice_from(3), [LEFT, RIGHT, TOKEN].as_slice());
+    }
+
+    #[test]
+    fn conflicts_without_precedence() {
+        let gram = arith(GrammarBuilder::new().token("PLUS").token("TIMES"));
+        let parser = parser(&gram);
+        assert_eq!((parser.sr_conflicts, parser.rr_conflicts), (4, 0));
+    }
+
+    #[test]
+    fn left_associative() {
+        let gram = arith(GrammarBuilder::new()
+            .precedence(Assoc::Left, &["PLUS"])
+            .precedence(Assoc::Left, &["TIMES"]));
+        let parser = parser(&gram);
+        assert_eq!((parser.sr_conflicts, parser.rr_conflicts), (0, 0));
+        // a + b . + c reduces; a + b . * c shifts, since TIMES is higher.
+        assert_eq!(actions_on(&gram, 3, "PLUS"), vec!["reduce 3".to_string()]);
+        assert_eq!(actions_on(&gram, 3, "TIMES"), vec!["shift".to_string()]);
+        assert_eq!(actions_on(&gram, 4, "PLUS"), vec!["reduce 4".to_string()]);
+        assert_eq!(actions_on(&gram, 4, "TIMES"), vec!["reduce 4".to_string()]);
+    }
+
+    #[test]
+    fn right_associative() {
+        let gram = GrammarBuilder::new()
+            .token("NUM")
+            .precedence(Assoc::Right, &["POW"])
+            .rule("e", &["e", "POW", "e"])
+            .rule("e", &["NUM"])
+            .build().unwrap();
+        assert_eq!(parser(&gram).sr_conflicts, 0);
+        assert_eq!(actions_on(&gram, 3, "POW"), vec!["shift".to_string()]);
+    }
+
+    #[test]
+    fn non_associative() {
+        // a == b . == c is a syntax error: both the shift and the reduction are removed.
+        let gram = GrammarBuilder::new()
+            .token("NUM")
+            .precedence(Assoc::NonAssoc, &["EQ"])
+            .rule("e", &["e", "EQ", "e"])
+            .rule("e", &["NUM"])
+            .build().unwrap();
+        let eq = symbol(&gram, "EQ");
+        assert_eq!((gram.prec[eq], gram.assoc[eq]), (1, NONASSOC));
+        assert_eq!(parser(&gram).sr_conflicts, 0);
+        assert_eq!(actions_on(&gram, 3, "EQ"), Vec::<String>::new());
+        assert_eq!(actions_on(&gram, 3, "$end"), vec!["reduce 3".to_string()]);
+    }
+
+    #[test]
+    fn precedence_redefined() {
+        let result = GrammarBuilder::new()
+            .precedence(Assoc::Left, &["PLUS"])
+            .precedence(Assoc::Right, &["PLUS"])
+            .rule("e", &["e", "PLUS", "e"])
+            .build();
+        match result {
+            Err(errors) => assert_eq!(errors, vec![GrammarError::PrecedenceRedefined("PLUS".to_string())]),
+            Ok(_) => panic!("the grammar should not build")
+        }
+    }
+
+    // e : e MINUS e | e TIMES e | MINUS e | NUM.  Rule 5 is e : MINUS e.
+    fn unary_minus(uminus: bool) -> Grammar {
+        let mut b = GrammarBuilder::new();
+        b.token("NUM")
+            .precedence(Assoc::Left, &["MINUS"])
+            .precedence(Assoc::Left, &["TIMES"])
+            .precedence(Assoc::Right, &["UMINUS"])
+            .rule("e", &["e", "MINUS", "e"])
+            .rule("e", &["e", "TIMES", "e"])
+            .rule("e", &["MINUS", "e"]);
+        if uminus {
+            b.prec("UMINUS");
+        }
+        b.rule("e", &["NUM"]).build().unwrap()
+    }
+
+    #[test]
+    fn prec_overrides_the_last_token() {
+        let gram = unary_minus(true);
+        assert_eq!(gram.rprec.slice_from(3), [1, 2, 3, 0].as_slice());
+        assert_eq!(gram.rassoc[5], RIGHT);
+        assert_eq!(parser(&gram).sr_conflicts, 0);
+        // - a . * b reduces, since UMINUS is higher than TIMES.
+        assert_eq!(actions_on(&gram, 5, "TIMES"), vec!["reduce 5".to_string()]);
+        assert_eq!(actions_on(&gram, 5, "MINUS"), vec!["reduce 5".to_string()]);
+    }
+
+    #[test]
+    fn without_prec() {
+        let gram = unary_minus(false);
+        assert_eq!((gram.rprec[5], gram.rassoc[5]), (1, LEFT));
+        // - a . * b shifts: the rule has the precedence of MINUS, which is lower than TIMES.
+        assert_eq!(actions_on(&gram, 5, "TIMES"), vec!["shift".to_string()]);
+        assert_eq!(actions_on(&gram, 5, "MINUS"), vec!["reduce 5".to_string()]);
+    }
+
+    #[test]
+    fn prec_needs_a_token() {
+        let result = GrammarBuilder::new()
+            .token("NUM")
+            .rule("e", &["MINUS", "e"]).prec("UMINUS")
+            .rule("e", &["NUM"])
+            .token("MINUS")
+            .build();
+        match result {
+            Err(errors) => assert_eq!(errors, vec![GrammarError::UndefinedSymbol("UMINUS".to_string())]),
+            Ok(_) => panic!("the grammar should not build")
+        }
+    }
+}
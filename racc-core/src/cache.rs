@@ -0,0 +1,83 @@
+// Caches the result of analyzing a grammar, so that a program whose grammar has not changed does
+// not have to build its automaton again.  The grammar! syntax extension is expanded every time
+// that the crate which uses it is compiled, and the analysis (LR(0), LALR(1), conflict
+// resolution, and packing) dominates the time that it takes; but most edits change only the
+// actions, which the analysis does not look at.
+//
+// A cache file holds the packed tables and the conflicts of one grammar, as JSON.  It is named
+// by a hash of everything that the analysis depends on: the symbols, rules, precedence, %resolve
+// annotations, and %prefer policy of the grammar, and the kind of automaton.  A cache file which
+// cannot be read, or which was written by a different version of this module, is ignored, and
+// the analysis is simply run again.
+
+use std::io::{File, IoResult};
+use std::io::fs;
+use serialize::json;
+
+use grammar::Grammar;
+use conflicts::ConflictKind;
+use tables::{PackedTables, StateItemTables, fnv1a};
+use LrType;
+
+/// The version of the cache format.  It changes whenever the format, or the analysis, changes in
+/// a way that makes old cache files wrong.
+pub const CACHE_VERSION: u32 = 3;
+
+/// A conflict, as it is reported to the author of the grammar.
+#[deriving(Encodable,Decodable)]
+pub struct CachedConflict {
+    pub kind: ConflictKind,
+    /// The rules involved in the conflict; see `Conflict::rules`.
+    pub rules: Vec<uint>,
+    /// The description of the conflict; see `Conflict::describe`.
+    pub description: String,
+}
+
+/// The result of analyzing a grammar, as it is stored in the cache.
+#[deriving(Encodable,Decodable)]
+pub struct CachedAnalysis {
+    pub version: u32,
+    /// The key of the grammar; see `grammar_key`.
+    pub key: u64,
+    pub tables: PackedTables,
+    pub state_items: StateItemTables,
+    pub sr_conflicts: uint,
+    pub rr_conflicts: uint,
+    /// The conflicts which were not resolved by precedence.
+    pub conflicts: Vec<CachedConflict>,
+}
+
+/// Returns a hash of everything about `gram` that the analysis depends on, together with the
+/// kind of automaton.  Actions are not part of a `Grammar`, so changing them does not change the
+/// key.
+pub fn grammar_key(gram: &Grammar, lr_type: LrType) -> u64 {
+    let text = format!("{} {} {} {} {} {} {} {} {} {} {} {} {} {}",
+        CACHE_VERSION, lr_type, gram.name, gram.pname, gram.value, gram.prec, gram.assoc,
+        gram.ritem, gram.rlhs, gram.rrhs, gram.rprec, gram.rassoc, gram.resolutions, gram.preferences);
+    fnv1a(text.as_bytes())
+}
+
+/// Returns the path of the cache file for `key`, in `dir`.
+pub fn cache_path(dir: &Path, key: u64) -> Path {
+    dir.join(format!("racc-{:016x}.json", key))
+}
+
+/// Reads the cache file at `path`.  Returns `None` if there is no cache file, or if it cannot be
+/// read, or if it is not for `key`.
+pub fn load(path: &Path, key: u64) -> Option<CachedAnalysis> {
+    let text = match File::open(path).and_then(|mut file| file.read_to_string()) {
+        Ok(text) => text,
+        Err(_) => return None
+    };
+    match json::decode::<CachedAnalysis>(text.as_slice()) {
+        Ok(cached) => if cached.version == CACHE_VERSION && cached.key == key { Some(cached) } else { None },
+        Err(_) => None
+    }
+}
+
+/// Writes a cache file at `path`, creating its directory if necessary.
+pub fn store(path: &Path, cached: &CachedAnalysis) -> IoResult<()> {
+    try!(fs::mkdir_recursive(&path.dir_path(), ::std::io::USER_RWX));
+    let mut file = try!(File::create(path));
+    file.write_str(json::encode(cached).as_slice())
+}
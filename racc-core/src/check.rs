@@ -0,0 +1,196 @@
+// Checks a grammar for mistakes which do not prevent building a parser, but which usually mean
+// that the grammar does not say what its author meant.
+//
+// A non-terminal is productive if it derives at least one string of tokens: that is, if one of
+// its rules has only tokens and productive non-terminals on its right-hand side.  This is
+// computed as a fixed point, starting with no productive non-terminals.  A non-terminal which is
+// not productive can never be reduced, so any rule which uses it is dead too; a mistake of this
+// kind usually comes from a recursive rule which lacks its base case.
+//
+// A non-terminal is reachable if it appears in a derivation from the start symbol, through rules
+// whose right-hand sides are all productive.  Unreachable non-terminals only make the automaton
+// bigger, and can cause conflicts that have nothing to do with the language.
+//
+// A rule is useless if its lhs is useless, or if its rhs uses an unproductive non-terminal.  A rule
+// is a duplicate if an earlier rule has the same lhs and rhs; the two can only produce a
+// reduce/reduce conflict.  Either way, the rule can be deleted without changing the language.
+
+use grammar::Grammar;
+use ids::{SymbolId, RuleId};
+
+/// What is wrong with a useless non-terminal.
+#[deriving(Copy,Clone,PartialEq,Show)]
+pub enum SymbolProblem {
+    /// The non-terminal does not derive any string of tokens, so no input contains it.
+    Unproductive,
+    /// The non-terminal cannot be reached from the start symbol, so its rules are never used.
+    Unreachable,
+}
+
+/// A non-terminal which plays no part in the language of the grammar.
+pub struct UselessSymbol {
+    pub symbol: SymbolId,
+    pub problem: SymbolProblem,
+    /// The rules of the non-terminal.
+    pub rules: Vec<RuleId>,
+}
+
+impl UselessSymbol {
+    /// Describes the problem, for the author of the grammar.
+    pub fn describe(&self, gram: &Grammar) -> String {
+        let name = gram.pname[self.symbol.index()].as_slice();
+        match self.problem {
+            SymbolProblem::Unproductive => format!("'{}' does not derive any string of tokens; each of its {} rule(s) uses a symbol which cannot be reduced", name, self.rules.len()),
+            SymbolProblem::Unreachable => format!("'{}' cannot be reached from the start symbol, so its {} rule(s) are never used", name, self.rules.len()),
+        }
+    }
+}
+
+// Returns true for each symbol which derives a string of tokens.  Every token does.
+fn productive_symbols(gram: &Grammar) -> Vec<bool> {
+    let mut productive: Vec<bool> = Vec::from_fn(gram.nsyms, |s| gram.is_token(s));
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for rule in range(2, gram.nrules) {
+            let lhs = gram.rlhs[rule] as uint;
+            if productive[lhs] {
+                continue;
+            }
+            if gram.get_rhs_items(rule).iter().all(|&s| productive[s as uint]) {
+                productive[lhs] = true;
+                changed = true;
+            }
+        }
+    }
+    productive
+}
+
+// Returns true for each symbol which can be reached from the start symbol, through rules whose
+// right-hand sides are all productive.
+fn reachable_symbols(gram: &Grammar, productive: &[bool]) -> Vec<bool> {
+    let mut reachable: Vec<bool> = Vec::from_elem(gram.nsyms, false);
+    let mut stack: Vec<uint> = vec![gram.start_symbol];
+    reachable[gram.start_symbol] = true;
+    loop {
+        let sym = match stack.pop() {
+            Some(sym) => sym,
+            None => break
+        };
+        for rule in range(2, gram.nrules) {
+            if gram.rlhs[rule] as uint != sym {
+                continue;
+            }
+            let rhs = gram.get_rhs_items(rule);
+            if !rhs.iter().all(|&s| productive[s as uint]) {
+                continue;
+            }
+            for &s in rhs.iter() {
+                if !reachable[s as uint] {
+                    reachable[s as uint] = true;
+                    stack.push(s as uint);
+                }
+            }
+        }
+    }
+    reachable
+}
+
+/// Finds the non-terminals which are unproductive or unreachable.  A non-terminal which is both
+/// is reported only as unproductive.
+pub fn find_useless_symbols(gram: &Grammar) -> Vec<UselessSymbol> {
+    let productive = productive_symbols(gram);
+    let reachable = reachable_symbols(gram, productive.as_slice());
+
+    let mut useless: Vec<UselessSymbol> = Vec::new();
+    for sym in range(gram.start_symbol + 1, gram.nsyms) {
+        let problem = if !productive[sym] {
+            SymbolProblem::Unproductive
+        }
+        else if !reachable[sym] {
+            SymbolProblem::Unreachable
+        }
+        else {
+            continue;
+        };
+        useless.push(UselessSymbol {
+            symbol: SymbolId(sym),
+            problem: problem,
+            rules: range(3, gram.nrules).filter(|&r| gram.rlhs[r] as uint == sym).map(|r| RuleId(r)).collect(),
+        });
+    }
+    useless
+}
+
+/// What is wrong with a useless rule.
+#[deriving(Copy,Clone,PartialEq,Show)]
+pub enum RuleProblem {
+    /// The rule can never be reduced: its lhs is useless, or its rhs uses an unproductive
+    /// non-terminal.
+    Useless,
+    /// The rule is the same as an earlier rule.
+    Duplicate(RuleId),
+}
+
+/// A rule which can be deleted without changing the language of the grammar.
+pub struct UselessRule {
+    pub rule: RuleId,
+    pub problem: RuleProblem,
+}
+
+impl UselessRule {
+    /// Describes the problem, for the author of the grammar.
+    pub fn describe(&self, gram: &Grammar) -> String {
+        let text = gram.rule_to_str(self.rule.index());
+        match self.problem {
+            RuleProblem::Useless => format!("rule is useless in the grammar: {}", text),
+            RuleProblem::Duplicate(first) => format!("rule is a duplicate of rule {}: {}", first.index(), text),
+        }
+    }
+
+    /// Suggests how to fix the problem.
+    pub fn suggestion(&self) -> &'static str {
+        match self.problem {
+            RuleProblem::Useless => "delete this rule, or make its symbols reachable and productive",
+            RuleProblem::Duplicate(_) => "delete this rule",
+        }
+    }
+}
+
+/// Finds the rules which are useless or duplicates, in order.  A rule which is both is reported
+/// only as useless.
+pub fn find_useless_rules(gram: &Grammar) -> Vec<UselessRule> {
+    let productive = productive_symbols(gram);
+    let reachable = reachable_symbols(gram, productive.as_slice());
+
+    let mut useless: Vec<UselessRule> = Vec::new();
+    for rule in range(3, gram.nrules) {
+        let lhs = gram.rlhs[rule] as uint;
+        let rhs = gram.get_rhs_items(rule);
+        let problem = if !productive[lhs] || !reachable[lhs] || rhs.iter().any(|&s| !productive[s as uint]) {
+            RuleProblem::Useless
+        }
+        else {
+            match range(3, rule).find(|&r| gram.rlhs[r] as uint == lhs && gram.get_rhs_items(r) == rhs) {
+                Some(first) => RuleProblem::Duplicate(RuleId(first)),
+                None => continue
+            }
+        };
+        useless.push(UselessRule { rule: RuleId(rule), problem: problem });
+    }
+    useless
+}
+
+/// Summarizes the useless symbols and rules of a grammar, as Bison does: for example,
+/// "1 nonterminal useless in grammar, 3 rules useless in grammar".  Returns `None` if there are
+/// none.
+pub fn summary(symbols: &[UselessSymbol], rules: &[UselessRule]) -> Option<String> {
+    let mut parts: Vec<String> = Vec::new();
+    if symbols.len() != 0 {
+        parts.push(format!("{} nonterminal{} useless in grammar", symbols.len(), if symbols.len() == 1 { "" } else { "s" }));
+    }
+    if rules.len() != 0 {
+        parts.push(format!("{} rule{} useless in grammar", rules.len(), if rules.len() == 1 { "" } else { "s" }));
+    }
+    if parts.len() == 0 { None } else { Some(parts.connect(", ")) }
+}
@@ -1,23 +1,20 @@
 use grammar::Grammar;
+use ids::{SymbolId,RuleId,ItemId,IdVec};
 use util::{Bitmat,Bitv32,word_size};
 use warshall::reflexive_transitive_closure;
 
 /// Computes the "epsilon-free firsts" (EFF) relation.
 /// The EFF is a bit matrix [nvars, nvars].
-fn set_eff(gram: &Grammar, derives: &[i16], derives_rules: &[i16]) -> Bitmat
+fn set_eff(gram: &Grammar, derives: &IdVec<SymbolId, Vec<RuleId>>) -> Bitmat
 {
     let nvars = gram.nvars;
     let mut eff: Bitmat = Bitmat::new(nvars, nvars);
     for row in range(0, nvars) {
-        let mut sp = derives[gram.start_symbol + row] as uint;
-		let mut rule = derives_rules[sp];
-        while rule > 0 {
-            let symbol = gram.ritem[gram.rrhs[rule as uint] as uint] as uint;
-            if gram.is_var(symbol) {
-                eff.set(row, symbol - gram.start_symbol);
+        for &rule in derives[SymbolId(gram.start_symbol + row)].iter() {
+            let first = gram.ritem[gram.rrhs[rule.index()] as uint];
+            if first >= 0 && gram.is_var(first as uint) {
+                eff.set(row, first as uint - gram.start_symbol);
             }
-            sp += 1;
-            rule = derives_rules[sp];
         }
     }
 
@@ -36,66 +33,21 @@ fn set_eff(gram: &Grammar, derives: &[i16], derives_rules: &[i16]) -> Bitmat
 ///
 /// This implementation processes bits in groups of 32, for the sake of efficiency.
 /// It is not clear whether this complexity is still justifiable, but it is preserved.
-pub fn set_first_derives(
-    gram: &Grammar, 
-    derives: &[i16], 
-    derives_rules: &[i16]) -> Bitmat
+pub fn set_first_derives(gram: &Grammar, derives: &IdVec<SymbolId, Vec<RuleId>>) -> Bitmat
 {
     // Compute EFF, which is a [nvars, nvars] bit matrix
-    let eff = set_eff(gram, derives, derives_rules);
+    let eff = set_eff(gram, derives);
 
     let mut first_derives = Bitmat::new(gram.nvars, gram.nrules);
 
-        /* known good, old
-    for i in range(0, gram.nvars) {
-        let mut vrow = i * eff.rowsize;     // used to read cols for an eff row, j is column
-        let mut cword: u32 = 0;
-        let mut k = BITS_PER_WORD;
-        for jvar in range(0, gram.nvars) {
-            let j = gram.start_symbol + jvar;
-            if k >= BITS_PER_WORD {
-                cword = eff.data[vrow];
-                vrow += 1;
-                k = 0;
-            }
-
-            if (cword & (1u32 << k)) != 0 {
-                let mut rp = derives[j] as uint;
-                while derives_rules[rp] >= 0 {
-                    first_derives.set(i, derives_rules[rp] as uint);
-                    rp += 1;
-                }
-            }
-        
-        	k += 1; // advance to next bit
-        }
-    }
-        */
-
-
-        /* this works! iter based
-    for i in range(0, gram.nvars) {
-        for j in eff.iter_ones_in_row(i) {
-            let mut rp = derives[gram.start_symbol + j] as uint;
-            while derives_rules[rp] >= 0 {
-                first_derives.set(i, derives_rules[rp] as uint);
-                rp += 1;
-            }
-        }
-    } */
-
     assert!(eff.rows == gram.nvars);
     assert!(eff.cols == gram.nvars);
     for (i, j) in eff.iter_ones() {
-        let mut rp = derives[gram.start_symbol + j] as uint;
-        while derives_rules[rp] >= 0 {
-            first_derives.set(i, derives_rules[rp] as uint);
-            rp += 1;
+        for &rule in derives[SymbolId(gram.start_symbol + j)].iter() {
+            first_derives.set(i, rule.index());
         }
     }
 
-
-
     print_first_derives(gram, &first_derives);
     first_derives
 }
@@ -114,11 +66,11 @@ pub fn set_first_derives(
 // item_set will be empty on call to closure(), and closure() writes its output into item_set.
 pub fn closure(
     gram: &Grammar,
-    nucleus: &[i16],
+    nucleus: &[ItemId],
     first_derives: &Bitmat,
     nrules: uint,
     rule_set: &mut Bitv32,      // bit vector, size=nrules; temporary data, written and read by this fn
-    item_set: &mut Vec<i16>)    // output is written to this vec
+    item_set: &mut Vec<ItemId>) // output is written to this vec
 {
     assert!(item_set.len() == 0);
 
@@ -134,8 +86,7 @@ pub fn closure(
     // that identifies the rules need to be added to the closure of the
     // current state.  Keep in mind that we process bit vectors in u32 chunks.
     for &ni in nucleus.iter() {
-        assert!(ni >= 0);
-        let symbol = gram.ritem[ni as uint];
+        let symbol = gram.ritem[ni.index()];
         if symbol >= 0 && gram.is_var(symbol as uint) {
             let dsp: uint = ((symbol as uint) - gram.ntokens) * first_derives.rowsize;
             for i in range(0, rulesetsize) {
@@ -147,12 +98,12 @@ pub fn closure(
     // Scan the rule_set that we just constructed.
     let mut csp: uint = 0;
     for r in rule_set.iter_ones() {
-        let itemno = gram.rrhs[r];
+        let itemno = ItemId::from_i16(gram.rrhs[r]);
         while csp < nucleus.len() && nucleus[csp] < itemno {
             item_set.push(nucleus[csp]);
             csp += 1;
         }
-        item_set.push(itemno as i16);
+        item_set.push(itemno);
         while csp < nucleus.len() && nucleus[csp] == itemno {
             csp += 1;
         }
@@ -0,0 +1,261 @@
+// Describes the conflicts that remain in a parser, so that the author of a grammar can find out
+// where they come from.
+//
+// For each state and token with a conflict, we report the actions that compete for the token,
+// the items of the state that produce those actions (with the position of the parser marked by
+// a dot), and an example of the input that leads to the state.  The example is found by a
+// breadth-first search from the initial state, so it is a shortest path, and then each
+// non-terminal on the path is replaced by the shortest sequence of tokens that it derives.
+
+use std::collections::RingBuf;
+
+use grammar::Grammar;
+use lr0::LR0Output;
+use mkpar::{ActionCode, YaccParser};
+use closure::{closure, set_first_derives};
+use util::Bitv32;
+use ids::{StateId, SymbolId, RuleId, ItemId};
+
+/// The kind of a conflict.
+#[deriving(Copy,Clone,PartialEq,Show,Encodable,Decodable)]
+pub enum ConflictKind {
+    /// The parser can either shift the token, or reduce by one or more rules.
+    ShiftReduce,
+    /// The parser can reduce by more than one rule.
+    ReduceReduce,
+}
+
+/// A conflict which was not resolved by precedence.  The parser takes the first action (the
+/// shift, if there is one, or else the reduction by the rule that comes first in the grammar).
+pub struct Conflict {
+    pub kind: ConflictKind,
+    pub state: StateId,
+    /// The lookahead token.
+    pub token: SymbolId,
+    /// The state that the parser goes to if it shifts the token.
+    pub shift: Option<StateId>,
+    /// The rules that the parser can reduce by.
+    pub reductions: Vec<RuleId>,
+    /// The items of the state which produce the competing actions.
+    pub items: Vec<ItemId>,
+    /// A shortest sequence of tokens which takes the parser from the initial state to `state`.
+    pub example: Vec<SymbolId>,
+}
+
+/// Finds the conflicts of a parser.  Conflicts which were resolved by precedence are not
+/// included.
+pub fn find_conflicts(gram: &Grammar, lr0: &LR0Output, parser: &YaccParser) -> Vec<Conflict> {
+    let first_derives = set_first_derives(gram, &lr0.derives);
+    let mut rule_set = Bitv32::from_elem(gram.nrules, false);
+    let paths = shortest_paths(lr0);
+    let yields = shortest_yields(gram);
+
+    let mut conflicts: Vec<Conflict> = Vec::new();
+    for state in range(0, parser.nstates) {
+        let actions = parser.actions_of(StateId(state));
+        let mut i = 0;
+        while i < actions.len() {
+            // The actions for one symbol are adjacent.
+            let symbol = actions[i].symbol;
+            let mut j = i;
+            while j < actions.len() && actions[j].symbol == symbol {
+                j += 1;
+            }
+            let group = actions.slice(i, j);
+            i = j;
+
+            if !group.iter().any(|p| p.suppressed == 1) {
+                continue;
+            }
+            let competing: Vec<_> = group.iter().filter(|p| p.suppressed == 0 || p.suppressed == 1).collect();
+            let shift = competing.iter().find(|p| p.action_code == ActionCode::Shift).map(|p| StateId(p.number as uint));
+            let reductions: Vec<RuleId> = competing.iter()
+                .filter(|p| p.action_code == ActionCode::Reduce)
+                .map(|p| RuleId(p.number as uint))
+                .collect();
+
+            // The items which produce the actions: completed items for the reductions, and items
+            // with the token after the dot for the shift.
+            let mut item_set: Vec<ItemId> = Vec::new();
+            closure(gram, lr0.states[state].items.as_slice(), &first_derives, gram.nrules, &mut rule_set, &mut item_set);
+            let mut items: Vec<ItemId> = Vec::new();
+            for &item in item_set.iter() {
+                let next = gram.ritem[item.index()];
+                if (shift.is_some() && next == symbol) || (next < 0 && reductions.contains(&RuleId((-next) as uint))) {
+                    items.push(item);
+                }
+            }
+
+            let mut example: Vec<SymbolId> = Vec::new();
+            for &s in paths[state].iter() {
+                match yields[s] {
+                    Some(ref y) => example.extend(y.iter().map(|&t| SymbolId(t))),
+                    // A non-terminal which derives no tokens is shown by name.
+                    None => example.push(SymbolId(s))
+                }
+            }
+
+            conflicts.push(Conflict {
+                kind: if shift.is_some() { ConflictKind::ShiftReduce } else { ConflictKind::ReduceReduce },
+                state: StateId(state),
+                token: SymbolId(symbol as uint),
+                shift: shift,
+                reductions: reductions,
+                items: items,
+                example: example,
+            });
+        }
+    }
+    conflicts
+}
+
+impl Conflict {
+    /// Returns the rules whose items produce the competing actions, in the order of their items:
+    /// the rules that can be reduced, and the rules that can shift the token.
+    pub fn rules(&self, gram: &Grammar) -> Vec<RuleId> {
+        let mut rules: Vec<RuleId> = Vec::new();
+        for &item in self.items.iter() {
+            let rule = item_rule(gram, item);
+            if !rules.contains(&rule) {
+                rules.push(rule);
+            }
+        }
+        rules
+    }
+
+    /// Returns a description of the conflict, for the author of the grammar.
+    pub fn describe(&self, gram: &Grammar) -> String {
+        let mut s = String::new();
+        s.push_str(format!("{} conflict in state {} on token '{}'\n",
+            match self.kind { ConflictKind::ShiftReduce => "shift/reduce", ConflictKind::ReduceReduce => "reduce/reduce" },
+            self.state.index(), gram.pname[self.token.index()]).as_slice());
+        if let Some(target) = self.shift {
+            s.push_str(format!("    shift, and go to state {}\n", target.index()).as_slice());
+        }
+        for rule in self.reductions.iter() {
+            s.push_str(format!("    reduce by rule {}\n", gram.rule_to_str(rule.index())).as_slice());
+        }
+        s.push_str("  items:\n");
+        for &item in self.items.iter() {
+            s.push_str(format!("    {}\n", item_to_str(gram, item)).as_slice());
+        }
+        s.push_str("  example:");
+        for t in self.example.iter() {
+            s.push_str(" ");
+            s.push_str(gram.pname[t.index()].as_slice());
+        }
+        s.push_str(format!(" . {}", gram.pname[self.token.index()]).as_slice());
+        s
+    }
+}
+
+/// Returns the rule that an item belongs to.
+pub fn item_rule(gram: &Grammar, item: ItemId) -> RuleId {
+    let mut end = item.index();
+    while gram.ritem[end] >= 0 {
+        end += 1;
+    }
+    RuleId((-gram.ritem[end]) as uint)
+}
+
+/// Formats an item as its rule, with a dot at the position of the parser.
+pub fn item_to_str(gram: &Grammar, item: ItemId) -> String {
+    let rule = item_rule(gram, item).index();
+    let mut end = item.index();
+    while gram.ritem[end] >= 0 {
+        end += 1;
+    }
+    let start = gram.rrhs[rule] as uint;
+
+    let mut s = String::new();
+    s.push_str(gram.pname[gram.rlhs[rule] as uint].as_slice());
+    s.push_str(" :");
+    for i in range(start, end + 1) {
+        if i == item.index() {
+            s.push_str(" .");
+        }
+        if i < end {
+            s.push_str(" ");
+            s.push_str(gram.pname[gram.ritem[i] as uint].as_slice());
+        }
+    }
+    s
+}
+
+// For each state, finds a shortest sequence of symbols which leads to it from the initial state.
+fn shortest_paths(lr0: &LR0Output) -> Vec<Vec<uint>> {
+    let nstates = lr0.nstates();
+    let mut pred: Vec<Option<uint>> = Vec::from_elem(nstates, None);
+    let mut seen: Vec<bool> = Vec::from_elem(nstates, false);
+    let mut queue: RingBuf<uint> = RingBuf::new();
+    seen[0] = true;
+    queue.push_back(0);
+    loop {
+        let state = match queue.pop_front() {
+            Some(state) => state,
+            None => break
+        };
+        for target in lr0.shifts_of(StateId(state)).iter() {
+            if !seen[target.index()] {
+                seen[target.index()] = true;
+                pred[target.index()] = Some(state);
+                queue.push_back(target.index());
+            }
+        }
+    }
+
+    range(0, nstates).map(|state| {
+        let mut path: Vec<uint> = Vec::new();
+        let mut s = state;
+        loop {
+            match pred[s] {
+                Some(p) => {
+                    path.push(lr0.states[s].accessing_symbol.index());
+                    s = p;
+                }
+                None => break
+            }
+        }
+        path.reverse();
+        path
+    }).collect()
+}
+
+// For each symbol, finds a shortest sequence of tokens that it derives.  Tokens derive
+// themselves.  A non-terminal which derives no sequence of tokens has None.
+fn shortest_yields(gram: &Grammar) -> Vec<Option<Vec<uint>>> {
+    let mut yields: Vec<Option<Vec<uint>>> = Vec::from_fn(gram.nsyms, |s| {
+        if gram.is_token(s) { Some(vec![s]) } else { None }
+    });
+
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for rule in range(2, gram.nrules) {
+            let lhs = gram.rlhs[rule] as uint;
+            let mut candidate: Vec<uint> = Vec::new();
+            let mut complete = true;
+            for &s in gram.get_rhs_items(rule).iter() {
+                match yields[s as uint] {
+                    Some(ref y) => candidate.push_all(y.as_slice()),
+                    None => {
+                        complete = false;
+                        break;
+                    }
+                }
+            }
+            if !complete {
+                continue;
+            }
+            let better = match yields[lhs] {
+                Some(ref y) => candidate.len() < y.len(),
+                None => true
+            };
+            if better {
+                yields[lhs] = Some(candidate);
+                changed = true;
+            }
+        }
+    }
+    yields
+}
@@ -0,0 +1,80 @@
+// Writes the automaton of a grammar as a Graphviz (DOT) graph.
+//
+// Each state is a node, labeled with its number and its kernel items.  If lookaheads are given,
+// each reduction is listed under the items, with its lookahead tokens.  Each transition is an
+// edge, labeled with its symbol; transitions on non-terminals (gotos) are dashed.
+//
+//      dot -Tsvg grammar.dot > grammar.svg
+
+use std::io::{IoResult, Writer};
+
+use grammar::Grammar;
+use lr0::LR0Output;
+use lalr::LALROutput;
+use conflicts::item_to_str;
+
+/// Writes the automaton as a DOT graph.  `lalr`, if given, supplies the lookaheads of the
+/// reductions.
+pub fn write_dot(w: &mut Writer, gram: &Grammar, lr0: &LR0Output, lalr: Option<&LALROutput>) -> IoResult<()> {
+    write_graph(w, gram, lr0, lalr, false)
+}
+
+/// Writes the automaton as a DOT graph, as `write_dot` does, with a link on each state to the
+/// anchor `#s<number>`, so that a graph which is rendered into a page (see `html`) leads to the
+/// description of each state.
+pub fn write_dot_with_links(w: &mut Writer, gram: &Grammar, lr0: &LR0Output, lalr: Option<&LALROutput>) -> IoResult<()> {
+    write_graph(w, gram, lr0, lalr, true)
+}
+
+fn write_graph(w: &mut Writer, gram: &Grammar, lr0: &LR0Output, lalr: Option<&LALROutput>, links: bool) -> IoResult<()> {
+    try!(writeln!(w, "digraph automaton {{"));
+    try!(writeln!(w, "    node [shape=box, fontname=\"monospace\"];"));
+
+    for state in lr0.iter_states() {
+        let mut label = format!("state {}\\l", state.id.index());
+        for &item in state.core.items.iter() {
+            label.push_str(escape(item_to_str(gram, item).as_slice()).as_slice());
+            label.push_str("\\l");
+        }
+        if let Some(lalr) = lalr {
+            for &(rule, ref tokens) in lalr.lookaheads_of(gram, state.id).iter() {
+                let names: Vec<&str> = tokens.iter().map(|t| gram.pname[t.index()].as_slice()).collect();
+                label.push_str(escape(format!("reduce by {} on {}", gram.rule_to_str(rule.index()), names.connect(" ")).as_slice()).as_slice());
+                label.push_str("\\l");
+            }
+        }
+        if links {
+            try!(writeln!(w, "    s{0} [label=\"{1}\", URL=\"#s{0}\"];", state.id.index(), label));
+        }
+        else {
+            try!(writeln!(w, "    s{} [label=\"{}\"];", state.id.index(), label));
+        }
+    }
+
+    for state in lr0.iter_states() {
+        for target in state.shifts.iter() {
+            let symbol = lr0.states[target.index()].accessing_symbol.index();
+            let style = if gram.is_var(symbol) { ", style=dashed" } else { "" };
+            try!(writeln!(w, "    s{} -> s{} [label=\"{}\"{}];",
+                state.id.index(), target.index(), escape(gram.pname[symbol].as_slice()), style));
+        }
+    }
+
+    try!(writeln!(w, "}}"));
+    Ok(())
+}
+
+// Escapes a string for use in a quoted DOT label.
+fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' | '\\' => {
+                out.push('\\');
+                out.push(c);
+            }
+            _ => out.push(c)
+        }
+    }
+    out
+}
@@ -0,0 +1,68 @@
+// Errors which can occur while generating a parser from a grammar that has already been
+// checked.  These are limits of the table representation (the tables index states, gotos, and
+// table entries with i32, and symbols with i16), rather than mistakes in the grammar.
+
+use std::error::Error;
+use std::fmt;
+
+/// The largest number of states, gotos, or table entries that can be represented.
+pub const MAX_TABLE_INDEX: uint = 0x7fff_ffff;
+
+/// An error which prevented RACC from generating a parser.
+#[deriving(Clone,PartialEq)]
+pub enum GenError {
+    /// The LR(0) automaton has too many states.  Carries the name of the symbol whose
+    /// transition would have created one state too many.
+    TooManyStates(String),
+
+    /// The automaton has too many gotos (transitions on non-terminals).  Carries the name of
+    /// the non-terminal whose goto would have exceeded the limit.
+    TooManyGotos(String),
+
+    /// A packed parsing table grew larger than the tables can index.  Carries the name of the
+    /// table and the size that it would have needed.
+    TableTooLarge(&'static str, uint),
+
+    /// A token's value is outside the range that the tables can represent.  Carries the name
+    /// of the token and its value.
+    TokenValueOutOfRange(String, i16),
+}
+
+impl GenError {
+    /// Returns the name of the symbol that the error is about, if there is one, so that the error
+    /// can be reported where the symbol is declared.
+    pub fn symbol<'a>(&'a self) -> Option<&'a str> {
+        match *self {
+            GenError::TooManyStates(ref symbol) => Some(symbol.as_slice()),
+            GenError::TooManyGotos(ref symbol) => Some(symbol.as_slice()),
+            GenError::TableTooLarge(_, _) => None,
+            GenError::TokenValueOutOfRange(ref token, _) => Some(token.as_slice()),
+        }
+    }
+}
+
+impl fmt::Show for GenError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            GenError::TooManyStates(ref symbol) =>
+                write!(f, "the grammar needs more than {} LR(0) states (limit reached while adding a transition on '{}')", MAX_TABLE_INDEX, symbol),
+            GenError::TooManyGotos(ref symbol) =>
+                write!(f, "the grammar needs more than {} gotos (limit reached at a goto on '{}')", MAX_TABLE_INDEX, symbol),
+            GenError::TableTooLarge(table, size) =>
+                write!(f, "the packed {} table needs {} entries, but at most {} are supported", table, size, MAX_TABLE_INDEX),
+            GenError::TokenValueOutOfRange(ref token, value) =>
+                write!(f, "token '{}' has value {}, which cannot be represented in the tables", token, value),
+        }
+    }
+}
+
+impl Error for GenError {
+    fn description(&self) -> &str {
+        match *self {
+            GenError::TooManyStates(_) => "too many LR(0) states",
+            GenError::TooManyGotos(_) => "too many gotos",
+            GenError::TableTooLarge(_, _) => "packed table too large",
+            GenError::TokenValueOutOfRange(_, _) => "token value out of range",
+        }
+    }
+}
@@ -0,0 +1,202 @@
+// Exports a grammar, its automaton, and its tables as JSON, for tools outside of RACC: CI jobs
+// which compare the size of the automaton and the list of conflicts between two versions of a
+// grammar, and visualizers written in other languages.
+//
+// The JSON has the shape of the structs below (field names are JSON keys; Option is null when
+// absent), which are the documented schema.  Symbols are named, not numbered, so that the output
+// of two versions of a grammar can be compared even when their symbols are numbered differently;
+// states and rules are numbered as in the reports (see report.rs).  A change to the schema
+// changes EXPORT_VERSION.
+//
+// The tables are the PackedTables, encoded as they are (see tables.rs for their meaning).
+// export_to_json puts all three in one object, {"grammar": ..., "automaton": ..., "tables": ...},
+// which is what "%define report.json" writes.
+
+use serialize::json;
+
+use grammar::{Grammar, LEFT, RIGHT, NONASSOC};
+use automaton::{Automaton, Action};
+use conflicts::{find_conflicts, item_to_str, ConflictKind};
+use tables::PackedTables;
+use ids::StateId;
+use Analysis;
+
+/// The version of the schema of the exported JSON.
+pub const EXPORT_VERSION: u32 = 1;
+
+/// A grammar, as exported by `grammar_to_json`.
+#[deriving(Encodable,Decodable)]
+pub struct GrammarJson {
+    pub version: u32,
+    /// The goal symbol.
+    pub start: String,
+    /// The tokens, including `$end` and `error`, in the order of their symbols.
+    pub tokens: Vec<TokenJson>,
+    /// The non-terminals, in the order of their symbols, not counting `$accept`.
+    pub nonterminals: Vec<String>,
+    /// The rules, from rule 3, the first rule of the grammar.
+    pub rules: Vec<RuleJson>,
+}
+
+#[deriving(Encodable,Decodable)]
+pub struct TokenJson {
+    pub name: String,
+    /// The number of the token, as the lexer returns it.
+    pub value: i16,
+    /// The precedence level of the token, or 0 if it has none.
+    pub prec: i16,
+    /// "left", "right", "nonassoc", or null.
+    pub assoc: Option<String>,
+}
+
+#[deriving(Encodable,Decodable)]
+pub struct RuleJson {
+    pub id: uint,
+    pub lhs: String,
+    pub rhs: Vec<String>,
+    /// The precedence level of the rule, or 0 if it has none.
+    pub prec: i16,
+}
+
+/// An automaton, as exported by `automaton_to_json`.
+#[deriving(Encodable,Decodable)]
+pub struct AutomatonJson {
+    pub version: u32,
+    pub states: Vec<StateJson>,
+    /// The conflicts which were not resolved by precedence.
+    pub conflicts: Vec<ConflictJson>,
+    pub sr_conflicts: uint,
+    pub rr_conflicts: uint,
+}
+
+#[deriving(Encodable,Decodable)]
+pub struct StateJson {
+    pub id: uint,
+    /// The symbol which is shifted to enter the state.
+    pub accessing_symbol: String,
+    /// The kernel items, written as rules with a dot.
+    pub kernel: Vec<String>,
+    /// The transitions on tokens and non-terminals.
+    pub transitions: Vec<TransitionJson>,
+    /// The actions of the final parser on tokens.
+    pub actions: Vec<ActionJson>,
+    /// The rule that the state reduces on any other token, if any.
+    pub default_reduction: Option<uint>,
+}
+
+#[deriving(Encodable,Decodable)]
+pub struct TransitionJson {
+    pub symbol: String,
+    pub target: uint,
+}
+
+#[deriving(Encodable,Decodable)]
+pub struct ActionJson {
+    pub token: String,
+    /// "shift", "reduce", or "accept".
+    pub action: String,
+    /// The state to shift to, or the rule to reduce by; null for "accept".
+    pub target: Option<uint>,
+}
+
+#[deriving(Encodable,Decodable)]
+pub struct ConflictJson {
+    /// "shift/reduce" or "reduce/reduce".
+    pub kind: String,
+    pub state: uint,
+    pub token: String,
+    /// The rules involved in the conflict.
+    pub rules: Vec<uint>,
+}
+
+/// Describes a grammar, for `grammar_to_json`.
+pub fn grammar_json(gram: &Grammar) -> GrammarJson {
+    let tokens = range(0, gram.ntokens).map(|t| TokenJson {
+        name: gram.name[t].clone(),
+        value: gram.value[t],
+        prec: gram.prec[t],
+        assoc: match gram.assoc[t] {
+            LEFT => Some("left".to_string()),
+            RIGHT => Some("right".to_string()),
+            NONASSOC => Some("nonassoc".to_string()),
+            _ => None
+        },
+    }).collect();
+    let rules = range(3, gram.nrules).map(|r| RuleJson {
+        id: r,
+        lhs: gram.name[gram.rlhs[r] as uint].clone(),
+        rhs: gram.get_rhs_items(r).iter().map(|&s| gram.name[s as uint].clone()).collect(),
+        prec: gram.rprec[r],
+    }).collect();
+    GrammarJson {
+        version: EXPORT_VERSION,
+        start: gram.name[gram.ritem[gram.rrhs[2] as uint] as uint].clone(),
+        tokens: tokens,
+        nonterminals: range(gram.start_symbol + 1, gram.nsyms).map(|v| gram.name[v].clone()).collect(),
+        rules: rules,
+    }
+}
+
+/// Describes the automaton that was built for a grammar, for `automaton_to_json`.
+pub fn automaton_json(gram: &Grammar, analysis: &Analysis) -> AutomatonJson {
+    let automaton = Automaton::new(gram, analysis);
+    let states = range(0, automaton.nstates()).map(|s| {
+        let state = StateId(s);
+        StateJson {
+            id: s,
+            accessing_symbol: gram.name[automaton.accessing_symbol(state).index()].clone(),
+            kernel: automaton.kernel(state).iter().map(|&item| item_to_str(gram, item)).collect(),
+            transitions: automaton.transitions(state).iter().map(|t| TransitionJson {
+                symbol: gram.name[t.symbol.index()].clone(),
+                target: t.target.index(),
+            }).collect(),
+            actions: automaton.actions(state).iter().map(|&(token, action)| {
+                let (kind, target) = match action {
+                    Action::Shift(target) => ("shift", Some(target.index())),
+                    Action::Reduce(rule) => ("reduce", Some(rule.index())),
+                    Action::Accept => ("accept", None)
+                };
+                ActionJson { token: gram.name[token.index()].clone(), action: kind.to_string(), target: target }
+            }).collect(),
+            default_reduction: automaton.default_reduction(state).map(|rule| rule.index()),
+        }
+    }).collect();
+    let conflicts = find_conflicts(gram, &analysis.lr0, &analysis.parser).iter().map(|c| ConflictJson {
+        kind: match c.kind {
+            ConflictKind::ShiftReduce => "shift/reduce".to_string(),
+            ConflictKind::ReduceReduce => "reduce/reduce".to_string()
+        },
+        state: c.state.index(),
+        token: gram.name[c.token.index()].clone(),
+        rules: c.rules(gram).iter().map(|r| r.index()).collect(),
+    }).collect();
+    AutomatonJson {
+        version: EXPORT_VERSION,
+        states: states,
+        conflicts: conflicts,
+        sr_conflicts: analysis.parser.sr_conflicts,
+        rr_conflicts: analysis.parser.rr_conflicts,
+    }
+}
+
+/// Returns the grammar as JSON; see `GrammarJson`.  This is `Grammar::to_json`.
+pub fn grammar_to_json(gram: &Grammar) -> String {
+    json::encode(&grammar_json(gram))
+}
+
+/// Returns the automaton of a grammar as JSON; see `AutomatonJson`.
+pub fn automaton_to_json(gram: &Grammar, analysis: &Analysis) -> String {
+    json::encode(&automaton_json(gram, analysis))
+}
+
+/// Returns the packed tables as JSON, with a field for each table.
+pub fn tables_to_json(tables: &PackedTables) -> String {
+    json::encode(tables)
+}
+
+/// Returns the grammar, its automaton, and its packed tables as one JSON object, with the fields
+/// "grammar", "automaton", and "tables".
+pub fn export_to_json(gram: &Grammar, analysis: &Analysis, tables: &PackedTables) -> String {
+    format!("{{\"grammar\":{},\"automaton\":{},\"tables\":{}}}",
+        grammar_to_json(gram), automaton_to_json(gram, analysis), tables_to_json(tables))
+}
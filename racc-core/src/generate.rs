@@ -0,0 +1,313 @@
+// Generates sentences of a grammar: sequences of tokens which the parser accepts.  They serve as
+// inputs for fuzzing the lexer and the actions of a parser, and as examples in documentation.
+//
+// A sentence is made by expanding the goal symbol, choosing one rule for each non-terminal, at
+// random, with a probability proportional to the weight of the rule (1 unless it is set).  To
+// keep the derivation tree from growing without bound, the generator first computes the height
+// of the smallest tree that each rule can produce.  A rule is only chosen if its smallest tree
+// fits in the depth that remains; when no rule fits, the rule with the smallest tree is taken,
+// so a derivation goes deeper than the limit only by as much as the grammar forces it to.
+//
+// Covering generation (`cover`) produces sentences until every rule has been used: while
+// expanding a non-terminal, it prefers the rules that no sentence has used yet.
+//
+// Rules which use the `error` token, or non-terminals which derive no string of tokens (see
+// check.rs), are never chosen.  All randomness comes from a `Random`, so that a sentence can be
+// produced again from its seed.
+
+use std::cmp;
+use std::uint;
+
+use grammar::Grammar;
+use ids::{SymbolId, RuleId};
+use random::Random;
+
+/// The depth of the derivation trees of sentences, unless `max_depth` sets it.
+pub const DEFAULT_MAX_DEPTH: uint = 16;
+
+// The height of a symbol or rule which derives no string of tokens.
+const NO_TREE: uint = uint::MAX;
+
+/// Generates sentences of a grammar.
+///
+/// ```ignore
+///     let mut rng = Random::new(random::seed_from_env(random::DEFAULT_SEED));
+///     let mut generator = SentenceGenerator::new(&gram);
+///     generator.max_depth(10).weight(RuleId(4), 3);
+///     let sentence = generator.generate(&mut rng).unwrap();
+///     println!("{}", sentence_to_string(&gram, sentence.as_slice()));
+/// ```
+pub struct SentenceGenerator<'a> {
+    gram: &'a Grammar,
+    max_depth: uint,
+    weights: Vec<uint>,
+    // The height of the smallest derivation tree of each symbol, and of each rule.
+    height: Vec<uint>,
+    rule_height: Vec<uint>,
+    // The rules of each symbol (empty for tokens).
+    rules_of: Vec<Vec<uint>>,
+}
+
+impl<'a> SentenceGenerator<'a> {
+    pub fn new(gram: &'a Grammar) -> SentenceGenerator<'a> {
+        let mut rules_of: Vec<Vec<uint>> = Vec::from_fn(gram.nsyms, |_| Vec::new());
+        for rule in range(3, gram.nrules) {
+            rules_of[gram.rlhs[rule] as uint].push(rule);
+        }
+        let (height, rule_height) = tree_heights(gram);
+        SentenceGenerator {
+            gram: gram,
+            max_depth: DEFAULT_MAX_DEPTH,
+            weights: Vec::from_elem(gram.nrules, 1),
+            height: height,
+            rule_height: rule_height,
+            rules_of: rules_of,
+        }
+    }
+
+    /// Sets the depth of the derivation trees of sentences.  Deeper trees make longer sentences.
+    pub fn max_depth(&mut self, depth: uint) -> &mut SentenceGenerator<'a> {
+        self.max_depth = depth;
+        self
+    }
+
+    /// Sets the weight of a rule: how likely it is to be chosen, relative to the other rules of
+    /// its non-terminal.  A rule with weight 0 is only chosen when no other rule fits.
+    pub fn weight(&mut self, rule: RuleId, weight: uint) -> &mut SentenceGenerator<'a> {
+        self.weights[rule.index()] = weight;
+        self
+    }
+
+    /// Generates a sentence of the goal symbol.  Returns None if the goal symbol derives no
+    /// string of tokens.
+    pub fn generate(&self, rng: &mut Random) -> Option<Vec<SymbolId>> {
+        self.generate_from(self.goal(), rng)
+    }
+
+    /// Generates a string of tokens which `symbol` derives.  Returns None if it derives none.
+    pub fn generate_from(&self, symbol: SymbolId, rng: &mut Random) -> Option<Vec<SymbolId>> {
+        let mut used = Vec::from_elem(self.gram.nrules, false);
+        self.sentence(symbol.index(), rng, used.as_mut_slice(), false)
+    }
+
+    /// Generates sentences of the goal symbol until each rule that can be used in a sentence
+    /// has been, or until `max_sentences` have been generated.  Returns the sentences, each of
+    /// which uses at least one rule that the sentences before it did not.
+    pub fn cover(&self, rng: &mut Random, max_sentences: uint) -> Vec<Vec<SymbolId>> {
+        let mut used = Vec::from_elem(self.gram.nrules, false);
+        let mut sentences: Vec<Vec<SymbolId>> = Vec::new();
+        let mut attempts = 0;
+        while sentences.len() < max_sentences && attempts < max_sentences * 2 {
+            attempts += 1;
+            let before = used.iter().filter(|&&u| u).count();
+            match self.sentence(self.goal().index(), rng, used.as_mut_slice(), true) {
+                Some(sentence) => {
+                    if used.iter().filter(|&&u| u).count() > before {
+                        sentences.push(sentence);
+                    }
+                }
+                None => break
+            }
+            if self.uncovered_rules(used.as_slice()).len() == 0 {
+                break;
+            }
+        }
+        sentences
+    }
+
+    /// Returns the rules which can be used in a sentence, but are not marked in `used`
+    /// (indexed by rule).
+    pub fn uncovered_rules(&self, used: &[bool]) -> Vec<RuleId> {
+        range(3, self.gram.nrules)
+            .filter(|&r| !used[r] && self.rule_height[r] != NO_TREE)
+            .map(|r| RuleId(r))
+            .collect()
+    }
+
+    fn goal(&self) -> SymbolId {
+        SymbolId(self.gram.ritem[self.gram.rrhs[2] as uint] as uint)    // rule 2 is $accept : goal $end
+    }
+
+    fn sentence(&self, symbol: uint, rng: &mut Random, used: &mut [bool], covering: bool) -> Option<Vec<SymbolId>> {
+        if self.height[symbol] == NO_TREE {
+            return None;
+        }
+        let mut out: Vec<SymbolId> = Vec::new();
+        self.expand(symbol, 0, rng, used, covering, &mut out);
+        Some(out)
+    }
+
+    fn expand(&self, symbol: uint, depth: uint, rng: &mut Random, used: &mut [bool], covering: bool, out: &mut Vec<SymbolId>) {
+        if !self.gram.is_var(symbol) {
+            out.push(SymbolId(symbol));
+            return;
+        }
+        let rule = self.choose_rule(symbol, depth, rng, used, covering);
+        used[rule] = true;
+        for &rhs in self.gram.get_rhs_items(rule).iter() {
+            self.expand(rhs as uint, depth + 1, rng, used, covering, out);
+        }
+    }
+
+    // Chooses a rule of `symbol` for a node at `depth` of the tree.
+    fn choose_rule(&self, symbol: uint, depth: uint, rng: &mut Random, used: &[bool], covering: bool) -> uint {
+        let rules = self.rules_of[symbol].as_slice();
+        let remaining = if depth < self.max_depth { self.max_depth - depth } else { 0 };
+        let mut fits: Vec<uint> = rules.iter().map(|&r| r)
+            .filter(|&r| self.rule_height[r] != NO_TREE && self.rule_height[r] <= remaining)
+            .collect();
+        if covering && fits.iter().any(|&r| !used[r]) {
+            fits.retain(|&r| !used[r]);
+        }
+        let weights: Vec<uint> = fits.iter().map(|&r| self.weights[r]).collect();
+        match rng.choose_weighted(weights.as_slice()) {
+            Some(i) => fits[i],
+            None if fits.len() > 0 => fits[0],
+            None => {
+                // No rule fits; take the one with the smallest tree.
+                let mut best = rules[0];
+                for &r in rules.iter() {
+                    if self.rule_height[r] < self.rule_height[best] {
+                        best = r;
+                    }
+                }
+                best
+            }
+        }
+    }
+}
+
+// Computes the height of the smallest derivation tree of each symbol and rule, as a fixed point.
+// A token is a tree of height 0, except for `error`, which is not a token of any input.
+fn tree_heights(gram: &Grammar) -> (Vec<uint>, Vec<uint>) {
+    let mut height: Vec<uint> = range(0, gram.nsyms).map(|s| if s == 1 || gram.is_var(s) { NO_TREE } else { 0 }).collect();
+    let mut rule_height: Vec<uint> = Vec::from_elem(gram.nrules, NO_TREE);
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for rule in range(3, gram.nrules) {
+            let mut h = 0;
+            for &sym in gram.get_rhs_items(rule).iter() {
+                h = cmp::max(h, height[sym as uint]);
+            }
+            if h == NO_TREE {
+                continue;
+            }
+            rule_height[rule] = h + 1;
+            let lhs = gram.rlhs[rule] as uint;
+            if h + 1 < height[lhs] {
+                height[lhs] = h + 1;
+                changed = true;
+            }
+        }
+    }
+    (height, rule_height)
+}
+
+/// Writes a sentence as the printable names of its tokens, separated by spaces.
+pub fn sentence_to_string(gram: &Grammar, sentence: &[SymbolId]) -> String {
+    let names: Vec<&str> = sentence.iter().map(|t| gram.pname[t.index()].as_slice()).collect();
+    names.connect(" ")
+}
+
+/// Returns the token values of a sentence, as a lexer returns them to the parser.
+pub fn token_values(gram: &Grammar, sentence: &[SymbolId]) -> Vec<u32> {
+    sentence.iter().map(|t| gram.value[t.index()] as u32).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use grammar::Grammar;
+    use ids::{SymbolId, RuleId};
+    use random::Random;
+    use yacc::read_yacc;
+    use super::{SentenceGenerator, tree_heights, sentence_to_string, NO_TREE};
+
+    // Rules 3 to 6.
+    const EXPR: &'static str = "%token NUM PLUS LP RP\n%%\ne : e PLUS t | t ;\nt : NUM | LP e RP ;\n";
+
+    fn grammar(text: &str) -> Grammar {
+        match read_yacc(text) {
+            Ok(y) => y.grammar,
+            Err(_) => panic!("cannot read the grammar")
+        }
+    }
+
+    fn symbol(gram: &Grammar, name: &str) -> SymbolId {
+        SymbolId(gram.name.iter().position(|n| n.as_slice() == name).unwrap())
+    }
+
+    #[test]
+    fn heights_of_the_smallest_trees() {
+        let gram = grammar(EXPR);
+        let (height, rule_height) = tree_heights(&gram);
+        assert_eq!(height[symbol(&gram, "NUM").index()], 0);
+        assert_eq!(height[symbol(&gram, "t").index()], 1);
+        assert_eq!(height[symbol(&gram, "e").index()], 2);
+        assert_eq!(rule_height.slice_from(3), [3u, 2, 1, 3].as_slice());
+        assert_eq!(height[1], NO_TREE);       // error
+    }
+
+    #[test]
+    fn depth_0_gives_the_smallest_sentence() {
+        let gram = grammar(EXPR);
+        let mut generator = SentenceGenerator::new(&gram);
+        generator.max_depth(0);
+        for seed in range(0u64, 20) {
+            let sentence = generator.generate(&mut Random::new(seed)).unwrap();
+            assert_eq!(sentence_to_string(&gram, sentence.as_slice()).as_slice(), "NUM");
+        }
+    }
+
+    #[test]
+    fn rules_of_weight_0_are_not_chosen() {
+        let gram = grammar(EXPR);
+        let mut generator = SentenceGenerator::new(&gram);
+        generator.weight(RuleId(3), 0).weight(RuleId(6), 0);
+        for seed in range(0u64, 20) {
+            let sentence = generator.generate(&mut Random::new(seed)).unwrap();
+            assert_eq!(sentence, vec![symbol(&gram, "NUM")]);
+        }
+    }
+
+    #[test]
+    fn same_seed_same_sentence() {
+        let gram = grammar(EXPR);
+        let generator = SentenceGenerator::new(&gram);
+        for seed in range(0u64, 20) {
+            assert_eq!(generator.generate(&mut Random::new(seed)), generator.generate(&mut Random::new(seed)));
+        }
+    }
+
+    #[test]
+    fn generate_from_a_token() {
+        let gram = grammar(EXPR);
+        let generator = SentenceGenerator::new(&gram);
+        let plus = symbol(&gram, "PLUS");
+        assert_eq!(generator.generate_from(plus, &mut Random::new(0)), Some(vec![plus]));
+    }
+
+    #[test]
+    fn cover_uses_every_rule() {
+        let gram = grammar(EXPR);
+        let generator = SentenceGenerator::new(&gram);
+        let sentences = generator.cover(&mut Random::new(0), 100);
+        assert!(sentences.len() != 0);
+        for name in ["NUM", "PLUS", "LP", "RP"].iter() {
+            let token = symbol(&gram, *name);
+            assert!(sentences.iter().any(|s| s.contains(&token)), "no sentence has {}", name);
+        }
+    }
+
+    #[test]
+    fn rules_with_error_are_not_chosen() {
+        let gram = grammar("%token NUM\n%%\ns : NUM | error ;\n");
+        let generator = SentenceGenerator::new(&gram);
+        for seed in range(0u64, 20) {
+            let sentence = generator.generate(&mut Random::new(seed)).unwrap();
+            assert_eq!(sentence_to_string(&gram, sentence.as_slice()).as_slice(), "NUM");
+        }
+        let used = [false, false, false, true, false];
+        assert_eq!(generator.uncovered_rules(&used), vec![]);
+    }
+}
@@ -0,0 +1,188 @@
+use std::default::Default;
+
+use builder::{GrammarBuilder, Resolution, Choice};
+use export;
+use ids::SymbolId;
+use recursion::{analyze_recursion, RecursionReport};
+use sets;
+use yacc;
+
+/* keyword codes */
+
+pub const TOKEN: u8 =0;
+pub const LEFT: u8 =1;
+pub const RIGHT: u8 =2;
+pub const NONASSOC: u8 =3;
+/*
+pub const MARK: u8 =4;
+pub const TEXT: u8 =5;
+pub const TYPE: u8 =6;
+pub const START: u8 =7;
+pub const UNION: u8 =8;
+pub const IDENT: u8 =9;
+pub const EXPECT: u8 =10;
+pub const EXPECT_RR: u8 =11;
+pub const CLASS: u8 =12;
+pub const NAMESPACE: u8 =13;
+pub const LANGUAGE: u8 =14;
+pub const LOCATION: u8 =15;
+pub const VISIBILITY: u8 =16;
+*/
+
+// the undefined value
+pub const UNDEFINED: i16 = -1;
+
+// Defines a grammar.  A grammar has these elements:
+//
+//     * a set of tokens (terminals), each having a name and potentially a value
+//     * a set of variables (non-terminals)
+//     * a set of rules, in the form A <- B C ... , where A is a variable and B and C are
+//       any combination of tokens or variables
+//     * (optional) precedence and associativity rules for rules
+//
+#[deriving(Default)]
+pub struct Grammar
+{
+    // the symbols (non-terminals and terminals/tokens)
+    // symbols are ordered as tokens first, then non-terminals.
+    // symbol[0] is the special $end token.
+    // the first non-terminal is the special "$accept" symbol.
+    pub nsyms: uint,
+    pub ntokens: uint,
+    pub nvars: uint,
+    pub start_symbol: uint,
+
+    pub name: Vec<String>,
+    // the printable name of each symbol: the name, or for a token which has an alias, the
+    // alias in quotes, e.g. "+".  Reports and diagnostics use this.
+    pub pname: Vec<String>,
+    pub value: Vec<i16>,
+
+    // these two are managed differently
+    pub prec: Vec<i16>,
+    pub assoc: Vec<u8>,
+
+    // the rules which describe the grammar
+    pub nitems: uint,
+    pub nrules: uint,
+
+    pub ritem: Vec<i16>,
+    pub rlhs: Vec<i16>,
+    pub rrhs: Vec<i16>,
+    pub rprec: Vec<i16>,
+    pub rassoc: Vec<u8>,
+
+    // the resolutions given by %resolve: (rule, token, resolution), ordered by rule
+    pub resolutions: Vec<(uint, uint, Resolution)>,
+
+    // the policy given by %prefer: (winner, loser), where Reduce holds a non-terminal
+    pub preferences: Vec<(Choice, Choice)>,
+}
+
+impl Grammar
+{
+	pub fn new() -> Grammar {
+        Grammar {
+            nitems: 4,
+            nrules: 3,
+            ritem: Vec::new(),
+            rlhs: Vec::new(),
+            rrhs: Vec::new(),
+            rprec: vec![0, 0, 0],
+            rassoc: vec![TOKEN, TOKEN, TOKEN],
+		    .. Default::default()
+        }
+	}
+
+    /// Returns a `GrammarBuilder`, which can be used to construct a grammar at run time.
+    pub fn builder() -> GrammarBuilder {
+        GrammarBuilder::new()
+    }
+
+    pub fn is_var(&self, s: uint) -> bool
+    {
+        s >= self.start_symbol
+    }
+
+    pub fn is_token(&self, s: uint) -> bool
+    {
+        s < self.start_symbol
+    }
+
+    pub fn rule_to_str(&self, r: uint) -> String {
+        let mut s = String::new();
+        s.push_str(format!("(r{}) ", r).as_slice());
+        s.push_str(self.pname[self.rlhs[r] as uint].as_slice());
+        s.push_str(" :");
+        for it in self.ritem.slice_from(self.rrhs[r] as uint).iter() {
+            if *it < 0 { break; } // end of this rule
+            s.push_str(" ");
+            s.push_str(self.pname[*it as uint].as_slice());
+        }
+        s
+    }
+
+    /// Writes the grammar as a YACC grammar (a .y file), with its tokens, precedence
+    /// declarations, and rules, but without actions.  Bison or YACC can then be run on it, to
+    /// compare their conflicts and tables with RACC's.  See `yacc::to_yacc_string`.
+    pub fn to_yacc_string(&self) -> String {
+        yacc::to_yacc_string(self)
+    }
+
+    /// Returns the grammar as JSON: its tokens, with their precedence, its non-terminals, and its
+    /// rules.  The schema is described in `export`.
+    pub fn to_json(&self) -> String {
+        export::grammar_to_json(self)
+    }
+
+    /// Returns FIRST(s) for each symbol s: the tokens which can begin a string derived from s.
+    /// The result is indexed by symbol; each set is sorted.  See `sets`.
+    pub fn first_sets(&self) -> Vec<Vec<SymbolId>> {
+        sets::first_sets(self)
+    }
+
+    /// Returns FOLLOW(s) for each symbol s: the tokens which can come right after s in a
+    /// sentence of the grammar, including `$end` (symbol 0) after the start symbol.  The result
+    /// is indexed by symbol; each set is sorted.
+    pub fn follow_sets(&self) -> Vec<Vec<SymbolId>> {
+        sets::follow_sets(self)
+    }
+
+    /// Returns true for each symbol which derives the empty string, indexed by symbol.
+    pub fn nullable_symbols(&self) -> Vec<bool> {
+        sets::nullable_symbols(self)
+    }
+
+    /// Finds the derivation cycles of the grammar (A =>+ A), classifies the recursion of each
+    /// non-terminal as left, right, both, or neither, and finds the rules which make
+    /// right-recursive lists.  See `recursion`.
+    pub fn recursion(&self) -> RecursionReport {
+        analyze_recursion(self)
+    }
+
+    /// Returns how `%resolve` settles a conflict between reducing by rule `r` and another action
+    /// on `token`, if it was given one.
+    pub fn resolution(&self, r: uint, token: uint) -> Option<Resolution> {
+        self.resolutions.iter()
+            .find(|&&(rule, t, _)| rule == r && t == token)
+            .map(|&(_, _, resolution)| resolution)
+    }
+
+    /// Returns which of two choices `%prefer` settles a conflict between them in favor of, if a
+    /// preference covers them.  The first preference which does, in either order, decides.
+    pub fn preference(&self, first: Choice, second: Choice) -> Option<Choice> {
+        self.preferences.iter()
+            .find(|&&(w, l)| (w == first && l == second) || (w == second && l == first))
+            .map(|&(w, _)| w)
+    }
+
+    pub fn get_rhs_items<'a>(&'a self, r: uint) -> &'a[i16] {
+        let rhs = self.rrhs[r];
+        assert!(rhs >= 0);
+        let mut end = rhs as uint;
+        while self.ritem[end] >= 0 {
+            end += 1;
+        }
+        self.ritem.slice(rhs as uint, end)
+    }
+}
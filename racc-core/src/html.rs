@@ -0,0 +1,235 @@
+// Writes a report of a grammar and its automaton as an HTML page, for reviewing a grammar in a
+// browser.  It holds what the text report (report.rs) holds, and more, with links between
+// them:
+//
+// * the rules, linked to the non-terminals which they use;
+// * the non-terminals, with whether each is nullable, its FIRST and FOLLOW sets (see sets.rs),
+//   and its rules;
+// * the conflicts, linked to their states and rules;
+// * the automaton, as a graph whose states link to their descriptions;
+// * every state: its items, actions, gotos, and lookaheads, linked to the states and rules that
+//   they name.
+//
+// The graph is the DOT output of dot.rs.  The page renders it with viz.js, which it loads from a
+// CDN; when that cannot be loaded, the page shows the DOT source instead, for `dot -Tsvg`.
+
+use std::io::{IoResult, Writer};
+
+use grammar::Grammar;
+use lr0::LR0Output;
+use lalr::LALROutput;
+use mkpar::{ActionCode, YaccParser};
+use closure::{closure, set_first_derives};
+use conflicts::{find_conflicts, item_rule, ConflictKind};
+use dot::write_dot_with_links;
+use sets::{nullable_symbols, first_sets, follow_sets};
+use util::Bitv32;
+use ids::{ItemId, SymbolId};
+
+const STYLE: &'static str = "\
+body { font-family: sans-serif; margin: 2em; }
+nav a { margin-right: 1em; }
+table { border-collapse: collapse; }
+td, th { border: 1px solid #ccc; padding: 2px 8px; text-align: left; vertical-align: top; }
+pre, td.rule { font-family: monospace; }
+.closure { color: #777; }
+.suppressed { color: #a00; }
+.conflict { border-left: 4px solid #a00; padding-left: 1em; margin-bottom: 1em; }
+section:target { background: #ffc; }
+tr:target { background: #ffc; }
+";
+
+const SCRIPT: &'static str = "\
+<script src=\"https://cdn.jsdelivr.net/npm/viz.js@2.1.2/viz.js\"></script>
+<script src=\"https://cdn.jsdelivr.net/npm/viz.js@2.1.2/full.render.js\"></script>
+<script>
+if (typeof Viz !== \"undefined\") {
+    var dot = document.getElementById(\"dot\");
+    new Viz().renderSVGElement(dot.textContent).then(function (svg) {
+        document.getElementById(\"graph\").appendChild(svg);
+        dot.style.display = \"none\";
+    });
+}
+</script>
+";
+
+/// Writes the HTML report of the grammar and its automaton to `w`.
+pub fn write_html(w: &mut Writer, gram: &Grammar, lr0: &LR0Output, lalr: &LALROutput, parser: &YaccParser) -> IoResult<()> {
+    let conflicts = find_conflicts(gram, lr0, parser);
+    let goal = gram.ritem[gram.rrhs[2] as uint] as uint;    // rule 2 is $accept : goal $end
+
+    try!(writeln!(w, "<!DOCTYPE html>"));
+    try!(writeln!(w, "<html><head><meta charset=\"utf-8\"><title>Grammar of {}</title>", escape(gram.name[goal].as_slice())));
+    try!(writeln!(w, "<style>\n{}</style></head><body>", STYLE));
+    try!(writeln!(w, "<nav><a href=\"#rules\">Rules</a><a href=\"#symbols\">Non-terminals</a><a href=\"#conflicts\">Conflicts</a><a href=\"#automaton\">Automaton</a><a href=\"#states\">States</a></nav>"));
+    try!(writeln!(w, "<p>{} rules, {} tokens, {} non-terminals, {} states, {} shift/reduce conflict(s), {} reduce/reduce conflict(s)</p>",
+        gram.nrules - 3, gram.ntokens, gram.nvars - 1, lr0.nstates(), parser.sr_conflicts, parser.rr_conflicts));
+
+    try!(writeln!(w, "<h2 id=\"rules\">Rules</h2>"));
+    try!(writeln!(w, "<table>"));
+    for rule in range(2, gram.nrules) {
+        try!(writeln!(w, "<tr id=\"r{0}\"><td>{0}</td><td class=\"rule\">{1}</td></tr>", rule, rule_html(gram, rule)));
+    }
+    try!(writeln!(w, "</table>"));
+
+    let nullable = nullable_symbols(gram);
+    let first = first_sets(gram);
+    let follow = follow_sets(gram);
+    try!(writeln!(w, "<h2 id=\"symbols\">Non-terminals</h2>"));
+    try!(writeln!(w, "<table><tr><th>Non-terminal</th><th>Nullable</th><th>FIRST</th><th>FOLLOW</th><th>Rules</th></tr>"));
+    for sym in range(gram.start_symbol + 1, gram.nsyms) {
+        let rules: Vec<String> = range(3, gram.nrules).filter(|&r| gram.rlhs[r] as uint == sym).map(|r| rule_link(r)).collect();
+        try!(writeln!(w, "<tr id=\"sym{}\"><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            sym, escape(gram.name[sym].as_slice()), if nullable[sym] { "yes" } else { "" },
+            tokens_html(gram, first[sym].as_slice()), tokens_html(gram, follow[sym].as_slice()), rules.connect(" ")));
+    }
+    try!(writeln!(w, "</table>"));
+
+    try!(writeln!(w, "<h2 id=\"conflicts\">Conflicts</h2>"));
+    if conflicts.len() == 0 {
+        try!(writeln!(w, "<p>None.</p>"));
+    }
+    for conflict in conflicts.iter() {
+        let kind = match conflict.kind { ConflictKind::ShiftReduce => "Shift/reduce", ConflictKind::ReduceReduce => "Reduce/reduce" };
+        let rules: Vec<String> = conflict.rules(gram).iter().map(|r| rule_link(r.index())).collect();
+        try!(writeln!(w, "<div class=\"conflict\"><p>{} conflict in {} on <code>{}</code>, between rules {}</p>",
+            kind, state_link(conflict.state.index()), escape(gram.pname[conflict.token.index()].as_slice()), rules.connect(", ")));
+        try!(writeln!(w, "<pre>{}</pre></div>", escape(conflict.describe(gram).as_slice())));
+    }
+
+    let mut dot: Vec<u8> = Vec::new();
+    try!(write_dot_with_links(&mut dot, gram, lr0, Some(lalr)));
+    try!(writeln!(w, "<h2 id=\"automaton\">Automaton</h2>"));
+    try!(writeln!(w, "<div id=\"graph\"></div>"));
+    try!(writeln!(w, "<pre id=\"dot\">{}</pre>", escape(String::from_utf8(dot).unwrap().as_slice())));
+
+    try!(writeln!(w, "<h2 id=\"states\">States</h2>"));
+    let first_derives = set_first_derives(gram, &lr0.derives);
+    let mut rule_set = Bitv32::from_elem(gram.nrules, false);
+    for state in lr0.iter_states() {
+        try!(writeln!(w, "<section id=\"s{0}\"><h3>state {0}</h3><pre>", state.id.index()));
+
+        let nucleus = state.core.items.as_slice();
+        let mut item_set: Vec<ItemId> = Vec::new();
+        closure(gram, nucleus, &first_derives, gram.nrules, &mut rule_set, &mut item_set);
+        for &item in nucleus.iter() {
+            try!(writeln!(w, "    {}", item_html(gram, item)));
+        }
+        for &item in item_set.iter().filter(|item| !nucleus.contains(*item)) {
+            try!(writeln!(w, "<span class=\"closure\">    {}</span>", item_html(gram, item)));
+        }
+        try!(writeln!(w, ""));
+
+        if state.id.index() == parser.final_state {
+            try!(writeln!(w, "    {:<16}accept", escape(gram.pname[0].as_slice())));
+        }
+        for p in parser.actions_of(state.id).iter() {
+            let action = match p.action_code {
+                ActionCode::Shift => format!("shift, and go to {}", state_link(p.number as uint)),
+                ActionCode::Reduce => format!("reduce by rule {}", rule_link(p.number as uint))
+            };
+            let symbol = escape(gram.pname[p.symbol as uint].as_slice());
+            match p.suppressed {
+                0 => try!(writeln!(w, "    {:<16}{}", symbol, action)),
+                1 => try!(writeln!(w, "<span class=\"suppressed\">    {:<16}[{}]  (conflict)</span>", symbol, action)),
+                2 => try!(writeln!(w, "<span class=\"suppressed\">    {:<16}[{}]  (removed by precedence)</span>", symbol, action)),
+                3 => try!(writeln!(w, "<span class=\"suppressed\">    {:<16}[{}]  (removed by %resolve)</span>", symbol, action)),
+                _ => try!(writeln!(w, "<span class=\"suppressed\">    {:<16}[{}]  (removed by %prefer)</span>", symbol, action)),
+            }
+        }
+        if let Some(rule) = parser.default_reduction(state.id) {
+            try!(writeln!(w, "    {:<16}reduce by rule {}", ".", rule_link(rule.index())));
+        }
+
+        for target in state.shifts.iter() {
+            let symbol = lr0.states[target.index()].accessing_symbol.index();
+            if gram.is_var(symbol) {
+                try!(writeln!(w, "    {:<16}go to {}", escape(gram.pname[symbol].as_slice()), state_link(target.index())));
+            }
+        }
+
+        for &(rule, ref tokens) in lalr.lookaheads_of(gram, state.id).iter() {
+            try!(writeln!(w, "  lookaheads of rule {}: {}", rule_link(rule.index()), tokens_html(gram, tokens.as_slice())));
+        }
+        try!(writeln!(w, "</pre></section>"));
+    }
+
+    try!(write!(w, "{}", SCRIPT));
+    try!(writeln!(w, "</body></html>"));
+    Ok(())
+}
+
+/// Returns the HTML report as a string.
+pub fn html_to_string(gram: &Grammar, lr0: &LR0Output, lalr: &LALROutput, parser: &YaccParser) -> String {
+    let mut buf: Vec<u8> = Vec::new();
+    write_html(&mut buf, gram, lr0, lalr, parser).unwrap();
+    String::from_utf8(buf).unwrap()
+}
+
+fn state_link(state: uint) -> String {
+    format!("<a href=\"#s{0}\">state {0}</a>", state)
+}
+
+fn rule_link(rule: uint) -> String {
+    format!("<a href=\"#r{0}\">{0}</a>", rule)
+}
+
+// A symbol, linked to its row in the table of non-terminals if it is one.
+fn symbol_html(gram: &Grammar, sym: uint) -> String {
+    if gram.is_var(sym) && sym != gram.start_symbol {
+        format!("<a href=\"#sym{}\">{}</a>", sym, escape(gram.pname[sym].as_slice()))
+    }
+    else {
+        escape(gram.pname[sym].as_slice())
+    }
+}
+
+fn tokens_html(gram: &Grammar, tokens: &[SymbolId]) -> String {
+    let names: Vec<String> = tokens.iter().map(|t| escape(gram.pname[t.index()].as_slice())).collect();
+    names.connect(" ")
+}
+
+fn rule_html(gram: &Grammar, rule: uint) -> String {
+    let mut s = symbol_html(gram, gram.rlhs[rule] as uint);
+    s.push_str(" :");
+    for &sym in gram.get_rhs_items(rule).iter() {
+        s.push(' ');
+        s.push_str(symbol_html(gram, sym as uint).as_slice());
+    }
+    s
+}
+
+// An item, as item_to_str writes it, with links to its rule and symbols.
+fn item_html(gram: &Grammar, item: ItemId) -> String {
+    let rule = item_rule(gram, item).index();
+    let start = gram.rrhs[rule] as uint;
+    let rhs = gram.get_rhs_items(rule);
+    let mut s = symbol_html(gram, gram.rlhs[rule] as uint);
+    s.push_str(" :");
+    for i in range(0, rhs.len() + 1) {
+        if start + i == item.index() {
+            s.push_str(" .");
+        }
+        if i < rhs.len() {
+            s.push(' ');
+            s.push_str(symbol_html(gram, rhs[i] as uint).as_slice());
+        }
+    }
+    s.push_str(format!("    ({})", rule_link(rule)).as_slice());
+    s
+}
+
+// Escapes text for HTML.
+fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '&' => out.push_str("&amp;"),
+            '"' => out.push_str("&quot;"),
+            _ => out.push(c)
+        }
+    }
+    out
+}
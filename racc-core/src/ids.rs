@@ -0,0 +1,194 @@
+// Typed indices for the states, symbols, rules, items, and gotos of a grammar and its automaton.
+//
+// The grammar stores symbols, rules, and items in i16 tables, exactly as Berkeley YACC does, and
+// the packed tables store states in i32 tables.  The analysis (lr0, closure, lalr) uses these
+// wrappers instead, so that a state cannot be passed where a symbol is expected, nor an item
+// where a rule is expected.  Each is a transparent wrapper around the index; use index() to get
+// it back, and from_i16() / to_i16() (or from_i32() / to_i32()) to convert to and from the
+// values stored in the tables.
+//
+// IdVec is a vector which is indexed by one kind of id, such as the rules of each symbol
+// (IdVec<SymbolId, Vec<RuleId>>), so that it cannot be indexed by another.
+
+use std::default::Default;
+use std::fmt;
+use std::ops::{Index, IndexMut};
+use std::slice;
+
+/// A state of the LR(0) automaton; an index into `LR0Output.states`.
+#[deriving(Copy,Clone,PartialEq,Eq,PartialOrd,Ord,Hash)]
+pub struct StateId(pub uint);
+
+/// A symbol (token or non-terminal); an index into `Grammar.name`.
+#[deriving(Copy,Clone,PartialEq,Eq,PartialOrd,Ord,Hash)]
+pub struct SymbolId(pub uint);
+
+/// A rule; an index into `Grammar.rlhs`.
+#[deriving(Copy,Clone,PartialEq,Eq,PartialOrd,Ord,Hash)]
+pub struct RuleId(pub uint);
+
+/// An item (a rule, and a position within its right-hand side); an index into `Grammar.ritem`.
+#[deriving(Copy,Clone,PartialEq,Eq,PartialOrd,Ord,Hash)]
+pub struct ItemId(pub uint);
+
+/// A goto (a transition on a non-terminal); an index into `GotoMap.from_state`.
+#[deriving(Copy,Clone,PartialEq,Eq,PartialOrd,Ord,Hash)]
+pub struct GotoId(pub uint);
+
+/// The conversions which all of the ids have, so that `IdVec` can be indexed by any of them.
+pub trait Id: Copy {
+    fn from_index(index: uint) -> Self;
+    fn to_index(self) -> uint;
+}
+
+macro_rules! impl_id {
+    ($name:ident, $prefix:expr) => {
+        impl $name {
+            /// Returns the index that this value wraps.
+            #[inline]
+            pub fn index(self) -> uint {
+                let $name(index) = self;
+                index
+            }
+
+            /// Converts a value stored in one of the `i16` tables.  Panics if `value` is negative.
+            #[inline]
+            pub fn from_i16(value: i16) -> $name {
+                assert!(value >= 0);
+                $name(value as uint)
+            }
+
+            /// Converts to the form stored in the `i16` tables.
+            #[inline]
+            pub fn to_i16(self) -> i16 {
+                self.index() as i16
+            }
+
+            /// Converts a value stored in one of the `i32` tables.  Panics if `value` is negative.
+            #[inline]
+            pub fn from_i32(value: i32) -> $name {
+                assert!(value >= 0);
+                $name(value as uint)
+            }
+
+            /// Converts to the form stored in the `i32` tables.
+            #[inline]
+            pub fn to_i32(self) -> i32 {
+                self.index() as i32
+            }
+        }
+
+        impl fmt::Show for $name {
+            fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+                write!(fmt, "{}{}", $prefix, self.index())
+            }
+        }
+
+        impl Id for $name {
+            #[inline]
+            fn from_index(index: uint) -> $name {
+                $name(index)
+            }
+
+            #[inline]
+            fn to_index(self) -> uint {
+                self.index()
+            }
+        }
+    }
+}
+
+impl_id! { StateId, "s" }
+impl_id! { SymbolId, "sym" }
+impl_id! { RuleId, "r" }
+impl_id! { ItemId, "i" }
+impl_id! { GotoId, "g" }
+
+/// A vector which is indexed by an id, `I`, rather than by `uint`.
+#[deriving(Clone,PartialEq,Show)]
+pub struct IdVec<I, T> {
+    pub data: Vec<T>,
+}
+
+impl<I: Id, T> IdVec<I, T> {
+    /// Creates an empty vector.
+    pub fn new() -> IdVec<I, T> {
+        IdVec { data: Vec::new() }
+    }
+
+    /// Creates a vector which holds `value` for each of the first `n` ids.
+    pub fn from_elem(n: uint, value: T) -> IdVec<I, T> where T: Clone {
+        IdVec { data: Vec::from_elem(n, value) }
+    }
+
+    /// Creates a vector from the values of the ids, in order.
+    pub fn from_vec(data: Vec<T>) -> IdVec<I, T> {
+        IdVec { data: data }
+    }
+
+    pub fn len(&self) -> uint {
+        self.data.len()
+    }
+
+    /// Appends a value, and returns its id.
+    pub fn push(&mut self, value: T) -> I {
+        self.data.push(value);
+        Id::from_index(self.data.len() - 1)
+    }
+
+    pub fn as_slice<'a>(&'a self) -> &'a [T] {
+        self.data.as_slice()
+    }
+
+    /// Iterates the values, in order of their ids.
+    pub fn iter<'a>(&'a self) -> slice::Items<'a, T> {
+        self.data.iter()
+    }
+
+    /// Iterates the ids, in order.
+    pub fn ids(&self) -> Ids<I> {
+        Ids { next: 0, end: self.data.len() }
+    }
+}
+
+impl<I: Id, T> Default for IdVec<I, T> {
+    fn default() -> IdVec<I, T> {
+        IdVec::new()
+    }
+}
+
+impl<I: Id, T> Index<I, T> for IdVec<I, T> {
+    #[inline]
+    fn index<'a>(&'a self, id: &I) -> &'a T {
+        &self.data[id.to_index()]
+    }
+}
+
+impl<I: Id, T> IndexMut<I, T> for IdVec<I, T> {
+    #[inline]
+    fn index_mut<'a>(&'a mut self, id: &I) -> &'a mut T {
+        &mut self.data[id.to_index()]
+    }
+}
+
+/// Iterates the ids of an `IdVec`, or the ids from one id up to (but not including) another;
+/// see `IdVec::ids` and `id_range`.
+pub struct Ids<I> {
+    next: uint,
+    end: uint,
+}
+
+/// Returns the ids from `first` up to (but not including) `end`.
+pub fn id_range<I: Id>(first: I, end: I) -> Ids<I> {
+    Ids { next: first.to_index(), end: end.to_index() }
+}
+
+impl<I: Id> Iterator<I> for Ids<I> {
+    fn next(&mut self) -> Option<I> {
+        if self.next >= self.end {
+            return None;
+        }
+        self.next += 1;
+        Some(Id::from_index(self.next - 1))
+    }
+}
@@ -0,0 +1,359 @@
+// Builds an IELR(1) automaton: one which has the conflicts of the canonical LR(1) automaton, but
+// which splits the LALR(1) states only where their lookaheads matter.
+//
+// The LALR(1) automaton merges every LR(1) state with the same LR(0) items, and the merged
+// lookaheads can give a state conflicts which no LR(1) state has (the "mysterious" conflicts of
+// LALR).  Such a conflict can only be on a token which already has more than one action in some
+// LALR(1) state, so IELR works from those:
+//
+// 1. Build the LR(0) automaton and its LALR(1) lookaheads.
+//
+// 2. Find the inadequate states: those with more than one action on some token, whether or not
+//    precedence resolves it.  Those tokens are the conflict tokens of the state.
+//
+// 3. Annotate each LR(0) state with the tokens that matter in it: the conflict tokens of the
+//    inadequate states which can be reached from it.  Only these tokens, in the lookaheads of
+//    its items, can change an action of an inadequate state.
+//
+// 4. Build the LR(1) automaton as lr1.rs does, but merge each new state into an existing state
+//    with the same LR(0) items unless their lookaheads differ in a token that matters.  When a
+//    merge adds lookaheads to a state which has already been expanded, the state is expanded
+//    again, so that the new lookaheads reach its successors.
+//
+// Two states are only merged when every token that matters is in the same lookaheads in both, so
+// each token that matters is in exactly the lookaheads that the canonical LR(1) automaton gives
+// it, and the inadequate states have the actions of the canonical automaton.  A grammar without
+// inadequate states gets the LALR(1) automaton, at little more than its cost.
+//
+// This differs from Bison's IELR(1) in one respect: Bison splits a state only when the merged
+// lookaheads would change which action wins, while this splits it whenever they differ in a
+// token that matters.  So this may split a few more states than Bison, but never fewer.
+
+use std::collections::{Bitv, HashMap};
+
+use grammar::Grammar;
+use lr0::{LR0Output, Core, Shifts, Reductions, compute_lr0};
+use lalr::{LALROutput, run_lalr, from_lookaheads};
+use lr1::{ItemSet, closure, set_first};
+use ids::{StateId, SymbolId, RuleId, ItemId, IdVec};
+use error::{GenError, MAX_TABLE_INDEX};
+
+struct IelrState<'a>
+{
+    gram: &'a Grammar,
+    nullable: Bitv,
+    first: Vec<Bitv>,
+    derives: IdVec<SymbolId, Vec<RuleId>>,
+
+    // For each LR(0) state: its transitions, as (symbol, target), and the tokens that matter.
+    lr0_gotos: Vec<Vec<(uint, uint)>>,
+    relevant: Vec<Bitv>,
+
+    kernels: Vec<ItemSet>,
+    accessing_symbol: Vec<uint>,
+    lr0_state: Vec<uint>,           // the LR(0) state that each state splits
+    expanded: Vec<bool>,            // the state has been expanded at least once
+    queued: Vec<bool>,              // the state is in `dirty`
+    dirty: Vec<uint>,               // expanded states whose lookaheads have grown since
+
+    // Maps an LR(0) state, and the tokens that matter in the lookaheads of each kernel item, to
+    // the state which has them.
+    state_table: HashMap<(uint, Vec<Vec<uint>>), uint>,
+}
+
+/// Computes the IELR(1) automaton of a grammar, and its lookaheads.  Fails if the automaton is
+/// too large for the tables to represent.
+pub fn compute_ielr1(gram: &Grammar) -> Result<(LR0Output, LALROutput), GenError>
+{
+    let lr0 = try!(compute_lr0(gram));
+    let lalr = try!(run_lalr(gram, &lr0));
+
+    let nstates = lr0.nstates();
+    let mut lr0_gotos: Vec<Vec<(uint, uint)>> = Vec::from_elem(nstates, Vec::new());
+    for sh in lr0.shifts.iter() {
+        lr0_gotos[sh.state.index()] = sh.shifts.iter().map(|&t| (lr0.states[t.index()].accessing_symbol.index(), t.index())).collect();
+    }
+
+    // The conflict tokens of each state, which are the first tokens that matter in it.
+    let mut relevant: Vec<Bitv> = range(0, nstates).map(|state| conflict_tokens(gram, &lr0, &lalr, state)).collect();
+    debug!("compute_ielr1: {} of {} LR(0) states are inadequate", relevant.iter().filter(|r| r.any()).count(), nstates);
+
+    // A token matters in a state if it matters in a state that it goes to.
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for state in range(0, nstates) {
+            for &(_, target) in lr0_gotos[state].iter() {
+                if target != state {
+                    let tokens = relevant[target].clone();
+                    if relevant[state].union(&tokens) {
+                        changed = true;
+                    }
+                }
+            }
+        }
+    }
+
+    let first = set_first(gram, &lr0.nullable);
+    let LR0Output { nullable, derives, .. } = lr0;
+    let mut ielr = IelrState {
+        gram: gram,
+        nullable: nullable,
+        first: first,
+        derives: derives,
+        lr0_gotos: lr0_gotos,
+        relevant: relevant,
+        kernels: Vec::new(),
+        accessing_symbol: Vec::new(),
+        lr0_state: Vec::new(),
+        expanded: Vec::new(),
+        queued: Vec::new(),
+        dirty: Vec::new(),
+        state_table: HashMap::new(),
+    };
+
+    // The initial state, as in lr1.rs.
+    let initial: ItemSet = ielr.derives[SymbolId(gram.start_symbol)].iter()
+        .map(|&rule| (gram.rrhs[rule.index()], Bitv::from_elem(gram.ntokens, false)))
+        .collect();
+    try!(get_state(&mut ielr, initial, 0, 0));
+
+    // Expand the states in order, and then those whose lookaheads grew after they were expanded,
+    // until nothing changes.
+    let mut targets: Vec<Vec<StateId>> = Vec::new();
+    let mut next: uint = 0;
+    loop {
+        let state = if next < ielr.kernels.len() {
+            next += 1;
+            next - 1
+        }
+        else {
+            match ielr.dirty.pop() {
+                Some(state) => {
+                    ielr.queued[state] = false;
+                    state
+                }
+                None => break
+            }
+        };
+        ielr.expanded[state] = true;
+        let state_targets = try!(expand(&mut ielr, state));
+        if state < targets.len() {
+            targets[state] = state_targets;
+        }
+        else {
+            targets.push(state_targets);
+        }
+    }
+    debug!("compute_ielr1: {} LR(0) states, {} IELR(1) states", nstates, ielr.kernels.len());
+
+    // The lookaheads are final now, so the reductions can be collected.
+    let mut shifts: Vec<Shifts> = Vec::new();
+    let mut reductions: Vec<Reductions> = Vec::new();
+    let mut la: Vec<Vec<uint>> = Vec::new();
+    for state in range(0, ielr.kernels.len()) {
+        let items = closure(gram, &ielr.nullable, ielr.first.as_slice(), &ielr.derives, ielr.kernels[state].as_slice());
+        let mut rules: Vec<RuleId> = Vec::new();
+        for &(item, ref lookahead) in items.iter() {
+            let symbol = gram.ritem[item as uint];
+            if symbol < 0 {
+                rules.push(RuleId::from_i16(-symbol));
+                la.push(range(0, gram.ntokens).filter(|&t| lookahead[t]).collect());
+            }
+        }
+        if rules.len() != 0 {
+            reductions.push(Reductions { state: StateId(state), rules: rules });
+        }
+        if targets[state].len() != 0 {
+            shifts.push(Shifts { state: StateId(state), shifts: targets[state].clone() });
+        }
+    }
+
+    let IelrState { nullable, derives, kernels, accessing_symbol, .. } = ielr;
+    let states: Vec<Core> = kernels.into_iter().zip(accessing_symbol.into_iter()).map(|(kernel, symbol)| Core {
+        accessing_symbol: SymbolId(symbol),
+        items: kernel.iter().map(|&(item, _)| ItemId::from_i16(item)).collect(),
+    }).collect();
+    let lr0 = LR0Output {
+        states: states,
+        shifts: shifts,
+        reductions: reductions,
+        nullable: nullable,
+        derives: derives,
+    };
+    let lalr = try!(from_lookaheads(gram, &lr0, la.as_slice()));
+    Ok((lr0, lalr))
+}
+
+// Returns the tokens which have more than one action in an LALR(1) state: a shift and a
+// reduction, or two reductions.
+fn conflict_tokens(gram: &Grammar, lr0: &LR0Output, lalr: &LALROutput, state: uint) -> Bitv
+{
+    let mut seen = Bitv::from_elem(gram.ntokens, false);
+    if let Some(sp) = lalr.shift_table[StateId(state)] {
+        for &target in lr0.shifts[sp].shifts.iter() {
+            let symbol = lr0.states[target.index()].accessing_symbol.index();
+            if gram.is_token(symbol) {
+                seen.set(symbol, true);
+            }
+        }
+    }
+    let mut conflicts = Bitv::from_elem(gram.ntokens, false);
+    for (_, tokens) in lalr.lookaheads_of(gram, StateId(state)).into_iter() {
+        for t in tokens.iter() {
+            if seen[t.index()] {
+                conflicts.set(t.index(), true);
+            }
+            seen.set(t.index(), true);
+        }
+    }
+    conflicts
+}
+
+// Expands a state: computes its closure, and finds or creates the state that each symbol goes
+// to.  Returns the targets, in ascending order of symbol.
+fn expand(ielr: &mut IelrState, state: uint) -> Result<Vec<StateId>, GenError>
+{
+    let gram = ielr.gram;
+    let items = closure(gram, &ielr.nullable, ielr.first.as_slice(), &ielr.derives, ielr.kernels[state].as_slice());
+
+    // Every item which is not at the end of its rule shifts its next symbol.  ($end is never
+    // shifted.)
+    let mut symbols: Vec<uint> = items.iter()
+        .map(|&(item, _)| gram.ritem[item as uint])
+        .filter(|&symbol| symbol > 0)
+        .map(|symbol| symbol as uint)
+        .collect();
+    symbols.sort();
+    symbols.dedup();
+
+    let lr0_state = ielr.lr0_state[state];
+    let mut targets: Vec<StateId> = Vec::with_capacity(symbols.len());
+    for &symbol in symbols.iter() {
+        let kernel: ItemSet = items.iter()
+            .filter(|&&(item, _)| gram.ritem[item as uint] as uint == symbol)
+            .map(|&(item, ref lookahead)| (item + 1, lookahead.clone()))
+            .collect();
+        let lr0_target = match ielr.lr0_gotos[lr0_state].iter().find(|&&(s, _)| s == symbol) {
+            Some(&(_, target)) => target,
+            None => panic!("LR(0) state {} has no transition on {}", lr0_state, gram.name[symbol])
+        };
+        let target = try!(get_state(ielr, kernel, symbol, lr0_target));
+        targets.push(StateId(target));
+    }
+    Ok(targets)
+}
+
+// Finds the state which splits `lr0_state` and whose lookaheads agree with `kernel` in the tokens
+// that matter, and adds the lookaheads of `kernel` to it; or creates the state.
+fn get_state(ielr: &mut IelrState, kernel: ItemSet, symbol: uint, lr0_state: uint) -> Result<uint, GenError>
+{
+    let key: Vec<Vec<uint>> = {
+        let relevant = &ielr.relevant[lr0_state];
+        kernel.iter().map(|&(_, ref lookahead)| {
+            range(0, ielr.gram.ntokens).filter(|&t| lookahead[t] && relevant[t]).collect()
+        }).collect()
+    };
+    let key = (lr0_state, key);
+
+    if let Some(&state) = ielr.state_table.get(&key) {
+        let mut grew = false;
+        for (&mut (_, ref mut lookahead), &(_, ref more)) in ielr.kernels[state].iter_mut().zip(kernel.iter()) {
+            if lookahead.union(more) {
+                grew = true;
+            }
+        }
+        if grew && ielr.expanded[state] && !ielr.queued[state] {
+            ielr.queued[state] = true;
+            ielr.dirty.push(state);
+        }
+        return Ok(state);
+    }
+
+    if ielr.kernels.len() >= MAX_TABLE_INDEX {
+        return Err(GenError::TooManyStates(ielr.gram.name[symbol].clone()));
+    }
+
+    let state = ielr.kernels.len();
+    ielr.kernels.push(kernel);
+    ielr.accessing_symbol.push(symbol);
+    ielr.lr0_state.push(lr0_state);
+    ielr.expanded.push(false);
+    ielr.queued.push(false);
+    ielr.state_table.insert(key, state);
+    Ok(state)
+}
+
+#[cfg(test)]
+mod tests {
+    use grammar::Grammar;
+    use lr0::compute_lr0;
+    use lr1::compute_lr1;
+    use mkpar::make_parser;
+    use ids::{StateId, RuleId};
+    use yacc::read_yacc;
+    use super::compute_ielr1;
+
+    // Two grammars in one.  After `a c` and `b c`, the LALR(1) state has reduce/reduce conflicts
+    // on d and e, which the canonical automaton does not have, so it must be split.  After x, the
+    // canonical automaton splits four states, which IELR keeps merged, since no lookahead of
+    // theirs leads to a conflict.  Rules 3 to 13; A : c is rule 9, and R : L is rule 13.
+    const GRAMMAR: &'static str = "%token a b c d e x EQ STAR ID\n%%\n\
+        S : a A d | b B d | a B e | b A e | x L EQ R | x R ;\n\
+        A : c ;\nB : c ;\nL : STAR R | ID ;\nR : L ;\n";
+
+    fn grammar(text: &str) -> Grammar {
+        match read_yacc(text) {
+            Ok(y) => y.grammar,
+            Err(_) => panic!("cannot read the grammar")
+        }
+    }
+
+    #[test]
+    fn splits_only_inadequate_states() {
+        let gram = grammar(GRAMMAR);
+        let (lr0, lalr) = compute_ielr1(&gram).unwrap();
+        // The LALR(1) automaton has 22 states, and the canonical one 27.
+        assert_eq!(compute_lr0(&gram).unwrap().nstates(), 22);
+        let (canonical, _) = compute_lr1(&gram).unwrap();
+        assert_eq!(canonical.nstates(), 27);
+        assert_eq!(lr0.nstates(), 23);
+
+        let lookaheads = |rule: uint| -> Vec<Vec<String>> {
+            let mut sets: Vec<Vec<String>> = Vec::new();
+            for state in range(0, lr0.nstates()) {
+                for (r, tokens) in lalr.lookaheads_of(&gram, StateId(state)).into_iter() {
+                    if r == RuleId(rule) {
+                        sets.push(tokens.iter().map(|t| gram.name[t.index()].clone()).collect());
+                    }
+                }
+            }
+            sets.sort();
+            sets
+        };
+        assert_eq!(lookaheads(9), vec![vec!["d".to_string()], vec!["e".to_string()]]);
+        assert_eq!(lookaheads(13), vec![vec!["$end".to_string()], vec!["$end".to_string(), "EQ".to_string()]]);
+
+        let parser = make_parser(&gram, &lr0, &lalr);
+        assert_eq!((parser.sr_conflicts, parser.rr_conflicts), (0, 0));
+    }
+
+    #[test]
+    fn same_as_lalr_without_inadequate_states() {
+        // LALR(1), so nothing is split, although the canonical automaton has 14 states.
+        let gram = grammar("%token EQ STAR ID\n%%\nS : L EQ R | R ;\nL : STAR R | ID ;\nR : L ;\n");
+        let (lr0, _) = compute_ielr1(&gram).unwrap();
+        assert_eq!(lr0.nstates(), compute_lr0(&gram).unwrap().nstates());
+        assert_eq!(lr0.nstates(), 10);
+    }
+
+    #[test]
+    fn keeps_real_conflicts() {
+        // Ambiguous, so the conflicts are in every LR(1) automaton, and splitting cannot help.
+        let gram = grammar("%token NUM PLUS\n%%\ne : e PLUS e | NUM ;\n");
+        let (lr0, lalr) = compute_ielr1(&gram).unwrap();
+        assert_eq!(lr0.nstates(), compute_lr0(&gram).unwrap().nstates());
+        let parser = make_parser(&gram, &lr0, &lalr);
+        assert_eq!((parser.sr_conflicts, parser.rr_conflicts), (1, 0));
+    }
+}
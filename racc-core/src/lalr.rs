@@ -0,0 +1,807 @@
+use grammar::Grammar;
+use util::{Bitmat,reverse_range};
+use lr0::LR0Output;
+use ids::{StateId,SymbolId,RuleId,GotoId,IdVec,Ids,id_range};
+use error::{GenError,MAX_TABLE_INDEX};
+
+/// The LALR(1) lookaheads of an LR(0) automaton.
+///
+/// For state `s`, the reductions possible in that state are numbered from `lookaheads[s]` up to
+/// (but not including) `lookaheads[s + 1]`.  For each such reduction `i`, `laruleno[i]` is the
+/// rule being reduced, and row `i` of `LA` is the set of tokens for which the reduction applies.
+///
+/// `shift_table` and `reduction_table` map states to indices into `LR0Output.shifts` and
+/// `LR0Output.reductions` (None for a state which has no shifts, or no reductions), and are
+/// internal.
+#[allow(non_snake_case)]
+pub struct LALROutput {
+    pub shift_table: IdVec<StateId, Option<uint>>,
+    pub reduction_table: IdVec<StateId, Option<uint>>,
+    pub lookaheads: Vec<i32>,
+    pub laruleno: Vec<RuleId>,
+    pub LA: Bitmat,
+    pub gotos: GotoMap
+}
+
+impl LALROutput
+{
+    /// Returns the reductions possible in `state`, each with the set of tokens for which it
+    /// applies (its lookahead set), in ascending order.
+    pub fn lookaheads_of(&self, gram: &Grammar, state: StateId) -> Vec<(RuleId, Vec<SymbolId>)> {
+        let first = self.lookaheads[state.index()] as uint;
+        let last = self.lookaheads[state.index() + 1] as uint;
+        range(first, last).map(|i| {
+            let tokens = range(0, gram.ntokens).filter(|&t| self.LA.get(i, t)).map(|t| SymbolId(t)).collect();
+            (self.laruleno[i], tokens)
+        }).collect()
+    }
+}
+
+/// All of the gotos (transitions on non-terminals) of the automaton.
+///
+/// The gotos on variable `v` (numbered from 0, so `v = symbol - ntokens`) are numbered from
+/// `goto_map[v]` up to `goto_map[v + 1]`, in ascending order of the state they leave from.
+/// Goto `g` goes from `from_state[g]` to `to_state[g]`.
+pub struct GotoMap {
+    pub ngotos: uint,
+    pub goto_map: Vec<GotoId>,
+    pub from_state: IdVec<GotoId, StateId>,
+    pub to_state: IdVec<GotoId, StateId>
+}
+
+impl GotoMap
+{
+    /// Returns the gotos on `symbol`, which must be a variable.
+    pub fn gotos_on(&self, gram: &Grammar, symbol: SymbolId) -> Ids<GotoId> {
+        let var = symbol.index() - gram.ntokens;
+        id_range(self.goto_map[var], self.goto_map[var + 1])
+    }
+}
+
+/// Computes the LALR(1) lookaheads of an LR(0) automaton.  Fails if the automaton has too many
+/// gotos for the tables to represent.
+#[allow(non_snake_case)]
+pub fn run_lalr(gram: &Grammar, lr0: &LR0Output) -> Result<LALROutput, GenError>
+{
+    let shift_table = set_shift_table(lr0);
+    let reduction_table = set_reduction_table(lr0);
+    let lookaheads = create_lookaheads(lr0, &reduction_table);
+
+    // the LA and lookback tables have len() = LA_len
+    let LA_len = lookaheads[lookaheads.len() - 1] as uint;
+
+    let laruleno = initialize_LA(lr0, LA_len, &reduction_table);
+    let gotos = try!(set_goto_map(gram, lr0));
+
+    let mut F = initialize_F(gram, lr0, &gotos, &shift_table);
+
+    let (includes, lookback) = build_relations(gram, lr0, &shift_table, &gotos, lookaheads.as_slice(), laruleno.as_slice(), LA_len);
+    
+    compute_FOLLOWS(&includes, &mut F);
+    
+    let LA = compute_lookaheads(gram, lr0, lookaheads.as_slice(), lookback, F);
+
+    Ok(LALROutput {
+        shift_table: shift_table,
+        reduction_table: reduction_table,
+        laruleno: laruleno,
+        lookaheads: lookaheads,
+        LA: LA,
+        gotos: gotos
+    })
+}
+
+// Builds a table which maps from states to shifts.  The value for each state is either None,
+// for states that do not have any shifts, or an index into LR0Output.shifts.
+fn set_shift_table(lr0: &LR0Output) -> IdVec<StateId, Option<uint>>
+{
+    let mut shift_table: IdVec<StateId, Option<uint>> = IdVec::from_elem(lr0.states.len(), None);
+    for (i, sp) in lr0.shifts.iter().enumerate() {
+        assert!(shift_table[sp.state].is_none());
+        shift_table[sp.state] = Some(i);
+    }
+    shift_table
+}
+
+// Builds a table which maps from states to reductions.  The value for each state is either None,
+// for states that do not have any reductions, or an index into LR0Output.reductions.
+fn set_reduction_table(lr0: &LR0Output) -> IdVec<StateId, Option<uint>>
+{
+    let mut reduction_table: IdVec<StateId, Option<uint>> = IdVec::from_elem(lr0.states.len(), None);
+    for (i, rp) in lr0.reductions.iter().enumerate() {
+        assert!(reduction_table[rp.state].is_none());
+        reduction_table[rp.state] = Some(i);
+    }
+    reduction_table
+}
+
+/// Builds an `LALROutput` for an automaton whose lookaheads are already known, such as a canonical
+/// LR(1) automaton.  `la[i]` lists the tokens of the lookahead set of reduction `i`, where the
+/// reductions are numbered as in `laruleno`: by state, then in the order of `Reductions.rules`.
+/// Fails if the automaton has too many gotos for the tables to represent.
+#[allow(non_snake_case)]
+pub fn from_lookaheads(gram: &Grammar, lr0: &LR0Output, la: &[Vec<uint>]) -> Result<LALROutput, GenError>
+{
+    let shift_table = set_shift_table(lr0);
+    let reduction_table = set_reduction_table(lr0);
+    let lookaheads = create_lookaheads(lr0, &reduction_table);
+    let LA_len = lookaheads[lookaheads.len() - 1] as uint;
+    assert!(la.len() == LA_len);
+
+    let laruleno = initialize_LA(lr0, LA_len, &reduction_table);
+    let gotos = try!(set_goto_map(gram, lr0));
+
+    let mut LA = Bitmat::new(LA_len, gram.ntokens);
+    for (i, tokens) in la.iter().enumerate() {
+        for &t in tokens.iter() {
+            LA.set(i, t);
+        }
+    }
+
+    Ok(LALROutput {
+        shift_table: shift_table,
+        reduction_table: reduction_table,
+        laruleno: laruleno,
+        lookaheads: lookaheads,
+        LA: LA,
+        gotos: gotos
+    })
+}
+
+// Finds the longest rhs
+fn set_max_rhs(gram: &Grammar) -> uint
+{
+    let mut length: uint = 0;
+    let mut max: uint = 0;
+    for itemp in range(0, gram.nitems) {
+        if gram.ritem[itemp] >= 0 {
+            length += 1;
+        }
+        else {
+            if length > max {
+                max = length;
+            }
+            length = 0;
+        }
+    }
+    max
+}
+
+// Creates the 'lookaheads' table.  (This is not the same as the LA table.)
+// The index of each entry in 'lookaheads' corresponds to a state, plus one
+// extra entry at the end which corresponds to nstates.  The value of each
+// entry is the sum of rules for all reductions in the previous states.
+fn create_lookaheads(lr0: &LR0Output, reduction_table: &IdVec<StateId, Option<uint>>) -> Vec<i32> {
+    let mut lookaheads: Vec<i32> = Vec::with_capacity(lr0.states.len() + 1);
+
+    // Count the total number of reductions, and also build the lookaheads table.
+    let mut k = 0;
+    for &rp in reduction_table.iter() {
+        lookaheads.push(k as i32);
+        if let Some(rp) = rp {
+            k += lr0.reductions[rp].rules.len();
+        }
+    }
+    lookaheads.push(k as i32);
+    assert!(lookaheads.len() == lr0.states.len() + 1);
+    lookaheads    
+}
+
+#[allow(non_snake_case)]
+fn initialize_LA(lr0: &LR0Output, LA_len: uint, reduction_table: &IdVec<StateId, Option<uint>>) -> Vec<RuleId> {
+    let mut laruleno: Vec<RuleId> = Vec::with_capacity(LA_len);
+    for &rp in reduction_table.iter() {
+        if let Some(rp) = rp {
+            laruleno.push_all(lr0.reductions[rp].rules.as_slice());
+        }
+    }
+    assert!(laruleno.len() == LA_len);
+    laruleno
+}
+
+fn set_goto_map(gram: &Grammar, lr0: &LR0Output) -> Result<GotoMap, GenError> {
+    // Count the number of gotos for each variable.
+    let mut goto_count: Vec<uint> = Vec::from_elem(gram.nvars, 0);
+    let mut ngotos: uint = 0;
+    for sp in lr0.shifts.iter() {
+        for i in reverse_range(sp.shifts.len(), 0) {
+            let state = sp.shifts[i].index();
+            let symbol = lr0.states[state].accessing_symbol.index();
+
+            if gram.is_token(symbol) {
+                break;
+            }
+
+            if ngotos >= MAX_TABLE_INDEX {
+                return Err(GenError::TooManyGotos(gram.name[symbol].clone()));
+            }
+            ngotos += 1;
+            goto_count[symbol - gram.ntokens] += 1;
+        }
+    }
+    let ngotos = ngotos;
+
+    // Next, we essentially "integrate" (in the numerical sense) goto_count: the gotos of each
+    // variable begin after the gotos of all of the variables before it.  next_goto starts as a
+    // copy of goto_map, and is advanced as the gotos of each variable are numbered.
+    let mut goto_map: Vec<GotoId> = Vec::with_capacity(gram.nvars + 1);
+    let mut k: uint = 0;
+    for i in range(0, gram.nvars) {
+        goto_map.push(GotoId(k));
+        k += goto_count[i];
+    }
+    goto_map.push(GotoId(ngotos));
+    let mut next_goto: Vec<uint> = goto_map.iter().map(|g| g.index()).collect();
+
+    let mut from_state: IdVec<GotoId, StateId> = IdVec::from_elem(ngotos, StateId(0));
+    let mut to_state: IdVec<GotoId, StateId> = IdVec::from_elem(ngotos, StateId(0));
+
+    for sp in lr0.shifts.iter() {
+        for i in reverse_range(sp.shifts.len(), 0) {
+            let state2 = sp.shifts[i];
+            let symbol = lr0.states[state2.index()].accessing_symbol.index();
+            if gram.is_token(symbol) {
+                break;
+            }
+
+            let k = GotoId(next_goto[symbol - gram.ntokens]);
+            next_goto[symbol - gram.ntokens] += 1;
+            from_state[k] = sp.state;
+            to_state[k] = state2;
+        }
+    }
+
+    debug!("set_goto_map: ngotos={}", ngotos);
+    for i in from_state.ids() {
+        debug!("    {}: from {} to {}", i, from_state[i], to_state[i]);
+    }
+
+    debug!("goto_map:");
+    for i in range(0, gram.nvars) {
+        debug!("    {:3} {} --> {}", i, gram.name[i + gram.ntokens], goto_map[i]);
+    }
+    debug!(".");
+
+    Ok(GotoMap { ngotos: ngotos, goto_map: goto_map, from_state: from_state, to_state: to_state })
+}
+
+// Finds the goto on `symbol` (a variable) from `state`.  There must be one.
+fn map_goto(
+    gram: &Grammar,
+    gotos: &GotoMap,
+    state: StateId, 
+    symbol: SymbolId) -> GotoId
+{
+    let var = symbol.index() - gram.ntokens;
+    let mut low = gotos.goto_map[var].index();
+    let mut high = gotos.goto_map[var + 1].index();
+
+    // The gotos on each variable are ordered by from_state.
+    loop {
+        assert!(low < high);
+        let middle = GotoId((low + high) >> 1);
+        let s = gotos.from_state[middle];
+        if s == state {
+            return middle;
+        }
+        if s < state {
+            low = middle.index() + 1;
+        }
+        else {
+            high = middle.index();
+        }
+    }
+}
+
+#[allow(non_snake_case)]
+fn initialize_F(
+    gram: &Grammar, 
+    lr0: &LR0Output,
+    gotos: &GotoMap,
+    shift_table: &IdVec<StateId, Option<uint>>) -> Bitmat
+{
+    debug!("initialize_F");
+
+    let ngotos = gotos.ngotos;
+    let mut F = Bitmat::new(ngotos, gram.ntokens);
+    let mut reads: IdVec<GotoId, Vec<GotoId>> = IdVec::from_elem(ngotos, Vec::new());
+
+    for i in gotos.to_state.ids() {
+        let stateno = gotos.to_state[i];
+
+        if let Some(sp) = shift_table[stateno] {
+            let sp = &lr0.shifts[sp];
+            let k = sp.shifts.len();
+
+            let mut j: uint = 0;
+            while j < k {
+                let symbol = lr0.states[sp.shifts[j].index()].accessing_symbol;
+                if gram.is_var(symbol.index()) {
+                    break;
+                }
+                F.set(i.index(), symbol.index());
+                j += 1;
+            }
+
+            while j < k {
+                let symbol = lr0.states[sp.shifts[j].index()].accessing_symbol;
+                if lr0.nullable[symbol.index()] {
+                    reads[i].push(map_goto(gram, gotos, stateno, symbol));
+                }
+                j += 1;
+            }
+        }
+    }
+
+    F.set(0, 0);
+    digraph(&reads, &mut F);
+    F
+}
+
+#[allow(non_snake_case)]
+fn build_relations(
+    gram: &Grammar,
+    lr0: &LR0Output,
+    shift_table: &IdVec<StateId, Option<uint>>,
+    gotos: &GotoMap,
+    lookaheads: &[i32],
+    laruleno: &[RuleId],
+    LA_len: uint) -> (IdVec<GotoId, Vec<GotoId>>, /*lookback:*/ Vec<Vec<GotoId>>)
+{
+    debug!("build_relations");
+
+    let ngotos = gotos.ngotos;
+    let mut includes: IdVec<GotoId, Vec<GotoId>> = IdVec::from_elem(ngotos, Vec::new());
+    let mut states: Vec<StateId> = Vec::with_capacity(set_max_rhs(gram) + 1);   // temporary, reused in loops
+    let mut lookback: Vec<Vec<GotoId>> = Vec::from_elem(LA_len, Vec::new());
+
+    for i in gotos.from_state.ids() {
+        let state1 = gotos.from_state[i];
+        let symbol1 = lr0.states[gotos.to_state[i].index()].accessing_symbol;
+
+        for &rule in lr0.derives[symbol1].iter() {
+            assert!(states.len() == 0);
+            states.push(state1);
+            let mut stateno = state1;
+            let mut rp: uint = gram.rrhs[rule.index()] as uint;
+            while gram.ritem[rp] >= 0 {
+                let symbol2 = SymbolId(gram.ritem[rp] as uint);
+                let sp = shift_table[stateno].expect("build_relations: state has no shifts");
+                for &shift in lr0.shifts[sp].shifts.iter() {
+                    stateno = shift;
+                    if lr0.states[stateno.index()].accessing_symbol == symbol2 {
+                        break;
+                    }
+                }
+
+                states.push(stateno);
+                rp += 1;
+            }
+
+            add_lookback_edge(stateno, rule, i, laruleno, lookaheads, &mut lookback);
+
+            let mut length = states.len() - 1;
+            let mut done_flag = false;
+            while !done_flag {
+                done_flag = true;
+                rp -= 1;
+                if gram.ritem[rp] >= 0 && gram.is_var(gram.ritem[rp] as uint) {
+                    let symbol = SymbolId(gram.ritem[rp] as uint);
+                    length -= 1;
+                    stateno = states[length];
+                    includes[i].push(map_goto(gram, gotos, stateno, symbol));
+                    if lr0.nullable[symbol.index()] && length > 0 {
+                        done_flag = false;
+                    }
+                }
+            }
+            states.clear(); // prepare for next use
+        }
+    }
+
+    (transpose(&includes), lookback)
+}
+
+// Adds an entry to the 'lookback' table.
+fn add_lookback_edge(stateno: StateId, rule: RuleId, gotono: GotoId, laruleno: &[RuleId], lookaheads: &[i32], lookback: &mut Vec<Vec<GotoId>>)
+{
+    let mut i = lookaheads[stateno.index()] as uint;
+    let k = lookaheads[stateno.index() + 1] as uint;
+    loop {
+        assert!(i < k);
+        if laruleno[i] == rule {
+            lookback[i].insert(0, gotono);
+            break;
+        }
+        else {
+            i += 1;
+        }
+    }
+}
+
+// Reverses the edges of a relation.  The edges of each goto in the result are in ascending order.
+fn transpose(relation: &IdVec<GotoId, Vec<GotoId>>) -> IdVec<GotoId, Vec<GotoId>>
+{
+    let mut reversed: IdVec<GotoId, Vec<GotoId>> = IdVec::from_elem(relation.len(), Vec::new());
+    for i in relation.ids() {
+        for &j in relation[i].iter() {
+            reversed[j].push(i);
+        }
+    }
+    reversed
+}
+
+#[allow(non_snake_case)]
+fn compute_FOLLOWS(includes: &IdVec<GotoId, Vec<GotoId>>, F: &mut Bitmat) {
+    digraph(includes, F);
+}
+
+// Computes rows first..last of LA into `out`.  Each row of LA (a reduction) is the union of the
+// rows of F (the follow sets of gotos) in the lookback list of the reduction.
+#[allow(non_snake_case)]
+fn union_lookbacks(first: uint, last: uint, lookback: &[Vec<GotoId>], F: &Bitmat, out: &mut [u32]) {
+    let rowsize = F.rowsize;
+    for i in range(first, last) {
+        let row = out.slice_mut((i - first) * rowsize, (i - first + 1) * rowsize);
+        for &sp in lookback[i].iter() {
+            let frow = F.data.slice(sp.index() * rowsize, (sp.index() + 1) * rowsize);
+            for (a, &b) in row.iter_mut().zip(frow.iter()) {
+                *a |= b;
+            }
+        }
+    }
+}
+
+#[cfg(not(feature = "parallel"))]
+#[allow(non_snake_case)]
+fn compute_lookaheads(gram: &Grammar, lr0: &LR0Output, lookaheads: &[i32], lookback: Vec<Vec<GotoId>>, F: Bitmat) -> Bitmat {
+    let n = lookaheads[lr0.nstates()] as uint;
+    let mut LA = Bitmat::new(n, gram.ntokens);
+
+    assert!(F.cols == LA.cols);
+    assert!(F.rowsize == LA.rowsize);
+
+    union_lookbacks(0, n, lookback.as_slice(), &F, LA.data.as_mut_slice());
+    LA
+}
+
+// Grammars with fewer reductions than this are not worth the cost of starting threads.
+#[cfg(feature = "parallel")]
+const PARALLEL_MIN_ROWS: uint = 4096;
+
+// With the "parallel" feature, the rows of LA are split into one block per CPU, and each block is
+// computed on its own thread.  The rows are independent, so the result is the same as computing
+// them in order.  The follow sets (F) that they are computed from are found in parallel by
+// digraph(), below.
+#[cfg(feature = "parallel")]
+#[allow(non_snake_case)]
+fn compute_lookaheads(gram: &Grammar, lr0: &LR0Output, lookaheads: &[i32], lookback: Vec<Vec<GotoId>>, F: Bitmat) -> Bitmat {
+    use std::cmp::min;
+    use std::iter::range_step;
+    use std::os::num_cpus;
+    use std::sync::Arc;
+    use std::thread::{Thread, JoinGuard};
+
+    let n = lookaheads[lr0.nstates()] as uint;
+    let mut LA = Bitmat::new(n, gram.ntokens);
+
+    assert!(F.cols == LA.cols);
+    assert!(F.rowsize == LA.rowsize);
+
+    let nthreads = num_cpus();
+    if nthreads <= 1 || n < PARALLEL_MIN_ROWS {
+        union_lookbacks(0, n, lookback.as_slice(), &F, LA.data.as_mut_slice());
+        return LA;
+    }
+
+    let rowsize = LA.rowsize;
+    let block = (n + nthreads - 1) / nthreads;
+    let lookback = Arc::new(lookback);
+    let F = Arc::new(F);
+    let guards: Vec<JoinGuard<Vec<u32>>> = range_step(0, n, block).map(|first| {
+        let last = min(first + block, n);
+        let lookback = lookback.clone();
+        let F = F.clone();
+        Thread::scoped(move || {
+            let mut rows: Vec<u32> = Vec::from_elem((last - first) * rowsize, 0);
+            union_lookbacks(first, last, lookback.as_slice(), &*F, rows.as_mut_slice());
+            rows
+        })
+    }).collect();
+
+    for (i, guard) in guards.into_iter().enumerate() {
+        let rows = guard.join().ok().expect("a thread computing lookaheads panicked");
+        let out = LA.data.slice_from_mut(i * block * rowsize);
+        for (a, &b) in out.iter_mut().zip(rows.iter()) {
+            *a = b;
+        }
+    }
+    LA
+}
+
+// state for recursion
+#[allow(non_snake_case)]
+struct DigraphState<'a> {
+    infinity: uint,
+    index: Vec<uint>,
+    vertices: Vec<GotoId>,
+    top: uint,
+    R: &'a IdVec<GotoId, Vec<GotoId>>,
+    F: &'a mut Bitmat
+}
+
+#[cfg(not(feature = "parallel"))]
+#[allow(non_snake_case)]
+fn digraph(relation: &IdVec<GotoId, Vec<GotoId>>, F: &mut Bitmat) {
+    serial_digraph(relation, F);
+}
+
+// For each goto i, sets row i of F to the union of the rows of all of the gotos that i reaches
+// through the relation, including itself.  This is the DeRemer and Pennello algorithm, which
+// finds the strongly connected components of the relation as it goes.
+#[allow(non_snake_case)]
+fn serial_digraph(relation: &IdVec<GotoId, Vec<GotoId>>, F: &mut Bitmat) {
+    let ngotos = F.rows;
+    let mut ds = DigraphState {
+        infinity: ngotos + 2,
+        index: Vec::from_elem(ngotos + 1, 0),
+        vertices: Vec::from_elem(ngotos + 1, GotoId(0)),
+        top: 0,
+        R: relation,
+        F: F
+    };
+
+    for i in relation.ids() {
+        if ds.index[i.index()] == 0 && relation[i].len() != 0 {
+            traverse(&mut ds, i);
+        }
+    }
+}
+
+fn traverse(ds: &mut DigraphState, i: GotoId) {
+    ds.top += 1;
+    ds.vertices[ds.top] = i;
+    let height = ds.top;
+    ds.index[i.index()] = ds.top;
+
+    let base = i.index() * ds.F.rowsize;
+    let fp3 = base + ds.F.rowsize;
+
+    let relation = ds.R;
+    for &j in relation[i].iter() {
+        if ds.index[j.index()] == 0 {
+            traverse(ds, j);
+        }
+
+        if ds.index[i.index()] > ds.index[j.index()] {
+            ds.index[i.index()] = ds.index[j.index()];
+        }
+
+        let mut fp1 = base;
+        let mut fp2 = j.index() * ds.F.rowsize;
+
+        while fp1 < fp3 {
+            ds.F.data[fp1] |= ds.F.data[fp2];
+            fp1 += 1;
+            fp2 += 1;
+        }
+    }
+
+    if ds.index[i.index()] == height {
+        loop {
+            let j = ds.vertices[ds.top];
+            ds.top -= 1;
+            ds.index[j.index()] = ds.infinity;
+
+            if i == j {
+                break;
+            }
+
+            let mut fp1 = base;
+            let mut fp2 = j.index() * ds.F.rowsize;
+
+            while fp1 < fp3 {
+                ds.F.data[fp2] = ds.F.data[fp1];
+                fp1 += 1;
+                fp2 += 1;
+            }
+        }
+    }
+}
+
+// Relations with fewer gotos than this are not worth the cost of starting threads.
+#[cfg(feature = "parallel")]
+const PARALLEL_MIN_GOTOS: uint = 4096;
+
+// Levels with fewer components than this are computed on the calling thread.
+#[cfg(feature = "parallel")]
+const PARALLEL_MIN_COMPONENTS: uint = 256;
+
+// With the "parallel" feature, digraph() first finds the strongly connected components of the
+// relation, all of whose gotos get the same row of F, and sorts them into levels, so that every
+// component that a component has edges to is in a lower level.  The rows of the components in a
+// level depend only on the rows of lower levels, so each level is split into one block per CPU,
+// and each block is computed on its own thread.  The result is the same as serial_digraph().
+#[cfg(feature = "parallel")]
+#[allow(non_snake_case)]
+fn digraph(relation: &IdVec<GotoId, Vec<GotoId>>, F: &mut Bitmat) {
+    use std::cmp::{min, max};
+    use std::iter::range_step;
+    use std::mem::replace;
+    use std::os::num_cpus;
+    use std::sync::Arc;
+    use std::thread::{Thread, JoinGuard};
+
+    let nthreads = num_cpus();
+    if nthreads <= 1 || F.rows < PARALLEL_MIN_GOTOS {
+        serial_digraph(relation, F);
+        return;
+    }
+
+    let (component_of, members) = find_components(relation);
+
+    // The components that each component has edges to, and the components of each level.
+    // Components which have one goto and no edges keep their rows, and are left out.
+    let mut successors: Vec<Vec<uint>> = Vec::with_capacity(members.len());
+    let mut level_of: Vec<uint> = Vec::with_capacity(members.len());
+    let mut levels: Vec<Vec<uint>> = Vec::new();
+    for c in range(0, members.len()) {
+        let mut succ: Vec<uint> = Vec::new();
+        for &i in members[c].iter() {
+            for &j in relation[i].iter() {
+                if component_of[j.index()] != c {
+                    succ.push(component_of[j.index()]);
+                }
+            }
+        }
+        succ.sort();
+        succ.dedup();
+
+        // find_components() returns each component after those that it has edges to.
+        let level = succ.iter().fold(0, |level, &s| max(level, level_of[s] + 1));
+        if members[c].len() > 1 || succ.len() != 0 {
+            if level == levels.len() {
+                levels.push(Vec::new());
+            }
+            levels[level].push(c);
+        }
+        level_of.push(level);
+        successors.push(succ);
+    }
+
+    let rowsize = F.rowsize;
+    let members = Arc::new(members);
+    let successors = Arc::new(successors);
+    let mut shared = Arc::new(replace(F, Bitmat::new(0, 0)));
+
+    for level in levels.into_iter() {
+        let rows: Vec<Vec<u32>> = if level.len() < PARALLEL_MIN_COMPONENTS {
+            level.iter().map(|&c| component_row(&*shared, c, members.as_slice(), successors.as_slice())).collect()
+        }
+        else {
+            let block = (level.len() + nthreads - 1) / nthreads;
+            let level = Arc::new(level.clone());
+            let guards: Vec<JoinGuard<Vec<Vec<u32>>>> = range_step(0, level.len(), block).map(|first| {
+                let last = min(first + block, level.len());
+                let level = level.clone();
+                let members = members.clone();
+                let successors = successors.clone();
+                let F = shared.clone();
+                Thread::scoped(move || {
+                    level.slice(first, last).iter()
+                        .map(|&c| component_row(&*F, c, members.as_slice(), successors.as_slice()))
+                        .collect()
+                })
+            }).collect();
+
+            let mut rows = Vec::with_capacity(level.len());
+            for guard in guards.into_iter() {
+                rows.extend(guard.join().ok().expect("a thread computing follow sets panicked").into_iter());
+            }
+            rows
+        };
+
+        // The threads have finished, so this does not copy the matrix.
+        let out = shared.make_unique();
+        for (&c, row) in level.iter().zip(rows.iter()) {
+            for &i in members[c].iter() {
+                let dest = out.data.slice_mut(i.index() * rowsize, (i.index() + 1) * rowsize);
+                for (a, &b) in dest.iter_mut().zip(row.iter()) {
+                    *a = b;
+                }
+            }
+        }
+    }
+
+    *F = replace(shared.make_unique(), Bitmat::new(0, 0));
+}
+
+// Returns the row of F for component c: the union of the rows of its gotos, and of the rows of
+// the components that it has edges to, which are complete.
+#[cfg(feature = "parallel")]
+#[allow(non_snake_case)]
+fn component_row(F: &Bitmat, c: uint, members: &[Vec<GotoId>], successors: &[Vec<uint>]) -> Vec<u32> {
+    let rowsize = F.rowsize;
+    let mut row: Vec<u32> = Vec::from_elem(rowsize, 0);
+    let sources: Vec<GotoId> = members[c].iter().map(|&i| i)
+        .chain(successors[c].iter().map(|&s| members[s][0]))
+        .collect();
+    for &i in sources.iter() {
+        let frow = F.data.slice(i.index() * rowsize, (i.index() + 1) * rowsize);
+        for (a, &b) in row.iter_mut().zip(frow.iter()) {
+            *a |= b;
+        }
+    }
+    row
+}
+
+// state for find_components()
+#[cfg(feature = "parallel")]
+#[allow(non_snake_case)]
+struct ComponentState<'a> {
+    infinity: uint,
+    index: Vec<uint>,
+    vertices: Vec<GotoId>,
+    top: uint,
+    R: &'a IdVec<GotoId, Vec<GotoId>>,
+    component_of: Vec<uint>,
+    members: Vec<Vec<GotoId>>,
+}
+
+// Finds the strongly connected components of a relation, with the traversal of serial_digraph().
+// Returns the component of each goto, and the gotos of each component.  The components are in the
+// order in which the traversal completes them, so each one comes after those it has edges to.
+#[cfg(feature = "parallel")]
+fn find_components(relation: &IdVec<GotoId, Vec<GotoId>>) -> (Vec<uint>, Vec<Vec<GotoId>>) {
+    let ngotos = relation.len();
+    let mut cs = ComponentState {
+        infinity: ngotos + 2,
+        index: Vec::from_elem(ngotos + 1, 0),
+        vertices: Vec::from_elem(ngotos + 1, GotoId(0)),
+        top: 0,
+        R: relation,
+        component_of: Vec::from_elem(ngotos, 0),
+        members: Vec::new(),
+    };
+
+    for i in relation.ids() {
+        if cs.index[i.index()] == 0 {
+            find_component(&mut cs, i);
+        }
+    }
+    (cs.component_of, cs.members)
+}
+
+#[cfg(feature = "parallel")]
+fn find_component(cs: &mut ComponentState, i: GotoId) {
+    cs.top += 1;
+    cs.vertices[cs.top] = i;
+    let height = cs.top;
+    cs.index[i.index()] = cs.top;
+
+    let relation = cs.R;
+    for &j in relation[i].iter() {
+        if cs.index[j.index()] == 0 {
+            find_component(cs, j);
+        }
+        if cs.index[i.index()] > cs.index[j.index()] {
+            cs.index[i.index()] = cs.index[j.index()];
+        }
+    }
+
+    if cs.index[i.index()] == height {
+        let c = cs.members.len();
+        let mut component: Vec<GotoId> = Vec::new();
+        loop {
+            let j = cs.vertices[cs.top];
+            cs.top -= 1;
+            cs.index[j.index()] = cs.infinity;
+            cs.component_of[j.index()] = c;
+            component.push(j);
+            if i == j {
+                break;
+            }
+        }
+        cs.members.push(component);
+    }
+}
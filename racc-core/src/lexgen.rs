@@ -0,0 +1,596 @@
+// Builds the DFA of a lexer, from the patterns of its tokens.
+//
+// Each token is described by a literal string, such as "while", or by a regular expression,
+// such as [a-zA-Z_][a-zA-Z0-9_]*.  The patterns are compiled into one NFA (Thompson's
+// construction), whose accepting states are labeled with the pattern that they accept, and the
+// NFA is turned into a DFA by the subset construction.  The scanner (in the runtime) runs the
+// DFA for as long as it can, and returns the longest match.
+//
+// When a DFA state accepts more than one pattern, a literal beats a regular expression, so
+// that keywords win over identifiers of the same length; otherwise, the pattern which was given
+// first wins.
+//
+// A lexer may have several modes (the states of %lexer-state), each with its own patterns, such
+// as the text inside a string literal.  The NFA then has an initial state for each mode, which
+// leads to the patterns of that mode only, and the subset construction starts from each of
+// them; the modes share the DFA states which they can (those past the start of a pattern that
+// is in more than one mode, for example).
+//
+// The regular expressions have the usual syntax:
+//
+//      x           the character x; \n \r \t \\ and \x (for any punctuation x) are escapes
+//      .           any character except newline
+//      [a-z_]      any of the characters in the class; [^...] is the complement
+//      \d \w \s    digits, word characters, white space (also usable in classes)
+//      \p{XID_Start}   characters with a Unicode property; \P{...} is the complement (also
+//                      usable in classes); see unicode.rs for the properties
+//      ab  a|b     concatenation, alternation
+//      a*  a+  a?  repetition
+//      (a)         grouping
+//
+// Characters are handled as ranges of code points, so classes over all of Unicode are cheap.
+// \d, \w and \s are ASCII only; an identifier in the manner of Rust or Python is
+// [\p{XID_Start}_][\p{XID_Continue}]*.
+
+use std::collections::HashMap;
+
+use unicode::{property_ranges, property_names};
+
+/// A pattern which describes the text of a token.
+#[deriving(Clone,PartialEq,Show)]
+pub enum Pattern {
+    /// Exactly this text.
+    Literal(String),
+    /// Text which matches this regular expression.
+    Regex(String),
+}
+
+/// A DFA which recognizes the tokens of a lexer.  State 0 is the initial state of the first
+/// mode, which is the only mode unless the DFA was built by `build_modal_dfa`.
+pub struct Dfa {
+    /// The transitions of each state, as `(first, last, target)`: a character from `first` to
+    /// `last` (code points, inclusive) leads to state `target`.  The ranges of a state are
+    /// sorted and do not overlap.
+    pub transitions: Vec<Vec<(u32, u32, uint)>>,
+    /// The pattern that each state accepts, as an index into the patterns given to `build_dfa`.
+    pub accept: Vec<Option<uint>>,
+    /// The initial state of each mode.
+    pub starts: Vec<uint>,
+}
+
+/// An error in a pattern: the index of the pattern, and a message.
+#[deriving(Clone,PartialEq,Show)]
+pub struct PatternError {
+    pub pattern: uint,
+    pub message: String,
+}
+
+const MAX_CHAR: u32 = 0x10ffff;
+
+// A parsed regular expression.
+enum Re {
+    Ranges(Vec<(u32, u32)>),
+    Concat(Vec<Re>),
+    Alt(Vec<Re>),
+    Star(Box<Re>),
+    Plus(Box<Re>),
+    Opt(Box<Re>),
+}
+
+struct RegexParser {
+    chars: Vec<char>,
+    pos: uint,
+}
+
+impl RegexParser {
+    fn peek(&self) -> Option<char> {
+        if self.pos < self.chars.len() { Some(self.chars[self.pos]) } else { None }
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek();
+        self.pos += 1;
+        c
+    }
+
+    fn alt(&mut self) -> Result<Re, String> {
+        let mut alts = vec![try!(self.concat())];
+        while self.peek() == Some('|') {
+            self.bump();
+            alts.push(try!(self.concat()));
+        }
+        Ok(if alts.len() == 1 { alts.pop().unwrap() } else { Re::Alt(alts) })
+    }
+
+    fn concat(&mut self) -> Result<Re, String> {
+        let mut items: Vec<Re> = Vec::new();
+        loop {
+            match self.peek() {
+                None | Some('|') | Some(')') => break,
+                _ => items.push(try!(self.repeat()))
+            }
+        }
+        Ok(Re::Concat(items))
+    }
+
+    fn repeat(&mut self) -> Result<Re, String> {
+        let mut re = try!(self.atom());
+        loop {
+            re = match self.peek() {
+                Some('*') => Re::Star(box re),
+                Some('+') => Re::Plus(box re),
+                Some('?') => Re::Opt(box re),
+                _ => return Ok(re)
+            };
+            self.bump();
+        }
+    }
+
+    fn atom(&mut self) -> Result<Re, String> {
+        match self.bump() {
+            Some('(') => {
+                let re = try!(self.alt());
+                if self.bump() != Some(')') {
+                    return Err("missing ')'".to_string());
+                }
+                Ok(re)
+            }
+            Some('[') => self.class(),
+            Some('.') => Ok(Re::Ranges(complement(&[('\n' as u32, '\n' as u32)]))),
+            Some('\\') => Ok(Re::Ranges(try!(self.escape()))),
+            Some(c @ '*') | Some(c @ '+') | Some(c @ '?') => Err(format!("'{}' does not follow anything that it can repeat", c)),
+            Some(c) => Ok(Re::Ranges(vec![(c as u32, c as u32)])),
+            None => Err("unexpected end of pattern".to_string())
+        }
+    }
+
+    // Reads an escape, after the backslash.
+    fn escape(&mut self) -> Result<Vec<(u32, u32)>, String> {
+        let c = match self.bump() {
+            Some(c) => c,
+            None => return Err("pattern ends with '\\'".to_string())
+        };
+        Ok(match c {
+            'n' => vec![('\n' as u32, '\n' as u32)],
+            'r' => vec![('\r' as u32, '\r' as u32)],
+            't' => vec![('\t' as u32, '\t' as u32)],
+            'd' => vec![('0' as u32, '9' as u32)],
+            'w' => vec![('0' as u32, '9' as u32), ('A' as u32, 'Z' as u32), ('_' as u32, '_' as u32), ('a' as u32, 'z' as u32)],
+            's' => vec![('\t' as u32, '\r' as u32), (' ' as u32, ' ' as u32)],
+            'p' => try!(self.property()),
+            'P' => complement(try!(self.property()).as_slice()),
+            c if !c.is_alphanumeric() => vec![(c as u32, c as u32)],
+            c => return Err(format!("unknown escape '\\{}'", c))
+        })
+    }
+
+    // Reads the name of a Unicode property, after "\p": either "{Name}" or a single letter.
+    fn property(&mut self) -> Result<Vec<(u32, u32)>, String> {
+        let name: String = match self.bump() {
+            Some('{') => {
+                let mut name = String::new();
+                loop {
+                    match self.bump() {
+                        Some('}') => break,
+                        Some(c) => name.push(c),
+                        None => return Err("missing '}' after the name of a Unicode property".to_string())
+                    }
+                }
+                name
+            }
+            Some(c) => c.to_string(),
+            None => return Err("pattern ends with '\\p'".to_string())
+        };
+        match property_ranges(name.as_slice()) {
+            Some(ranges) => Ok(ranges),
+            None => Err(format!("unknown Unicode property '{}'; the properties are {}", name, property_names().connect(", ")))
+        }
+    }
+
+    // Reads a character class, after the '['.
+    fn class(&mut self) -> Result<Re, String> {
+        let negated = self.peek() == Some('^');
+        if negated {
+            self.bump();
+        }
+        let mut ranges: Vec<(u32, u32)> = Vec::new();
+        let mut first = true;
+        loop {
+            let c = match self.bump() {
+                Some(']') if !first => break,
+                Some('\\') => {
+                    ranges.push_all(try!(self.escape()).as_slice());
+                    first = false;
+                    continue;
+                }
+                Some(c) => c,
+                None => return Err("missing ']'".to_string())
+            };
+            first = false;
+            if self.peek() == Some('-') && self.pos + 1 < self.chars.len() && self.chars[self.pos + 1] != ']' {
+                self.bump();
+                let last = self.bump().unwrap();
+                if (last as u32) < (c as u32) {
+                    return Err(format!("the range {}-{} is backwards", c, last));
+                }
+                ranges.push((c as u32, last as u32));
+            }
+            else {
+                ranges.push((c as u32, c as u32));
+            }
+        }
+        let ranges = normalize(ranges);
+        Ok(Re::Ranges(if negated { complement(ranges.as_slice()) } else { ranges }))
+    }
+}
+
+// Sorts ranges, and merges those which overlap or touch.
+fn normalize(mut ranges: Vec<(u32, u32)>) -> Vec<(u32, u32)> {
+    ranges.sort();
+    let mut out: Vec<(u32, u32)> = Vec::new();
+    for &(lo, hi) in ranges.iter() {
+        match out.last_mut() {
+            Some(&(_, ref mut last_hi)) if lo <= *last_hi + 1 => {
+                if hi > *last_hi {
+                    *last_hi = hi;
+                }
+                continue;
+            }
+            _ => {}
+        }
+        out.push((lo, hi));
+    }
+    out
+}
+
+// Returns the characters which are not in the (normalized) ranges.
+fn complement(ranges: &[(u32, u32)]) -> Vec<(u32, u32)> {
+    let mut out: Vec<(u32, u32)> = Vec::new();
+    let mut next: u32 = 0;
+    for &(lo, hi) in ranges.iter() {
+        if lo > next {
+            out.push((next, lo - 1));
+        }
+        next = hi + 1;
+    }
+    if next <= MAX_CHAR {
+        out.push((next, MAX_CHAR));
+    }
+    out
+}
+
+struct NfaState {
+    eps: Vec<uint>,
+    edges: Vec<(u32, u32, uint)>,
+    accept: Option<uint>,
+}
+
+struct Nfa {
+    states: Vec<NfaState>,
+}
+
+impl Nfa {
+    fn add(&mut self) -> uint {
+        self.states.push(NfaState { eps: Vec::new(), edges: Vec::new(), accept: None });
+        self.states.len() - 1
+    }
+
+    // Adds states which match `re`, starting at `start`, and returns the state at which they end.
+    fn compile(&mut self, re: &Re, start: uint) -> uint {
+        match *re {
+            Re::Ranges(ref ranges) => {
+                let end = self.add();
+                for &(lo, hi) in ranges.iter() {
+                    self.states[start].edges.push((lo, hi, end));
+                }
+                end
+            }
+            Re::Concat(ref items) => {
+                let mut at = start;
+                for item in items.iter() {
+                    at = self.compile(item, at);
+                }
+                at
+            }
+            Re::Alt(ref alts) => {
+                let end = self.add();
+                for alt in alts.iter() {
+                    let s = self.add();
+                    self.states[start].eps.push(s);
+                    let e = self.compile(alt, s);
+                    self.states[e].eps.push(end);
+                }
+                end
+            }
+            Re::Star(ref inner) => {
+                let s = self.add();
+                self.states[start].eps.push(s);
+                let e = self.compile(&**inner, s);
+                self.states[e].eps.push(s);
+                let end = self.add();
+                self.states[s].eps.push(end);
+                end
+            }
+            Re::Plus(ref inner) => {
+                let s = self.add();
+                self.states[start].eps.push(s);
+                let e = self.compile(&**inner, s);
+                self.states[e].eps.push(s);
+                let end = self.add();
+                self.states[e].eps.push(end);
+                end
+            }
+            Re::Opt(ref inner) => {
+                let e = self.compile(&**inner, start);
+                self.states[start].eps.push(e);
+                e
+            }
+        }
+    }
+
+    // The states reachable from `states` by epsilon edges, including `states`; sorted.
+    fn closure(&self, states: &[uint]) -> Vec<uint> {
+        let mut set: Vec<bool> = Vec::from_elem(self.states.len(), false);
+        let mut stack: Vec<uint> = states.to_vec();
+        loop {
+            let s = match stack.pop() {
+                Some(s) => s,
+                None => break
+            };
+            if set[s] {
+                continue;
+            }
+            set[s] = true;
+            stack.push_all(self.states[s].eps.as_slice());
+        }
+        range(0, set.len()).filter(|&s| set[s]).collect()
+    }
+}
+
+// Parses a pattern into a regular expression.
+fn parse_pattern(pattern: &Pattern) -> Result<Re, String> {
+    match *pattern {
+        Pattern::Literal(ref text) => {
+            if text.len() == 0 {
+                return Err("a literal token cannot be empty".to_string());
+            }
+            Ok(Re::Concat(text.chars().map(|c| Re::Ranges(vec![(c as u32, c as u32)])).collect()))
+        }
+        Pattern::Regex(ref text) => {
+            let mut p = RegexParser { chars: text.chars().collect(), pos: 0 };
+            let re = try!(p.alt());
+            if p.pos < p.chars.len() {
+                return Err("unmatched ')'".to_string());
+            }
+            Ok(re)
+        }
+    }
+}
+
+// The order in which patterns win, when a DFA state accepts more than one: a literal beats a
+// regular expression; otherwise the first pattern wins.
+fn priority(patterns: &[Pattern], p: uint) -> (uint, uint) {
+    match patterns[p] {
+        Pattern::Literal(_) => (0, p),
+        Pattern::Regex(_) => (1, p)
+    }
+}
+
+/// Builds the DFA which recognizes the patterns.
+pub fn build_dfa(patterns: &[Pattern]) -> Result<Dfa, Vec<PatternError>> {
+    build_modal_dfa(patterns, &[range(0, patterns.len()).collect()])
+}
+
+/// Builds the DFA of a lexer with several modes, in which `modes[m]` lists the patterns (by
+/// index) which can be matched in mode m.  A pattern may be in more than one mode.  The initial
+/// state of each mode is in `Dfa::starts`.
+pub fn build_modal_dfa(patterns: &[Pattern], modes: &[Vec<uint>]) -> Result<Dfa, Vec<PatternError>> {
+    let mut errors: Vec<PatternError> = Vec::new();
+    let mut res: Vec<Option<Re>> = Vec::new();
+    for (i, pattern) in patterns.iter().enumerate() {
+        match parse_pattern(pattern) {
+            Ok(re) => res.push(Some(re)),
+            Err(message) => {
+                errors.push(PatternError { pattern: i, message: message });
+                res.push(None);
+            }
+        }
+    }
+    if errors.len() != 0 {
+        return Err(errors);
+    }
+
+    let mut nfa = Nfa { states: Vec::new() };
+    let mut initials: Vec<Vec<uint>> = Vec::new();
+    for mode in modes.iter() {
+        let start = nfa.add();
+        for &i in mode.iter() {
+            if let Some(ref re) = res[i] {
+                let s = nfa.add();
+                nfa.states[start].eps.push(s);
+                let e = nfa.compile(re, s);
+                nfa.states[e].accept = Some(i);
+            }
+        }
+        let initial = nfa.closure(&[start]);
+        for &s in initial.iter() {
+            if let Some(p) = nfa.states[s].accept {
+                if !errors.iter().any(|e| e.pattern == p) {
+                    errors.push(PatternError { pattern: p, message: "the pattern matches the empty string".to_string() });
+                }
+            }
+        }
+        initials.push(initial);
+    }
+    if errors.len() != 0 {
+        return Err(errors);
+    }
+
+    let mut dfa = Dfa { transitions: Vec::new(), accept: Vec::new(), starts: Vec::new() };
+    let mut sets: Vec<Vec<uint>> = Vec::new();
+    let mut index: HashMap<Vec<uint>, uint> = HashMap::new();
+    for initial in initials.into_iter() {
+        // Each initial set holds the initial NFA state of its own mode, so no two are the same.
+        dfa.starts.push(sets.len());
+        index.insert(initial.clone(), sets.len());
+        sets.push(initial);
+    }
+
+    let mut d = 0;
+    while d < sets.len() {
+        let set = sets[d].clone();
+        dfa.accept.push(set.iter().filter_map(|&s| nfa.states[s].accept).min_by(|&p| priority(patterns, p)));
+
+        // Split the characters into intervals, on which every edge of the set either applies or
+        // does not.
+        let mut points: Vec<u32> = Vec::new();
+        for &s in set.iter() {
+            for &(lo, hi, _) in nfa.states[s].edges.iter() {
+                points.push(lo);
+                points.push(hi + 1);
+            }
+        }
+        points.sort();
+        points.dedup();
+
+        let mut transitions: Vec<(u32, u32, uint)> = Vec::new();
+        for w in points.windows(2) {
+            let (lo, hi) = (w[0], w[1] - 1);
+            let mut targets: Vec<uint> = Vec::new();
+            for &s in set.iter() {
+                for &(elo, ehi, t) in nfa.states[s].edges.iter() {
+                    if elo <= lo && hi <= ehi {
+                        targets.push(t);
+                    }
+                }
+            }
+            if targets.len() == 0 {
+                continue;
+            }
+            let target_set = nfa.closure(targets.as_slice());
+            let target = match index.get(&target_set) {
+                Some(&t) => t,
+                None => {
+                    let t = sets.len();
+                    index.insert(target_set.clone(), t);
+                    sets.push(target_set);
+                    t
+                }
+            };
+            match transitions.last_mut() {
+                Some(&(_, ref mut last_hi, last_target)) if last_target == target && *last_hi + 1 == lo => {
+                    *last_hi = hi;
+                    continue;
+                }
+                _ => {}
+            }
+            transitions.push((lo, hi, target));
+        }
+        dfa.transitions.push(transitions);
+        d += 1;
+    }
+
+    debug!("lexer DFA has {} states", dfa.transitions.len());
+    Ok(dfa)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Dfa, Pattern, PatternError, build_dfa, build_modal_dfa};
+
+    fn regex(text: &str) -> Pattern {
+        Pattern::Regex(text.to_string())
+    }
+
+    fn literal(text: &str) -> Pattern {
+        Pattern::Literal(text.to_string())
+    }
+
+    // Runs the DFA from the initial state of `mode`, as the scanner does, and returns the
+    // pattern of the longest match at the start of `text`, and its length in characters.
+    fn longest(dfa: &Dfa, mode: uint, text: &str) -> Option<(uint, uint)> {
+        let mut state = dfa.starts[mode];
+        let mut matched = None;
+        for (i, c) in text.chars().enumerate() {
+            let c = c as u32;
+            match dfa.transitions[state].iter().find(|&&(lo, hi, _)| lo <= c && c <= hi) {
+                Some(&(_, _, target)) => state = target,
+                None => break
+            }
+            if let Some(p) = dfa.accept[state] {
+                matched = Some((p, i + 1));
+            }
+        }
+        matched
+    }
+
+    fn errors(patterns: &[Pattern]) -> Vec<PatternError> {
+        match build_dfa(patterns) {
+            Ok(_) => panic!("the patterns should not build"),
+            Err(errors) => errors
+        }
+    }
+
+    #[test]
+    fn keywords_beat_identifiers() {
+        let dfa = build_dfa(&[regex("[a-z]+"), literal("if")]).unwrap();
+        assert_eq!(dfa.transitions.len(), 4);
+        assert_eq!(dfa.starts, vec![0]);
+        assert_eq!(longest(&dfa, 0, "if x"), Some((1, 2)));
+        assert_eq!(longest(&dfa, 0, "ifx"), Some((0, 3)));
+        assert_eq!(longest(&dfa, 0, "i"), Some((0, 1)));
+        assert_eq!(longest(&dfa, 0, "9"), None);
+    }
+
+    #[test]
+    fn first_regex_wins() {
+        let dfa = build_dfa(&[regex("[a-z]+"), regex("[a-c]+")]).unwrap();
+        assert_eq!(longest(&dfa, 0, "abc"), Some((0, 3)));
+    }
+
+    #[test]
+    fn longest_match() {
+        let dfa = build_dfa(&[regex("\\d+(\\.\\d+)?"), literal(".")]).unwrap();
+        assert_eq!(longest(&dfa, 0, "3.14x"), Some((0, 4)));
+        // "3." is not a number, so the longest match stops before the dot.
+        assert_eq!(longest(&dfa, 0, "3.x"), Some((0, 1)));
+        assert_eq!(longest(&dfa, 0, ".5"), Some((1, 1)));
+    }
+
+    #[test]
+    fn classes() {
+        let dfa = build_dfa(&[regex("[^a]"), regex("a.")]).unwrap();
+        assert_eq!(longest(&dfa, 0, "b"), Some((0, 1)));
+        assert_eq!(longest(&dfa, 0, "\n"), Some((0, 1)));
+        assert_eq!(longest(&dfa, 0, "ab"), Some((1, 2)));
+        assert_eq!(longest(&dfa, 0, "a\n"), None);
+
+        let dfa = build_dfa(&[regex("[\\p{XID_Start}_][\\p{XID_Continue}]*")]).unwrap();
+        assert_eq!(longest(&dfa, 0, "αβ1 γ"), Some((0, 3)));
+        assert_eq!(longest(&dfa, 0, "_x"), Some((0, 2)));
+        assert_eq!(longest(&dfa, 0, "1x"), None);
+    }
+
+    #[test]
+    fn pattern_errors() {
+        assert_eq!(errors(&[literal("")]), vec![PatternError { pattern: 0, message: "a literal token cannot be empty".to_string() }]);
+        assert_eq!(errors(&[literal("a"), regex("a)")]), vec![PatternError { pattern: 1, message: "unmatched ')'".to_string() }]);
+        assert_eq!(errors(&[regex("(a")]), vec![PatternError { pattern: 0, message: "missing ')'".to_string() }]);
+        assert_eq!(errors(&[regex("[b-a]")]), vec![PatternError { pattern: 0, message: "the range b-a is backwards".to_string() }]);
+        assert_eq!(errors(&[regex("a*")]), vec![PatternError { pattern: 0, message: "the pattern matches the empty string".to_string() }]);
+    }
+
+    #[test]
+    fn modes() {
+        // Mode 0 is the program, where a quote starts a string; mode 1 is the text of a string,
+        // where a quote ends it.
+        let patterns = [literal("\""), regex("[a-z]+"), regex("[^\"]+")];
+        let dfa = build_modal_dfa(&patterns, &[vec![0, 1], vec![0, 2]]).unwrap();
+        assert_eq!(dfa.starts, vec![0, 1]);
+        assert_eq!(longest(&dfa, 0, "ab c\""), Some((1, 2)));
+        assert_eq!(longest(&dfa, 1, "ab c\""), Some((2, 4)));
+        assert_eq!(longest(&dfa, 0, "\"ab"), Some((0, 1)));
+        assert_eq!(longest(&dfa, 1, "\"ab"), Some((0, 1)));
+        assert_eq!(longest(&dfa, 0, " "), None);
+    }
+}
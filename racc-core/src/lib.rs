@@ -0,0 +1,258 @@
+//! The grammar analysis engine of RACC (Rust Another Compiler-Compiler).
+//!
+//! This crate contains everything that RACC does between reading a grammar and generating code:
+//! building the grammar tables, constructing the LR(0) automaton, computing LALR(1) lookaheads,
+//! and building the parser actions, including conflict resolution.  It does not depend on the
+//! Rust compiler's `syntax` crate, so it can be used by ordinary programs, such as grammar
+//! linters, visualizers, and test tools.
+//!
+//! The pipeline is:
+//!
+//! ```ignore
+//!     let gram = Grammar::builder()
+//!         .token("NUM").token("PLUS")
+//!         .rule("Expr", &["Expr", "PLUS", "NUM"])
+//!         .rule("Expr", &["NUM"])
+//!         .build()
+//!         .unwrap();
+//!
+//!     let lr0 = try!(lr0::compute_lr0(&gram));                // LR(0) automaton
+//!     let lalr = try!(lalr::run_lalr(&gram, &lr0));           // LALR(1) lookaheads
+//!     let parser = mkpar::make_parser(&gram, &lr0, &lalr);    // actions, conflicts resolved
+//! ```
+//!
+//! or, equivalently, `analyze(&gram)`.  `analyze_with(&gram, LrType::CanonicalLr)` builds the
+//! canonical LR(1) automaton (see `lr1`) instead.  `tables::make_tables` then packs the parser
+//! actions into the compressed tables that the run-time parser (the `racc-runtime` crate) uses.
+//!
+//! Tools which draw or check the automaton should read it through `automaton::Automaton`, which
+//! gives the items, transitions, lookaheads, and actions of each state, rather than through the
+//! tables of `LR0Output`, `LALROutput`, and `YaccParser`.  Tools which only need CLOSURE and
+//! GOTO on sets of items, such as course material which builds an automaton step by step, can use
+//! `analysis::ItemSets`, which computes them without building the automaton.
+//!
+//! A grammar written for YACC or Bison can be read with `yacc::read_yacc`, which takes the text
+//! of a .y file and returns the `Grammar`, along with the text of each rule's action.  Only the
+//! declarations that describe the grammar are supported; see `yacc` for the details.
+//!
+//! When a large grammar shows a conflict or a bug, `shrink::shrink_grammar` finds a small grammar
+//! which still shows it: it removes rules for as long as a predicate (such as "has a
+//! reduce/reduce conflict") stays true.  `Grammar::to_yacc_string` then writes the result, for a
+//! bug report.
+//!
+//! With the `parallel` feature, the LALR(1) lookahead computation is split across threads, one
+//! per CPU, for grammars with many gotos or reductions: both the propagation of the follow sets
+//! through the reads and includes relations, and the union of the follow sets into the
+//! lookaheads of each reduction.  The result is the same.
+//!
+//! # Stability
+//!
+//! The public API of this crate is the set of items re-exported or declared `pub` in the modules
+//! listed below, and their documented meaning.  Within a 0.x release series, that API only grows:
+//! fields and functions are not removed or changed in meaning without bumping the minor version.
+//! Items whose documentation says that they are internal (such as the intermediate tables in
+//! `LALROutput`) may change at any time.
+//!
+//! States, symbols, and rules are identified by index, exactly as in Berkeley YACC:
+//!
+//! * Symbols are indices into `Grammar.name`.  Tokens come first (`0` is `$end`, `1` is `error`),
+//!   followed by non-terminals, starting at `Grammar.start_symbol` (which is `$accept`).
+//! * Rules are indices into `Grammar.rlhs`.  Rules 0 through 2 are predefined; rule 2 is
+//!   `$accept : <start> $end`.
+//! * States are indices into `LR0Output.states`.  State 0 is the initial state.
+//!
+//! The public structures of the automaton use the typed wrappers in `ids` (`StateId`, `SymbolId`,
+//! `RuleId`, `ItemId`) for these indices, rather than bare integers.
+
+#![feature(phase)]
+#![feature(macro_rules)]
+
+#![allow(dead_code)]
+#![allow(non_upper_case_globals)]
+
+#[phase(plugin, link)]
+extern crate log;
+extern crate serialize;
+extern crate time;
+
+pub use grammar::Grammar;
+pub use builder::{GrammarBuilder, GrammarError, Assoc, Resolution, Choice};
+pub use lr0::LR0Output;
+pub use lalr::LALROutput;
+pub use mkpar::YaccParser;
+pub use automaton::Automaton;
+pub use error::GenError;
+pub use ids::{StateId, SymbolId, RuleId, ItemId, GotoId, IdVec};
+pub use profile::{Profile, Phase};
+
+use profile::{lr0_bytes, lalr_bytes, parser_bytes};
+
+mod closure;
+mod warshall;
+mod unicode;
+
+/// Errors which can occur while generating a parser.
+pub mod error;
+
+/// Typed indices for states, symbols, rules, and items.
+pub mod ids;
+
+/// Programmatic construction of grammars.
+pub mod builder;
+
+/// The grammar representation used by the analysis and table-building stages.
+pub mod grammar;
+
+/// Computes the LR(0) automaton of a grammar.
+pub mod lr0;
+
+/// Computes LALR(1) lookaheads for an LR(0) automaton.
+pub mod lalr;
+
+/// Computes SLR(1) lookaheads for an LR(0) automaton, and compares their conflicts with LALR(1).
+pub mod slr;
+
+/// Builds the canonical LR(1) automaton, an alternative to LR(0) plus LALR(1).
+pub mod lr1;
+
+/// Builds the IELR(1) automaton, by splitting the LALR(1) states that need it.
+pub mod ielr;
+
+/// Checks a grammar for non-terminals and rules which play no part in its language.
+pub mod check;
+
+/// Computes the FIRST and FOLLOW sets of the symbols of a grammar.
+pub mod sets;
+
+/// Finds derivation cycles, and the left and right recursion of non-terminals.
+pub mod recursion;
+
+/// Builds parser actions from the LALR(1) automaton, and resolves conflicts.
+pub mod mkpar;
+
+/// A read-only view of the automaton: the items, transitions, lookaheads, and actions of each
+/// state.
+pub mod automaton;
+
+/// Describes the conflicts of a parser, for the author of the grammar.
+pub mod conflicts;
+
+/// CLOSURE and GOTO on sets of LR(0) items, without building the whole automaton.
+pub mod analysis;
+
+/// Caches the packed tables and conflicts of a grammar, keyed by a hash of the grammar.
+pub mod cache;
+
+/// Writes a human-readable report of the automaton, like YACC's y.output.
+pub mod report;
+
+/// Writes the automaton as a Graphviz (DOT) graph.
+pub mod dot;
+
+/// Writes a report of the grammar and its automaton as an HTML page, with links between the
+/// rules, symbols, states, and conflicts.
+pub mod html;
+
+/// Exports the grammar, the automaton, and the packed tables as JSON, for other tools.
+pub mod export;
+
+/// Packs the parser actions into the compressed tables used at run time.
+pub mod tables;
+
+/// Reads grammars written for YACC or Bison (.y files).
+pub mod yacc;
+
+/// Builds the DFA of a lexer from the patterns of its tokens.
+pub mod lexgen;
+
+/// Measures the time and memory of each phase of the analysis.
+pub mod profile;
+
+/// Counts the symbols, rules, states, conflicts, and table entries of a grammar.
+pub mod stats;
+
+/// Bit matrices and bit vectors used by the analysis code.
+pub mod util;
+
+/// The deterministic pseudo-random number generator used by all of RACC's randomized utilities.
+pub mod random;
+
+/// Generates random sentences of a grammar, with bounded depth and weighted rules, and sets of
+/// sentences which cover every rule.
+pub mod generate;
+
+/// Shrinks a grammar to a minimal set of rules which still has some property, for bug reports.
+pub mod shrink;
+
+/// The kind of automaton that the analysis builds.
+#[deriving(Copy,Clone,PartialEq,Show)]
+pub enum LrType {
+    /// The LALR(1) automaton: the LR(0) states, with LALR(1) lookaheads.  This is the default,
+    /// and is what YACC builds.
+    Lalr,
+    /// The canonical LR(1) automaton.  It has no conflicts which come only from merging states
+    /// with the same LR(0) items, but it may have many more states than the LALR(1) automaton.
+    CanonicalLr,
+    /// The IELR(1) automaton: the LALR(1) automaton, with its states split wherever merging
+    /// them gives a conflict which the canonical LR(1) automaton does not have.  It has the
+    /// conflicts of the canonical LR(1) automaton, and close to the states of the LALR(1)
+    /// automaton.  See `ielr`.
+    Ielr,
+    /// The LR(0) states, with SLR(1) lookaheads: each reduction applies to every token in
+    /// FOLLOW of its left-hand side.  This has every conflict of `Lalr`, and often more; it is
+    /// here for teaching and comparison.  See `slr`.
+    Slr,
+}
+
+/// The complete result of analyzing a grammar.  With `LrType::CanonicalLr` or `LrType::Ielr`,
+/// `lr0` holds the states of the LR(1) automaton, and `lalr` holds their exact lookaheads.
+pub struct Analysis {
+    pub lr0: LR0Output,
+    pub lalr: LALROutput,
+    pub parser: YaccParser,
+}
+
+/// Runs the whole analysis pipeline (LR(0), LALR(1), and parser construction) over a grammar.
+pub fn analyze(gram: &Grammar) -> Result<Analysis, GenError> {
+    analyze_with(gram, LrType::Lalr)
+}
+
+/// Runs the whole analysis pipeline over a grammar, building the given kind of automaton.
+pub fn analyze_with(gram: &Grammar, lr_type: LrType) -> Result<Analysis, GenError> {
+    analyze_profiled(gram, lr_type, &mut Profile::new())
+}
+
+/// Runs the whole analysis pipeline over a grammar, as `analyze_with` does, and records the time
+/// and memory of each phase in `profile`.
+pub fn analyze_profiled(gram: &Grammar, lr_type: LrType, profile: &mut Profile) -> Result<Analysis, GenError> {
+    let (lr0, lalr) = match lr_type {
+        LrType::Lalr => {
+            let lr0 = try!(lr0::compute_lr0_profiled(gram, profile));
+            let start = profile.start();
+            let lalr = try!(lalr::run_lalr(gram, &lr0));
+            profile.record(Phase::Lookaheads, start, lalr_bytes(&lalr));
+            (lr0, lalr)
+        }
+        LrType::Slr => {
+            let lr0 = try!(lr0::compute_lr0_profiled(gram, profile));
+            let start = profile.start();
+            let lalr = try!(slr::run_slr(gram, &lr0));
+            profile.record(Phase::Lookaheads, start, lalr_bytes(&lalr));
+            (lr0, lalr)
+        }
+        LrType::CanonicalLr | LrType::Ielr => {
+            let start = profile.start();
+            let (lr0, lalr) = if lr_type == LrType::Ielr { try!(ielr::compute_ielr1(gram)) } else { try!(lr1::compute_lr1(gram)) };
+            profile.record(Phase::Lr1, start, lr0_bytes(&lr0) + lalr_bytes(&lalr));
+            (lr0, lalr)
+        }
+    };
+    let start = profile.start();
+    let parser = mkpar::make_parser(gram, &lr0, &lalr);
+    profile.record(Phase::Actions, start, parser_bytes(&parser));
+    Ok(Analysis {
+        lr0: lr0,
+        lalr: lalr,
+        parser: parser
+    })
+}
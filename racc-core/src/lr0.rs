@@ -0,0 +1,575 @@
+use grammar::Grammar;
+use ids::{StateId,SymbolId,RuleId,ItemId,IdVec};
+use error::{GenError,MAX_TABLE_INDEX};
+use closure::set_first_derives;
+use closure::closure;
+use util::Bitv32;
+use profile::{Profile, Phase, slice_bytes, derives_bytes, lr0_bytes};
+use std::collections::{Bitv, HashMap};
+
+/// the structure of the LR(0) state machine
+pub struct Core
+{
+    /// The symbol which is shifted in order to enter this state.  For state 0, this is 0.
+    pub accessing_symbol: SymbolId,
+    /// The kernel items of this state, as indices into `Grammar.ritem`, in ascending order.
+    /// Each item identifies a rule and a position (the "dot") within its right-hand side.
+    pub items: Vec<ItemId>,
+}
+
+/// The structure used to record shifts
+pub struct Shifts
+{
+    /// The state that these shifts leave from.
+    pub state: StateId,
+    /// The target states, in ascending order of their accessing symbols.  Targets reached by
+    /// tokens come before targets reached by non-terminals (gotos).
+    pub shifts: Vec<StateId>,
+}
+
+/// the structure used to store reductions
+pub struct Reductions
+{
+    /// The state in which these reductions are possible.
+    pub state: StateId,
+    /// The rules which can be reduced in this state.
+    pub rules: Vec<RuleId>,
+}
+
+/// The LR(0) automaton of a grammar.
+///
+/// States which have no shifts have no entry in `shifts`, and states which have no reductions
+/// have no entry in `reductions`.  Both vectors are ordered by state.
+#[deriving(Default)]
+pub struct LR0Output
+{
+    pub states: Vec<Core>,
+    pub shifts: Vec<Shifts>,
+    pub reductions: Vec<Reductions>,
+    /// `nullable[s]` is true if symbol `s` can derive the empty string.
+    pub nullable: Bitv,
+    /// `derives[v]` lists the rules whose left-hand side is `v`, in ascending order.  Tokens
+    /// have no rules, so their lists are empty.
+    pub derives: IdVec<SymbolId, Vec<RuleId>>,
+}
+
+impl LR0Output
+{
+    /// Returns the number of states in the automaton.
+    pub fn nstates(&self) -> uint {
+        self.states.len()
+    }
+
+    /// Returns a state, together with its shifts and reductions.
+    pub fn state<'a>(&'a self, state: StateId) -> StateView<'a> {
+        StateView {
+            id: state,
+            core: &self.states[state.index()],
+            shifts: self.shifts_of(state),
+            reductions: self.reductions_of(state),
+        }
+    }
+
+    /// Iterates the states of the automaton, in order, together with their shifts and reductions.
+    pub fn iter_states<'a>(&'a self) -> States<'a> {
+        States {
+            lr0: self,
+            next: 0,
+            next_shifts: 0,
+            next_reductions: 0,
+        }
+    }
+
+    /// Returns the states that `state` shifts to (including gotos), in ascending order of their
+    /// accessing symbols.  Returns an empty slice if the state has no shifts.
+    pub fn shifts_of<'a>(&'a self, state: StateId) -> &'a [StateId] {
+        // self.shifts is ordered by state.
+        let (mut low, mut high) = (0u, self.shifts.len());
+        while low < high {
+            let middle = (low + high) / 2;
+            let s = self.shifts[middle].state;
+            if s == state {
+                return self.shifts[middle].shifts.as_slice();
+            }
+            if s < state { low = middle + 1; } else { high = middle; }
+        }
+        &[]
+    }
+
+    /// Returns the rules that can be reduced in `state`.  Returns an empty slice if the state
+    /// has no reductions.
+    pub fn reductions_of<'a>(&'a self, state: StateId) -> &'a [RuleId] {
+        // self.reductions is ordered by state.
+        let (mut low, mut high) = (0u, self.reductions.len());
+        while low < high {
+            let middle = (low + high) / 2;
+            let s = self.reductions[middle].state;
+            if s == state {
+                return self.reductions[middle].rules.as_slice();
+            }
+            if s < state { low = middle + 1; } else { high = middle; }
+        }
+        &[]
+    }
+
+    /// Returns the state that `state` moves to when `symbol` (a token or a non-terminal) is
+    /// shifted, or `None` if there is no such transition.
+    pub fn shift_target(&self, state: StateId, symbol: SymbolId) -> Option<StateId> {
+        for &target in self.shifts_of(state).iter() {
+            if self.states[target.index()].accessing_symbol == symbol {
+                return Some(target);
+            }
+        }
+        None
+    }
+}
+
+/// A state of the automaton, together with its shifts and reductions.  Returned by
+/// `LR0Output::state` and `LR0Output::iter_states`.
+pub struct StateView<'a>
+{
+    pub id: StateId,
+    pub core: &'a Core,
+    /// The states that this state shifts to; see `Shifts.shifts`.
+    pub shifts: &'a [StateId],
+    /// The rules that can be reduced in this state.
+    pub reductions: &'a [RuleId],
+}
+
+/// Iterates the states of an `LR0Output`; see `LR0Output::iter_states`.
+pub struct States<'a>
+{
+    lr0: &'a LR0Output,
+    next: uint,
+    next_shifts: uint,          // index into lr0.shifts
+    next_reductions: uint,      // index into lr0.reductions
+}
+
+impl<'a> Iterator<StateView<'a>> for States<'a>
+{
+    fn next(&mut self) -> Option<StateView<'a>> {
+        if self.next >= self.lr0.states.len() {
+            return None;
+        }
+
+        // shifts and reductions are ordered by state, so we can walk them in step with the states.
+        let state = StateId(self.next);
+        let shifts: &'a [StateId] = match self.lr0.shifts.get(self.next_shifts) {
+            Some(sp) if sp.state == state => { self.next_shifts += 1; sp.shifts.as_slice() }
+            _ => &[]
+        };
+        let reductions: &'a [RuleId] = match self.lr0.reductions.get(self.next_reductions) {
+            Some(rp) if rp.state == state => { self.next_reductions += 1; rp.rules.as_slice() }
+            _ => &[]
+        };
+
+        self.next += 1;
+        Some(StateView {
+            id: state,
+            core: &self.lr0.states[state.index()],
+            shifts: shifts,
+            reductions: reductions,
+        })
+    }
+
+    fn size_hint(&self) -> (uint, Option<uint>) {
+        let n = self.lr0.states.len() - self.next;
+        (n, Some(n))
+    }
+}
+
+// intermediate variables for LR(0)
+struct LR0State<'a>
+{
+    gram: &'a Grammar,
+
+    // Maps the kernel items of each state (other than state 0) to the state, so that the
+    // state for a kernel can be found without comparing it against other states.  States are
+    // still numbered in the order that they are created, so the numbering is deterministic.
+    state_map: HashMap<Vec<ItemId>, StateId>,
+    
+    states: Vec<Core>,
+
+    kernel_base: IdVec<SymbolId, uint>,         // where the kernel items of each symbol begin in kernel_items
+    kernel_end: IdVec<SymbolId, Option<uint>>,  // where they end, or None if no item shifts the symbol
+    kernel_items: Vec<ItemId>,
+}
+
+fn sort_shift_symbols(shift_symbol: &mut [SymbolId]) {
+    // this appears to be a bubble-sort of shift_symbol?
+    for i in range(1, shift_symbol.len()) {
+        let symbol = shift_symbol[i];
+        let mut j = i;
+        while j > 0 && (shift_symbol[j - 1]) > symbol {
+            shift_symbol[j] = shift_symbol[j - 1];
+            j -= 1;
+        }
+        shift_symbol[j] = symbol;
+    }
+}
+
+// shift_symbol contains a list of symbols.  it will be sorted.
+// shiftset is empty when called, and will be filled with the states that correspond
+// to the given shifted symbols.
+fn append_states(lr0: &mut LR0State, shiftset: &mut Vec<StateId>, shift_symbol: &Vec<SymbolId>) -> Result<(), GenError>
+{
+    assert!(shiftset.len() == 0);
+
+    for &symbol in shift_symbol.iter() {
+        let state = try!(get_state(lr0, symbol));
+        shiftset.push(state);
+    }
+
+    assert!(shiftset.len() == shift_symbol.len());
+    Ok(())
+}
+
+/// Computes the LR(0) automaton of a grammar.  Fails if the automaton is too large for the
+/// tables to represent.
+pub fn compute_lr0(gram: &Grammar) -> Result<LR0Output, GenError>
+{
+    compute_lr0_profiled(gram, &mut Profile::new())
+}
+
+/// Computes the LR(0) automaton of a grammar, as `compute_lr0` does, and records the derives,
+/// closure, and LR(0) phases in `profile`.
+pub fn compute_lr0_profiled(gram: &Grammar, profile: &mut Profile) -> Result<LR0Output, GenError>
+{
+    let start = profile.start();
+    let derives = set_derives(gram);
+    profile.record(Phase::Derives, start, derives_bytes(&derives));
+
+    let start = profile.start();
+    let first_derives = set_first_derives(gram, &derives);
+    profile.record(Phase::Closure, start, slice_bytes(first_derives.data.as_slice()));
+
+    let start = profile.start();
+
+    // was: allocate_item_sets()
+    // This defines LR0State fields: kernel_base, kernel_items, kernel_end, shift_symbol
+    // The kernel_* fields are allocated to well-defined sizes, but their contents are
+    // not well-defined yet.
+    let mut kernel_items_count: uint = 0;
+    let mut symbol_count: Vec<uint> = Vec::from_elem(gram.nsyms, 0);
+    for i in range(0, gram.nitems) {
+        let symbol = gram.ritem[i];
+        if symbol >= 0 {
+            kernel_items_count += 1;
+            symbol_count[symbol as uint] += 1;
+        }
+    }
+    let kernel_base = {
+        let mut kernel_base: IdVec<SymbolId, uint> = IdVec::new();
+        let mut count: uint = 0;
+        for i in range(0, gram.nsyms) {
+            kernel_base.push(count);
+            count += symbol_count[i];
+        }
+        kernel_base
+    };
+
+    let mut lr0: LR0State = LR0State {
+        gram: gram,
+        state_map: HashMap::new(),
+        kernel_base: kernel_base,
+        kernel_end: IdVec::from_elem(gram.nsyms, None),
+        kernel_items: Vec::from_elem(kernel_items_count, ItemId(0)),
+        states: initialize_states(gram, &derives)
+    };
+
+    // These vectors are used for building tables during each state.
+    // It is inefficient to allocate and free these vectors within
+    // the scope of processing each state.
+    let mut red_set: Vec<RuleId> = Vec::new();
+    let mut shift_set: Vec<StateId> = Vec::with_capacity(gram.nsyms);
+    let mut item_set: Vec<ItemId> = Vec::with_capacity(gram.nitems);
+    let mut rule_set: Bitv32 = Bitv32::from_elem(gram.nrules, false);
+    let mut shift_symbol: Vec<SymbolId> = Vec::new();
+
+    // this_state represents our position within our work list.  The output.states
+    // array represents both our final output, and this_state is the next state
+    // within that array, where we need to generate new states from.  New states
+    // are added to output.states within append_states().
+    let mut this_state: uint = 0;
+
+    // State which becomes the output
+    let mut reductions: Vec<Reductions> = Vec::new();
+    let mut shifts: Vec<Shifts> = Vec::new();
+
+    while this_state < lr0.states.len() {
+        assert!(item_set.len() == 0);
+        debug!("computing closure for state s{}:", this_state);
+        print_core(gram, StateId(this_state), &lr0.states[this_state]);
+
+        // The output of closure() is stored in item_set.
+        // rule_set is used only as temporary storage.
+        // debug!("    nucleus items: {}", lr0.states[this_state].items.as_slice());
+        closure(gram, lr0.states[this_state].items.as_slice(), &first_derives, gram.nrules, &mut rule_set, &mut item_set);
+
+        // The output of save_reductions() is stored in reductions.
+        // red_set is used only as temporary storage.
+        save_reductions(gram, StateId(this_state), item_set.as_slice(), &mut red_set, &mut reductions);
+
+        // new_item_sets updates kernel_items, kernel_end, and shift_symbol, and also
+        // computes (returns) the number of shifts for the current state.
+        debug!("    new_item_sets: item_set = {}", item_set);
+        new_item_sets(gram, &mut lr0, item_set.as_slice(), &mut shift_symbol);
+        sort_shift_symbols(shift_symbol.as_mut_slice());
+
+        // append_states() potentially adds new states to lr0.states
+        try!(append_states(&mut lr0, &mut shift_set, &shift_symbol));
+        debug!("    shifts: {}", shift_set.as_slice());
+
+        // If there are any shifts for this state, record them.
+        if shift_symbol.len() > 0 {
+            shifts.push(Shifts {
+                state: StateId(this_state),
+                shifts: shift_set.clone()
+            });
+        }
+
+        item_set.clear();
+        shift_set.clear();
+        red_set.clear();
+        shift_symbol.clear();
+
+        debug!("");
+        this_state += 1;
+    }
+
+    // Return results
+    let output = LR0Output {
+        states: lr0.states,
+        reductions: reductions,
+        shifts: shifts,
+        nullable: set_nullable(gram),
+        derives: derives,
+    };
+    profile.record(Phase::Lr0, start, lr0_bytes(&output));
+    Ok(output)
+}
+
+// Gets the state for a particular symbol.  If no appropriate state exists,
+// then a new state will be created.
+fn get_state(lr0: &mut LR0State, symbol: SymbolId) -> Result<StateId, GenError>
+{
+    let isp = lr0.kernel_base[symbol];
+    let iend = lr0.kernel_end[symbol].expect("get_state: no kernel items for symbol");
+    let items: Vec<ItemId> = lr0.kernel_items.slice(isp, iend).to_vec();
+
+    // Look for an existing Core that has the same items.
+    if let Some(&state) = lr0.state_map.get(&items) {
+        return Ok(state);
+    }
+
+    // No match.  Add a new state.
+
+    if lr0.states.len() >= MAX_TABLE_INDEX {
+        return Err(GenError::TooManyStates(lr0.gram.name[symbol.index()].clone()));
+    }
+
+    let new_state = StateId(lr0.states.len());
+    lr0.state_map.insert(items.clone(), new_state);
+    lr0.states.push(Core {
+        accessing_symbol: symbol,
+        items: items
+    });
+
+    debug!("    created state {}:", new_state);
+    print_core(lr0.gram, new_state, &lr0.states[new_state.index()]);
+
+    Ok(new_state)
+}
+
+// This function creates the initial state, using the DERIVES relation for
+// the start symbol.  From this initial state, we will discover / create all
+// other states, by examining a state, the next variables that could be
+// encountered in those states, and finding the transitive closure over same.
+// Initializes the state table.
+fn initialize_states(gram: &Grammar, derives: &IdVec<SymbolId, Vec<RuleId>>) -> Vec<Core>
+{
+    debug!("initialize_states");
+
+    // create the initial state
+    let mut states: Vec<Core> = Vec::new();
+    states.push(Core {
+        items: derives[SymbolId(gram.start_symbol)].iter().map(|&rule| ItemId::from_i16(gram.rrhs[rule.index()])).collect(),
+        accessing_symbol: SymbolId(0)
+    });
+
+    debug!("initial state:");
+    print_core(gram, StateId(0), &states[0]);
+
+    states
+}
+
+fn print_core(gram: &Grammar, state: StateId, core: &Core)
+{
+    debug!("    {} : accessing_symbol={}", state, gram.name[core.accessing_symbol.index()]);
+
+    let mut line = String::new();
+    for i in range(0, core.items.len()) {
+        let rhs = core.items[i].index();
+        line.push_str(format!("item {:4} : ", rhs).as_slice());
+
+        // back up to start of this rule
+        let mut rhs_first = rhs;
+        while rhs_first > 0 && gram.ritem[rhs_first - 1] >= 0 {
+            rhs_first -= 1;
+        }
+
+        // loop through rhs
+        let mut j = rhs_first;
+        while gram.ritem[j] >= 0 {
+            if j == rhs {
+                line.push_str(" .");
+            }
+            line.push(' ');
+            line.push_str(gram.name[gram.ritem[j] as uint].as_slice());
+            j += 1;
+        }
+        if j == rhs {
+            line.push_str(" .");
+        }
+
+        debug!("        {}", line);
+        line.clear();
+    }
+}
+
+// fills shift_symbol with shifts
+fn new_item_sets(gram: &Grammar, lr0: &mut LR0State, item_set: &[ItemId], shift_symbol: &mut Vec<SymbolId>)
+{
+    assert!(shift_symbol.len() == 0);
+
+    // reset kernel_end
+    for i in lr0.kernel_end.data.iter_mut() {
+        *i = None;
+    }
+
+    for &it in item_set.iter() {
+        let symbol = gram.ritem[it.index()];
+        if symbol > 0 {
+            let symbol = SymbolId(symbol as uint);
+            let ksp = match lr0.kernel_end[symbol] {
+                Some(end) => end,
+                None => {
+                    shift_symbol.push(symbol);
+                    lr0.kernel_base[symbol]
+                }
+            };
+
+            // The kernel item of the new state is this item, with the dot moved past the symbol.
+            lr0.kernel_items[ksp] = ItemId(it.index() + 1);
+            lr0.kernel_end[symbol] = Some(ksp + 1);
+        }
+    }
+}
+
+fn save_reductions(gram: &Grammar, this_state: StateId, item_set: &[ItemId], red_set: &mut Vec<RuleId>, reductions: &mut Vec<Reductions>)
+{
+    assert!(red_set.len() == 0);
+
+    // Examine the items in the given item set.  If any of the items have reached the
+    // end of the rhs list for a particular rule, then add that rule to the reduction set.
+    // We discover this by testing the sign of the next symbol in the item; if it is
+    // negative, then we have reached the end of the symbols on the rhs of a rule.  See
+    // the code in reader::pack_grammar(), where this information is set up.
+    for &i in item_set.iter() {
+        let item = gram.ritem[i.index()];
+        if item < 0 {
+            let rule = RuleId::from_i16(-item);
+            debug!("        reduction: {}  {}", rule, gram.rule_to_str(rule.index()));
+            red_set.push(rule);
+        }
+    }
+
+    if red_set.len() != 0 {
+        reductions.push(Reductions {
+            state: this_state,
+            rules: red_set.clone()
+        });
+        red_set.clear();
+    }
+    else {
+        debug!("    no reductions");
+    }
+}
+
+/// Computes the `derives` table; see `LR0Output.derives`.
+pub fn set_derives(gram: &Grammar) -> IdVec<SymbolId, Vec<RuleId>>
+{
+    let mut derives: IdVec<SymbolId, Vec<RuleId>> = IdVec::from_elem(gram.nsyms, Vec::new());
+
+    // Rules 0 and 1 are placeholders, whose left-hand side is not a variable.
+    for r in range(0, gram.nrules) {
+        let lhs = gram.rlhs[r] as uint;
+        if lhs >= gram.start_symbol {
+            derives[SymbolId(lhs)].push(RuleId(r));
+        }
+    }
+
+    print_derives(gram, &derives);
+
+    derives
+}
+
+fn print_derives(gram: &Grammar, derives: &IdVec<SymbolId, Vec<RuleId>>)
+{
+    debug!("");
+    debug!("DERIVES:");
+    debug!("");
+
+    for lhs in range(gram.start_symbol, gram.nsyms) {
+        debug!("    {} derives rules: ", gram.name[lhs]);
+        for &r in derives[SymbolId(lhs)].iter() {
+            debug!("        {}", gram.rule_to_str(r.index()).as_slice());
+        }
+    }
+    debug!("");
+}
+
+/// Computes the set of nullable symbols; see `LR0Output.nullable`.
+pub fn set_nullable(gram: &Grammar) -> Bitv
+{
+    let mut nullable = Bitv::from_elem(gram.nsyms, false);
+
+    let mut done_flag = false;
+    while !done_flag {
+        done_flag = true;
+        let mut i = 1;
+        while i < gram.nitems {
+            let mut empty = true;
+            let mut j: i16;
+            loop {
+                j = gram.ritem[i];
+                if j < 0 {
+                    break;
+                }
+                if !nullable[j as uint] {
+                    empty = false;
+                }
+                i += 1;
+            }
+            if empty {
+                j = gram.rlhs[(-j) as uint];
+                if !nullable[j as uint] {
+                    nullable.set(j as uint, true);
+                    done_flag = false;
+                }
+            }
+        	i += 1;
+        }
+    }
+
+    for i in range(gram.start_symbol, gram.nsyms) {
+        if nullable[i] {
+            debug!("{} is nullable", gram.name[i]);
+        }
+        else {
+            debug!("{} is not nullable", gram.name[i]);
+        }
+    }
+
+    nullable
+}
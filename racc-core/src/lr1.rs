@@ -0,0 +1,355 @@
+// Builds the canonical LR(1) automaton of a grammar.
+//
+// This is an alternative to compute_lr0() followed by run_lalr().  The states of the canonical
+// LR(1) automaton are sets of LR(1) items: an LR(0) item together with a set of lookahead tokens.
+// Two states with the same LR(0) items but different lookaheads are different states, so the
+// automaton may have many more states than the LALR(1) automaton, but it has no conflicts
+// that come only from merging states (the "mysterious" reduce/reduce conflicts of LALR).
+//
+// The result is expressed in the same structures as the LALR pipeline (LR0Output, with one
+// entry for each LR(1) state, and LALROutput, with the exact lookaheads), so mkpar and the
+// table builder work without any changes.
+
+use std::collections::{Bitv, HashMap};
+
+use grammar::Grammar;
+use lr0::{LR0Output, Core, Shifts, Reductions, set_derives, set_nullable};
+use lalr::{LALROutput, from_lookaheads};
+use ids::{StateId, SymbolId, RuleId, ItemId, IdVec};
+use error::{GenError, MAX_TABLE_INDEX};
+
+/// A set of LR(1) items: items (indices into ritem), in ascending order, each with its set of
+/// lookahead tokens.
+pub type ItemSet = Vec<(i16, Bitv)>;
+
+struct LR1State<'a>
+{
+    gram: &'a Grammar,
+    nullable: Bitv,
+    first: Vec<Bitv>,           // first[s] is FIRST(s), as a set of tokens
+    derives: IdVec<SymbolId, Vec<RuleId>>,
+
+    kernels: Vec<ItemSet>,
+    accessing_symbol: Vec<uint>,
+
+    // Maps the LR(0) items of a kernel to the states which have that kernel.
+    state_table: HashMap<Vec<i16>, Vec<uint>>,
+}
+
+/// Computes the canonical LR(1) automaton of a grammar, and its lookaheads.  Fails if the
+/// automaton is too large for the tables to represent.
+pub fn compute_lr1(gram: &Grammar) -> Result<(LR0Output, LALROutput), GenError>
+{
+    let (lr0, la) = try!(build_lr1(gram));
+    let lalr = try!(from_lookaheads(gram, &lr0, la.as_slice()));
+    Ok((lr0, lalr))
+}
+
+// Builds the states of the canonical LR(1) automaton.  Returns the states, and the lookahead
+// tokens of each reduction, in the order of the reductions in LR0Output.reductions (the form
+// that lalr::from_lookaheads takes).
+fn build_lr1(gram: &Grammar) -> Result<(LR0Output, Vec<Vec<uint>>), GenError>
+{
+    let derives = set_derives(gram);
+    let nullable = set_nullable(gram);
+    let first = set_first(gram, &nullable);
+
+    let mut lr1 = LR1State {
+        gram: gram,
+        nullable: nullable,
+        first: first,
+        derives: derives,
+        kernels: Vec::new(),
+        accessing_symbol: Vec::new(),
+        state_table: HashMap::new(),
+    };
+
+    // The initial state contains the rules of $accept.  Its lookahead set is empty; the parser
+    // accepts on $end in the final state, rather than reducing $accept.
+    let initial: ItemSet = {
+        let mut kernel = Vec::new();
+        for &rule in lr1.derives[SymbolId(gram.start_symbol)].iter() {
+            kernel.push((gram.rrhs[rule.index()], Bitv::from_elem(gram.ntokens, false)));
+        }
+        kernel
+    };
+    try!(get_state(&mut lr1, initial, 0));
+
+    let mut shifts: Vec<Shifts> = Vec::new();
+    let mut reductions: Vec<Reductions> = Vec::new();
+    let mut la: Vec<Vec<uint>> = Vec::new();
+
+    let mut this_state: uint = 0;
+    while this_state < lr1.kernels.len() {
+        let items = closure(gram, &lr1.nullable, lr1.first.as_slice(), &lr1.derives, lr1.kernels[this_state].as_slice());
+
+        // Items at the end of a rule are reductions.  They are in ascending order of rule.
+        let mut rules: Vec<RuleId> = Vec::new();
+        for &(item, ref lookahead) in items.iter() {
+            let symbol = gram.ritem[item as uint];
+            if symbol < 0 {
+                rules.push(RuleId::from_i16(-symbol));
+                la.push(range(0, gram.ntokens).filter(|&t| lookahead[t]).collect());
+            }
+        }
+        if rules.len() != 0 {
+            reductions.push(Reductions { state: StateId(this_state), rules: rules });
+        }
+
+        // Every other item shifts its next symbol.  ($end is never shifted.)
+        let mut symbols: Vec<uint> = items.iter()
+            .map(|&(item, _)| gram.ritem[item as uint])
+            .filter(|&symbol| symbol > 0)
+            .map(|symbol| symbol as uint)
+            .collect();
+        symbols.sort();
+        symbols.dedup();
+
+        let mut targets: Vec<StateId> = Vec::with_capacity(symbols.len());
+        for &symbol in symbols.iter() {
+            let kernel: ItemSet = items.iter()
+                .filter(|&&(item, _)| gram.ritem[item as uint] as uint == symbol)
+                .map(|&(item, ref lookahead)| (item + 1, lookahead.clone()))
+                .collect();
+            let target = try!(get_state(&mut lr1, kernel, symbol));
+            targets.push(StateId(target));
+        }
+        if targets.len() != 0 {
+            shifts.push(Shifts { state: StateId(this_state), shifts: targets });
+        }
+
+        this_state += 1;
+    }
+
+    debug!("compute_lr1: {} states", lr1.kernels.len());
+
+    let LR1State { nullable, derives, kernels, accessing_symbol, .. } = lr1;
+    let states: Vec<Core> = kernels.into_iter().zip(accessing_symbol.into_iter()).map(|(kernel, symbol)| Core {
+        accessing_symbol: SymbolId(symbol),
+        items: kernel.iter().map(|&(item, _)| ItemId::from_i16(item)).collect(),
+    }).collect();
+
+    let lr0 = LR0Output {
+        states: states,
+        shifts: shifts,
+        reductions: reductions,
+        nullable: nullable,
+        derives: derives,
+    };
+    Ok((lr0, la))
+}
+
+// Finds the state with the given kernel, or creates it.
+fn get_state(lr1: &mut LR1State, kernel: ItemSet, symbol: uint) -> Result<uint, GenError>
+{
+    let core: Vec<i16> = kernel.iter().map(|&(item, _)| item).collect();
+
+    if let Some(states) = lr1.state_table.get(&core) {
+        for &state in states.iter() {
+            if lr1.kernels[state] == kernel {
+                return Ok(state);
+            }
+        }
+    }
+
+    if lr1.kernels.len() >= MAX_TABLE_INDEX {
+        return Err(GenError::TooManyStates(lr1.gram.name[symbol].clone()));
+    }
+
+    let state = lr1.kernels.len();
+    lr1.kernels.push(kernel);
+    lr1.accessing_symbol.push(symbol);
+    if let Some(states) = lr1.state_table.get_mut(&core) {
+        states.push(state);
+        return Ok(state);
+    }
+    lr1.state_table.insert(core, vec![state]);
+    Ok(state)
+}
+
+/// Computes the closure of a kernel.  For each item A : x . B y [L], the items B : . z are
+/// added, with lookaheads FIRST(y), plus L if y is nullable.  This is repeated until nothing
+/// changes.  `first` is the result of `set_first`.
+pub fn closure(gram: &Grammar, nullable: &Bitv, first: &[Bitv], derives: &IdVec<SymbolId, Vec<RuleId>>,
+               kernel: &[(i16, Bitv)]) -> ItemSet
+{
+    let mut lookaheads: Vec<Option<Bitv>> = Vec::from_elem(gram.nitems, None);
+    let mut work: Vec<uint> = Vec::new();
+
+    for &(item, ref lookahead) in kernel.iter() {
+        lookaheads[item as uint] = Some(lookahead.clone());
+        work.push(item as uint);
+    }
+
+    loop {
+        let item = match work.pop() {
+            Some(item) => item,
+            None => break
+        };
+        let symbol = gram.ritem[item];
+        if symbol < 0 || !gram.is_var(symbol as uint) {
+            continue;
+        }
+
+        // Compute the lookaheads of the new items.
+        let mut new_lookahead = Bitv::from_elem(gram.ntokens, false);
+        let mut rp = item + 1;
+        loop {
+            let next = gram.ritem[rp];
+            if next < 0 {
+                // Everything after B is nullable, so the lookaheads of this item carry over.
+                let lookahead = lookaheads[item].as_ref().unwrap().clone();
+                new_lookahead.union(&lookahead);
+                break;
+            }
+            new_lookahead.union(&first[next as uint]);
+            if !nullable[next as uint] {
+                break;
+            }
+            rp += 1;
+        }
+
+        for &rule in derives[SymbolId(symbol as uint)].iter() {
+            let new_item = gram.rrhs[rule.index()] as uint;
+            let changed = match lookaheads[new_item] {
+                Some(ref mut lookahead) => lookahead.union(&new_lookahead),
+                None => {
+                    lookaheads[new_item] = Some(new_lookahead.clone());
+                    true
+                }
+            };
+            if changed {
+                work.push(new_item);
+            }
+        }
+    }
+
+    lookaheads.into_iter().enumerate()
+        .filter_map(|(item, lookahead)| lookahead.map(|l| (item as i16, l)))
+        .collect()
+}
+
+/// Computes FIRST(s) for every symbol s, as a set of tokens (indexed by symbol).  `nullable` is
+/// the result of `lr0::set_nullable`.
+pub fn set_first(gram: &Grammar, nullable: &Bitv) -> Vec<Bitv>
+{
+    let mut first: Vec<Bitv> = Vec::from_fn(gram.nsyms, |s| {
+        let mut set = Bitv::from_elem(gram.ntokens, false);
+        if gram.is_token(s) {
+            set.set(s, true);
+        }
+        set
+    });
+
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for rule in range(2, gram.nrules) {
+            let lhs = gram.rlhs[rule] as uint;
+            let mut rp = gram.rrhs[rule] as uint;
+            while gram.ritem[rp] >= 0 {
+                let symbol = gram.ritem[rp] as uint;
+                let symbol_first = first[symbol].clone();
+                if first[lhs].union(&symbol_first) {
+                    changed = true;
+                }
+                if !nullable[symbol] {
+                    break;
+                }
+                rp += 1;
+            }
+        }
+    }
+
+    first
+}
+
+#[cfg(test)]
+mod tests {
+    use grammar::Grammar;
+    use lr0::{LR0Output, compute_lr0, set_nullable};
+    use lalr::{LALROutput, run_lalr};
+    use mkpar::make_parser;
+    use ids::{StateId, RuleId};
+    use yacc::read_yacc;
+    use super::{compute_lr1, set_first};
+
+    // LR(1), but not LALR(1): the states after `a c` and `b c` have the same items, and merging
+    // them gives reduce/reduce conflicts on d and e.  Rules 3 to 8; A : c is rule 7.
+    const NOT_LALR: &'static str = "%token a b c d e\n%%\nS : a A d | b B d | a B e | b A e ;\nA : c ;\nB : c ;\n";
+
+    // LALR(1), and so LR(1).  Its canonical automaton splits four of the LR(0) states.
+    const LVALUES: &'static str = "%token EQ STAR ID\n%%\nS : L EQ R | R ;\nL : STAR R | ID ;\nR : L ;\n";
+
+    fn grammar(text: &str) -> Grammar {
+        match read_yacc(text) {
+            Ok(y) => y.grammar,
+            Err(_) => panic!("cannot read the grammar")
+        }
+    }
+
+    // The lookaheads of `rule` in each state which reduces by it, as token names, sorted.
+    fn lookaheads(gram: &Grammar, lr0: &LR0Output, lalr: &LALROutput, rule: uint) -> Vec<Vec<String>> {
+        let mut sets: Vec<Vec<String>> = Vec::new();
+        for state in range(0, lr0.nstates()) {
+            for (r, tokens) in lalr.lookaheads_of(gram, StateId(state)).into_iter() {
+                if r == RuleId(rule) {
+                    sets.push(tokens.iter().map(|t| gram.name[t.index()].clone()).collect());
+                }
+            }
+        }
+        sets.sort();
+        sets
+    }
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|n| n.to_string()).collect()
+    }
+
+    #[test]
+    fn canonical_lr1_splits_the_merged_state() {
+        let gram = grammar(NOT_LALR);
+        let (lr0, lalr) = compute_lr1(&gram).unwrap();
+        assert_eq!(lr0.nstates(), 14);
+        assert_eq!(lookaheads(&gram, &lr0, &lalr, 7), vec![names(&["d"]), names(&["e"])]);
+        let parser = make_parser(&gram, &lr0, &lalr);
+        assert_eq!((parser.sr_conflicts, parser.rr_conflicts), (0, 0));
+    }
+
+    #[test]
+    fn lalr_merges_the_state() {
+        let gram = grammar(NOT_LALR);
+        let lr0 = compute_lr0(&gram).unwrap();
+        let lalr = run_lalr(&gram, &lr0).unwrap();
+        assert_eq!(lr0.nstates(), 13);
+        assert_eq!(lookaheads(&gram, &lr0, &lalr, 7), vec![names(&["d", "e"])]);
+        let parser = make_parser(&gram, &lr0, &lalr);
+        assert_eq!((parser.sr_conflicts, parser.rr_conflicts), (0, 2));
+    }
+
+    #[test]
+    fn canonical_lr1_of_an_lalr_grammar() {
+        let gram = grammar(LVALUES);
+        let (lr0, lalr) = compute_lr1(&gram).unwrap();
+        assert_eq!(lr0.nstates(), 14);
+        assert_eq!(compute_lr0(&gram).unwrap().nstates(), 10);
+        // R : L is reduced at the end of the input, and before EQ only where L is not on the
+        // left of EQ.  Rule 7 is R : L.
+        assert_eq!(lookaheads(&gram, &lr0, &lalr, 7), vec![names(&["$end"]), names(&["$end"]), names(&["$end", "EQ"])]);
+        let parser = make_parser(&gram, &lr0, &lalr);
+        assert_eq!((parser.sr_conflicts, parser.rr_conflicts), (0, 0));
+    }
+
+    #[test]
+    fn first_sets() {
+        let gram = grammar(LVALUES);
+        let first = set_first(&gram, &set_nullable(&gram));
+        let first_of = |name: &str| -> Vec<String> {
+            let s = range(0, gram.nsyms).find(|&s| gram.name[s].as_slice() == name).unwrap();
+            range(0, gram.ntokens).filter(|&t| first[s][t]).map(|t| gram.name[t].clone()).collect()
+        };
+        assert_eq!(first_of("S"), names(&["STAR", "ID"]));
+        assert_eq!(first_of("R"), names(&["STAR", "ID"]));
+        assert_eq!(first_of("EQ"), names(&["EQ"]));
+    }
+}
@@ -1,9 +1,11 @@
 use std::collections::Bitv;
 
 use grammar::Grammar;
+use builder::{Resolution, Choice};
 use lr0::LR0Output;
 use lalr::LALROutput;
 use util::{reverse_range};
+use ids::{StateId,RuleId};
 
 #[deriving(Copy,PartialEq)]
 pub enum ActionCode {
@@ -16,18 +18,53 @@ pub const RIGHT: u8 = 2;
 
 pub struct ParserAction {
     pub symbol: i16,
-    pub number: i16,
+    /// The state to shift to, or the rule to reduce by.
+    pub number: i32,
     pub prec: i16,
     pub action_code: ActionCode,
     pub assoc: u8,
     pub suppressed: u8
 }
 
+impl ParserAction {
+    /// Returns true if this action is part of the final parser, i.e. it was not suppressed by
+    /// conflict resolution.
+    pub fn is_active(&self) -> bool {
+        self.suppressed == 0
+    }
+}
+
+/// The parser actions for every state of the automaton.
+///
+/// `actions[s]` lists the shift and reduce actions of state `s`, ordered by symbol.  Actions
+/// whose `suppressed` field is non-zero lost a conflict (1), were removed by precedence (2), were
+/// removed by a `%resolve` annotation (3), or were removed by a `%prefer` policy (4), and are not
+/// part of the final parser.
 pub struct YaccParser {
     pub nstates: uint,
     pub actions: Vec<Vec<ParserAction>>,
     pub default_reductions: Vec<i16>,
-    pub final_state: uint
+    pub final_state: uint,
+    /// The number of shift/reduce conflicts which were not resolved by precedence.
+    pub sr_conflicts: uint,
+    /// The number of reduce/reduce conflicts.
+    pub rr_conflicts: uint,
+}
+
+impl YaccParser {
+    /// Returns the actions of `state`, ordered by symbol, including suppressed actions.  Use
+    /// `ParserAction::is_active` to select the actions of the final parser.
+    pub fn actions_of<'a>(&'a self, state: StateId) -> &'a [ParserAction] {
+        self.actions[state.index()].as_slice()
+    }
+
+    /// Returns the rule that `state` reduces by default, if any.
+    pub fn default_reduction(&self, state: StateId) -> Option<RuleId> {
+        match self.default_reductions[state.index()] {
+            0 => None,
+            rule => Some(RuleId::from_i16(rule))
+        }
+    }
 }
 
 pub fn make_parser(gram: &Grammar, lr0: &LR0Output, lalr: &LALROutput) -> YaccParser {
@@ -37,7 +74,7 @@ pub fn make_parser(gram: &Grammar, lr0: &LR0Output, lalr: &LALROutput) -> YaccPa
     }
 
     let final_state = find_final_state(gram, lr0, lalr);
-    remove_conflicts(lr0, final_state, &mut parser);
+    let (sr_conflicts, rr_conflicts) = remove_conflicts(gram, lr0, final_state, &mut parser);
     unused_rules(gram, &parser);
     let defred = default_reductions(lr0, &parser);
 
@@ -45,7 +82,9 @@ pub fn make_parser(gram: &Grammar, lr0: &LR0Output, lalr: &LALROutput) -> YaccPa
         nstates: lr0.nstates(),
         actions: parser,
         default_reductions: defred,
-        final_state: final_state
+        final_state: final_state,
+        sr_conflicts: sr_conflicts,
+        rr_conflicts: rr_conflicts
     }
 }
 
@@ -57,16 +96,16 @@ fn parse_actions(gram: &Grammar, lr0: &LR0Output, lalr: &LALROutput, stateno: ui
 
 fn get_shifts(gram: &Grammar, lr0: &LR0Output, lalr: &LALROutput, stateno: uint) -> Vec<ParserAction> {
     let mut actions: Vec<ParserAction> = Vec::new();
-    if lalr.shift_table[stateno] != -1 {
-        let sp = &lr0.shifts[lalr.shift_table[stateno] as uint];
+    if let Some(sp) = lalr.shift_table[StateId(stateno)] {
+        let sp = &lr0.shifts[sp];
         let to_state2 = &sp.shifts;
         for i in reverse_range(sp.shifts.len(), 0) {
-            let k = to_state2[i] as uint;
-            let symbol = lr0.states[k].accessing_symbol;
+            let k = to_state2[i].index();
+            let symbol = lr0.states[k].accessing_symbol.index();
             if gram.is_token(symbol) {
                 actions.push(ParserAction {
                     symbol: symbol as i16,
-                    number: k as i16,
+                    number: k as i32,
                     prec: gram.prec[symbol],
                     action_code: ActionCode::Shift,
                     assoc: gram.assoc[symbol],
@@ -85,7 +124,7 @@ fn add_reductions(gram: &Grammar, lalr: &LALROutput, stateno: uint, actions: &mu
     let m = lalr.lookaheads[stateno] as uint;
     let n = lalr.lookaheads[stateno + 1] as uint;
     for i in range(m, n) {
-        let ruleno = lalr.laruleno[i] as uint;
+        let ruleno = lalr.laruleno[i].index();
         for j in reverse_range(gram.ntokens, 0) {
             if lalr.LA.get(i, j) {
                 add_reduce(gram, actions, ruleno, j);
@@ -111,7 +150,7 @@ fn add_reduce(gram: &Grammar, actions: &mut Vec<ParserAction>, ruleno: uint, sym
 
     let temp = ParserAction {
         symbol: symbol16,
-        number: ruleno as i16,
+        number: ruleno as i32,
         prec: gram.rprec[ruleno],
         action_code: ActionCode::Reduce,
         assoc: gram.rassoc[ruleno],
@@ -123,13 +162,13 @@ fn add_reduce(gram: &Grammar, actions: &mut Vec<ParserAction>, ruleno: uint, sym
 
 fn find_final_state(gram: &Grammar, lr0: &LR0Output, lalr: &LALROutput) -> uint
 {
-    let p = &lr0.shifts[lalr.shift_table[0] as uint];
+    let p = &lr0.shifts[lalr.shift_table[StateId(0)].expect("the initial state has no shifts")];
     let to_state2 = &p.shifts;
     let goal = gram.ritem[1] as uint;
     let mut final_state: uint = 0;
     for i in reverse_range(to_state2.len(), 0) {
-        final_state = to_state2[i] as uint;
-        if lr0.states[final_state].accessing_symbol == goal {
+        final_state = to_state2[i].index();
+        if lr0.states[final_state].accessing_symbol.index() == goal {
             break;
         }
     }
@@ -160,7 +199,11 @@ fn unused_rules(gram: &Grammar, parser: &Vec<Vec<ParserAction>>)
     }
 }
 
-fn remove_conflicts(lr0: &LR0Output, final_state: uint, parser: &mut Vec<Vec<ParserAction>>) {
+// Returns the total number of shift/reduce and reduce/reduce conflicts.  A conflict between
+// reducing by a rule and another action on a token for which the rule has a %resolve annotation
+// is settled by the annotation, before precedence, and is not counted.  A conflict which
+// precedence does not settle either may be settled by a %prefer policy, and is not counted then.
+fn remove_conflicts(gram: &Grammar, lr0: &LR0Output, final_state: uint, parser: &mut Vec<Vec<ParserAction>>) -> (uint, uint) {
     let mut srtotal = 0;
     let mut rrtotal = 0;
     let mut srconflicts: Vec<i16> = Vec::from_elem(lr0.nstates(), 0);
@@ -182,7 +225,15 @@ fn remove_conflicts(lr0: &LR0Output, final_state: uint, parser: &mut Vec<Vec<Par
                     pvec[p].suppressed = 1;
                 }
                 else if pvec[pref].action_code == ActionCode::Shift {
-                    if pvec[pref].prec > 0 && pvec[p].prec > 0 {
+                    let resolution = gram.resolution(pvec[p].number as uint, symbol as uint);
+                    if resolution == Some(Resolution::Shift) {
+                        pvec[p].suppressed = 3;
+                    }
+                    else if resolution == Some(Resolution::Reduce) {
+                        pvec[pref].suppressed = 3;
+                        pref = p;
+                    }
+                    else if pvec[pref].prec > 0 && pvec[p].prec > 0 {
                         if pvec[pref].prec < pvec[p].prec {
                             pvec[pref].suppressed = 2;
                             pref = p;
@@ -203,13 +254,41 @@ fn remove_conflicts(lr0: &LR0Output, final_state: uint, parser: &mut Vec<Vec<Par
                         }
                     }
                     else {
-                        srcount += 1;
-                        pvec[p].suppressed = 1;
+                        let lhs = gram.rlhs[pvec[p].number as uint] as uint;
+                        match gram.preference(Choice::Shift, Choice::Reduce(lhs)) {
+                            Some(Choice::Shift) => pvec[p].suppressed = 4,
+                            Some(_) => {
+                                pvec[pref].suppressed = 4;
+                                pref = p;
+                            }
+                            None => {
+                                srcount += 1;
+                                pvec[p].suppressed = 1;
+                            }
+                        }
                     }
                 }
+                else if gram.resolution(pvec[pref].number as uint, symbol as uint) == Some(Resolution::Reduce) {
+                    pvec[p].suppressed = 3;
+                }
+                else if gram.resolution(pvec[p].number as uint, symbol as uint) == Some(Resolution::Reduce) {
+                    pvec[pref].suppressed = 3;
+                    pref = p;
+                }
                 else {
-                    rrcount += 1;
-                    pvec[p].suppressed = 1;
+                    let first = Choice::Reduce(gram.rlhs[pvec[pref].number as uint] as uint);
+                    let second = Choice::Reduce(gram.rlhs[pvec[p].number as uint] as uint);
+                    match gram.preference(first, second) {
+                        Some(winner) if winner == first => pvec[p].suppressed = 4,
+                        Some(_) => {
+                            pvec[pref].suppressed = 4;
+                            pref = p;
+                        }
+                        None => {
+                            rrcount += 1;
+                            pvec[p].suppressed = 1;
+                        }
+                    }
                 }
             }        
         }
@@ -223,6 +302,8 @@ fn remove_conflicts(lr0: &LR0Output, final_state: uint, parser: &mut Vec<Vec<Par
     if srtotal + rrtotal > 0 {
         total_conflicts(srtotal, rrtotal);
     }
+
+    (srtotal, rrtotal)
 }
 
 fn total_conflicts(srtotal: uint, rrtotal: uint)
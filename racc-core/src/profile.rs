@@ -0,0 +1,153 @@
+// Measures the phases of the analysis: how long each one takes, and how much memory its result
+// occupies.  The memory of a phase is the size of the tables that it produces (the length of each
+// vector times the size of its elements), not the peak use of the allocator, which cannot be
+// observed; but the tables are almost all of the memory that the analysis uses.
+//
+// analyze_with() runs with a Profile which it then discards; analyze_profiled() takes one from
+// the caller.  Setting RACC_PROFILE when compiling a grammar makes the grammar! macro print the
+// summary.
+
+use std::fmt;
+use std::mem::size_of;
+use time::precise_time_ns;
+
+use lr0::LR0Output;
+use lalr::LALROutput;
+use mkpar::{ParserAction, YaccParser};
+use tables::PackedTables;
+use ids::{StateId, SymbolId, RuleId, ItemId, IdVec};
+
+/// A phase of the analysis.
+#[deriving(Copy,Clone,PartialEq,Show)]
+pub enum Phase {
+    /// Finding the rules of each non-terminal (`lr0::set_derives`).
+    Derives,
+    /// Computing the relation which `closure` uses to add items to a state.
+    Closure,
+    /// Building the LR(0) automaton.
+    Lr0,
+    /// Computing the LALR(1) lookaheads.
+    Lookaheads,
+    /// Building the canonical LR(1) or IELR(1) automaton, with its lookaheads.
+    Lr1,
+    /// Building the parser actions, and resolving conflicts.
+    Actions,
+    /// Packing the actions into the run-time tables.
+    Packing,
+}
+
+impl Phase {
+    /// The name of the phase, as it is shown in the summary.
+    pub fn name(self) -> &'static str {
+        match self {
+            Phase::Derives => "derives",
+            Phase::Closure => "closure",
+            Phase::Lr0 => "LR(0)",
+            Phase::Lookaheads => "lookaheads",
+            Phase::Lr1 => "LR(1)",
+            Phase::Actions => "conflict resolution",
+            Phase::Packing => "packing",
+        }
+    }
+}
+
+/// The measurements of one phase.
+#[deriving(Copy,Clone,Show)]
+pub struct PhaseStats {
+    pub phase: Phase,
+    /// The time that the phase took, in nanoseconds.
+    pub nanos: u64,
+    /// The size of the tables that the phase produced, in bytes.
+    pub bytes: uint,
+}
+
+/// The measurements of the phases of an analysis, in the order that they ran.
+pub struct Profile {
+    pub phases: Vec<PhaseStats>,
+}
+
+impl Profile {
+    pub fn new() -> Profile {
+        Profile { phases: Vec::new() }
+    }
+
+    /// Returns the time at which a phase starts, to be passed to `record` when it ends.
+    pub fn start(&self) -> u64 {
+        precise_time_ns()
+    }
+
+    /// Records a phase which began at `start` (see `start`), and which produced `bytes` bytes of
+    /// tables.
+    pub fn record(&mut self, phase: Phase, start: u64, bytes: uint) {
+        self.phases.push(PhaseStats {
+            phase: phase,
+            nanos: precise_time_ns() - start,
+            bytes: bytes,
+        });
+    }
+
+    /// The total time of all of the phases, in nanoseconds.
+    pub fn total_nanos(&self) -> u64 {
+        self.phases.iter().fold(0, |total, p| total + p.nanos)
+    }
+
+    /// The total size of the tables of all of the phases, in bytes.
+    pub fn total_bytes(&self) -> uint {
+        self.phases.iter().fold(0, |total, p| total + p.bytes)
+    }
+}
+
+impl fmt::Show for Profile {
+    /// Writes a summary of the profile, with one line for each phase, and a total.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for p in self.phases.iter() {
+            try!(writeln!(f, "{:<20} {:>10.3} ms {:>12} bytes", p.phase.name(), (p.nanos as f64) / 1e6, p.bytes));
+        }
+        write!(f, "{:<20} {:>10.3} ms {:>12} bytes", "total", (self.total_nanos() as f64) / 1e6, self.total_bytes())
+    }
+}
+
+/// Returns the size of the elements of a slice, in bytes.
+pub fn slice_bytes<T>(v: &[T]) -> uint {
+    v.len() * size_of::<T>()
+}
+
+/// Returns the size of the tables of an LR(0) automaton, in bytes.
+pub fn lr0_bytes(lr0: &LR0Output) -> uint {
+    let states = lr0.states.iter().fold(0, |n, s| n + slice_bytes(s.items.as_slice()));
+    let shifts = lr0.shifts.iter().fold(0, |n, s| n + slice_bytes(s.shifts.as_slice()));
+    let reductions = lr0.reductions.iter().fold(0, |n, r| n + slice_bytes(r.rules.as_slice()));
+    states + shifts + reductions
+        + lr0.states.len() * size_of::<ItemId>()
+        + lr0.shifts.len() * size_of::<StateId>()
+        + lr0.reductions.len() * size_of::<RuleId>()
+        + derives_bytes(&lr0.derives)
+}
+
+/// Returns the size of the rules of each symbol (`LR0Output.derives`), in bytes.
+pub fn derives_bytes(derives: &IdVec<SymbolId, Vec<RuleId>>) -> uint {
+    derives.iter().fold(derives.len() * size_of::<Vec<RuleId>>(), |n, rules| n + slice_bytes(rules.as_slice()))
+}
+
+/// Returns the size of the lookahead tables, in bytes.
+pub fn lalr_bytes(lalr: &LALROutput) -> uint {
+    slice_bytes(lalr.shift_table.as_slice()) + slice_bytes(lalr.reduction_table.as_slice())
+        + slice_bytes(lalr.lookaheads.as_slice()) + slice_bytes(lalr.laruleno.as_slice())
+        + slice_bytes(lalr.LA.data.as_slice())
+        + slice_bytes(lalr.gotos.goto_map.as_slice()) + slice_bytes(lalr.gotos.from_state.as_slice())
+        + slice_bytes(lalr.gotos.to_state.as_slice())
+}
+
+/// Returns the size of the parser actions, in bytes.
+pub fn parser_bytes(parser: &YaccParser) -> uint {
+    parser.actions.iter().fold(0, |n, a| n + a.len() * size_of::<ParserAction>())
+        + slice_bytes(parser.default_reductions.as_slice())
+}
+
+/// Returns the size of the packed tables, in bytes, not counting the names and rule text.
+pub fn tables_bytes(tables: &PackedTables) -> uint {
+    [&tables.yydefred, &tables.yydgoto, &tables.yysindex, &tables.yyrindex, &tables.yygindex,
+     &tables.yytable, &tables.yycheck, &tables.yyconflicts]
+        .iter().fold(0, |n, t| n + slice_bytes(t.as_slice()))
+        + slice_bytes(tables.yylhs.as_slice()) + slice_bytes(tables.yylen.as_slice())
+}
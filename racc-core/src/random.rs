@@ -0,0 +1,299 @@
+//! Deterministic pseudo-random numbers for RACC's randomized utilities.
+//!
+//! Every randomized utility in RACC (sentence generation, grammar mutation, fuzzing helpers)
+//! draws all of its randomness from `Random`, and from nothing else.  None of them seed
+//! themselves from the clock or from the OS; the caller always supplies a seed.  This means
+//! that a failure which looks flaky can always be replayed, by running again with the same
+//! seed.
+//!
+//! The algorithm is fixed, and is part of RACC's contract: the seed is scrambled with one round
+//! of SplitMix64, and the stream is produced by xorshift64*.  A given seed produces the same
+//! sequence on every platform, and in every version of RACC.  Do not replace this with
+//! `std::rand`; its generators are free to change between releases of Rust.
+//!
+//! Test code should obtain its seed from `seed_from_env`, which honors the `RACC_SEED`
+//! environment variable, and should hold a `SeedGuard` (or use `with_seed`) while it runs, so
+//! that the seed is printed if the test panics.
+
+use std::io;
+
+/// The environment variable which `seed_from_env` reads.
+pub const SEED_ENV_VAR: &'static str = "RACC_SEED";
+
+/// The seed used by `seed_from_env` when `RACC_SEED` is not set.
+pub const DEFAULT_SEED: u64 = 0x5241_4343_2014_1226;
+
+/// A deterministic pseudo-random number generator (SplitMix64-seeded xorshift64*).
+#[deriving(Clone)]
+pub struct Random {
+    seed: u64,
+    state: u64,
+}
+
+// One round of SplitMix64.  Used to turn small or patterned seeds (0, 1, 2, ...) into
+// well-distributed initial states, and to derive the seeds of forked generators.
+fn splitmix64(x: u64) -> u64 {
+    let mut z = x + 0x9e37_79b9_7f4a_7c15;
+    z = (z ^ (z >> 30)) * 0xbf58_476d_1ce4_e5b9;
+    z = (z ^ (z >> 27)) * 0x94d0_49bb_1331_11eb;
+    z ^ (z >> 31)
+}
+
+impl Random {
+    /// Creates a generator from an explicit seed.  Any value is a valid seed, including 0.
+    pub fn new(seed: u64) -> Random {
+        let mut state = splitmix64(seed);
+        if state == 0 {
+            // xorshift has a fixed point at 0; this cannot happen for SplitMix64 output in
+            // practice, but be safe.
+            state = 0x2545_f491_4f6c_dd1d;
+        }
+        Random {
+            seed: seed,
+            state: state
+        }
+    }
+
+    /// Returns the seed this generator was created with.  Report this value when a
+    /// randomized check fails.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Returns the next 64 bits of the stream.
+    pub fn next_u64(&mut self) -> u64 {
+        let mut x = self.state;
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        self.state = x;
+        x * 0x2545_f491_4f6c_dd1d
+    }
+
+    /// Returns the next 32 bits of the stream (the high half of `next_u64`).
+    pub fn next_u32(&mut self) -> u32 {
+        (self.next_u64() >> 32) as u32
+    }
+
+    /// Returns a value uniformly distributed in `[0, n)`.  `n` must not be zero.
+    pub fn below(&mut self, n: uint) -> uint {
+        assert!(n > 0);
+        let n = n as u64;
+        // Reject the top partial "bucket", so that every value is equally likely.
+        let zone = !0u64 - (!0u64 % n);
+        loop {
+            let x = self.next_u64();
+            if x < zone {
+                return (x % n) as uint;
+            }
+        }
+    }
+
+    /// Returns a value uniformly distributed in `[low, high)`.
+    pub fn range(&mut self, low: uint, high: uint) -> uint {
+        assert!(low < high);
+        low + self.below(high - low)
+    }
+
+    /// Returns true with probability `numer / denom`.
+    pub fn chance(&mut self, numer: uint, denom: uint) -> bool {
+        assert!(denom > 0);
+        self.below(denom) < numer
+    }
+
+    /// Chooses one element of `items`, or `None` if `items` is empty.
+    pub fn choose<'a, T>(&mut self, items: &'a [T]) -> Option<&'a T> {
+        if items.len() == 0 {
+            None
+        }
+        else {
+            Some(&items[self.below(items.len())])
+        }
+    }
+
+    /// Chooses an index into `weights`, with probability proportional to the weight at that
+    /// index.  Returns `None` if all weights are zero (or there are none).
+    pub fn choose_weighted(&mut self, weights: &[uint]) -> Option<uint> {
+        let total = weights.iter().fold(0u, |a, &w| a + w);
+        if total == 0 {
+            return None;
+        }
+        let mut pick = self.below(total);
+        for (i, &w) in weights.iter().enumerate() {
+            if pick < w {
+                return Some(i);
+            }
+            pick -= w;
+        }
+        unreachable!();
+    }
+
+    /// Shuffles `items` in place (Fisher-Yates).
+    pub fn shuffle<T>(&mut self, items: &mut [T]) {
+        let mut i = items.len();
+        while i > 1 {
+            let j = self.below(i);
+            i -= 1;
+            items.swap(i, j);
+        }
+    }
+
+    /// Derives a new, independent generator from this one.  Use this to give each sub-task
+    /// (for example, each generated sentence) its own stream, so that changing how much
+    /// randomness one sub-task consumes does not perturb the others.
+    pub fn fork(&mut self) -> Random {
+        let seed = splitmix64(self.next_u64());
+        Random::new(seed)
+    }
+}
+
+/// Returns the seed given by the `RACC_SEED` environment variable (decimal, or hex with a `0x`
+/// prefix), or `default` if the variable is not set.  Panics if the variable is set but cannot
+/// be parsed, rather than silently using some other seed.
+pub fn seed_from_env(default: u64) -> u64 {
+    match ::std::os::getenv(SEED_ENV_VAR) {
+        Some(s) => {
+            match parse_seed(s.as_slice()) {
+                Some(seed) => seed,
+                None => panic!("{} is set to '{}', which is not a valid seed", SEED_ENV_VAR, s)
+            }
+        }
+        None => default
+    }
+}
+
+fn parse_seed(s: &str) -> Option<u64> {
+    let s = s.trim();
+    if s.starts_with("0x") || s.starts_with("0X") {
+        ::std::num::from_str_radix::<u64>(s.slice_from(2), 16)
+    }
+    else {
+        s.parse::<u64>()
+    }
+}
+
+/// Prints a seed (and how to replay it) if the current task panics while the guard is alive.
+///
+/// ```ignore
+///     let seed = random::seed_from_env(random::DEFAULT_SEED);
+///     let _guard = SeedGuard::new("sentence generation", seed);
+///     let mut rng = Random::new(seed);
+///     ...
+/// ```
+pub struct SeedGuard {
+    context: String,
+    seed: u64,
+}
+
+impl SeedGuard {
+    pub fn new(context: &str, seed: u64) -> SeedGuard {
+        SeedGuard {
+            context: context.to_string(),
+            seed: seed
+        }
+    }
+}
+
+impl Drop for SeedGuard {
+    fn drop(&mut self) {
+        if ::std::thread::Thread::panicking() {
+            let _ = writeln!(&mut io::stderr(), "racc: {} failed with seed {} (0x{:016x}); rerun with {}={} to reproduce",
+                self.context, self.seed, self.seed, SEED_ENV_VAR, self.seed);
+        }
+    }
+}
+
+/// Runs `f` with a generator seeded by `seed`, printing the seed if `f` panics.
+pub fn with_seed<T, F: FnMut(&mut Random) -> T>(context: &str, seed: u64, mut f: F) -> T {
+    let _guard = SeedGuard::new(context, seed);
+    let mut rng = Random::new(seed);
+    f(&mut rng)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Random, splitmix64, parse_seed};
+
+    // The first output of the reference SplitMix64, from state 0.
+    #[test]
+    fn splitmix64_matches_the_reference() {
+        assert_eq!(splitmix64(0), 0xe220_a839_7b1d_cdaf);
+    }
+
+    // The stream is part of the contract, so these values must never change.
+    #[test]
+    fn stream_of_seed_0() {
+        let mut rng = Random::new(0);
+        assert_eq!(rng.seed(), 0);
+        assert_eq!(rng.next_u64(), 0x7bbc_b40d_5506_82d0);
+        assert_eq!(rng.next_u64(), 0xde7f_e413_d00c_c9fd);
+        assert_eq!(rng.next_u64(), 0xb3c6_3835_3c66_8c91);
+    }
+
+    #[test]
+    fn next_u32_is_the_high_half() {
+        let mut rng = Random::new(!0u64);
+        assert_eq!(rng.next_u32(), 0x079c_e65d);
+    }
+
+    #[test]
+    fn below_of_seed_42() {
+        let mut rng = Random::new(42);
+        let values: Vec<uint> = range(0u, 10).map(|_| rng.below(10)).collect();
+        assert_eq!(values, vec![2u, 3, 9, 3, 2, 3, 1, 9, 7, 3]);
+    }
+
+    #[test]
+    fn shuffle_of_seed_1() {
+        let mut rng = Random::new(1);
+        let mut items = [0u, 1, 2, 3, 4, 5, 6, 7];
+        rng.shuffle(&mut items);
+        assert_eq!(items.as_slice(), [7u, 5, 1, 4, 6, 2, 0, 3].as_slice());
+    }
+
+    #[test]
+    fn same_seed_same_stream() {
+        let mut a = Random::new(1234);
+        let mut b = Random::new(1234);
+        for _ in range(0u, 100) {
+            assert_eq!(a.next_u64(), b.next_u64());
+        }
+    }
+
+    #[test]
+    fn fork_of_seed_7() {
+        let mut rng = Random::new(7);
+        let mut forked = rng.fork();
+        assert_eq!(forked.seed(), 0x8a62_1804_1fc8_cbed);
+        assert_eq!(forked.next_u64(), 0x3774_6c0a_b668_173a);
+        assert_eq!(rng.next_u64(), 0x421d_9d8f_ff2d_1844);
+    }
+
+    #[test]
+    fn choose_weighted_never_chooses_a_zero_weight() {
+        let mut rng = Random::new(3);
+        for _ in range(0u, 1000) {
+            assert!(rng.choose_weighted(&[1, 0, 3]) != Some(1));
+        }
+        assert_eq!(rng.choose_weighted(&[0, 0]), None);
+        assert_eq!(rng.choose_weighted(&[]), None);
+    }
+
+    #[test]
+    fn choose_from_empty_is_none() {
+        let mut rng = Random::new(3);
+        let empty: [uint, ..0] = [];
+        assert_eq!(rng.choose(&empty), None);
+        assert_eq!(rng.choose(&[5u]), Some(&5u));
+    }
+
+    #[test]
+    fn parse_seed_reads_decimal_and_hex() {
+        assert_eq!(parse_seed("42"), Some(42));
+        assert_eq!(parse_seed(" 42\n"), Some(42));
+        assert_eq!(parse_seed("0x2a"), Some(42));
+        assert_eq!(parse_seed("0X2A"), Some(42));
+        assert_eq!(parse_seed("forty-two"), None);
+        assert_eq!(parse_seed("0x"), None);
+    }
+}
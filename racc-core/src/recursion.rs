@@ -0,0 +1,144 @@
+// Finds the recursion in a grammar: derivation cycles, and left and right recursion.
+//
+// Each is found with a graph over the non-terminals and the transitive closure of that graph
+// (see warshall.rs).  For a rule A : X1 ... Xn, the graphs have these edges:
+//
+//      unit    A -> Xi, if every other symbol of the rule is nullable
+//      left    A -> Xi, if X1 ... X(i-1) are nullable
+//      right   A -> Xi, if X(i+1) ... Xn are nullable
+//
+// A non-terminal is in a cycle (A =>+ A) if it reaches itself in the unit graph; it is left
+// recursive (A =>+ A ...) if it reaches itself in the left graph; and it is right recursive
+// (A =>+ ... A) if it reaches itself in the right graph.
+//
+// A grammar with a cycle is ambiguous, since a cycle can be repeated any number of times.  Right
+// recursion is not a mistake, but an LR parser cannot reduce any of a right-recursive list until
+// it has shifted all of it, so its stack grows with the length of the list.
+
+use grammar::Grammar;
+use ids::{SymbolId, RuleId};
+use util::Bitmat;
+use warshall::transitive_closure;
+
+/// How a non-terminal refers to itself.
+#[deriving(Copy,Clone,PartialEq,Show)]
+pub enum Recursion {
+    /// It does not derive itself.
+    None,
+    /// It derives itself only in the middle of other symbols, as in `A : "(" A ")"`.
+    Middle,
+    /// It derives itself at the start, as in `List : List Item`.
+    Left,
+    /// It derives itself at the end, as in `List : Item List`.
+    Right,
+    /// It derives itself at both ends, as in `Expr : Expr "+" Expr`.
+    Both,
+}
+
+/// The recursion of the non-terminals of a grammar.
+pub struct RecursionReport {
+    /// The kind of recursion of each non-terminal, indexed by symbol.  Tokens are `None`.
+    pub kinds: Vec<Recursion>,
+    /// The non-terminals which derive themselves alone (A =>+ A).
+    pub cycles: Vec<SymbolId>,
+    /// The rules which make right-recursive lists, such as `List : Item "," List`: a rule of two
+    /// or three symbols, whose last symbol is its lhs, of a non-terminal which is not also left
+    /// recursive.  Parsing such a list takes stack space for every item.
+    pub right_recursive_lists: Vec<RuleId>,
+}
+
+// Returns true for each symbol which derives the empty string.
+fn nullable_symbols(gram: &Grammar) -> Vec<bool> {
+    let mut nullable: Vec<bool> = Vec::from_elem(gram.nsyms, false);
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for rule in range(2, gram.nrules) {
+            let lhs = gram.rlhs[rule] as uint;
+            if !nullable[lhs] && gram.get_rhs_items(rule).iter().all(|&s| nullable[s as uint]) {
+                nullable[lhs] = true;
+                changed = true;
+            }
+        }
+    }
+    nullable
+}
+
+// The graphs described above.
+#[deriving(Copy,PartialEq)]
+enum Graph {
+    Unit,
+    Left,
+    Right,
+    Any,        // an edge to every non-terminal in the rhs
+}
+
+// Builds one of the graphs, over the non-terminals, and returns its transitive closure.
+fn closure_of(gram: &Grammar, nullable: &[bool], graph: Graph) -> Bitmat {
+    let all_nullable = |syms: &[i16]| syms.iter().all(|&s| nullable[s as uint]);
+    let start = gram.start_symbol;
+    let mut m = Bitmat::new(gram.nvars, gram.nvars);
+    for rule in range(3, gram.nrules) {
+        let lhs = gram.rlhs[rule] as uint;
+        let rhs = gram.get_rhs_items(rule);
+        for i in range(0, rhs.len()) {
+            let s = rhs[i] as uint;
+            if !gram.is_var(s) {
+                continue;
+            }
+            let edge = match graph {
+                Graph::Unit => all_nullable(rhs.slice_to(i)) && all_nullable(rhs.slice_from(i + 1)),
+                Graph::Left => all_nullable(rhs.slice_to(i)),
+                Graph::Right => all_nullable(rhs.slice_from(i + 1)),
+                Graph::Any => true
+            };
+            if edge {
+                m.set(lhs - start, s - start);
+            }
+        }
+    }
+    transitive_closure(&mut m);
+    m
+}
+
+/// Finds the cycles and the recursion of a grammar.
+pub fn analyze_recursion(gram: &Grammar) -> RecursionReport {
+    let nullable = nullable_symbols(gram);
+    let unit = closure_of(gram, nullable.as_slice(), Graph::Unit);
+    let left = closure_of(gram, nullable.as_slice(), Graph::Left);
+    let right = closure_of(gram, nullable.as_slice(), Graph::Right);
+    let any = closure_of(gram, nullable.as_slice(), Graph::Any);
+
+    let start = gram.start_symbol;
+    let mut kinds: Vec<Recursion> = Vec::from_elem(gram.nsyms, Recursion::None);
+    let mut cycles: Vec<SymbolId> = Vec::new();
+    for v in range(0, gram.nvars) {
+        let (l, r) = (left.get(v, v), right.get(v, v));
+        kinds[start + v] = match (l, r) {
+            (true, true) => Recursion::Both,
+            (true, false) => Recursion::Left,
+            (false, true) => Recursion::Right,
+            (false, false) if any.get(v, v) => Recursion::Middle,
+            _ => Recursion::None
+        };
+        if unit.get(v, v) {
+            cycles.push(SymbolId(start + v));
+        }
+    }
+
+    let mut right_recursive_lists: Vec<RuleId> = Vec::new();
+    for rule in range(3, gram.nrules) {
+        let lhs = gram.rlhs[rule] as uint;
+        let rhs = gram.get_rhs_items(rule);
+        if (rhs.len() == 2 || rhs.len() == 3) && rhs[rhs.len() - 1] as uint == lhs
+            && rhs[0] as uint != lhs && kinds[lhs] == Recursion::Right {
+            right_recursive_lists.push(RuleId(rule));
+        }
+    }
+
+    RecursionReport {
+        kinds: kinds,
+        cycles: cycles,
+        right_recursive_lists: right_recursive_lists,
+    }
+}
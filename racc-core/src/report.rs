@@ -0,0 +1,117 @@
+// Writes a description of the automaton that was built for a grammar, like the y.output file of
+// Berkeley YACC (yacc -v).
+//
+// The report lists the rules of the grammar, and then every state: its kernel items, the items
+// added by closure, its actions on tokens (shifts, and reductions with their lookaheads), its
+// default reduction, and its gotos on non-terminals.  Actions which lost a conflict, or which were
+// removed by precedence, by %resolve, or by %prefer, are listed in brackets, as YACC does.  A
+// summary of the conflicts ends the report.
+
+use std::io::{IoResult, Writer};
+
+use grammar::Grammar;
+use lr0::LR0Output;
+use lalr::LALROutput;
+use mkpar::{ActionCode, YaccParser};
+use closure::{closure, set_first_derives};
+use conflicts::{find_conflicts, item_to_str};
+use util::Bitv32;
+use ids::ItemId;
+
+/// Writes a report of the automaton to `w`.
+pub fn write_report(w: &mut Writer, gram: &Grammar, lr0: &LR0Output, lalr: &LALROutput, parser: &YaccParser) -> IoResult<()> {
+    try!(writeln!(w, "Grammar"));
+    try!(writeln!(w, ""));
+    for rule in range(2, gram.nrules) {
+        try!(writeln!(w, "    {}", gram.rule_to_str(rule)));
+    }
+
+    let first_derives = set_first_derives(gram, &lr0.derives);
+    let mut rule_set = Bitv32::from_elem(gram.nrules, false);
+
+    for state in lr0.iter_states() {
+        try!(writeln!(w, ""));
+        try!(writeln!(w, ""));
+        try!(writeln!(w, "state {}", state.id.index()));
+        try!(writeln!(w, ""));
+
+        let nucleus = state.core.items.as_slice();
+        let mut item_set: Vec<ItemId> = Vec::new();
+        closure(gram, nucleus, &first_derives, gram.nrules, &mut rule_set, &mut item_set);
+        for &item in state.core.items.iter() {
+            try!(writeln!(w, "    {}", item_to_str(gram, item)));
+        }
+        let mut first_closure_item = true;
+        for &item in item_set.iter() {
+            if nucleus.contains(&item) {
+                continue;
+            }
+            if first_closure_item {
+                try!(writeln!(w, "  closure:"));
+                first_closure_item = false;
+            }
+            try!(writeln!(w, "    {}", item_to_str(gram, item)));
+        }
+        try!(writeln!(w, ""));
+
+        if state.id.index() == parser.final_state {
+            try!(writeln!(w, "    {:<16}accept", gram.pname[0]));
+        }
+        for p in parser.actions_of(state.id).iter() {
+            let symbol = gram.pname[p.symbol as uint].as_slice();
+            let action = match p.action_code {
+                ActionCode::Shift => format!("shift, and go to state {}", p.number),
+                ActionCode::Reduce => format!("reduce by rule {}", gram.rule_to_str(p.number as uint))
+            };
+            match p.suppressed {
+                0 => try!(writeln!(w, "    {:<16}{}", symbol, action)),
+                1 => try!(writeln!(w, "    {:<16}[{}]  (conflict)", symbol, action)),
+                2 => try!(writeln!(w, "    {:<16}[{}]  (removed by precedence)", symbol, action)),
+                3 => try!(writeln!(w, "    {:<16}[{}]  (removed by %resolve)", symbol, action)),
+                _ => try!(writeln!(w, "    {:<16}[{}]  (removed by %prefer)", symbol, action)),
+            }
+        }
+        if let Some(rule) = parser.default_reduction(state.id) {
+            try!(writeln!(w, "    {:<16}reduce by rule {}", ".", gram.rule_to_str(rule.index())));
+        }
+
+        let mut first_goto = true;
+        for target in state.shifts.iter() {
+            let symbol = lr0.states[target.index()].accessing_symbol.index();
+            if gram.is_var(symbol) {
+                if first_goto {
+                    try!(writeln!(w, ""));
+                    first_goto = false;
+                }
+                try!(writeln!(w, "    {:<16}go to state {}", gram.pname[symbol], target.index()));
+            }
+        }
+
+        let lookaheads = lalr.lookaheads_of(gram, state.id);
+        if lookaheads.len() != 0 {
+            try!(writeln!(w, ""));
+            for &(rule, ref tokens) in lookaheads.iter() {
+                let names: Vec<&str> = tokens.iter().map(|t| gram.pname[t.index()].as_slice()).collect();
+                try!(writeln!(w, "  lookaheads of rule {}: {}", gram.rule_to_str(rule.index()), names.connect(" ")));
+            }
+        }
+    }
+
+    let conflicts = find_conflicts(gram, lr0, parser);
+    try!(writeln!(w, ""));
+    try!(writeln!(w, ""));
+    try!(writeln!(w, "{} states, {} shift/reduce conflict(s), {} reduce/reduce conflict(s)",
+        lr0.nstates(), parser.sr_conflicts, parser.rr_conflicts));
+    for conflict in conflicts.iter() {
+        try!(writeln!(w, ""));
+        try!(writeln!(w, "{}", conflict.describe(gram)));
+    }
+    Ok(())
+}
+
+/// Returns the report of the automaton as a string.
+pub fn report_to_string(gram: &Grammar, lr0: &LR0Output, lalr: &LALROutput, parser: &YaccParser) -> String {
+    let mut buf: Vec<u8> = Vec::new();
+    write_report(&mut buf, gram, lr0, lalr, parser).unwrap();
+    String::from_utf8(buf).unwrap()
+}
@@ -0,0 +1,72 @@
+// FIRST and FOLLOW sets, for tools which work with a grammar rather than with its automaton.
+//
+// FIRST(X) is the set of tokens which can begin a string derived from X; FIRST of a token is the
+// token itself.  FOLLOW(X) is the set of tokens which can come right after X in a sentence of the
+// grammar; $end follows the start symbol.  FIRST is computed by lr1::set_first, which the LR(1)
+// construction uses.  FOLLOW is the least fixed point of these rules, for each rule
+// A : X1 ... Xn:
+//
+//      FOLLOW(Xi) includes FIRST(X(i+1)), and FIRST(X(i+2)) if X(i+1) is nullable, and so on
+//      FOLLOW(Xi) includes FOLLOW(A), if X(i+1) ... Xn are all nullable
+//
+// The sets are returned as sorted lists of token symbols.
+
+use std::collections::Bitv;
+
+use grammar::Grammar;
+use ids::SymbolId;
+use lr0::set_nullable;
+use lr1::set_first;
+
+fn to_symbols(set: &Bitv) -> Vec<SymbolId> {
+    set.iter().enumerate().filter(|&(_, b)| b).map(|(t, _)| SymbolId(t)).collect()
+}
+
+/// Returns true for each symbol (indexed by symbol) which derives the empty string.
+pub fn nullable_symbols(gram: &Grammar) -> Vec<bool> {
+    set_nullable(gram).iter().collect()
+}
+
+/// Computes FIRST(s) for every symbol s.  The result is indexed by symbol.
+pub fn first_sets(gram: &Grammar) -> Vec<Vec<SymbolId>> {
+    let nullable = set_nullable(gram);
+    set_first(gram, &nullable).iter().map(|set| to_symbols(set)).collect()
+}
+
+/// Computes FOLLOW(s) for every symbol s.  The result is indexed by symbol.
+pub fn follow_sets(gram: &Grammar) -> Vec<Vec<SymbolId>> {
+    let nullable = set_nullable(gram);
+    let first = set_first(gram, &nullable);
+    let mut follow: Vec<Bitv> = Vec::from_fn(gram.nsyms, |_| Bitv::from_elem(gram.ntokens, false));
+
+    // Rule 2, $accept : start $end, puts $end in FOLLOW(start).
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for rule in range(2, gram.nrules) {
+            let lhs = gram.rlhs[rule] as uint;
+            let rhs = gram.get_rhs_items(rule);
+            for i in range(0, rhs.len()) {
+                let symbol = rhs[i] as uint;
+                let mut rest_nullable = true;
+                for &next in rhs.slice_from(i + 1).iter() {
+                    if follow[symbol].union(&first[next as uint]) {
+                        changed = true;
+                    }
+                    if !nullable[next as uint] {
+                        rest_nullable = false;
+                        break;
+                    }
+                }
+                if rest_nullable && symbol != lhs {
+                    let lhs_follow = follow[lhs].clone();
+                    if follow[symbol].union(&lhs_follow) {
+                        changed = true;
+                    }
+                }
+            }
+        }
+    }
+
+    follow.iter().map(|set| to_symbols(set)).collect()
+}
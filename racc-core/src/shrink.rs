@@ -0,0 +1,254 @@
+// Shrinks a grammar to a small one which still has some property, such as "has a reduce/reduce
+// conflict" or "makes the generator panic", for bug reports.  A grammar of a real language has
+// hundreds of rules; the conflict or the bug usually needs a handful of them.
+//
+// The shrinking is delta debugging (Zeller's ddmin, testing complements only): the rules are
+// split into n chunks, and the grammar is rebuilt without each chunk in turn.  If one of those
+// grammars still has the property, it becomes the current grammar, and n shrinks by one;
+// otherwise n doubles, until the chunks are single rules.  The result is 1-minimal: removing any
+// one of its rules loses the property (or leaves a grammar which does not build).  Then the
+// tokens which no remaining rule uses are dropped, if that keeps the property.
+//
+// Each candidate grammar is rebuilt with GrammarBuilder from the original's tokens (with their
+// values and precedence), its start symbol, the chosen rules (with their %prec and %resolve), and
+// the %prefer policy for the non-terminals which still have rules.  A candidate which does not
+// build, because it uses a non-terminal whose rules were all removed, is simply rejected; the
+// predicate only ever sees valid grammars.
+
+use std::cmp;
+
+use grammar::{Grammar, LEFT, RIGHT};
+use builder::{GrammarBuilder, GrammarError, Assoc, Choice, PREDEFINED_RULES};
+use ids::RuleId;
+
+/// The result of `shrink_grammar`.
+pub struct Shrunk {
+    /// The smallest grammar found which has the property.
+    pub grammar: Grammar,
+    /// The rules of the original grammar which it kept, in order.
+    pub rules: Vec<RuleId>,
+    /// The number of grammars that the predicate was called with.
+    pub tests: uint,
+}
+
+/// Shrinks `gram` to a minimal grammar for which `predicate` is true.  Returns None if it is not
+/// true of `gram` itself.
+///
+/// ```ignore
+///     let shrunk = shrink_grammar(&gram, |g| analyze(g).map(|a| a.parser.rr_conflicts > 0).unwrap_or(false));
+///     println!("{}", shrunk.unwrap().grammar.to_yacc_string());
+/// ```
+pub fn shrink_grammar<F: FnMut(&Grammar) -> bool>(gram: &Grammar, mut predicate: F) -> Option<Shrunk> {
+    let mut tests = 0;
+    let mut rules: Vec<uint> = range(PREDEFINED_RULES, gram.nrules).collect();
+    let mut best = match try_rules(gram, rules.as_slice(), true, &mut predicate, &mut tests) {
+        Some(g) => g,
+        None => return None
+    };
+
+    let mut n = 2;
+    while rules.len() >= 2 {
+        let chunk = (rules.len() + n - 1) / n;
+        let mut reduced = false;
+        let mut start = 0;
+        while start < rules.len() {
+            let end = cmp::min(start + chunk, rules.len());
+            let mut complement: Vec<uint> = rules.slice_to(start).to_vec();
+            complement.push_all(rules.slice_from(end));
+            if let Some(g) = try_rules(gram, complement.as_slice(), true, &mut predicate, &mut tests) {
+                rules = complement;
+                best = g;
+                n = cmp::max(n - 1, 2);
+                reduced = true;
+                break;
+            }
+            start = end;
+        }
+        if !reduced {
+            if n >= rules.len() {
+                break;
+            }
+            n = cmp::min(n * 2, rules.len());
+        }
+    }
+
+    if let Some(g) = try_rules(gram, rules.as_slice(), false, &mut predicate, &mut tests) {
+        best = g;
+    }
+
+    Some(Shrunk {
+        grammar: best,
+        rules: rules.iter().map(|&r| RuleId(r)).collect(),
+        tests: tests,
+    })
+}
+
+// Builds the grammar with only `rules`, and tests it.
+fn try_rules<F: FnMut(&Grammar) -> bool>(gram: &Grammar, rules: &[uint], all_tokens: bool, predicate: &mut F, tests: &mut uint) -> Option<Grammar> {
+    match grammar_with_rules(gram, rules, all_tokens) {
+        Ok(g) => {
+            *tests += 1;
+            if (*predicate)(&g) { Some(g) } else { None }
+        }
+        Err(_) => None
+    }
+}
+
+/// Rebuilds `gram` with only some of its rules, which are given by their numbers in `gram`.
+/// The tokens keep their values and precedence, and the rules their `%prec` and `%resolve`; the
+/// preferences of `%prefer` are kept if their non-terminals still have rules.  Unless
+/// `all_tokens` is true, only the tokens that the rules use are declared.
+pub fn grammar_with_rules(gram: &Grammar, rules: &[uint], all_tokens: bool) -> Result<Grammar, Vec<GrammarError>> {
+    // The precedence token of each rule, if it was given one by %prec.  See yacc::to_yacc_string.
+    let prec_tokens: Vec<Option<uint>> = rules.iter().map(|&r| {
+        let natural = gram.get_rhs_items(r).iter().rev()
+            .find(|&&s| gram.is_token(s as uint))
+            .map_or(0, |&s| gram.prec[s as uint]);
+        if gram.rprec[r] != natural && gram.rprec[r] != 0 {
+            range(2, gram.ntokens).find(|&t| gram.prec[t] == gram.rprec[r] && gram.assoc[t] == gram.rassoc[r])
+        }
+        else {
+            None
+        }
+    }).collect();
+
+    let mut used = Vec::from_elem(gram.ntokens, all_tokens);
+    for (i, &r) in rules.iter().enumerate() {
+        for &s in gram.get_rhs_items(r).iter() {
+            if gram.is_token(s as uint) {
+                used[s as uint] = true;
+            }
+        }
+        if let Some(t) = prec_tokens[i] {
+            used[t] = true;
+        }
+        for &(_, t, _) in gram.resolutions.iter().filter(|&&(rule, _, _)| rule == r) {
+            used[t] = true;
+        }
+    }
+
+    // Tokens 0 and 1 are $end and error, which the builder defines itself.
+    let mut builder = GrammarBuilder::new();
+    for t in range(2, gram.ntokens) {
+        if used[t] && gram.prec[t] == 0 {
+            builder.token(gram.name[t].as_slice());
+        }
+    }
+    let max_prec = range(0, gram.ntokens).map(|t| gram.prec[t]).max().unwrap_or(0);
+    for level in range(1, max_prec + 1) {
+        let tokens: Vec<uint> = range(2, gram.ntokens).filter(|&t| used[t] && gram.prec[t] == level).collect();
+        if tokens.len() == 0 {
+            continue;
+        }
+        let assoc = match gram.assoc[tokens[0]] {
+            LEFT => Assoc::Left,
+            RIGHT => Assoc::Right,
+            _ => Assoc::NonAssoc
+        };
+        let names: Vec<&str> = tokens.iter().map(|&t| gram.name[t].as_slice()).collect();
+        builder.precedence(assoc, names.as_slice());
+    }
+    for t in range(2, gram.ntokens) {
+        if used[t] {
+            builder.token_value(gram.name[t].as_slice(), gram.value[t]);
+            let pname = gram.pname[t].as_slice();
+            if pname != gram.name[t].as_slice() && pname.len() >= 2 && !pname.contains("\\") {
+                builder.alias(gram.name[t].as_slice(), pname.slice(1, pname.len() - 1));
+            }
+        }
+    }
+
+    for (i, &r) in rules.iter().enumerate() {
+        let rhs: Vec<&str> = gram.get_rhs_items(r).iter().map(|&s| gram.name[s as uint].as_slice()).collect();
+        builder.rule(gram.name[gram.rlhs[r] as uint].as_slice(), rhs.as_slice());
+        if let Some(t) = prec_tokens[i] {
+            builder.prec(gram.name[t].as_slice());
+        }
+        for &(_, t, resolution) in gram.resolutions.iter().filter(|&&(rule, _, _)| rule == r) {
+            builder.resolve(resolution, gram.name[t].as_slice());
+        }
+    }
+    let has_rules = |choice: Choice| match choice {
+        Choice::Reduce(nt) => rules.iter().any(|&r| gram.rlhs[r] as uint == nt),
+        Choice::Shift => true
+    };
+    for &(winner, loser) in gram.preferences.iter() {
+        if !has_rules(winner) || !has_rules(loser) {
+            continue;
+        }
+        match (winner, loser) {
+            (Choice::Reduce(w), Choice::Reduce(l)) => builder.prefer(gram.name[w].as_slice(), gram.name[l].as_slice()),
+            (Choice::Shift, Choice::Reduce(l)) => builder.prefer_shift(gram.name[l].as_slice()),
+            (Choice::Reduce(w), Choice::Shift) => builder.prefer_reduce(gram.name[w].as_slice()),
+            (Choice::Shift, Choice::Shift) => continue
+        };
+    }
+    let goal = gram.ritem[gram.rrhs[2] as uint] as uint;    // rule 2 is $accept : goal $end
+    builder.start(gram.name[goal].as_slice());
+    builder.build()
+}
+
+#[cfg(test)]
+mod tests {
+    use grammar::{Grammar, LEFT};
+    use ids::RuleId;
+    use yacc::read_yacc;
+    use super::shrink_grammar;
+
+    fn grammar(text: &str) -> Grammar {
+        match read_yacc(text) {
+            Ok(y) => y.grammar,
+            Err(_) => panic!("cannot read the grammar")
+        }
+    }
+
+    fn token(gram: &Grammar, name: &str) -> Option<uint> {
+        range(0, gram.ntokens).find(|&t| gram.name[t].as_slice() == name)
+    }
+
+    // True if a rule of the grammar uses the token `name`.
+    fn uses(gram: &Grammar, name: &str) -> bool {
+        range(3, gram.nrules).any(|r| gram.get_rhs_items(r).iter().any(|&s| gram.name[s as uint].as_slice() == name))
+    }
+
+    #[test]
+    fn shrinks_to_the_rules_that_are_needed() {
+        // Rules 3 to 6.
+        let gram = grammar("%token NUM PLUS LP RP\n%%\ne : e PLUS t | t ;\nt : NUM | LP e RP ;\n");
+        let shrunk = shrink_grammar(&gram, |g| uses(g, "PLUS")).unwrap();
+        // Without 3 or without 6, some non-terminal has no rules; [3, 5] would also do, but ddmin
+        // removes 5 first.
+        assert_eq!(shrunk.rules, vec![RuleId(3), RuleId(6)]);
+        assert_eq!(shrunk.grammar.nrules, 5);
+        assert_eq!(shrunk.grammar.rule_to_str(3).as_slice(), "(r3) e : e PLUS t");
+        assert_eq!(shrunk.grammar.rule_to_str(4).as_slice(), "(r4) t : LP e RP");
+        // NUM is no longer used, so it is dropped.
+        assert_eq!(token(&shrunk.grammar, "NUM"), None);
+        assert_eq!(shrunk.grammar.value[token(&shrunk.grammar, "LP").unwrap()], gram.value[token(&gram, "LP").unwrap()]);
+        // The whole grammar, [4, 5, 6], [3, 5, 6], [3, 6], and [3, 6] without NUM; the others do
+        // not build.
+        assert_eq!(shrunk.tests, 5);
+    }
+
+    #[test]
+    fn keeps_prec() {
+        let gram = grammar("%token NUM MINUS\n%left PLUS\n%left TIMES\n%%\ne : e PLUS e | e TIMES e | MINUS e %prec TIMES | NUM ;\n");
+        let shrunk = shrink_grammar(&gram, |g| uses(g, "MINUS")).unwrap();
+        assert_eq!(shrunk.rules, vec![RuleId(5)]);
+        let g = &shrunk.grammar;
+        assert_eq!(token(g, "PLUS"), None);
+        assert_eq!(token(g, "NUM"), None);
+        // TIMES is used only by %prec, which keeps it, and its precedence.
+        let times = token(g, "TIMES").unwrap();
+        assert!(g.prec[times] > 0);
+        assert_eq!(g.assoc[times], LEFT);
+        assert_eq!(g.rprec[3], g.prec[times]);
+        assert_eq!(shrunk.tests, 5);
+    }
+
+    #[test]
+    fn none_if_the_grammar_lacks_the_property() {
+        let gram = grammar("%token NUM\n%%\ne : NUM ;\n");
+        assert!(shrink_grammar(&gram, |g| uses(g, "PLUS")).is_none());
+    }
+}
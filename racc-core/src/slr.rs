@@ -0,0 +1,149 @@
+// Computes SLR(1) lookaheads for an LR(0) automaton, and compares them with LALR(1).
+//
+// SLR(1) is the simplest way to add lookaheads to the LR(0) automaton: a state reduces by a rule
+// on every token which can follow the left-hand side of the rule anywhere in the grammar (its
+// FOLLOW set; see sets.rs).  LALR(1) uses only the tokens which can follow it in the context of
+// that state, which is a subset.  Every grammar which is SLR(1) is LALR(1), but not the other
+// way around; the conflicts which only SLR(1) has come from tokens in FOLLOW(A) which cannot
+// follow A where the state is.  This construction is mostly of interest for teaching, and for
+// showing what LALR(1) gains; compare() lists exactly those conflicts.
+//
+// The result is in the same form as that of run_lalr, so mkpar and the table builder work
+// without any changes.
+
+use lr0::LR0Output;
+use lalr::{LALROutput, from_lookaheads, run_lalr};
+use mkpar::make_parser;
+use conflicts::{find_conflicts, Conflict};
+use grammar::Grammar;
+use sets::follow_sets;
+use error::GenError;
+
+/// Computes the SLR(1) lookaheads of an LR(0) automaton: the lookahead set of each reduction is
+/// FOLLOW of the left-hand side of its rule.  Fails if the automaton has too many gotos for the
+/// tables to represent.
+pub fn run_slr(gram: &Grammar, lr0: &LR0Output) -> Result<LALROutput, GenError> {
+    let follow = follow_sets(gram);
+    let mut la: Vec<Vec<uint>> = Vec::new();
+    for reductions in lr0.reductions.iter() {
+        for rule in reductions.rules.iter() {
+            let lhs = gram.rlhs[rule.index()] as uint;
+            la.push(follow[lhs].iter().map(|t| t.index()).collect());
+        }
+    }
+    from_lookaheads(gram, lr0, la.as_slice())
+}
+
+/// The conflicts of the SLR(1) and LALR(1) parsers of a grammar.  Both have the same LR(0)
+/// states, so a conflict of one is the same as a conflict of the other if it has the same state
+/// and token.
+pub struct SlrComparison {
+    /// The conflicts of the SLR(1) parser which the LALR(1) parser does not have.
+    pub slr_only: Vec<Conflict>,
+    /// The conflicts which both parsers have, as the LALR(1) parser has them.
+    pub both: Vec<Conflict>,
+}
+
+/// Builds the SLR(1) and LALR(1) parsers of a grammar, and compares their conflicts.
+pub fn compare(gram: &Grammar, lr0: &LR0Output) -> Result<SlrComparison, GenError> {
+    let slr = try!(run_slr(gram, lr0));
+    let lalr = try!(run_lalr(gram, lr0));
+    let slr_conflicts = find_conflicts(gram, lr0, &make_parser(gram, lr0, &slr));
+    let lalr_conflicts = find_conflicts(gram, lr0, &make_parser(gram, lr0, &lalr));
+
+    let mut slr_only: Vec<Conflict> = Vec::new();
+    for conflict in slr_conflicts.into_iter() {
+        if !lalr_conflicts.iter().any(|c| c.state == conflict.state && c.token == conflict.token) {
+            slr_only.push(conflict);
+        }
+    }
+    Ok(SlrComparison {
+        slr_only: slr_only,
+        both: lalr_conflicts,
+    })
+}
+
+impl SlrComparison {
+    /// Describes the comparison: each conflict which only SLR(1) has, and then a count of the
+    /// conflicts which both have.
+    pub fn describe(&self, gram: &Grammar) -> String {
+        let mut s = String::new();
+        if self.slr_only.len() == 0 {
+            s.push_str("Every conflict of the SLR(1) parser is also a conflict of the LALR(1) parser.\n");
+        }
+        else {
+            s.push_str(format!("{} conflict(s) of the SLR(1) parser vanish with LALR(1) lookaheads:\n", self.slr_only.len()).as_slice());
+            for conflict in self.slr_only.iter() {
+                s.push_str("\n");
+                s.push_str(conflict.describe(gram).as_slice());
+                s.push_str("\n");
+            }
+        }
+        s.push_str(format!("\n{} conflict(s) are in both parsers.\n", self.both.len()).as_slice());
+        s
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use grammar::Grammar;
+    use lr0::compute_lr0;
+    use mkpar::make_parser;
+    use conflicts::ConflictKind;
+    use ids::{SymbolId, RuleId};
+    use yacc::read_yacc;
+    use super::{run_slr, compare};
+
+    // LALR(1), but not SLR(1): EQ is in FOLLOW(R), so the state after L (at the start of the
+    // input) reduces R : L on EQ, where it must shift.  Rule 7 is R : L.
+    const LVALUES: &'static str = "%token EQ STAR ID\n%%\nS : L EQ R | R ;\nL : STAR R | ID ;\nR : L ;\n";
+
+    fn grammar(text: &str) -> Grammar {
+        match read_yacc(text) {
+            Ok(y) => y.grammar,
+            Err(_) => panic!("cannot read the grammar")
+        }
+    }
+
+    fn token(gram: &Grammar, name: &str) -> SymbolId {
+        SymbolId(range(0, gram.ntokens).find(|&t| gram.name[t].as_slice() == name).unwrap())
+    }
+
+    #[test]
+    fn slr_conflict() {
+        let gram = grammar(LVALUES);
+        let lr0 = compute_lr0(&gram).unwrap();
+        let slr = run_slr(&gram, &lr0).unwrap();
+        let parser = make_parser(&gram, &lr0, &slr);
+        assert_eq!((parser.sr_conflicts, parser.rr_conflicts), (1, 0));
+    }
+
+    #[test]
+    fn conflicts_which_lalr_removes() {
+        let gram = grammar(LVALUES);
+        let lr0 = compute_lr0(&gram).unwrap();
+        let comparison = compare(&gram, &lr0).unwrap();
+        assert_eq!(comparison.both.len(), 0);
+        assert_eq!(comparison.slr_only.len(), 1);
+        let conflict = &comparison.slr_only[0];
+        assert_eq!(conflict.kind, ConflictKind::ShiftReduce);
+        assert_eq!(conflict.token, token(&gram, "EQ"));
+        assert_eq!(conflict.reductions, vec![RuleId(7)]);
+        assert!(conflict.shift.is_some());
+        assert!(comparison.describe(&gram).as_slice().starts_with("1 conflict(s) of the SLR(1) parser vanish with LALR(1) lookaheads:\n"));
+    }
+
+    #[test]
+    fn conflicts_in_both() {
+        // Not LALR(1): the reduce/reduce conflicts on d and e come from merging states, and
+        // SLR(1) has them too.
+        let gram = grammar("%token a b c d e\n%%\nS : a A d | b B d | a B e | b A e ;\nA : c ;\nB : c ;\n");
+        let lr0 = compute_lr0(&gram).unwrap();
+        let comparison = compare(&gram, &lr0).unwrap();
+        assert_eq!(comparison.slr_only.len(), 0);
+        let tokens: Vec<SymbolId> = comparison.both.iter().map(|c| c.token).collect();
+        assert_eq!(tokens, vec![token(&gram, "d"), token(&gram, "e")]);
+        assert!(comparison.both.iter().all(|c| c.kind == ConflictKind::ReduceReduce));
+        assert!(comparison.describe(&gram).as_slice().starts_with("Every conflict of the SLR(1) parser is also a conflict of the LALR(1) parser.\n"));
+    }
+}
@@ -0,0 +1,123 @@
+// Counts the parts of a grammar and of the parser built for it: the summary that Berkeley YACC
+// writes at the end of y.output, plus the conflicts that precedence resolved and the size of the
+// packed tables.
+//
+// The conflicts "before precedence" are counted as if the grammar had no precedence
+// declarations: for each state and token, every action after the first is a conflict, a
+// shift/reduce conflict if one of the actions is a shift, and a reduce/reduce conflict if not.
+// The conflicts "after precedence" are those of the final parser (YaccParser.sr_conflicts and
+// rr_conflicts), which is what %expect counts.
+
+use std::fmt;
+
+use grammar::Grammar;
+use mkpar::ActionCode;
+use tables::PackedTables;
+use profile::tables_bytes;
+use ids::StateId;
+use Analysis;
+
+/// The sizes of a grammar, its automaton, and its tables.
+#[deriving(Copy,Clone,PartialEq)]
+pub struct GrammarStats {
+    /// The tokens, including `$end` and `error`.
+    pub terminals: uint,
+    /// The non-terminals, not counting `$accept`.
+    pub nonterminals: uint,
+    /// The rules of the grammar, not counting `$accept : goal $end`.
+    pub rules: uint,
+    /// The LR(0) items of the rules (a rule of n symbols has n + 1 items).
+    pub items: uint,
+    /// The states of the automaton.  These are the LR(0) states, unless the analysis built an
+    /// LR(1) automaton.
+    pub states: uint,
+    /// The shift/reduce conflicts, counted without precedence.
+    pub sr_conflicts_before: uint,
+    /// The reduce/reduce conflicts, counted without precedence.
+    pub rr_conflicts_before: uint,
+    /// The shift/reduce conflicts of the final parser.
+    pub sr_conflicts: uint,
+    /// The reduce/reduce conflicts of the final parser.
+    pub rr_conflicts: uint,
+    /// The actions that precedence removed.
+    pub resolved_by_precedence: uint,
+    /// The entries of the packed action table (`yytable`, and `yycheck`).
+    pub table_entries: uint,
+    /// The entries that the action and goto tables would have if they were not packed: one for
+    /// each state and symbol.
+    pub unpacked_entries: uint,
+    /// The size of the packed tables, in bytes (see `profile::tables_bytes`).
+    pub table_bytes: uint,
+}
+
+/// Counts the parts of a grammar, of its analysis, and of the tables built from it.
+pub fn grammar_stats(gram: &Grammar, analysis: &Analysis, tables: &PackedTables) -> GrammarStats {
+    let parser = &analysis.parser;
+    let mut sr_before = 0;
+    let mut rr_before = 0;
+    let mut resolved = 0;
+    for state in range(0, parser.nstates) {
+        let actions = parser.actions_of(StateId(state));
+        let mut i = 0;
+        while i < actions.len() {
+            let mut j = i + 1;
+            while j < actions.len() && actions[j].symbol == actions[i].symbol {
+                j += 1;
+            }
+            if j - i > 1 {
+                if actions.slice(i, j).iter().any(|a| a.action_code == ActionCode::Shift) {
+                    sr_before += j - i - 1;
+                }
+                else {
+                    rr_before += j - i - 1;
+                }
+            }
+            i = j;
+        }
+        resolved += actions.iter().filter(|a| a.suppressed == 2).count();
+    }
+
+    GrammarStats {
+        terminals: gram.ntokens,
+        nonterminals: gram.nvars - 1,
+        rules: gram.nrules - 3,
+        items: range(3, gram.nrules).fold(0, |n, r| n + gram.get_rhs_items(r).len() + 1),
+        states: analysis.lr0.nstates(),
+        sr_conflicts_before: sr_before,
+        rr_conflicts_before: rr_before,
+        sr_conflicts: parser.sr_conflicts,
+        rr_conflicts: parser.rr_conflicts,
+        resolved_by_precedence: resolved,
+        table_entries: tables.yytable.len(),
+        unpacked_entries: analysis.lr0.nstates() * gram.nsyms,
+        table_bytes: tables_bytes(tables),
+    }
+}
+
+impl GrammarStats {
+    /// Writes the statistics as a summary of a few lines, like the end of YACC's y.output:
+    ///
+    /// ```ignore
+    ///     8 terminals, 4 nonterminals
+    ///     7 grammar rules, 19 items, 12 states
+    ///     2 shift/reduce and 0 reduce/reduce conflicts before precedence, 0 and 0 after
+    ///     2 actions removed by precedence
+    ///     26 table entries (96 unpacked), 138 bytes of tables
+    /// ```
+    pub fn summary(&self) -> String {
+        format!("{}", self)
+    }
+}
+
+impl fmt::Show for GrammarStats {
+    /// Writes the summary; see `summary`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        try!(writeln!(f, "{} terminals, {} nonterminals", self.terminals, self.nonterminals));
+        try!(writeln!(f, "{} grammar rules, {} items, {} states", self.rules, self.items, self.states));
+        try!(writeln!(f, "{} shift/reduce and {} reduce/reduce conflicts before precedence, {} and {} after",
+            self.sr_conflicts_before, self.rr_conflicts_before, self.sr_conflicts, self.rr_conflicts));
+        try!(writeln!(f, "{} actions removed by precedence", self.resolved_by_precedence));
+        write!(f, "{} table entries ({} unpacked), {} bytes of tables",
+            self.table_entries, self.unpacked_entries, self.table_bytes)
+    }
+}
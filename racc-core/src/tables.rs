@@ -0,0 +1,829 @@
+// Builds the packed parsing tables for a parser.  This was output.c in Berkeley YACC, minus
+// the parts that write C source code.  The tables built here are independent of the way that
+// they are emitted; the grammar! syntax extension turns them into static arrays, but they can
+// equally be used directly, at run time.
+//
+// The tables have the same layout, and the same names, as those generated by Berkeley YACC:
+//
+//      yydefred    default reduction for each state, or 0 (rule numbers are offset by 2)
+//      yysindex    base of the shift row of each state, in yytable/yycheck
+//      yyrindex    base of the reduce row of each state, in yytable/yycheck
+//      yygindex    base of the goto column of each non-terminal, in yytable/yycheck
+//      yydgoto     default goto for each non-terminal
+//      yytable     packed actions: target states (for shifts and gotos) or rules (for reduces)
+//      yycheck     packed check values; yycheck[base + token] == token if the entry is valid
+//      yylhs       the lhs of each rule (as a non-terminal value)
+//      yylen       the length of the rhs of each rule
+//      yyname      token names, indexed by token value
+//      yyrules     rule text, for debugging
+//      yyfinal     the final (accepting) state
+//
+// and two which are not in Berkeley YACC, which describe the grammar to tools at run time:
+//
+//      yyvarname   non-terminal names, indexed by the values in yylhs; yyvarname[0] is the goal
+//      yyhash      a hash of the grammar (its token and rule text), which identifies the grammar
+//
+// and one which describes the actions that lost conflicts, for GLR parsing:
+//
+//      yyconflicts (state, token, action) triples; action is the state to shift to, or the
+//                  negation of the rule to reduce by
+//
+// The kernel items of the states are kept separately, in StateItemTables, since only tools which
+// describe the parser need them.
+//
+// The tables are compressed exactly as in Berkeley YACC:
+//
+// * Each state whose only action is a single reduction gets that reduction as its default
+//   (yydefred), and its reduce row is left out of the packed table.  The most common goto of
+//   each non-terminal is its default (yydgoto), and only the other gotos are packed.
+// * A row (the shifts or reductions of a state, or the gotos on a non-terminal) which is
+//   identical to a row that has already been packed shares its place (see matching_vector).
+// * The remaining rows are overlaid in yytable, each displaced by its base (yysindex,
+//   yyrindex, or yygindex) so that its entries fall into unused slots; yycheck records which
+//   row each slot belongs to (see pack_vector).
+
+use std::cmp;
+
+use grammar::Grammar;
+use mkpar::{ActionCode, YaccParser};
+use lalr::GotoMap;
+use lr0::LR0Output;
+use conflicts::item_rule;
+use util::reverse_range;
+use error::{GenError,MAX_TABLE_INDEX};
+use ids::SymbolId;
+use export;
+
+const I16_MAX: i16 = 0x7fff;
+const I16_MIN: i16 = -0x8000;
+const I32_MAX: i32 = 0x7fff_ffff;
+
+/// The packed parsing tables of a parser.  See the Berkeley YACC documentation for the meaning
+/// of each table; the names are the same.  All rule numbers in these tables are offset by 2,
+/// so that the first user-defined rule (rule 3 in `Grammar`) is rule 1.  The tables which hold
+/// states, rules, or indices into `yytable` are `i32`; `yylhs` and `yylen`, which hold symbols and
+/// lengths, are `i16`, as the grammar is.
+#[deriving(Encodable,Decodable)]
+pub struct PackedTables {
+    pub yydefred: Vec<i32>,
+    pub yydgoto: Vec<i32>,
+    pub yysindex: Vec<i32>,
+    pub yyrindex: Vec<i32>,
+    pub yygindex: Vec<i32>,
+    pub yytable: Vec<i32>,
+    pub yycheck: Vec<i32>,
+    pub yylhs: Vec<i16>,
+    pub yylen: Vec<i16>,
+    pub yyname: Vec<String>,
+    pub yyrules: Vec<String>,
+    pub yyfinal: uint,
+    pub yyvarname: Vec<String>,
+    pub yyhash: u64,
+    pub yyconflicts: Vec<i32>,
+}
+
+/// Builds the packed parsing tables for a parser.  Fails if the tables would be too large to
+/// index with `i32`.
+pub fn make_tables(gram: &Grammar, gotos: &GotoMap, parser: &YaccParser) -> Result<PackedTables, GenError> {
+    let nstates = parser.nstates;
+
+    let yydefred: Vec<i32> = parser.default_reductions.iter().map(|&s| if s != 0 { s as i32 - 2 } else { 0 }).collect();
+
+    let mut act = token_actions(gram, parser);
+    let dgoto = goto_actions(gram, nstates, gotos, &mut act);
+    let (nentries, order) = sort_actions(&mut act);
+
+    let packed = try!(pack_table(parser.nstates, nentries, order.as_slice(), &act));
+    debug!("packed {} states into {} table entries (unpacked, the action and goto tables would have {} entries)",
+        nstates, packed.high + 1, nstates * gram.nsyms);
+
+    let yyname = try!(symbol_names_by_value(gram));
+    let yyrules: Vec<String> = range(2, gram.nrules).map(|rule| gram.rule_to_str(rule)).collect();
+    let yyhash = grammar_hash(yyname.as_slice(), yyrules.as_slice());
+
+    Ok(PackedTables {
+        yydefred: yydefred,
+        yydgoto: dgoto,
+        // was output_base
+        yysindex: packed.base.slice(0, nstates).to_vec(),
+        yyrindex: packed.base.slice(nstates, nstates * 2).to_vec(),
+        yygindex: packed.base.slice(nstates * 2, act.nvectors).to_vec(),
+        // was output_table and output_check
+        yytable: packed.table.slice(0, packed.high + 1).to_vec(),
+        yycheck: packed.check.slice(0, packed.high + 1).to_vec(),
+        yylhs: rule_lhs_values(gram),
+        yylen: range(2, gram.nrules).map(|r| gram.rrhs[r + 1] - gram.rrhs[r] - 1).collect(),
+        yyname: yyname,
+        yyrules: yyrules,
+        yyfinal: parser.final_state,
+        yyvarname: variable_names_by_value(gram),
+        yyhash: yyhash,
+        yyconflicts: conflict_actions(gram, parser)
+    })
+}
+
+/// The first bytes of the binary form of the tables (see `PackedTables::to_binary`).
+pub const BINARY_MAGIC: &'static [u8] = b"RACC";
+
+/// The version of the binary form of the tables.  It changes whenever the layout does.
+pub const BINARY_VERSION: u16 = 2;
+
+impl PackedTables {
+    /// Returns true if the parser reduces by `rule` (a grammar rule) only by default, that is,
+    /// in states whose only action is that reduction, so that it never reads a lookahead token
+    /// to decide on it.  Returns false if some reduce row has the rule.
+    pub fn reduces_by_default_only(&self, rule: uint) -> bool {
+        let entry = (rule - 2) as i32;
+        for &base in self.yyrindex.iter() {
+            if base == 0 {
+                continue;
+            }
+            for token in range(0, self.yyname.len()) {
+                let index = base as int + token as int;
+                if index >= 0 && (index as uint) < self.yycheck.len()
+                    && self.yycheck[index as uint] as uint == token && self.yytable[index as uint] == entry {
+                    return false;
+                }
+            }
+        }
+        true
+    }
+
+    /// Returns the tables as JSON, with one field for each table, named as above.  See `export`.
+    pub fn to_json(&self) -> String {
+        export::tables_to_json(self)
+    }
+
+    /// Writes the tables in a compact binary form, which `LoadedTables::from_binary` (in the
+    /// runtime) reads.  This avoids compiling large tables as Rust source, and allows tables to
+    /// be shared with programs in other languages.  The layout, with all integers little-endian:
+    ///
+    /// ```ignore
+    ///     "RACC"  version: u16  yyhash: u64  yyfinal: u32
+    ///     yydefred yydgoto yysindex yyrindex yygindex yytable yycheck
+    ///                             (each a u32 count, then that many i32s)
+    ///     yylhs yylen             (each a u32 count, then that many i16s)
+    ///     yyname yyrules yyvarname
+    ///                             (each a u32 count, then that many strings; each string is a
+    ///                              u32 length, then that many bytes of UTF-8)
+    ///     checksum: u64           (64-bit FNV-1a of all of the bytes before it)
+    /// ```
+    ///
+    /// `yyhash` identifies the grammar, so a loader can check that the tables are for the
+    /// grammar that it expects.  `yyconflicts` is not included.
+    pub fn to_binary(&self) -> Vec<u8> {
+        let mut out: Vec<u8> = Vec::new();
+        out.push_all(BINARY_MAGIC);
+        put_u16(&mut out, BINARY_VERSION);
+        put_u64(&mut out, self.yyhash);
+        put_u32(&mut out, self.yyfinal as u32);
+        for table in [&self.yydefred, &self.yydgoto, &self.yysindex, &self.yyrindex, &self.yygindex,
+                      &self.yytable, &self.yycheck].iter() {
+            put_u32(&mut out, table.len() as u32);
+            for &value in table.iter() {
+                put_u32(&mut out, value as u32);
+            }
+        }
+        for table in [&self.yylhs, &self.yylen].iter() {
+            put_u32(&mut out, table.len() as u32);
+            for &value in table.iter() {
+                put_u16(&mut out, value as u16);
+            }
+        }
+        for strings in [&self.yyname, &self.yyrules, &self.yyvarname].iter() {
+            put_u32(&mut out, strings.len() as u32);
+            for s in strings.iter() {
+                put_u32(&mut out, s.len() as u32);
+                out.push_all(s.as_bytes());
+            }
+        }
+        let checksum = fnv1a(out.as_slice());
+        put_u64(&mut out, checksum);
+        out
+    }
+}
+
+/// The value in `StateItemTables::yyrhs` or `yystos` of a non-terminal has this bit set; the
+/// rest is its value, as in `yylhs`.  A token is its token value.
+pub const RHS_NONTERMINAL: u32 = 0x8000_0000;
+
+/// The kernel items and accessing symbol of each state, and the symbols of each rule, so that a
+/// parser can describe
+/// its states at run time (to a debugger, for example).  These are kept apart from the
+/// `PackedTables` because the parser does not need them to parse.  Rules are numbered as in
+/// `yyrules`.
+#[deriving(Clone,Encodable,Decodable)]
+pub struct StateItemTables {
+    /// The symbol which is shifted to enter each state (see `RHS_NONTERMINAL`).  For state 0,
+    /// this is 0, the value of `$end`.
+    pub yystos: Vec<u32>,
+    /// For each state, the index in `yykernel` at which its items begin; then the length of
+    /// `yykernel`.
+    pub yykernelindex: Vec<u32>,
+    /// The kernel items, as (rule, dot) pairs, where dot is the number of symbols of the rule
+    /// which come before the position of the parser.
+    pub yykernel: Vec<u32>,
+    /// For each rule, the index in `yyrhs` at which its symbols begin; then the length of
+    /// `yyrhs`.
+    pub yyrhsindex: Vec<u32>,
+    /// The symbols of the right-hand sides of the rules; see `RHS_NONTERMINAL`.
+    pub yyrhs: Vec<u32>,
+}
+
+/// Builds the `StateItemTables` of an automaton.
+pub fn make_state_items(gram: &Grammar, lr0: &LR0Output) -> StateItemTables {
+    let symbol_value = |sym: uint| if gram.is_var(sym) { RHS_NONTERMINAL | gram.value[sym] as u32 } else { gram.value[sym] as u32 };
+    let yystos: Vec<u32> = lr0.states.iter().map(|state| symbol_value(state.accessing_symbol.index())).collect();
+
+    let mut yykernelindex: Vec<u32> = Vec::with_capacity(lr0.nstates() + 1);
+    let mut yykernel: Vec<u32> = Vec::new();
+    for state in lr0.states.iter() {
+        yykernelindex.push(yykernel.len() as u32);
+        for &item in state.items.iter() {
+            let rule = item_rule(gram, item).index();
+            yykernel.push((rule - 2) as u32);
+            yykernel.push((item.index() - gram.rrhs[rule] as uint) as u32);
+        }
+    }
+    yykernelindex.push(yykernel.len() as u32);
+
+    let mut yyrhsindex: Vec<u32> = Vec::with_capacity(gram.nrules - 1);
+    let mut yyrhs: Vec<u32> = Vec::new();
+    for rule in range(2, gram.nrules) {
+        yyrhsindex.push(yyrhs.len() as u32);
+        for &sym in gram.get_rhs_items(rule).iter() {
+            yyrhs.push(symbol_value(sym as uint));
+        }
+    }
+    yyrhsindex.push(yyrhs.len() as u32);
+
+    StateItemTables {
+        yystos: yystos,
+        yykernelindex: yykernelindex,
+        yykernel: yykernel,
+        yyrhsindex: yyrhsindex,
+        yyrhs: yyrhs,
+    }
+}
+
+fn put_u16(out: &mut Vec<u8>, value: u16) {
+    out.push(value as u8);
+    out.push((value >> 8) as u8);
+}
+
+fn put_u32(out: &mut Vec<u8>, value: u32) {
+    for i in range(0u, 4) {
+        out.push((value >> (i * 8)) as u8);
+    }
+}
+
+fn put_u64(out: &mut Vec<u8>, value: u64) {
+    for i in range(0u, 8) {
+        out.push((value >> (i * 8)) as u8);
+    }
+}
+
+/// Returns the 64-bit FNV-1a hash of `bytes`, as used for `yyhash` and the checksum of the
+/// binary form.
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for &b in bytes.iter() {
+        hash = hash ^ (b as u64);
+        hash = hash * 0x100000001b3;
+    }
+    hash
+}
+
+// Builds the YYCONFLICTS table.  Actions which were removed by precedence, by %resolve, or by
+// %prefer (suppressed == 2, 3, or 4) are not conflicts, and are not included.
+fn conflict_actions(gram: &Grammar, parser: &YaccParser) -> Vec<i32> {
+    let mut conflicts: Vec<i32> = Vec::new();
+    for state in range(0, parser.nstates) {
+        for p in parser.actions[state].iter() {
+            if p.suppressed == 1 {
+                conflicts.push(state as i32);
+                conflicts.push(gram.value[p.symbol as uint] as i32);
+                conflicts.push(match p.action_code {
+                    ActionCode::Shift => p.number,
+                    ActionCode::Reduce => -(p.number - 2)
+                });
+            }
+        }
+    }
+    conflicts
+}
+
+// Builds the YYVARNAME table.  Variable values are assigned by the builder: the goal symbol is 0,
+// and the rest are numbered from 1.  $accept (value -1) is not included.
+fn variable_names_by_value(gram: &Grammar) -> Vec<String> {
+    let mut names: Vec<String> = Vec::from_elem(gram.nvars - 1, String::new());
+    for v in range(gram.start_symbol + 1, gram.nsyms) {
+        names[gram.value[v] as uint] = gram.name[v].clone();
+    }
+    names
+}
+
+// Computes YYHASH, using 64-bit FNV-1a over the token names and the rule text.  This must not
+// change between releases, since programs may store it, to recognize tables that they have seen.
+fn grammar_hash(yyname: &[String], yyrules: &[String]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for s in yyname.iter().chain(yyrules.iter()) {
+        for &b in s.as_bytes().iter().chain([0u8].iter()) {
+            hash = hash ^ (b as u64);
+            hash = hash * 0x100000001b3;
+        }
+    }
+    hash
+}
+
+// Builds the YYLHS table.
+fn rule_lhs_values(gram: &Grammar) -> Vec<i16> {
+    let mut data: Vec<i16> = Vec::new();
+    data.push(gram.value[gram.start_symbol]);
+    for i in range(3, gram.nrules) {
+        data.push(gram.value[gram.rlhs[i] as uint]);
+    }
+    data
+}
+
+// Builds the YYNAME table.
+fn symbol_names_by_value(gram: &Grammar) -> Result<Vec<String>, GenError> {
+    // The values used at runtime are not symbol indices.  They are token values, which come from gram.value[token].value.
+    // This is ugly and inefficient.
+
+    let mut max_value: i16 = I16_MIN;
+    for i in range(0, gram.ntokens) {
+        if gram.value[i] < 0 || gram.value[i] == I16_MAX {
+            return Err(GenError::TokenValueOutOfRange(gram.name[i].clone(), gram.value[i]));
+        }
+        max_value = cmp::max(max_value, gram.value[i]);
+    }
+
+    let length = (max_value + 1) as uint;
+
+    let mut toknames: Vec<String> = Vec::from_elem(length, String::new());
+
+    // Now put the names into proper places.
+    for i in range(0, gram.ntokens) {
+        toknames[gram.value[i] as uint] = gram.pname[i].clone();
+    }
+
+    Ok(toknames)
+}
+
+struct ActionsTable {
+    nvectors: uint,
+    tally: Vec<i32>,
+    width: Vec<i32>,
+    froms: Vec<Vec<i32>>,
+    tos: Vec<Vec<i32>>
+}
+
+fn token_actions(gram: &Grammar, parser: &YaccParser) -> ActionsTable {
+    debug!("token_actions()");
+
+    let nstates = parser.nstates;
+    let nvectors = 2 * nstates + gram.nvars;
+    let mut tally: Vec<i32> = Vec::from_elem(nvectors, 0);
+    let mut width: Vec<i32> = Vec::from_elem(nvectors, 0);
+    let mut froms: Vec<Vec<i32>> = Vec::from_elem(nvectors, Vec::new());
+    let mut tos: Vec<Vec<i32>> = Vec::from_elem(nvectors, Vec::new());
+    let mut actionrow: Vec<i32> = Vec::from_elem(2 * gram.ntokens, 0);
+
+    for i in range(0, nstates) {
+        let actions = &parser.actions[i];
+        if actions.len() != 0 {
+            debug!("    state={}", i);
+            for ii in actionrow.iter_mut() {
+                *ii = 0;
+            }
+
+            let mut shiftcount: uint = 0;
+            let mut reducecount: uint = 0;
+            for p in actions.iter() {
+                if p.suppressed == 0 {
+                    if p.action_code == ActionCode::Shift {
+                        shiftcount += 1;
+                        actionrow[p.symbol as uint] = p.number;
+                        // debug!("        shift {}", p.number);
+                    }
+                    else if p.action_code == ActionCode::Reduce && p.number != parser.default_reductions[i] as i32 {
+                        reducecount += 1;
+                        actionrow[(p.symbol as uint) + gram.ntokens] = p.number;
+                        // debug!("        reduce {}", p.number);
+                    }
+                }
+            }
+
+            debug!("        shiftcount={} reducecount={}", shiftcount, reducecount);
+
+            tally[i] = shiftcount as i32;
+            tally[nstates + i] = reducecount as i32;
+            width[i] = 0;
+            width[nstates + i] = 0;
+
+            if shiftcount > 0 {
+                let mut r: Vec<i32> = Vec::with_capacity(shiftcount);
+                let mut s: Vec<i32> = Vec::with_capacity(shiftcount);
+                let mut min = I32_MAX;
+                let mut max = 0;
+                for j in range(0, gram.ntokens) {
+                    if actionrow[j] != 0 {
+                        let value = gram.value[j] as i32;
+                        min = cmp::min(min, value);
+                        max = cmp::max(max, value);
+                        r.push(value);
+                        s.push(actionrow[j]);
+                        debug!("        shift for token {} {}, pushing r={} s={}", j, gram.name[j], gram.value[j], actionrow[j]);
+                    }
+                }
+                froms[i] = r;
+                tos[i] = s;
+                width[i] = max - min + 1;
+            }
+
+            if reducecount > 0 {
+                let mut r: Vec<i32> = Vec::with_capacity(reducecount);
+                let mut s: Vec<i32> = Vec::with_capacity(reducecount);
+                let mut min = I32_MAX;
+                let mut max = 0;
+                for j in range(0, gram.ntokens) {
+                    if actionrow[gram.ntokens + j] != 0 {
+                        let value = gram.value[j] as i32;
+                        min = cmp::min(min, value);
+                        max = cmp::max(max, value);
+                        r.push(value);
+                        s.push(actionrow[gram.ntokens + j] - 2);
+                        debug!("        reduce for token {} {}, pushing r={} s={}", j, gram.name[j], gram.value[j], actionrow[gram.ntokens + j] - 2);
+                    }
+                }
+                froms[nstates + i] = r;
+                tos[nstates + i] = s;
+                width[nstates + i] = max - min + 1;
+            }
+        }
+        else {
+            debug!("    state={} has no actions", i);
+        }
+    }
+
+    ActionsTable {
+        nvectors: nvectors,
+        tally: tally,
+        width: width,
+        froms: froms,
+        tos: tos
+    }
+}
+
+fn default_goto(
+    gram: &Grammar,
+    gotos: &GotoMap,
+    symbol: uint,
+    nstates: uint,
+    state_count: &mut Vec<i32>) -> uint
+{
+    if gotos.gotos_on(gram, SymbolId(symbol)).next().is_none() {
+        return 0;
+    }
+
+    for i in range(0, nstates) {
+        state_count[i] = 0;
+    }
+
+    for i in gotos.gotos_on(gram, SymbolId(symbol)) {
+        state_count[gotos.to_state[i].index()] += 1;
+    }
+
+    let mut max = 0;
+    let mut default_state = 0;
+    for i in range(0, nstates) {
+        if state_count[i] > max {
+            max = state_count[i];
+            default_state = i;
+        }
+    }
+
+    debug!("default_goto({}) = {}", symbol, default_state);
+
+    default_state
+}
+
+fn save_column(
+    gram: &Grammar, 
+    nstates: uint,
+    gotos: &GotoMap,
+    symbol: uint, 
+    default_state: uint,
+    act: &mut ActionsTable)
+{
+    debug!("save_column: symbol={} default_state={}", symbol, default_state);
+
+    let mut count: uint = 0;
+    for i in gotos.gotos_on(gram, SymbolId(symbol)) {
+        if gotos.to_state[i].index() != default_state {
+            debug!("    to_state[{}]={}", i, gotos.to_state[i]);
+            count += 1;
+        }
+    }
+    if count == 0 {
+        debug!("    none");
+        return;
+    }
+
+
+    let mut spf: Vec<i32> = Vec::with_capacity(count);
+    let mut spt: Vec<i32> = Vec::with_capacity(count);
+    for i in gotos.gotos_on(gram, SymbolId(symbol)) {
+        if gotos.to_state[i].index() != default_state {
+            spf.push(gotos.from_state[i].to_i32());
+            spt.push(gotos.to_state[i].to_i32());
+        }
+    }
+
+    let symno = (gram.value[symbol] as uint) + 2 * nstates;
+    let spf_width = spf[spf.len() - 1] - spf[0] + 1;
+    act.froms[symno] = spf;
+    act.tos[symno] = spt;
+    act.tally[symno] = count as i32;
+    act.width[symno] = spf_width;
+    debug!("    tally[{}]={} width[{}]={}", symno, act.tally[symno], symno, act.width[symno]);
+}
+
+// build the "dgoto" table
+fn goto_actions(gram: &Grammar, nstates: uint, gotos: &GotoMap, act: &mut ActionsTable) -> Vec<i32> {
+    debug!("goto_actions");
+
+    let mut state_count: Vec<i32> = Vec::from_elem(nstates, 0);         // temporary data, used in default_goto()
+    let mut dgoto_table: Vec<i32> = Vec::with_capacity(gram.nvars);    // the table that we are building
+
+    let k = default_goto(gram, gotos, gram.start_symbol + 1, nstates, &mut state_count);
+    dgoto_table.push(k as i32);
+    save_column(gram, nstates, gotos, gram.start_symbol + 1, k, act);
+
+    for i in range(gram.start_symbol + 2, gram.nsyms) {
+        let k = default_goto(gram, gotos, i, nstates, &mut state_count);
+        dgoto_table.push(k as i32);
+        save_column(gram, nstates, gotos, i, k, act);
+    }
+
+    dgoto_table
+}
+
+fn sort_actions(act: &ActionsTable) -> (uint, Vec<uint>) {
+    debug!("sort_actions() nvectors={}", act.nvectors);
+
+    let mut order: Vec<uint> = Vec::from_elem(act.nvectors, 0);
+    let mut nentries: int = 0;
+
+    for i in range(0, act.nvectors) {
+        debug!("tally[{}]={}", i, act.tally[i]);
+        if act.tally[i] > 0 {
+            let t = act.tally[i];
+            let w = act.width[i];
+            let mut j: int = nentries - 1;
+            debug!("    t={} w={} j={}", t, w, j);
+
+            while j >= 0 && (act.width[order[j as uint]] < w) {
+                j -= 1;
+                debug!("    j-- to {}, because width < w", j);
+            }
+
+            while j >= 0 && (act.width[order[j as uint]] == w) && (act.tally[order[j as uint]] < t) {
+                j -= 1;
+                debug!("    j-- to {}, because tally < t", j);
+            }
+
+            let mut k = nentries - 1;
+            while k > j {
+                debug!("        order[{}] = order[{}] = {} (shifting)", (k + 1) as uint, k as uint, order[k as uint]);
+                order[(k + 1) as uint] = order[k as uint];
+                k -= 1;
+            }
+
+            debug!("        order[{}] = {}", (j + 1) as uint, i);
+            order[(j + 1) as uint] = i;
+            nentries += 1;
+        }
+    }
+
+    debug!("order:");
+    for i in range(0, order.len()) {
+        debug!("    {}", order[i]);
+    }
+    debug!("nentries={}", nentries);
+
+    (nentries as uint, order)
+}
+
+// The function matching_vector determines if the vector specified by
+// the input parameter matches a previously considered vector. The
+// test at the start of the function checks if the vector represents
+// a row of shifts over terminal symbols or a row of reductions, or a
+// column of shifts over a nonterminal symbol.  Berkeley Yacc does not
+// check if a column of shifts over a nonterminal symbols matches a
+// previously considered vector.  Because of the nature of LR parsing
+// tables, no two columns can match.  Therefore, the only possible
+// match would be between a row and a column.  Such matches are
+// unlikely.  Therefore, to save time, no attempt is made to see if a
+// column matches a previously considered vector.
+//
+// Matching_vector is poorly designed.  The test could easily be made
+// faster.  Also, it depends on the vectors being in a specific
+// order.
+fn matching_vector(pack: &PackState, vector: uint) -> Option<uint>
+{
+    let i = pack.order[vector];
+    if i >= 2 * pack.nstates {
+        debug!("    matching_vector: vector={} no match", vector);
+        return None;
+    }
+
+    let t = pack.act.tally[i];
+    let w = pack.act.width[i];
+
+    let act = pack.act;
+
+    for prev in reverse_range(vector, 0) {
+        let j = pack.order[prev];
+        if act.width[j] != w || act.tally[j] != t {
+            return None;
+        }
+
+        let mut is_match = true;
+        for k in range(0, t as uint) {
+            if act.tos[j][k] != act.tos[i][k] || act.froms[j][k] != act.froms[i][k] {
+                is_match = false;
+                break;
+            }
+        }
+        if is_match {
+            debug!("    matching_vector: vector={} matches at {}", vector, j);
+            return Some(j);
+        }
+    }
+
+    debug!("    matching_vector: vector={} - no match", vector);
+    return None;
+}
+
+fn pack_vector(pack: &mut PackState, vector: uint) -> Result<int, GenError> {
+    // debug!("pack_vector: vector={} lowzero={}", vector, pack.lowzero);
+    let act = pack.act;
+    let i = pack.order[vector];
+    let t = act.tally[i];
+    assert!(t != 0);
+
+    let from = &act.froms[i];
+    let to = &act.tos[i];
+
+    // debug!("from[0]={}", from[0]);
+
+    let mut j: int = (pack.lowzero as int) - (from[0] as int);
+    // debug!("j={}", j);
+    for k in range(1, t as uint) {
+        if (pack.lowzero as int) - (from[k] as int) > j {
+            j = (pack.lowzero as int) - (from[k] as int);
+            // debug!("j={}", j);
+        }
+    }
+
+    loop {
+        // debug!("    loop: j={}", j);
+        if j == 0 {
+            j = 1;
+            continue;
+        }
+
+        let mut ok = true;
+        for k in range(0, t as uint) {
+            let loc = (j + (from[k] as int)) as uint;
+
+            // The tables are indexed by i32, so they cannot grow without limit.
+            if loc >= MAX_TABLE_INDEX {
+                return Err(GenError::TableTooLarge("action", loc + 1));
+            }
+
+            // make sure we can read/write table[loc] and table[check]
+            if loc > pack.table.len() {
+                assert!(pack.table.len() == pack.check.len());
+                let grow = loc + 1 - pack.table.len();
+                debug!("        growing table/check by {}", grow);
+                pack.table.grow(grow, 0);
+                pack.check.grow(grow, -1);
+            }
+
+            if pack.check[loc] != -1 {
+                ok = false;
+                break;
+            }
+        }
+        if !ok {
+            j += 1;
+            continue;
+        }
+        for k in range(0, vector) {
+            if pack.pos[k] as int == j {
+                ok = false;
+                break;
+            }
+        }
+        if !ok {
+            j += 1;
+            continue;
+        }
+
+        for k in range(0, t as uint) {
+            let loc = (j + (from[k] as int)) as uint;
+            pack.table[loc] = to[k];
+            pack.check[loc] = from[k];
+            if loc > pack.high {
+                pack.high = loc;
+            }
+        }
+
+        while pack.check[pack.lowzero] != -1 {
+            pack.lowzero += 1;
+        }
+
+        return Ok(j);
+    }
+}
+
+struct PackState<'a> {
+    base: Vec<i32>,
+    pos: Vec<i32>,
+    table: Vec<i32>,        // table and check always have same len
+    check: Vec<i32>,        // table is 0-filled, check is -1-filled
+    lowzero: uint,
+    high: uint,
+
+    // read-only references to stuff
+    order: &'a [uint],
+    nstates: uint,
+    act: &'a ActionsTable
+}
+
+fn pack_table<'a>(nstates: uint, nentries: uint, order: &'a [uint], act: &'a ActionsTable) -> Result<PackState<'a>, GenError> {
+    debug!("pack_table: nentries={}", nentries);
+
+    let initial_maxtable = 1000;
+
+    let mut pack = PackState {
+        base: Vec::from_elem(act.nvectors, 0),
+        pos: Vec::from_elem(nentries, 0),
+        table: Vec::from_elem(initial_maxtable, 0),
+        check: Vec::from_elem(initial_maxtable, -1),
+        lowzero: 0,
+        high: 0,
+        order: order,
+        nstates: nstates,
+        act: act
+    };
+
+    for i in range(0, nentries) {
+        // debug!("i={}", i);
+        let place: int = match matching_vector(&mut pack, i) {
+            Some(state) => pack.base[state] as int,
+            None => try!(pack_vector(&mut pack, i))
+        };
+
+        // debug!("    place={}", place);
+        pack.pos[i] = place as i32;
+        pack.base[order[i]] = place as i32;
+    }
+
+    Ok(pack)
+}
+
+#[cfg(test)]
+mod tests {
+    use grammar::Grammar;
+    use yacc::read_yacc;
+    use analyze;
+    use super::make_tables;
+
+    fn grammar(text: &str) -> Grammar {
+        match read_yacc(text) {
+            Ok(y) => y.grammar,
+            Err(_) => panic!("cannot read the grammar")
+        }
+    }
+
+    #[test]
+    fn identical_rows_share_a_base() {
+        // The states after A and after B both shift C, to the same state, so their shift rows are
+        // identical, and are packed once.
+        let gram = grammar("%token A B C\n%%\ns : A x | B x ;\nx : C ;\n");
+        let analysis = analyze(&gram).unwrap();
+        let tables = make_tables(&gram, &analysis.lalr.gotos, &analysis.parser).ok().unwrap();
+        let after = |name: &str| -> uint {
+            let symbol = range(0, gram.ntokens).find(|&t| gram.name[t].as_slice() == name).unwrap();
+            range(0, analysis.lr0.nstates()).find(|&s| analysis.lr0.states[s].accessing_symbol.index() == symbol).unwrap()
+        };
+        let (a, b) = (after("A"), after("B"));
+        assert!(a != b);
+        assert!(tables.yysindex[a] != 0);
+        assert_eq!(tables.yysindex[a], tables.yysindex[b]);
+    }
+}
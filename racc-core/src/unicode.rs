@@ -0,0 +1,67 @@
+// The Unicode properties which lexer patterns can use, as \p{Name} (see lexgen.rs).
+//
+// The character data comes from the standard library, whose tables follow the version of
+// Unicode that the compiler was built with.  A property is turned into the list of ranges of
+// the code points which have it, by testing every code point once; the DFA then holds only
+// ranges, so neither the generator's output nor the run-time scanner needs any Unicode tables of
+// its own.  (XID_Continue, the largest, is about 700 ranges.)
+
+use std::char;
+
+// The properties, by name and by alias, and the test for each.  These are only the properties
+// that the standard library defines exactly; there is no general category L, for example,
+// because Alphabetic is not quite the same thing.
+const PROPERTIES: &'static [(&'static str, &'static str, fn(char) -> bool)] = &[
+    ("XID_Start", "XIDS", is_xid_start),
+    ("XID_Continue", "XIDC", is_xid_continue),
+    ("Alphabetic", "Alpha", is_alphabetic),
+    ("Lowercase", "Lower", is_lowercase),
+    ("Uppercase", "Upper", is_uppercase),
+    ("White_Space", "space", is_whitespace),
+    ("N", "Number", is_numeric),
+    ("Cc", "Control", is_control),
+    ("ASCII", "ASCII", is_ascii),
+];
+
+fn is_xid_start(c: char) -> bool { c.is_xid_start() }
+fn is_xid_continue(c: char) -> bool { c.is_xid_continue() }
+fn is_alphabetic(c: char) -> bool { c.is_alphabetic() }
+fn is_lowercase(c: char) -> bool { c.is_lowercase() }
+fn is_uppercase(c: char) -> bool { c.is_uppercase() }
+fn is_whitespace(c: char) -> bool { c.is_whitespace() }
+fn is_numeric(c: char) -> bool { c.is_numeric() }
+fn is_control(c: char) -> bool { c.is_control() }
+fn is_ascii(c: char) -> bool { (c as u32) < 0x80 }
+
+/// Returns the names of the properties, for error messages.
+pub fn property_names() -> Vec<&'static str> {
+    PROPERTIES.iter().map(|&(name, _, _)| name).collect()
+}
+
+/// Returns the ranges of code points which have the property `name`, sorted, or `None` if there
+/// is no such property.
+pub fn property_ranges(name: &str) -> Option<Vec<(u32, u32)>> {
+    let test = match PROPERTIES.iter().find(|&&(n, alias, _)| n == name || alias == name) {
+        Some(&(_, _, test)) => test,
+        None => return None
+    };
+    let mut ranges: Vec<(u32, u32)> = Vec::new();
+    for cp in range(0, char::MAX as u32 + 1) {
+        let has = match char::from_u32(cp) {
+            Some(c) => test(c),
+            None => false       // a surrogate
+        };
+        if !has {
+            continue;
+        }
+        match ranges.last_mut() {
+            Some(&(_, ref mut hi)) if *hi + 1 == cp => {
+                *hi = cp;
+                continue;
+            }
+            _ => {}
+        }
+        ranges.push((cp, cp));
+    }
+    Some(ranges)
+}
@@ -5,6 +5,7 @@ pub const BITS_PER_WORD: uint = 32;
 // An M x N matrix of bits
 // The representation is in row-major form.
 // The representation is exposed.
+#[deriving(Clone)]
 pub struct Bitmat
 {
 	pub data: Vec<u32>,			// contains all bits in matrix, in row-major form, with padding at end of row
@@ -1,7 +1,7 @@
 use util::BITS_PER_WORD;
 use util::Bitmat;
 
-fn transitive_closure(r: &mut Bitmat) {
+pub fn transitive_closure(r: &mut Bitmat) {
     let relend = r.rows * r.rowsize;
 
     let mut cword: uint = 0;
@@ -0,0 +1,501 @@
+// Reads grammars written for YACC or Bison (.y files), so that existing grammars can be analyzed
+// and ported without rewriting them by hand.
+//
+// Only the parts of a .y file that describe the grammar are understood:
+//
+//      %token NAME ...                 (a <tag> is allowed, and ignored; so are token numbers)
+//      %left, %right, %nonassoc        (names and character literals such as '+')
+//      %start NAME
+//      %type, %union, %{ ... %}        (ignored)
+//      %%
+//      name : symbols { action } | ... ;
+//      %%
+//      epilogue                        (ignored)
+//
+// Rules may use %prec and %empty.  Character literals such as '+' in rules are tokens, named
+// with their quotes.  Actions are not translated; their text is kept, so that a tool can show
+// it or convert it.  Actions in the middle of a rule are not supported.  Other directives are
+// reported as errors, rather than silently changing the meaning of the grammar.
+//
+// to_yacc_string goes the other way, writing a Grammar as a .y file.
+
+use std::fmt;
+
+use builder::{GrammarBuilder, GrammarError, Assoc, PREDEFINED_RULES};
+use grammar::{Grammar, TOKEN, LEFT, RIGHT, NONASSOC};
+
+/// A grammar read from a .y file.
+pub struct YaccGrammar {
+    pub grammar: Grammar,
+    /// The text of the action of each rule, without the braces, indexed by rule number.  The
+    /// predefined rules, and rules without actions, have `None`.
+    pub actions: Vec<Option<String>>,
+}
+
+/// An error found while reading a .y file.
+#[deriving(Clone,PartialEq)]
+pub enum YaccError {
+    /// The file could not be parsed.  Carries the line number (starting at 1) and a message.
+    Syntax(uint, String),
+    /// The file was parsed, but the grammar that it describes is not valid.
+    Grammar(GrammarError),
+}
+
+impl fmt::Show for YaccError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            YaccError::Syntax(line, ref message) => write!(f, "line {}: {}", line, message),
+            YaccError::Grammar(ref e) => write!(f, "{}", e),
+        }
+    }
+}
+
+#[deriving(Clone,PartialEq,Show)]
+enum Tok {
+    Ident(String),
+    Char(String),           // a character literal, with its quotes
+    Number,
+    Tag,                    // <type>
+    Directive(String),      // %name, without the %
+    Mark,                   // %%
+    Colon,
+    Semi,
+    Bar,
+    Comma,
+    Action(String),         // { ... }, without the braces
+    Eof,
+}
+
+struct Lexer {
+    chars: Vec<char>,
+    pos: uint,
+    line: uint,
+}
+
+impl Lexer {
+    fn new(source: &str) -> Lexer {
+        Lexer { chars: source.chars().collect(), pos: 0, line: 1 }
+    }
+
+    fn peek(&self, offset: uint) -> Option<char> {
+        if self.pos + offset < self.chars.len() { Some(self.chars[self.pos + offset]) } else { None }
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek(0);
+        if let Some(c) = c {
+            self.pos += 1;
+            if c == '\n' {
+                self.line += 1;
+            }
+        }
+        c
+    }
+
+    fn error(&self, message: &str) -> YaccError {
+        YaccError::Syntax(self.line, message.to_string())
+    }
+
+    // Skips white space and comments.
+    fn skip_space(&mut self) -> Result<(), YaccError> {
+        loop {
+            match (self.peek(0), self.peek(1)) {
+                (Some(c), _) if c.is_whitespace() => { self.bump(); }
+                (Some('/'), Some('*')) => {
+                    self.bump();
+                    self.bump();
+                    loop {
+                        match (self.peek(0), self.peek(1)) {
+                            (Some('*'), Some('/')) => { self.bump(); self.bump(); break; }
+                            (Some(_), _) => { self.bump(); }
+                            (None, _) => return Err(self.error("unterminated comment"))
+                        }
+                    }
+                }
+                (Some('/'), Some('/')) => {
+                    loop {
+                        match self.bump() {
+                            Some('\n') | None => break,
+                            _ => {}
+                        }
+                    }
+                }
+                _ => return Ok(())
+            }
+        }
+    }
+
+    // Skips a quoted string or character literal, whose opening quote is next.
+    fn skip_quoted(&mut self) -> Result<(), YaccError> {
+        let quote = self.bump().unwrap();
+        loop {
+            match self.bump() {
+                Some('\\') => { self.bump(); }
+                Some(c) if c == quote => return Ok(()),
+                Some('\n') | None => return Err(self.error("unterminated quoted literal")),
+                _ => {}
+            }
+        }
+    }
+
+    fn next(&mut self) -> Result<Tok, YaccError> {
+        try!(self.skip_space());
+        let c = match self.peek(0) {
+            Some(c) => c,
+            None => return Ok(Tok::Eof)
+        };
+        let start = self.pos;
+        match c {
+            ':' => { self.bump(); Ok(Tok::Colon) }
+            ';' => { self.bump(); Ok(Tok::Semi) }
+            '|' => { self.bump(); Ok(Tok::Bar) }
+            ',' => { self.bump(); Ok(Tok::Comma) }
+            '\'' => {
+                try!(self.skip_quoted());
+                Ok(Tok::Char(self.text(start, self.pos)))
+            }
+            '<' => {
+                loop {
+                    match self.bump() {
+                        Some('>') => return Ok(Tok::Tag),
+                        Some('\n') | None => return Err(self.error("unterminated <tag>")),
+                        _ => {}
+                    }
+                }
+            }
+            '{' => {
+                self.bump();
+                let mut depth = 1u;
+                loop {
+                    try!(self.skip_space());
+                    match self.peek(0) {
+                        Some('{') => { depth += 1; self.bump(); }
+                        Some('}') => {
+                            depth -= 1;
+                            if depth == 0 {
+                                let text = self.text(start + 1, self.pos);
+                                self.bump();
+                                return Ok(Tok::Action(text));
+                            }
+                            self.bump();
+                        }
+                        Some('"') | Some('\'') => try!(self.skip_quoted()),
+                        Some(_) => { self.bump(); }
+                        None => return Err(self.error("unterminated action"))
+                    }
+                }
+            }
+            '%' => {
+                self.bump();
+                match self.peek(0) {
+                    Some('%') => { self.bump(); Ok(Tok::Mark) }
+                    Some('{') => {
+                        // Prologue code is copied by YACC to its output; here it is ignored.
+                        loop {
+                            match (self.bump(), self.peek(0)) {
+                                (Some('%'), Some('}')) => { self.bump(); break; }
+                                (Some(_), _) => {}
+                                (None, _) => return Err(self.error("unterminated %{ ... %}"))
+                            }
+                        }
+                        self.next()
+                    }
+                    _ => {
+                        let name_start = self.pos;
+                        loop {
+                            match self.peek(0) {
+                                Some(c) if c.is_alphanumeric() || c == '_' || c == '-' => { self.bump(); }
+                                _ => break
+                            }
+                        }
+                        Ok(Tok::Directive(self.text(name_start, self.pos)))
+                    }
+                }
+            }
+            _ if c.is_digit(10) => {
+                loop {
+                    match self.peek(0) {
+                        Some(c) if c.is_digit(10) => { self.bump(); }
+                        _ => break
+                    }
+                }
+                Ok(Tok::Number)
+            }
+            _ if c.is_alphabetic() || c == '_' || c == '.' => {
+                loop {
+                    match self.peek(0) {
+                        Some(c) if c.is_alphanumeric() || c == '_' || c == '.' => { self.bump(); }
+                        _ => break
+                    }
+                }
+                Ok(Tok::Ident(self.text(start, self.pos)))
+            }
+            _ => Err(self.error(format!("unexpected character '{}'", c).as_slice()))
+        }
+    }
+
+    fn text(&self, start: uint, end: uint) -> String {
+        self.chars.slice(start, end).iter().map(|&c| c).collect()
+    }
+}
+
+/// Reads a grammar from the text of a .y file, and builds it.
+pub fn read_yacc(source: &str) -> Result<YaccGrammar, Vec<YaccError>> {
+    let mut lexer = Lexer::new(source);
+    let mut builder = GrammarBuilder::new();
+    let mut actions: Vec<Option<String>> = Vec::from_fn(PREDEFINED_RULES, |_| None);
+
+    let mut declared: Vec<String> = Vec::new();
+
+    match read_declarations(&mut lexer, &mut builder, &mut declared) {
+        Ok(()) => {}
+        Err(e) => return Err(vec![e])
+    }
+    match read_rules(&mut lexer, &mut builder, &mut actions, &mut declared) {
+        Ok(()) => {}
+        Err(e) => return Err(vec![e])
+    }
+
+    match builder.build() {
+        Ok(grammar) => Ok(YaccGrammar { grammar: grammar, actions: actions }),
+        Err(errors) => Err(errors.into_iter().map(|e| YaccError::Grammar(e)).collect())
+    }
+}
+
+// Reads the declarations, up to and including the first %%.  Character literals which are
+// declared as tokens are added to `declared`.
+fn read_declarations(lexer: &mut Lexer, builder: &mut GrammarBuilder, declared: &mut Vec<String>) -> Result<(), YaccError> {
+    let mut tok = try!(lexer.next());
+    loop {
+        let name = match tok {
+            Tok::Mark => return Ok(()),
+            Tok::Directive(ref name) => name.clone(),
+            Tok::Eof => return Err(lexer.error("expected %% before the rules")),
+            _ => return Err(lexer.error(format!("unexpected {} in the declarations", tok).as_slice()))
+        };
+        let assoc = match name.as_slice() {
+            "token" => None,
+            "left" => Some(Assoc::Left),
+            "right" => Some(Assoc::Right),
+            "nonassoc" => Some(Assoc::NonAssoc),
+            "start" => {
+                match try!(lexer.next()) {
+                    Tok::Ident(name) => { builder.start(name.as_slice()); }
+                    _ => return Err(lexer.error("expected a symbol after %start"))
+                }
+                tok = try!(lexer.next());
+                continue;
+            }
+            "type" | "union" => {
+                // Value types are not part of the grammar.  Skip the declaration.
+                loop {
+                    tok = try!(lexer.next());
+                    match tok {
+                        Tok::Directive(_) | Tok::Mark | Tok::Eof => break,
+                        _ => {}
+                    }
+                }
+                continue;
+            }
+            _ => return Err(lexer.error(format!("%{} is not supported", name).as_slice()))
+        };
+
+        // Read the names of the tokens, skipping tags and token numbers.
+        let mut names: Vec<String> = Vec::new();
+        loop {
+            tok = try!(lexer.next());
+            match tok {
+                Tok::Ident(ref name) => names.push(name.clone()),
+                Tok::Char(ref name) => {
+                    names.push(name.clone());
+                    declared.push(name.clone());
+                }
+                Tok::Tag | Tok::Number | Tok::Comma => {}
+                _ => break
+            }
+        }
+        let names: Vec<&str> = names.iter().map(|n| n.as_slice()).collect();
+        match assoc {
+            Some(assoc) => { builder.precedence(assoc, names.as_slice()); }
+            None => {
+                for name in names.iter() {
+                    builder.token(*name);
+                }
+            }
+        }
+    }
+}
+
+// Reads the rules, up to the second %% or the end of the file.  Character literals which are
+// used in rules are declared as tokens, unless they are already in `declared`.
+fn read_rules(lexer: &mut Lexer, builder: &mut GrammarBuilder, actions: &mut Vec<Option<String>>,
+              declared: &mut Vec<String>) -> Result<(), YaccError> {
+    loop {
+        let lhs = match try!(lexer.next()) {
+            Tok::Mark | Tok::Eof => return Ok(()),
+            Tok::Ident(name) => name,
+            _ => return Err(lexer.error("expected the name of a rule"))
+        };
+        if try!(lexer.next()) != Tok::Colon {
+            return Err(lexer.error(format!("expected ':' after '{}'", lhs).as_slice()));
+        }
+
+        // Read the alternatives.
+        loop {
+            let mut rhs: Vec<String> = Vec::new();
+            let mut prec: Option<String> = None;
+            let mut action: Option<String> = None;
+            let mut end = Tok::Eof;
+            loop {
+                let tok = try!(lexer.next());
+                if action.is_some() {
+                    match tok {
+                        Tok::Ident(_) | Tok::Char(_) | Tok::Action(_) =>
+                            return Err(lexer.error("actions in the middle of a rule are not supported")),
+                        _ => {}
+                    }
+                }
+                match tok {
+                    Tok::Ident(name) => rhs.push(name),
+                    Tok::Char(name) => {
+                        if !declared.contains(&name) {
+                            builder.token(name.as_slice());
+                            declared.push(name.clone());
+                        }
+                        rhs.push(name);
+                    }
+                    Tok::Action(text) => action = Some(text),
+                    Tok::Directive(ref name) if name.as_slice() == "prec" => {
+                        match try!(lexer.next()) {
+                            Tok::Ident(name) | Tok::Char(name) => prec = Some(name),
+                            _ => return Err(lexer.error("expected a token after %prec"))
+                        }
+                    }
+                    Tok::Directive(ref name) if name.as_slice() == "empty" => {}
+                    Tok::Bar => { end = Tok::Bar; break; }
+                    Tok::Semi => { end = Tok::Semi; break; }
+                    Tok::Mark | Tok::Eof => break,
+                    _ => return Err(lexer.error("unexpected symbol in a rule"))
+                }
+            }
+
+            let names: Vec<&str> = rhs.iter().map(|n| n.as_slice()).collect();
+            builder.rule(lhs.as_slice(), names.as_slice());
+            if let Some(prec) = prec {
+                builder.prec(prec.as_slice());
+            }
+            actions.push(action);
+
+            match end {
+                Tok::Bar => continue,
+                Tok::Semi => break,
+                _ => return Ok(())
+            }
+        }
+    }
+}
+
+/// Writes a grammar as the text of a .y file, which Bison and YACC can read.  The file has the
+/// tokens, the precedence declarations, and the rules, but no actions.  Names which are not
+/// valid YACC identifiers (such as those of the non-terminals generated for EBNF operators and
+/// parameterized rules) have their other characters replaced with `_`.
+pub fn to_yacc_string(gram: &Grammar) -> String {
+    let mut out = String::new();
+
+    // Tokens 0 and 1 are $end and error, which YACC defines itself.
+    let mut plain: Vec<String> = Vec::new();
+    for t in range(2, gram.ntokens) {
+        if gram.prec[t] == 0 && !is_char_literal(gram.name[t].as_slice()) {
+            plain.push(yacc_name(gram.name[t].as_slice()));
+        }
+    }
+    if plain.len() != 0 {
+        out.push_str(format!("%token {}\n", plain.connect(" ")).as_slice());
+    }
+
+    // Precedence levels, lowest first.
+    let max_prec = range(0, gram.ntokens).map(|t| gram.prec[t]).max().unwrap_or(0);
+    for level in range(1, max_prec + 1) {
+        let tokens: Vec<uint> = range(2, gram.ntokens).filter(|&t| gram.prec[t] == level).collect();
+        if tokens.len() == 0 {
+            continue;
+        }
+        let directive = match gram.assoc[tokens[0]] {
+            LEFT => "%left",
+            RIGHT => "%right",
+            NONASSOC => "%nonassoc",
+            _ => "%token"
+        };
+        let names: Vec<String> = tokens.iter().map(|&t| yacc_name(gram.name[t].as_slice())).collect();
+        out.push_str(format!("{} {}\n", directive, names.connect(" ")).as_slice());
+    }
+
+    // Rule 2 is "$accept : goal $end".
+    let goal = gram.ritem[gram.rrhs[2] as uint] as uint;
+    out.push_str(format!("%start {}\n%%\n", yacc_name(gram.name[goal].as_slice())).as_slice());
+
+    for r in range(PREDEFINED_RULES, gram.nrules) {
+        let lhs = gram.rlhs[r] as uint;
+        let first = r == PREDEFINED_RULES || gram.rlhs[r - 1] as uint != lhs;
+        if first {
+            out.push_str(format!("\n{}\n    :", yacc_name(gram.name[lhs].as_slice())).as_slice());
+        }
+        else {
+            out.push_str("    |");
+        }
+
+        let rhs = gram.get_rhs_items(r);
+        if rhs.len() == 0 {
+            out.push_str(" /* empty */");
+        }
+        let mut natural: i16 = 0;
+        for &s in rhs.iter() {
+            out.push_str(" ");
+            out.push_str(yacc_name(gram.name[s as uint].as_slice()).as_slice());
+            if gram.is_token(s as uint) {
+                natural = gram.prec[s as uint];
+            }
+        }
+
+        // A rule whose precedence is not that of its last token was given one by %prec.
+        if gram.rprec[r] != natural && gram.rprec[r] != 0 {
+            let prec_token = range(2, gram.ntokens)
+                .find(|&t| gram.prec[t] == gram.rprec[r] && gram.assoc[t] == gram.rassoc[r]);
+            if let Some(t) = prec_token {
+                out.push_str(format!(" %prec {}", yacc_name(gram.name[t].as_slice())).as_slice());
+            }
+        }
+        out.push_str("\n");
+
+        let last = r + 1 == gram.nrules || gram.rlhs[r + 1] as uint != lhs;
+        if last {
+            out.push_str("    ;\n");
+        }
+    }
+    out.push_str("\n%%\n");
+    out
+}
+
+fn is_char_literal(name: &str) -> bool {
+    name.len() >= 3 && name.starts_with("'") && name.ends_with("'")
+}
+
+// Makes a name into a valid YACC identifier.
+fn yacc_name(name: &str) -> String {
+    if is_char_literal(name) {
+        return name.to_string();
+    }
+    let mut out = String::with_capacity(name.len());
+    for c in name.chars() {
+        if c.is_alphanumeric() || c == '_' || c == '.' {
+            out.push(c);
+        }
+        else {
+            out.push('_');
+        }
+    }
+    if out.len() == 0 || out.as_slice().char_at(0).is_digit(10) {
+        out.insert(0, '_');
+    }
+    out
+}
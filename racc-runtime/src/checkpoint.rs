@@ -0,0 +1,138 @@
+// Reparses a text after an edit, starting from the last checkpoint before the edit rather than
+// from the beginning.
+//
+// This is checkpoint restart, not incremental parsing in the sense of tree-sitter: everything
+// after the checkpoint is lexed and parsed again, up to the end of the input, even where the text
+// after the edit has not changed.  No subtree after the edit is reused.
+//
+// While it parses, a CheckpointParser saves a snapshot of the parser (see
+// ParserState::snapshot) before every `interval` tokens, together with the offset at which that
+// token starts.  After an edit at offset e, the parser is restored to the last snapshot whose
+// previous token ended before e, and the lexer is restarted at the offset of that snapshot.
+// Nothing before that point is lexed or parsed again: the values on the restored stack (for a
+// parser which builds a tree, the subtrees of everything before the edit) are reused as they are.
+//
+// The snapshot must come from before the token that ends at (or after) e, because an edit which
+// touches the end of a token can change that token: inserting "c" after "ab" makes "abc".
+//
+// The reductions after the restored point run again, so their actions run again.  Actions which
+// only compute values are fine; actions which change the context should be written so that
+// running them again does no harm.
+
+#[cfg(not(feature = "std"))]
+use std::prelude::*;
+
+use std::fmt::Show;
+
+use {ParserState, ParserSnapshot, FinishParseResult};
+use lexer::Lexer;
+
+// A point at which the parser can be restored.
+struct Checkpoint<SymbolValue> {
+    offset: uint,           // where the next token starts
+    prev_end: uint,         // where the token before it ends; 0 at the start of the input
+    snapshot: ParserSnapshot<SymbolValue>,
+}
+
+/// A parser which can reparse its input after an edit, from the last checkpoint before the edit.
+/// The locations of the tokens must be byte offsets (or some other position in the text), as
+/// they are for a `Scanner`; use `Scanner::starting_at` to lex from `resume_offset`.
+pub struct CheckpointParser<SymbolValue: Show, AppContext> {
+    parser: ParserState<SymbolValue, AppContext>,
+    interval: uint,
+    checkpoints: Vec<Checkpoint<SymbolValue>>,
+}
+
+impl<SymbolValue: Show + Clone, AppContext> CheckpointParser<SymbolValue, AppContext> {
+    /// Creates a checkpoint parser, which saves a checkpoint before every `interval` tokens.
+    /// A smaller interval means that less is reparsed after an edit, and that more memory is
+    /// used for checkpoints.
+    pub fn new(parser: ParserState<SymbolValue, AppContext>, interval: uint) -> CheckpointParser<SymbolValue, AppContext> {
+        assert!(interval > 0);
+        CheckpointParser {
+            parser: parser,
+            interval: interval,
+            checkpoints: Vec::new(),
+        }
+    }
+
+    /// Returns the parser, for example to look at its last error.
+    pub fn parser(&self) -> &ParserState<SymbolValue, AppContext> {
+        &self.parser
+    }
+
+    /// Parses the whole of the input, from the start, and saves checkpoints as it goes.
+    pub fn parse<L: Lexer<SymbolValue>>(&mut self, ctx: &mut AppContext, lexer: &mut L) -> FinishParseResult<SymbolValue> {
+        self.parser.reset();
+        self.checkpoints.clear();
+        self.checkpoints.push(Checkpoint { offset: 0, prev_end: 0, snapshot: self.parser.snapshot() });
+        self.run(ctx, lexer, 0)
+    }
+
+    /// Returns the offset at which the lexer must start for a reparse after an edit at `edit`.
+    /// This is never after `edit`.
+    pub fn resume_offset(&self, edit: uint) -> uint {
+        match self.checkpoint_before(edit) {
+            Some(i) => self.checkpoints[i].offset,
+            None => 0
+        }
+    }
+
+    /// Reparses the input after an edit at offset `edit`, from the last checkpoint before it to
+    /// the end.  `lexer` must return the tokens of the edited text, starting at
+    /// `resume_offset(edit)`, with their locations in the edited text.
+    pub fn reparse<L: Lexer<SymbolValue>>(&mut self, ctx: &mut AppContext, edit: uint, lexer: &mut L) -> FinishParseResult<SymbolValue> {
+        let i = match self.checkpoint_before(edit) {
+            Some(i) => i,
+            None => return self.parse(ctx, lexer)
+        };
+        // The checkpoints after this one describe text which may have changed.
+        self.checkpoints.truncate(i + 1);
+        let snapshot = self.checkpoints[i].snapshot.clone();
+        if self.parser.restore(snapshot).is_err() {
+            return self.parse(ctx, lexer);
+        }
+        let prev_end = self.checkpoints[i].prev_end;
+        self.run(ctx, lexer, prev_end)
+    }
+
+    // Returns the index of the last checkpoint which comes before the token that an edit at
+    // `edit` could change.
+    fn checkpoint_before(&self, edit: uint) -> Option<uint> {
+        let mut found = None;
+        for (i, c) in self.checkpoints.iter().enumerate() {
+            if c.offset == 0 || c.prev_end < edit {
+                found = Some(i);
+            }
+            else {
+                break;
+            }
+        }
+        found
+    }
+
+    // Pushes the tokens of `lexer`, saving a checkpoint before every `interval` tokens, and then
+    // finishes parsing.  This is ParserState::parse, with checkpoints.
+    fn run<L: Lexer<SymbolValue>>(&mut self, ctx: &mut AppContext, lexer: &mut L, prev_end: uint) -> FinishParseResult<SymbolValue> {
+        let mut prev_end = prev_end;
+        let mut count = 0u;
+        loop {
+            let (token, lval, location) = match lexer.next_token() {
+                Some(t) => t,
+                None => return self.parser.finish(ctx)
+            };
+            if count != 0 && count % self.interval == 0 {
+                self.checkpoints.push(Checkpoint {
+                    offset: location.start,
+                    prev_end: prev_end,
+                    snapshot: self.parser.snapshot(),
+                });
+            }
+            count += 1;
+            prev_end = location.end;
+            if !self.parser.parse_token(ctx, token, lval, location) {
+                return self.parser.stopped();
+            }
+        }
+    }
+}
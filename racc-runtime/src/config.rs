@@ -0,0 +1,121 @@
+// Configuration of the run-time behavior of a parser.
+//
+// The generated tables describe a grammar; a ParserConfig describes how one particular
+// ParserState uses them.  This allows the same generated parser to be used for batch
+// processing, where the first error ends the parse, and for interactive use (e.g. in an
+// editor), where the parser should keep going and report as much as it can.
+
+#[cfg(not(feature = "std"))]
+use std::prelude::*;
+use std::default::Default;
+
+/// What a parser does after it reports a syntax error.
+#[deriving(Copy,Clone,PartialEq,Show)]
+pub enum RecoveryPolicy {
+    /// The parser recovers as YACC does: it pops states until it can shift the `error` token,
+    /// and then discards tokens until it can shift one.  If no state on the stack can shift
+    /// `error` (as when the grammar does not use it), recovery fails, and the parse fails as it
+    /// does under `Strict`.  This is the default.
+    Tolerant,
+    /// The parser rejects all further input.  `push_token` returns `SyntaxError` without looking
+    /// at the token, and `finish` returns `SyntaxError`.  Use `reset` to start over.
+    Strict,
+}
+
+/// Receives a description of each step that a parser takes.  Used for tracing the execution of
+/// a parser, without rebuilding with debug logging enabled.
+pub trait TraceSink {
+    fn trace(&mut self, message: &str);
+}
+
+/// A `TraceSink` which sends each step to the `log` crate, at the `info` level, so that a trace
+/// shows up with the rest of a program's logging.
+#[cfg(feature = "std")]
+#[deriving(Copy,Clone)]
+pub struct LogTrace;
+
+#[cfg(feature = "std")]
+impl TraceSink for LogTrace {
+    fn trace(&mut self, message: &str) {
+        info!("{}", message);
+    }
+}
+
+/// Any closure which takes the description of a step can be used as a trace sink.
+impl<F> TraceSink for F where F: FnMut(&str) {
+    fn trace(&mut self, message: &str) {
+        (*self)(message)
+    }
+}
+
+/// Configures the run-time behavior of a `ParserState`.  Pass it to `ParserState::with_config`.
+///
+/// `ParserConfig::default()` gives the behavior of `ParserState::new`.  To change only some of
+/// the settings, use struct update syntax:
+///
+/// ```ignore
+///     let config = ParserConfig { max_depth: Some(1000), ..ParserConfig::strict() };
+///     let mut parser = ParserState::with_config(get_parser_tables(), config);
+/// ```
+pub struct ParserConfig {
+    /// What the parser does after a syntax error.
+    pub recovery: RecoveryPolicy,
+
+    /// The maximum depth of the state stack.  A token which arrives when the stack is this deep
+    /// is rejected with `PushTokenResult::StackOverflow`, and the parser stays as it was.  `None`
+    /// means there is no limit, so input from an untrusted source (such as a long
+    /// right-recursive list) can make the stacks grow until memory runs out.  The reductions of
+    /// empty rules made before a token is shifted can take the stack a few states past the
+    /// limit; never more than the grammar has empty rules in a row.
+    ///
+    /// Unless `initial_capacity` is given, setting a limit also makes the parser allocate its
+    /// stacks once, at their full capacity, so that parsing does not allocate.  The stacks are
+    /// still `Vec`s on the heap; there is no driver whose stacks are arrays of a fixed size.
+    pub max_depth: Option<uint>,
+
+    /// The number of states that the stacks have room for when the parser is created.  The
+    /// stacks grow beyond it as needed, up to `max_depth`.  `None` means `max_depth` if there is
+    /// one, and a small default otherwise.
+    pub initial_capacity: Option<uint>,
+
+    /// If present, receives a description of each shift, reduction, goto, and error, and of
+    /// each step of error recovery.  `ParserState::set_trace` changes it after the parser has
+    /// been created.
+    pub trace: Option<Box<TraceSink + 'static>>,
+
+    /// If true, the parser counts its reductions by each rule, and the states that it enters;
+    /// see `ParserState::coverage`.  Off by default.
+    pub coverage: bool,
+}
+
+impl ParserConfig {
+    /// Returns a configuration suitable for batch processing: the first syntax error ends
+    /// the parse.
+    pub fn strict() -> ParserConfig {
+        ParserConfig {
+            recovery: RecoveryPolicy::Strict,
+            .. Default::default()
+        }
+    }
+
+    /// Returns a configuration suitable for interactive use: the parser continues after syntax
+    /// errors.
+    pub fn tolerant() -> ParserConfig {
+        ParserConfig {
+            recovery: RecoveryPolicy::Tolerant,
+            .. Default::default()
+        }
+    }
+}
+
+impl Default for ParserConfig {
+    fn default() -> ParserConfig {
+        ParserConfig {
+            recovery: RecoveryPolicy::Tolerant,
+            max_depth: None,
+            initial_capacity: None,
+            trace: None,
+            coverage: false,
+        }
+    }
+}
@@ -0,0 +1,98 @@
+// Records which rules a parser reduces by, and which states it enters, so that the tests of a
+// grammar can check that their inputs exercise every alternative of every rule.
+//
+// Coverage is off unless ParserConfig.coverage is set, and then costs an increment for each
+// shift and each reduction.  A parser keeps its coverage across `reset`, so one parser can count
+// a whole test corpus; coverage from several parsers (for example, one per test) can be combined
+// with `merge`.  Rules are numbered as in the generated YYRULES table, and rule 0 ($accept),
+// which is never reduced, is not counted.  The initial state is not counted either, since every
+// parse begins in it.
+
+#[cfg(not(feature = "std"))]
+use std::prelude::*;
+
+use std::fmt::Show;
+
+use ParserTables;
+
+/// The number of times that a parser reduced by each rule, and entered each state.
+#[deriving(Clone,PartialEq,Show)]
+pub struct Coverage {
+    /// The number of reductions by each rule, indexed by rule.
+    pub rules: Vec<u64>,
+    /// The number of times that each state was entered, by a shift or a goto, indexed by state.
+    pub states: Vec<u64>,
+}
+
+impl Coverage {
+    /// Creates an empty record of coverage for a parser with these tables.
+    pub fn new<SymbolValue: Show, AppContext>(tables: &ParserTables<SymbolValue, AppContext>) -> Coverage {
+        Coverage {
+            rules: Vec::from_elem(tables.yylen.len(), 0),
+            states: Vec::from_elem(tables.yydefred.len(), 0),
+        }
+    }
+
+    /// Adds the counts of `other`, which must be the coverage of a parser for the same grammar.
+    pub fn merge(&mut self, other: &Coverage) {
+        assert!(self.rules.len() == other.rules.len() && self.states.len() == other.states.len(),
+            "coverage of parsers for different grammars");
+        for (a, b) in self.rules.iter_mut().zip(other.rules.iter()) {
+            *a += *b;
+        }
+        for (a, b) in self.states.iter_mut().zip(other.states.iter()) {
+            *a += *b;
+        }
+    }
+
+    /// Returns the rules which were never reduced.
+    pub fn unused_rules(&self) -> Vec<uint> {
+        range(1, self.rules.len()).filter(|&r| self.rules[r] == 0).collect()
+    }
+
+    /// Returns the states which were never entered.
+    pub fn unvisited_states(&self) -> Vec<uint> {
+        range(1, self.states.len()).filter(|&s| self.states[s] == 0).collect()
+    }
+
+    /// Returns true if every rule was reduced, and every state entered.
+    pub fn is_complete(&self) -> bool {
+        self.unused_rules().len() == 0 && self.unvisited_states().len() == 0
+    }
+
+    /// Describes the coverage: how many of the rules and states were used, and which were not.
+    ///
+    /// ```ignore
+    ///     rules: 11 of 12 reduced (91%)
+    ///     never reduced:
+    ///         7  Expr : Expr PERCENT Expr
+    ///     states: 20 of 21 entered (95%)
+    ///     never entered: 14
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn report<SymbolValue: Show, AppContext>(&self, tables: &ParserTables<SymbolValue, AppContext>) -> String {
+        let mut s = String::new();
+        let unused = self.unused_rules();
+        let nrules = self.rules.len() - 1;
+        s.push_str(format!("rules: {} of {} reduced ({}%)\n", nrules - unused.len(), nrules, percent(nrules - unused.len(), nrules)).as_slice());
+        if unused.len() > 0 {
+            s.push_str("never reduced:\n");
+            for &rule in unused.iter() {
+                s.push_str(format!("    {}  {}\n", rule, tables.yyrules[rule]).as_slice());
+            }
+        }
+        let unvisited = self.unvisited_states();
+        let nstates = self.states.len() - 1;
+        s.push_str(format!("states: {} of {} entered ({}%)\n", nstates - unvisited.len(), nstates, percent(nstates - unvisited.len(), nstates)).as_slice());
+        if unvisited.len() > 0 {
+            let states: Vec<String> = unvisited.iter().map(|s| s.to_string()).collect();
+            s.push_str(format!("never entered: {}\n", states.connect(" ")).as_slice());
+        }
+        s
+    }
+}
+
+#[cfg(feature = "std")]
+fn percent(n: uint, total: uint) -> uint {
+    if total == 0 { 100 } else { n * 100 / total }
+}
@@ -0,0 +1,240 @@
+// Runs a parser one action at a time, for grammar debuggers, and for tests which check what the
+// automaton does.
+//
+// ParserState::push_token runs every reduction that a token allows, shifts the token, and then
+// runs the default reductions of the state that it shifts to, all in one call.  DebugParser
+// takes the same steps, in the same order, but returns after each one: each call to `step`
+// shifts one token or reduces by one rule.  It reads a token from its lexer only when the next
+// step needs one, as parse() does, so a context-dependent lexer sees the same parser either
+// way.
+//
+// A syntax error is one step: the parser reports the error and recovers from it (or fails) as
+// it would in push_token, and `step` returns SyntaxError.
+
+#[cfg(not(feature = "std"))]
+use std::prelude::*;
+
+use std::fmt::Show;
+
+use {ParserState, ParserTables, FinishParseResult, TableAction, Location, Item};
+use lexer::Lexer;
+
+/// A step that a `DebugParser` took.
+pub enum Step<SymbolValue> {
+    /// The parser shifted the token, and went to the state.
+    Shift(u32, uint),
+    /// The parser reduced by the rule, and went to the state.  Rules are numbered as in the
+    /// generated `YYRULES` table.
+    Reduce(uint, uint),
+    /// The token was a syntax error.  The parser has recovered from it, as `push_token` would,
+    /// and can take more steps; see `ParserState::last_error`.
+    SyntaxError(u32),
+    /// The parse is over.  No more steps can be taken.
+    Finished(FinishParseResult<SymbolValue>),
+}
+
+/// An entry of the stack of a parser: a state, and the symbol which was shifted or reduced to
+/// enter it.  The entry at the bottom of the stack is the initial state, which has no symbol.
+pub struct StackEntry<'a, SymbolValue: 'a> {
+    pub state: uint,
+    pub value: Option<&'a SymbolValue>,
+    pub location: Option<Location>,
+}
+
+/// A parser which takes one step at a time, and which can stop at breakpoints.  It wraps a
+/// `ParserState` and a lexer:
+///
+/// ```ignore
+///     let mut debugger = DebugParser::new(ParserState::new(get_parser_tables()), new_scanner(input));
+///     debugger.break_on_rule(3);
+///     loop {
+///         match debugger.run(&mut ctx) {
+///             Step::Finished(result) => break,
+///             _ => println!("state {}: {}", debugger.state(), debugger.current_state_items()),
+///         }
+///     }
+/// ```
+pub struct DebugParser<SymbolValue: Show, AppContext, L> {
+    parser: ParserState<SymbolValue, AppContext>,
+    lexer: L,
+    lookahead: Option<(u32, SymbolValue, Location)>,
+    end_of_input: bool,         // the lexer has no more tokens
+    finished: bool,
+    rule_breakpoints: Vec<uint>,
+    state_breakpoints: Vec<uint>,
+}
+
+impl<SymbolValue: Show, AppContext, L: Lexer<SymbolValue>> DebugParser<SymbolValue, AppContext, L> {
+    /// Creates a debugger which parses the tokens of `lexer` with `parser`.  The parser is
+    /// reset first.
+    pub fn new(parser: ParserState<SymbolValue, AppContext>, lexer: L) -> DebugParser<SymbolValue, AppContext, L> {
+        let mut parser = parser;
+        parser.reset();
+        DebugParser {
+            parser: parser,
+            lexer: lexer,
+            lookahead: None,
+            end_of_input: false,
+            finished: false,
+            rule_breakpoints: Vec::new(),
+            state_breakpoints: Vec::new(),
+        }
+    }
+
+    /// Returns the parser, for example to look at its last error.
+    pub fn parser(&self) -> &ParserState<SymbolValue, AppContext> {
+        &self.parser
+    }
+
+    /// Returns the tables of the parser.
+    pub fn tables(&self) -> &ParserTables<SymbolValue, AppContext> {
+        &self.parser.tables
+    }
+
+    /// Returns the state that the parser is in.
+    pub fn state(&self) -> uint {
+        self.parser.yystate
+    }
+
+    /// Returns the next token, if it has been read from the lexer.  The parser reads a token
+    /// only when it needs one to decide what to do.
+    pub fn lookahead(&self) -> Option<u32> {
+        match self.lookahead {
+            Some((token, _, _)) => Some(token),
+            None if self.end_of_input => Some(0),
+            None => None
+        }
+    }
+
+    /// Returns the stack of the parser, from the bottom (the initial state) to the top (the
+    /// current state).
+    pub fn peek_stack(&self) -> Vec<StackEntry<SymbolValue>> {
+        let p = &self.parser;
+        range(0, p.state_stack.len()).map(|i| StackEntry {
+            state: p.state_stack[i],
+            value: if i == 0 { None } else { Some(&p.value_stack[i - 1]) },
+            location: if i == 0 { None } else { Some(p.location_stack[i - 1]) },
+        }).collect()
+    }
+
+    /// Returns the kernel items of the current state.  This is empty if the parser was generated
+    /// without debug tables; see `ParserTables::kernel_items`.
+    pub fn current_state_items(&self) -> Vec<Item> {
+        self.parser.tables.kernel_items(self.parser.yystate)
+    }
+
+    /// Makes `run` stop after a reduction by `rule`.
+    pub fn break_on_rule(&mut self, rule: uint) {
+        self.rule_breakpoints.push(rule);
+    }
+
+    /// Makes `run` stop when the parser enters `state`, by a shift or by the goto after a
+    /// reduction.
+    pub fn break_on_state(&mut self, state: uint) {
+        self.state_breakpoints.push(state);
+    }
+
+    /// Removes all of the breakpoints.
+    pub fn clear_breakpoints(&mut self) {
+        self.rule_breakpoints.clear();
+        self.state_breakpoints.clear();
+    }
+
+    /// Takes steps until one of them reaches a breakpoint, or the parse is over, and returns
+    /// that step.  Syntax errors always stop it.
+    pub fn run(&mut self, ctx: &mut AppContext) -> Step<SymbolValue> {
+        loop {
+            let step = self.step(ctx);
+            let stop = match step {
+                Step::Shift(_, state) => self.state_breakpoints.contains(&state),
+                Step::Reduce(rule, state) => self.rule_breakpoints.contains(&rule) || self.state_breakpoints.contains(&state),
+                Step::SyntaxError(_) | Step::Finished(_) => true
+            };
+            if stop {
+                return step;
+            }
+        }
+    }
+
+    /// Takes one step: shifts a token, or reduces by a rule.  Must not be called after it has
+    /// returned `Finished`.
+    pub fn step(&mut self, ctx: &mut AppContext) -> Step<SymbolValue> {
+        assert!(!self.finished, "the parse is over");
+        if self.parser.failed {
+            let result = self.parser.stopped();
+            return self.finish(result);
+        }
+
+        // A default reduction is made before the next token is read, as push_token makes it
+        // right after the shift.
+        let defred = self.parser.tables.yydefred[self.parser.yystate] as uint;
+        if defred != 0 {
+            return self.reduce(defred, ctx);
+        }
+
+        if self.lookahead.is_none() && !self.end_of_input {
+            match self.lexer.next_token() {
+                Some(t) => self.lookahead = Some(t),
+                None => self.end_of_input = true
+            }
+        }
+
+        if self.end_of_input {
+            return match self.parser.tables.action(self.parser.yystate, 0) {
+                TableAction::Reduce(rule) => self.reduce(rule, ctx),
+                _ => {
+                    let result = self.parser.finish(ctx);
+                    self.finish(result)
+                }
+            };
+        }
+
+        let token = match self.lookahead {
+            Some((token, _, _)) => token,
+            None => unreachable!()
+        };
+        let at_limit = match self.parser.config.max_depth {
+            Some(max_depth) => self.parser.state_stack.len() >= max_depth,
+            None => false
+        };
+        match self.parser.tables.action(self.parser.yystate, token) {
+            TableAction::Reduce(rule) => self.reduce(rule, ctx),
+            TableAction::Shift(next_state) if !at_limit => {
+                let (token, lval, location) = self.lookahead.take().unwrap();
+                self.parser.shift(token, next_state, lval, location);
+                if self.parser.recovering > 0 {
+                    self.parser.recovering -= 1;
+                }
+                Step::Shift(token, next_state)
+            }
+            _ => {
+                // A syntax error, or a token which would take the stack too deep.  push_token
+                // deals with both.
+                let (token, lval, location) = self.lookahead.take().unwrap();
+                if self.parser.parse_token(ctx, token, lval, location) {
+                    Step::SyntaxError(token)
+                }
+                else {
+                    let result = self.parser.stopped();
+                    self.finish(result)
+                }
+            }
+        }
+    }
+
+    // Reduces by a rule.
+    fn reduce(&mut self, rule: uint, ctx: &mut AppContext) -> Step<SymbolValue> {
+        if self.parser.yyreduce(rule, ctx) {
+            Step::Reduce(rule, self.parser.yystate)
+        }
+        else {
+            let result = self.parser.stopped();
+            self.finish(result)
+        }
+    }
+
+    fn finish(&mut self, result: FinishParseResult<SymbolValue>) -> Step<SymbolValue> {
+        self.finished = true;
+        Step::Finished(result)
+    }
+}
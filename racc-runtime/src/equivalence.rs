@@ -0,0 +1,292 @@
+// Checks that two parsers accept the same inputs, and reduce them in the same way, so that a
+// grammar can be refactored safely: the parser generated from the old grammar is the reference,
+// and the parser generated from the new one must agree with it on every input tried.
+//
+// Only the tables are compared, not the actions, so the parsers may have different value types
+// and contexts.  Each input is a sequence of token names, as in the YYNAME table, since a
+// refactoring may renumber the tokens.  Each parser runs the input without error recovery, and
+// the outcome is whether it accepted, where it found the first syntax error if it did not, and
+// the text of each rule that it reduced by, in order.  Two parsers are equivalent on an input if
+// the outcomes are the same; comparing the text of the rules, not their numbers, allows the
+// rules to be reordered.  A refactoring which renames a non-terminal or changes a rule on
+// purpose changes the text, and shows up as a difference.
+//
+// The inputs can be sentences from racc_core::generate (written as token names), or be derived
+// from bytes by `sentence_from_bytes`, which follows the reference parser through its acceptable
+// tokens (as racc_runtime::fuzz does), so that a fuzzer or a property-testing tool can supply
+// them.
+
+use std::fmt::Show;
+
+use {ParserTables, TableAction};
+use fuzz::FuzzInput;
+
+/// What a parser did with an input.
+#[deriving(Clone,PartialEq,Show)]
+pub struct Outcome {
+    /// True if the parser accepted the input.
+    pub accepted: bool,
+    /// The index of the token at which the parser found a syntax error (the length of the input
+    /// for the end of input), or None if it found none.
+    pub error_at: Option<uint>,
+    /// The text of the rules that the parser reduced by, in order.
+    pub reductions: Vec<&'static str>,
+}
+
+/// An input on which two parsers disagree, and what each of them did with it.
+#[deriving(Clone,Show)]
+pub struct Mismatch {
+    pub input: Vec<String>,
+    pub reference: Outcome,
+    pub candidate: Outcome,
+}
+
+impl Mismatch {
+    /// Describes the difference, for a test failure.
+    pub fn describe(&self) -> String {
+        let mut s = format!("the parsers disagree on the input: {}\n", self.input.connect(" "));
+        if self.reference.accepted != self.candidate.accepted || self.reference.error_at != self.candidate.error_at {
+            s.push_str(format!("the reference parser {}, and the other parser {}\n",
+                describe_result(&self.reference), describe_result(&self.candidate)).as_slice());
+        }
+        let common = self.reference.reductions.iter().zip(self.candidate.reductions.iter())
+            .take_while(|&(a, b)| a == b)
+            .count();
+        if common < self.reference.reductions.len() || common < self.candidate.reductions.len() {
+            s.push_str(format!("after {} reductions in common, the reference parser reduces by {}, and the other parser by {}\n",
+                common, describe_reduction(self.reference.reductions.as_slice(), common),
+                describe_reduction(self.candidate.reductions.as_slice(), common)).as_slice());
+        }
+        s
+    }
+}
+
+fn describe_result(outcome: &Outcome) -> String {
+    match outcome.error_at {
+        _ if outcome.accepted => "accepts it".to_string(),
+        Some(i) => format!("rejects it at token {}", i),
+        None => "rejects it".to_string()
+    }
+}
+
+fn describe_reduction(reductions: &[&'static str], i: uint) -> String {
+    if i < reductions.len() {
+        format!("`{}`", reductions[i])
+    }
+    else {
+        "nothing more".to_string()
+    }
+}
+
+/// Runs a parser's tables over an input of token names, and returns what it did.  A name which
+/// is not a token of the parser is a syntax error.
+pub fn run<V: Show, C>(tables: &ParserTables<V, C>, input: &[&str]) -> Outcome {
+    let mut stack: Vec<uint> = vec![0];
+    let mut reductions: Vec<uint> = Vec::new();
+    for i in range(0, input.len() + 1) {
+        let token = if i < input.len() {
+            match token_value(tables, input[i]) {
+                Some(token) => token,
+                None => return outcome(tables, false, Some(i), reductions.as_slice())
+            }
+        }
+        else {
+            0
+        };
+        match advance(tables, &mut stack, token, &mut reductions) {
+            TableAction::Shift(_) => {}
+            TableAction::Accept => return outcome(tables, true, None, reductions.as_slice()),
+            _ => return outcome(tables, false, Some(i), reductions.as_slice())
+        }
+    }
+    // The end of input is either accepted or an error, so the loop always returns.
+    unreachable!()
+}
+
+/// Runs both parsers over an input, and returns the difference, if they do not agree.
+pub fn compare<V1: Show, C1, V2: Show, C2>(reference: &ParserTables<V1, C1>, candidate: &ParserTables<V2, C2>, input: &[&str]) -> Result<(), Mismatch> {
+    let a = run(reference, input);
+    let b = run(candidate, input);
+    if a == b {
+        Ok(())
+    }
+    else {
+        Err(Mismatch {
+            input: input.iter().map(|s| s.to_string()).collect(),
+            reference: a,
+            candidate: b,
+        })
+    }
+}
+
+/// Runs both parsers over each of the inputs, and panics with a description of the first input
+/// on which they disagree.
+///
+/// ```ignore
+///     let inputs = ...;   // for example, from racc_core::generate::SentenceGenerator::cover
+///     equivalence::assert_equivalent(&old_grammar::get_parser_tables(), &new_grammar::get_parser_tables(), inputs.as_slice());
+/// ```
+pub fn assert_equivalent<V1: Show, C1, V2: Show, C2>(reference: &ParserTables<V1, C1>, candidate: &ParserTables<V2, C2>, inputs: &[Vec<&str>]) {
+    for input in inputs.iter() {
+        if let Err(mismatch) = compare(reference, candidate, input.as_slice()) {
+            panic!("{}", mismatch.describe());
+        }
+    }
+}
+
+/// Derives an input from bytes, by following the parser through the tokens that it can accept:
+/// each byte chooses the next token, or the end of the input.  As with `fuzz::fuzz_parse`, one
+/// more choice picks any token at all, so that some inputs are syntax errors.  The input ends
+/// when the bytes run out, or after `max_tokens` tokens.
+pub fn sentence_from_bytes<V: Show, C>(tables: &ParserTables<V, C>, data: &[u8], max_tokens: uint) -> Vec<&'static str> {
+    let mut input = FuzzInput::new(data);
+    let mut stack: Vec<uint> = vec![0];
+    let mut scratch: Vec<uint> = Vec::new();
+    let mut sentence: Vec<&'static str> = Vec::new();
+    while sentence.len() < max_tokens {
+        let candidates: Vec<u32> = range(0, tables.yyname.len() as u32).filter(|&t| {
+            let mut copy = stack.clone();
+            (t == 0 || is_token_name(tables.yyname[t as uint]))
+                && advance(tables, &mut copy, t, &mut scratch) != TableAction::Error
+        }).collect();
+        let token = match input.choose(candidates.len() + 1) {
+            Some(i) if i < candidates.len() => candidates[i],
+            Some(_) => match input.choose(tables.yyname.len()) {
+                Some(t) if is_token_name(tables.yyname[t]) => t as u32,
+                _ => break
+            },
+            None => break
+        };
+        if token == 0 {
+            break;
+        }
+        sentence.push(tables.yyname[token as uint]);
+        if advance(tables, &mut stack, token, &mut scratch) == TableAction::Error {
+            break;
+        }
+    }
+    sentence
+}
+
+// Runs the reductions that `token` calls for, and then shifts it.  Returns the action which
+// ended the step: Shift, Accept, or Error.
+fn advance<V: Show, C>(tables: &ParserTables<V, C>, stack: &mut Vec<uint>, token: u32, reductions: &mut Vec<uint>) -> TableAction {
+    loop {
+        let state = stack[stack.len() - 1];
+        match tables.action(state, token) {
+            TableAction::Reduce(rule) => {
+                reductions.push(rule);
+                let new_len = stack.len() - tables.yylen[rule] as uint;
+                stack.truncate(new_len);
+                let top = stack[stack.len() - 1];
+                stack.push(tables.goto(top, tables.yylhs[rule]));
+            }
+            TableAction::Shift(next) => {
+                stack.push(next);
+                return TableAction::Shift(next);
+            }
+            action => return action
+        }
+    }
+}
+
+fn outcome<V: Show, C>(tables: &ParserTables<V, C>, accepted: bool, error_at: Option<uint>, reductions: &[uint]) -> Outcome {
+    Outcome {
+        accepted: accepted,
+        error_at: error_at,
+        reductions: reductions.iter().map(|&r| rule_text(tables.yyrules[r])).collect(),
+    }
+}
+
+// Returns the text of a rule without the "(rN) " which numbers it, so that reordering the rules
+// does not change their text.
+fn rule_text(text: &'static str) -> &'static str {
+    match text.find_str(") ") {
+        Some(i) if text.starts_with("(r") => text.slice_from(i + 2),
+        _ => text
+    }
+}
+
+fn token_value<V: Show, C>(tables: &ParserTables<V, C>, name: &str) -> Option<u32> {
+    if !is_token_name(name) {
+        return None;
+    }
+    tables.yyname.iter().position(|n| *n == name).map(|t| t as u32)
+}
+
+// Unused token values have no name; the error token is not part of any input.
+fn is_token_name(name: &str) -> bool {
+    name.len() > 0 && name != "error"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{run, compare, assert_equivalent, sentence_from_bytes, Outcome};
+    use testing::{left, right, reordered};
+
+    fn outcome(accepted: bool, error_at: Option<uint>, reductions: &[&'static str]) -> Outcome {
+        Outcome { accepted: accepted, error_at: error_at, reductions: reductions.to_vec() }
+    }
+
+    #[test]
+    fn run_accepts() {
+        assert_eq!(run(&left::tables(), &["A", "A"]), outcome(true, None, &["s : A", "s : s A"]));
+        assert_eq!(run(&right::tables(), &["A", "A"]), outcome(true, None, &["s : A", "s : A s"]));
+    }
+
+    #[test]
+    fn run_rejects() {
+        // B is a token, which no state accepts; C is not a token at all.
+        assert_eq!(run(&left::tables(), &["A", "B"]), outcome(false, Some(1), &["s : A"]));
+        assert_eq!(run(&left::tables(), &["C"]), outcome(false, Some(0), &[]));
+        assert_eq!(run(&left::tables(), &["error"]), outcome(false, Some(0), &[]));
+        // The end of the input is at the index after the last token.
+        assert_eq!(run(&left::tables(), &[]), outcome(false, Some(0), &[]));
+    }
+
+    #[test]
+    fn reordered_rules_are_equivalent() {
+        // The rules have different numbers, but the same text.
+        assert!(compare(&left::tables(), &reordered::tables(), &["A", "A", "A"]).is_ok());
+        assert_equivalent(&left::tables(), &reordered::tables(), &[vec![], vec!["A"], vec!["A", "A"], vec!["A", "B"]]);
+    }
+
+    #[test]
+    fn different_reductions() {
+        let mismatch = compare(&left::tables(), &right::tables(), &["A", "A"]).unwrap_err();
+        assert_eq!(mismatch.input, vec!["A".to_string(), "A".to_string()]);
+        assert_eq!(mismatch.describe().as_slice(),
+            "the parsers disagree on the input: A A\n\
+             after 1 reductions in common, the reference parser reduces by `s : s A`, and the other parser by `s : A s`\n");
+    }
+
+    #[test]
+    fn different_results() {
+        let mismatch = compare(&left::tables(), &right::tables(), &["A", "B"]).unwrap_err();
+        assert_eq!(mismatch.candidate, outcome(false, Some(1), &[]));
+        assert_eq!(mismatch.describe().as_slice(),
+            "the parsers disagree on the input: A B\n\
+             after 0 reductions in common, the reference parser reduces by `s : A`, and the other parser by nothing more\n");
+
+        // Both reject it at the same token, before reducing.
+        assert!(compare(&left::tables(), &right::tables(), &["B"]).is_ok());
+    }
+
+    #[test]
+    #[should_fail]
+    fn assert_equivalent_panics() {
+        assert_equivalent(&left::tables(), &right::tables(), &[vec!["A"], vec!["A", "A"]]);
+    }
+
+    #[test]
+    fn sentences_from_bytes() {
+        let tables = left::tables();
+        // Each byte chooses among the acceptable tokens, which are A and then also the end.
+        assert_eq!(sentence_from_bytes(&tables, &[0, 0], 10), vec!["A"]);
+        assert_eq!(sentence_from_bytes(&tables, &[0, 1, 1, 2], 10), vec!["A", "A", "A"]);
+        assert_eq!(sentence_from_bytes(&tables, &[0, 1, 1, 1, 1], 2), vec!["A", "A"]);
+        // The last choice takes two more bytes, which pick any token: 0x0102 % 259 is B.
+        assert_eq!(sentence_from_bytes(&tables, &[0, 2, 1, 2], 10), vec!["A", "B"]);
+        assert_eq!(sentence_from_bytes(&tables, &[], 10), Vec::<&str>::new());
+    }
+}
@@ -0,0 +1,144 @@
+// Builds the values of expressions declared with %expr, by precedence climbing.
+//
+// Writing an expression language with one non-terminal per precedence level gives the LR
+// automaton a chain of unit reductions and states for every level; writing it with one ambiguous
+// non-terminal and %left/%right gives it a shift/reduce conflict for every pair of operators,
+// all resolved by precedence.  A %expr non-terminal does neither.  In the LR grammar, it is a
+// flat list of operands, separated by binary operators and preceded by prefix operators, which
+// has no conflicts and few states, since every operator is treated alike.  The reductions of the
+// list collect it into a Vec of ExprItems, and the reduction of the expression itself hands that
+// list to `climb`, which arranges it into a tree by the precedence and associativity of the
+// operators, and calls the grammar's actions to combine the operands.
+//
+// The operators are tokens, and are given to the actions by their values.  A prefix operator
+// applies to everything that follows it up to the first binary operator whose precedence is not
+// higher than its own, so with "%left TIMES; %prefix MINUS; %right POW;", "MINUS a TIMES b" is
+// (MINUS a) TIMES b, and "MINUS a POW b" is MINUS (a POW b).  A %nonassoc operator cannot follow
+// an operand of the same level: "a LT b LT c" makes climb fail, and the action fails with the
+// ExprError.
+
+#[cfg(not(feature = "std"))]
+use std::prelude::*;
+
+use std::fmt;
+
+/// An element of the flat list that an expression is parsed as.
+#[deriving(Clone,PartialEq,Show)]
+pub enum ExprItem<V> {
+    Operand(V),
+    /// A prefix operator, by its token value.
+    Prefix(u32),
+    /// A binary operator, by its token value.
+    Binary(u32),
+}
+
+/// The associativity of a binary operator.
+#[deriving(Copy,Clone,PartialEq,Show)]
+pub enum OpAssoc {
+    Left,
+    Right,
+    NonAssoc,
+}
+
+/// A binary operator of an expression.  Operators with higher `prec` bind more tightly.
+#[deriving(Copy,Clone,Show)]
+pub struct BinaryOp {
+    pub token: u32,
+    pub prec: u32,
+    pub assoc: OpAssoc,
+}
+
+/// A prefix operator of an expression.
+#[deriving(Copy,Clone,Show)]
+pub struct PrefixOp {
+    pub token: u32,
+    pub prec: u32,
+}
+
+/// The operators of an expression, which RACC generates from a %expr declaration.
+pub struct ExprOperators {
+    pub binary: &'static [BinaryOp],
+    pub prefix: &'static [PrefixOp],
+}
+
+/// An operator applied to its operands, which the caller of `climb` turns into a value.
+pub enum ExprNode<V> {
+    Binary(u32, V, V),
+    Prefix(u32, V),
+}
+
+/// The error of an expression in which a non-associative operator follows an operand of the
+/// same precedence, such as "a LT b LT c".  `token` is the second operator.
+#[deriving(Copy,Clone,PartialEq)]
+pub struct ExprError {
+    pub token: u32,
+}
+
+impl fmt::Show for ExprError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "operator {} is non-associative, and cannot follow an operand of the same precedence", self.token)
+    }
+}
+
+/// Arranges a flat list of operands and operators into a tree, by precedence climbing, and
+/// returns its value.  `combine` gives the value of each operator applied to its operands, from
+/// the innermost out.  The list must be well formed (as the LR parser ensures): each operand is
+/// preceded by any number of prefix operators, and operands are separated by binary operators.
+pub fn climb<V, F: FnMut(ExprNode<V>) -> V>(items: Vec<ExprItem<V>>, ops: &ExprOperators, mut combine: F) -> Result<V, ExprError> {
+    // The list is reversed, so that the next item is at the end, and can be popped.
+    let mut items = items;
+    items.reverse();
+    let value = try!(climb_expr(&mut items, ops, &mut combine, 0));
+    assert!(items.len() == 0, "malformed expression list");
+    Ok(value)
+}
+
+// Parses operands and the binary operators between them, for as long as the operators have at
+// least the precedence `min_prec`.
+fn climb_expr<V, F: FnMut(ExprNode<V>) -> V>(items: &mut Vec<ExprItem<V>>, ops: &ExprOperators, combine: &mut F, min_prec: u32) -> Result<V, ExprError> {
+    let mut left = try!(climb_operand(items, ops, combine));
+    loop {
+        let op = match items.last() {
+            Some(&ExprItem::Binary(token)) => binary_op(ops, token),
+            _ => break
+        };
+        if op.prec < min_prec {
+            break;
+        }
+        items.pop();
+        let next_min = if op.assoc == OpAssoc::Right { op.prec } else { op.prec + 1 };
+        let right = try!(climb_expr(items, ops, combine, next_min));
+        if op.assoc == OpAssoc::NonAssoc {
+            if let Some(&ExprItem::Binary(token)) = items.last() {
+                if binary_op(ops, token).prec == op.prec {
+                    return Err(ExprError { token: token });
+                }
+            }
+        }
+        left = (*combine)(ExprNode::Binary(op.token, left, right));
+    }
+    Ok(left)
+}
+
+// Parses an operand, with its prefix operators.
+fn climb_operand<V, F: FnMut(ExprNode<V>) -> V>(items: &mut Vec<ExprItem<V>>, ops: &ExprOperators, combine: &mut F) -> Result<V, ExprError> {
+    match items.pop() {
+        Some(ExprItem::Operand(value)) => Ok(value),
+        Some(ExprItem::Prefix(token)) => {
+            let prec = match ops.prefix.iter().find(|op| op.token == token) {
+                Some(op) => op.prec,
+                None => panic!("{} is not a prefix operator of this expression", token)
+            };
+            let operand = try!(climb_expr(items, ops, combine, prec + 1));
+            Ok((*combine)(ExprNode::Prefix(token, operand)))
+        }
+        _ => panic!("malformed expression list")
+    }
+}
+
+fn binary_op(ops: &ExprOperators, token: u32) -> BinaryOp {
+    match ops.binary.iter().find(|op| op.token == token) {
+        Some(&op) => op,
+        None => panic!("{} is not a binary operator of this expression", token)
+    }
+}
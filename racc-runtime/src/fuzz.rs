@@ -0,0 +1,114 @@
+// Fuzzes a parser and its actions with inputs from a coverage-guided fuzzer (cargo-fuzz, AFL),
+// which supplies arbitrary bytes.
+//
+// Feeding the bytes to the lexer finds bugs in the lexer, but almost every input is a syntax
+// error in its first few tokens, so the actions of the grammar are hardly ever run.  fuzz_parse
+// turns the bytes into tokens instead, as a series of choices: each byte chooses the next token
+// from those that the parser can accept in its current state (see ParserState::accepts), so the
+// tokens it pushes are almost always a prefix of a valid input, and the parse reaches the deep
+// rules of the grammar.  One more choice, after the acceptable tokens, pushes any token at all
+// (chosen by the next byte), so that error recovery is fuzzed too.  Because the choices follow
+// the parser, a small change to the bytes makes a small change to the tokens, which is what a
+// mutating fuzzer needs.
+//
+// The input ends when the bytes run out, when a choice picks the end of input, or after
+// `max_tokens` tokens.  The parser's own limits apply: a parser whose ParserConfig has a
+// max_depth reports a stack overflow, rather than growing its stacks without bound.
+
+#[cfg(not(feature = "std"))]
+use std::prelude::*;
+
+use std::fmt::Show;
+
+use {ParserState, FinishParseResult, PushTokenResult};
+
+/// The number of tokens after which `fuzz_parse` ends the input.
+pub const DEFAULT_MAX_TOKENS: uint = 10000;
+
+/// The bytes from a fuzzer, read as a series of choices.
+pub struct FuzzInput<'a> {
+    data: &'a [u8],
+    pos: uint,
+}
+
+impl<'a> FuzzInput<'a> {
+    pub fn new(data: &'a [u8]) -> FuzzInput<'a> {
+        FuzzInput { data: data, pos: 0 }
+    }
+
+    /// Returns true if all of the bytes have been used.
+    pub fn is_empty(&self) -> bool {
+        self.pos >= self.data.len()
+    }
+
+    /// Chooses a number in `[0, n)`, from one byte, or from two if `n` is more than 256.
+    /// Returns None if the bytes have run out.  `n` must not be zero.
+    pub fn choose(&mut self, n: uint) -> Option<uint> {
+        assert!(n > 0);
+        let mut x = 0u;
+        let nbytes = if n > 256 { 2 } else { 1 };
+        for _ in range(0, nbytes) {
+            if self.is_empty() {
+                return None;
+            }
+            x = (x << 8) | self.data[self.pos] as uint;
+            self.pos += 1;
+        }
+        Some(x % n)
+    }
+}
+
+/// Returns the tokens that `parser` can accept in its current state, including 0 (the end of
+/// input) if it can accept the input here.  The `error` token is not included.
+pub fn acceptable_tokens<SymbolValue: Show, AppContext>(parser: &ParserState<SymbolValue, AppContext>) -> Vec<u32> {
+    let names = parser.tables.yyname;
+    range(0, names.len() as u32)
+        .filter(|&t| (t == 0 || (names[t as uint].len() > 0 && names[t as uint] != "error")) && parser.accepts(t))
+        .collect()
+}
+
+/// Parses the tokens that `data` chooses (see the top of this module), with `make_value` giving
+/// the value of each token, and returns the result.  The parser is reset first.  This is meant
+/// to be the body of a fuzz target:
+///
+/// ```ignore
+///     fuzz_target!(|data: &[u8]| {
+///         let mut parser = ParserState::with_config(get_parser_tables(), config);
+///         racc_runtime::fuzz::fuzz_parse(&mut parser, &mut Ctx::new(), data, DEFAULT_MAX_TOKENS,
+///             |&mut: token| sample_value(token));
+///     });
+/// ```
+///
+/// A panic in an action, or in the parser, is what the fuzzer finds.  Syntax errors and failed
+/// actions are not failures; they are returned.
+pub fn fuzz_parse<SymbolValue: Show, AppContext, F>(parser: &mut ParserState<SymbolValue, AppContext>, ctx: &mut AppContext, data: &[u8], max_tokens: uint, mut make_value: F) -> FinishParseResult<SymbolValue>
+    where F: FnMut(u32) -> SymbolValue
+{
+    parser.reset();
+    let mut input = FuzzInput::new(data);
+    let ntokens = parser.tables.yyname.len();
+    for _ in range(0, max_tokens) {
+        if parser.failed {
+            break;
+        }
+        let candidates = acceptable_tokens(parser);
+        // The last choice pushes any token, valid here or not.
+        let token = match input.choose(candidates.len() + 1) {
+            Some(i) if i < candidates.len() => candidates[i],
+            Some(_) => match input.choose(ntokens) {
+                Some(t) => t as u32,
+                None => break
+            },
+            None => break
+        };
+        if token == 0 {
+            break;
+        }
+        let value = make_value(token);
+        match parser.push_token(ctx, token, value) {
+            PushTokenResult::Ok | PushTokenResult::SyntaxError | PushTokenResult::StackOverflow => {}
+            PushTokenResult::ActionFailed => break
+        }
+    }
+    parser.finish(ctx)
+}
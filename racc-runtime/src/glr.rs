@@ -0,0 +1,555 @@
+// A generalized LR (GLR) parser engine, for grammars with conflicts.
+//
+// ParserState follows the parser actions that remain after conflict resolution, so it always
+// takes one path through the automaton.  GlrParser also follows the actions that conflict
+// resolution discarded (the YYCONFLICTS table), so it finds every parse of the input.  When
+// the tables offer more than one action, the stack forks; stacks which reach the same state
+// after the same token are joined again.  The stacks are kept in a graph-structured stack
+// (GSS): each node is a state, and each link from a node to an earlier node carries the value
+// of the symbol between them.
+//
+// When two different reductions produce the same non-terminal over the same span of input (the
+// same pair of nodes), the input is ambiguous.  The two values are combined by the merge
+// function of the parser, which can pick one of them, combine them, or report the ambiguity.
+//
+// Reductions are scheduled so that each path through the GSS is reduced exactly once.  Every
+// link has a sequence number.  When a node is created, its reductions are applied to the paths
+// which use only links that existed at that time; when a link is added to an existing node,
+// the reductions of the current nodes are applied to the paths on which that link is the
+// newest one.
+//
+// A program can also prune the forks as they arise: the conflict choice of the parser, if it has
+// one, is called with the actions of each state and token which has more than one, and may pick
+// the only one to follow.  This settles conflicts by rules the grammar cannot state, such as
+// which syntax the version of the language being parsed allows.  Since the parser asks for the
+// actions of a state and token more than once, the choice must give the same answer each time.
+//
+// Values are cloned, because a value may be used by more than one stack.  A merged value is
+// used by the reductions that follow the merge; reductions that were already applied to the
+// first value are not repeated.
+//
+// A token which no stack can shift is discarded, and the GSS is returned to what it was before
+// the token was pushed.  The reductions for the token only add to the GSS: they create nodes
+// (after all of the existing ones), add links to the heads, and merge values into links of the
+// heads.  So push_token_at records the number of nodes and the number of links of each head
+// before reducing, and reduce_all records the value of each link before it is merged; undoing
+// the reductions puts the values back, and truncates the links and the nodes.
+
+#[cfg(not(feature = "std"))]
+use std::prelude::*;
+use std::fmt::Show;
+use std::mem;
+
+use {ParserTables, PushTokenResult, FinishParseResult, TableAction, Location, decode_goto};
+
+// A node of the GSS.
+struct Node<SymbolValue> {
+    state: uint,
+    links: Vec<Link<SymbolValue>>,
+}
+
+// A link from a node to an earlier node.
+struct Link<SymbolValue> {
+    pred: uint,                 // index of the earlier node, in GlrParser.nodes
+    value: SymbolValue,         // value of the symbol that was shifted or reduced
+    location: Location,         // location of that symbol
+    seq: uint,                  // sequence number; see above
+}
+
+// A reduction that has been scheduled, but not yet applied.
+struct Task {
+    node: uint,
+    rule: uint,
+    limit: uint,                // only links with seq < limit may be used, except via
+    via: Option<uint>,          // if present, the path must use the link with this seq
+}
+
+/// A GLR parser for the tables generated by `grammar!`.  It accepts the same tokens as a
+/// `ParserState`, but it explores every action of a state which has conflicts, rather than the
+/// one that conflict resolution chose.
+///
+/// ```ignore
+///     fn merge(ctx: &mut Context, lhs: uint, first: Value, second: Value) -> Value {
+///         ...
+///     }
+///
+///     let mut parser = GlrParser::with_merge(get_parser_tables(), merge);
+/// ```
+pub struct GlrParser<SymbolValue:Show+Clone, AppContext> {
+    tables: ParserTables<SymbolValue, AppContext>,
+    merge: fn(ctx: &mut AppContext, lhs: uint, first: SymbolValue, second: SymbolValue) -> SymbolValue,
+    choose: Option<fn(ctx: &mut AppContext, conflict: &GlrConflict) -> Option<TableAction>>,
+    nodes: Vec<Node<SymbolValue>>,
+    heads: Vec<uint>,           // the tops of the stacks
+    next_seq: uint,
+}
+
+/// A state which has more than one action on a token, as a `GlrParser` meets it.  See
+/// `GlrParser::set_conflict_choice`.
+pub struct GlrConflict<'a> {
+    pub state: uint,
+    pub token: u32,
+    /// The actions; the first is the one that conflict resolution chose.
+    pub actions: &'a [TableAction],
+    yyrules: &'static [&'static str],
+    yylhs: &'static [u16],
+    yyvarname: &'static [&'static str],
+}
+
+impl<'a> GlrConflict<'a> {
+    /// Returns the shift action, if there is one.
+    pub fn shift(&self) -> Option<TableAction> {
+        self.actions.iter().find(|a| match **a { TableAction::Shift(_) => true, _ => false }).map(|&a| a)
+    }
+
+    /// Returns the first action which reduces by a rule of the non-terminal `name`, if any.
+    pub fn reduction_to(&self, name: &str) -> Option<TableAction> {
+        self.actions.iter().find(|&&a| self.lhs_name(a) == Some(name)).map(|&a| a)
+    }
+
+    /// Returns the name of the non-terminal that `action` reduces to, or None if it is not a
+    /// reduction.
+    pub fn lhs_name(&self, action: TableAction) -> Option<&'static str> {
+        match action {
+            TableAction::Reduce(rule) => Some(self.yyvarname[self.yylhs[rule] as uint]),
+            _ => None
+        }
+    }
+
+    /// Returns the text of the rule that `action` reduces by, as in `YYRULES`, or None if it is
+    /// not a reduction.
+    pub fn rule_text(&self, action: TableAction) -> Option<&'static str> {
+        match action {
+            TableAction::Reduce(rule) => Some(self.yyrules[rule]),
+            _ => None
+        }
+    }
+}
+
+// The initial state for all parsers.
+const INITIAL_STATE: uint = 0;
+
+// The default merge function keeps the first value.
+fn keep_first<SymbolValue, AppContext>(_: &mut AppContext, _: uint, first: SymbolValue, _: SymbolValue) -> SymbolValue {
+    first
+}
+
+impl<SymbolValue:Show+Clone, AppContext> GlrParser<SymbolValue, AppContext> {
+    /// Creates a GLR parser for `tables`.  When the input is ambiguous, the value of the first
+    /// parse that is found is kept.
+    pub fn new(tables: ParserTables<SymbolValue, AppContext>) -> GlrParser<SymbolValue, AppContext> {
+        GlrParser::with_merge(tables, keep_first)
+    }
+
+    /// Creates a GLR parser for `tables`.  When the input is ambiguous, `merge` is called with
+    /// the two values of the ambiguous non-terminal (`lhs` is its index in `YYVARNAME`), and
+    /// returns the value to use.
+    pub fn with_merge(tables: ParserTables<SymbolValue, AppContext>,
+                      merge: fn(ctx: &mut AppContext, lhs: uint, first: SymbolValue, second: SymbolValue) -> SymbolValue)
+                      -> GlrParser<SymbolValue, AppContext> {
+        GlrParser {
+            tables: tables,
+            merge: merge,
+            choose: None,
+            nodes: vec![Node { state: INITIAL_STATE, links: Vec::new() }],
+            heads: vec![0],
+            next_seq: 0,
+        }
+    }
+
+    /// Sets the conflict choice of the parser.  Whenever a state has more than one action on a
+    /// token, `choose` is called with them, and returns the only one to follow, or None to
+    /// follow them all (as the parser does without a choice).  It decides by the context, which
+    /// can hold anything that the choice depends on, such as the version of the language:
+    ///
+    /// ```ignore
+    ///     fn choose(ctx: &mut Context, conflict: &GlrConflict) -> Option<TableAction> {
+    ///         if ctx.version < 3 { conflict.reduction_to("OldCall") } else { None }
+    ///     }
+    ///
+    ///     parser.set_conflict_choice(choose);
+    /// ```
+    ///
+    /// `choose` may be called more than once for the same state and token, and must return the
+    /// same action each time.  An action which is not one of those offered is ignored.
+    pub fn set_conflict_choice(&mut self, choose: fn(ctx: &mut AppContext, conflict: &GlrConflict) -> Option<TableAction>) {
+        self.choose = Some(choose);
+    }
+
+    /// Resets the parser to its initial state.
+    pub fn reset(&mut self) {
+        self.nodes.clear();
+        self.nodes.push(Node { state: INITIAL_STATE, links: Vec::new() });
+        self.heads.clear();
+        self.heads.push(0);
+        self.next_seq = 0;
+    }
+
+    /// Returns the number of stacks that the parser is following.
+    pub fn stack_count(&self) -> uint {
+        self.heads.len()
+    }
+
+    /// Advances the parser with a new token.  If no stack can shift the token, then the token is
+    /// discarded, and the parser stays in the state that it was in before the token was pushed.
+    pub fn push_token(&mut self, ctx: &mut AppContext, token: u32, lval: SymbolValue) -> PushTokenResult {
+        self.push_token_at(ctx, token, lval, Location::default())
+    }
+
+    /// Advances the parser with a new token, exactly as `push_token` does, and records the
+    /// location of the token.  See `ParserState::push_token_at`.
+    pub fn push_token_at(&mut self, ctx: &mut AppContext, token: u32, lval: SymbolValue, location: Location) -> PushTokenResult {
+        debug!("glr: reading {} ({}), {} stacks", token, self.tables.yyname[token as uint], self.heads.len());
+        let saved_heads = self.heads.clone();
+        let saved_nodes = self.nodes.len();
+        let saved_links: Vec<uint> = saved_heads.iter().map(|&n| self.nodes[n].links.len()).collect();
+        let mut merged: Vec<(uint, uint, SymbolValue)> = Vec::new();
+        self.reduce_all(ctx, token, &mut merged);
+
+        let mut new_heads: Vec<uint> = Vec::new();
+        for i in range(0, self.heads.len()) {
+            let head = self.heads[i];
+            for action in self.actions(ctx, self.nodes[head].state, token).into_iter() {
+                if let TableAction::Shift(next_state) = action {
+                    let seq = self.next_seq;
+                    self.next_seq += 1;
+                    let link = Link { pred: head, value: lval.clone(), location: location, seq: seq };
+                    let existing = new_heads.iter().position(|&n| self.nodes[n].state == next_state);
+                    match existing {
+                        Some(k) => self.nodes[new_heads[k]].links.push(link),
+                        None => {
+                            new_heads.push(self.nodes.len());
+                            self.nodes.push(Node { state: next_state, links: vec![link] });
+                        }
+                    }
+                }
+            }
+        }
+
+        if new_heads.len() == 0 {
+            debug!("glr: syntax error at token {}", token);
+            // Undo the reductions, latest first.
+            for (node, link, value) in merged.into_iter().rev() {
+                self.nodes[node].links[link].value = value;
+            }
+            for (&head, &links) in saved_heads.iter().zip(saved_links.iter()) {
+                self.nodes[head].links.truncate(links);
+            }
+            self.nodes.truncate(saved_nodes);
+            self.heads = saved_heads;
+            return PushTokenResult::SyntaxError;
+        }
+        self.heads = new_heads;
+        PushTokenResult::Ok
+    }
+
+    /// Ends the input, and checks whether the grammar accepts it.  If more than one stack
+    /// accepts the input, their values are merged.
+    pub fn finish(&mut self, ctx: &mut AppContext) -> FinishParseResult<SymbolValue> {
+        self.reduce_all(ctx, 0, &mut Vec::new());
+
+        let mut result: Option<SymbolValue> = None;
+        for i in range(0, self.heads.len()) {
+            let head = self.heads[i];
+            if self.nodes[head].state != self.tables.yyfinal {
+                continue;
+            }
+            for link in self.nodes[head].links.iter() {
+                let value = link.value.clone();
+                result = Some(match result {
+                    Some(first) => (self.merge)(ctx, 0, first, value),
+                    None => value
+                });
+            }
+        }
+
+        match result {
+            Some(value) => {
+                debug!("glr: accept");
+                FinishParseResult::Accepted(value)
+            }
+            None => {
+                debug!("glr: syntax error at end of input");
+                FinishParseResult::SyntaxError
+            }
+        }
+    }
+
+    // Returns all of the actions for (state, token): the one in the packed tables, and the ones
+    // that lost conflicts; or only the one that the conflict choice picks from them.
+    fn actions(&self, ctx: &mut AppContext, state: uint, token: u32) -> Vec<TableAction> {
+        let mut actions = vec![self.tables.action(state, token)];
+        let conflicts = self.tables.yyconflicts;
+        let mut i = 0;
+        while i + 2 < conflicts.len() {
+            if conflicts[i] as uint == state && conflicts[i + 1] as u32 == token {
+                let action = conflicts[i + 2] as i32;
+                actions.push(if action > 0 {
+                    TableAction::Shift(action as uint)
+                } else {
+                    TableAction::Reduce((-action) as uint)
+                });
+            }
+            i += 3;
+        }
+
+        if let Some(choose) = self.choose {
+            if actions.len() > 1 {
+                let chosen = {
+                    let conflict = GlrConflict {
+                        state: state,
+                        token: token,
+                        actions: actions.as_slice(),
+                        yyrules: self.tables.yyrules,
+                        yylhs: self.tables.yylhs,
+                        yyvarname: self.tables.yyvarname,
+                    };
+                    choose(ctx, &conflict)
+                };
+                if let Some(action) = chosen {
+                    if actions.contains(&action) {
+                        debug!("glr: state {}, token {}: the conflict choice follows only {}", state, token, action);
+                        return vec![action];
+                    }
+                }
+            }
+        }
+        actions
+    }
+
+    // Schedules the reductions of a node, over the paths which use only existing links.
+    fn schedule_node(&self, ctx: &mut AppContext, node: uint, token: u32, tasks: &mut Vec<Task>) {
+        for action in self.actions(ctx, self.nodes[node].state, token).into_iter() {
+            if let TableAction::Reduce(rule) = action {
+                tasks.push(Task { node: node, rule: rule, limit: self.next_seq, via: None });
+            }
+        }
+    }
+
+    // Applies all of the reductions that are possible with the lookahead token.  Records the
+    // (node, link, value) of each link before a value is merged into it, in `merged`.
+    fn reduce_all(&mut self, ctx: &mut AppContext, token: u32, merged: &mut Vec<(uint, uint, SymbolValue)>) {
+        let mut tasks: Vec<Task> = Vec::new();
+        for i in range(0, self.heads.len()) {
+            self.schedule_node(ctx, self.heads[i], token, &mut tasks);
+        }
+
+        loop {
+            let task = match tasks.pop() {
+                Some(task) => task,
+                None => break
+            };
+            let len = self.tables.yylen[task.rule] as uint;
+            let lhs = self.tables.yylhs[task.rule] as uint;
+
+            let mut paths: Vec<(uint, Vec<SymbolValue>, Vec<Location>)> = Vec::new();
+            self.find_paths(task.node, len, task.limit, task.via, task.via.is_none(), &mut Vec::new(), &mut Vec::new(), &mut paths);
+
+            for (end, mut values, mut locations) in paths.into_iter() {
+                debug!("glr: state {} reducing by rule {}", self.nodes[task.node].state, self.tables.yyrules[task.rule]);
+                values.reverse();
+                locations.reverse();
+                // The location of a node is that of the symbol before it on any of its links.
+                let location = Location::of_rhs(locations.as_slice(), self.nodes[end].links.get(0).map(|l| &l.location));
+                // A fallible action which fails rules out its interpretation of the input, as if
+                // the stack had a syntax error.
+                let value = match (self.tables.reduce)(&mut values, locations.as_slice(), location, task.rule, ctx) {
+                    Ok(value) => value,
+                    Err(_) => {
+                        debug!("glr: the action of rule {} failed", self.tables.yyrules[task.rule]);
+                        continue;
+                    }
+                };
+                let goto = decode_goto(self.tables.yygindex, self.tables.yytable, self.tables.yycheck,
+                    self.tables.yydgoto, self.tables.yyfinal, self.nodes[end].state, lhs);
+
+                let existing = self.heads.iter().position(|&n| self.nodes[n].state == goto);
+                match existing {
+                    Some(k) => {
+                        let target = self.heads[k];
+                        let existing_link = self.nodes[target].links.iter().position(|l| l.pred == end);
+                        match existing_link {
+                            Some(j) => {
+                                // The same symbol, over the same span: the input is ambiguous.
+                                debug!("glr: ambiguity in non-terminal {}", lhs);
+                                let first = mem::replace(&mut self.nodes[target].links[j].value, value.clone());
+                                merged.push((target, j, first.clone()));
+                                let value = (self.merge)(ctx, lhs, first, value);
+                                self.nodes[target].links[j].value = value;
+                            }
+                            None => {
+                                let seq = self.next_seq;
+                                self.next_seq += 1;
+                                self.nodes[target].links.push(Link { pred: end, value: value, location: location, seq: seq });
+                                for i in range(0, self.heads.len()) {
+                                    let head = self.heads[i];
+                                    for action in self.actions(ctx, self.nodes[head].state, token).into_iter() {
+                                        if let TableAction::Reduce(rule) = action {
+                                            if self.tables.yylen[rule] != 0 {
+                                                tasks.push(Task { node: head, rule: rule, limit: seq, via: Some(seq) });
+                                            }
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                    None => {
+                        let seq = self.next_seq;
+                        self.next_seq += 1;
+                        let node = self.nodes.len();
+                        self.nodes.push(Node { state: goto, links: vec![Link { pred: end, value: value, location: location, seq: seq }] });
+                        self.heads.push(node);
+                        self.schedule_node(ctx, node, token, &mut tasks);
+                    }
+                }
+            }
+        }
+    }
+
+    // Finds the paths of length len from node, and collects the end node of each, and the values
+    // and locations along it (from right to left).  found_via is true if the path has used the via
+    // link.
+    fn find_paths(&self, node: uint, len: uint, limit: uint, via: Option<uint>, found_via: bool,
+                  values: &mut Vec<SymbolValue>, locations: &mut Vec<Location>,
+                  paths: &mut Vec<(uint, Vec<SymbolValue>, Vec<Location>)>) {
+        if len == 0 {
+            if found_via {
+                paths.push((node, values.clone(), locations.clone()));
+            }
+            return;
+        }
+        for link in self.nodes[node].links.iter() {
+            let is_via = via == Some(link.seq);
+            if link.seq >= limit && !is_via {
+                continue;
+            }
+            values.push(link.value.clone());
+            locations.push(link.location);
+            self.find_paths(link.pred, len - 1, limit, via, found_via || is_via, values, locations, paths);
+            values.pop();
+            locations.pop();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {PushTokenResult, FinishParseResult, TableAction};
+    use testing::ambig;
+    use testing::ambig::{NUM, PLUS};
+    use super::{GlrParser, GlrConflict};
+
+    // Joins the values of an ambiguous expression, so that the test can see both.
+    fn join(_: &mut (), _: uint, first: String, second: String) -> String {
+        format!("{}|{}", first, second)
+    }
+
+    fn shift_first(_: &mut (), conflict: &GlrConflict) -> Option<TableAction> {
+        conflict.shift()
+    }
+
+    fn reduce_first(_: &mut (), conflict: &GlrConflict) -> Option<TableAction> {
+        conflict.reduction_to("e")
+    }
+
+    // Pushes "1+2+...+n".
+    fn push_sum(parser: &mut GlrParser<String, ()>, n: uint) {
+        for i in range(1, n + 1) {
+            if i > 1 {
+                assert!(accepts(parser.push_token(&mut (), PLUS, "+".to_string())));
+            }
+            assert!(accepts(parser.push_token(&mut (), NUM, i.to_string())));
+        }
+    }
+
+    fn accepts(result: PushTokenResult) -> bool {
+        match result {
+            PushTokenResult::Ok => true,
+            _ => false
+        }
+    }
+
+    // Returns the parses that the parser accepted, sorted.
+    fn parses(parser: &mut GlrParser<String, ()>) -> Vec<String> {
+        match parser.finish(&mut ()) {
+            FinishParseResult::Accepted(value) => {
+                let mut parses: Vec<String> = value.as_slice().split('|').map(|s| s.to_string()).collect();
+                parses.sort();
+                parses
+            }
+            _ => panic!("the input should be accepted")
+        }
+    }
+
+    #[test]
+    fn unambiguous() {
+        let mut parser = GlrParser::with_merge(ambig::tables(), join);
+        push_sum(&mut parser, 2);
+        assert_eq!(parses(&mut parser), vec!["(1+2)".to_string()]);
+    }
+
+    #[test]
+    fn merges_ambiguous_parses() {
+        let mut parser = GlrParser::with_merge(ambig::tables(), join);
+        push_sum(&mut parser, 3);
+        assert_eq!(parses(&mut parser), vec!["((1+2)+3)".to_string(), "(1+(2+3))".to_string()]);
+    }
+
+    #[test]
+    fn keeps_the_first_parse() {
+        let mut parser = GlrParser::new(ambig::tables());
+        push_sum(&mut parser, 3);
+        let value = parses(&mut parser);
+        assert!(value == vec!["((1+2)+3)".to_string()] || value == vec!["(1+(2+3))".to_string()]);
+    }
+
+    #[test]
+    fn joins_stacks() {
+        // After "1+2+", both stacks have shifted the second PLUS into the same state.
+        let mut parser = GlrParser::with_merge(ambig::tables(), join);
+        push_sum(&mut parser, 2);
+        assert!(accepts(parser.push_token(&mut (), PLUS, "+".to_string())));
+        assert_eq!(parser.stack_count(), 1);
+    }
+
+    #[test]
+    fn conflict_choice() {
+        let mut parser = GlrParser::with_merge(ambig::tables(), join);
+        parser.set_conflict_choice(shift_first);
+        push_sum(&mut parser, 3);
+        assert_eq!(parses(&mut parser), vec!["(1+(2+3))".to_string()]);
+
+        let mut parser = GlrParser::with_merge(ambig::tables(), join);
+        parser.set_conflict_choice(reduce_first);
+        push_sum(&mut parser, 3);
+        assert_eq!(parses(&mut parser), vec!["((1+2)+3)".to_string()]);
+    }
+
+    #[test]
+    fn undoes_a_token_that_no_stack_shifts() {
+        let mut parser = GlrParser::with_merge(ambig::tables(), join);
+        push_sum(&mut parser, 2);
+        assert!(!accepts(parser.push_token(&mut (), NUM, "9".to_string())));
+        assert!(accepts(parser.push_token(&mut (), PLUS, "+".to_string())));
+        assert!(accepts(parser.push_token(&mut (), NUM, "3".to_string())));
+        assert_eq!(parses(&mut parser), vec!["((1+2)+3)".to_string(), "(1+(2+3))".to_string()]);
+
+        // The reductions that the token caused are undone, so finish still finds both parses.
+        let mut parser = GlrParser::with_merge(ambig::tables(), join);
+        push_sum(&mut parser, 3);
+        assert!(!accepts(parser.push_token(&mut (), NUM, "9".to_string())));
+        assert_eq!(parses(&mut parser), vec!["((1+2)+3)".to_string(), "(1+(2+3))".to_string()]);
+    }
+
+    #[test]
+    fn rejects() {
+        let mut parser = GlrParser::with_merge(ambig::tables(), join);
+        push_sum(&mut parser, 1);
+        assert!(accepts(parser.push_token(&mut (), PLUS, "+".to_string())));
+        match parser.finish(&mut ()) {
+            FinishParseResult::SyntaxError => {}
+            _ => panic!("the input should be rejected")
+        }
+    }
+}
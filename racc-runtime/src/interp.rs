@@ -0,0 +1,359 @@
+// A table-driven parser engine, for tables which are loaded at run time.
+//
+// ParserState runs the tables that grammar! compiles into a program, and calls the generated
+// reduce() function.  Interpreter runs tables that are held in ordinary vectors (for example,
+// tables that were read from a file), and calls actions which the program registers for each
+// rule.  This allows a long-running program to replace its grammar without being rebuilt.
+//
+// The tables have the same layout as the generated tables; see ParserTables.  They can be read
+// from the binary form that racc_core writes, with LoadedTables::from_binary.
+
+use std::collections::HashMap;
+
+use {PushTokenResult, FinishParseResult, TableAction};
+use super::{decode_action, decode_goto};
+
+/// A complete set of parsing tables, owned rather than static.  The fields have the same meaning
+/// as the fields of `ParserTables`.
+#[deriving(Clone)]
+pub struct LoadedTables {
+    pub yyrindex: Vec<u32>,
+    pub yysindex: Vec<u32>,
+    pub yygindex: Vec<u32>,
+    pub yytable: Vec<u32>,
+    pub yydgoto: Vec<u32>,
+    pub yydefred: Vec<u32>,
+    pub yylhs: Vec<u16>,
+    pub yylen: Vec<u16>,
+    pub yycheck: Vec<u32>,
+    pub yyname: Vec<String>,
+    pub yyfinal: uint,
+    pub yyrules: Vec<String>,
+    pub yyvarname: Vec<String>,
+    pub yyhash: u64,
+}
+
+impl LoadedTables {
+    /// Returns the action that the parser takes when it is in `state`, and the next token is
+    /// `token`.  See `ParserTables::action`.
+    pub fn action(&self, state: uint, token: u32) -> TableAction {
+        decode_action(self.yysindex.as_slice(), self.yyrindex.as_slice(), self.yytable.as_slice(), self.yycheck.as_slice(),
+            self.yydefred.as_slice(), self.yyfinal, state, token as uint)
+    }
+
+    /// Returns the number of rules, which is the number of actions that an interpreter needs.
+    pub fn nrules(&self) -> uint {
+        self.yylen.len()
+    }
+
+    /// Returns the value of the token with the given name, if there is one.
+    pub fn token_value(&self, name: &str) -> Option<u32> {
+        self.yyname.iter().position(|n| n.as_slice() == name).map(|i| i as u32)
+    }
+}
+
+/// An error found while reading tables with `LoadedTables::from_binary`.
+#[deriving(Clone,PartialEq,Show)]
+pub enum LoadError {
+    /// The data does not start with the magic bytes, so it is not a set of tables.
+    NotTables,
+    /// The tables were written in a version of the binary form that this runtime cannot read.
+    UnsupportedVersion(u16),
+    /// The data ends early, or has bytes left over.
+    BadLength,
+    /// The checksum does not match the data, so the data has been damaged.
+    BadChecksum,
+    /// A string is not valid UTF-8.
+    BadString,
+    /// The tables are for a different grammar than the one expected.  Carries the hash of the
+    /// grammar of the tables.
+    WrongGrammar(u64),
+}
+
+// The magic bytes and version of the binary form.  These must match racc_core::tables, which
+// writes it.
+const BINARY_MAGIC: &'static [u8] = b"RACC";
+const BINARY_VERSION: u16 = 2;
+
+// Reads the binary form of the tables.
+struct BinaryReader<'a> {
+    data: &'a [u8],
+    pos: uint,
+}
+
+impl<'a> BinaryReader<'a> {
+    fn bytes(&mut self, n: uint) -> Result<&'a [u8], LoadError> {
+        if self.pos + n > self.data.len() {
+            return Err(LoadError::BadLength);
+        }
+        let bytes = self.data.slice(self.pos, self.pos + n);
+        self.pos += n;
+        Ok(bytes)
+    }
+
+    fn uint(&mut self, n: uint) -> Result<u64, LoadError> {
+        let bytes = try!(self.bytes(n));
+        let mut value: u64 = 0;
+        for (i, &b) in bytes.iter().enumerate() {
+            value = value | ((b as u64) << (i * 8));
+        }
+        Ok(value)
+    }
+
+    // Reads a table of 32-bit entries.
+    fn table(&mut self) -> Result<Vec<u32>, LoadError> {
+        let len = try!(self.uint(4)) as uint;
+        let mut table: Vec<u32> = Vec::with_capacity(len);
+        for _ in range(0, len) {
+            table.push(try!(self.uint(4)) as u32);
+        }
+        Ok(table)
+    }
+
+    // Reads a table of 16-bit entries.
+    fn table16(&mut self) -> Result<Vec<u16>, LoadError> {
+        let len = try!(self.uint(4)) as uint;
+        let mut table: Vec<u16> = Vec::with_capacity(len);
+        for _ in range(0, len) {
+            table.push(try!(self.uint(2)) as u16);
+        }
+        Ok(table)
+    }
+
+    fn strings(&mut self) -> Result<Vec<String>, LoadError> {
+        let len = try!(self.uint(4)) as uint;
+        let mut strings: Vec<String> = Vec::with_capacity(len);
+        for _ in range(0, len) {
+            let n = try!(self.uint(4)) as uint;
+            let bytes = try!(self.bytes(n));
+            match String::from_utf8(bytes.to_vec()) {
+                Ok(s) => strings.push(s),
+                Err(_) => return Err(LoadError::BadString)
+            }
+        }
+        Ok(strings)
+    }
+}
+
+impl LoadedTables {
+    /// Reads tables in the binary form that `racc_core::tables::PackedTables::to_binary` writes.
+    /// If `expected_hash` is given, the tables must be for the grammar with that hash (see
+    /// `GrammarInfo::hash`); this catches tables that are out of date.
+    pub fn from_binary(data: &[u8], expected_hash: Option<u64>) -> Result<LoadedTables, LoadError> {
+        if data.len() < BINARY_MAGIC.len() || data.slice_to(BINARY_MAGIC.len()) != BINARY_MAGIC {
+            return Err(LoadError::NotTables);
+        }
+        if data.len() < BINARY_MAGIC.len() + 2 + 8 {
+            return Err(LoadError::BadLength);
+        }
+
+        let body = data.slice_to(data.len() - 8);
+        let mut r = BinaryReader { data: data.slice_from(body.len()), pos: 0 };
+        let checksum = try!(r.uint(8));
+
+        let mut r = BinaryReader { data: body, pos: BINARY_MAGIC.len() };
+        let version = try!(r.uint(2)) as u16;
+        if version != BINARY_VERSION {
+            return Err(LoadError::UnsupportedVersion(version));
+        }
+        if fnv1a(body) != checksum {
+            return Err(LoadError::BadChecksum);
+        }
+        let yyhash = try!(r.uint(8));
+        if let Some(expected) = expected_hash {
+            if yyhash != expected {
+                return Err(LoadError::WrongGrammar(yyhash));
+            }
+        }
+        let yyfinal = try!(r.uint(4)) as uint;
+
+        let yydefred = try!(r.table());
+        let yydgoto = try!(r.table());
+        let yysindex = try!(r.table());
+        let yyrindex = try!(r.table());
+        let yygindex = try!(r.table());
+        let yytable = try!(r.table());
+        let yycheck = try!(r.table());
+        let yylhs = try!(r.table16());
+        let yylen = try!(r.table16());
+        let yyname = try!(r.strings());
+        let yyrules = try!(r.strings());
+        let yyvarname = try!(r.strings());
+        if r.pos != body.len() {
+            return Err(LoadError::BadLength);
+        }
+
+        Ok(LoadedTables {
+            yyrindex: yyrindex,
+            yysindex: yysindex,
+            yygindex: yygindex,
+            yytable: yytable,
+            yydgoto: yydgoto,
+            yydefred: yydefred,
+            yylhs: yylhs,
+            yylen: yylen,
+            yycheck: yycheck,
+            yyname: yyname,
+            yyfinal: yyfinal,
+            yyrules: yyrules,
+            yyvarname: yyvarname,
+            yyhash: yyhash,
+        })
+    }
+}
+
+// 64-bit FNV-1a, as used for the checksum of the binary form.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for &b in bytes.iter() {
+        hash = hash ^ (b as u64);
+        hash = hash * 0x100000001b3;
+    }
+    hash
+}
+
+/// The action of a rule, for an `Interpreter`.  It receives the values of the right-hand side
+/// of the rule, in order, and returns the value of the rule.
+pub trait RuleAction<SymbolValue, AppContext> {
+    fn reduce(&mut self, ctx: &mut AppContext, values: Vec<SymbolValue>) -> SymbolValue;
+}
+
+/// Any closure which takes the context and the values of the right-hand side can be used as an
+/// action.
+impl<SymbolValue, AppContext, F> RuleAction<SymbolValue, AppContext> for F
+    where F: FnMut(&mut AppContext, Vec<SymbolValue>) -> SymbolValue {
+    fn reduce(&mut self, ctx: &mut AppContext, values: Vec<SymbolValue>) -> SymbolValue {
+        (*self)(ctx, values)
+    }
+}
+
+/// A parser which runs `LoadedTables`.  Actions are registered for each rule with `set_action`;
+/// rules which have no registered action use the default action, which is given to `new`.
+pub struct Interpreter<SymbolValue, AppContext> {
+    tables: LoadedTables,
+    actions: HashMap<uint, Box<RuleAction<SymbolValue, AppContext> + 'static>>,
+    default_action: Box<RuleAction<SymbolValue, AppContext> + 'static>,
+    yystate: uint,
+    value_stack: Vec<SymbolValue>,
+    state_stack: Vec<uint>,
+}
+
+// The initial state for all parsers.
+const INITIAL_STATE: uint = 0;
+
+impl<SymbolValue, AppContext> Interpreter<SymbolValue, AppContext> {
+    /// Creates a parser for `tables`.  `default_action` is used for every rule which has no
+    /// action of its own.
+    pub fn new(tables: LoadedTables, default_action: Box<RuleAction<SymbolValue, AppContext> + 'static>) -> Interpreter<SymbolValue, AppContext> {
+        Interpreter {
+            tables: tables,
+            actions: HashMap::new(),
+            default_action: default_action,
+            yystate: INITIAL_STATE,
+            value_stack: Vec::new(),
+            state_stack: vec![INITIAL_STATE],
+        }
+    }
+
+    /// Returns the tables that this parser runs.
+    pub fn tables(&self) -> &LoadedTables {
+        &self.tables
+    }
+
+    /// Sets the action for a rule.  Rules are numbered as in `LoadedTables.yyrules`.
+    pub fn set_action(&mut self, rule: uint, action: Box<RuleAction<SymbolValue, AppContext> + 'static>) {
+        assert!(rule < self.tables.nrules());
+        self.actions.insert(rule, action);
+    }
+
+    /// Sets the action for the rule whose text (as in `LoadedTables.yyrules`) is `rule_text`.
+    /// Returns false if there is no such rule.
+    pub fn set_action_by_text(&mut self, rule_text: &str, action: Box<RuleAction<SymbolValue, AppContext> + 'static>) -> bool {
+        match self.tables.yyrules.iter().position(|r| r.as_slice() == rule_text) {
+            Some(rule) => {
+                self.set_action(rule, action);
+                true
+            }
+            None => false
+        }
+    }
+
+    /// Resets the parser to its initial state.  The registered actions are kept.
+    pub fn reset(&mut self) {
+        self.yystate = INITIAL_STATE;
+        self.value_stack.clear();
+        self.state_stack.clear();
+        self.state_stack.push(INITIAL_STATE);
+    }
+
+    /// Advances the state of the parser with a new token.  See `ParserState::push_token`.
+    pub fn push_token(&mut self, ctx: &mut AppContext, token: u32, lval: SymbolValue) -> PushTokenResult {
+        loop {
+            match self.tables.action(self.yystate, token) {
+                TableAction::Shift(next_state) => {
+                    debug!("state {}, shifting token {} to state {}", self.yystate, token, next_state);
+                    self.yystate = next_state;
+                    self.state_stack.push(next_state);
+                    self.value_stack.push(lval);
+                    return PushTokenResult::Ok;
+                }
+                TableAction::Reduce(rule) => {
+                    self.reduce(ctx, rule);
+                }
+                TableAction::Accept | TableAction::Error => {
+                    debug!("state {}, syntax error at token {}", self.yystate, token);
+                    return PushTokenResult::SyntaxError;
+                }
+            }
+        }
+    }
+
+    /// Ends the input, and checks whether the grammar accepts it.  See `ParserState::finish`.
+    pub fn finish(&mut self, ctx: &mut AppContext) -> FinishParseResult<SymbolValue> {
+        loop {
+            match self.tables.action(self.yystate, 0) {
+                TableAction::Reduce(rule) => {
+                    self.reduce(ctx, rule);
+                }
+                TableAction::Accept => {
+                    debug!("accept");
+                    return match self.value_stack.pop() {
+                        Some(value) => FinishParseResult::Accepted(value),
+                        None => FinishParseResult::SyntaxError
+                    };
+                }
+                TableAction::Shift(_) | TableAction::Error => {
+                    debug!("state {}, syntax error at end of input", self.yystate);
+                    return FinishParseResult::SyntaxError;
+                }
+            }
+        }
+    }
+
+    fn reduce(&mut self, ctx: &mut AppContext, rule: uint) {
+        let len = self.tables.yylen[rule] as uint;
+        let lhs = self.tables.yylhs[rule] as uint;
+        debug!("state {} reducing by rule {}", self.yystate, self.tables.yyrules[rule]);
+        assert!(self.value_stack.len() >= len);
+        assert!(self.state_stack.len() > len);
+
+        let first = self.value_stack.len() - len;
+        let values: Vec<SymbolValue> = range(0, len).map(|_| self.value_stack.remove(first).unwrap()).collect();
+        let value = match self.actions.get_mut(&rule) {
+            Some(action) => action.reduce(ctx, values),
+            None => self.default_action.reduce(ctx, values)
+        };
+        self.value_stack.push(value);
+
+        let new_len = self.state_stack.len() - len;
+        self.state_stack.truncate(new_len);
+        let top_state = self.state_stack[new_len - 1];
+
+        // The goto on the lhs, from the state that is now on top of the stack.
+        let next_state = decode_goto(self.tables.yygindex.as_slice(), self.tables.yytable.as_slice(), self.tables.yycheck.as_slice(),
+            self.tables.yydgoto.as_slice(), self.tables.yyfinal, top_state, lhs);
+        debug!("    goto state {}", next_state);
+        self.yystate = next_state;
+        self.state_stack.push(next_state);
+    }
+}
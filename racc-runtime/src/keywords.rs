@@ -0,0 +1,81 @@
+// Keyword tables, generated from "%keywords" declarations.
+//
+// A language's keywords usually look like identifiers, so the lexer scans them as identifiers,
+// and then looks the text up in a table.  That keeps the lexer's DFA small, which it would not
+// be if every keyword were a pattern of its own.  The table is sorted by text, and searched by
+// bisection.
+//
+// A reserved keyword is always a keyword.  A contextual keyword (such as "async" or "where" in
+// some languages) is a keyword only where the parser can accept it, and an identifier
+// everywhere else, so it must be classified with the parser at hand.
+
+#[cfg(not(feature = "std"))]
+use std::prelude::*;
+
+use std::fmt::Show;
+
+use ParserState;
+
+/// A keyword: its text, its token, and whether it is contextual.
+#[deriving(Copy,Clone,PartialEq,Show)]
+pub struct Keyword {
+    pub text: &'static str,
+    pub token: u32,
+    pub contextual: bool,
+}
+
+/// The keywords of a grammar, and the token (usually an identifier) whose text they are found
+/// in.  Grammars which declare `%keywords` generate one, named `KEYWORDS`.
+pub struct KeywordTable {
+    /// The token which keywords are scanned as.
+    pub ident: u32,
+    /// The keywords, sorted by text.
+    pub keywords: &'static [Keyword],
+}
+
+impl KeywordTable {
+    /// Returns the keyword whose text is `text`, if there is one.
+    pub fn find(&self, text: &str) -> Option<&'static Keyword> {
+        let keywords = self.keywords;
+        let (mut lo, mut hi) = (0u, keywords.len());
+        while lo < hi {
+            let mid = (lo + hi) / 2;
+            if keywords[mid].text < text {
+                lo = mid + 1;
+            }
+            else if keywords[mid].text > text {
+                hi = mid;
+            }
+            else {
+                return Some(&keywords[mid]);
+            }
+        }
+        None
+    }
+
+    /// Returns the token for a token `token` with text `text`: if it is the identifier token,
+    /// and the text is a reserved keyword, the keyword's token; otherwise `token`.  Contextual
+    /// keywords are left as identifiers.  The generated scanner does this for every token.
+    pub fn reserved(&self, token: u32, text: &str) -> u32 {
+        if token != self.ident {
+            return token;
+        }
+        match self.find(text) {
+            Some(k) if !k.contextual => k.token,
+            _ => token
+        }
+    }
+
+    /// Returns the token for a token `token` with text `text`, as `reserved` does, except that a
+    /// contextual keyword is also its keyword's token if `parser` can accept that token now (see
+    /// `ParserState::accepts`).
+    pub fn contextual<SymbolValue: Show, AppContext>(&self, token: u32, text: &str, parser: &ParserState<SymbolValue, AppContext>) -> u32 {
+        if token != self.ident {
+            return token;
+        }
+        match self.find(text) {
+            Some(k) if !k.contextual || parser.accepts(k.token) => k.token,
+            _ => token
+        }
+    }
+}
@@ -0,0 +1,192 @@
+// Turns indentation into tokens, for languages in which the layout of the lines is part of the
+// syntax, as in Python.
+//
+// A grammar for such a language uses tokens which no text matches: INDENT at the start of a
+// block, DEDENT at its end, and NEWLINE at the end of each logical line.  Layout stands between
+// the lexer and the parser and adds them.  The lexer skips white space and comments as usual
+// (including the line breaks), so blank lines and lines which hold only a comment give no tokens,
+// and do not count.  For each token which is the first on its line, Layout compares the column
+// of the token with the stack of open indentations, as Python does:
+//
+//      NEWLINE                 ends the line before, unless this is the first token
+//      INDENT                  if the column is greater than the top of the stack, which it
+//                              is pushed on
+//      DEDENT ...              one for each indentation greater than the column, which are
+//                              popped; the column must then equal the top of the stack
+//
+// At the end of the input, it ends the last line with NEWLINE, and closes the open blocks with
+// DEDENTs.  Between an opening bracket and its closing bracket, line breaks are not significant,
+// so an expression can span several lines.
+//
+// A dedent to a column which no enclosing block has (less indented than the block, but more than
+// the block around it) is an error.  Layout returns the error token for it, so that the parser
+// reports it as it reports any other unexpected text, and then treats the column as a new block.
+//
+// The tokens that Layout adds are empty, and placed where they belong in the text: NEWLINE at the
+// end of the last token of its line, and INDENT and DEDENT at the start of the token which
+// begins the line.  The tokens of the lexer keep their own locations, which must be byte offsets
+// into the text that Layout is given.
+
+#[cfg(not(feature = "std"))]
+use std::prelude::*;
+
+use Location;
+use ERROR_TOKEN;
+use lexer::Lexer;
+
+/// The tokens that `Layout` adds, and how it measures indentation.
+#[deriving(Copy,Clone)]
+pub struct LayoutConfig {
+    /// The token which opens a block, when a line is indented more than the line before.
+    pub indent: u32,
+    /// The token which closes a block, when a line is indented less.
+    pub dedent: u32,
+    /// The token which ends each logical line, or None for a grammar which separates its
+    /// statements in some other way.
+    pub newline: Option<u32>,
+    /// The width of a tab: a tab advances the column to the next multiple of it.  Python uses 8.
+    pub tab_width: uint,
+    /// Pairs of (opening, closing) tokens, such as parentheses, between which line breaks and
+    /// indentation are ignored.
+    pub brackets: &'static [(u32, u32)],
+}
+
+/// A lexer which adds INDENT, DEDENT and NEWLINE tokens to the tokens of another lexer, from the
+/// indentation of the lines of `input`.
+///
+/// ```ignore
+///     static BRACKETS: [(u32, u32), ..2] = [(LPAREN, RPAREN), (LBRACKET, RBRACKET)];
+///     let config = LayoutConfig { indent: INDENT, dedent: DEDENT, newline: Some(NEWLINE), tab_width: 8, brackets: &BRACKETS };
+///     let tokens = new_scanner(input).map(|(t, text, loc)| (t, value_of(t, text), loc));
+///     match parser.parse(&mut ctx, &mut Layout::new(input, tokens, config, empty_value)) { ... }
+/// ```
+pub struct Layout<'a, L, SymbolValue> {
+    input: &'a str,
+    lexer: L,
+    config: LayoutConfig,
+    value: fn(u32) -> SymbolValue,
+    indents: Vec<uint>,                 // the columns of the open blocks; the first is 0
+    open_brackets: uint,                // the number of brackets which are open
+    last_end: Option<uint>,             // the end of the last token of the lexer, if there was one
+    pending: Vec<(u32, SymbolValue, Location)>,     // tokens to return, in reverse order
+    done: bool,
+}
+
+impl<'a, L: Lexer<SymbolValue>, SymbolValue> Layout<'a, L, SymbolValue> {
+    /// Creates a layout over the tokens of `lexer`, whose locations are byte offsets into
+    /// `input`.  `value` gives the value of each token that the layout adds.
+    pub fn new(input: &'a str, lexer: L, config: LayoutConfig, value: fn(u32) -> SymbolValue) -> Layout<'a, L, SymbolValue> {
+        Layout {
+            input: input,
+            lexer: lexer,
+            config: config,
+            value: value,
+            indents: vec![0],
+            open_brackets: 0,
+            last_end: None,
+            pending: Vec::new(),
+            done: false,
+        }
+    }
+
+    /// The depth of indentation: the number of blocks which are open.
+    pub fn depth(&self) -> uint {
+        self.indents.len() - 1
+    }
+
+    // Returns the column of the byte offset `pos`, which is the start of a token.
+    fn column(&self, pos: uint) -> uint {
+        let line_start = match self.input.slice_to(pos).rfind('\n') {
+            Some(i) => i + 1,
+            None => 0
+        };
+        let mut column = 0;
+        for ch in self.input.slice(line_start, pos).chars() {
+            column = if ch == '\t' { (column / self.config.tab_width + 1) * self.config.tab_width } else { column + 1 };
+        }
+        column
+    }
+
+    // Returns true if there is a line break between the end of the last token and `pos`.
+    fn starts_line(&self, pos: uint) -> bool {
+        match self.last_end {
+            Some(end) => end <= pos && self.input.slice(end, pos).contains_char('\n'),
+            None => true
+        }
+    }
+
+    // Adds the tokens which come before a token that starts a line at `pos` to `out`, in order,
+    // and opens or closes blocks.
+    fn begin_line(&mut self, pos: uint, out: &mut Vec<(u32, Location)>) {
+        if let (Some(newline), Some(end)) = (self.config.newline, self.last_end) {
+            out.push((newline, Location { start: end, end: end }));
+        }
+        let here = Location { start: pos, end: pos };
+        let column = self.column(pos);
+        if column > self.indents[self.indents.len() - 1] {
+            self.indents.push(column);
+            out.push((self.config.indent, here));
+            return;
+        }
+        while column < self.indents[self.indents.len() - 1] {
+            self.indents.pop();
+            out.push((self.config.dedent, here));
+        }
+        if column > self.indents[self.indents.len() - 1] {
+            // A dedent to a column which no open block has.
+            self.indents.push(column);
+            out.push((ERROR_TOKEN, here));
+        }
+    }
+
+    // Queues tokens to be returned, in order, before any which are already queued.
+    fn queue(&mut self, tokens: Vec<(u32, Location)>) {
+        for &(token, location) in tokens.iter().rev() {
+            let value = (self.value)(token);
+            self.pending.push((token, value, location));
+        }
+    }
+}
+
+impl<'a, L: Lexer<SymbolValue>, SymbolValue> Iterator<(u32, SymbolValue, Location)> for Layout<'a, L, SymbolValue> {
+    fn next(&mut self) -> Option<(u32, SymbolValue, Location)> {
+        if let Some(token) = self.pending.pop() {
+            return Some(token);
+        }
+        if self.done {
+            return None;
+        }
+        let mut layout: Vec<(u32, Location)> = Vec::new();
+        match self.lexer.next_token() {
+            Some((token, value, location)) => {
+                if self.open_brackets == 0 && self.starts_line(location.start) {
+                    self.begin_line(location.start, &mut layout);
+                }
+                if self.config.brackets.iter().any(|&(open, _)| open == token) {
+                    self.open_brackets += 1;
+                }
+                else if self.open_brackets > 0 && self.config.brackets.iter().any(|&(_, close)| close == token) {
+                    self.open_brackets -= 1;
+                }
+                self.last_end = Some(location.end);
+                self.pending.push((token, value, location));
+            }
+            None => {
+                // End the last line, and close the blocks which are still open.
+                self.done = true;
+                if let Some(end) = self.last_end {
+                    let here = Location { start: end, end: end };
+                    if let Some(newline) = self.config.newline {
+                        layout.push((newline, here));
+                    }
+                    while self.indents.len() > 1 {
+                        self.indents.pop();
+                        layout.push((self.config.dedent, here));
+                    }
+                }
+            }
+        }
+        self.queue(layout);
+        self.pending.pop()
+    }
+}
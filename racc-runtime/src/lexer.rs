@@ -0,0 +1,180 @@
+// The interface between a lexer and a parser.
+//
+// A parser can be driven by hand, by calling push_token_at for each token and then finish.
+// ParserState::parse does that loop for any Lexer.  Iterators of (token, value, location)
+// triples are lexers, so a lexer from another crate can be adapted with a map() which converts
+// its tokens to the token numbers of the grammar.
+//
+// Nothing here collects the input: a lexer is asked for one token at a time, as the parser needs
+// it, so the memory that a parse takes depends on the depth of the parse stack, not on the
+// length of the input.  Indexed gives locations to an iterator of (token, value) pairs, and
+// ReaderLexer reads its input a line at a time from a Buffer (such as a BufferedReader over a
+// file), and tokenizes each line as it is read.  Layout (see layout.rs) is a lexer over another
+// lexer, which adds tokens for the indentation of the lines.
+//
+// Some languages cannot be lexed without knowing what the parser has seen: in C, whether an
+// identifier is a typedef name depends on the declarations before it.  A ContextLexer is given
+// the application context, which the actions of the grammar also have, so that the actions can
+// record what the lexer needs to know.  ParserState::parse_with_context passes the context back
+// and forth: the lexer has it between tokens, and the actions have it while a token is pushed.
+
+#[cfg(not(feature = "std"))]
+use std::prelude::*;
+
+#[cfg(feature = "std")]
+use std::collections::RingBuf;
+#[cfg(feature = "std")]
+use std::io::{Buffer, IoError, IoErrorKind};
+
+use Location;
+#[cfg(feature = "std")]
+use scanner::{Scanner, ScannerTables};
+
+/// A source of tokens for a parser.
+pub trait Lexer<SymbolValue> {
+    /// Returns the next token, as its number (one of the token constants that the grammar
+    /// defines), its value, and its location; or `None` at the end of the input.
+    fn next_token(&mut self) -> Option<(u32, SymbolValue, Location)>;
+}
+
+impl<SymbolValue, I> Lexer<SymbolValue> for I where I: Iterator<(u32, SymbolValue, Location)> {
+    fn next_token(&mut self) -> Option<(u32, SymbolValue, Location)> {
+        self.next()
+    }
+}
+
+/// A source of tokens which reads (and may change) the application context, which the actions of
+/// the grammar can also change.  See `ParserState::parse_with_context`.
+pub trait ContextLexer<SymbolValue, AppContext> {
+    /// Returns the next token, as `Lexer::next_token` does.  `ctx` reflects the actions of every
+    /// rule which the parser has reduced so far.
+    fn next_token(&mut self, ctx: &mut AppContext) -> Option<(u32, SymbolValue, Location)>;
+}
+
+/// Gives locations to an iterator of `(token, value)` pairs, which have none of their own, so
+/// that it is a lexer.  The location of each token is its index in the sequence: the first token
+/// is at `0..1`, the second at `1..2`, and so on.  See `ParserState::parse_tokens`.
+pub struct Indexed<I> {
+    tokens: I,
+    next: uint,
+}
+
+impl<I> Indexed<I> {
+    pub fn new(tokens: I) -> Indexed<I> {
+        Indexed { tokens: tokens, next: 0 }
+    }
+}
+
+impl<SymbolValue, I> Iterator<(u32, SymbolValue, Location)> for Indexed<I> where I: Iterator<(u32, SymbolValue)> {
+    fn next(&mut self) -> Option<(u32, SymbolValue, Location)> {
+        match self.tokens.next() {
+            Some((token, value)) => {
+                let location = Location { start: self.next, end: self.next + 1 };
+                self.next += 1;
+                Some((token, value, location))
+            }
+            None => None
+        }
+    }
+}
+
+/// Splits one line of the input of a `ReaderLexer` into tokens.
+#[cfg(feature = "std")]
+pub trait LineTokenizer<SymbolValue> {
+    /// Appends the tokens of `line` to `out`.  `offset` is the offset (in bytes) of the start of
+    /// the line in the input, which the locations of the tokens should include.  The line ends
+    /// with its newline, except perhaps for the last line of the input.
+    fn tokenize(&mut self, line: &str, offset: uint, out: &mut RingBuf<(u32, SymbolValue, Location)>);
+}
+
+/// Tokenizes lines with the tables of a generated lexer (see `Scanner`).  `value` gives the
+/// value of each token, from its number and its text.  No token can span two lines, but the
+/// mode of the lexer carries over from one line to the next.
+#[cfg(feature = "std")]
+pub struct ScanLines<SymbolValue> {
+    tables: &'static ScannerTables,
+    value: fn(u32, &str) -> SymbolValue,
+    mode: uint,
+}
+
+#[cfg(feature = "std")]
+impl<SymbolValue> ScanLines<SymbolValue> {
+    pub fn new(tables: &'static ScannerTables, value: fn(u32, &str) -> SymbolValue) -> ScanLines<SymbolValue> {
+        ScanLines { tables: tables, value: value, mode: 0 }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<SymbolValue> LineTokenizer<SymbolValue> for ScanLines<SymbolValue> {
+    fn tokenize(&mut self, line: &str, offset: uint, out: &mut RingBuf<(u32, SymbolValue, Location)>) {
+        let mut scanner = Scanner::new(self.tables, line);
+        scanner.set_mode(self.mode);
+        loop {
+            let (token, text, location) = match scanner.next() {
+                Some(t) => t,
+                None => break
+            };
+            let value = (self.value)(token, text);
+            out.push_back((token, value, Location { start: offset + location.start, end: offset + location.end }));
+        }
+        self.mode = scanner.mode();
+    }
+}
+
+/// A lexer which reads its input from a `Buffer`, one line at a time, and tokenizes each line
+/// with a `LineTokenizer`.  Only the tokens of the current line are held in memory.  A read
+/// error ends the input; `error` returns it.
+#[cfg(feature = "std")]
+pub struct ReaderLexer<R, T, SymbolValue> {
+    reader: R,
+    tokenizer: T,
+    pending: RingBuf<(u32, SymbolValue, Location)>,
+    offset: uint,
+    error: Option<IoError>,
+    done: bool,
+}
+
+#[cfg(feature = "std")]
+impl<SymbolValue, R: Buffer, T: LineTokenizer<SymbolValue>> ReaderLexer<R, T, SymbolValue> {
+    pub fn new(reader: R, tokenizer: T) -> ReaderLexer<R, T, SymbolValue> {
+        ReaderLexer {
+            reader: reader,
+            tokenizer: tokenizer,
+            pending: RingBuf::new(),
+            offset: 0,
+            error: None,
+            done: false,
+        }
+    }
+
+    /// The error which ended the input early, if there was one.
+    pub fn error(&self) -> Option<&IoError> {
+        self.error.as_ref()
+    }
+}
+
+#[cfg(feature = "std")]
+impl<SymbolValue, R: Buffer, T: LineTokenizer<SymbolValue>> Lexer<SymbolValue> for ReaderLexer<R, T, SymbolValue> {
+    fn next_token(&mut self) -> Option<(u32, SymbolValue, Location)> {
+        loop {
+            if let Some(token) = self.pending.pop_front() {
+                return Some(token);
+            }
+            if self.done {
+                return None;
+            }
+            match self.reader.read_line() {
+                Ok(line) => {
+                    self.tokenizer.tokenize(line.as_slice(), self.offset, &mut self.pending);
+                    self.offset += line.len();
+                }
+                Err(e) => {
+                    if e.kind != IoErrorKind::EndOfFile {
+                        self.error = Some(e);
+                    }
+                    self.done = true;
+                }
+            }
+        }
+    }
+}
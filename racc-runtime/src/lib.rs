@@ -0,0 +1,1722 @@
+//! The runtime support for parsers generated by RACC (Rust Another Compiler-Compiler).
+//!
+//! The code generated by the `grammar!` syntax extension depends only on this crate.  Programs
+//! which use generated parsers need the `racc` crate only as a compiler plugin; at run time,
+//! they link against `racc_runtime`, which is small and does not depend on the compiler:
+//!
+//! ```ignore
+//!     #![feature(phase)]
+//!
+//!     #[phase(plugin)] extern crate racc;
+//!     extern crate racc_runtime;
+//!
+//!     use racc_runtime::{ParserState, ParserTables, Location, FinishParseResult};
+//! ```
+//!
+//! Parsers are re-entrant.  The generated tables are immutable statics, and everything that
+//! changes during a parse (the state, value, and location stacks, and the error recovery
+//! state) is owned by a `ParserState`.  Any number of parses can run at once, on the same or on
+//! different threads, and an action can start a parse of its own, for example to parse the
+//! contents of a string literal, by creating another `ParserState` from the same tables.
+//!
+//! # `no_std`
+//!
+//! The runtime uses only `core` and `alloc` (for `Vec` and `Box`), unless the `std` feature is
+//! enabled, which it is by default.  Without `std`, the parser does not log (`debug!`) or trace,
+//! and `interp` and `ParseError::message` are not available.  Generated code uses `Vec` and the
+//! `debug!` macro, so a `no_std` crate which contains a grammar must have both in scope; a
+//! `debug!` which expands to nothing will do.  It also names `::std::any::Any`, so such a
+//! crate's `std` module must re-export `core::any`.
+
+#![feature(phase)]
+#![feature(macro_rules)]
+#![feature(globs)]
+#![feature(unboxed_closures)]
+#![cfg_attr(not(feature = "std"), no_std)]
+
+#![allow(dead_code)]
+
+#[cfg(feature = "std")]
+#[phase(plugin, link)]
+extern crate log;
+
+#[cfg(not(feature = "std"))]
+#[phase(plugin, link)]
+extern crate core;
+#[cfg(not(feature = "std"))]
+extern crate alloc;
+#[cfg(not(feature = "std"))]
+#[phase(plugin, link)]
+extern crate collections;
+
+// Without std, this stands in for it, so that paths such as std::fmt (including those in the
+// code generated by #[deriving]) work either way.  Each module imports std::prelude::*.
+#[cfg(not(feature = "std"))]
+mod std {
+    pub use core::{any, clone, cmp, default, fmt, kinds, mem, option, str};
+
+    pub mod prelude {
+        pub use core::prelude::*;
+        pub use alloc::boxed::Box;
+        pub use collections::vec::Vec;
+        pub use collections::slice::CloneSliceExt;
+    }
+}
+
+#[cfg(not(feature = "std"))]
+use std::prelude::*;
+
+#[cfg(not(feature = "std"))]
+macro_rules! debug {
+    ($($arg:tt)*) => (())
+}
+
+use std::fmt::Show;
+use std::cmp;
+use std::mem;
+use std::any::{Any, AnyRefExt};
+
+pub use config::{ParserConfig, RecoveryPolicy, TraceSink};
+#[cfg(feature = "std")]
+pub use config::LogTrace;
+pub use glr::{GlrParser, GlrConflict};
+pub use recovery::{ErrorRecovery, RecoveryAction};
+pub use tree::Node;
+pub use lexer::{Lexer, ContextLexer, Indexed};
+pub use layout::{Layout, LayoutConfig};
+pub use remap::{TokenMap, Remap};
+#[cfg(feature = "std")]
+pub use lexer::{LineTokenizer, ScanLines, ReaderLexer};
+pub use scanner::{Scanner, ScannerTables};
+pub use checkpoint::CheckpointParser;
+pub use keywords::{Keyword, KeywordTable};
+pub use debugger::{DebugParser, Step};
+pub use coverage::Coverage;
+
+/// Configuration of the run-time behavior of a parser.
+pub mod config;
+
+/// A parser engine which runs tables that are loaded at run time.
+#[cfg(feature = "std")]
+pub mod interp;
+
+/// A generalized LR parser engine, which follows every action of states with conflicts.
+pub mod glr;
+
+/// Hooks into the error recovery of a parser.
+pub mod recovery;
+
+/// Concrete syntax trees, for parsers which build them.
+pub mod tree;
+
+/// The interface between a lexer and a parser.
+pub mod lexer;
+
+/// Runs the lexers that are generated from the token patterns of grammars.
+pub mod scanner;
+
+/// Adds INDENT, DEDENT and NEWLINE tokens to the tokens of a lexer, for languages in which
+/// indentation is significant.
+pub mod layout;
+
+/// Translates the token numbers of lexers which were not generated from the grammar.
+pub mod remap;
+
+/// Reparses a text after an edit, from the last checkpoint before the edit.
+pub mod checkpoint;
+
+/// Tables of reserved and contextual keywords, for lexers which scan keywords as identifiers.
+pub mod keywords;
+
+/// Runs a parser one step at a time, for debuggers and tests.
+pub mod debugger;
+
+/// Counts the rules that a parser reduces by, and the states that it enters.
+pub mod coverage;
+
+/// Checks that two parsers (such as those of a grammar before and after a refactoring) accept
+/// the same inputs, and reduce them by the same rules.
+#[cfg(feature = "std")]
+pub mod equivalence;
+
+/// Fuzzes a parser with tokens chosen by the bytes from a fuzzer.
+pub mod fuzz;
+
+/// Builds the values of expressions declared with `%expr`, by precedence climbing.
+pub mod expr;
+
+// Parser tables for small grammars, for the unit tests.
+#[cfg(test)]
+mod testing;
+
+// Sends a message to the trace sink of a parser, if it has one.  The message is not formatted
+// unless there is a sink.
+#[cfg(feature = "std")]
+macro_rules! trace {
+    ($parser:expr, $($arg:tt)*) => {
+        if let Some(ref mut sink) = $parser.config.trace {
+            sink.trace(format!($($arg)*).as_slice());
+        }
+    }
+}
+
+// Without std, there is no format!, so nothing is traced.
+#[cfg(not(feature = "std"))]
+macro_rules! trace {
+    ($parser:expr, $($arg:tt)*) => (())
+}
+
+#[deriving(Copy,Show)]
+pub enum PushTokenResult {
+    Ok,                         // The token was consumed.
+    SyntaxError,
+    StackOverflow,              // The token was rejected, because the stack is ParserConfig.max_depth deep.
+    ActionFailed                // An action returned an error; see ParserState::take_action_error.
+}
+
+/// What the caller of `ParserState::push` should do next.
+#[deriving(Copy,Show,PartialEq)]
+pub enum PushStep {
+    /// The token was consumed.  Push the next token, or call `finish` at the end of the input.
+    NeedMore,
+    /// The token was a syntax error (see `ParserState::last_error`), but the parser recovered
+    /// from it.  Push the next token, as for `NeedMore`.
+    Recovered,
+    /// The token was rejected, and the parse cannot go on: the parser could not recover from a
+    /// syntax error, the stack reached `ParserConfig.max_depth`, or an action failed.  Call
+    /// `finish` for the result, or `reset` to start again.
+    Failed(PushTokenResult),
+}
+
+/// The final result of parsing a stream of tokens.
+///
+/// This value is returned from the `ParserState::finish` method.
+pub enum FinishParseResult<SymbolValue> {
+    Accepted(SymbolValue),
+    SyntaxError,
+    /// A fallible action returned an error, which ended the parse.
+    ActionFailed(ActionError)
+}
+
+/// The location of a symbol in the input.  The parser does not interpret locations; `start` and
+/// `end` are whatever the lexer says they are, such as byte offsets or token numbers.  Pass the
+/// location of each token to `ParserState::push_token_at`.  The location of a non-terminal
+/// runs from the start of its first symbol to the end of its last symbol.
+#[deriving(Copy,Clone,PartialEq,Show,Default)]
+pub struct Location {
+    pub start: uint,
+    pub end: uint,
+}
+
+impl Location {
+    // Returns the location of the lhs of a rule, given the locations of its rhs.  An empty rhs
+    // has an empty location, at the end of the symbol before it.
+    fn of_rhs(rhs: &[Location], before: Option<&Location>) -> Location {
+        if rhs.len() != 0 {
+            Location { start: rhs[0].start, end: rhs[rhs.len() - 1].end }
+        }
+        else {
+            let end = match before {
+                Some(loc) => loc.end,
+                None => 0
+            };
+            Location { start: end, end: end }
+        }
+    }
+}
+
+/// Describes a syntax error.  After `push_token` or `finish` returns `SyntaxError`, get it from
+/// `ParserState::last_error`.
+#[deriving(Clone,PartialEq,Show)]
+pub struct ParseError {
+    /// The state that the parser was in when it found the error.
+    pub state: uint,
+    /// The token which caused the error.  Token 0 is the end of input.
+    pub token: u32,
+    /// The location of the token which caused the error.
+    pub location: Location,
+    /// The tokens that the parser would have accepted in `state`, in order of token value.
+    /// Like YACC, the parser may accept a token here (by reducing) and then find that it cannot
+    /// shift it, so this is sometimes larger than the set of tokens that can really follow.
+    pub expected: Vec<u32>,
+}
+
+impl ParseError {
+    /// Returns a message for the error, such as "unexpected PLUS; expected NUM, LPAREN or
+    /// MINUS".  `info` supplies the names of the tokens.
+    #[cfg(feature = "std")]
+    pub fn message(&self, info: &GrammarInfo) -> String {
+        let name = |token: u32| if token == 0 { "end of input" } else { info.token_name(token).unwrap_or("?") };
+        let mut s = format!("unexpected {}", name(self.token));
+        for (i, &t) in self.expected.iter().enumerate() {
+            s.push_str(if i == 0 { "; expected " } else if i + 1 == self.expected.len() { " or " } else { ", " });
+            s.push_str(name(t));
+        }
+        s
+    }
+}
+
+/// An error returned by a fallible action (one written `{ ... }?`), with the location of the
+/// rule whose action it was.  The error has the type that the action returned; get it back with
+/// `downcast_ref`, or with `%define api.error.type`, which makes every fallible action return
+/// the same type.
+pub struct ActionError {
+    pub location: Location,
+    pub error: Box<Any + 'static>,
+}
+
+impl ActionError {
+    /// Returns the error, if it is an `E`.
+    pub fn downcast_ref<E: 'static>(&self) -> Option<&E> {
+        self.error.downcast_ref::<E>()
+    }
+}
+
+impl Show for ActionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "action failed at {}", self.location)
+    }
+}
+
+/// Contains references to the parsing tables (and related information) needed by parsers.
+/// 
+/// You generally should not need to deal with `ParserTables` directly.  Instead, app code
+/// should use the generated 'get_parser_tables()' method.  Example:
+///
+/// ```
+///     grammar! {
+///         ... grammar definition ...
+///     }
+///
+///     fn main() {
+///         let mut parser = ParserState::new(get_parser_tables());
+///
+///         for token in (...) {
+///             parser.push_token(token);
+///         }
+///
+///         match parser.finish() {
+///             ...
+///         }
+///     }
+#[deriving(Copy)]
+pub struct ParserTables<SymbolValue:Show, AppContext> {
+    pub yyrindex: &'static [u32],
+    pub yysindex: &'static [u32],
+    pub yygindex: &'static [u32],
+    pub yytable: &'static [u32],
+    pub yydgoto: &'static [u32],
+    pub yydefred: &'static [u32],
+    pub yylhs: &'static [u16],
+    pub yylen: &'static [u16],
+    pub yycheck: &'static [u32],
+    pub yyname: &'static [&'static str],
+    pub yyfinal: uint,
+
+    // for debugging
+    pub yyrules: &'static [&'static str],
+
+    // describe the grammar; see GrammarInfo
+    pub yyvarname: &'static [&'static str],
+    pub yyhash: u64,
+
+    // the actions which lost conflicts, for GlrParser: (state, token, action) triples, where
+    // action is a state to shift to, or the negation of a rule to reduce by
+    pub yyconflicts: &'static [u32],
+
+    // the lexer mode that each rule enters when it is reduced (given by %begin), plus one, or 0;
+    // empty if no rule has a %begin.  See ParserState::parse_scanner.
+    pub yylexbegin: &'static [u16],
+
+    // for each token, 1 if it is trivia (given by %trivia), 2 if it is trivia which ends a line,
+    // or 0; empty if the grammar has no trivia.  See ParserState::push_token_at.
+    pub yytrivia: &'static [u16],
+
+    // the accessing symbol and kernel items of each state, and the symbols of each rule, for
+    // describing states; see ParserTables::kernel_items.  Empty unless the parser was generated
+    // with the state-items feature of racc.
+    pub yystos: &'static [u32],
+    pub yykernelindex: &'static [u32],
+    pub yykernel: &'static [u32],
+    pub yyrhsindex: &'static [u32],
+    pub yyrhs: &'static [u32],
+
+    // the generated actions; locations holds the locations of the rhs, and location that of the lhs
+    pub reduce: fn(parser: &mut Vec<SymbolValue>, locations: &[Location], location: Location, reduction: uint, ctx: &mut AppContext) -> Result<SymbolValue, Box<Any + 'static>>,
+
+    // makes the value of the error token from the values that recovery skips; Node::error for
+    // parsers which build a tree, and None for others, whose error token has the value of the
+    // token which caused the error
+    pub error_node: Option<fn(skipped: Vec<SymbolValue>, location: Location) -> SymbolValue>,
+
+    // attach trivia to tokens, for parsers which build a tree: Node::with_leading_trivia and
+    // Node::attach_trailing_trivia.  None for others.
+    pub leading_trivia: Option<fn(token: SymbolValue, trivia: Vec<SymbolValue>) -> SymbolValue>,
+    pub trailing_trivia: Option<fn(value: &mut SymbolValue, trivia: SymbolValue) -> Result<(), SymbolValue>>,
+}
+
+/// An item of a state: a rule that the parser may be in the middle of, and how much of it the
+/// parser has seen.  Rules are numbered as in the generated `YYRULES` table.
+#[deriving(Copy,Clone,PartialEq,Show)]
+pub struct Item {
+    pub rule: uint,
+    /// The number of symbols of the right-hand side of the rule which come before the position
+    /// of the parser (the "dot").
+    pub dot: uint,
+}
+
+// A symbol in the yyrhs and yystos tables with this bit set is a non-terminal.
+const RHS_NONTERMINAL: u32 = 0x8000_0000;
+
+/// The action that a parser takes for a given state and token.  Returned by
+/// `ParserTables::action`.
+#[deriving(Copy,Clone,PartialEq,Show)]
+pub enum TableAction {
+    /// Shift the token, and go to the given state.
+    Shift(uint),
+    /// Reduce by the given rule.  Rules are numbered as in the generated `YYRULES` table.
+    Reduce(uint),
+    /// The input has been accepted.  This happens only for the end of input (token 0).
+    Accept,
+    /// The token is a syntax error in this state.
+    Error,
+}
+
+impl<SymbolValue:Show, AppContext> ParserTables<SymbolValue, AppContext> {
+    /// Returns the action that the parser takes when it is in `state`, and the next token is
+    /// `token`.  (Token 0 is the end of input.)  This decodes the packed tables, so tools such as
+    /// completion engines, test oracles, and debuggers can query the automaton directly:
+    ///
+    /// ```ignore
+    ///     match get_parser_tables().action(state, PLUS) {
+    ///         TableAction::Shift(next) => ...,
+    ///         ...
+    ///     }
+    /// ```
+    pub fn action(&self, state: uint, token: u32) -> TableAction {
+        decode_action(self.yysindex, self.yyrindex, self.yytable, self.yycheck, self.yydefred, self.yyfinal, state, token as uint)
+    }
+
+    /// Returns the state that the parser goes to from `state` after reducing to the non-terminal
+    /// `lhs` (as in the `yylhs` table).  From state 0, the goal symbol (lhs 0) goes to the final
+    /// state.
+    pub fn goto(&self, state: uint, lhs: u16) -> uint {
+        decode_goto(self.yygindex, self.yytable, self.yycheck, self.yydgoto, self.yyfinal, state, lhs as uint)
+    }
+
+    /// Returns the kernel items of `state`: the rules that the parser may be in the middle of
+    /// when it is in the state.  This is empty unless the parser was generated with the
+    /// `state-items` feature of racc, and with debug tables.
+    pub fn kernel_items(&self, state: uint) -> Vec<Item> {
+        if self.yykernelindex.len() == 0 {
+            return Vec::new();
+        }
+        let start = self.yykernelindex[state] as uint;
+        let end = self.yykernelindex[state + 1] as uint;
+        range(start / 2, end / 2).map(|i| Item { rule: self.yykernel[i * 2] as uint, dot: self.yykernel[i * 2 + 1] as uint }).collect()
+    }
+
+    /// Returns the name of the symbol which is shifted to enter `state`, or `None` if the parser
+    /// has no state items (see `kernel_items`).  For state 0, this is `$end`.
+    pub fn accessing_symbol(&self, state: uint) -> Option<&'static str> {
+        self.yystos.get(state).map(|&sym| self.symbol_name(sym))
+    }
+
+    // Returns the name of a symbol in the yyrhs and yystos tables.
+    fn symbol_name(&self, sym: u32) -> &'static str {
+        if sym & RHS_NONTERMINAL != 0 { self.yyvarname[(sym & !RHS_NONTERMINAL) as uint] } else { self.yyname[sym as uint] }
+    }
+
+    /// Returns the text of an item, with a dot at the position of the parser, e.g.
+    /// `"Expr : Expr . PLUS Expr"`.  The item must come from `kernel_items`.
+    #[cfg(feature = "std")]
+    pub fn item_text(&self, item: Item) -> String {
+        let mut s = String::from_str(match self.yylhs[item.rule] as i16 {
+            -1 => "$accept",
+            lhs => self.yyvarname[lhs as uint]
+        });
+        s.push_str(" :");
+        let start = self.yyrhsindex[item.rule] as uint;
+        let end = self.yyrhsindex[item.rule + 1] as uint;
+        for i in range(start, end + 1) {
+            if i == start + item.dot {
+                s.push_str(" .");
+            }
+            if i < end {
+                s.push(' ');
+                s.push_str(self.symbol_name(self.yyrhs[i]));
+            }
+        }
+        s
+    }
+
+    /// Describes what the parser is doing in `state`, for messages to the people who wrote the
+    /// input, e.g. "while parsing `Expr . PLUS Expr`".  The description shows the right-hand
+    /// sides of the kernel items of the state, which are what the parser has seen so far.  It is
+    /// empty unless the parser has state items (see `kernel_items`).
+    #[cfg(feature = "std")]
+    pub fn state_description(&self, state: uint) -> String {
+        let items = self.kernel_items(state);
+        if items.len() == 0 || state == 0 {
+            return String::new();
+        }
+        let mut s = String::from_str("while parsing ");
+        for (i, &item) in items.iter().enumerate() {
+            if i != 0 {
+                s.push_str(if i + 1 == items.len() { " or " } else { ", " });
+            }
+            let text = self.item_text(item);
+            let rhs = match text.as_slice().find_str(" : ") {
+                Some(colon) => text.as_slice().slice_from(colon + 3).to_string(),
+                None => text.clone()
+            };
+            s.push('`');
+            s.push_str(rhs.as_slice());
+            s.push('`');
+        }
+        s
+    }
+}
+
+// Decodes the packed tables.  This is shared by ParserTables and the table interpreter.
+fn decode_action(yysindex: &[u32], yyrindex: &[u32], yytable: &[u32], yycheck: &[u32], yydefred: &[u32], yyfinal: uint, state: uint, token: uint) -> TableAction {
+    if state == yyfinal && token == 0 {
+        return TableAction::Accept;
+    }
+    if let Some(index) = table_index(yycheck, yysindex[state], token) {
+        return TableAction::Shift(yytable[index] as uint);
+    }
+    if let Some(index) = table_index(yycheck, yyrindex[state], token) {
+        return TableAction::Reduce(yytable[index] as uint);
+    }
+    match yydefred[state] {
+        0 => TableAction::Error,
+        rule => TableAction::Reduce(rule as uint)
+    }
+}
+
+impl<SymbolValue:Show, AppContext> ParserTables<SymbolValue, AppContext> {
+    /// Returns a description of the grammar that these tables were generated from.
+    pub fn grammar_info(&self) -> GrammarInfo<'static> {
+        GrammarInfo {
+            token_names: self.yyname,
+            nonterminal_names: self.yyvarname,
+            rule_lhs: self.yylhs,
+            rule_len: self.yylen,
+            rule_text: self.yyrules,
+            hash: self.yyhash,
+        }
+    }
+}
+
+/// Describes the grammar of a generated parser: its symbols and rules.  Get one from
+/// `get_parser_tables().grammar_info()`.  Tools such as REPLs and debuggers can use this to
+/// work with any RACC-generated parser.
+///
+/// Rules are numbered as in the `reduce` function; rule 0 is `$accept : <goal> $end`.
+#[deriving(Copy)]
+pub struct GrammarInfo<'a> {
+    token_names: &'a [&'a str],
+    nonterminal_names: &'a [&'a str],
+    rule_lhs: &'a [u16],
+    rule_len: &'a [u16],
+    rule_text: &'a [&'a str],
+    hash: u64,
+}
+
+impl<'a> GrammarInfo<'a> {
+    /// Returns the name of the token whose value is `token`, if there is such a token.
+    pub fn token_name(&self, token: u32) -> Option<&'a str> {
+        match self.token_names.get(token as uint) {
+            Some(name) if name.len() != 0 => Some(*name),
+            _ => None
+        }
+    }
+
+    /// Returns the number of non-terminals, not counting `$accept`.
+    pub fn nonterminal_count(&self) -> uint {
+        self.nonterminal_names.len()
+    }
+
+    /// Returns the name of a non-terminal.  Non-terminal 0 is the start symbol.
+    pub fn nonterminal_name(&self, nonterminal: uint) -> &'a str {
+        self.nonterminal_names[nonterminal]
+    }
+
+    /// Returns the name of the start symbol (the goal of the grammar).
+    pub fn start_symbol(&self) -> &'a str {
+        self.nonterminal_names[0]
+    }
+
+    /// Returns the number of rules.
+    pub fn rule_count(&self) -> uint {
+        self.rule_len.len()
+    }
+
+    /// Returns the non-terminal on the left-hand side of a rule, or `None` for rule 0, whose
+    /// left-hand side is `$accept`.
+    pub fn rule_lhs(&self, rule: uint) -> Option<uint> {
+        match self.rule_lhs[rule] as i16 {
+            -1 => None,
+            lhs => Some(lhs as uint)
+        }
+    }
+
+    /// Returns the number of symbols on the right-hand side of a rule.
+    pub fn rule_arity(&self, rule: uint) -> uint {
+        self.rule_len[rule] as uint
+    }
+
+    /// Returns the text of a rule, e.g. `"Expr : Expr PLUS Expr"`.
+    pub fn rule_text(&self, rule: uint) -> &'a str {
+        self.rule_text[rule]
+    }
+
+    /// Returns a hash of the grammar, computed from its tokens and rules when the parser was
+    /// generated.  Parsers generated from the same grammar have the same hash.
+    pub fn hash(&self) -> u64 {
+        self.hash
+    }
+}
+
+/// An active instance of a parser.  This structure contains the state of a parsing state
+/// machine, including the state stack and the value stack.
+///
+/// To create an instance of `ParserState`, use `ParserState::new` and pass it a `ParserTables` which
+/// describes your application's grammar.
+pub struct ParserState<SymbolValue:Show, AppContext> {
+    tables: ParserTables<SymbolValue, AppContext>,
+    config: ParserConfig,
+    yystate: uint,
+    value_stack: Vec<SymbolValue>,
+    location_stack: Vec<Location>,  // parallel to value_stack
+    state_stack: Vec<uint>,
+    failed: bool,               // the parse has failed: under RecoveryPolicy::Strict, or recovery gave up
+    rejected: bool,             // a syntax error was discarded without recovery; the input cannot be accepted
+    recovering: uint,           // the number of tokens to shift before errors are reported again
+    error_recovery: Option<Box<ErrorRecovery<SymbolValue> + 'static>>,
+    last_error: Option<ParseError>,
+    action_error: Option<ActionError>,
+    coverage: Option<Coverage>,     // if ParserConfig.coverage is set
+    lexer_mode: Option<uint>,   // the mode that the last rule with a %begin entered, until taken
+    trivia: Vec<SymbolValue>,   // trivia which waits for the next token, as its leading trivia
+    trailing_open: bool,        // trivia now is trailing trivia of the last token
+}
+
+/// The complete state of a parser, as saved by `ParserState::snapshot`.  It is plain data, so it
+/// can be stored, or serialized, and later given to `ParserState::restore`.
+#[deriving(Clone,Show)]
+pub struct ParserSnapshot<SymbolValue> {
+    /// The hash of the grammar of the parser; see `GrammarInfo::hash`.
+    pub grammar_hash: u64,
+    /// The current state; the top of `state_stack`.
+    pub state: uint,
+    pub state_stack: Vec<uint>,
+    /// The values of the symbols on the stack.  There is one fewer than there are states.
+    pub value_stack: Vec<SymbolValue>,
+    /// The locations of the symbols on the stack, parallel to `value_stack`.
+    pub location_stack: Vec<Location>,
+    /// True if the parse has failed: after a syntax error under `RecoveryPolicy::Strict`, or one
+    /// which could not be recovered from.
+    pub failed: bool,
+    /// True if a syntax error was discarded (by an `ErrorRecovery`) without recovering from it,
+    /// so that the input cannot be accepted.
+    pub rejected: bool,
+    /// The number of tokens to shift before syntax errors are reported again.
+    pub recovering: uint,
+    pub last_error: Option<ParseError>,
+}
+
+/// The reason that `ParserState::restore` rejected a snapshot.
+#[deriving(Copy,Clone,PartialEq,Show)]
+pub enum SnapshotError {
+    /// The snapshot was taken from a parser for a different grammar.
+    WrongGrammar,
+    /// The stacks of the snapshot do not fit together.
+    Inconsistent,
+}
+
+// The initial state for all parsers.
+const INITIAL_STATE: uint = 0;
+
+// The value of the error token, which is used in rules to recover from syntax errors.
+const ERROR_TOKEN: u32 = 256;
+
+impl<SymbolValue:Show, AppContext> ParserState<SymbolValue, AppContext> {
+    /// Initializes a new `ParserState`, given the parsing tables that were generated by the
+    /// `grammar!` syntax extension.  Use the `push_token` and `finish` methods to advance the
+    /// state of the parser.  Use the `reset` method to reset the parser to its initial state.
+    pub fn new(tables: ParserTables<SymbolValue, AppContext>) -> ParserState<SymbolValue, AppContext> {
+        ParserState::with_config(tables, ParserConfig::default())
+    }
+
+    /// Initializes a new `ParserState`, exactly as `new` does, but with the given configuration
+    /// rather than the default one.
+    ///
+    /// The stacks are allocated with room for `config.initial_capacity` states.  If only
+    /// `config.max_depth` is set, then the stacks are allocated at their full capacity here, and
+    /// the parser does not allocate memory while parsing.  (Your actions may, of course.)
+    pub fn with_config(tables: ParserTables<SymbolValue, AppContext>, config: ParserConfig) -> ParserState<SymbolValue, AppContext> {
+        // The value stack is never deeper than the state stack.
+        let capacity = match (config.initial_capacity, config.max_depth) {
+            (Some(initial), Some(max_depth)) => cmp::min(initial, max_depth),
+            (Some(initial), None) => initial,
+            (None, Some(max_depth)) => max_depth,
+            (None, None) => 20
+        };
+        let coverage = if config.coverage { Some(Coverage::new(&tables)) } else { None };
+        ParserState {
+            tables: tables,
+            config: config,
+            yystate: INITIAL_STATE,
+            value_stack: Vec::with_capacity(capacity),
+            location_stack: Vec::with_capacity(capacity),
+            state_stack: { let mut v = Vec::with_capacity(capacity); v.push(INITIAL_STATE); v },
+            failed: false,
+            rejected: false,
+            recovering: 0,
+            error_recovery: None,
+            last_error: None,
+            action_error: None,
+            coverage: coverage,
+            lexer_mode: None,
+            trivia: Vec::new(),
+            trailing_open: false,
+        }
+    }
+
+    /// Returns the configuration of this parser.
+    pub fn config(&self) -> &ParserConfig {
+        &self.config
+    }
+
+    /// Returns the last syntax error, if there has been one since the parser was created or
+    /// reset.
+    pub fn last_error(&self) -> Option<&ParseError> {
+        self.last_error.as_ref()
+    }
+
+    /// Returns the error of the fallible action which ended the parse, if one did, and clears it.
+    /// After an action fails, the parser rejects every token until it is reset.
+    pub fn take_action_error(&mut self) -> Option<ActionError> {
+        self.action_error.take()
+    }
+
+    /// Returns the tokens that the parser would have accepted when the last syntax error
+    /// occurred.  This is empty if there has been no syntax error.
+    pub fn expected_tokens(&self) -> &[u32] {
+        match self.last_error {
+            Some(ref error) => error.expected.as_slice(),
+            None => &[]
+        }
+    }
+
+    /// Returns the rules that the parser has reduced by, and the states that it has entered,
+    /// since it was created, if it was created with `ParserConfig.coverage` set.  `reset` does not
+    /// clear it, so a parser which parses each input of a test corpus in turn counts them all:
+    ///
+    /// ```ignore
+    ///     let config = ParserConfig { coverage: true, ..Default::default() };
+    ///     let mut parser = ParserState::with_config(get_parser_tables(), config);
+    ///     for input in corpus.iter() { ... }
+    ///     print!("{}", parser.coverage().unwrap().report(&get_parser_tables()));
+    /// ```
+    pub fn coverage(&self) -> Option<&Coverage> {
+        self.coverage.as_ref()
+    }
+
+    /// Returns the coverage of the parser, as `coverage` does, and starts counting again from
+    /// zero.
+    pub fn take_coverage(&mut self) -> Option<Coverage> {
+        match self.coverage.take() {
+            Some(coverage) => {
+                self.coverage = Some(Coverage::new(&self.tables));
+                Some(coverage)
+            }
+            None => None
+        }
+    }
+
+    /// Starts tracing the parser's actions to `sink`, or stops tracing them if `sink` is `None`.
+    /// This can be done at any time, for example to trace only the part of the input where a
+    /// parse goes wrong:
+    ///
+    /// ```ignore
+    ///     parser.set_trace(Some(box LogTrace as Box<TraceSink>));
+    ///     parser.set_trace(Some(box |&mut: step: &str| println!("{}", step) as Box<TraceSink>));
+    /// ```
+    pub fn set_trace(&mut self, sink: Option<Box<TraceSink + 'static>>) {
+        self.config.trace = sink;
+    }
+
+    /// Installs hooks which are told about each step of error recovery, and which can choose to
+    /// recover by inserting or discarding tokens instead.  See `ErrorRecovery`.
+    pub fn set_error_recovery(&mut self, hooks: Box<ErrorRecovery<SymbolValue> + 'static>) {
+        self.error_recovery = Some(hooks);
+    }
+
+    /// Resets this parser to its initial state, exactly as if `Parser::new` had been used to generate a
+    /// new ParserState object.  There is no semantic difference between using `Parser::new` and using
+    /// `reset()`.  The `reset()` parser may be more efficient, since it does not require freeing and
+    /// reallocating the internal state tables.
+    pub fn reset(&mut self) {
+        self.yystate = INITIAL_STATE;
+        self.value_stack.clear();
+        self.location_stack.clear();
+        self.state_stack.clear();
+        self.state_stack.push(INITIAL_STATE);
+        self.failed = false;
+        self.rejected = false;
+        self.recovering = 0;
+        self.last_error = None;
+        self.action_error = None;
+        self.lexer_mode = None;
+        self.trivia.clear();
+        self.trailing_open = false;
+    }
+
+    /// Returns a copy of the complete state of the parser: its stacks, its current state, and
+    /// its error state.  Restoring the snapshot (with `restore`) returns the parser to this point,
+    /// so that, for example, an editor can save the parser at the start of each line and resume
+    /// from the line which changed.  The parser holds no lookahead between calls to
+    /// `push_token`, so nothing else is needed to resume.
+    pub fn snapshot(&self) -> ParserSnapshot<SymbolValue> where SymbolValue: Clone {
+        ParserSnapshot {
+            grammar_hash: self.tables.yyhash,
+            state: self.yystate,
+            state_stack: self.state_stack.clone(),
+            value_stack: self.value_stack.clone(),
+            location_stack: self.location_stack.clone(),
+            failed: self.failed,
+            rejected: self.rejected,
+            recovering: self.recovering,
+            last_error: self.last_error.clone(),
+        }
+    }
+
+    /// Returns the parser to the state saved in `snapshot`.  Fails, leaving the parser as it was,
+    /// if the snapshot was taken from a parser for a different grammar, or if its stacks do not
+    /// fit together (for example, because it was changed after it was taken).
+    pub fn restore(&mut self, snapshot: ParserSnapshot<SymbolValue>) -> Result<(), SnapshotError> {
+        if snapshot.grammar_hash != self.tables.yyhash {
+            return Err(SnapshotError::WrongGrammar);
+        }
+        let nstates = self.tables.yysindex.len();
+        if snapshot.state_stack.len() == 0
+            || snapshot.state_stack[snapshot.state_stack.len() - 1] != snapshot.state
+            || snapshot.state_stack.iter().any(|&s| s >= nstates)
+            || snapshot.value_stack.len() + 1 != snapshot.state_stack.len()
+            || snapshot.location_stack.len() != snapshot.value_stack.len() {
+            return Err(SnapshotError::Inconsistent);
+        }
+        self.yystate = snapshot.state;
+        self.state_stack = snapshot.state_stack;
+        self.value_stack = snapshot.value_stack;
+        self.location_stack = snapshot.location_stack;
+        self.failed = snapshot.failed;
+        self.rejected = snapshot.rejected;
+        self.recovering = snapshot.recovering;
+        self.last_error = snapshot.last_error;
+        self.action_error = None;
+        Ok(())
+    }
+
+    // Records a syntax error in the current state.
+    fn syntax_error(&mut self, token: u32, location: Location) {
+        trace!(self, "state {}: syntax error at token {} ({})", self.yystate, token, self.tables.yyname[token as uint]);
+        if self.config.recovery == RecoveryPolicy::Strict {
+            self.failed = true;
+        }
+        let mut expected: Vec<u32> = Vec::new();
+        for t in range(0, self.tables.yyname.len()) {
+            if self.tables.yyname[t].len() == 0 || t as u32 == ERROR_TOKEN {
+                continue;
+            }
+            match self.tables.action(self.yystate, t as u32) {
+                TableAction::Error => {}
+                _ => expected.push(t as u32)
+            }
+        }
+        self.last_error = Some(ParseError { state: self.yystate, token: token, location: location, expected: expected });
+    }
+
+    // Reduces by a rule, running its action.  Returns false if the action failed; then the
+    // parser has failed, and the error is in action_error.
+    fn yyreduce(&mut self, reduction: uint, ctx: &mut AppContext) -> bool {
+        let len = self.tables.yylen[reduction] as uint;
+        let lhs = self.tables.yylhs[reduction];
+
+        debug!("state {} reducing by rule {}, len={}, lhs={}", self.yystate, self.tables.yyrules[reduction], len, lhs);
+        trace!(self, "state {}: reduce by rule {}", self.yystate, self.tables.yyrules[reduction]);
+        assert!(self.value_stack.len() >= len);
+        assert!(self.state_stack.len() >= len);
+
+        // Invoke the generated "reduce" method.  This method handles popping values from
+        // parser.values_stack, and then executing the app-supplied code for this reduction.
+        // Because the generated code handles popping items from the stack, it is not necessary
+        // for us to consult a 'yylen' table here; that information is implicit.
+        let old_values_len = self.value_stack.len();
+        let location = {
+            let rhs = self.location_stack.slice_from(old_values_len - len);
+            let before = self.location_stack.slice_to(old_values_len - len).last();
+            Location::of_rhs(rhs, before)
+        };
+        let reduce_value = match (self.tables.reduce)(&mut self.value_stack, self.location_stack.slice_from(old_values_len - len), location, reduction, ctx) {
+            Ok(value) => value,
+            Err(error) => {
+                trace!(self, "state {}: the action of rule {} failed", self.yystate, self.tables.yyrules[reduction]);
+                self.failed = true;
+                self.action_error = Some(ActionError { location: location, error: error });
+                return false;
+            }
+        };
+        assert!(self.value_stack.len() + len == old_values_len);
+        if self.tables.yylexbegin.len() != 0 && self.tables.yylexbegin[reduction] != 0 {
+            self.lexer_mode = Some(self.tables.yylexbegin[reduction] as uint - 1);
+        }
+        self.location_stack.truncate(old_values_len - len);
+        self.location_stack.push(location);
+        debug!("    generated code popped {} values from value stack, new len = {}", old_values_len, self.value_stack.len());
+        // Push the value that represents the reduction of this rule (the LHS).
+        debug!("    after pushing the result of the reduction, value_stack.len = {}, reduce_value={}", self.value_stack.len() + 1, reduce_value);
+        self.value_stack.push(reduce_value);
+
+        // pop states
+        for _ in range(0, len) {
+            self.state_stack.pop().unwrap();
+        }
+        let top_state = self.state_stack[self.state_stack.len() - 1] as uint;
+
+        let next_state = self.goto(top_state, lhs);
+        debug!("        after reduction, shifting from state {} to state {}", top_state, next_state);
+        trace!(self, "state {}: goto state {} on {}", top_state, next_state, self.tables.yyvarname.get(lhs as uint).map_or("$accept", |name| *name));
+        if let Some(ref mut coverage) = self.coverage {
+            coverage.rules[reduction] += 1;
+            coverage.states[next_state] += 1;
+        }
+        self.yystate = next_state;
+        self.state_stack.push(next_state);
+        true
+    }
+
+    // Returns the state that the parser goes to from `state`, after reducing to the non-terminal
+    // `lhs`.  From state 0, the goal symbol (lhs 0) goes to the final state.
+    fn goto(&self, state: uint, lhs: u16) -> uint {
+        self.tables.goto(state, lhs)
+    }
+
+    /// Returns true if the parser, in its current state, would shift `token` (perhaps after some
+    /// reductions), or accept the input if `token` is 0.  This runs the reductions on a copy of
+    /// the stack, so it is exact: unlike the lookaheads of an LALR(1) state, it does not say yes
+    /// to a token which a reduction would then reject.  Lexers use it to decide whether a
+    /// contextual keyword is a keyword here; see `KeywordTable::contextual`.
+    pub fn accepts(&self, token: u32) -> bool {
+        let mut stack: Vec<uint> = self.state_stack.clone();
+        loop {
+            let state = stack[stack.len() - 1];
+            match self.tables.action(state, token) {
+                TableAction::Shift(_) | TableAction::Accept => return true,
+                TableAction::Error => return false,
+                TableAction::Reduce(rule) => {
+                    let len = self.tables.yylen[rule] as uint;
+                    let new_len = stack.len() - len;
+                    stack.truncate(new_len);
+                    let top = stack[stack.len() - 1];
+                    stack.push(self.goto(top, self.tables.yylhs[rule]));
+                }
+            }
+        }
+    }
+
+    fn do_defreds(&mut self, ctx: &mut AppContext) -> bool {
+        // Check for default reductions.
+        let mut any = false;
+        loop {
+            let defred = self.tables.yydefred[self.yystate];
+            if defred != 0 {
+                // debug!("    default reduction: yyn={}", defred);
+                if !self.yyreduce(defred as uint, ctx) {
+                    return any;
+                }
+                any = true;
+            }
+            else {
+                // debug!("    no more defreds");
+                return any;
+            }
+        }
+    }
+
+    // Shifts a token, whose action in the current state is to go to next_state.
+    fn shift(&mut self, token: u32, next_state: uint, lval: SymbolValue, location: Location) {
+        debug!("state {}, shifting to state {}, pushing lval {}", self.yystate, next_state, lval);
+        trace!(self, "state {}: shift {} ({}), go to state {}", self.yystate, token, self.tables.yyname[token as uint], next_state);
+        if let Some(ref mut coverage) = self.coverage {
+            coverage.states[next_state] += 1;
+        }
+        self.yystate = next_state;
+        self.state_stack.push(next_state);
+        self.value_stack.push(lval); // <-- lval is consumed
+        self.location_stack.push(location);
+    }
+
+    // Recovers from a syntax error, as YACC does: pops states until the state on top of the stack
+    // can shift the error token, and then shifts it.  The value of the error token is the value
+    // of the token that caused the error, which is therefore consumed.  Returns false, without
+    // changing the stacks (and discarding the token), if no state on the stack can shift the
+    // error token; then the parser has failed, since it has nothing to resynchronize with.
+    fn recover(&mut self, ctx: &mut AppContext, token: u32, lval: SymbolValue, location: Location) -> bool {
+        let depth = match range(0, self.state_stack.len()).rev().find(|&i|
+                match self.tables.action(self.state_stack[i], ERROR_TOKEN) {
+                    TableAction::Shift(_) => true,
+                    _ => false
+                }) {
+            Some(depth) => depth,
+            None => {
+                trace!(self, "state {}: no state on the stack can shift the error token", self.yystate);
+                self.discard(token, &lval);
+                if let Some(ref mut hooks) = self.error_recovery {
+                    hooks.recovery_failed(token);
+                }
+                self.failed = true;
+                return false;
+            }
+        };
+
+        let popped = self.state_stack.len() - 1 - depth;
+        trace!(self, "state {}: recovering from syntax error, popping {} state(s)", self.yystate, popped);
+        let mut skipped: Vec<SymbolValue> = Vec::new();
+        let mut start = location.start;
+        for _ in range(0, popped) {
+            let state = self.state_stack.pop().unwrap();
+            let value = self.value_stack.pop().unwrap();
+            start = self.location_stack.pop().unwrap().start;
+            if let Some(ref mut hooks) = self.error_recovery {
+                hooks.state_popped(state, &value);
+            }
+            if self.tables.error_node.is_some() {
+                skipped.push(value);
+            }
+        }
+        self.yystate = self.state_stack[depth];
+
+        // A parser which builds a tree gives the error token a node which holds everything that
+        // was popped, and the bad token.
+        let (lval, location) = match self.tables.error_node {
+            Some(error_node) => {
+                let location = Location { start: start, end: location.end };
+                skipped.reverse();
+                skipped.push(lval);
+                (error_node(skipped, location), location)
+            }
+            None => (lval, location)
+        };
+
+        match self.tables.action(self.yystate, ERROR_TOKEN) {
+            TableAction::Shift(next_state) => self.shift(ERROR_TOKEN, next_state, lval, location),
+            _ => unreachable!()
+        }
+        self.recovering = 3;
+        if let Some(ref mut hooks) = self.error_recovery {
+            hooks.recovered(self.yystate);
+        }
+        self.do_defreds(ctx);
+        true
+    }
+
+    // Discards a token that cannot be shifted.
+    fn discard(&mut self, token: u32, lval: &SymbolValue) {
+        trace!(self, "state {}: discarding token {} ({})", self.yystate, token, self.tables.yyname[token as uint]);
+        if let Some(ref mut hooks) = self.error_recovery {
+            hooks.token_discarded(token, lval);
+        }
+    }
+
+    // Discards a token while recovering from an error.  If the parser builds a tree, and the
+    // error token is still on top of the stack, the token becomes part of its error node.
+    fn skip(&mut self, token: u32, lval: SymbolValue, location: Location) {
+        self.discard(token, &lval);
+        let error_node = match self.tables.error_node {
+            Some(error_node) if self.error_on_top() => error_node,
+            _ => return
+        };
+        let node = self.value_stack.pop().unwrap();
+        let top = self.location_stack.len() - 1;
+        let location = Location { start: self.location_stack[top].start, end: location.end };
+        self.location_stack[top] = location;
+        self.value_stack.push(error_node(vec![node, lval], location));
+    }
+
+    // Returns true if the symbol on top of the stack is the error token.  Each state is entered
+    // on only one symbol, so this is so if the state below it shifts the error token to it.
+    fn error_on_top(&self) -> bool {
+        let n = self.state_stack.len();
+        n >= 2 && self.tables.action(self.state_stack[n - 2], ERROR_TOKEN) == TableAction::Shift(self.state_stack[n - 1])
+    }
+
+    /// Advances the state of the parser by reporting a new token to the parser.
+    ///
+    /// Calling this method is the equivalent of returning a token (other than `YYEOF`) from a `yylex()`
+    /// function in a YACC parser.
+    ///
+    /// If the token is a syntax error, and the grammar has rules which use the `error` token, then
+    /// the parser recovers as a YACC parser does: it pops states until it can shift `error`, shifts
+    /// it (with the value of the bad token), and then discards tokens until one of them can be
+    /// shifted.  Only the first error is reported (as `SyntaxError`); the tokens that are discarded
+    /// while recovering, and errors within the next three tokens, are not.  If no state on the
+    /// stack can shift `error`, the parse has failed: this and all further tokens return
+    /// `SyntaxError`, and so does `finish`.
+    pub fn push_token(&mut self, ctx: &mut AppContext, token: u32, lval: SymbolValue) -> PushTokenResult {
+        self.push_token_at(ctx, token, lval, Location::default())
+    }
+
+    /// Advances the state of the parser, exactly as `push_token` does, and records the location
+    /// of the token.  The actions of the grammar can use the locations of the symbols of a rule;
+    /// see `Location`.
+    pub fn push_token_at(&mut self, ctx: &mut AppContext, token: u32, lval: SymbolValue, location: Location) -> PushTokenResult {
+        assert!(self.state_stack.len() > 0);
+
+        debug!("");
+        debug!("state {}, reading {} ({}) lval {}, state_stack = {}", self.yystate, token, self.tables.yyname[token as uint], lval, self.state_stack);
+        debug!("value_stack = {}", self.value_stack);
+
+        if self.failed {
+            return if self.action_error.is_some() { PushTokenResult::ActionFailed } else { PushTokenResult::SyntaxError };
+        }
+
+        if self.tables.yytrivia.len() != 0 && self.tables.yytrivia[token as uint] != 0 {
+            self.push_trivia(token, lval);
+            return PushTokenResult::Ok;
+        }
+
+        if let Some(max_depth) = self.config.max_depth {
+            if self.state_stack.len() >= max_depth {
+                trace!(self, "state {}: token {} rejected, stack depth {} has reached the limit", self.yystate, token, self.state_stack.len());
+                return PushTokenResult::StackOverflow;
+            }
+        }
+
+        let lval = match self.tables.leading_trivia {
+            Some(leading) if self.trivia.len() != 0 => leading(lval, mem::replace(&mut self.trivia, Vec::new())),
+            _ => lval
+        };
+        self.trailing_open = true;
+
+        loop {
+            match self.tables.action(self.yystate, token) {
+                TableAction::Shift(next_state) => {
+                    self.shift(token, next_state, lval, location);
+                    if self.recovering > 0 {
+                        self.recovering -= 1;
+                    }
+                    self.do_defreds(ctx);
+                    if self.action_error.is_some() {
+                        return PushTokenResult::ActionFailed;
+                    }
+                    return PushTokenResult::Ok;
+                }
+                TableAction::Reduce(rule) => {
+                    if !self.yyreduce(rule, ctx) {
+                        return PushTokenResult::ActionFailed;
+                    }
+                }
+                TableAction::Accept | TableAction::Error => {
+                    break;
+                }
+            }
+        }
+
+        // If there is neither a shift nor a reduce action defined for this (state, token),
+        // then we have encountered a syntax error.
+
+        if self.recovering == 3 {
+            // The error token was just shifted, and this token cannot follow it.  Discard it.
+            self.skip(token, lval, location);
+            return PushTokenResult::Ok;
+        }
+        if self.recovering > 0 {
+            // Another error, soon after the last one.  Recover again, but do not report it.
+            if self.recover(ctx, token, lval, location) {
+                return PushTokenResult::Ok;
+            }
+            self.recovering = 0;
+            return PushTokenResult::SyntaxError;
+        }
+
+        debug!("syntax error!  token is not recognized in this state.");
+        self.syntax_error(token, location);
+        if self.config.recovery == RecoveryPolicy::Strict {
+            return PushTokenResult::SyntaxError;
+        }
+
+        let action = match self.error_recovery {
+            Some(ref mut hooks) => hooks.syntax_error(self.yystate, token, self.last_error.as_ref().unwrap().expected.as_slice()),
+            None => RecoveryAction::Default
+        };
+        match action {
+            RecoveryAction::Insert(inserted, inserted_lval) => {
+                let accepted = match self.tables.action(self.yystate, inserted) {
+                    TableAction::Error | TableAction::Accept => false,
+                    _ => true
+                };
+                if accepted {
+                    trace!(self, "state {}: inserting token {} ({})", self.yystate, inserted, self.tables.yyname[inserted as uint]);
+                    // If either token cannot be shifted, it is discarded, rather than starting
+                    // another round of recovery.
+                    self.recovering = 3;
+                    let inserted_location = Location { start: location.start, end: location.start };
+                    self.push_token_at(ctx, inserted, inserted_lval, inserted_location);
+                    self.recovering = 3;
+                    self.push_token_at(ctx, token, lval, location);
+                }
+                else {
+                    self.recover(ctx, token, lval, location);
+                }
+            }
+            RecoveryAction::Discard => {
+                self.discard(token, &lval);
+                self.rejected = true;
+            }
+            RecoveryAction::Default => {
+                self.recover(ctx, token, lval, location);
+            }
+        }
+        return PushTokenResult::SyntaxError;
+    }
+
+    /// Pushes one token, with its value and location (as a `Lexer` returns them), exactly as
+    /// `push_token_at` does, and says whether the caller should go on pushing tokens.  This is
+    /// the interface for a caller which receives its tokens one at a time, from an event loop or
+    /// from the frames of a network protocol:
+    ///
+    /// ```ignore
+    ///     // for each token, as it arrives:
+    ///     if let PushStep::Failed(_) = parser.push(&mut ctx, (token, value, location)) {
+    ///         return parser.finish(&mut ctx);
+    ///     }
+    ///     // and at the end of the input:
+    ///     parser.finish(&mut ctx)
+    /// ```
+    pub fn push(&mut self, ctx: &mut AppContext, token: (u32, SymbolValue, Location)) -> PushStep {
+        let (token, lval, location) = token;
+        match self.push_token_at(ctx, token, lval, location) {
+            PushTokenResult::Ok => PushStep::NeedMore,
+            PushTokenResult::SyntaxError if !self.failed => PushStep::Recovered,
+            result => PushStep::Failed(result)
+        }
+    }
+
+    /// Parses all of the tokens of `lexer`, and then finishes parsing.  Under
+    /// `RecoveryPolicy::Strict`, the first syntax error ends the parse; otherwise, the parser
+    /// recovers from errors and keeps going, and the errors can be seen with an `ErrorRecovery`
+    /// (see `set_error_recovery`).  Parsing also ends if the stack exceeds `max_depth`.
+    pub fn parse<L: Lexer<SymbolValue>>(&mut self, ctx: &mut AppContext, lexer: &mut L) -> FinishParseResult<SymbolValue> {
+        loop {
+            let (token, lval, location) = match lexer.next_token() {
+                Some(t) => t,
+                None => return self.finish(ctx)
+            };
+            if !self.parse_token(ctx, token, lval, location) {
+                return self.stopped();
+            }
+        }
+    }
+
+    /// Parses all of the tokens of `lexer`, as `parse` does, but for a lexer which depends on
+    /// what the parser has seen (such as a C lexer, which must know which names are typedefs).
+    /// The lexer is given `ctx` before each token, and the actions of the grammar are given it
+    /// while the token is pushed, so the actions can tell the lexer what it needs to know.
+    ///
+    /// The parser reduces a rule when it sees the token after the rule, unless the state has a
+    /// default reduction; so that token has already been lexed when the rule's action runs.  A
+    /// grammar which declares typedefs should end the rule which records a name with a token
+    /// (such as the `;` of a declaration), so that it is reduced by default, before the next
+    /// token is lexed.  The report of the automaton (`%define report.file`) shows which states
+    /// have default reductions.
+    pub fn parse_with_context<L: ContextLexer<SymbolValue, AppContext>>(&mut self, ctx: &mut AppContext, lexer: &mut L) -> FinishParseResult<SymbolValue> {
+        loop {
+            let (token, lval, location) = match lexer.next_token(ctx) {
+                Some(t) => t,
+                None => return self.finish(ctx)
+            };
+            if !self.parse_token(ctx, token, lval, location) {
+                return self.stopped();
+            }
+        }
+    }
+
+    /// Parses the text that `scanner` scans, as `parse` does, with `value` giving the value of
+    /// each token from its number and its text.  When the parser reduces a rule which has a
+    /// `%begin`, the scanner is put in that mode before it scans the next token.  The grammar!
+    /// macro checks that each such rule is reduced by default, so that the token after it has
+    /// not been scanned yet when it is reduced.
+    pub fn parse_scanner<'a>(&mut self, ctx: &mut AppContext, scanner: &mut Scanner<'a>, value: fn(u32, &str) -> SymbolValue) -> FinishParseResult<SymbolValue> {
+        loop {
+            if let Some(mode) = self.take_lexer_mode() {
+                scanner.set_mode(mode);
+            }
+            let (token, text, location) = match scanner.next() {
+                Some(t) => t,
+                None => return self.finish(ctx)
+            };
+            if !self.parse_token(ctx, token, value(token, text), location) {
+                return self.stopped();
+            }
+        }
+    }
+
+    /// Returns the trivia which the parser could not attach to a token, because the input had no
+    /// tokens, and clears it.
+    pub fn take_trivia(&mut self) -> Vec<SymbolValue> {
+        mem::replace(&mut self.trivia, Vec::new())
+    }
+
+    // Keeps a trivia token, which the parser does not parse.  Trivia after a token, up to the
+    // first trivia which ends a line, is trailing trivia of that token; the rest waits for the
+    // next token, as its leading trivia.
+    fn push_trivia(&mut self, token: u32, lval: SymbolValue) {
+        let ends_line = self.tables.yytrivia[token as uint] == 2;
+        let mut lval = lval;
+        if self.trailing_open && self.trivia.len() == 0 {
+            match self.attach_trailing_trivia(lval) {
+                None => {
+                    self.trailing_open = !ends_line;
+                    return;
+                }
+                Some(t) => lval = t
+            }
+        }
+        self.trailing_open = false;
+        self.trivia.push(lval);
+    }
+
+    // Attaches trailing trivia to the last token on the value stack.  Returns the trivia if there
+    // is no token to attach it to.
+    fn attach_trailing_trivia(&mut self, trivia: SymbolValue) -> Option<SymbolValue> {
+        let attach = match self.tables.trailing_trivia {
+            Some(attach) => attach,
+            None => return Some(trivia)
+        };
+        let mut trivia = trivia;
+        let mut i = self.value_stack.len();
+        while i > 0 {
+            i -= 1;
+            trivia = match attach(&mut self.value_stack[i], trivia) {
+                Ok(()) => return None,
+                Err(trivia) => trivia
+            };
+        }
+        Some(trivia)
+    }
+
+    /// Returns the lexer mode that the last rule with a `%begin` entered, if one has been
+    /// reduced since the mode was last taken, and clears it.  A lexer which is driven by hand,
+    /// rather than by `parse_scanner`, calls this before it scans each token.
+    pub fn take_lexer_mode(&mut self) -> Option<uint> {
+        self.lexer_mode.take()
+    }
+
+    // Pushes a token for parse() and its variants.  Returns false if parsing must stop: after
+    // a syntax error under RecoveryPolicy::Strict, or one which could not be recovered from, or
+    // when the stack is too deep.
+    fn parse_token(&mut self, ctx: &mut AppContext, token: u32, lval: SymbolValue, location: Location) -> bool {
+        match self.push_token_at(ctx, token, lval, location) {
+            PushTokenResult::Ok => true,
+            PushTokenResult::SyntaxError => !(self.config.recovery == RecoveryPolicy::Strict || self.failed),
+            PushTokenResult::StackOverflow | PushTokenResult::ActionFailed => false
+        }
+    }
+
+    // The result of parse() and its variants, when parse_token has stopped them.
+    fn stopped(&mut self) -> FinishParseResult<SymbolValue> {
+        match self.action_error.take() {
+            Some(error) => FinishParseResult::ActionFailed(error),
+            None => FinishParseResult::SyntaxError
+        }
+    }
+
+    /// Parses a sequence of `(token, value)` pairs, as `parse` does.  The tokens are taken from
+    /// the iterator one at a time, as the parser needs them.  Their locations are their indices
+    /// in the sequence; see `lexer::Indexed`.
+    pub fn parse_tokens<I: Iterator<(u32, SymbolValue)>>(&mut self, ctx: &mut AppContext, tokens: I) -> FinishParseResult<SymbolValue> {
+        self.parse(ctx, &mut Indexed::new(tokens))
+    }
+
+    /// Pushes the final "end of input" token into the state machine, and checks whether the grammar has
+    /// accepted or rejected the sequence of tokens.
+    ///
+    /// Calling this method is the equivalent of returning `YYEOF` from a `yylex()` function in a YACC parser.
+    pub fn finish(&mut self, ctx: &mut AppContext) -> FinishParseResult<SymbolValue> {
+        assert!(self.state_stack.len() > 0);
+
+        // let mut yystate = self.state_stack[self.state_stack.len() - 1] as uint;
+
+        debug!("");
+        debug!("push_end: yystate={}  state_stack = {}", self.yystate, self.state_stack);
+
+        if self.failed {
+            return self.stopped();
+        }
+
+        // Trivia at the end of the input trails the last token.
+        let trivia = mem::replace(&mut self.trivia, Vec::new());
+        for t in trivia.into_iter() {
+            if let Some(t) = self.attach_trailing_trivia(t) {
+                self.trivia.push(t);
+            }
+        }
+
+        // Reduce for as long as the end of input allows.  There is no error recovery at the end of
+        // the input, since there are no tokens left to resynchronize with.
+        loop {
+            match self.tables.action(self.yystate, 0) {
+                TableAction::Reduce(rule) => {
+                    if !self.yyreduce(rule, ctx) {
+                        return self.stopped();
+                    }
+                }
+                _ => break
+            }
+        }
+
+        // The input is accepted only in the final state, which is entered by reducing to the goal
+        // symbol.  One value on the stack is not enough: a start token, or the first symbol of
+        // the goal, leaves one value too.
+        let accepting = self.yystate == self.tables.yyfinal && self.value_stack.len() == 1;
+
+        if accepting && self.rejected {
+            // The input would be accepted, but only because a syntax error was discarded.
+            trace!(self, "state {}: not accepting, since a syntax error was discarded", self.yystate);
+            return FinishParseResult::SyntaxError;
+        }
+
+        if accepting {
+            debug!("accept");
+            trace!(self, "state {}: accept", self.yystate);
+            let final_lval = self.value_stack.pop().unwrap();
+            self.location_stack.pop();
+            return FinishParseResult::Accepted(final_lval);
+        }
+
+        debug!("done with all reductions.  yystate={}  state_stack={}", self.yystate, self.state_stack);
+
+        // If there is neither a shift nor a reduce action defined for this (state, token),
+        // then we have encountered a syntax error.
+
+        debug!("syntax error!  token is not recognized in this state.");
+        // The end of input is located at the end of the last symbol.
+        let location = Location::of_rhs(&[], self.location_stack.last());
+        self.syntax_error(0, location);
+        return FinishParseResult::SyntaxError;
+    }
+}
+
+// Returns the state that the parser goes to after reducing to the non-terminal 'lhs', when the
+// state on top of the stack (after popping the rhs) is 'state'.
+fn decode_goto(yygindex: &[u32], yytable: &[u32], yycheck: &[u32], yydgoto: &[u32], yyfinal: uint, state: uint, lhs: uint) -> uint {
+    if state == INITIAL_STATE && lhs == 0 {
+        return yyfinal;
+    }
+    let base = yygindex[lhs] as i32;
+    let index = base as int + state as int;
+    if base != 0 && index >= 0 && (index as uint) < yycheck.len() && yycheck[index as uint] as uint == state {
+        yytable[index as uint] as uint
+    }
+    else {
+        yydgoto[lhs] as uint
+    }
+}
+
+// Returns the index of the entry for 'token' in the row of yytable which starts at 'base', if
+// the row has an entry for it.
+fn table_index(yycheck: &[u32], base: u32, token: uint) -> Option<uint> {
+    let base = base as i32;
+    if base == 0 {
+        return None;
+    }
+    let index = base as int + token as int;
+    if index >= 0 && (index as uint) < yycheck.len() && yycheck[index as uint] as uint == token {
+        Some(index as uint)
+    }
+    else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::any::Any;
+    use std::rc::Rc;
+    use std::cell::RefCell;
+    use std::thread::{Thread, JoinGuard};
+
+    use {ParserState, ParserConfig, PushTokenResult, PushStep, FinishParseResult, Location};
+    use {ErrorRecovery, RecoveryAction};
+    use testing::{left, stmts, starts};
+    use testing::stmts::{A, SEMI};
+
+    // The value of each token: A counts 1, and the others count nothing.
+    fn value(token: u32) -> int {
+        if token == A { 1 } else { 0 }
+    }
+
+    fn push(parser: &mut ParserState<int, ()>, token: u32) -> PushTokenResult {
+        parser.push_token(&mut (), token, value(token))
+    }
+
+    fn is_ok(result: PushTokenResult) -> bool {
+        match result {
+            PushTokenResult::Ok => true,
+            _ => false
+        }
+    }
+
+    fn is_syntax_error(result: PushTokenResult) -> bool {
+        match result {
+            PushTokenResult::SyntaxError => true,
+            _ => false
+        }
+    }
+
+    fn accepted(result: FinishParseResult<int>) -> Option<int> {
+        match result {
+            FinishParseResult::Accepted(value) => Some(value),
+            _ => None
+        }
+    }
+
+    // Records the calls of the recovery hooks.  A syntax error is answered by inserting `insert`,
+    // if it is given, or else by discarding the token if `discard` is set.
+    struct Recorder {
+        events: Rc<RefCell<Vec<String>>>,
+        insert: Option<u32>,
+        discard: bool,
+    }
+
+    impl ErrorRecovery<int> for Recorder {
+        fn syntax_error(&mut self, state: uint, token: u32, expected: &[u32]) -> RecoveryAction<int> {
+            self.events.borrow_mut().push(format!("error {} {} {}", state, token, expected));
+            match self.insert {
+                Some(token) => RecoveryAction::Insert(token, value(token)),
+                None if self.discard => RecoveryAction::Discard,
+                None => RecoveryAction::Default
+            }
+        }
+
+        fn token_discarded(&mut self, token: u32, value: &int) {
+            self.events.borrow_mut().push(format!("discarded {} {}", token, value));
+        }
+
+        fn state_popped(&mut self, state: uint, value: &int) {
+            self.events.borrow_mut().push(format!("popped {} {}", state, value));
+        }
+
+        fn recovered(&mut self, state: uint) {
+            self.events.borrow_mut().push(format!("recovered {}", state));
+        }
+
+        fn recovery_failed(&mut self, token: u32) {
+            self.events.borrow_mut().push(format!("failed {}", token));
+        }
+    }
+
+    fn recorder(parser: &mut ParserState<int, ()>, insert: Option<u32>, discard: bool) -> Rc<RefCell<Vec<String>>> {
+        let events = Rc::new(RefCell::new(Vec::new()));
+        parser.set_error_recovery(box Recorder { events: events.clone(), insert: insert, discard: discard });
+        events
+    }
+
+    #[test]
+    fn tolerant_recovers_with_the_error_token() {
+        // The second A is a syntax error; "A SEMI" before it is popped, and "error SEMI" (whose
+        // value is 100) takes its place.
+        let mut parser = ParserState::with_config(stmts::tables(), ParserConfig::tolerant());
+        assert!(is_ok(push(&mut parser, A)));
+        assert!(is_syntax_error(push(&mut parser, A)));
+        {
+            let error = parser.last_error().unwrap();
+            assert_eq!((error.state, error.token, error.expected.clone()), (3, A, vec![SEMI]));
+        }
+        for &token in [SEMI, A, SEMI].iter() {
+            assert!(is_ok(push(&mut parser, token)));
+        }
+        assert_eq!(accepted(parser.finish(&mut ())), Some(101));
+    }
+
+    #[test]
+    fn tolerant_discards_tokens_after_the_error() {
+        // The third A cannot follow the error token, and is discarded without being reported.
+        let mut parser = ParserState::with_config(stmts::tables(), ParserConfig::tolerant());
+        assert!(is_ok(push(&mut parser, A)));
+        assert!(is_syntax_error(push(&mut parser, A)));
+        assert!(is_ok(push(&mut parser, A)));
+        assert!(is_ok(push(&mut parser, SEMI)));
+        assert_eq!(accepted(parser.finish(&mut ())), Some(100));
+    }
+
+    #[test]
+    fn tolerant_push_steps() {
+        let mut parser = ParserState::with_config(stmts::tables(), ParserConfig::tolerant());
+        assert_eq!(parser.push(&mut (), (A, 1, Location::default())), PushStep::NeedMore);
+        assert_eq!(parser.push(&mut (), (A, 1, Location::default())), PushStep::Recovered);
+        assert_eq!(parser.push(&mut (), (SEMI, 0, Location::default())), PushStep::NeedMore);
+        assert_eq!(accepted(parser.finish(&mut ())), Some(100));
+    }
+
+    #[test]
+    fn tolerant_fails_without_an_error_rule() {
+        // `left` has no rule with the error token, so recovery gives up, and the parse fails.
+        let mut parser = ParserState::with_config(left::tables(), ParserConfig::tolerant());
+        let events = recorder(&mut parser, None, false);
+        assert_eq!(parser.push(&mut (), (left::A, 1, Location::default())), PushStep::NeedMore);
+        match parser.push(&mut (), (left::B, 0, Location::default())) {
+            PushStep::Failed(PushTokenResult::SyntaxError) => {}
+            step => panic!("unexpected step {}", step)
+        }
+        assert!(is_syntax_error(push(&mut parser, left::A)));
+        assert!(accepted(parser.finish(&mut ())).is_none());
+        assert_eq!(*events.borrow(), vec!["error 1 258 [0, 257]".to_string(), "discarded 258 0".to_string(), "failed 258".to_string()]);
+    }
+
+    #[test]
+    fn strict_rejects_everything_after_an_error() {
+        let mut parser = ParserState::with_config(stmts::tables(), ParserConfig::strict());
+        let events = recorder(&mut parser, None, false);
+        assert!(is_ok(push(&mut parser, A)));
+        match parser.push(&mut (), (A, 1, Location::default())) {
+            PushStep::Failed(PushTokenResult::SyntaxError) => {}
+            step => panic!("unexpected step {}", step)
+        }
+        assert!(is_syntax_error(push(&mut parser, SEMI)));
+        assert!(is_syntax_error(push(&mut parser, A)));
+        assert!(accepted(parser.finish(&mut ())).is_none());
+        // Strict does not recover, so the hooks are not called.
+        assert_eq!(events.borrow().len(), 0);
+
+        // reset() starts over.
+        parser.reset();
+        for &token in [A, SEMI].iter() {
+            assert!(is_ok(push(&mut parser, token)));
+        }
+        assert_eq!(accepted(parser.finish(&mut ())), Some(1));
+    }
+
+    #[test]
+    fn hooks_see_each_step() {
+        let mut parser = ParserState::with_config(stmts::tables(), ParserConfig::tolerant());
+        let events = recorder(&mut parser, None, false);
+        for &token in [A, A, A, SEMI].iter() {
+            push(&mut parser, token);
+        }
+        assert_eq!(accepted(parser.finish(&mut ())), Some(100));
+        assert_eq!(*events.borrow(), vec![
+            "error 3 257 [258]".to_string(),
+            "popped 3 1".to_string(),
+            "recovered 4".to_string(),
+            "discarded 257 1".to_string()]);
+    }
+
+    #[test]
+    fn hooks_insert_a_token() {
+        // SEMI is inserted before the second A, so the input is read as "A SEMI A SEMI".
+        let mut parser = ParserState::with_config(stmts::tables(), ParserConfig::tolerant());
+        recorder(&mut parser, Some(SEMI), false);
+        assert!(is_ok(push(&mut parser, A)));
+        assert_eq!(parser.push(&mut (), (A, 1, Location::default())), PushStep::Recovered);
+        assert!(is_ok(push(&mut parser, SEMI)));
+        assert_eq!(accepted(parser.finish(&mut ())), Some(2));
+    }
+
+    #[test]
+    fn hooks_discard_a_token() {
+        // The second A is discarded, and the rest parses, but the input is not accepted.
+        let mut parser = ParserState::with_config(stmts::tables(), ParserConfig::tolerant());
+        recorder(&mut parser, None, true);
+        for &token in [A, A, SEMI].iter() {
+            push(&mut parser, token);
+        }
+        assert!(accepted(parser.finish(&mut ())).is_none());
+    }
+
+    // Parses `tokens` as the entry point of a start symbol does: it pushes the start token, and
+    // then parses the tokens.
+    fn parse_from(start: u32, tokens: &[u32]) -> Option<int> {
+        let mut parser = ParserState::new(starts::tables());
+        assert!(is_ok(parser.push_token(&mut (), start, 0)));
+        accepted(parser.parse_tokens(&mut (), tokens.iter().map(|&t| (t, value(t)))))
+    }
+
+    #[test]
+    fn start_symbols() {
+        assert_eq!(parse_from(starts::YYSTART_PAIR, &[starts::A, starts::B]), Some(1));
+        assert_eq!(parse_from(starts::YYSTART_LIST, &[starts::A, starts::COMMA, starts::A]), Some(2));
+        assert_eq!(parse_from(starts::YYSTART_LIST, &[starts::A, starts::B]), None);
+    }
+
+    #[test]
+    fn start_symbols_reject_empty_input() {
+        // Only the start token is on the stack, which is one value, but not the final state.
+        assert_eq!(parse_from(starts::YYSTART_PAIR, &[]), None);
+        assert_eq!(parse_from(starts::YYSTART_LIST, &[]), None);
+    }
+
+    #[test]
+    fn start_symbols_reject_truncated_input() {
+        assert_eq!(parse_from(starts::YYSTART_PAIR, &[starts::A]), None);
+        assert_eq!(parse_from(starts::YYSTART_LIST, &[starts::A, starts::COMMA]), None);
+    }
+
+    #[test]
+    fn finish_rejects_a_prefix() {
+        // "A" is the first symbol of "stmt : A SEMI", and the only value on the stack.
+        let mut parser = ParserState::new(stmts::tables());
+        assert!(is_ok(push(&mut parser, A)));
+        assert!(accepted(parser.finish(&mut ())).is_none());
+        assert_eq!(parser.last_error().unwrap().token, 0);
+    }
+
+    #[test]
+    fn concurrent_parses() {
+        // Each thread has a parser of its own, over the same static tables.
+        let guards: Vec<JoinGuard<Option<int>>> = range(1u, 9).map(|n| Thread::scoped(move || {
+            let mut parser = ParserState::new(stmts::tables());
+            for _ in range(0, n * 100) {
+                assert!(is_ok(push(&mut parser, A)));
+                assert!(is_ok(push(&mut parser, SEMI)));
+            }
+            accepted(parser.finish(&mut ()))
+        })).collect();
+        for (i, guard) in guards.into_iter().enumerate() {
+            let value = guard.join().ok().expect("a parsing thread panicked");
+            assert_eq!(value, Some(((i + 1) * 100) as int));
+        }
+    }
+
+    // The reduce function of stmts, except that stmt : A SEMI (rule 3) adds the value of "A A A",
+    // which it parses with a parser of its own, in the middle of the outer parser's reduction.
+    fn reduce_with_nested_parse(values: &mut Vec<int>, _: &[Location], _: Location, rule: uint, ctx: &mut ()) -> Result<int, Box<Any + 'static>> {
+        let len = [2u, 2, 1, 2, 2][rule];
+        let at = values.len() - len;
+        let sum = values.slice_from(at).iter().fold(0, |a, &b| a + b);
+        values.truncate(at);
+        if rule != 3 {
+            return Ok(sum);
+        }
+        let mut inner = ParserState::new(left::tables());
+        let nested = accepted(inner.parse_tokens(ctx, [left::A, left::A, left::A].iter().map(|&t| (t, 1))));
+        Ok(sum + nested.expect("the nested parse failed"))
+    }
+
+    #[test]
+    fn nested_parse_in_an_action() {
+        let mut tables = stmts::tables();
+        tables.reduce = reduce_with_nested_parse;
+        let mut parser = ParserState::new(tables);
+        for _ in range(0u, 2) {
+            assert!(is_ok(push(&mut parser, A)));
+            assert!(is_ok(push(&mut parser, SEMI)));
+        }
+        // Each statement is 1 for its A, and 3 for the nested parse.
+        assert_eq!(accepted(parser.finish(&mut ())), Some(8));
+    }
+}
@@ -0,0 +1,46 @@
+// Hooks into the error recovery of a parser.
+//
+// The built-in recovery is YACC's: pop states until the `error` token can be shifted, and then
+// discard tokens until one can be shifted.  An ErrorRecovery is told about each step of that
+// process, and is asked first whether it wants to repair the input some other way, for example
+// by inserting a missing token.  This allows recovery policies to be written outside of the
+// runtime.
+
+/// What a parser does about a syntax error.  Returned by `ErrorRecovery::syntax_error`.
+pub enum RecoveryAction<SymbolValue> {
+    /// Recover with the `error` token, as YACC does.
+    Default,
+    /// Push this token (with this value) first, and then push the offending token again.  The
+    /// inserted token must be one that the parser accepts in its current state; if it is not,
+    /// the parser recovers as for `Default`.  If the offending token still cannot be shifted,
+    /// it is discarded.
+    Insert(u32, SymbolValue),
+    /// Discard the offending token, without popping any states.  Parsing continues, but since
+    /// the error was not recovered from, `finish` returns `SyntaxError` rather than accepting.
+    Discard,
+}
+
+/// Receives notice of each step of error recovery, and may choose how to recover.  Install one
+/// with `ParserState::set_error_recovery`.  All methods have default implementations, which do
+/// nothing, so an implementation need only provide the ones that it is interested in.
+pub trait ErrorRecovery<SymbolValue> {
+    /// Called when a syntax error is reported.  `expected` is the set of tokens that the parser
+    /// would have accepted in `state`.
+    fn syntax_error(&mut self, _state: uint, _token: u32, _expected: &[u32]) -> RecoveryAction<SymbolValue> {
+        RecoveryAction::Default
+    }
+
+    /// Called when a token is discarded.
+    fn token_discarded(&mut self, _token: u32, _value: &SymbolValue) {}
+
+    /// Called when a state, and the value of the symbol that led to it, are popped from the stack
+    /// while looking for a state which can shift `error`.
+    fn state_popped(&mut self, _state: uint, _value: &SymbolValue) {}
+
+    /// Called when `error` has been shifted.  `state` is the state that the parser is in after
+    /// shifting it.
+    fn recovered(&mut self, _state: uint) {}
+
+    /// Called when no state on the stack can shift `error`.  The parse has then failed.
+    fn recovery_failed(&mut self, _token: u32) {}
+}
@@ -0,0 +1,89 @@
+// Translates the token numbers of an external lexer into the token values of a grammar.
+//
+// A lexer which was not generated from the grammar (a C scanner, a tree-sitter lexer, or a
+// lexer shared with another parser) numbers its tokens its own way.  "%external NUM = 258, ...;"
+// gives the external number of each token, and RACC generates EXTERNAL_TOKENS, a TokenMap with
+// the pairs sorted by external number, which is searched by bisection.  Several external numbers
+// may stand for the same token.
+//
+// The grammar! macro checks that each name is a token and that no external number is given
+// twice, and warns about tokens which have no external number, since the external lexer can
+// never produce them.  It cannot know which numbers the external lexer produces, so
+// TokenMap::check checks that at run time: given the numbers that the lexer uses, it returns
+// those which the map does not cover.  Remap translates the tokens of a lexer as they are read;
+// a number which the map does not cover becomes the error token, so that the parser reports it.
+
+#[cfg(not(feature = "std"))]
+use std::prelude::*;
+
+use Location;
+use ERROR_TOKEN;
+use lexer::Lexer;
+
+/// A translation from external token numbers to the token values of a grammar.  Grammars which
+/// use `%external` generate one, named `EXTERNAL_TOKENS`.
+pub struct TokenMap {
+    /// The external numbers, in ascending order.
+    pub external: &'static [u32],
+    /// The token value of each external number.
+    pub internal: &'static [u32],
+}
+
+impl TokenMap {
+    /// Returns the token value for the external number `token`, if the map has one.
+    pub fn get(&self, token: u32) -> Option<u32> {
+        let (mut lo, mut hi) = (0u, self.external.len());
+        while lo < hi {
+            let mid = (lo + hi) / 2;
+            if self.external[mid] < token {
+                lo = mid + 1;
+            }
+            else if self.external[mid] > token {
+                hi = mid;
+            }
+            else {
+                return Some(self.internal[mid]);
+            }
+        }
+        None
+    }
+
+    /// Returns the token value for the external number `token`, or the error token if the map
+    /// does not cover it.
+    pub fn translate(&self, token: u32) -> u32 {
+        self.get(token).unwrap_or(ERROR_TOKEN)
+    }
+
+    /// Checks that the map covers every external number in `tokens` (all of the numbers that
+    /// the external lexer can produce).  Returns the numbers which it does not cover, if any.
+    pub fn check(&self, tokens: &[u32]) -> Result<(), Vec<u32>> {
+        let missing: Vec<u32> = tokens.iter().map(|&t| t).filter(|&t| self.get(t).is_none()).collect();
+        if missing.len() == 0 { Ok(()) } else { Err(missing) }
+    }
+}
+
+/// A lexer which translates the token numbers of another lexer with a `TokenMap`.
+///
+/// ```ignore
+///     try!(EXTERNAL_TOKENS.check(c_scanner::ALL_TOKENS).map_err(|missing| ...));
+///     match parser.parse(&mut ctx, &mut Remap::new(&EXTERNAL_TOKENS, c_tokens)) { ... }
+/// ```
+pub struct Remap<'a, L> {
+    map: &'a TokenMap,
+    lexer: L,
+}
+
+impl<'a, L> Remap<'a, L> {
+    pub fn new(map: &'a TokenMap, lexer: L) -> Remap<'a, L> {
+        Remap { map: map, lexer: lexer }
+    }
+}
+
+impl<'a, SymbolValue, L: Lexer<SymbolValue>> Iterator<(u32, SymbolValue, Location)> for Remap<'a, L> {
+    fn next(&mut self) -> Option<(u32, SymbolValue, Location)> {
+        match self.lexer.next_token() {
+            Some((token, value, location)) => Some((self.map.translate(token), value, location)),
+            None => None
+        }
+    }
+}
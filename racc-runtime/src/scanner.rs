@@ -0,0 +1,162 @@
+// The scanner that runs the lexer generated from "%token NAME <pattern> ;" declarations.
+//
+// The DFA is stored in three tables.  The transitions of state s are the triples
+// (first, last, target) in ranges[3 * state_offsets[s] .. 3 * state_offsets[s + 1]], sorted by
+// character, so the transition for a character is found by binary search.  accept[s] is the
+// token that state s accepts, or SCAN_NONE or SCAN_SKIP.
+//
+// The scanner always takes the longest match.  Text that matches no pattern is returned as the
+// error token, one character at a time, so that the parser reports (and can recover from) it.
+//
+// A lexer with modes (declared by %lexer-state) has an initial state for each mode, in
+// mode_starts; the scanner starts each token in the initial state of its current mode.  begin[s]
+// is the mode that the scanner enters after taking the match of an accepting state s, if the
+// pattern was given one by %begin, or SCAN_NONE.  The parser can also change the mode, when it
+// reduces a rule which has a %begin; see ParserState::parse_scanner.
+
+#[cfg(not(feature = "std"))]
+use std::prelude::*;
+
+use std::str::CharRange;
+
+use Location;
+use ERROR_TOKEN;
+use keywords::KeywordTable;
+
+/// In `ScannerTables.accept`, a state which does not accept.
+pub const SCAN_NONE: u32 = 0xffff_ffff;
+
+/// In `ScannerTables.accept`, a state which accepts text that is skipped (given by `%skip`).
+pub const SCAN_SKIP: u32 = 0xffff_fffe;
+
+/// The DFA of a lexer, which is generated by the `grammar!` syntax extension from the `%token`
+/// and `%skip` declarations of a grammar.
+pub struct ScannerTables {
+    pub state_offsets: &'static [u32],
+    pub ranges: &'static [u32],
+    pub accept: &'static [u32],
+    /// The initial state of each mode.  Mode 0 is `INITIAL`.
+    pub mode_starts: &'static [u32],
+    /// The mode that each state enters when its match is taken, or `SCAN_NONE`.  Empty if no
+    /// pattern enters a mode.
+    pub begin: &'static [u32],
+}
+
+impl ScannerTables {
+    // Returns the state that the DFA moves to from `state` on `c`, if any.
+    fn next_state(&self, state: uint, c: char) -> Option<uint> {
+        let c = c as u32;
+        let mut lo = self.state_offsets[state] as uint;
+        let mut hi = self.state_offsets[state + 1] as uint;
+        while lo < hi {
+            let mid = (lo + hi) / 2;
+            if c < self.ranges[mid * 3] {
+                hi = mid;
+            }
+            else if c > self.ranges[mid * 3 + 1] {
+                lo = mid + 1;
+            }
+            else {
+                return Some(self.ranges[mid * 3 + 2] as uint);
+            }
+        }
+        None
+    }
+}
+
+/// Splits a string into tokens, using the tables of a generated lexer.  The scanner is an
+/// iterator of `(token, text, location)` triples, where the location is in bytes, so it is a
+/// `Lexer`; map the text of each token to its value before handing it to a parser.
+pub struct Scanner<'a> {
+    tables: &'static ScannerTables,
+    input: &'a str,
+    pos: uint,
+    mode: uint,
+    keywords: Option<&'static KeywordTable>,
+}
+
+impl<'a> Scanner<'a> {
+    /// Creates a scanner of `input`.  Grammars which declare patterns also generate a
+    /// `new_scanner` function, which calls this with their tables.
+    pub fn new(tables: &'static ScannerTables, input: &'a str) -> Scanner<'a> {
+        Scanner { tables: tables, input: input, pos: 0, mode: 0, keywords: None }
+    }
+
+    /// Creates a scanner of `input` which starts at the byte offset `pos`, which must be at the
+    /// start of a token.  The locations of the tokens are still offsets into all of `input`.
+    pub fn starting_at(tables: &'static ScannerTables, input: &'a str, pos: uint) -> Scanner<'a> {
+        assert!(pos <= input.len());
+        Scanner { tables: tables, input: input, pos: pos, mode: 0, keywords: None }
+    }
+
+    /// Makes the scanner return the token of each reserved keyword in `keywords`, in place of the
+    /// identifier token, when it scans the keyword's text.  The `new_scanner` function of a
+    /// grammar which declares `%keywords` does this.
+    pub fn with_keywords(mut self, keywords: &'static KeywordTable) -> Scanner<'a> {
+        self.keywords = Some(keywords);
+        self
+    }
+
+    /// The offset (in bytes) at which the next token starts.
+    pub fn position(&self) -> uint {
+        self.pos
+    }
+
+    /// The mode that the next token is scanned in, as one of the `LEX_` constants that the
+    /// grammar defines.  A scanner starts in mode 0, `LEX_INITIAL`.
+    pub fn mode(&self) -> uint {
+        self.mode
+    }
+
+    /// Scans the tokens after this point in `mode`.
+    pub fn set_mode(&mut self, mode: uint) {
+        assert!(mode < self.tables.mode_starts.len(), "the lexer has no mode {}", mode);
+        self.mode = mode;
+    }
+}
+
+impl<'a> Iterator<(u32, &'a str, Location)> for Scanner<'a> {
+    fn next(&mut self) -> Option<(u32, &'a str, Location)> {
+        loop {
+            let start = self.pos;
+            if start >= self.input.len() {
+                return None;
+            }
+
+            // Run the DFA for as long as it can, remembering the last accepting state.
+            let mut state = self.tables.mode_starts[self.mode] as uint;
+            let mut at = start;
+            let mut matched: Option<(uint, uint)> = None;     // (accepting state, end)
+            while at < self.input.len() {
+                let CharRange { ch, next } = self.input.char_range_at(at);
+                state = match self.tables.next_state(state, ch) {
+                    Some(state) => state,
+                    None => break
+                };
+                at = next;
+                if self.tables.accept[state] != SCAN_NONE {
+                    matched = Some((state, at));
+                }
+            }
+
+            let (token, end) = match matched {
+                Some((state, end)) => {
+                    if self.tables.begin.len() != 0 && self.tables.begin[state] != SCAN_NONE {
+                        self.mode = self.tables.begin[state] as uint;
+                    }
+                    (self.tables.accept[state], end)
+                }
+                None => (ERROR_TOKEN, self.input.char_range_at(start).next)
+            };
+            self.pos = end;
+            if token != SCAN_SKIP {
+                let text = self.input.slice(start, end);
+                let token = match self.keywords {
+                    Some(keywords) => keywords.reserved(token, text),
+                    None => token
+                };
+                return Some((token, text, Location { start: start, end: end }));
+            }
+        }
+    }
+}
@@ -0,0 +1,509 @@
+// Parser tables for the unit tests of the runtime, for a few small grammars, so that the runtime
+// can be tested without the grammar! macro.  The tables are laid out as the macro lays them out
+// (see racc_core::tables): tokens are numbered from 257, rules from 1 (rule 0 accepts), and the
+// actions which lost conflicts are in YYCONFLICTS.  The values of the symbols are ints, except
+// in `ambig`, where they are the text of the expression, with parentheses which show how it
+// was parsed.
+
+// Pops the values of the right-hand side of a rule, and returns them in order.
+fn rhs<V: Clone>(values: &mut Vec<V>, len: uint) -> Vec<V> {
+    let at = values.len() - len;
+    let rhs = values.slice_from(at).to_vec();
+    values.truncate(at);
+    rhs
+}
+
+// The action of the grammars whose values are ints: the sum of the values of the right-hand
+// side.
+fn sum(values: &mut Vec<int>, len: uint) -> int {
+    rhs(values, len).iter().fold(0, |a, &b| a + b)
+}
+
+static NONE_U16: [u16, ..0] = [];
+static NONE_U32: [u32, ..0] = [];
+
+// Builds the ParserTables of a grammar from the tables of the module that it is used in.
+macro_rules! tables {
+    ($reduce:expr, $hash:expr) => (
+        ParserTables {
+            yyrindex: YYRINDEX.as_slice(),
+            yysindex: YYSINDEX.as_slice(),
+            yygindex: YYGINDEX.as_slice(),
+            yytable: YYTABLE.as_slice(),
+            yydgoto: YYDGOTO.as_slice(),
+            yydefred: YYDEFRED.as_slice(),
+            yylhs: YYLHS.as_slice(),
+            yylen: YYLEN.as_slice(),
+            yycheck: YYCHECK.as_slice(),
+            yyname: YYNAME.as_slice(),
+            yyfinal: YYFINAL,
+            yyrules: YYRULES.as_slice(),
+            yyvarname: YYVARNAME.as_slice(),
+            yyhash: $hash,
+            yyconflicts: YYCONFLICTS.as_slice(),
+            yylexbegin: super::NONE_U16.as_slice(),
+            yytrivia: super::NONE_U16.as_slice(),
+            yystos: super::NONE_U32.as_slice(),
+            yykernelindex: super::NONE_U32.as_slice(),
+            yykernel: super::NONE_U32.as_slice(),
+            yyrhsindex: super::NONE_U32.as_slice(),
+            yyrhs: super::NONE_U32.as_slice(),
+            reduce: $reduce,
+            error_node: None,
+            leading_trivia: None,
+            trailing_trivia: None,
+        }
+    )
+}
+
+// s : s A | A ;    (B is not used)
+pub mod left {
+    use std::any::Any;
+
+    use {ParserTables, Location};
+
+    pub const A: u32 = 257;
+    pub const B: u32 = 258;
+
+    const YYFINAL: uint = 1;
+
+    static YYDEFRED: [u32, ..4] = [
+        0, 0, 2, 1,
+    ];
+    static YYDGOTO: [u32, ..1] = [
+        0,
+    ];
+    static YYSINDEX: [u32, ..4] = [
+        4294967039, 4294967040, 0, 0,
+    ];
+    static YYRINDEX: [u32, ..4] = [
+        0, 0, 0, 0,
+    ];
+    static YYGINDEX: [u32, ..1] = [
+        0,
+    ];
+    static YYTABLE: [u32, ..2] = [
+        2, 3,
+    ];
+    static YYCHECK: [u32, ..2] = [
+        257, 257,
+    ];
+    static YYLHS: [u16, ..3] = [
+        65535, 0, 0,
+    ];
+    static YYLEN: [u16, ..3] = [
+        2, 2, 1,
+    ];
+    static YYNAME: [&'static str, ..259] = [
+        "$end", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "",
+        "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "",
+        "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "",
+        "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "",
+        "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "",
+        "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "",
+        "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "",
+        "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "",
+        "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "",
+        "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "",
+        "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "",
+        "", "", "", "", "", "A", "B",
+    ];
+    static YYRULES: [&'static str, ..3] = [
+        "(r2) $accept : s $end",
+        "(r3) s : s A",
+        "(r4) s : A",
+    ];
+    static YYVARNAME: [&'static str, ..1] = [
+        "s",
+    ];
+    static YYCONFLICTS: [u32, ..0] = [];
+
+    fn reduce(values: &mut Vec<int>, _: &[Location], _: Location, rule: uint, _: &mut ()) -> Result<int, Box<Any + 'static>> {
+        Ok(super::sum(values, YYLEN[rule] as uint))
+    }
+
+    pub fn tables() -> ParserTables<int, ()> {
+        tables!(reduce, 1)
+    }
+}
+
+// s : A s | A ;    (B is not used)
+pub mod right {
+    use std::any::Any;
+
+    use {ParserTables, Location};
+
+    pub const A: u32 = 257;
+    pub const B: u32 = 258;
+
+    const YYFINAL: uint = 1;
+
+    static YYDEFRED: [u32, ..4] = [
+        0, 0, 0, 1,
+    ];
+    static YYDGOTO: [u32, ..1] = [
+        3,
+    ];
+    static YYSINDEX: [u32, ..4] = [
+        4294967039, 0, 4294967040, 0,
+    ];
+    static YYRINDEX: [u32, ..4] = [
+        0, 0, 2, 0,
+    ];
+    static YYGINDEX: [u32, ..1] = [
+        0,
+    ];
+    static YYTABLE: [u32, ..3] = [
+        2, 2, 2,
+    ];
+    static YYCHECK: [u32, ..3] = [
+        257, 257, 0,
+    ];
+    static YYLHS: [u16, ..3] = [
+        65535, 0, 0,
+    ];
+    static YYLEN: [u16, ..3] = [
+        2, 2, 1,
+    ];
+    static YYNAME: [&'static str, ..259] = [
+        "$end", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "",
+        "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "",
+        "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "",
+        "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "",
+        "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "",
+        "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "",
+        "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "",
+        "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "",
+        "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "",
+        "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "",
+        "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "",
+        "", "", "", "", "", "A", "B",
+    ];
+    static YYRULES: [&'static str, ..3] = [
+        "(r2) $accept : s $end",
+        "(r3) s : A s",
+        "(r4) s : A",
+    ];
+    static YYVARNAME: [&'static str, ..1] = [
+        "s",
+    ];
+    static YYCONFLICTS: [u32, ..0] = [];
+
+    fn reduce(values: &mut Vec<int>, _: &[Location], _: Location, rule: uint, _: &mut ()) -> Result<int, Box<Any + 'static>> {
+        Ok(super::sum(values, YYLEN[rule] as uint))
+    }
+
+    pub fn tables() -> ParserTables<int, ()> {
+        tables!(reduce, 2)
+    }
+}
+
+// s : A | s A ;    (the rules of `left`, in the other order)
+pub mod reordered {
+    use std::any::Any;
+
+    use {ParserTables, Location};
+
+    pub const A: u32 = 257;
+    pub const B: u32 = 258;
+
+    const YYFINAL: uint = 1;
+
+    static YYDEFRED: [u32, ..4] = [
+        0, 0, 1, 2,
+    ];
+    static YYDGOTO: [u32, ..1] = [
+        0,
+    ];
+    static YYSINDEX: [u32, ..4] = [
+        4294967039, 4294967040, 0, 0,
+    ];
+    static YYRINDEX: [u32, ..4] = [
+        0, 0, 0, 0,
+    ];
+    static YYGINDEX: [u32, ..1] = [
+        0,
+    ];
+    static YYTABLE: [u32, ..2] = [
+        2, 3,
+    ];
+    static YYCHECK: [u32, ..2] = [
+        257, 257,
+    ];
+    static YYLHS: [u16, ..3] = [
+        65535, 0, 0,
+    ];
+    static YYLEN: [u16, ..3] = [
+        2, 1, 2,
+    ];
+    static YYNAME: [&'static str, ..259] = [
+        "$end", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "",
+        "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "",
+        "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "",
+        "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "",
+        "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "",
+        "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "",
+        "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "",
+        "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "",
+        "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "",
+        "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "",
+        "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "",
+        "", "", "", "", "", "A", "B",
+    ];
+    static YYRULES: [&'static str, ..3] = [
+        "(r2) $accept : s $end",
+        "(r3) s : A",
+        "(r4) s : s A",
+    ];
+    static YYVARNAME: [&'static str, ..1] = [
+        "s",
+    ];
+    static YYCONFLICTS: [u32, ..0] = [];
+
+    fn reduce(values: &mut Vec<int>, _: &[Location], _: Location, rule: uint, _: &mut ()) -> Result<int, Box<Any + 'static>> {
+        Ok(super::sum(values, YYLEN[rule] as uint))
+    }
+
+    pub fn tables() -> ParserTables<int, ()> {
+        tables!(reduce, 3)
+    }
+}
+
+// stmts : stmts stmt | stmt ;
+// stmt : A SEMI | error SEMI ;
+//
+// The value of a statement which was recovered from is 100.
+pub mod stmts {
+    use std::any::Any;
+
+    use {ParserTables, Location};
+
+    pub const A: u32 = 257;
+    pub const SEMI: u32 = 258;
+
+    const YYFINAL: uint = 1;
+
+    static YYDEFRED: [u32, ..8] = [
+        0, 0, 2, 0, 0, 1, 3, 4,
+    ];
+    static YYDGOTO: [u32, ..2] = [
+        0, 2,
+    ];
+    static YYSINDEX: [u32, ..8] = [
+        4294967040, 4294967042, 0, 4294967043, 4294967044, 0, 0, 0,
+    ];
+    static YYRINDEX: [u32, ..8] = [
+        0, 0, 0, 0, 0, 0, 0, 0,
+    ];
+    static YYGINDEX: [u32, ..2] = [
+        0, 3,
+    ];
+    static YYTABLE: [u32, ..7] = [
+        4, 3, 4, 3, 5, 6, 7,
+    ];
+    static YYCHECK: [u32, ..7] = [
+        256, 257, 256, 257, 1, 258, 258,
+    ];
+    static YYLHS: [u16, ..5] = [
+        65535, 0, 0, 1, 1,
+    ];
+    static YYLEN: [u16, ..5] = [
+        2, 2, 1, 2, 2,
+    ];
+    static YYNAME: [&'static str, ..259] = [
+        "$end", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "",
+        "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "",
+        "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "",
+        "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "",
+        "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "",
+        "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "",
+        "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "",
+        "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "",
+        "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "",
+        "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "",
+        "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "",
+        "", "", "", "", "error", "A", "SEMI",
+    ];
+    static YYRULES: [&'static str, ..5] = [
+        "(r2) $accept : stmts $end",
+        "(r3) stmts : stmts stmt",
+        "(r4) stmts : stmt",
+        "(r5) stmt : A SEMI",
+        "(r6) stmt : error SEMI",
+    ];
+    static YYVARNAME: [&'static str, ..2] = [
+        "stmts", "stmt",
+    ];
+    static YYCONFLICTS: [u32, ..0] = [];
+
+    fn reduce(values: &mut Vec<int>, _: &[Location], _: Location, rule: uint, _: &mut ()) -> Result<int, Box<Any + 'static>> {
+        let sum = super::sum(values, YYLEN[rule] as uint);
+        Ok(if rule == 4 { 100 } else { sum })
+    }
+
+    pub fn tables() -> ParserTables<int, ()> {
+        tables!(reduce, 4)
+    }
+}
+
+// $start : YYSTART_PAIR pair | YYSTART_LIST list ; pair : A B ; list : list COMMA A | A ;
+// These are the rules that the grammar! macro adds for two start symbols, pair and list: the
+// value of $start is that of the start symbol.
+pub mod starts {
+    use std::any::Any;
+
+    use {ParserTables, Location};
+
+    pub const A: u32 = 257;
+    pub const B: u32 = 258;
+    pub const COMMA: u32 = 259;
+    pub const YYSTART_PAIR: u32 = 260;
+    pub const YYSTART_LIST: u32 = 261;
+
+    const YYFINAL: uint = 1;
+
+    static YYDEFRED: [u32, ..11] = [
+        0, 0, 0, 0, 1, 0, 0, 5, 3, 0, 4,
+    ];
+    static YYDGOTO: [u32, ..3] = [
+        0, 4, 6,
+    ];
+    static YYSINDEX: [u32, ..11] = [
+        4294967036, 0, 4294967041, 4294967042, 0, 4294967043, 4294967044, 0, 0, 4294967045, 0,
+    ];
+    static YYRINDEX: [u32, ..11] = [
+        0, 0, 0, 0, 0, 0, 4, 0, 0, 0, 0,
+    ];
+    static YYGINDEX: [u32, ..3] = [
+        0, 0, 0,
+    ];
+    static YYTABLE: [u32, ..8] = [
+        2, 3, 5, 7, 2, 8, 10, 9,
+    ];
+    static YYCHECK: [u32, ..8] = [
+        260, 261, 257, 257, 0, 258, 257, 259,
+    ];
+    static YYLHS: [u16, ..6] = [
+        65535, 0, 0, 1, 2, 2,
+    ];
+    static YYLEN: [u16, ..6] = [
+        2, 2, 2, 2, 3, 1,
+    ];
+    static YYNAME: [&'static str, ..262] = [
+        "$end", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "",
+        "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "",
+        "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "",
+        "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "",
+        "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "",
+        "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "",
+        "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "",
+        "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "",
+        "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "",
+        "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "",
+        "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "",
+        "", "", "", "", "", "A", "B", "COMMA", "YYSTART_PAIR", "YYSTART_LIST",
+    ];
+    static YYRULES: [&'static str, ..6] = [
+        "(r2) $accept : $start $end",
+        "(r3) $start : YYSTART_PAIR pair",
+        "(r4) $start : YYSTART_LIST list",
+        "(r5) pair : A B",
+        "(r6) list : list COMMA A",
+        "(r7) list : A",
+    ];
+    static YYVARNAME: [&'static str, ..3] = [
+        "$start", "pair", "list",
+    ];
+    static YYCONFLICTS: [u32, ..0] = [];
+
+    fn reduce(values: &mut Vec<int>, _: &[Location], _: Location, rule: uint, _: &mut ()) -> Result<int, Box<Any + 'static>> {
+        Ok(match rule {
+            1 | 2 => super::rhs(values, 2)[1],
+            _ => super::sum(values, YYLEN[rule] as uint)
+        })
+    }
+
+    pub fn tables() -> ParserTables<int, ()> {
+        tables!(reduce, 6)
+    }
+}
+
+// e : e PLUS e | NUM ;
+//
+// The grammar is ambiguous: the state after `e PLUS e` shifts PLUS, and the reduction by
+// `e : e PLUS e` which lost to the shift is in YYCONFLICTS.
+pub mod ambig {
+    use std::any::Any;
+
+    use {ParserTables, Location};
+
+    pub const NUM: u32 = 257;
+    pub const PLUS: u32 = 258;
+
+    const YYFINAL: uint = 1;
+
+    static YYDEFRED: [u32, ..5] = [
+        0, 0, 2, 0, 0,
+    ];
+    static YYDGOTO: [u32, ..1] = [
+        4,
+    ];
+    static YYSINDEX: [u32, ..5] = [
+        4294967039, 4294967040, 0, 4294967042, 4294967043,
+    ];
+    static YYRINDEX: [u32, ..5] = [
+        0, 0, 0, 0, 1,
+    ];
+    static YYGINDEX: [u32, ..1] = [
+        0,
+    ];
+    static YYTABLE: [u32, ..6] = [
+        2, 1, 3, 2, 0, 3,
+    ];
+    static YYCHECK: [u32, ..6] = [
+        257, 0, 258, 257, 4294967295, 258,
+    ];
+    static YYLHS: [u16, ..3] = [
+        65535, 0, 0,
+    ];
+    static YYLEN: [u16, ..3] = [
+        2, 3, 1,
+    ];
+    static YYNAME: [&'static str, ..259] = [
+        "$end", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "",
+        "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "",
+        "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "",
+        "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "",
+        "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "",
+        "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "",
+        "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "",
+        "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "",
+        "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "",
+        "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "",
+        "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "", "",
+        "", "", "", "", "", "NUM", "PLUS",
+    ];
+    static YYRULES: [&'static str, ..3] = [
+        "(r2) $accept : e $end",
+        "(r3) e : e PLUS e",
+        "(r4) e : NUM",
+    ];
+    static YYVARNAME: [&'static str, ..1] = [
+        "e",
+    ];
+    static YYCONFLICTS: [u32, ..3] = [
+        4, 258, 4294967295,
+    ];
+
+    fn reduce(values: &mut Vec<String>, _: &[Location], _: Location, rule: uint, _: &mut ()) -> Result<String, Box<Any + 'static>> {
+        let v = super::rhs(values, YYLEN[rule] as uint);
+        Ok(match rule {
+            1 => format!("({}+{})", v[0], v[2]),
+            _ => v[0].clone()
+        })
+    }
+
+    pub fn tables() -> ParserTables<String, ()> {
+        tables!(reduce, 5)
+    }
+}
@@ -0,0 +1,179 @@
+// Concrete syntax trees, for parsers generated with "%define tree cst".
+//
+// Such a parser has no actions.  Every reduction makes a node, whose children are the nodes of
+// the symbols of the rule, so the tree that the parser returns records the whole derivation of
+// the input.  Tokens are the leaves; they carry whatever value the lexer gave them.
+//
+// When the parser recovers from a syntax error, the symbols that it pops and the tokens that it
+// discards become the children of an Error node, which is the value of the error token.  So no
+// input is lost from the tree, and the location of each Error node is the text that it skipped.
+//
+// A grammar may declare trivia (%trivia): tokens such as comments and white space, which the
+// rules do not mention, but which a formatter or a documentation tool needs.  The parser does
+// not parse them; it attaches them to the tokens around them, which become WithTrivia nodes.
+// Trivia after a token, up to and including the first trivia which ends a line, is the token's
+// trailing trivia; the rest is the leading trivia of the token after it.  So a comment at the end
+// of a line belongs to the last token of that line, and a comment on a line of its own belongs
+// to the first token of the next line.  Trivia does not change the locations of the nodes.
+
+#[cfg(not(feature = "std"))]
+use std::prelude::*;
+
+use std::mem;
+
+use Location;
+
+/// A node of a concrete syntax tree.  `TokenValue` is the value type given in the grammar.
+#[deriving(Clone,PartialEq,Show)]
+pub enum Node<TokenValue> {
+    /// A token: its number (as given to `push_token`), its value, and its location.
+    Token(u32, TokenValue, Location),
+    /// A reduction by a rule: the number of the rule (as in `GrammarInfo::rule_text`), the
+    /// location of the rule, and one child for each symbol of the rule, in order.
+    Rule(uint, Location, Vec<Node<TokenValue>>),
+    /// The input that the parser skipped to recover from a syntax error: its location, and the
+    /// symbols and tokens that were popped or discarded, in order.
+    Error(Location, Vec<Node<TokenValue>>),
+    /// A token with trivia: the leading trivia, the token, and the trailing trivia.  The token
+    /// is a `Token` node, and so is each trivia.
+    WithTrivia(Vec<Node<TokenValue>>, Box<Node<TokenValue>>, Vec<Node<TokenValue>>),
+}
+
+impl<TokenValue> Node<TokenValue> {
+    /// Pops the nodes of the `len` symbols of `rule` from the value stack, and returns a node
+    /// which has them as its children.  This is called by generated code.
+    pub fn from_stack(value_stack: &mut Vec<Node<TokenValue>>, rule: uint, len: uint, location: Location) -> Node<TokenValue> {
+        assert!(value_stack.len() >= len);
+        let mut children: Vec<Node<TokenValue>> = Vec::with_capacity(len);
+        for _ in range(0, len) {
+            children.push(value_stack.pop().unwrap());
+        }
+        children.reverse();
+        Node::Rule(rule, location, children)
+    }
+
+    /// Returns an error node which covers `location` and has `children`.  The children of any
+    /// child which is itself an error node are taken in its place, so that the input skipped by
+    /// one recovery is a single node.  This is called by the parser while it recovers.
+    pub fn error(children: Vec<Node<TokenValue>>, location: Location) -> Node<TokenValue> {
+        let mut flat: Vec<Node<TokenValue>> = Vec::with_capacity(children.len());
+        for child in children.into_iter() {
+            match child {
+                Node::Error(_, grandchildren) => flat.extend(grandchildren.into_iter()),
+                child => flat.push(child)
+            }
+        }
+        Node::Error(location, flat)
+    }
+
+    /// Returns a token node with leading trivia.  This is called by the parser, for the first
+    /// token after some trivia.
+    pub fn with_leading_trivia(token: Node<TokenValue>, trivia: Vec<Node<TokenValue>>) -> Node<TokenValue> {
+        Node::WithTrivia(trivia, box token, Vec::new())
+    }
+
+    /// Adds trailing trivia to the last token of `node`, and returns it if `node` has no tokens.
+    /// This is called by the parser, for trivia after a token.
+    pub fn attach_trailing_trivia(node: &mut Node<TokenValue>, trivia: Node<TokenValue>) -> Result<(), Node<TokenValue>> {
+        match *node {
+            Node::WithTrivia(_, _, ref mut trailing) => {
+                trailing.push(trivia);
+                return Ok(());
+            }
+            Node::Rule(_, _, ref mut children) | Node::Error(_, ref mut children) => {
+                let mut trivia = trivia;
+                for child in children.iter_mut().rev() {
+                    trivia = match Node::attach_trailing_trivia(child, trivia) {
+                        Ok(()) => return Ok(()),
+                        Err(trivia) => trivia
+                    };
+                }
+                return Err(trivia);
+            }
+            Node::Token(..) => {}
+        }
+        let token = mem::replace(node, Node::Error(Location::default(), Vec::new()));
+        *node = Node::WithTrivia(Vec::new(), box token, vec![trivia]);
+        Ok(())
+    }
+
+    /// Returns the location of the node.  The location of a token does not include its trivia.
+    pub fn location(&self) -> Location {
+        match *self {
+            Node::Token(_, _, location) => location,
+            Node::Rule(_, location, _) => location,
+            Node::Error(location, _) => location,
+            Node::WithTrivia(_, ref token, _) => token.location(),
+        }
+    }
+
+    /// Returns the children of the node.  Tokens have none, whether or not they have trivia.
+    pub fn children(&self) -> &[Node<TokenValue>] {
+        match *self {
+            Node::Token(..) | Node::WithTrivia(..) => &[],
+            Node::Rule(_, _, ref children) => children.as_slice(),
+            Node::Error(_, ref children) => children.as_slice(),
+        }
+    }
+
+    /// Returns the leading trivia of a token: the comments and white space before it.
+    pub fn leading_trivia(&self) -> &[Node<TokenValue>] {
+        match *self {
+            Node::WithTrivia(ref leading, _, _) => leading.as_slice(),
+            _ => &[],
+        }
+    }
+
+    /// Returns the trailing trivia of a token: the trivia after it, to the end of its line.
+    pub fn trailing_trivia(&self) -> &[Node<TokenValue>] {
+        match *self {
+            Node::WithTrivia(_, _, ref trailing) => trailing.as_slice(),
+            _ => &[],
+        }
+    }
+
+    /// Returns the number of the rule that made the node, or `None` if it is a token or an
+    /// error.
+    pub fn rule(&self) -> Option<uint> {
+        match *self {
+            Node::Rule(rule, _, _) => Some(rule),
+            _ => None,
+        }
+    }
+
+    /// Returns the number of the token, or `None` if the node was made by a rule or is an error.
+    pub fn token(&self) -> Option<u32> {
+        match *self {
+            Node::Token(token, _, _) => Some(token),
+            Node::WithTrivia(_, ref token, _) => token.token(),
+            _ => None,
+        }
+    }
+
+    /// Returns true if the node is an error node.
+    pub fn is_error(&self) -> bool {
+        match *self {
+            Node::Error(..) => true,
+            _ => false,
+        }
+    }
+
+    /// Returns the locations of the error nodes in the tree, in order.  These are the places to
+    /// report syntax errors, or to attach diagnostics to.
+    pub fn error_locations(&self) -> Vec<Location> {
+        let mut locations: Vec<Location> = Vec::new();
+        self.collect_errors(&mut locations);
+        locations
+    }
+
+    fn collect_errors(&self, locations: &mut Vec<Location>) {
+        match *self {
+            Node::Error(location, _) => locations.push(location),
+            _ => {
+                for child in self.children().iter() {
+                    child.collect_errors(locations);
+                }
+            }
+        }
+    }
+}
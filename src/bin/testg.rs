@@ -9,7 +9,7 @@ extern crate log;
 
 #[phase(plugin, link)] extern crate racc;
 
-use racc::runtime::{ParserState,ParserTables,FinishParseResult};
+use racc::runtime::{ParserState,ParserTables,Location,FinishParseResult};
 
 struct AppContext {
     x: uint
@@ -90,5 +90,8 @@ fn main()
         FinishParseResult::SyntaxError => {
             println!("SyntaxError");
         }
+        FinishParseResult::ActionFailed(error) => {
+            println!("{}", error);
+        }
     }
 }
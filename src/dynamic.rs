@@ -0,0 +1,63 @@
+// Builds parsers from grammars at run time, without generating any code.
+//
+// The grammar! macro analyzes a grammar when the program is compiled, and compiles its tables
+// and actions into the program.  This module does the same analysis while the program runs, so
+// that it can parse with grammars that it did not know when it was built, such as grammars
+// supplied by its users.  The result is an Interpreter (see racc_runtime::interp), whose
+// actions are closures or other RuleAction implementations.
+//
+//      let gram = Grammar::builder()
+//          .token("NUM").token("PLUS")
+//          .rule("Expr", &["Expr", "PLUS", "NUM"])
+//          .rule("Expr", &["NUM"])
+//          .build()
+//          .unwrap();
+//
+//      let mut parser = try!(dynamic::parser_from_grammar(&gram, box |_: &mut (), values: Vec<int>| values[0]));
+//      parser.set_action(1, box |_: &mut (), values: Vec<int>| values[0] + values[2]);
+
+use racc_core::{Grammar, GenError, LrType};
+use racc_core::tables::{make_tables, PackedTables};
+use racc_runtime::interp::{Interpreter, LoadedTables, RuleAction};
+
+/// Converts tables built by `racc_core::tables::make_tables` into tables that an `Interpreter`
+/// can run.
+pub fn load_tables(tables: &PackedTables) -> LoadedTables {
+    let to_u32 = |table: &Vec<i32>| -> Vec<u32> { table.iter().map(|&v| v as u32).collect() };
+    let to_u16 = |table: &Vec<i16>| -> Vec<u16> { table.iter().map(|&v| v as u16).collect() };
+    LoadedTables {
+        yyrindex: to_u32(&tables.yyrindex),
+        yysindex: to_u32(&tables.yysindex),
+        yygindex: to_u32(&tables.yygindex),
+        yytable: to_u32(&tables.yytable),
+        yydgoto: to_u32(&tables.yydgoto),
+        yydefred: to_u32(&tables.yydefred),
+        yylhs: to_u16(&tables.yylhs),
+        yylen: to_u16(&tables.yylen),
+        yycheck: to_u32(&tables.yycheck),
+        yyname: tables.yyname.clone(),
+        yyfinal: tables.yyfinal,
+        yyrules: tables.yyrules.clone(),
+        yyvarname: tables.yyvarname.clone(),
+        yyhash: tables.yyhash,
+    }
+}
+
+/// Analyzes a grammar, building the kind of automaton given by `lr_type`, and returns its
+/// tables.  Conflicts are resolved as they are for the grammar! macro, but are not reported.
+pub fn tables_from_grammar(gram: &Grammar, lr_type: LrType) -> Result<LoadedTables, GenError> {
+    let analysis = try!(::racc_core::analyze_with(gram, lr_type));
+    let tables = try!(make_tables(gram, &analysis.lalr.gotos, &analysis.parser));
+    Ok(load_tables(&tables))
+}
+
+/// Builds an LALR(1) parser for a grammar.  `default_action` is the action of every rule, until
+/// the rule is given its own action with `Interpreter::set_action`.  Rules are numbered as in
+/// `LoadedTables.yyrules`: the first rule of the grammar is rule 1.
+pub fn parser_from_grammar<SymbolValue, AppContext>(
+    gram: &Grammar,
+    default_action: Box<RuleAction<SymbolValue, AppContext> + 'static>)
+    -> Result<Interpreter<SymbolValue, AppContext>, GenError> {
+    let tables = try!(tables_from_grammar(gram, LrType::Lalr));
+    Ok(Interpreter::new(tables, default_action))
+}
@@ -0,0 +1,146 @@
+// Grammar fragments, which grammars include.
+//
+// A large grammar can be split into pieces, each of which is a sequence of grammar items (token
+// definitions, directives, and rules), just as they are written in the body of grammar!.  A
+// piece is either a file, or a fragment declared with the grammar_fragment! macro:
+//
+//      grammar_fragment! { ExprRules; Expr : Expr PLUS Term | Term; Term : NUM; }
+//
+// A grammar includes a piece with "%include ExprRules;" or "%include "expr.racc";".  The items
+// of the piece are read in place of the directive, as if they had been written there, so its
+// rules join the rules of the same non-terminals in the grammar, and a token that it defines
+// must not be defined again.  A piece may include other pieces, but not itself.
+//
+// A piece can say which of its non-terminals a grammar may use, with "%export Expr, Term;".
+// The other non-terminals of the piece are internal: when it is included, they are renamed
+// with the name of the piece as a prefix (a fragment's name, or a file's name without its
+// extension), so "Atom" in ExprRules becomes "ExprRules_Atom".  Then one piece can be included
+// in grammars which have their own non-terminals with the same names, and two pieces can both
+// have an internal "Atom".  A piece without %export has no internal non-terminals.  Only the
+// symbols of rules and directives are renamed, not the names within actions, as with
+// parameterized rules.
+//
+// As with token sets, fragments are recorded in the compiler process, so a fragment must be
+// declared (in source order) before any grammar that includes it, within the same crate.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use syntax::ast;
+use syntax::ext::base::{ExtCtxt, MacResult, MacItems, DummyResult};
+use syntax::codemap::Span;
+use syntax::parse::token;
+use syntax::parse::token::{Token,BinOpToken};
+
+thread_local!(static FRAGMENTS: RefCell<HashMap<String, Vec<ast::TokenTree>>> = RefCell::new(HashMap::new()));
+
+// Returns the items of a fragment, or None if no fragment has that name.
+pub fn find_fragment(name: &str) -> Option<Vec<ast::TokenTree>> {
+    FRAGMENTS.with(|fragments| fragments.borrow().get(name).map(|tts| tts.clone()))
+}
+
+// Expands grammar_fragment! { <name> ; <items> }
+pub fn expand_grammar_fragment(cx: &mut ExtCtxt, sp: Span, tts: &[ast::TokenTree]) -> Box<MacResult+'static> {
+    let mut parser = cx.new_parser_from_tts(tts);
+    let name = parser.parse_ident().as_str().to_string();
+    parser.expect(&Token::Semi);
+    let mut items: Vec<ast::TokenTree> = Vec::new();
+    while parser.token != Token::Eof {
+        items.push(parser.parse_token_tree());
+    }
+
+    let already_defined = FRAGMENTS.with(|fragments| {
+        let mut fragments = fragments.borrow_mut();
+        if fragments.contains_key(&name) {
+            true
+        }
+        else {
+            fragments.insert(name.clone(), items);
+            false
+        }
+    });
+    if already_defined {
+        cx.span_err(sp, format!("grammar fragment '{}' is defined more than once", name).as_slice());
+        return DummyResult::any(sp);
+    }
+    MacItems::new(Vec::new().into_iter())
+}
+
+fn ident_of(tt: &ast::TokenTree) -> Option<String> {
+    match *tt {
+        ast::TtToken(_, Token::Ident(id, _)) => Some(id.as_str().to_string()),
+        _ => None
+    }
+}
+
+fn is_token(tt: &ast::TokenTree, t: &Token) -> bool {
+    match *tt {
+        ast::TtToken(_, ref u) => u == t,
+        _ => false
+    }
+}
+
+// Applies the %export directive of a piece, if it has one: removes it, and renames the
+// non-terminals which are defined by the piece but not exported.
+pub fn namespace(cx: &ExtCtxt, prefix: &str, tts: Vec<ast::TokenTree>) -> Vec<ast::TokenTree> {
+    // Find "%export <ident> , ... ;", and remove it.
+    let mut exports: Vec<(String, Span)> = Vec::new();
+    let mut items: Vec<ast::TokenTree> = Vec::with_capacity(tts.len());
+    let mut i = 0;
+    let mut has_export = false;
+    while i < tts.len() {
+        if is_token(&tts[i], &Token::BinOp(BinOpToken::Percent)) && i + 1 < tts.len()
+            && ident_of(&tts[i + 1]).as_ref().map(|s| s.as_slice()) == Some("export") {
+            has_export = true;
+            i += 2;
+            while i < tts.len() && !is_token(&tts[i], &Token::Semi) {
+                match tts[i] {
+                    ast::TtToken(sp, Token::Ident(id, _)) => exports.push((id.as_str().to_string(), sp)),
+                    ast::TtToken(_, Token::Comma) => {}
+                    ref tt => cx.span_err(tt.get_span(), "expected the name of a non-terminal")
+                }
+                i += 1;
+            }
+            i += 1;
+            continue;
+        }
+        items.push(tts[i].clone());
+        i += 1;
+    }
+    if !has_export {
+        return items;
+    }
+
+    // The non-terminals that the piece defines are the names which begin an item, followed by
+    // ":" (a rule) or "<" (a parameterized rule).
+    let mut defined: Vec<String> = Vec::new();
+    let mut start = true;
+    for i in range(0, items.len()) {
+        if start && i + 1 < items.len() && (is_token(&items[i + 1], &Token::Colon) || is_token(&items[i + 1], &Token::Lt)) {
+            if let Some(name) = ident_of(&items[i]) {
+                if !defined.contains(&name) {
+                    defined.push(name);
+                }
+            }
+        }
+        start = is_token(&items[i], &Token::Semi);
+    }
+    for &(ref name, sp) in exports.iter() {
+        if !defined.contains(name) {
+            cx.span_err(sp, format!("'{}' is exported, but '{}' has no rules for it", name, prefix).as_slice());
+        }
+    }
+
+    let internal: Vec<String> = defined.into_iter().filter(|name| !exports.iter().any(|&(ref e, _)| e == name)).collect();
+    let mut after_percent = false;
+    items.iter().map(|tt| {
+        let renamed = match *tt {
+            ast::TtToken(sp, Token::Ident(id, style)) if !after_percent && internal.iter().any(|n| n.as_slice() == id.as_str()) => {
+                ast::TtToken(sp, Token::Ident(token::str_to_ident(format!("{}_{}", prefix, id.as_str()).as_slice()), style))
+            }
+            _ => tt.clone()
+        };
+        after_percent = is_token(tt, &Token::BinOp(BinOpToken::Percent));
+        renamed
+    }).collect()
+}
@@ -25,7 +25,7 @@
 //!
 //! #[phase(plugin, link)] extern crate racc;
 //!
-//! use racc::runtime::{ParserState, ParserTables, FinishParseResult};
+//! use racc::runtime::{ParserState, ParserTables, Location, FinishParseResult};
 //!
 //! grammar! {
 //!     uint ctx;    // application context; not used in this example
@@ -117,6 +117,205 @@
 //! (each instance of `ParsingState`) contains only the state necessary to advance the
 //! state machine, and the contents of the "value" stack.
 //!
+//! Because the parser never asks for input, tokens can be pushed whenever they become
+//! available: from an event loop, from the frames of a network protocol, or from a lexer which
+//! is itself fed in pieces.  Each call to `push_token` consumes one token and returns
+//! `PushTokenResult::Ok` or `PushTokenResult::SyntaxError`; call `finish` when the input has
+//! ended, to get the value of the start symbol.  `reset` returns the parser to its initial
+//! state, so that it can be used for another input.
+//!
+//! `push` takes a token as a lexer returns it, `(token, value, location)`, and returns a
+//! `PushStep`, which says what to do next: `NeedMore` (push the next token), `Recovered` (the
+//! token was a syntax error, but the parser recovered, so push the next token), or `Failed` (the
+//! parse is over, so call `finish` for its result):
+//!
+//! ```ignore
+//!     fn on_token(&mut self, token: (u32, Value, Location)) -> Option<FinishParseResult<Value>> {
+//!         match self.parser.push(&mut self.ctx, token) {
+//!             PushStep::NeedMore | PushStep::Recovered => None,
+//!             PushStep::Failed(_) => Some(self.parser.finish(&mut self.ctx))
+//!         }
+//!     }
+//! ```
+//!
+//! `push_token` never blocks.  It runs every reduction that the token allows, shifts the token,
+//! and returns.  The parser holds no lookahead between calls, so a token which has not been
+//! pushed has not been seen, and a parser can wait for its next token for as long as it likes
+//! (for example, for the next frame of a network stream) while the program does other work.
+//! The one thing that the parser holds between calls is trivia (see `%trivia`): trivia which is
+//! not trailing trivia of the last token is kept until the next token is pushed, and becomes its
+//! leading trivia.  At the end of the input, `take_trivia` returns what is left.
+//!
+//! There is no `async` entry point, such as a `parse_stream` over a stream of tokens: the
+//! compiler that RACC is written for has no `async` functions or futures.  An event loop calls
+//! `push_token` as each token arrives.
+//!
+//! When all of the input is at hand, `parse` runs that loop over a `Lexer`, which returns each
+//! token with its value and location.  Any iterator of `(token, value, location)` is a
+//! `Lexer`, so the tokens of a lexer from another crate only need to be mapped to the token
+//! constants of the grammar:
+//!
+//! ```ignore
+//!     let mut tokens = my_lexer.map(|t| match t {
+//!         MyToken::Num(n) => (NUM, n, Location::default()),
+//!         MyToken::Plus => (PLUS, 0, Location::default()),
+//!     });
+//!     match parser.parse(&mut ctx, &mut tokens) { ... }
+//! ```
+//!
+//! The lexer is asked for one token at a time, so the input is never collected.  Tokens
+//! without locations can be parsed with `parse_tokens`, which takes an iterator of
+//! `(token, value)` pairs.  To parse a file without reading all of it first, use a
+//! `ReaderLexer`, which reads a line at a time from a `Buffer` and tokenizes each line; with a
+//! generated lexer (see below), `ScanLines` does the tokenizing:
+//!
+//! ```ignore
+//!     let file = BufferedReader::new(try!(File::open(&path)));
+//!     let mut lexer = ReaderLexer::new(file, ScanLines::new(&SCANNER_TABLES, token_value));
+//!     match parser.parse(&mut ctx, &mut lexer) { ... }
+//! ```
+//!
+//! ## Generating a lexer
+//!
+//! RACC can also generate the lexer.  `%token NAME pattern;` defines a token (or gives a pattern
+//! to a token which has already been defined), and `%skip pattern;` describes text between
+//! tokens, such as white space and comments.  A string is matched literally; a raw string is a
+//! regular expression, with the usual syntax (`.`, `[a-z]`, `[^"]`, `\d`, `\w`, `\s`, `|`,
+//! `*`, `+`, `?`, and parentheses):
+//!
+//! ```ignore
+//!     %token IF "if";
+//!     %token PLUS "+";
+//!     %token IDENT r"[a-zA-Z_][a-zA-Z0-9_]*";
+//!     %token NUM r"[0-9]+";
+//!     %skip r"[ \t\n]+|//[^\n]*";
+//! ```
+//!
+//! The patterns are compiled into a DFA, and RACC generates its tables and a `new_scanner`
+//! function, which returns a `Scanner` (from the runtime) over a string.  The scanner takes the
+//! longest match at each point.  When two patterns match the same text, a literal wins over a
+//! regular expression, so `if` is `IF` but `iffy` is `IDENT`; otherwise the pattern given first
+//! wins.  Text that matches no pattern becomes the `error` token, one character at a time.
+//!
+//! `\d`, `\w`, and `\s` match only ASCII.  For the identifiers of most programming languages,
+//! use Unicode properties: `\p{Name}` matches the characters with the property, and `\P{Name}`
+//! the others, both in and out of classes.  The properties are `XID_Start`, `XID_Continue`,
+//! `Alphabetic`, `Lowercase`, `Uppercase`, `White_Space`, `N` (numbers), `Cc` (control
+//! characters), and `ASCII`:
+//!
+//! ```ignore
+//!     %token IDENT r"[\p{XID_Start}_][\p{XID_Continue}]*";
+//! ```
+//!
+//! A `Scanner` is an iterator of `(token, text, location)`, where the location is a range of
+//! byte offsets, so the only thing left to do is to turn the text of each token into its value:
+//!
+//! ```ignore
+//!     let mut tokens = new_scanner(input).map(|(t, text, loc)|
+//!         (t, if t == NUM { from_str(text).unwrap() } else { 0 }, loc));
+//!     match parser.parse(&mut ctx, &mut tokens) { ... }
+//! ```
+//!
+//! The generated code refers to `Scanner` and `ScannerTables`, which must be in scope.
+//!
+//! The text of a token which is declared with a string is also an alias for it.  A rule can use
+//! the string in place of the name of the token, and reports, conflict descriptions, and syntax
+//! error messages show the string:
+//!
+//! ```ignore
+//!     %token PLUS "+";
+//!
+//!     Expr : Expr "+" Term | Term ;
+//! ```
+//!
+//! ## Lexer states
+//!
+//! Some text needs different patterns in different places: the inside of a string literal, or
+//! of a nested comment, or of a template.  `%lexer-state` declares the states of the lexer,
+//! besides `INITIAL`, which it starts in.  A pattern is active in `INITIAL` unless it lists its
+//! states, and `%begin` after a pattern puts the lexer in a state when the pattern matches:
+//!
+//! ```ignore
+//!     %lexer-state STRING;
+//!
+//!     %token QUOTE "\"" %begin STRING;
+//!     %token <STRING> CHARS r"[^\"\\]+";
+//!     %token <STRING> ESCAPE r"\\.";
+//!     %token <STRING> END_QUOTE "\"" %begin INITIAL;
+//! ```
+//!
+//! The same literal may be the pattern of tokens in different states; it is an alias of the first
+//! of them.  RACC defines a constant for each state, `LEX_INITIAL`, `LEX_STRING`, and so on,
+//! which `Scanner::mode` returns and `Scanner::set_mode` takes, for a program which changes the
+//! state itself.
+//!
+//! A rule can also end with `%begin STATE`, so that the parser puts the lexer in `STATE` when it
+//! reduces by the rule.  This works when the parser drives the scanner, with
+//! `ParserState::parse_scanner`, or when the loop which pushes tokens asks
+//! `ParserState::take_lexer_mode` before it scans each one.  The parser must reduce by the rule
+//! before it reads the token after it, since that token is scanned in the new state; RACC
+//! reports an error for a rule that the parser reduces only after looking at the next token.
+//!
+//! ## Indentation
+//!
+//! In a language such as Python, the indentation of the lines shows where blocks begin and end.
+//! A grammar for one declares tokens for the layout, which no pattern matches, and uses them
+//! like any others:
+//!
+//! ```ignore
+//!     INDENT; DEDENT; NEWLINE;
+//!     %skip r"[ \t\n]+|#[^\n]*";
+//!
+//!     Stmt : Expr NEWLINE | IF Expr ":" NEWLINE INDENT Stmt+ DEDENT ;
+//! ```
+//!
+//! A `Layout` from the runtime wraps the lexer, and adds the layout tokens from the indentation
+//! of the text, as Python's tokenizer does.  A line which is indented more than the one before
+//! it begins with `INDENT`; a line which is indented less begins with one `DEDENT` for each block
+//! that it closes; and each line ends with `NEWLINE`.  Blank lines and comments do not count,
+//! and neither do line breaks inside brackets.  A `LayoutConfig` names the tokens, the width of
+//! a tab, and the brackets:
+//!
+//! ```ignore
+//!     static BRACKETS: [(u32, u32), ..1] = [(LPAREN, RPAREN)];
+//!     let config = LayoutConfig { indent: INDENT, dedent: DEDENT, newline: Some(NEWLINE), tab_width: 8, brackets: &BRACKETS };
+//!     let tokens = new_scanner(input).map(|(t, text, loc)| (t, value_of(t, text), loc));
+//!     match parser.parse(&mut ctx, &mut Layout::new(input, tokens, config, empty_value)) { ... }
+//! ```
+//!
+//! The layout tokens are empty, and located where they apply: `NEWLINE` at the end of its
+//! line, and `INDENT` and `DEDENT` at the start of the first token of the line.  A line which
+//! dedents to a column that no enclosing block has gets the `error` token.
+//!
+//! ## Keywords
+//!
+//! A language with many keywords is better served by scanning them as identifiers, and looking
+//! them up in a table, than by giving each one a pattern.  `%keywords` declares the keywords,
+//! and the token that they are scanned as; each keyword is defined as a token, and its text is
+//! an alias for it:
+//!
+//! ```ignore
+//!     %token IDENT r"[a-zA-Z_][a-zA-Z0-9_]*";
+//!     %keywords IDENT: IF "if", ELSE "else", WHILE "while";
+//!     %keywords contextual IDENT: ASYNC "async";
+//! ```
+//!
+//! RACC generates `KEYWORDS`, a `KeywordTable` sorted by text, and the scanner returns `IF`
+//! rather than `IDENT` for "if".  A contextual keyword is a keyword only where the parser can
+//! accept it, and an identifier elsewhere, so the scanner leaves it as `IDENT`; the loop which
+//! pushes tokens classifies it, with the parser at hand:
+//!
+//! ```ignore
+//!     for (t, text, loc) in new_scanner(input) {
+//!         let t = KEYWORDS.contextual(t, text, &parser);
+//!         parser.push_token_at(&mut ctx, t, value_of(t, text), loc);
+//!     }
+//! ```
+//!
+//! `KeywordTable::contextual` asks `ParserState::accepts`, which runs the reductions that the
+//! keyword would cause on a copy of the stack, to see whether the keyword would be shifted.
+//! The generated code refers to `KeywordTable` and `Keyword`, which must be in scope.
+//!
 //! ## Accessing external data during parsing
 //!
 //! It is often necessary, when imlementing a parser, to access external or "environmental"
@@ -132,6 +331,37 @@
 //! specified in the grammar definition.  In the example above, the identifier is `ctx`,
 //! and the type of the context is `uint`.
 //!
+//! The context is also the way for actions to talk to the lexer, as a C parser must, to tell
+//! it which names are typedefs.  Implement `ContextLexer` (rather than `Lexer`), whose
+//! `next_token` is given the context, and parse with `parser.parse_with_context(&mut ctx,
+//! &mut lexer)`.  The lexer has the context between tokens, and the actions have it while a
+//! token is pushed, so neither needs shared mutable state.  An action runs when its rule is
+//! reduced, which may not be until the token after the rule has been lexed; see
+//! `parse_with_context` for how to arrange the grammar so that this does not matter.
+//!
+//! When the actions need several things, such as a symbol table and an arena, declare each of
+//! them with `%parse-param`, rather than making a context type to hold them:
+//!
+//!     grammar! {
+//!         ParseParams<'a> params;
+//!         uint;
+//!
+//!         %parse-param symbols: &mut SymbolTable;
+//!         %parse-param options: &Options;
+//!
+//!         Expr : IDENT=id { if options.trace { println!("{}", symbols.name(id)); } id };
+//!     }
+//!
+//!     let result = parse(&mut lexer, &mut symbols, &options);
+//!
+//! RACC generates the struct `ParseParams`, with a field for each parameter (references are
+//! given the lifetime `'a`), which is the context of the grammar, and a `parse` function which
+//! takes the parameters and a lexer, and runs a new parser.  In every action that you write,
+//! each parameter is bound to its name: a `&mut` reference is reborrowed, and a value which is
+//! not a reference is bound as a `&mut` reference to it.  A context type may have lifetimes in
+//! general; the generated functions are generic over them.  With several start symbols, there
+//! is no `parse`; pass a `ParseParams` to the entry points instead.
+//!
 //! ## Propagating values through the parsing tree
 //!
 //! In Berkeley YACC, the tokenizer stage (lexer) may set the `yylval` variable to a value,
@@ -149,7 +379,8 @@
 //!     };
 //!
 //! In this code, `Expr=left` means "match the symbol `Expr` and bind its value to the
-//! name `left` within the scope of the action," and similarly for `Expr=right`.
+//! name `left` within the scope of the action," and similarly for `Expr=right`.  The binding
+//! can also be written `Expr(left)`.  A name can be bound only once in a rule.
 //! Instead of using `$$` for setting the value of the rule action, the value of the rule
 //! action is simply the value of the action, when evaluated using the normal rules of Rust.
 //! This is why the action block in the example ends with `left + right` and not `left + right;`.
@@ -162,6 +393,669 @@
 //! If you do not wish to propagate values in this way, you can use a symbol value of `()`.
 //! If you do this, then you may have empty rule actions.
 //!
+//! ## Giving symbols their own types
+//!
+//! A single value type means that most grammars need a large enum of values, and actions which
+//! unwrap it.  Instead, `%type` gives some symbols their own types, as in YACC:
+//!
+//! ```ignore
+//!     %type <Box<Ast>> Expr Term;
+//!     %type <Vec<Box<Ast>>> Args;
+//!
+//!     Expr : Expr=left PLUS Term=right { Box::new(Ast::Add(left, right)) };
+//!     Args : Args=args COMMA Expr=e { let mut args = args; args.push(e); args };
+//! ```
+//!
+//! In these actions, `left` and `right` are `Box<Ast>`s, and `args` is a `Vec<Box<Ast>>`.
+//! Symbols without a `%type` (usually the tokens) have the value type given at the start of the
+//! grammar.
+//!
+//! When a grammar uses `%type`, RACC generates an enum, `YYValue`, which is the type of the
+//! values in the parser's value stack.  It has a variant for each `%type`, named after the first
+//! symbol of the `%type` (here, `Expr` and `Args`), and a variant `Untyped` for the value type of
+//! the grammar.  The parser is a `ParserState<YYValue, ...>`; push tokens with values such as
+//! `YYValue::Untyped(value)`, and `finish` returns the value of the start symbol in its variant.
+//! All of the types must implement `Show`.
+//!
+//! ## Optional and repeated symbols
+//!
+//! In the right-hand side of a rule, a symbol can be followed by an operator, as in EBNF:
+//!
+//! * `X?` matches zero or one `X`.  Its value is an `Option<T>`, where `T` is the type of `X`.
+//! * `X*` matches zero or more `X`, and `X+` one or more.  Its value is a `Vec<T>`.
+//! * `X % SEP` matches one or more `X`, separated by `SEP`.  Its value is a `Vec<T>` of the `X`s.
+//!
+//! A binding follows the operator:
+//!
+//! ```ignore
+//!     Call : IDENT=name LPAREN Expr % COMMA=args RPAREN { ... };
+//!     Block : LBRACE Stmt*=stmts RBRACE { ... };
+//! ```
+//!
+//! RACC generates a non-terminal for each of these (named, for example, `Stmt*`), with
+//! left-recursive rules that build the list.  Because these non-terminals have their own types,
+//! a grammar which uses the operators has a `YYValue` enum, as if it used `%type`.  The variant
+//! for `Stmt*` is `Stmt_many`; the others are `_many1`, `_opt`, and `_sep_` followed by the
+//! separator.
+//!
+//! ## Tracking locations
+//!
+//! To know where each symbol came from, push tokens with `parser.push_token_at(&mut ctx,
+//! token, value, location)`, where `location` is a `Location { start: ..., end: ... }`.  The
+//! parser does not interpret locations, so they can be byte offsets, token numbers, or anything
+//! else that fits in a `uint`.  The parser keeps a stack of locations alongside the value stack.
+//! In an action block, `yylocs` holds the locations of the symbols of the rule (`yylocs[0]` is
+//! the first, like `@1` in Bison), and `yyloc` is the location of the whole rule (like `@$`),
+//! which runs from the start of its first symbol to the end of its last:
+//!
+//! ```ignore
+//!     Expr : Expr=left PLUS Expr=right {
+//!         println!("addition at {}..{}, operator at {}", yyloc.start, yyloc.end, yylocs[1].start);
+//!         left + right
+//!     };
+//! ```
+//!
+//! A rule with no symbols has an empty location at the end of the symbol before it.  Tokens
+//! pushed with `push_token` have the default location, `0..0`.  When a syntax error occurs,
+//! `ParseError::location` is the location of the offending token.
+//!
+//! ## Parameterized rules
+//!
+//! A rule can have parameters, which stand for symbols.  It is not part of the grammar by
+//! itself; instead, each use of it with arguments creates a non-terminal, whose rules are those
+//! of the parameterized rule with the arguments in place of the parameters:
+//!
+//! ```ignore
+//!     Pair<A, B> : LPAREN A=a COMMA B=b RPAREN { make_pair(a, b) };
+//!
+//!     Point : Pair<NUM, NUM>=p { p };
+//!     Entry : Pair<IDENT, Expr>=p { p };
+//! ```
+//!
+//! Here `Pair<NUM, NUM>` and `Pair<IDENT, Expr>` are two different non-terminals.  A
+//! parameterized rule can use itself, or other parameterized rules, with the same or other
+//! arguments.  The arguments must be symbols, not instances of parameterized rules.  Only the
+//! symbols of a rule are replaced; names in actions are not.  Instances of parameterized rules
+//! cannot be given a `%type`, so their values have the value type of the grammar.
+//!
+//! ## Inlining rules
+//!
+//! `%inline` names non-terminals whose rules are substituted into the rules that use them,
+//! rather than being reduced on their own.  This can remove conflicts which come only from
+//! having to decide on a reduction too early:
+//!
+//! ```ignore
+//!     %inline BinOp;
+//!
+//!     BinOp : PLUS { Op::Add } | MINUS { Op::Sub };
+//!
+//!     Expr : Expr=a BinOp=op Expr=b { Expr::Binary(op, box a, box b) };
+//! ```
+//!
+//! Here `Expr` has the two rules `Expr PLUS Expr` and `Expr MINUS Expr`, so `%left PLUS MINUS`
+//! can give them their precedence.  The action of the rule of the inline non-terminal runs
+//! first, and its value is bound to the name that the using rule gave to the non-terminal
+//! (`op`, here).  The bindings of the inline rules become bindings of the combined rules, so
+//! they must not use the same names as the rules that use them.  An inline non-terminal cannot
+//! use itself, cannot be the start symbol, and neither it nor the rules that use it can use
+//! action methods.  `yylocs` and `yyloc` in the action of an inline rule refer to the combined
+//! rule.
+//!
+//! ## Building a syntax tree
+//!
+//! `%define tree cst;` makes the parser build a concrete syntax tree, with no actions at all.
+//! The value stack then holds `Node<T>` (from the runtime), where `T` is the value type given
+//! at the top of the grammar.  Tokens are pushed as leaves, `Node::Token(token, value,
+//! location)`, and each reduction makes a `Node::Rule(rule, location, children)`, with a child
+//! for each symbol of the rule.  `rule` is the number of the rule, as in
+//! `GrammarInfo::rule_text`.  `finish` returns the node of the start symbol.  Rules cannot have
+//! actions, and symbols cannot be given types with `%type`.
+//!
+//! A tree is built even for input with syntax errors, if the grammar uses the `error` token.
+//! When the parser recovers, the value of the `error` token is a `Node::Error(location,
+//! children)`, whose children are the symbols that were popped, the token that caused the
+//! error, and the tokens that were discarded after it.  `node.error_locations()` returns the
+//! locations of the error nodes of a tree, to report or to attach diagnostics to; the rest of
+//! the tree has its usual structure.
+//!
+//! A tree can keep the comments and white space of the input too, for a formatter or a
+//! documentation tool.  `%trivia` declares the tokens which are trivia; the lexer returns them
+//! like any other token, but the rules cannot use them, and the parser attaches them to the
+//! tokens around them instead of parsing them.  `newline` marks trivia which ends a line:
+//!
+//! ```ignore
+//!     %token SPACE r"[ \t]+";
+//!     %token COMMENT r"//[^\n]*";
+//!     %token EOL r"\n";
+//!     %trivia SPACE COMMENT;
+//!     %trivia newline EOL;
+//! ```
+//!
+//! A token with trivia is a `Node::WithTrivia(leading, token, trailing)`.  The trivia after a
+//! token, up to and including the first which ends a line, is its trailing trivia, and the rest
+//! is the leading trivia of the next token; so a comment at the end of a line belongs to the
+//! token before it, and a comment on a line of its own to the token after it.  Trivia at the end
+//! of the input trails the last token.  `node.leading_trivia()` and `node.trailing_trivia()`
+//! return them; `node.token()` and `node.location()` look through them, to the token.  Only
+//! `ParserState` attaches trivia; the other engines reject trivia tokens as syntax errors.
+//!
+//! `%define tree ast;` instead generates a type for the abstract syntax tree, and actions which
+//! build it.  Each non-terminal gets an enum of the same name, deriving `Show` and `Clone`,
+//! with a variant for each of its rules.  The variant is named with `=> Name` after the rule,
+//! or after the non-terminal if it has only one rule:
+//!
+//! ```ignore
+//!     %define tree ast;
+//!
+//!     Expr : Expr=left PLUS Expr=right => Add
+//!          | NUM=value => Num
+//!          | LPAREN Expr=inner RPAREN => Paren;
+//!
+//!     // generates:
+//!     pub enum Expr { Add(Box<Expr>, Box<Expr>), Num(T), Paren(Box<Expr>) }
+//! ```
+//!
+//! The fields of a variant are the bound symbols of the rule, or all of its symbols if none are
+//! bound.  Tokens have the value type of the grammar, other non-terminals are boxed, and
+//! optional and repeated symbols are `Option`s and `Vec`s.  Rules cannot have actions, and
+//! `%type` and `%inline` cannot be used.
+//!
+//! Three traits are generated to walk the AST, with a pair of methods for each enum (here, for
+//! `Expr`).  `Visit` has `visit_expr(&mut self, node: &Expr)`, which by default calls
+//! `walk_expr`, which visits the children of the node.  `VisitMut` is the same, with
+//! `visit_expr(&mut self, node: &mut Expr)` and `walk_expr`, so that nodes can be changed in
+//! place.  `Fold` has `fold_expr(&mut self, node: Expr) -> Expr`, which by default calls
+//! `fold_expr_children`, which rebuilds the node from its folded children.  Override
+//! `visit_expr` or `fold_expr` to handle `Expr` nodes, and call the other method to continue
+//! into their children.
+//!
+//! The nodes of an AST can be allocated in an arena, rather than boxed, which is faster, and
+//! frees the whole tree at once.  Declare the arena as a parameter (see `%parse-param`), and
+//! name it with `%define tree.arena`:
+//!
+//! ```ignore
+//!     ParseParams<'a> params;
+//!     T;
+//!
+//!     %define tree ast;
+//!     %parse-param arena: &Arena;
+//!     %define tree.arena arena;
+//!
+//!     // generates:
+//!     pub enum Expr<'a> { Add(&'a Expr<'a>, &'a Expr<'a>), Num(T), Paren(&'a Expr<'a>) }
+//! ```
+//!
+//! Each enum then has the lifetime `'a` of the arena, and so do the value enum and the
+//! generated functions.  Each node is allocated with `arena.alloc(node)`, which returns a
+//! reference to it, as the `alloc` of `arena::TypedArena` does; wrap another kind of arena in
+//! a type with such a method.  `Visit` is generated as `Visit<'a>`, but `VisitMut` and `Fold`
+//! are not, since the nodes cannot be changed or moved out of the arena.
+//!
+//! ## Implementing actions in a trait
+//!
+//! Instead of a code block, a rule may name a method, using `=> name`:
+//!
+//!     Expr : Expr=left PLUS Expr=right => add;
+//!
+//! RACC then generates a trait named `Actions`, with one method for each rule that names one.
+//! The parameters of each method are the values bound by the rule, in order, and the method
+//! returns the value of the rule:
+//!
+//!     pub trait Actions {
+//!         fn add(&mut self, left: i32, right: i32) -> i32;
+//!     }
+//!
+//! The context type must implement `Actions`; the generated `reduce` function calls the method
+//! on the app context.  Because the methods receive `&mut self`, they can use anything that the
+//! context owns, such as interners or arenas, and they can be tested without running a parser.
+//! Each method name may be used by only one rule.
+//!
+//! ## Token values
+//!
+//! Each token gets a constant (`NUM`, above), and a variant of a generated `Token` enum
+//! (`Token::NUM`) whose discriminant is the same value.  `Token::all()` lists the tokens in
+//! order, `token.value()` and `Token::from_value(n)` convert to and from values, and the enum
+//! converts to and from the names of the tokens with `Show` and `FromStr`, which is handy in
+//! tests and in hand-written lexers.
+//!
+//! Tokens are numbered from 257, in the order that they are defined, so adding or reordering
+//! tokens changes their values.  To keep a value fixed (because it is stored, or sent to
+//! another program), give it in the definition:
+//!
+//!     NUM = 300; PLUS = 301;
+//!
+//! The tokens without values are numbered around the ones that have them.  Values must be
+//! between 1 and 32766, other than 256, which is the value of `error`.
+//!
+//! A lexer which was not generated from the grammar, such as a C scanner or a lexer shared with
+//! another tool, has its own numbers for the tokens.  `%external` gives them, and RACC generates
+//! `EXTERNAL_TOKENS`, a `TokenMap` which translates them into token values:
+//!
+//!     %external NUM = 258, PLUS = 43, MINUS = 45;
+//!
+//! Several numbers may stand for one token.  RACC warns about each token which has no number,
+//! since the external lexer can never produce it.  Which numbers the lexer produces is known
+//! only at run time: `EXTERNAL_TOKENS.check(numbers)` returns those which the map does not
+//! cover.  `Remap` wraps the lexer, and translates each token as it is read; a number which
+//! the map does not cover becomes the `error` token:
+//!
+//! ```ignore
+//!     if let Err(missing) = EXTERNAL_TOKENS.check(c_lexer::TOKENS) { panic!("unmapped tokens: {}", missing); }
+//!     match parser.parse(&mut ctx, &mut Remap::new(&EXTERNAL_TOKENS, c_lexer::tokens(input))) { ... }
+//! ```
+//!
+//! The generated code refers to `TokenMap`, which must be in scope.
+//!
+//! ## Borrowing token values from the input
+//!
+//! Token values do not have to own their text.  The value type can borrow from the input, with a
+//! lifetime of its own, so that identifiers and literals are slices of the input rather than a
+//! `String` each:
+//!
+//! ```ignore
+//!     type SymbolValue = &'input str;
+//! ```
+//!
+//! The lifetime is declared by the generated code, not by the grammar: `reduce`,
+//! `get_parser_tables`, `parse`, and the types of the symbols which carry it (the value enum of
+//! a grammar with `%type`, and the enums of an AST) are generic over it.  A `Scanner` yields the
+//! text of each token as a slice of its input, so it can be used as the value directly:
+//!
+//! ```ignore
+//!     let mut parser = ParserState::new(get_parser_tables());
+//!     let mut tokens = new_scanner(input.as_slice());
+//!     match parser.parse(&mut ctx, &mut tokens) { ... }
+//! ```
+//!
+//! The values, and the tree that is built from them, cannot outlive the input.
+//!
+//! ## Sharing tokens between grammars
+//!
+//! Several grammars can share one set of token definitions, so that their parsers agree on
+//! token values and can be driven by the same lexer.  Declare the set once, with
+//! `grammar_tokens!`, which also generates the token constants, and an enum of the tokens
+//! named after the set (here, `ExprTokens`):
+//!
+//!     grammar_tokens! { ExprTokens; NUM; PLUS; MINUS; LPAREN; RPAREN; }
+//!
+//! Then, in each grammar, write `use ExprTokens;` instead of defining tokens.  A grammar which
+//! uses a token set may not define any other tokens, and does not generate its own `Token`
+//! enum.  The set must be declared before the grammars that use it, in the same crate.
+//!
+//! ## Splitting a grammar
+//!
+//! A large grammar can be split into pieces.  A piece is a sequence of grammar items (tokens,
+//! directives, and rules), written just as in `grammar!`, and declared with
+//! `grammar_fragment!`:
+//!
+//!     grammar_fragment! {
+//!         ExprRules;
+//!         Expr : Expr "+" Term | Term;
+//!         Term : NUM;
+//!     }
+//!
+//! A grammar includes it with `%include ExprRules;`.  A piece can also be a file, which is
+//! included with `%include "expr.racc";`, and found relative to the file that includes it.
+//! The items of the piece are read as if they were written in place of `%include`: rules for
+//! a non-terminal which is also defined elsewhere are added to its other rules, but a token
+//! may be defined only once, and doing so in two pieces is reported as an error.  A piece may
+//! include other pieces, but not itself.  As with token sets, a fragment must be declared
+//! before the grammars that include it, in the same crate.
+//!
+//! A piece which is meant to be reused can keep its helper non-terminals to itself, by saying
+//! which of its non-terminals it exports:
+//!
+//!     grammar_fragment! {
+//!         ExprRules;
+//!         %export Expr;
+//!         Expr : Expr "+" Atom | Atom;
+//!         Atom : NUM | "(" Expr ")";
+//!     }
+//!
+//! When it is included, its other non-terminals are renamed with the name of the piece (or of
+//! the file, without its extension) as a prefix, so `Atom` becomes `ExprRules_Atom`, and does
+//! not collide with an `Atom` of the grammar, or of another piece.  Only the symbols of rules
+//! and directives are renamed, not names within actions.
+//!
+//! ## Conditional rules
+//!
+//! A rule, a token definition, a directive, or one alternative of a rule can be enabled only
+//! for some configurations of the crate, with `#[cfg(...)]`, as in Rust code:
+//!
+//!     #[cfg(feature = "pipes")]
+//!     %token PIPE "|>";
+//!
+//!     Expr : Expr "+" Expr
+//!          | #[cfg(feature = "pipes")] Expr "|>" Expr
+//!          | NUM;
+//!
+//! The condition is evaluated when the grammar is expanded, against the same configuration as
+//! the rest of the crate (so cargo features, `test`, `target_os`, and so on), and an item or
+//! alternative whose condition is false is skipped, as if it had not been written.  The parser
+//! is generated from what remains, so each configuration gets its own tables.
+//!
+//! ## Precedence and associativity
+//!
+//! Expression grammars are simplest when written with one non-terminal for all expressions,
+//! which is ambiguous.  As in YACC, precedence declarations resolve the shift/reduce conflicts
+//! that this causes:
+//!
+//!     %left PLUS MINUS;
+//!     %left TIMES DIVIDE;
+//!     %right POW;
+//!
+//!     Expr : Expr PLUS Expr | Expr MINUS Expr | Expr TIMES Expr | Expr DIVIDE Expr
+//!          | Expr POW Expr | NUM ;
+//!
+//! Each declaration is a new precedence level, higher than the ones before it.  `%left`,
+//! `%right`, and `%nonassoc` give the associativity of the tokens in the level.  A rule has the
+//! precedence of the last token in it.  These declarations also define their tokens, so the
+//! tokens need not be defined separately.
+//!
+//! `%prec NAME` at the end of a rule (before its action) gives the rule the precedence of the
+//! token `NAME` instead.  The usual example is unary minus, which binds more tightly than binary
+//! minus, although it is the same token:
+//!
+//!     %left PLUS MINUS;
+//!     %left TIMES;
+//!     %right UMINUS;
+//!
+//!     Expr : Expr MINUS Expr | MINUS Expr %prec UMINUS | ... ;
+//!
+//! `UMINUS` is never produced by the lexer; it exists only to name a precedence level.
+//!
+//! ## Expressions with many operators
+//!
+//! A language with many levels of operators (C has fifteen) makes a large automaton either way:
+//! one non-terminal per level adds states and unit reductions for every level, and one ambiguous
+//! non-terminal adds a conflict for every pair of operators, which precedence then resolves.
+//! `%expr` declares an expression by its operators instead:
+//!
+//! ```ignore
+//!     %expr Expr : Atom => binary, prefix {
+//!         %left OROR;
+//!         %left ANDAND;
+//!         %nonassoc EQ NE LT GT;
+//!         %left PLUS MINUS;
+//!         %left TIMES DIVIDE;
+//!         %prefix MINUS NOT;
+//!         %right POW;
+//!     }
+//!
+//!     Atom : NUM=n { n } | LPAREN Expr=e RPAREN { e };
+//! ```
+//!
+//! The levels are listed loosest first, as with `%left` and `%right`; `%prefix` declares prefix
+//! operators.  The LR parser reads an `Expr` as a flat list of `Atom`s and operators, without any
+//! conflicts, and when the list is complete it is arranged by precedence climbing (see
+//! `racc_runtime::expr`).  The two methods that the declaration names are added to the
+//! `Actions` trait (see "Implementing actions in a trait"), and combine the operands, from the
+//! innermost out; each operator is given by its token value:
+//!
+//!     fn binary(&mut self, op: u32, left: i32, right: i32) -> i32;
+//!     fn prefix(&mut self, op: u32, operand: i32) -> i32;
+//!
+//! The second method is named only if there are prefix operators.  A prefix operator applies to
+//! the operand after it, with the binary operators of higher levels, so here `-a * b` is
+//! `(-a) * b`, and `-a ** b` is `-(a ** b)`.  An operand must have the same type as the
+//! expression.  A `%nonassoc` operator after an operand of the same level, as in `a == b == c`,
+//! makes the action fail (see "Failing in an action") with a `racc_runtime::expr::ExprError`.
+//! The operators get no precedence in the grammar, so they can be used by other rules too; the
+//! lists are non-terminals named `Expr$list`, `Expr$operand`, and `Expr$prefix`, which appear
+//! in reports.  `%expr` cannot be used by a parser which builds a tree.
+//!
+//! ## Conflicts
+//!
+//! Before it builds the automaton, RACC checks that every non-terminal derives some string of
+//! tokens, and can be reached from the start symbol.  Each one which does not is reported as a
+//! warning, at its definition; it usually means that a recursive rule lacks its base case, or
+//! that a rule was left behind by an edit.  A start symbol which derives nothing is an error.
+//! Rules which can never be reduced, and rules which repeat an earlier alternative of the same
+//! non-terminal, are reported too, and the number of each is summed up at the end, as Bison does.
+//! So are non-terminals which can derive themselves alone (which makes the grammar ambiguous),
+//! and lists which are right recursive, such as `List : Item List`, which the parser cannot
+//! reduce until it has read all of them.  `Grammar::recursion` gives the details.
+//!
+//! RACC reports each conflict that precedence does not resolve as a warning, with the items
+//! of the state that cause it and an example of the input that reaches it.  The parser resolves
+//! the conflict as YACC does: it prefers shifting to reducing, and the earlier rule to the
+//! later one.  When a grammar's conflicts are intended, state how many there are:
+//!
+//!     %expect 1;          // shift/reduce conflicts
+//!     %expect-rr 0;       // reduce/reduce conflicts
+//!
+//! The conflicts of that kind are then not reported, but generating the parser fails if the
+//! number changes.
+//!
+//! `%expect` covers the whole grammar, so it also hides any new conflict which takes the place of
+//! an old one.  A conflict which is intended can be settled where it arises instead, with
+//! `%resolve` at the end of a rule (before its action), naming a token:
+//!
+//!     Stmt : IF Expr THEN Stmt %resolve shift ELSE
+//!          | IF Expr THEN Stmt ELSE Stmt
+//!          | ... ;
+//!
+//! This is the "dangling else": after `IF Expr THEN Stmt`, an `ELSE` could either belong to this
+//! `IF` or end it, and `%resolve shift ELSE` says that it belongs to the innermost `IF`.  A
+//! conflict between reducing by the rule and another action on the token is then settled, and is
+//! neither reported nor counted by `%expect`: `%resolve shift` shifts the token, and
+//! `%resolve reduce` reduces by this rule (rather than shifting, or reducing by another rule).
+//! It comes before precedence, and affects no other rule and no other token, unlike `%prec`.  A
+//! rule may have a `%resolve` for each of several tokens.  Reports list the actions that it
+//! removed as "removed by %resolve".
+//!
+//! A policy for a whole family of conflicts can be stated once, with `%prefer`, by the
+//! non-terminals whose rules are involved:
+//!
+//!     %prefer shift over Expr;        // "longest match": extend an Expr rather than end it
+//!     %prefer Decl over Expr;         // a statement which could be either is a declaration
+//!     %prefer Cast over shift;
+//!
+//! `%prefer Decl over Expr` settles every reduce/reduce conflict between a rule of `Decl` and a
+//! rule of `Expr` by reducing by the rule of `Decl`, in every state and on every token; `shift`
+//! stands for shifting the token.  A policy applies only to the conflicts which neither
+//! `%resolve` nor precedence settles, and the conflicts that it settles are neither reported nor
+//! counted by `%expect`.  Reports list the actions that it removed as "removed by %prefer".
+//!
+//! To see the automaton itself, write
+//!
+//!     %define report.file "grammar.output";
+//!
+//! and RACC writes a report of every state (its items, actions, gotos, and lookaheads) to that
+//! file, like the `y.output` file of YACC.  The path is relative to the directory that the
+//! compiler is run in.  `%define report.dot "grammar.dot";` writes the automaton as a Graphviz
+//! graph, with each state labeled with its items and lookaheads.
+//!
+//! `%define report.html "grammar.html";` writes a page to read in a browser, for reviewing a
+//! grammar: the rules, the non-terminals with their FIRST and FOLLOW sets, the conflicts, and
+//! every state, all linked to each other, and the graph of the automaton, whose states link to
+//! their descriptions.  The page draws the graph with viz.js, which it loads from the web; when
+//! that is not possible, it shows the DOT source.
+//!
+//! `%define report.json "grammar.json";` writes the grammar, the automaton, and the packed
+//! tables as JSON, for tools: a CI job can compare the number of states and the list of
+//! conflicts of two versions of a grammar, and a visualizer can draw the automaton.  Symbols
+//! appear by name, so the output of two versions can be compared even if their symbols are
+//! numbered differently.  The schema is the set of structs in `racc_core::export`, and its
+//! `version` field changes when the schema does.  The same JSON is available from a program
+//! through `Grammar::to_json`, `Automaton::to_json`, and `PackedTables::to_json`.
+//!
+//! ## Start symbols
+//!
+//! The start symbol of a grammar is the left-hand side of its first rule, unless `%start Name;`
+//! names another.  `%start` can also name several symbols, so that one parser can parse
+//! fragments as well as whole files:
+//!
+//! ```ignore
+//!     %start File Stmt Expr;
+//! ```
+//!
+//! The parser then has one set of tables, and a token for each start symbol, such as
+//! `YYSTART_Expr`, which selects the symbol to parse when it is the first token pushed.  RACC
+//! also generates an entry point for each start symbol, named after it, which resets a parser,
+//! pushes its start token, and parses the tokens of a `Lexer`:
+//!
+//! ```ignore
+//!     match parse_expr(&mut parser, &mut ctx, &mut tokens, 0) { ... }
+//! ```
+//!
+//! The last argument is the value of the start token, which is discarded.  A parser which is
+//! driven with `push_token` must push the start token itself.  The generated entry points refer
+//! to `Lexer` and `FinishParseResult`, which must be in scope.
+//!
+//! ## Choosing the kind of automaton
+//!
+//! By default, RACC builds an LALR(1) parser, just as YACC does.  Some grammars are LR(1) but
+//! not LALR(1): merging states with the same LR(0) items causes reduce/reduce conflicts that
+//! are not really in the grammar.  For these, write
+//!
+//!     %define lr.type canonical-lr;
+//!
+//! among the token definitions, and RACC builds the canonical LR(1) parser instead.  The
+//! canonical LR(1) tables can be much larger, so use this only for grammars which need it.
+//!
+//! `%define lr.type ielr;` builds an IELR(1) parser, as Bison does: it has the same conflicts as
+//! the canonical LR(1) parser, but RACC starts from the LALR(1) states and splits only those
+//! whose merged lookaheads cause a conflict, so the tables stay close to the size of LALR(1).
+//! (RACC splits a state whenever the lookaheads that reach its conflicts differ, where Bison
+//! also checks that the winning action would differ, so it may have a few more states than
+//! Bison's.)  `%define lr.type lalr;` selects the default.
+//!
+//! `%define lr.type slr;` builds an SLR(1) parser, whose states reduce by a rule on every token
+//! which can follow its left-hand side anywhere in the grammar.  It is weaker than LALR(1), and
+//! is there for teaching: RACC notes which of its conflicts would vanish with LALR(1)
+//! lookaheads (see `racc_core::slr::compare`).
+//!
+//! ## Configuring the generated code
+//!
+//! Some names and properties of the generated items can be set with `%define`:
+//!
+//!     %define api.token.enum CalcToken;       // the enum of the tokens; Token by default
+//!     %define api.parser.type CalcParser;     // a type alias for ParserState<V, C>
+//!     %define api.visibility private;         // the items are not pub; pub by default
+//!     %define api.derive PartialEq, Hash;     // also derived by the generated enums
+//!     %define debug.tables false;             // drop the text of the rules
+//!
+//! `api.derive` adds traits to the enums that the grammar generates (the token enum, the enum
+//! of the values of a typed grammar, and the enums of an AST), besides the ones that they
+//! already derive.  `api.visibility private` is useful for a grammar in a module whose items
+//! should not be visible outside it.  Without debug tables, the parser does not log each
+//! reduction, its `GrammarInfo` has empty rule text, and its states have no items (see
+//! "Describing states", below), which makes the generated code smaller.
+//!
+//! ## Caching the tables
+//!
+//! Building the automaton is most of the time that the `grammar!` macro takes.  If the
+//! `RACC_CACHE_DIR` environment variable names a directory when the grammar is compiled (or,
+//! failing that, if Cargo sets `OUT_DIR`, as it does for packages with a build script), RACC
+//! saves the tables and conflicts of each grammar there, in a file named by a hash of the
+//! grammar's symbols, rules, and precedence.  When only the actions change, the hash does not,
+//! and the saved tables are used.  Grammars which ask for a report or a graph are always
+//! analyzed again.
+//!
+//! Setting `RACC_PROFILE` when compiling a grammar prints how long each phase of the analysis
+//! took, and how large its tables are (unless the tables come from the cache), followed by a
+//! summary of the grammar like the one at the end of YACC's y.output: the number of symbols,
+//! rules, items, and states, the conflicts before and after precedence, and the size of the
+//! packed tables.  `racc_core::analyze_profiled` and `racc_core::stats::grammar_stats` give the
+//! same figures to programs.
+//!
+//! ## Building parsers at run time
+//!
+//! A program can also build a parser for a grammar that it only learns about while it runs.
+//! `dynamic::parser_from_grammar` takes a `Grammar` (from `builder`, or from a .y file, with
+//! `racc_core::yacc::read_yacc`), analyzes it, and returns an `Interpreter` for its tables.
+//! Actions are registered for each rule, and can be closures which receive the context and the
+//! values of the right-hand side.
+//!
+//! ## Generating sentences
+//!
+//! `racc_core::generate::SentenceGenerator` produces random sentences of a grammar: sequences of
+//! tokens which its parser accepts.  They make inputs for fuzzing a lexer and the actions of a
+//! parser, and examples for documentation.  The depth of the derivation tree is bounded, and
+//! each rule can be given a weight, which makes it more (or less) likely to be chosen:
+//!
+//! ```ignore
+//!     let mut generator = SentenceGenerator::new(&gram);
+//!     generator.max_depth(8).weight(RuleId(5), 4);
+//!     let mut rng = Random::new(random::seed_from_env(random::DEFAULT_SEED));
+//!     let tokens = generator.generate(&mut rng).unwrap();
+//! ```
+//!
+//! `cover` generates sentences until every rule has been used by one of them, which makes a
+//! small test corpus that exercises every alternative of the grammar.
+//!
+//! To fuzz a parser and its actions, `racc_runtime::fuzz::fuzz_parse` makes a fuzz target (for
+//! cargo-fuzz, or any fuzzer which supplies bytes) out of a generated parser.  It uses the bytes
+//! to choose each token from those that the parser can accept, so that the fuzzer explores the
+//! grammar rather than its first syntax error:
+//!
+//! ```ignore
+//!     fuzz_target!(|data: &[u8]| {
+//!         let mut parser = ParserState::with_config(get_parser_tables(), config);
+//!         fuzz_parse(&mut parser, &mut ctx, data, DEFAULT_MAX_TOKENS, |&mut: token| value_of(token));
+//!     });
+//! ```
+//!
+//! Give the parser a `max_depth` (see "Configuring the parser"), so that a deeply nested input is
+//! reported as a stack overflow rather than exhausting memory.
+//!
+//! Before refactoring a grammar, keep a copy of the old one, and generate both parsers.
+//! `racc_runtime::equivalence` then checks that the new parser accepts and rejects the same
+//! inputs as the old, and reduces them by the same rules (compared by their text), in the same
+//! order.  `assert_equivalent` takes the inputs as token names, such as the sentences of
+//! `cover`; `sentence_from_bytes` derives them from the bytes of a fuzzer or a property-testing
+//! tool, following the old parser so that most of them are valid.
+//!
+//! ## Parsing ambiguous grammars
+//!
+//! A grammar with genuine ambiguities has conflicts that no choice of automaton removes.
+//! `ParserState` follows the action that conflict resolution chose, as YACC does.
+//! `GlrParser` (in `racc_runtime::glr`) is a generalized LR parser: it follows every action of a
+//! state with conflicts, by forking the parser stack, and joins the stacks again when they
+//! reach the same state.  It takes the same tables, so it needs no change to the grammar:
+//!
+//!     let mut parser = GlrParser::with_merge(get_parser_tables(), merge);
+//!
+//! When two parses produce the same non-terminal over the same tokens, `merge` is called with
+//! both values, and returns the one to keep (or a value which records the ambiguity).  The
+//! symbol value type must implement `Clone`, because values may be shared between stacks.
+//!
+//! A choice which depends on something that the grammar cannot state, such as the version of
+//! the language being parsed, can be made while parsing instead.  `set_conflict_choice` gives
+//! the parser a function which is called with the actions of each conflict that it meets, and
+//! the context; it returns the one action to follow, or `None` to follow them all.  The
+//! `GlrConflict` that it is given names the non-terminal and the rule of each reduction, so
+//! that the choice can be made by name.
+//!
+//! ## Recovering from syntax errors
+//!
+//! As in YACC, a rule can use the predefined token `error` to say where the parser should
+//! resume after a syntax error.  For example:
+//!
+//! ```ignore
+//!     Stmt : Expr SEMI
+//!          | error SEMI { ... report the bad statement ... };
+//! ```
+//!
+//! When `push_token` finds a syntax error, it pops states from the stack until it reaches a
+//! state which can shift `error`, and shifts it.  The value of the `error` symbol is the value
+//! of the token which caused the error.  Then the parser discards tokens until it finds one
+//! that it can shift (here, `SEMI`).  The first error is reported by returning
+//! `PushTokenResult::SyntaxError`; the discarded tokens are not reported, and neither are new
+//! errors until three tokens have been shifted.  Unlike YACC, the parser does not try the
+//! offending token again after shifting `error`; it has already been consumed as the value of
+//! `error`.
+//!
+//! If no state on the stack can shift `error` (for example, if the grammar does not use it),
+//! the token is discarded, and the parser stays in the state that it was in.  Recovery is
+//! disabled by `RecoveryPolicy::Strict`.
+//!
+//! Other kinds of recovery can be added with `ParserState::set_error_recovery`.  The
+//! `ErrorRecovery` that it installs is told about each token that is discarded and each state
+//! that is popped, and it is asked about each error first: it can insert a token (for example,
+//! a missing `SEMI`), discard the offending token, or leave the recovery to the `error` token.
+//!
 //! ## Finishing parsing
 //!
 //! In Berkeley YACC, the lexer indicates the end of an input stream by reporting a `YYEOF`
@@ -172,6 +1066,108 @@
 //! return `FinishParseResult::Accept(value)`, where `value` is the value of the entire
 //! parse tree.
 //!
+//! ## Failing in an action
+//!
+//! An action which can fail, such as one which converts a number that may overflow, can say so
+//! by following its block with `?`.  The block then evaluates to a `Result`: an `Ok` value is
+//! the value of the rule, and an `Err` ends the parse.
+//!
+//!     Expr : Expr=a PLUS Expr=b {
+//!         a.checked_add(b).ok_or(CalcError::Overflow)
+//!     }?;
+//!
+//! `parse` (and `finish`) return `FinishParseResult::ActionFailed(error)`, and `push_token`
+//! returns `PushTokenResult::ActionFailed`, after which `ParserState::take_action_error` gives
+//! the error.  An `ActionError` holds the location of the rule and the error itself, which
+//! can be of any type; get it back with `error.downcast_ref::<CalcError>()`.  To make every
+//! fallible action return the same error type, and have the compiler check that they do,
+//! write `%define api.error.type CalcError;`.  After an action fails, the parser rejects every
+//! token until it is reset.  In a GLR parser, a failing action instead rules out the stack
+//! that it was reducing, as a syntax error would.
+//!
+//! ## Reparsing after an edit
+//!
+//! An editor which parses its text after every keystroke does not need to parse all of it
+//! again from the start.  `racc_runtime::CheckpointParser` wraps a parser, and saves a snapshot
+//! of it every few tokens.  After an edit at some byte offset, `resume_offset` tells where to
+//! start lexing again (use `Scanner::starting_at`), and `reparse` restores the last snapshot
+//! before the edit and parses from there to the end.  The values on the restored stack, such as
+//! the subtrees of a syntax tree, are reused; everything after the snapshot is parsed again, so
+//! the actions of those rules run again.  RACC does not reuse the subtrees after an edit, as an
+//! incremental parser such as tree-sitter does.
+//!
+//! ## Configuring the parser
+//!
+//! `ParserState::new` creates a parser with the default behavior.  `ParserState::with_config`
+//! takes a `ParserConfig` as well, which controls what happens after a syntax error (the parser
+//! can either recover and continue, or reject all further input), sizes and limits the
+//! parser's stack, and sends a trace of the parser's actions to a `TraceSink`.  A parser of
+//! untrusted input should set `max_depth`: a token which would take the stack deeper is
+//! rejected with `PushTokenResult::StackOverflow`, instead of the stack growing without bound.
+//! `ParserConfig::strict()` and `ParserConfig::tolerant()` are good starting points for batch
+//! and interactive use.
+//!
+//! The trace is the equivalent of YACC's `yydebug`.  It describes every shift (with the name of
+//! the token), every reduction (with the text of the rule), the goto which follows it, and each
+//! step of error recovery, one line at a time:
+//!
+//! ```text
+//!     state 0: shift 257 (NUM), go to state 1
+//!     state 1: reduce by rule Expr : NUM
+//!     state 0: goto state 3 on Expr
+//! ```
+//!
+//! `LogTrace` sends the trace to the `log` crate; any closure which takes a `&str` can receive
+//! it, too.  `parser.set_trace` turns tracing on and off while the parser runs.
+//!
+//! With `coverage: true` in its `ParserConfig`, a parser counts how often it reduces by each
+//! rule and enters each state, across all of the inputs that it parses.  Running a grammar's
+//! test inputs through one such parser shows which alternatives they never exercise:
+//! `parser.coverage()` returns the counts, and `Coverage::report` lists the rules which were
+//! never reduced and the states which were never entered.
+//!
+//! ## Stepping through a parse
+//!
+//! A `DebugParser` runs a parser over a lexer one action at a time.  `step` shifts one token or
+//! reduces by one rule, and returns what it did; `run` takes steps until it reaches a
+//! breakpoint, which can be set on a rule (`break_on_rule`) or on a state (`break_on_state`).
+//! Between steps, `peek_stack` shows the states, values, and locations on the stack, and
+//! `current_state_items` the items of the current state, which `ParserTables::item_text`
+//! writes as rules with a dot, such as `Expr : Expr . PLUS Expr`.  This is meant for
+//! interactive grammar debuggers, and for tests which check that the automaton takes the path
+//! that they expect:
+//!
+//! ```ignore
+//!     let mut debugger = DebugParser::new(ParserState::new(get_parser_tables()), tokens);
+//!     match debugger.step(&mut ctx) { Step::Shift(NUM, _) => {}, _ => panic!() }
+//!     match debugger.step(&mut ctx) { Step::Reduce(1, _) => {}, _ => panic!() }
+//! ```
+//!
+//! The items of the states are only there if the parser was generated with them; see below.
+//!
+//! ## Describing states
+//!
+//! With the `state-items` feature of the `racc` crate, the generated tables include the kernel
+//! items of each state and the symbol which leads to it, in a few compact tables of integers.
+//! `ParserTables::kernel_items` returns the items of a state, `accessing_symbol` the name of its
+//! symbol, and `state_description` a description for the people who wrote the input, such as
+//! "while parsing `Expr . PLUS Expr`", which goes well with a syntax error:
+//!
+//! ```ignore
+//!     let error = parser.last_error().unwrap();
+//!     println!("{}, {}", error.message(&info), tables.state_description(error.state));
+//! ```
+//!
+//! Without the feature, or with `%define debug.tables false`, the tables are empty, and so are
+//! the descriptions.
+//!
+//! ## Reporting syntax errors
+//!
+//! After `push_token` or `finish` returns `SyntaxError`, `parser.last_error()` describes the
+//! error: the state, the offending token, and the tokens that the parser would have accepted
+//! instead.  `ParseError::message` turns it into a message such as "unexpected PLUS; expected
+//! NUM, LPAREN or MINUS", using the token names from `get_parser_tables().grammar_info()`.
+//!
 //! # License
 //!
 //! Berkeley YACC is in the public domain.  From its `README` file:
@@ -198,6 +1194,15 @@
 //! So if you build anything using RACC, please be aware that both Rust and RACC are still evolving
 //! quickly, and your code may break quickly and without notice.
 //!
+//! RACC does not build on a stable toolchain.  It is written for the Rust nightlies of late 2014,
+//! and the `racc!` macro is a compiler plugin, which uses the compiler's own parser and syntax
+//! tree.  RACC has not been ported to the `proc_macro` interface: that would mean porting all
+//! three crates to a later Rust, since the forms they are written in (`uint`, `#[deriving]`, and
+//! so on) are not accepted by it.  Within this crate, the compiler-specific code is confined to
+//! two modules: `reader` (which turns the macro's tokens into calls on a `GrammarBuilder`) and
+//! `output` (which turns the packed tables and action blocks into items).  `racc-core` and
+//! `racc-runtime` do not use any compiler internals.
+//!
 //! The original Berkeley YACC contains a strident disclaimer, which is repeated here:
 //!
 //!          Berkeley Yacc is distributed with no warranty whatever.  The author
@@ -211,14 +1216,8 @@
 //!
 //! * `finish` is probably not strict enough.
 //!
-//! * Allow grammars to specify precedence and associativity.  The underlying code implements
-//!   support for precedence and associativity, exactly as in Berkeley YACC, but this is not
-//!   yet expose.
-//!
 //! * Support reading standalone grammars, either using the Rust parser or something else.
 //!
-//! * Port a lexical analyzer, too.
-//!
 //! # Author
 //!
 //! RACC was implemented by Arlie Davis `arlie.davis@gmail.com`.  I did this as an experiment
@@ -235,6 +1234,7 @@
 #![feature(phase)]
 #![feature(quote)]
 #![feature(macro_rules)]
+#![feature(globs)]
 
 #![allow(dead_code)]
 #![allow(non_upper_case_globals)]
@@ -247,8 +1247,13 @@ extern crate rustc;
 // #[phase(plugin, link)]
 extern crate syntax;
 
+extern crate racc_core;
+
+#[cfg(feature = "runtime")]
+extern crate racc_runtime;
+
 use syntax::ast;
-use syntax::ext::base::{ExtCtxt, MacResult, MacItems};
+use syntax::ext::base::{ExtCtxt, MacResult, MacItems, DummyResult};
 // use syntax::ext::build::AstBuilder;
 use syntax::codemap;
 use syntax::parse::token::Token;
@@ -256,23 +1261,43 @@ use syntax::ptr::P;
 use syntax::print::pprust;
 use rustc::plugin::Registry;
 
-mod closure;
-mod grammar;
-mod lalr;
-mod lr0;
-mod util;
-mod warshall;
+use std::io::File;
+use std::os;
+
+use racc_core::{Grammar, GenError, SymbolId, RuleId, Profile, Phase, LrType};
+use racc_core::cache::{CachedAnalysis, CachedConflict};
+use racc_core::check::SymbolProblem;
+use racc_core::conflicts::ConflictKind;
+use racc_core::lexgen::Pattern;
+
 mod reader;
 mod output;
-mod mkpar;
+mod token_sets;
+mod fragments;
+
+// The grammar analysis engine lives in the racc-core crate.  It is re-exported here, so that
+// users of the grammar! macro can also use the analysis API without a second dependency.
+pub use racc_core::{builder, grammar, lr0, lalr, mkpar, tables, util, random};
 
 /// Contains the supporting logic needed for applications that wish to use RACC-generated parsers.
-pub mod runtime;
+/// This is a re-export of the `racc_runtime` crate, and is present only when the `runtime`
+/// feature is enabled (it is, by default).  Programs which only run generated parsers can
+/// depend on `racc_runtime` directly.
+#[cfg(feature = "runtime")]
+pub mod runtime {
+    pub use racc_runtime::*;
+}
+
+/// Builds parsers from grammars at run time, without generating any code.
+#[cfg(feature = "runtime")]
+pub mod dynamic;
 
 #[plugin_registrar]
 pub fn plugin_registrar(reg: &mut Registry) {
     info!("yacc plugin_registrar");
     reg.register_macro("grammar", expand_grammar);
+    reg.register_macro("grammar_tokens", token_sets::expand_grammar_tokens);
+    reg.register_macro("grammar_fragment", fragments::expand_grammar_fragment);
 }
 
 fn expand_grammar(cx: &mut ExtCtxt, sp: codemap::Span, tts: &[ast::TokenTree]) -> Box<MacResult+'static> {
@@ -296,17 +1321,177 @@ fn expand_grammar(cx: &mut ExtCtxt, sp: codemap::Span, tts: &[ast::TokenTree]) -
 
     // Read the tokens and rules.
 
-    let (gram, action_blocks, rhs_binding) = reader::read_grammar(sp, &mut parser);
+    let reader::GrammarDef { gram, actions, rhs_binding, shared_tokens, options, symbol_types, ast_items, spans, starts, lex_rules, lexer_states, keywords, trivia, externals, parse_params, exprs } = reader::read_grammar(cx, sp, &mut parser, &symbol_value_ty);
 
-    let lr0 = lr0::compute_lr0(&gram);
-    let lalr_out = lalr::run_lalr(&gram, &lr0);
-    let yaccparser = mkpar::make_parser(&gram, &lr0, &lalr_out);
+    // A parser which builds a tree has nodes on its value stack; the values of the tokens are in
+    // the leaves.
+    let symbol_value_ty = if options.tree == reader::TreeKind::Cst { quote_ty!(cx, Node<$symbol_value_ty>) } else { symbol_value_ty };
+    gen_items.extend(ast_items.into_iter());
 
-    let yacc_items = output::output_parser_to_ast(cx, sp, &gram, &lalr_out.gotos, &yaccparser, action_blocks, rhs_binding, context_type_ident, context_param_ident, symbol_value_ty);
+    // Non-terminals which derive nothing, or which cannot be reached, are almost always mistakes.
+    // A start symbol which derives nothing makes a parser which accepts nothing.
+    let useless_symbols = racc_core::check::find_useless_symbols(&gram);
+    for useless in useless_symbols.iter() {
+        let span = spans.symbol(useless.symbol);
+        if useless.symbol.index() == gram.start_symbol + 1 && useless.problem == SymbolProblem::Unproductive {
+            cx.span_err(span, useless.describe(&gram).as_slice());
+        }
+        else {
+            cx.span_warn(span, useless.describe(&gram).as_slice());
+        }
+    }
+    let useless_rules = racc_core::check::find_useless_rules(&gram);
+    for useless in useless_rules.iter() {
+        let span = spans.rule(useless.rule);
+        cx.span_warn(span, useless.describe(&gram).as_slice());
+        cx.span_note(span, useless.suggestion());
+    }
+    // A cycle makes the grammar ambiguous.  A right-recursive list makes the parser's stack as
+    // deep as the list is long.
+    let recursion = gram.recursion();
+    for &sym in recursion.cycles.iter() {
+        cx.span_warn(spans.symbol(sym), format!("'{}' can derive itself alone ({} =>+ {}), so the grammar is ambiguous", gram.pname[sym.index()], gram.pname[sym.index()], gram.pname[sym.index()]).as_slice());
+    }
+    for &rule in recursion.right_recursive_lists.iter() {
+        let span = spans.rule(rule);
+        cx.span_warn(span, format!("right-recursive list: {}; the parser's stack grows with the length of the list", gram.rule_to_str(rule.index())).as_slice());
+        cx.span_note(span, "a left-recursive rule, such as List : List Item, parses a list in constant stack space");
+    }
+    if let Some(summary) = racc_core::check::summary(useless_symbols.as_slice(), useless_rules.as_slice()) {
+        cx.span_warn(sp, summary.as_slice());
+    }
+
+    // The analysis is cached (see racc_core::cache) in the directory named by RACC_CACHE_DIR,
+    // or else by OUT_DIR, so that changing only the actions does not rebuild the automaton.  A
+    // report or a graph needs the automaton itself, so asking for one bypasses the cache.
+    let key = racc_core::cache::grammar_key(&gram, options.lr_type);
+    let cache_file = if options.report_file.is_none() && options.dot_file.is_none() && options.html_file.is_none()
+        && options.json_file.is_none() {
+        os::getenv("RACC_CACHE_DIR").or_else(|| os::getenv("OUT_DIR"))
+            .map(|dir| racc_core::cache::cache_path(&Path::new(dir), key))
+    }
+    else {
+        None
+    };
+    let analysis = match cache_file.as_ref().and_then(|path| racc_core::cache::load(path, key)) {
+        Some(analysis) => analysis,
+        None => {
+            let analysis = match analyze_grammar(cx, sp, &gram, &spans, &options, key) {
+                Some(analysis) => analysis,
+                None => return DummyResult::any(sp)
+            };
+            if let Some(ref path) = cache_file {
+                if let Err(e) = racc_core::cache::store(path, &analysis) {
+                    cx.span_warn(sp, format!("cannot write the cache file '{}': {}", path.display(), e).as_slice());
+                }
+            }
+            analysis
+        }
+    };
+
+    // Conflicts of a kind whose number is given by %expect or %expect-rr are not reported one by
+    // one.  Instead, the number must match.
+    check_expected_conflicts(cx, sp, "shift/reduce", options.expect_sr, analysis.sr_conflicts);
+    check_expected_conflicts(cx, sp, "reduce/reduce", options.expect_rr, analysis.rr_conflicts);
+    for conflict in analysis.conflicts.iter() {
+        let expected = match conflict.kind {
+            ConflictKind::ShiftReduce => options.expect_sr.is_some(),
+            ConflictKind::ReduceReduce => options.expect_rr.is_some()
+        };
+        if !expected {
+            // The conflict is reported at the first rule involved in it, with a note at each of
+            // the others.
+            let span = conflict.rules.first().map_or(sp, |&rule| spans.rule(RuleId(rule)));
+            cx.span_warn(span, conflict.description.as_slice());
+            for &rule in conflict.rules.iter().skip(1) {
+                cx.span_note(spans.rule(RuleId(rule)), format!("rule {} is also involved in this conflict", gram.rule_to_str(rule)).as_slice());
+            }
+        }
+    }
+    let tables = analysis.tables;
+
+    // A rule with %begin changes the state of the lexer when it is reduced.  If the parser had to
+    // read the next token to decide on the reduction, that token would have been scanned in the
+    // old state, so the parser must reduce by the rule by default.
+    for &(rule, _) in lexer_states.rules.iter() {
+        if !tables.reduces_by_default_only(rule) {
+            cx.span_err(spans.rule(RuleId(rule)), format!("the parser reduces by {} only after reading the next token, which the lexer has already scanned in its old state, so this rule cannot use %begin", gram.rule_to_str(rule)).as_slice());
+            cx.span_note(spans.rule(RuleId(rule)), "a rule which ends with a token that nothing else can follow in the rule's place is reduced without reading the next token");
+        }
+    }
+    // The state that each rule enters, plus one, or 0; indexed by rule, as in yyrules.
+    let lex_begin: Vec<i16> = if lexer_states.rules.len() == 0 {
+        Vec::new()
+    }
+    else {
+        let mut lex_begin: Vec<i16> = Vec::from_elem(gram.nrules - 2, 0);
+        for &(rule, state) in lexer_states.rules.iter() {
+            lex_begin[rule - 2] = state as i16 + 1;
+        }
+        lex_begin
+    };
+
+    // Which tokens are trivia, indexed by token value: 1, or 2 for trivia which ends a line.
+    let yytrivia: Vec<i16> = if trivia.len() == 0 {
+        Vec::new()
+    }
+    else {
+        let mut yytrivia: Vec<i16> = Vec::from_elem(tables.yyname.len(), 0);
+        for &(ref name, newline) in trivia.iter() {
+            yytrivia[token_value(&gram, name.as_slice()) as uint] = if newline { 2 } else { 1 };
+        }
+        yytrivia
+    };
+
+    // The lexer, if the grammar gives patterns for its tokens.  Each state of the lexer is a mode
+    // of the DFA, with the patterns which are active in it.
+    if lex_rules.len() != 0 {
+        let patterns: Vec<Pattern> = lex_rules.iter().map(|&(_, ref pattern, _)| pattern.clone()).collect();
+        let modes: Vec<Vec<uint>> = range(0, lexer_states.names.len()).map(|m|
+            range(0, patterns.len()).filter(|&p| {
+                let (ref states, _) = lexer_states.patterns[p];
+                states.contains(&m)
+            }).collect()).collect();
+        match racc_core::lexgen::build_modal_dfa(patterns.as_slice(), modes.as_slice()) {
+            Ok(dfa) => {
+                let token_values: Vec<Option<u32>> = lex_rules.iter().map(|&(ref name, _, _)|
+                    name.as_ref().map(|name| token_value(&gram, name.as_slice()))).collect();
+                let begins: Vec<Option<uint>> = lexer_states.patterns.iter().map(|&(_, begin)| begin).collect();
+                gen_items.push_all(output::make_scanner(cx, sp, &dfa, token_values.as_slice(), begins.as_slice(),
+                    lexer_states.names.as_slice(), keywords.is_some()).as_slice());
+            }
+            Err(errors) => {
+                for e in errors.iter() {
+                    let &(_, _, span) = &lex_rules[e.pattern];
+                    cx.span_err(span, format!("invalid pattern: {}", e.message).as_slice());
+                }
+                return DummyResult::any(sp);
+            }
+        }
+    }
+
+    // The keyword table, if the grammar declares keywords.
+    if let Some((ref ident, ref words)) = keywords {
+        let mut table: Vec<(String, u32, bool)> = Vec::new();
+        for &(ref token, ref text, contextual) in words.iter() {
+            table.push((text.clone(), token_value(&gram, token.as_slice()), contextual));
+        }
+        gen_items.push(output::make_keywords(cx, token_value(&gram, ident.as_slice()), table));
+    }
+
+    // The translation from the token numbers of an external lexer, if the grammar gives them.
+    if externals.len() != 0 {
+        let pairs: Vec<(u32, u32)> = externals.iter().map(|&(n, ref name)| (n, token_value(&gram, name.as_slice()))).collect();
+        gen_items.push_all(output::make_token_map(cx, sp, pairs.as_slice()).as_slice());
+    }
+
+    let yacc_items = output::output_parser_to_ast(cx, sp, &gram, &tables, &analysis.state_items, actions, rhs_binding, context_type_ident, context_param_ident, symbol_value_ty, symbol_types, !shared_tokens, options.tree == reader::TreeKind::Cst, &options.codegen, parse_params.as_slice(), starts.as_slice(), exprs.as_slice(), lex_begin.as_slice(), yytrivia.as_slice());
     for it in yacc_items.into_iter() {
         gen_items.push(it);
     }
 
+    let gen_items = output::apply_codegen_options(cx, sp, gen_items, &options.codegen);
+
     debug!("final items:");
     for it in gen_items.iter() {
         debug!("{}", pprust::item_to_string(&**it));
@@ -314,3 +1499,111 @@ fn expand_grammar(cx: &mut ExtCtxt, sp: codemap::Span, tts: &[ast::TokenTree]) -
 
     MacItems::new(gen_items.into_iter())
 }
+
+// Returns the value of the token named `name`, which must be a token of `gram`.
+fn token_value(gram: &Grammar, name: &str) -> u32 {
+    let sym = gram.name.iter().position(|n| n.as_slice() == name).unwrap();
+    gram.value[sym] as u32
+}
+
+// Builds the automaton and the packed tables of a grammar, and finds its conflicts.  Writes the
+// report and the graph, if the grammar asks for them.  Returns None if the tables cannot be
+// built, having reported why.
+fn analyze_grammar(cx: &mut ExtCtxt, sp: codemap::Span, gram: &Grammar, spans: &reader::GrammarSpans, options: &reader::GrammarOptions, key: u64) -> Option<CachedAnalysis> {
+    let mut profile = Profile::new();
+    let analysis = match racc_core::analyze_profiled(gram, options.lr_type, &mut profile) {
+        Ok(analysis) => analysis,
+        Err(e) => {
+            cx.span_err(gen_error_span(gram, spans, sp, &e), format!("cannot generate parser: {}", e).as_slice());
+            return None;
+        }
+    };
+
+    // An SLR(1) parser is chosen to be compared with LALR(1), so say what the difference is.
+    if options.lr_type == LrType::Slr {
+        match racc_core::slr::compare(gram, &analysis.lr0) {
+            Ok(ref comparison) if comparison.slr_only.len() != 0 => cx.span_note(sp, comparison.describe(gram).as_slice()),
+            Ok(_) => {}
+            Err(e) => cx.span_err(sp, format!("cannot build the LALR(1) parser to compare with: {}", e).as_slice())
+        }
+    }
+
+    if let Some(ref path) = options.report_file {
+        let result = File::create(&Path::new(path.as_slice())).and_then(|mut file|
+            racc_core::report::write_report(&mut file, gram, &analysis.lr0, &analysis.lalr, &analysis.parser));
+        if let Err(e) = result {
+            cx.span_err(sp, format!("cannot write the report to '{}': {}", path, e).as_slice());
+        }
+    }
+    if let Some(ref path) = options.dot_file {
+        let result = File::create(&Path::new(path.as_slice())).and_then(|mut file|
+            racc_core::dot::write_dot(&mut file, gram, &analysis.lr0, Some(&analysis.lalr)));
+        if let Err(e) = result {
+            cx.span_err(sp, format!("cannot write the graph to '{}': {}", path, e).as_slice());
+        }
+    }
+    if let Some(ref path) = options.html_file {
+        let result = File::create(&Path::new(path.as_slice())).and_then(|mut file|
+            racc_core::html::write_html(&mut file, gram, &analysis.lr0, &analysis.lalr, &analysis.parser));
+        if let Err(e) = result {
+            cx.span_err(sp, format!("cannot write the HTML report to '{}': {}", path, e).as_slice());
+        }
+    }
+
+    let conflicts: Vec<CachedConflict> = racc_core::conflicts::find_conflicts(gram, &analysis.lr0, &analysis.parser).iter().map(|conflict| {
+        CachedConflict {
+            kind: conflict.kind,
+            rules: conflict.rules(gram).iter().map(|rule| rule.index()).collect(),
+            description: conflict.describe(gram),
+        }
+    }).collect();
+
+    let start = profile.start();
+    let tables = match racc_core::tables::make_tables(gram, &analysis.lalr.gotos, &analysis.parser) {
+        Ok(tables) => tables,
+        Err(e) => {
+            cx.span_err(gen_error_span(gram, spans, sp, &e), format!("cannot generate parser tables: {}", e).as_slice());
+            return None;
+        }
+    };
+    profile.record(Phase::Packing, start, racc_core::profile::tables_bytes(&tables));
+    let state_items = racc_core::tables::make_state_items(gram, &analysis.lr0);
+    if let Some(ref path) = options.json_file {
+        let json = racc_core::export::export_to_json(gram, &analysis, &tables);
+        let result = File::create(&Path::new(path.as_slice())).and_then(|mut file| file.write_str(json.as_slice()));
+        if let Err(e) = result {
+            cx.span_err(sp, format!("cannot write the JSON export to '{}': {}", path, e).as_slice());
+        }
+    }
+    if os::getenv("RACC_PROFILE").is_some() {
+        cx.span_note(sp, format!("time and memory of each phase of the analysis:\n{}", profile).as_slice());
+        let stats = racc_core::stats::grammar_stats(gram, &analysis, &tables);
+        cx.span_note(sp, format!("statistics of the grammar:\n{}", stats).as_slice());
+    }
+
+    Some(CachedAnalysis {
+        version: racc_core::cache::CACHE_VERSION,
+        key: key,
+        tables: tables,
+        state_items: state_items,
+        sr_conflicts: analysis.parser.sr_conflicts,
+        rr_conflicts: analysis.parser.rr_conflicts,
+        conflicts: conflicts,
+    })
+}
+
+// Returns the span at which to report an error from the analysis: the declaration of the symbol
+// that the error names, or else the whole grammar.
+fn gen_error_span(gram: &Grammar, spans: &reader::GrammarSpans, sp: codemap::Span, e: &GenError) -> codemap::Span {
+    e.symbol()
+        .and_then(|name| gram.name.iter().position(|n| n.as_slice() == name))
+        .map_or(sp, |sym| spans.symbol(SymbolId(sym)))
+}
+
+fn check_expected_conflicts(cx: &mut ExtCtxt, sp: codemap::Span, kind: &str, expected: Option<uint>, actual: uint) {
+    if let Some(expected) = expected {
+        if expected != actual {
+            cx.span_err(sp, format!("the grammar was expected to have {} {} conflict(s), but it has {}", expected, kind, actual).as_slice());
+        }
+    }
+}
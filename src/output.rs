@@ -1,30 +1,56 @@
-use std::cmp;
-
 use syntax::ast;
-use syntax::ast::{Arm, Block, Expr, Generics, Item, Mutability, Pat, Stmt, UnsignedIntLit, Ty, TyU16, Ty_, WhereClause, MutMutable};
+use syntax::attr::AttrMetaMethods;
+use syntax::ast::{Arm, Expr, Generics, Item, Mutability, Pat, Stmt, UnsignedIntLit, Ty, TyU16, Ty_, WhereClause, MutMutable};
 use syntax::ext::build::{AstBuilder};
 use syntax::ext::base::{ExtCtxt};
 use syntax::ext::quote::rt::ExtParseUtils;
-use syntax::parse::token::{intern_and_get_ident};
+use syntax::parse::token;
+use syntax::parse::token::{intern_and_get_ident, InternedString};
+use syntax::print::pprust;
 use syntax::ptr::P;
 use syntax::codemap::{Span};
+use syntax::visit;
+use syntax::visit::Visitor;
 
 use syntax::owned_slice::OwnedSlice;
 
-use grammar::Grammar;
-use mkpar::{ActionCode, YaccParser};
-use lalr::GotoMap;
-use util::reverse_range;
-
-const I16_MAX: i16 = 0x7fff;
-const I16_MIN: i16 = -0x8000;
+use racc_core::grammar::Grammar;
+use racc_core::Assoc;
+use racc_core::lexgen::Dfa;
+use racc_core::tables::{PackedTables, StateItemTables};
+use racc_core::util::reverse_range;
+
+use reader::{Action, Builtin, ExprDef};
+
+// The options of the generated code, which a grammar sets with %define (see reader.rs).
+pub struct CodegenOptions {
+    // The name of the enum of the tokens; "api.token.enum".
+    pub token_enum: String,
+    // The name of a type alias for the parser, ParserState<V, C>; "api.parser.type".
+    pub parser_type: Option<String>,
+    // Whether the generated items are pub; "api.visibility".
+    pub public: bool,
+    // Traits to derive for the generated enums and structs, besides the ones that they always
+    // derive; "api.derive".
+    pub derives: Vec<String>,
+    // Whether the parser has the text of its rules, and logs each reduction; "debug.tables".
+    pub debug_tables: bool,
+    // The type of the errors of fallible actions, if they must all have the same one;
+    // "api.error.type".
+    pub error_type: Option<String>,
+}
 
-struct ActionsTable {
-    nvectors: uint,
-    tally: Vec<i16>,
-    width: Vec<i16>,
-    froms: Vec<Vec<i16>>,
-    tos: Vec<Vec<i16>>
+impl CodegenOptions {
+    pub fn new() -> CodegenOptions {
+        CodegenOptions {
+            token_enum: "Token".to_string(),
+            parser_type: None,
+            public: true,
+            derives: Vec::new(),
+            debug_tables: true,
+            error_type: None,
+        }
+    }
 }
 
 fn no_generics() -> Generics {
@@ -43,46 +69,74 @@ fn no_where() -> WhereClause {
 }
 
 
-// Given a constructed parser (a description of a state machine which parses
-// a given grammar), produces a Rust AST which implements the parser.
+// Given the packed tables of a constructed parser (a description of a state machine which
+// parses a given grammar), produces a Rust AST which implements the parser.
 pub fn output_parser_to_ast(
     cx: &ExtCtxt,
     grammar_span: Span,
     gram: &Grammar,
-    gotos: &GotoMap,
-    parser: &YaccParser,
-    blocks: Vec<Option<P<Block>>>,
-    rhs_binding: Vec<Option<ast::Ident>>,
+    tables: &PackedTables,
+    state_items: &StateItemTables,
+    actions: Vec<Option<Action>>,
+    rhs_binding: Vec<Vec<Option<ast::Ident>>>,   // indexed by rule, then by rhs position
     context_ty: P<Ty>,                      // Ident to use for the context type, passed to the reduce() method
     context_param_ident: ast::Ident,        // Ident to use for the context arg, passed to the reduce() method
-    symbol_value_ty: P<Ty>                  // type to use for value_stack
+    symbol_value_ty: P<Ty>,                 // type to use for value_stack
+    symbol_types: Vec<Option<(ast::Ident, P<Ty>)>>,  // variant and type of each symbol given by %type
+    emit_token_consts: bool,                // false if the grammar uses a shared token set
+    tree_nodes: bool,                       // true if the parser builds a tree (%define tree cst)
+    codegen: &CodegenOptions,
+    parse_params: &[(ast::Ident, P<Ty>)],   // the parameters given by %parse-param
+    starts: &[(String, String)],            // start token and entry point of each start symbol, if there are several
+    exprs: &[ExprDef],                      // the expressions declared by %expr
+    lex_begin: &[i16],                      // the lexer mode that each rule enters, plus one, or 0 (see YYLEXBEGIN)
+    trivia: &[i16]                          // for each token, 1 or 2 if it is trivia, or 0 (see YYTRIVIA)
     ) -> Vec<P<Item>> {
 
-    assert!(blocks.len() == gram.nrules);
+    assert!(actions.len() == gram.nrules);
 
     let sp = grammar_span;
 
     let mut items: Vec<P<Item>> = Vec::new();
 
-    items.push({
-        let v: Vec<i16> = parser.default_reductions.iter().map(|s| if *s != 0 { *s - 2 } else { 0 }).collect();
-        make_table_i16(cx, grammar_span, "YYDEFRED", v.as_slice())
-    });
-
-    for i in output_actions(cx, grammar_span, gram, gotos, parser).into_iter() {
-        items.push(i);
+    // If the grammar uses %type, then the value stack holds a generated enum, which has a variant
+    // for each type, and a variant (Untyped) for the symbols which do not have their own type.
+    // The enum has the lifetimes of the types of its variants, such as the 'a of the nodes of an
+    // AST in an arena.
+    let typed = symbol_types.len() != 0;
+    let mut value_lifetimes = ty_lifetimes(&*symbol_value_ty);
+    for t in symbol_types.iter() {
+        if let Some((_, ref ty)) = *t {
+            for l in ty_lifetimes(&**ty).into_iter() {
+                if !value_lifetimes.iter().any(|v| v.name == l.name) {
+                    value_lifetimes.push(l);
+                }
+            }
+        }
     }
+    if typed {
+        items.push(make_value_enum(cx, &symbol_value_ty, symbol_types.as_slice(), value_lifetimes.as_slice()));
+    }
+    let stack_value_ty: P<Ty> = if typed { ty_with_lifetimes(cx, sp, "YYValue", value_lifetimes.as_slice()) } else { symbol_value_ty.clone() };
+
+    items.push(make_table_i32(cx, sp, "YYDEFRED", tables.yydefred.as_slice()));
+    items.push(make_table_i32(cx, sp, "YYDGOTO", tables.yydgoto.as_slice()));
+    items.push(make_table_i32(cx, sp, "YYSINDEX", tables.yysindex.as_slice()));
+    items.push(make_table_i32(cx, sp, "YYRINDEX", tables.yyrindex.as_slice()));
+    items.push(make_table_i32(cx, sp, "YYGINDEX", tables.yygindex.as_slice()));
+    // todo, emit const YYTABLESIZE = m_high
+    items.push(make_table_i32(cx, sp, "YYTABLE", tables.yytable.as_slice()));
+    items.push(make_table_i32(cx, sp, "YYCHECK", tables.yycheck.as_slice()));
 
-    for t in range(1, gram.ntokens) {
+    if emit_token_consts {
         // todo: use the original Ident from parsing, for better error reporting
-        let tokvalue = gram.value[t];
-        let tok_ident = cx.ident_of(gram.name[t].as_slice());
-        let ty_u32 = quote_ty!(cx, u32);
-        items.push(cx.item_const(sp, tok_ident, ty_u32, expr_u32(cx, sp, tokvalue as u32)));
+        let tokens: Vec<(String, u32)> = range(1, gram.ntokens).map(|t| (gram.name[t].clone(), gram.value[t] as u32)).collect();
+        items.push_all(make_token_consts(cx, sp, tokens.as_slice()).as_slice());
+        items.push_all(make_token_enum(cx, codegen.token_enum.as_slice(), tokens.as_slice()).as_slice());
     }
 
     // Generate YYFINAL constant.
-    items.push(cx.item_const(sp, cx.ident_of("YYFINAL"), quote_ty!(cx, uint), cx.expr_uint(sp, parser.final_state)));
+    items.push(cx.item_const(sp, cx.ident_of("YYFINAL"), quote_ty!(cx, uint), cx.expr_uint(sp, tables.yyfinal)));
 
     /*
     items.push((quote_item!(cx, 
@@ -112,8 +166,9 @@ pub fn output_parser_to_ast(
 
     // Build up actions
     let mut action_arms: Vec<Arm> = Vec::new();
+    let mut trait_methods: Vec<(ast::Ident, String)> = Vec::new();
     let mut rule_iter: uint = 0;
-    for block in blocks.into_iter() {
+    for action in actions.into_iter() {
         let rule = rule_iter;
         rule_iter += 1;
 
@@ -127,27 +182,134 @@ pub fn output_parser_to_ast(
         // and bind them as a tuple named 'args'.
         let mut stmts: Vec<P<Stmt>> = Vec::new();
 
-        stmts.push(cx.parse_stmt(format!("debug!(\"{}\");", gram.rule_to_str(rule))));
-
-        let final_expr = match block {
-            Some(block) => {
-                // We need to pop items off the stack and associate them with variables from right to left.
-                let rhs_index = gram.rrhs[rule] as uint;
-                let rhs = gram.get_rhs_items(rule);
-                for i in reverse_range(rhs.len(), 0) {
-                    match rhs_binding[rhs_index + i] {
-                        Some(rbind) => {
-                            stmts.push(cx.stmt_let_typed(sp, false, rbind, 
-                                symbol_value_ty.clone(),
-                                cx.parse_expr(format!("value_stack.pop().unwrap()"))));
-                        }
-                        None => {
-                            // The rule has no binding for this value.  Pop it from the stack and discard it.
-                            stmts.push(cx.parse_stmt(format!("drop(value_stack.pop())")));
+        if codegen.debug_tables {
+            stmts.push(cx.parse_stmt(format!("debug!(\"{}\");", gram.rule_to_str(rule))));
+        }
+
+        // Bind the parameters given by %parse-param, for the actions which the author wrote.
+        // (An action method is called on the context, which these would borrow.)
+        let user_code = match action {
+            Some(Action::Block(_)) | Some(Action::Fallible(_)) | Some(Action::Inlined(..)) => true,
+            _ => false
+        };
+        if user_code {
+            for &(name, ref ty) in parse_params.iter() {
+                stmts.push(cx.parse_stmt(format!("let {} = {};", name.as_str(), param_expr(context_param_ident, name, &**ty))));
+            }
+        }
+
+        let lhs = gram.rlhs[rule] as uint;
+        let final_expr = match action {
+            Some(Action::Block(block)) => {
+                pop_bound_values(cx, sp, gram, rule, &rhs_binding[rule], &symbol_value_ty, symbol_types.as_slice(), &mut stmts);
+                Some(wrap_value(cx, sp, symbol_types.as_slice(), lhs, cx.expr_block(block)))
+            }
+            Some(Action::Fallible(block)) => {
+                pop_bound_values(cx, sp, gram, rule, &rhs_binding[rule], &symbol_value_ty, symbol_types.as_slice(), &mut stmts);
+                Some(wrap_value(cx, sp, symbol_types.as_slice(), lhs, fallible_expr(cx, block, codegen)))
+            }
+            Some(Action::Method(method_span, method)) => {
+                // The action is a method of the Actions trait, which the context type implements.
+                // The bound values are passed to the method, in order.
+                pop_bound_values(cx, sp, gram, rule, &rhs_binding[rule], &symbol_value_ty, symbol_types.as_slice(), &mut stmts);
+                let args: Vec<(ast::Ident, P<Ty>)> = rhs_binding[rule].iter()
+                    .zip(gram.get_rhs_items(rule).iter())
+                    .filter_map(|(b, &sym)| b.map(|b| (b, symbol_ty(&symbol_value_ty, symbol_types.as_slice(), sym as uint))))
+                    .collect();
+
+                if trait_methods.iter().any(|&(m, _)| m == method) {
+                    cx.span_err(method_span, "each action method can be used by only one rule");
+                }
+                let result_ty = symbol_ty(&symbol_value_ty, symbol_types.as_slice(), lhs);
+                trait_methods.push((method, make_action_method_sig(method, args.as_slice(), &result_ty)));
+
+                Some(wrap_value(cx, sp, symbol_types.as_slice(), lhs,
+                    cx.expr_method_call(sp,
+                        cx.expr_ident(sp, context_param_ident),
+                        method,
+                        args.iter().map(|&(a, _)| cx.expr_ident(sp, a)).collect())))
+            }
+            Some(Action::Builtin(Builtin::TreeNode)) => {
+                // The parser builds a tree.  All of the values of the rhs become the children of
+                // the new node, whether they are bound or not.
+                let len = gram.get_rhs_items(rule).len();
+                Some(cx.parse_expr(format!("Node::from_stack(value_stack, {}, {}, yyloc)", rule - 2, len)))
+            }
+            Some(Action::Builtin(Builtin::StartRule)) => {
+                // The rule "$start : YYSTART_A A" of one of several start symbols.  Its value is
+                // the value of A, which is already wrapped, and the start token is dropped.
+                stmts.push(cx.parse_stmt("let yyvalue = value_stack.pop().unwrap();".to_string()));
+                stmts.push(cx.parse_stmt("drop(value_stack.pop());".to_string()));
+                Some(cx.parse_expr("yyvalue".to_string()))
+            }
+            Some(Action::Builtin(Builtin::ExprClimb(i))) => {
+                // The rule "E : E$list" of an expression declared by %expr.  The list of operands
+                // and operators is arranged by precedence climbing, and the methods of the Actions
+                // trait that the expression names combine the operands.
+                pop_bound_values(cx, sp, gram, rule, &rhs_binding[rule], &symbol_value_ty, symbol_types.as_slice(), &mut stmts);
+                let expr = &exprs[i];
+                let value_ty = symbol_ty(&symbol_value_ty, symbol_types.as_slice(), lhs);
+                let op = (cx.ident_of("op"), quote_ty!(cx, u32));
+                let methods = vec![
+                    (Some(expr.binary_method), vec![op.clone(), (cx.ident_of("left"), value_ty.clone()), (cx.ident_of("right"), value_ty.clone())]),
+                    (expr.prefix_method, vec![op.clone(), (cx.ident_of("operand"), value_ty.clone())])];
+                for (method, args) in methods.into_iter() {
+                    if let Some((method_span, method)) = method {
+                        if trait_methods.iter().any(|&(m, _)| m == method) {
+                            cx.span_err(method_span, "each action method can be used by only one rule");
                         }
+                        trait_methods.push((method, make_action_method_sig(method, args.as_slice(), &value_ty)));
                     }
                 }
-                Some(cx.expr_block(block))
+                items.push(make_expr_operators(cx, gram, i, expr));
+
+                let context = token::get_ident(context_param_ident);
+                let (_, binary_method) = expr.binary_method;
+                let prefix_call = match expr.prefix_method {
+                    Some((_, method)) => format!("{}.{}(op, operand)", context, token::get_ident(method)),
+                    None => "unreachable!()".to_string()
+                };
+                Some(wrap_value(cx, sp, symbol_types.as_slice(), lhs, cx.parse_expr(format!(
+                    "match ::racc_runtime::expr::climb(list, &YYEXPR{}, |node| match node {{ \
+                        ::racc_runtime::expr::ExprNode::Binary(op, left, right) => {}.{}(op, left, right), \
+                        ::racc_runtime::expr::ExprNode::Prefix(op, operand) => {} }}) {{ \
+                        Ok(yyvalue) => yyvalue, \
+                        Err(yyerror) => return Err(box yyerror as Box<::std::any::Any + 'static>) }}",
+                    i, context, token::get_ident(binary_method), prefix_call))))
+            }
+            Some(Action::Builtin(builtin)) => {
+                // The rule was generated for an EBNF operator, or for the list of an expression.
+                pop_bound_values(cx, sp, gram, rule, &rhs_binding[rule], &symbol_value_ty, symbol_types.as_slice(), &mut stmts);
+                let value = match builtin {
+                    Builtin::ExprOperand | Builtin::ExprPrefix | Builtin::ExprBinary => expr_list_expr(cx, gram, rule, builtin),
+                    _ => builtin_expr(cx, builtin)
+                };
+                Some(wrap_value(cx, sp, symbol_types.as_slice(), lhs, value))
+            }
+            Some(Action::Inlined(inlined, outer)) => {
+                // The rules of %inline non-terminals were substituted into this rule.  Their
+                // actions run first, and their values are bound to the names that this rule
+                // gave to the non-terminals.
+                pop_bound_values(cx, sp, gram, rule, &rhs_binding[rule], &symbol_value_ty, symbol_types.as_slice(), &mut stmts);
+                for entry in inlined.into_iter() {
+                    let value = match entry.action {
+                        Some(Action::Block(block)) => cx.expr_block(block),
+                        Some(Action::Fallible(block)) => fallible_expr(cx, block, codegen),
+                        Some(Action::Builtin(builtin)) => builtin_expr(cx, builtin),
+                        _ => continue
+                    };
+                    stmts.push(match (entry.binding, entry.ty) {
+                        (Some(binding), Some(ty)) => cx.stmt_let_typed(sp, false, binding, ty, value),
+                        (Some(binding), None) => cx.stmt_let(sp, false, binding, value),
+                        (None, _) => cx.stmt_expr(value)
+                    });
+                }
+                match outer.map(|a| *a) {
+                    Some(Action::Block(block)) => Some(wrap_value(cx, sp, symbol_types.as_slice(), lhs, cx.expr_block(block))),
+                    Some(Action::Fallible(block)) => Some(wrap_value(cx, sp, symbol_types.as_slice(), lhs, fallible_expr(cx, block, codegen))),
+                    Some(Action::Builtin(builtin)) => Some(wrap_value(cx, sp, symbol_types.as_slice(), lhs, builtin_expr(cx, builtin))),
+                    _ => None
+                }
             }
             None => {
                 // This reduction does not have any code to execute.  Still, we need to
@@ -159,31 +321,67 @@ pub fn output_parser_to_ast(
             }
         };
 
-        action_arms.push(cx.arm(sp, vec![ pat ], cx.expr_block(cx.block(sp, stmts, final_expr))));
+        // The value of every rule is Ok; a fallible action returns its error early.
+        action_arms.push(cx.arm(sp, vec![ pat ], cx.expr_ok(sp, cx.expr_block(cx.block(sp, stmts, final_expr)))));
     }
     action_arms.push(cx.arm_unreachable(sp));
 
+    // Generate the Actions trait, if any rule uses an action method.
+    if trait_methods.len() != 0 {
+        let mut source = String::from_str("pub trait Actions {\n");
+        for &(_, ref sig) in trait_methods.iter() {
+            source.push_str(format!("    {};\n", sig).as_slice());
+        }
+        source.push_str("}\n");
+        debug!("{}", source);
+        items.push(cx.parse_item(source));
+    }
+
     // let ty_vec_symbol_value: P<Ty> = cx.ty(sp, ast::TyVec(symbol_value_ty.clone()));       // Vec<SymbolValue>
-    let ty_vec_symbol_value: P<Ty> = ty_vec_of(cx, sp, symbol_value_ty.clone());
+    let ty_vec_symbol_value: P<Ty> = ty_vec_of(cx, sp, stack_value_ty.clone());
     let ty_mutptr_vec_symbol_value: P<Ty> = cx.ty_rptr(sp, ty_vec_symbol_value.clone(), None, MutMutable);     // &mut Vec<SymbolValue>
 
-    // Generate the reduce() function.
-    let reduce_fn = cx.item_fn(
+    // Generate the reduce() function.  The actions can use the locations of the rhs symbols
+    // (yylocs[0] is the first) and the location of the lhs (yyloc), so these are often unused.
+    // All of the actions are arms of one match on the rule, rather than functions of their own,
+    // so that the compiler can inline them and optimize them together; benches/parse.rs measures
+    // the result.
+    // The context may be a type with lifetimes, such as the ParseParams<'a> of %parse-param, and
+    // so may the values; then the functions which name them are generic over those lifetimes.
+    let mut context_lifetimes = ty_lifetimes(&*context_ty);
+    for l in ty_lifetimes(&*stack_value_ty).into_iter() {
+        if !context_lifetimes.iter().any(|c| c.name == l.name) {
+            context_lifetimes.push(l);
+        }
+    }
+    let context_generics = Generics {
+        lifetimes: context_lifetimes.iter().map(|&l| ast::LifetimeDef { lifetime: l, bounds: vec![] }).collect(),
+        ty_params: OwnedSlice::empty(),
+        where_clause: no_where()
+    };
+    let reduce_fn = cx.item_fn_poly(
         sp,
         cx.ident_of("reduce"),
         vec![ // inputs
             // Arg::new_self(sp, Mutability::MutImmutable, cx.ident_of("self")),
             cx.arg(sp, cx.ident_of("value_stack"), ty_mutptr_vec_symbol_value),
+            cx.arg(sp, cx.ident_of("yylocs"), quote_ty!(cx, &[Location])),
+            cx.arg(sp, cx.ident_of("yyloc"), quote_ty!(cx, Location)),
             cx.arg(sp, cx.ident_of("reduction"), quote_ty!(cx, uint)),
             cx.arg(sp, context_param_ident, cx.ty_rptr(sp, context_ty.clone(), None, Mutability::MutMutable))
         ],
-        symbol_value_ty.clone(), // output type
-        
+        quote_ty!(cx, Result<$stack_value_ty, Box<::std::any::Any + 'static>>), // output type
+        context_generics.clone(),
         cx.block_expr(
             cx.expr_match(sp,
                 cx.expr_ident(sp, cx.ident_of("reduction")), action_arms)
         ));
-    items.push(reduce_fn);
+    let allow_unused = cx.attribute(sp, cx.meta_list(sp, InternedString::new("allow"),
+        vec![cx.meta_word(sp, InternedString::new("unused_variables"))]));
+    items.push(reduce_fn.map(|mut item| {
+        item.attrs.push(allow_unused);
+        item
+    }));
 
     // ParserTables<SymbolValue, AppContext>
     let ty_parser_tables = cx.ty_path(ast::Path {
@@ -194,7 +392,7 @@ pub fn output_parser_to_ast(
                 identifier: cx.ident_of("ParserTables"),
                 parameters: ast::AngleBracketedParameters(ast::AngleBracketedParameterData {
                     lifetimes: vec![],
-                    types: OwnedSlice::from_vec(vec![ symbol_value_ty.clone(), context_ty.clone() ]),
+                    types: OwnedSlice::from_vec(vec![ stack_value_ty.clone(), context_ty.clone() ]),
                     bindings: OwnedSlice::empty()
                 })
             }
@@ -209,18 +407,19 @@ pub fn output_parser_to_ast(
                 identifier: cx.ident_of("ParserState"),
                 parameters: ast::AngleBracketedParameters(ast::AngleBracketedParameterData {
                     lifetimes: vec![],
-                    types: OwnedSlice::from_vec(vec![ symbol_value_ty.clone(), context_ty.clone() ]),
+                    types: OwnedSlice::from_vec(vec![ stack_value_ty.clone(), context_ty.clone() ]),
                     bindings: OwnedSlice::empty()
                 })
             }
         ]});
 
     // Generate the get_parser_tables() function.
-    items.push(cx.item_fn(
+    items.push(cx.item_fn_poly(
         sp,
         cx.ident_of("get_parser_tables"),
         vec![], // inputs
         ty_parser_tables,
+        context_generics,
         cx.block_expr(
             cx.expr_struct(
                 sp,
@@ -240,13 +439,36 @@ pub fn output_parser_to_ast(
                         // ("yyfinal", "YYFINAL"),
                         ("yydgoto", "YYDGOTO"),
                         ("yyname", "YYNAME"),           // for debugging
-                        ("yyrules", "YYRULES")         // for debugging
+                        ("yyrules", "YYRULES"),         // for debugging
+                        ("yyvarname", "YYVARNAME"),
+                        ("yyconflicts", "YYCONFLICTS"),
+                        ("yylexbegin", "YYLEXBEGIN"),
+                        ("yytrivia", "YYTRIVIA"),
+                        ("yystos", "YYSTOS"),           // for describing states
+                        ("yykernelindex", "YYKERNELINDEX"),
+                        ("yykernel", "YYKERNEL"),
+                        ("yyrhsindex", "YYRHSINDEX"),
+                        ("yyrhs", "YYRHS")
                         // reduce: reduce
                     ]).into_iter().map(|(field, sitem)|
                             cx.field_imm(sp, cx.ident_of(field), cx.expr_method_call(sp, cx.expr_ident(sp, cx.ident_of(sitem)), as_slice_ident, vec![]))
                         ).collect();
                     fields.push(cx.field_imm(sp, cx.ident_of("yyfinal"), cx.expr_ident(sp, cx.ident_of("YYFINAL"))));
+                    fields.push(cx.field_imm(sp, cx.ident_of("yyhash"), cx.expr_ident(sp, cx.ident_of("YYHASH"))));
                     fields.push(cx.field_imm(sp, cx.ident_of("reduce"), cx.expr_ident(sp, cx.ident_of("reduce"))));
+                    // A parser which builds a tree keeps what it skips while recovering, in error nodes.
+                    let error_node = if tree_nodes { "Some(Node::error as fn(Vec<Node<_>>, Location) -> Node<_>)" } else { "None" };
+                    fields.push(cx.field_imm(sp, cx.ident_of("error_node"), cx.parse_expr(error_node.to_string())));
+                    // It also attaches trivia to the tokens around it.
+                    let (leading, trailing) = if tree_nodes {
+                        ("Some(Node::with_leading_trivia as fn(Node<_>, Vec<Node<_>>) -> Node<_>)",
+                         "Some(Node::attach_trailing_trivia as fn(&mut Node<_>, Node<_>) -> Result<(), Node<_>>)")
+                    }
+                    else {
+                        ("None", "None")
+                    };
+                    fields.push(cx.field_imm(sp, cx.ident_of("leading_trivia"), cx.parse_expr(leading.to_string())));
+                    fields.push(cx.field_imm(sp, cx.ident_of("trailing_trivia"), cx.parse_expr(trailing.to_string())));
                     fields
                 }
             )
@@ -266,567 +488,600 @@ pub fn output_parser_to_ast(
                 ]))));
     */
 
-    items.push(output_rule_data(cx, sp, gram));
-
-    // Emit the YYLEN table.
-    items.push({
-        let yylen: Vec<i16> = range(2, gram.nrules).map(|r| gram.rrhs[r + 1] - gram.rrhs[r] - 1).collect();
-        make_table_i16(cx, sp, "YYLEN", yylen.as_slice())
-    });
-
-    // emit some tables just for debugging
-    items.push(make_symbol_names_table(cx, sp, gram));
-    items.push(make_rule_text_table(cx, sp, gram));
-
-    items
-}
-
-// Generates the YYLHS table.
-fn output_rule_data(cx: &ExtCtxt, span: Span, gram: &Grammar) -> P<Item> {
-    let mut data: Vec<i16> = Vec::new();
-    data.push(gram.value[gram.start_symbol]);
-    for i in range(3, gram.nrules) {
-        data.push(gram.value[gram.rlhs[i] as uint]);
+    // Generate an entry point for each start symbol, if there are several.  Each one pushes the
+    // start token that selects its symbol, and then parses the tokens of the lexer.
+    let value_ty_str = pprust::ty_to_string(&*stack_value_ty);
+    let context_ty_str = pprust::ty_to_string(&*context_ty);
+    let lifetimes_str: String = context_lifetimes.iter().map(|l| format!("{}, ", token::get_name(l.name).get())).collect();
+    for &(ref start_token, ref entry) in starts.iter() {
+        let source = format!(
+            "pub fn {entry}<{lts}L: Lexer<{v}>>(parser: &mut ParserState<{v}, {c}>, ctx: &mut {c}, lexer: &mut L, start_value: {v}) -> FinishParseResult<{v}> {{ \
+                parser.reset(); \
+                parser.push_token(ctx, {token}, start_value); \
+                parser.parse(ctx, lexer) \
+            }}",
+            entry = entry, lts = lifetimes_str, v = value_ty_str, c = context_ty_str, token = start_token);
+        debug!("{}", source);
+        items.push(cx.parse_item(source));
     }
-    make_table_i16(cx, span, "YYLHS", data.as_slice())
-}
-
-fn make_symbol_names_table(cx: &ExtCtxt, span: Span, gram: &Grammar) -> P<Item> {
-    // The values used at runtime are not symbol indices.  They are token values, which come from gram.value[token].value.
-    // This is ugly and inefficient.
 
-    let mut max_value: i16 = I16_MIN;
-    for i in range(0, gram.ntokens) {
-        max_value = cmp::max(max_value, gram.value[i]);
+    // A type alias for the parser, if the grammar names one.
+    if let Some(ref name) = codegen.parser_type {
+        items.push(cx.parse_item(format!("pub type {}{} = ParserState<{}, {}>;", name, lifetime_params(context_lifetimes.as_slice()), value_ty_str, context_ty_str)));
     }
 
-    assert!(max_value >= 0);
-    assert!(max_value < I16_MAX);
-    let length = (max_value + 1) as uint;
+    // The ParseParams struct, and parse(), if the grammar uses %parse-param.
+    if parse_params.len() != 0 {
+        items.push_all(make_parse_params(cx, parse_params, value_ty_str.as_slice(), ty_lifetimes(&*stack_value_ty).as_slice(), starts.len() == 0).as_slice());
+    }
 
-    let mut toknames: Vec<String> = Vec::from_elem(length, String::new());
-    
-    // Now put the names into proper places.
-    for i in range(0, gram.ntokens) {
-        toknames[gram.value[i] as uint] = gram.name[i].clone();
+    items.push(make_table_i16(cx, sp, "YYLHS", tables.yylhs.as_slice()));
+    items.push(make_table_i16(cx, sp, "YYLEN", tables.yylen.as_slice()));
+
+    // emit some tables just for debugging.  Without debug tables, the rules have no text, but
+    // the table keeps its length, since the runtime indexes it.
+    items.push(make_table_string(cx, sp, "YYNAME", &tables.yyname));
+    let no_rules: Vec<String> = tables.yyrules.iter().map(|_| String::new()).collect();
+    items.push(make_table_string(cx, sp, "YYRULES", if codegen.debug_tables { &tables.yyrules } else { &no_rules }));
+
+    // emit the grammar description used by GrammarInfo
+    items.push(make_table_string(cx, sp, "YYVARNAME", &tables.yyvarname));
+    items.push(cx.item_const(sp, cx.ident_of("YYHASH"), quote_ty!(cx, u64),
+        cx.expr_lit(sp, ast::LitInt(tables.yyhash, ast::UnsignedIntLit(ast::TyU64)))));
+
+    // emit the actions which lost conflicts, used by GlrParser
+    items.push(make_table_i32(cx, sp, "YYCONFLICTS", tables.yyconflicts.as_slice()));
+
+    // emit the lexer modes that rules enter (%begin), used by ParserState::parse_scanner
+    items.push(make_table_i16(cx, sp, "YYLEXBEGIN", lex_begin));
+
+    // emit the tokens which are trivia (%trivia), used by ParserState::push_token_at
+    items.push(make_table_i16(cx, sp, "YYTRIVIA", trivia));
+
+    // emit the items of the states, for error messages and debuggers.  These are empty unless
+    // the state-items feature is enabled, and the grammar has debug tables.
+    let no_items: Vec<u32> = Vec::new();
+    let emit_items = codegen.debug_tables && cfg!(feature = "state-items");
+    let state_tables = [
+        ("YYSTOS", &state_items.yystos),
+        ("YYKERNELINDEX", &state_items.yykernelindex),
+        ("YYKERNEL", &state_items.yykernel),
+        ("YYRHSINDEX", &state_items.yyrhsindex),
+        ("YYRHS", &state_items.yyrhs)
+    ];
+    for &(name, table) in state_tables.iter() {
+        items.push(make_table_u32(cx, sp, name, if emit_items { table.as_slice() } else { no_items.as_slice() }));
     }
 
-    make_table_string(cx, span, "YYNAME", &toknames)
+    items
 }
 
-fn make_table_string(cx: &ExtCtxt, span: Span, name: &str, strings: &Vec<String>) -> P<Item> {
-    cx.item_static(span, 
-        cx.ident_of(name), 
-        cx.ty(span, Ty_::TyFixedLengthVec(quote_ty!(cx, &'static str), cx.expr_uint(span, strings.len()))),
-        Mutability::MutImmutable,
-        cx.expr_vec(span, Vec::from_fn(strings.len(), |i| {
-            let iname = intern_and_get_ident(strings[i].as_slice());
-            cx.expr_str(span, iname)
+// Emits statements which pop the values of the rhs of a rule off the value stack, from right to
+// left, and bind them to the names given in the grammar.  Values without a name are discarded.
+// If the grammar uses %type, each value is unwrapped from its variant of YYValue.
+fn pop_bound_values(cx: &ExtCtxt, sp: Span, gram: &Grammar, rule: uint, binding: &Vec<Option<ast::Ident>>, symbol_value_ty: &P<Ty>,
+                    symbol_types: &[Option<(ast::Ident, P<Ty>)>], stmts: &mut Vec<P<Stmt>>) {
+    let rhs = gram.get_rhs_items(rule);
+    for i in reverse_range(rhs.len(), 0) {
+        match binding[i] {
+            Some(rbind) => {
+                let sym = rhs[i] as uint;
+                let value = if symbol_types.len() == 0 {
+                    cx.parse_expr(format!("value_stack.pop().unwrap()"))
+                }
+                else {
+                    cx.parse_expr(format!("match value_stack.pop().unwrap() {{ YYValue::{}(value) => value, _ => unreachable!() }}",
+                        token::get_ident(symbol_variant(cx, symbol_types, sym))))
+                };
+                stmts.push(cx.stmt_let_typed(sp, false, rbind, symbol_ty(symbol_value_ty, symbol_types, sym), value));
+            }
+            None => {
+                // The rule has no binding for this value.  Pop it from the stack and discard it.
+                stmts.push(cx.parse_stmt(format!("drop(value_stack.pop())")));
+            }
         }
-        )))
-}
-
-fn make_rule_text_table(cx: &ExtCtxt, span: Span, gram: &Grammar) -> P<Item> {
-    let rules: Vec<String> = range(2, gram.nrules).map(|rule| gram.rule_to_str(rule)).collect();
-    make_table_string(cx, span, "YYRULES", &rules)
-}
-
-
-#[allow(dead_code)]
-fn make_table_uint(cx: &ExtCtxt, span: Span, name: &str, values: &[uint]) -> P<Item> {
-    let values_expr = cx.expr_vec(span, Vec::from_fn(values.len(), |i| cx.expr_uint(span, values[i])));
-    let ty_uint = quote_ty!(cx, uint);
-    let table_ident = cx.ident_of(name);
-    let table_ty = cx.ty(span, Ty_::TyFixedLengthVec(ty_uint, cx.expr_uint(span, values.len())));
-    let table_item = cx.item_static(span, table_ident, table_ty, Mutability::MutImmutable, values_expr);
-    // debug!("built table item for '{}': values {}", name, values);
-    // debug!("item: {}", pprust::item_to_string(&*table_item));
-    table_item
+    }
 }
 
-fn make_table_i16(cx: &ExtCtxt, span: Span, name: &str, values: &[i16]) -> P<Item> {
-    make_table_i16_as_u16(cx, span, name, values)
+// Returns the value of a fallible action ("{ ... }?"), whose block evaluates to a Result: the Ok
+// value, or else reduce() returns the error, which ends the parse (see ActionError).  With
+// api.error.type, the error must be of that type.
+fn fallible_expr(cx: &ExtCtxt, block: P<ast::Block>, codegen: &CodegenOptions) -> P<Expr> {
+    let error_ty = match codegen.error_type {
+        Some(ref ty) => format!(": {}", ty),
+        None => String::new()
+    };
+    cx.parse_expr(format!("match {} {{ Ok(yyvalue) => yyvalue, Err(yyerror) => {{ let yyerror{} = yyerror; return Err(box yyerror as Box<::std::any::Any + 'static>); }} }}",
+        pprust::block_to_string(&*block), error_ty))
 }
 
-fn make_table_i16_real(cx: &ExtCtxt, span: Span, name: &str, values: &[i16]) -> P<Item> {
-    let values_expr = cx.expr_vec(span, Vec::from_fn(values.len(), |i| expr_i16(cx, span, values[i])));
-    let ty_i16 = quote_ty!(cx, i16);
-    let table_ident = cx.ident_of(name);
-    let table_ty = cx.ty(span, Ty_::TyFixedLengthVec(ty_i16, cx.expr_uint(span, values.len())));
-    let table_item = cx.item_static(span, table_ident, table_ty, Mutability::MutImmutable, values_expr);
-    // debug!("built table item for '{}': values {}", name, values);
-    // debug!("item: {}", pprust::item_to_string(&*table_item));
-    table_item
+// Returns the lifetimes which are named in a type, other than 'static, in order.
+pub fn ty_lifetimes(ty: &Ty) -> Vec<ast::Lifetime> {
+    struct LifetimeFinder {
+        lifetimes: Vec<ast::Lifetime>,
+    }
+    impl<'v> Visitor<'v> for LifetimeFinder {
+        fn visit_lifetime_ref(&mut self, lifetime: &'v ast::Lifetime) {
+            if token::get_name(lifetime.name).get() != "'static" && !self.lifetimes.iter().any(|l| l.name == lifetime.name) {
+                self.lifetimes.push(*lifetime);
+            }
+        }
+    }
+    let mut finder = LifetimeFinder { lifetimes: Vec::new() };
+    visit::walk_ty(&mut finder, ty);
+    finder.lifetimes
 }
 
-fn expr_i16(cx: &ExtCtxt, span: Span, i: i16) -> P<ast::Expr> {
-    cx.expr_lit(span, ast::LitInt(
-        i as u64,
-        ast::LitIntType::SignedIntLit(ast::TyI16, ast::Sign::new(i as int))))
+// Returns "<'a, 'b>" for the lifetimes, or "" if there are none.
+pub fn lifetime_params(lifetimes: &[ast::Lifetime]) -> String {
+    if lifetimes.len() == 0 {
+        return String::new();
+    }
+    let names: Vec<String> = lifetimes.iter().map(|l| token::get_name(l.name).get().to_string()).collect();
+    format!("<{}>", names.connect(", "))
 }
 
-fn expr_u16(cx: &ExtCtxt, span: Span, u: u16) -> P<ast::Expr> {
-    cx.expr_lit(span, ast::LitInt(
-        u as u64,
-        UnsignedIntLit(TyU16)))
+// Returns the type `name<'a, ...>`, with the given lifetimes.
+pub fn ty_with_lifetimes(cx: &ExtCtxt, sp: Span, name: &str, lifetimes: &[ast::Lifetime]) -> P<Ty> {
+    cx.ty_path(ast::Path {
+        span: sp,
+        global: false,
+        segments: vec![
+            ast::PathSegment {
+                identifier: cx.ident_of(name),
+                parameters: ast::AngleBracketedParameters(ast::AngleBracketedParameterData {
+                    lifetimes: lifetimes.to_vec(),
+                    types: OwnedSlice::empty(),
+                    bindings: OwnedSlice::empty()
+                })
+            }
+        ]})
 }
 
-// yuck
-fn make_table_i16_as_u16(cx: &ExtCtxt, span: Span, name: &str, values: &[i16]) -> P<Item> {
-    let values_expr = cx.expr_vec(span, Vec::from_fn(values.len(), |i| expr_u16(cx, span, values[i] as u16)));
-    let ty_u16 = quote_ty!(cx, u16);
-    let table_ident = cx.ident_of(name);
-    let table_ty = cx.ty(span, Ty_::TyFixedLengthVec(ty_u16, cx.expr_uint(span, values.len())));
-    let table_item = cx.item_static(span, table_ident, table_ty, Mutability::MutImmutable, values_expr);
-    table_item
+// Returns the type of a %parse-param field: a reference is given the lifetime 'a, unless it has
+// one already.
+fn param_field_ty(ty: &Ty) -> String {
+    match ty.node {
+        ast::TyRptr(None, ref mt) => {
+            format!("&'a {}{}", if mt.mutbl == MutMutable { "mut " } else { "" }, pprust::ty_to_string(&*mt.ty))
+        }
+        _ => pprust::ty_to_string(ty)
+    }
 }
 
-
-
-fn output_actions(cx: &ExtCtxt, span: Span, gram: &Grammar, gotos: &GotoMap, parser: &YaccParser) -> Vec<P<Item>> {
-    // debug!("output_actions");
-
-    let nstates = parser.nstates;
-    let mut items: Vec<P<Item>> = Vec::new();
-
-    let mut act = token_actions(gram, parser);
-    let dgoto = goto_actions(gram, nstates, gotos, &mut act);
-    let (nentries, order) = sort_actions(&mut act);
-    
-    let packed = pack_table(parser.nstates, nentries, order.as_slice(), &act);
-
-    // debug!("emitting tables");
-
-    items.push(make_table_i16(cx, span, "YYDGOTO", dgoto.as_slice()));
-
-    // was output_base
-    items.push(make_table_i16(cx, span, "YYSINDEX", packed.base.slice(0, nstates)));
-    items.push(make_table_i16(cx, span, "YYRINDEX", packed.base.slice(nstates, nstates * 2)));
-    items.push(make_table_i16(cx, span, "YYGINDEX", packed.base.slice(nstates * 2, act.nvectors)));
-
-    // was output_table
-    // todo, emit const YYTABLESIZE = m_high
-    items.push(make_table_i16(cx, span, "YYTABLE", packed.table.slice(0, packed.high + 1)));
-
-    // was output_check
-    items.push(make_table_i16(cx, span, "YYCHECK", packed.check.slice(0, packed.high + 1)));
-
-    items
+// Returns the expression which binds a %parse-param in an action: a reborrow of a &mut
+// reference, a copy of a & reference, or else a &mut reference to the field.
+fn param_expr(context: ast::Ident, name: ast::Ident, ty: &Ty) -> String {
+    match ty.node {
+        ast::TyRptr(_, ref mt) if mt.mutbl == MutMutable => format!("&mut *{}.{}", context.as_str(), name.as_str()),
+        ast::TyRptr(..) => format!("{}.{}", context.as_str(), name.as_str()),
+        _ => format!("&mut {}.{}", context.as_str(), name.as_str())
+    }
 }
 
-fn token_actions(gram: &Grammar, parser: &YaccParser) -> ActionsTable {
-    debug!("token_actions()");
-
-    let nstates = parser.nstates;
-    let nvectors = 2 * nstates + gram.nvars;
-    let mut tally: Vec<i16> = Vec::from_elem(nvectors, 0);
-    let mut width: Vec<i16> = Vec::from_elem(nvectors, 0);
-    let mut froms: Vec<Vec<i16>> = Vec::from_elem(nvectors, Vec::new());
-    let mut tos: Vec<Vec<i16>> = Vec::from_elem(nvectors, Vec::new());
-    let mut actionrow: Vec<i16> = Vec::from_elem(2 * gram.ntokens, 0);
-
-    for i in range(0, nstates) {
-        let actions = &parser.actions[i];
-        if actions.len() != 0 {
-            debug!("    state={}", i);
-            for ii in actionrow.iter_mut() {
-                *ii = 0;
+// Generates the ParseParams struct, whose fields are the parameters given by %parse-param, and
+// (if the grammar has one start symbol) a parse() function which takes them, along with a lexer.
+// The struct has the lifetime 'a if any of its fields are references.
+fn make_parse_params(cx: &ExtCtxt, params: &[(ast::Ident, P<Ty>)], value_ty: &str, value_lifetimes: &[ast::Lifetime], with_parse: bool) -> Vec<P<Item>> {
+    let any_refs = params.iter().any(|&(_, ref ty)| match ty.node { ast::TyRptr(..) => true, _ => false });
+    let lifetime = if any_refs { "<'a>" } else { "" };
+    let fields: Vec<String> = params.iter().map(|&(name, ref ty)| format!("pub {}: {},", name.as_str(), param_field_ty(&**ty))).collect();
+    let mut items = vec![cx.parse_item(format!("pub struct ParseParams{} {{ {} }}", lifetime, fields.concat()))];
+    if with_parse {
+        // parse() is generic over 'a, and over the lifetimes of the values, such as the 'input
+        // of tokens which borrow from the input.
+        let mut lifetimes: Vec<String> = if any_refs { vec!["'a".to_string()] } else { Vec::new() };
+        for l in value_lifetimes.iter() {
+            let name = token::get_name(l.name).get().to_string();
+            if !lifetimes.contains(&name) {
+                lifetimes.push(name);
             }
-
-            let mut shiftcount: uint = 0;
-            let mut reducecount: uint = 0;
-            for p in actions.iter() {
-                if p.suppressed == 0 {
-                    if p.action_code == ActionCode::Shift {
-                        shiftcount += 1;
-                        actionrow[p.symbol as uint] = p.number;
-                        // debug!("        shift {}", p.number);
-                    }
-                    else if p.action_code == ActionCode::Reduce && p.number != parser.default_reductions[i] {
-                        reducecount += 1;
-                        actionrow[(p.symbol as uint) + gram.ntokens] = p.number;
-                        // debug!("        reduce {}", p.number);
-                    }
-                }
-            }
-
-            debug!("        shiftcount={} reducecount={}", shiftcount, reducecount);
-
-            tally[i] = shiftcount as i16;
-            tally[nstates + i] = reducecount as i16;
-            width[i] = 0;
-            width[nstates + i] = 0;
-
-            if shiftcount > 0 {
-                let mut r: Vec<i16> = Vec::with_capacity(shiftcount);
-                let mut s: Vec<i16> = Vec::with_capacity(shiftcount);
-                let mut min = I16_MAX;
-                let mut max = 0;
-                for j in range(0, gram.ntokens) {
-                    if actionrow[j] != 0 {
-                        min = cmp::min(min, gram.value[j]);
-                        max = cmp::max(max, gram.value[j]);
-                        r.push(gram.value[j]);
-                        s.push(actionrow[j]);
-                        debug!("        shift for token {} {}, pushing r={} s={}", j, gram.name[j], gram.value[j], actionrow[j]);
-                    }
-                }
-                froms[i] = r;
-                tos[i] = s;
-                width[i] = max - min + 1;
-            }
-
-            if reducecount > 0 {
-                let mut r: Vec<i16> = Vec::with_capacity(reducecount);
-                let mut s: Vec<i16> = Vec::with_capacity(reducecount);
-                let mut min = I16_MAX;
-                let mut max = 0;
-                for j in range(0, gram.ntokens) {
-                    if actionrow[gram.ntokens + j] != 0 {
-                        min = cmp::min(min, gram.value[j]);
-                        max = cmp::max(max, gram.value[j]);
-                        r.push(gram.value[j]);
-                        s.push(actionrow[gram.ntokens + j] - 2);
-                        debug!("        reduce for token {} {}, pushing r={} s={}", j, gram.name[j], gram.value[j], actionrow[gram.ntokens + j] - 2);
-                    }
-                }
-                froms[nstates + i] = r;
-                tos[nstates + i] = s;
-                width[nstates + i] = max - min + 1;
-            }
-        }
-        else {
-            debug!("    state={} has no actions", i);
         }
+        let lts: String = lifetimes.iter().map(|l| format!("{}, ", l)).collect();
+        let args: Vec<String> = params.iter().map(|&(name, ref ty)| format!(", {}: {}", name.as_str(), param_field_ty(&**ty))).collect();
+        let inits: Vec<String> = params.iter().map(|&(name, _)| format!("{0}: {0}, ", name.as_str())).collect();
+        let source = format!(
+            "pub fn parse<{lt}L: Lexer<{v}>>(lexer: &mut L{args}) -> FinishParseResult<{v}> {{ \
+                let mut params = ParseParams {{ {inits} }}; \
+                let mut parser = ParserState::new(get_parser_tables()); \
+                parser.parse(&mut params, lexer) \
+            }}",
+            lt = lts, v = value_ty, args = args.concat(), inits = inits.concat());
+        debug!("{}", source);
+        items.push(cx.parse_item(source));
     }
-
-    ActionsTable {
-        nvectors: nvectors,
-        tally: tally,
-        width: width,
-        froms: froms,
-        tos: tos
-    }
+    items
 }
 
-fn default_goto(
-    gram: &Grammar,
-    gotos: &GotoMap,
-    symbol: uint,
-    nstates: uint,
-    state_count: &mut Vec<i16>) -> uint
-{
-    let m = gotos.goto_map[symbol - gram.ntokens] as uint;
-    let n = gotos.goto_map[symbol - gram.ntokens + 1] as uint;
-    if m == n {
-        return 0;
-    }
+// The value of a rule that was generated for an EBNF operator.
+fn builtin_expr(cx: &ExtCtxt, builtin: Builtin) -> P<Expr> {
+    cx.parse_expr(match builtin {
+        Builtin::EmptyList => "Vec::new()",
+        Builtin::SingletonList => "vec![item]",
+        Builtin::Append => "{ let mut list = list; list.push(item); list }",
+        Builtin::NoValue => "None",
+        Builtin::SomeValue => "Some(item)",
+        Builtin::TreeNode => panic!("tree nodes are built from the whole value stack"),
+        Builtin::StartRule => panic!("the value of a start rule is not wrapped"),
+        Builtin::ExprList => "list",
+        Builtin::ExprOperand | Builtin::ExprPrefix | Builtin::ExprBinary => panic!("the items of an expression depend on their rule"),
+        Builtin::ExprClimb(_) => panic!("an expression is built from its operators"),
+    }.to_string())
+}
 
-    for i in range(0, nstates) {
-        state_count[i] = 0;
-    }
+// The value of a rule of the list of an expression (see reader::read_expr): the list, with the
+// operand or operator of the rule pushed onto it.  Operators are pushed as their token values.
+fn expr_list_expr(cx: &ExtCtxt, gram: &Grammar, rule: uint, builtin: Builtin) -> P<Expr> {
+    let rhs = gram.get_rhs_items(rule);
+    let list = if rhs.len() == 1 { "Vec::new()" } else { "list" };
+    cx.parse_expr(match builtin {
+        Builtin::ExprOperand => format!("{{ let mut list = {}; list.push(::racc_runtime::expr::ExprItem::Operand(item)); list }}", list),
+        Builtin::ExprPrefix => format!("{{ let mut list = {}; list.push(::racc_runtime::expr::ExprItem::Prefix({})); list }}",
+            list, gram.value[rhs[rhs.len() - 1] as uint]),
+        Builtin::ExprBinary => format!("{{ let mut list = list; list.push(::racc_runtime::expr::ExprItem::Binary({})); list.extend(item.into_iter()); list }}",
+            gram.value[rhs[1] as uint]),
+        _ => unreachable!()
+    })
+}
 
-    for i in range(m, n) {
-        state_count[gotos.to_state[i] as uint] += 1;
+// Generates YYEXPR<i>, the operators of the expression exprs[i], for racc_runtime::expr::climb.
+fn make_expr_operators(cx: &ExtCtxt, gram: &Grammar, i: uint, expr: &ExprDef) -> P<Item> {
+    let value = |name: &String| gram.value[gram.name.iter().position(|n| n == name).unwrap()];
+    let mut source = format!("static YYEXPR{}: ::racc_runtime::expr::ExprOperators = ::racc_runtime::expr::ExprOperators {{\n    binary: &[\n", i);
+    for &(ref name, prec, assoc) in expr.binary.iter() {
+        let assoc = match assoc {
+            Assoc::Left => "Left",
+            Assoc::Right => "Right",
+            Assoc::NonAssoc => "NonAssoc",
+        };
+        source.push_str(format!("        ::racc_runtime::expr::BinaryOp {{ token: {}, prec: {}, assoc: ::racc_runtime::expr::OpAssoc::{} }},\n",
+            value(name), prec, assoc).as_slice());
     }
-
-    let mut max = 0;
-    let mut default_state = 0;
-    for i in range(0, nstates) {
-        if state_count[i] > max {
-            max = state_count[i];
-            default_state = i;
-        }
+    source.push_str("    ],\n    prefix: &[\n");
+    for &(ref name, prec) in expr.prefix.iter() {
+        source.push_str(format!("        ::racc_runtime::expr::PrefixOp {{ token: {}, prec: {} }},\n", value(name), prec).as_slice());
     }
-
-    debug!("default_goto({}) = {}", symbol, default_state);
-
-    default_state
+    source.push_str("    ],\n};\n");
+    debug!("{}", source);
+    cx.parse_item(source)
 }
 
-fn save_column(
-    gram: &Grammar, 
-    nstates: uint,
-    gotos: &GotoMap,
-    symbol: uint, 
-    default_state: uint,
-    act: &mut ActionsTable)
-{
-    let m = gotos.goto_map[symbol - gram.ntokens] as uint;
-    let n = gotos.goto_map[symbol - gram.ntokens + 1] as uint;
-    debug!("save_column: symbol={} default_state={} m={} n={}", symbol, default_state, m, n);
-
-    let mut count: uint = 0;
-    for i in range(m, n) {
-        if (gotos.to_state[i] as uint) != default_state {
-            debug!("    to_state[{}]={}", i, gotos.to_state[i]);
-            count += 1;
-        }
+// Returns the type of the values of a symbol.
+fn symbol_ty(symbol_value_ty: &P<Ty>, symbol_types: &[Option<(ast::Ident, P<Ty>)>], sym: uint) -> P<Ty> {
+    match symbol_types.get(sym) {
+        Some(&Some((_, ref ty))) => ty.clone(),
+        _ => symbol_value_ty.clone()
     }
-    if count == 0 {
-        debug!("    none");
-        return;
-    }
-
+}
 
-    let mut spf: Vec<i16> = Vec::with_capacity(count);
-    let mut spt: Vec<i16> = Vec::with_capacity(count);
-    for i in range(m, n) {
-        if (gotos.to_state[i] as uint) != default_state {
-            spf.push(gotos.from_state[i]);
-            spt.push(gotos.to_state[i]);
-        }
+// Returns the variant of YYValue which holds the values of a symbol.
+fn symbol_variant(cx: &ExtCtxt, symbol_types: &[Option<(ast::Ident, P<Ty>)>], sym: uint) -> ast::Ident {
+    match symbol_types[sym] {
+        Some((variant, _)) => variant,
+        None => cx.ident_of("Untyped")
     }
-
-    let symno = (gram.value[symbol] as uint) + 2 * nstates;
-    let spf_width = spf[spf.len() - 1] - spf[0] + 1;
-    act.froms[symno] = spf;
-    act.tos[symno] = spt;
-    act.tally[symno] = count as i16;
-    act.width[symno] = spf_width;
-    debug!("    tally[{}]={} width[{}]={}", symno, act.tally[symno], symno, act.width[symno]);
 }
 
-// build the "dgoto" table
-fn goto_actions(gram: &Grammar, nstates: uint, gotos: &GotoMap, act: &mut ActionsTable) -> Vec<i16> {
-    debug!("goto_actions");
-
-    let mut state_count: Vec<i16> = Vec::from_elem(nstates, 0);         // temporary data, used in default_goto()
-    let mut dgoto_table: Vec<i16> = Vec::with_capacity(gram.nvars);    // the table that we are building
-
-    let k = default_goto(gram, gotos, gram.start_symbol + 1, nstates, &mut state_count);
-    dgoto_table.push(k as i16);
-    save_column(gram, nstates, gotos, gram.start_symbol + 1, k, act);
-
-    for i in range(gram.start_symbol + 2, gram.nsyms) {
-        let k = default_goto(gram, gotos, i, nstates, &mut state_count);
-        dgoto_table.push(k as i16);
-        save_column(gram, nstates, gotos, i, k, act);
+// Wraps the value of a rule in the variant of YYValue for its lhs, if the grammar uses %type.
+fn wrap_value(cx: &ExtCtxt, sp: Span, symbol_types: &[Option<(ast::Ident, P<Ty>)>], lhs: uint, value: P<Expr>) -> P<Expr> {
+    if symbol_types.len() == 0 {
+        return value;
     }
-
-    dgoto_table
+    let path = cx.path(sp, vec![cx.ident_of("YYValue"), symbol_variant(cx, symbol_types, lhs)]);
+    cx.expr_call(sp, cx.expr_path(path), vec![value])
 }
 
-fn sort_actions(act: &ActionsTable) -> (uint, Vec<uint>) {
-    debug!("sort_actions() nvectors={}", act.nvectors);
-
-    let mut order: Vec<uint> = Vec::from_elem(act.nvectors, 0);
-    let mut nentries: int = 0;
-
-    for i in range(0, act.nvectors) {
-        debug!("tally[{}]={}", i, act.tally[i]);
-        if act.tally[i] > 0 {
-            let t = act.tally[i];
-            let w = act.width[i];
-            let mut j: int = nentries - 1;
-            debug!("    t={} w={} j={}", t, w, j);
-
-            while j >= 0 && (act.width[order[j as uint]] < w) {
-                j -= 1;
-                debug!("    j-- to {}, because width < w", j);
+// Generates the YYValue enum, for a grammar which uses %type or EBNF operators.  The symbols of
+// one %type share a variant, which is named after the first of them; see reader.rs for the names
+// of the variants of the EBNF non-terminals.
+fn make_value_enum(cx: &ExtCtxt, symbol_value_ty: &P<Ty>, symbol_types: &[Option<(ast::Ident, P<Ty>)>], lifetimes: &[ast::Lifetime]) -> P<Item> {
+    let mut source = format!("#[deriving(Show)]\n#[allow(non_camel_case_types)]\npub enum YYValue{} {{\n", lifetime_params(lifetimes));
+    source.push_str(format!("    Untyped({}),\n", pprust::ty_to_string(&**symbol_value_ty)).as_slice());
+    let mut variants: Vec<ast::Ident> = Vec::new();
+    for t in symbol_types.iter() {
+        if let Some((variant, ref ty)) = *t {
+            if !variants.contains(&variant) {
+                variants.push(variant);
+                source.push_str(format!("    {}({}),\n", token::get_ident(variant), pprust::ty_to_string(&**ty)).as_slice());
             }
-
-            while j >= 0 && (act.width[order[j as uint]] == w) && (act.tally[order[j as uint]] < t) {
-                j -= 1;
-                debug!("    j-- to {}, because tally < t", j);
-            }
-
-            let mut k = nentries - 1;
-            while k > j {
-                debug!("        order[{}] = order[{}] = {} (shifting)", (k + 1) as uint, k as uint, order[k as uint]);
-                order[(k + 1) as uint] = order[k as uint];
-                k -= 1;
-            }
-
-            debug!("        order[{}] = {}", (j + 1) as uint, i);
-            order[(j + 1) as uint] = i;
-            nentries += 1;
         }
     }
+    source.push_str("}\n");
+    debug!("{}", source);
+    cx.parse_item(source)
+}
 
-    debug!("order:");
-    for i in range(0, order.len()) {
-        debug!("    {}", order[i]);
+// Builds the signature of a method of the Actions trait, e.g. "fn add(&mut self, left: V, right: V) -> V".
+fn make_action_method_sig(method: ast::Ident, args: &[(ast::Ident, P<Ty>)], result_ty: &P<Ty>) -> String {
+    let mut sig = format!("fn {}(&mut self", token::get_ident(method));
+    for &(arg, ref ty) in args.iter() {
+        sig.push_str(format!(", {}: {}", token::get_ident(arg), pprust::ty_to_string(&**ty)).as_slice());
     }
-    debug!("nentries={}", nentries);
+    sig.push_str(format!(") -> {}", pprust::ty_to_string(&**result_ty)).as_slice());
+    sig
+}
 
-    (nentries as uint, order)
+// Generates a u32 constant for each token.
+pub fn make_token_consts(cx: &ExtCtxt, sp: Span, tokens: &[(String, u32)]) -> Vec<P<Item>> {
+    tokens.iter().map(|&(ref name, value)| {
+        let tok_ident = cx.ident_of(name.as_slice());
+        let ty_u32 = quote_ty!(cx, u32);
+        cx.item_const(sp, tok_ident, ty_u32, expr_u32(cx, sp, value))
+    }).collect()
 }
 
-// The function matching_vector determines if the vector specified by
-// the input parameter matches a previously considered vector. The
-// test at the start of the function checks if the vector represents
-// a row of shifts over terminal symbols or a row of reductions, or a
-// column of shifts over a nonterminal symbol.  Berkeley Yacc does not
-// check if a column of shifts over a nonterminal symbols matches a
-// previously considered vector.  Because of the nature of LR parsing
-// tables, no two columns can match.  Therefore, the only possible
-// match would be between a row and a column.  Such matches are
-// unlikely.  Therefore, to save time, no attempt is made to see if a
-// column matches a previously considered vector.
-//
-// Matching_vector is poorly designed.  The test could easily be made
-// faster.  Also, it depends on the vectors being in a specific
-// order.
-fn matching_vector(pack: &PackState, vector: uint) -> Option<uint>
-{
-    let i = pack.order[vector];
-    if i >= 2 * pack.nstates {
-        debug!("    matching_vector: vector={} no match", vector);
-        return None;
+// Generates an enum of the tokens, named enum_name, whose discriminants are the values of the
+// tokens, with a variant for each token named as the token is.  It has all() (every token, in
+// order), value(), from_value(), and name(), and it converts to and from the names of the tokens
+// with Show and FromStr.
+pub fn make_token_enum(cx: &ExtCtxt, enum_name: &str, tokens: &[(String, u32)]) -> Vec<P<Item>> {
+    let mut variants = String::new();
+    let mut all = String::new();
+    let mut from_value = String::new();
+    let mut names = String::new();
+    let mut from_name = String::new();
+    for &(ref name, value) in tokens.iter() {
+        variants.push_str(format!("    {} = {},\n", name, value).as_slice());
+        all.push_str(format!("{}::{}, ", enum_name, name).as_slice());
+        from_value.push_str(format!("            {} => Some({}::{}),\n", value, enum_name, name).as_slice());
+        names.push_str(format!("            {}::{} => \"{}\",\n", enum_name, name, name).as_slice());
+        from_name.push_str(format!("            \"{}\" => Some({}::{}),\n", name, enum_name, name).as_slice());
     }
 
-    let t = pack.act.tally[i];
-    let w = pack.act.width[i];
-
-    let act = pack.act;
+    let sources = vec![
+        format!("#[deriving(Copy,Clone,PartialEq,Eq,Hash)]\n\
+                 #[repr(u32)]\n\
+                 #[allow(non_camel_case_types)]\n\
+                 pub enum {} {{\n{}}}", enum_name, variants),
+        format!("impl {0} {{\n\
+                     pub fn all() -> &'static [{0}] {{\n\
+                         static ALL: [{0}, ..{1}] = [{2}];\n\
+                         &ALL\n\
+                     }}\n\
+                     pub fn value(self) -> u32 {{\n\
+                         self as u32\n\
+                     }}\n\
+                     pub fn from_value(value: u32) -> Option<{0}> {{\n\
+                         match value {{\n{3}            _ => None\n        }}\n\
+                     }}\n\
+                     pub fn name(self) -> &'static str {{\n\
+                         match self {{\n{4}        }}\n\
+                     }}\n\
+                 }}", enum_name, tokens.len(), all, from_value, names),
+        format!("impl ::std::str::FromStr for {0} {{\n\
+                     fn from_str(name: &str) -> Option<{0}> {{\n\
+                         match name {{\n{1}            _ => None\n        }}\n\
+                     }}\n\
+                 }}", enum_name, from_name),
+        format!("impl ::std::fmt::Show for {0} {{\n\
+                     fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {{\n\
+                         f.pad(self.name())\n\
+                     }}\n\
+                 }}", enum_name),
+    ];
+    sources.into_iter().map(|source| {
+        debug!("{}", source);
+        cx.parse_item(source)
+    }).collect()
+}
 
-    for prev in reverse_range(vector, 0) {
-        let j = pack.order[prev];
-        if act.width[j] != w || act.tally[j] != t {
-            return None;
+// The values of ScannerTables.accept for states which do not accept a token (see scanner.rs in
+// the runtime).
+const SCAN_NONE: u32 = 0xffff_ffff;
+const SCAN_SKIP: u32 = 0xffff_fffe;
+
+// Generates the tables of the lexer, and a new_scanner() function which scans a string with
+// them.  token_values gives the token value of each pattern, or None for a pattern which is
+// skipped, and begins the mode that each pattern enters, if any.  mode_names names the modes of
+// the lexer, for the LEX_ constants.  If keywords is true, the scanner also looks up the
+// reserved keywords in KEYWORDS (see make_keywords).
+pub fn make_scanner(cx: &ExtCtxt, sp: Span, dfa: &Dfa, token_values: &[Option<u32>], begins: &[Option<uint>], mode_names: &[String], keywords: bool) -> Vec<P<Item>> {
+    let mut offsets: Vec<u32> = vec![0];
+    let mut ranges: Vec<u32> = Vec::new();
+    for transitions in dfa.transitions.iter() {
+        for &(first, last, target) in transitions.iter() {
+            ranges.push(first);
+            ranges.push(last);
+            ranges.push(target as u32);
         }
+        offsets.push((ranges.len() / 3) as u32);
+    }
+    let accept: Vec<u32> = dfa.accept.iter().map(|a| match *a {
+        Some(p) => token_values[p].unwrap_or(SCAN_SKIP),
+        None => SCAN_NONE
+    }).collect();
+    let mode_starts: Vec<u32> = dfa.starts.iter().map(|&s| s as u32).collect();
+    // The table of modes entered is left empty if no pattern enters one.
+    let begin: Vec<u32> = if begins.iter().any(|b| b.is_some()) {
+        dfa.accept.iter().map(|a| match *a {
+            Some(p) => begins[p].map_or(SCAN_NONE, |m| m as u32),
+            None => SCAN_NONE
+        }).collect()
+    }
+    else {
+        Vec::new()
+    };
 
-        let mut is_match = true;
-        for k in range(0, t as uint) {
-            if act.tos[j][k] != act.tos[i][k] || act.froms[j][k] != act.froms[i][k] {
-                is_match = false;
-                break;
-            }
+    let mut items = vec![
+        make_table_u32(cx, sp, "YYSCANOFFSETS", offsets.as_slice()),
+        make_table_u32(cx, sp, "YYSCANRANGES", ranges.as_slice()),
+        make_table_u32(cx, sp, "YYSCANACCEPT", accept.as_slice()),
+        make_table_u32(cx, sp, "YYSCANMODES", mode_starts.as_slice()),
+        make_table_u32(cx, sp, "YYSCANBEGIN", begin.as_slice()),
+        quote_item!(cx,
+            pub static SCANNER_TABLES: ScannerTables = ScannerTables {
+                state_offsets: &YYSCANOFFSETS,
+                ranges: &YYSCANRANGES,
+                accept: &YYSCANACCEPT,
+                mode_starts: &YYSCANMODES,
+                begin: &YYSCANBEGIN,
+            };
+        ).unwrap(),
+        if keywords {
+            quote_item!(cx,
+                pub fn new_scanner<'a>(input: &'a str) -> Scanner<'a> {
+                    Scanner::new(&SCANNER_TABLES, input).with_keywords(&KEYWORDS)
+                }
+            ).unwrap()
         }
-        if is_match {
-            debug!("    matching_vector: vector={} matches at {}", vector, j);
-            return Some(j);
+        else {
+            quote_item!(cx,
+                pub fn new_scanner<'a>(input: &'a str) -> Scanner<'a> {
+                    Scanner::new(&SCANNER_TABLES, input)
+                }
+            ).unwrap()
+        },
+    ];
+    // The modes, by name, for Scanner::set_mode, if the lexer has more than one.
+    if mode_names.len() > 1 {
+        for (m, name) in mode_names.iter().enumerate() {
+            items.push(cx.item_const(sp, cx.ident_of(format!("LEX_{}", name).as_slice()), quote_ty!(cx, uint), cx.expr_uint(sp, m)));
         }
     }
-
-    debug!("    matching_vector: vector={} - no match", vector);
-    return None;
+    items
 }
 
-fn pack_vector(pack: &mut PackState, vector: uint) -> int {
-    // debug!("pack_vector: vector={} lowzero={}", vector, pack.lowzero);
-    let act = pack.act;
-    let i = pack.order[vector];
-    let t = act.tally[i];
-    assert!(t != 0);
-
-    let from = &act.froms[i];
-    let to = &act.tos[i];
-
-    // debug!("from[0]={}", from[0]);
-
-    let mut j: int = (pack.lowzero as int) - (from[0] as int);
-    // debug!("j={}", j);
-    for k in range(1, t as uint) {
-        if (pack.lowzero as int) - (from[k] as int) > j {
-            j = (pack.lowzero as int) - (from[k] as int);
-            // debug!("j={}", j);
-        }
+// Generates KEYWORDS, the KeywordTable of a grammar which declares %keywords: ident is the value
+// of the token that keywords are scanned as, and words gives (text, token value, contextual) for
+// each keyword.  The runtime searches the table by bisection, so it is sorted by text.
+pub fn make_keywords(cx: &ExtCtxt, ident: u32, mut words: Vec<(String, u32, bool)>) -> P<Item> {
+    words.sort_by(|&(ref a, _, _), &(ref b, _, _)| a.cmp(b));
+    let mut source = format!("pub static KEYWORDS: KeywordTable = KeywordTable {{ ident: {}, keywords: &[\n", ident);
+    for &(ref text, token, contextual) in words.iter() {
+        source.push_str(format!("    Keyword {{ text: \"{}\", token: {}, contextual: {} }},\n", text.escape_default(), token, contextual).as_slice());
     }
+    source.push_str("] };");
+    debug!("{}", source);
+    cx.parse_item(source)
+}
 
-    loop {
-        // debug!("    loop: j={}", j);
-        if j == 0 {
-            j = 1;
-            continue;
-        }
-
-        let mut ok = true;
-        for k in range(0, t as uint) {
-            let loc = (j + (from[k] as int)) as uint;
-
-            // make sure we can read/write table[loc] and table[check]
-            if loc > pack.table.len() {
-                assert!(pack.table.len() == pack.check.len());
-                let grow = loc + 1 - pack.table.len();
-                debug!("        growing table/check by {}", grow);
-                pack.table.grow(grow, 0);
-                pack.check.grow(grow, -1);
-            }
+// Generates EXTERNAL_TOKENS, the TokenMap of a grammar which uses %external: the external number
+// and the token value of each token, sorted by external number.
+pub fn make_token_map(cx: &ExtCtxt, sp: Span, pairs: &[(u32, u32)]) -> Vec<P<Item>> {
+    let external: Vec<u32> = pairs.iter().map(|&(n, _)| n).collect();
+    let internal: Vec<u32> = pairs.iter().map(|&(_, t)| t).collect();
+    vec![
+        make_table_u32(cx, sp, "YYEXTERNAL", external.as_slice()),
+        make_table_u32(cx, sp, "YYINTERNAL", internal.as_slice()),
+        quote_item!(cx,
+            pub static EXTERNAL_TOKENS: TokenMap = TokenMap {
+                external: &YYEXTERNAL,
+                internal: &YYINTERNAL,
+            };
+        ).unwrap(),
+    ]
+}
 
-            if pack.check[loc] != -1 {
-                ok = false;
-                break;
-            }
+// Applies the visibility and the derives of `codegen` to the generated items.  Items which are
+// already private (the tables, and reduce) stay private.  Each enum and struct derives the
+// traits of api.derive that it does not derive already.
+pub fn apply_codegen_options(cx: &ExtCtxt, sp: Span, items: Vec<P<Item>>, codegen: &CodegenOptions) -> Vec<P<Item>> {
+    items.into_iter().map(|item| item.map(|mut item| {
+        if !codegen.public {
+            item.vis = ast::Inherited;
         }
-        if !ok {
-            j += 1;
-            continue;
-        }
-        for k in range(0, vector) {
-            if pack.pos[k] as int == j {
-                ok = false;
-                break;
+        let is_type = match item.node {
+            ast::ItemEnum(..) | ast::ItemStruct(..) => true,
+            _ => false
+        };
+        if is_type && codegen.derives.len() != 0 {
+            let mut derived: Vec<String> = Vec::new();
+            for a in item.attrs.iter() {
+                if a.check_name("deriving") {
+                    if let Some(list) = a.meta_item_list() {
+                        derived.extend(list.iter().map(|m| m.name().get().to_string()));
+                    }
+                }
             }
-        }
-        if !ok {
-            j += 1;
-            continue;
-        }
-
-        for k in range(0, t as uint) {
-            let loc = (j + (from[k] as int)) as uint;
-            pack.table[loc] = to[k];
-            pack.check[loc] = from[k];
-            if loc > pack.high {
-                pack.high = loc;
+            let words: Vec<P<ast::MetaItem>> = codegen.derives.iter()
+                .filter(|d| !derived.contains(*d))
+                .map(|d| cx.meta_word(sp, intern_and_get_ident(d.as_slice())))
+                .collect();
+            if words.len() != 0 {
+                item.attrs.push(cx.attribute(sp, cx.meta_list(sp, InternedString::new("deriving"), words)));
             }
         }
+        item
+    })).collect()
+}
 
-        while pack.check[pack.lowzero] != -1 {
-            pack.lowzero += 1;
+fn make_table_u32(cx: &ExtCtxt, span: Span, name: &str, values: &[u32]) -> P<Item> {
+    let values_expr = cx.expr_vec(span, Vec::from_fn(values.len(), |i| expr_u32(cx, span, values[i])));
+    let ty_u32 = quote_ty!(cx, u32);
+    let table_ident = cx.ident_of(name);
+    let table_ty = cx.ty(span, Ty_::TyFixedLengthVec(ty_u32, cx.expr_uint(span, values.len())));
+    cx.item_static(span, table_ident, table_ty, Mutability::MutImmutable, values_expr)
+}
+
+fn make_table_string(cx: &ExtCtxt, span: Span, name: &str, strings: &Vec<String>) -> P<Item> {
+    cx.item_static(span, 
+        cx.ident_of(name), 
+        cx.ty(span, Ty_::TyFixedLengthVec(quote_ty!(cx, &'static str), cx.expr_uint(span, strings.len()))),
+        Mutability::MutImmutable,
+        cx.expr_vec(span, Vec::from_fn(strings.len(), |i| {
+            let iname = intern_and_get_ident(strings[i].as_slice());
+            cx.expr_str(span, iname)
         }
+        )))
+}
 
-        return j;
-    }
+#[allow(dead_code)]
+fn make_table_uint(cx: &ExtCtxt, span: Span, name: &str, values: &[uint]) -> P<Item> {
+    let values_expr = cx.expr_vec(span, Vec::from_fn(values.len(), |i| cx.expr_uint(span, values[i])));
+    let ty_uint = quote_ty!(cx, uint);
+    let table_ident = cx.ident_of(name);
+    let table_ty = cx.ty(span, Ty_::TyFixedLengthVec(ty_uint, cx.expr_uint(span, values.len())));
+    let table_item = cx.item_static(span, table_ident, table_ty, Mutability::MutImmutable, values_expr);
+    // debug!("built table item for '{}': values {}", name, values);
+    // debug!("item: {}", pprust::item_to_string(&*table_item));
+    table_item
 }
 
-struct PackState<'a> {
-    base: Vec<i16>,
-    pos: Vec<i16>, 
-    table: Vec<i16>,        // table and check always have same len
-    check: Vec<i16>,        // table is 0-filled, check is -1-filled
-    lowzero: uint,
-    high: uint,
+// The state and table indices are i32, and are emitted as u32, as the i16 tables are emitted as
+// u16; the runtime casts them back.
+fn make_table_i32(cx: &ExtCtxt, span: Span, name: &str, values: &[i32]) -> P<Item> {
+    let values: Vec<u32> = values.iter().map(|&v| v as u32).collect();
+    make_table_u32(cx, span, name, values.as_slice())
+}
 
-    // read-only references to stuff
-    order: &'a [uint],
-    nstates: uint,
-    act: &'a ActionsTable
+fn make_table_i16(cx: &ExtCtxt, span: Span, name: &str, values: &[i16]) -> P<Item> {
+    make_table_i16_as_u16(cx, span, name, values)
 }
 
-fn pack_table<'a>(nstates: uint, nentries: uint, order: &'a [uint], act: &'a ActionsTable) -> PackState<'a> {
-    debug!("pack_table: nentries={}", nentries);
+fn make_table_i16_real(cx: &ExtCtxt, span: Span, name: &str, values: &[i16]) -> P<Item> {
+    let values_expr = cx.expr_vec(span, Vec::from_fn(values.len(), |i| expr_i16(cx, span, values[i])));
+    let ty_i16 = quote_ty!(cx, i16);
+    let table_ident = cx.ident_of(name);
+    let table_ty = cx.ty(span, Ty_::TyFixedLengthVec(ty_i16, cx.expr_uint(span, values.len())));
+    let table_item = cx.item_static(span, table_ident, table_ty, Mutability::MutImmutable, values_expr);
+    // debug!("built table item for '{}': values {}", name, values);
+    // debug!("item: {}", pprust::item_to_string(&*table_item));
+    table_item
+}
 
-    let initial_maxtable = 1000;
+fn expr_i16(cx: &ExtCtxt, span: Span, i: i16) -> P<ast::Expr> {
+    cx.expr_lit(span, ast::LitInt(
+        i as u64,
+        ast::LitIntType::SignedIntLit(ast::TyI16, ast::Sign::new(i as int))))
+}
 
-    let mut pack = PackState {
-        base: Vec::from_elem(act.nvectors, 0),
-        pos: Vec::from_elem(nentries, 0),
-        table: Vec::from_elem(initial_maxtable, 0),
-        check: Vec::from_elem(initial_maxtable, -1),
-        lowzero: 0,
-        high: 0,
-        order: order,
-        nstates: nstates,
-        act: act
-    };
+fn expr_u16(cx: &ExtCtxt, span: Span, u: u16) -> P<ast::Expr> {
+    cx.expr_lit(span, ast::LitInt(
+        u as u64,
+        UnsignedIntLit(TyU16)))
+}
 
-    for i in range(0, nentries) {
-        // debug!("i={}", i);
-        let place: int = match matching_vector(&mut pack, i) {
-            Some(state) => pack.base[state] as int,
-            None => pack_vector(&mut pack, i)
-        };
+// yuck
+fn make_table_i16_as_u16(cx: &ExtCtxt, span: Span, name: &str, values: &[i16]) -> P<Item> {
+    let values_expr = cx.expr_vec(span, Vec::from_fn(values.len(), |i| expr_u16(cx, span, values[i] as u16)));
+    let ty_u16 = quote_ty!(cx, u16);
+    let table_ident = cx.ident_of(name);
+    let table_ty = cx.ty(span, Ty_::TyFixedLengthVec(ty_u16, cx.expr_uint(span, values.len())));
+    let table_item = cx.item_static(span, table_ident, table_ty, Mutability::MutImmutable, values_expr);
+    table_item
+}
 
-        // debug!("    place={}", place);
-        pack.pos[i] = place as i16;
-        pack.base[order[i]] = place as i16;
-    }
 
-    pack
-}
 
 fn expr_u32(cx: &ExtCtxt, span: Span, u: u32) -> P<Expr> {
     cx.expr_lit(span, ast::LitInt(u as u64, ast::UnsignedIntLit(ast::TyU32)))
@@ -3,45 +3,126 @@
 
 // our grammar for a grammar:
 //
-// <ident> : <ident> ... | <ident> ... ;    // rule def
+// <ident> : <ident> ... | <ident> ... ;    // rule def; each rhs <ident> may be followed by
+//                                          // "= <name>" or "(<name>)", which binds its value
 // <ident> [ = <literal> ];                 // token def, must precede all rule defs
+// use <ident> ;                            // use a shared token set (see token_sets.rs), instead of token defs
+// % define <key> <value> ;                 // grammar option, e.g. "%define lr.type canonical-lr;"
+// % left <ident> ... ;                     // precedence level (also %right, %nonassoc)
+// % expect <n> ;                           // expected number of shift/reduce conflicts (also %expect-rr)
+// % start <ident> ... ;                    // the start symbol, or several, each an entry point
+// % prefer <ident> over <ident> ;          // conflict policy: reduce by rules of the first (or "shift")
+// <ident> : <ident> ... % prec <ident> ;   // rule with the precedence of a given token
+// <ident> : <ident> ... % resolve shift <ident> ;  // rule whose conflicts on a token are settled (also "reduce")
+// <ident> : <ident> "+" <ident> ;          // in a rule, a string names the token declared with it
+// % type < <type> > <ident> ... ;          // the type of the values of some symbols
+//
+// In the rhs of a rule, a symbol may be followed by an EBNF operator, before its binding:
+//
+// <ident> ?                                // optional, of type Option<T>
+// <ident> *                                // zero or more, of type Vec<T>
+// <ident> +                                // one or more, of type Vec<T>
+// <ident> % <ident>                        // one or more, separated by a token; of type Vec<T>
+//
+// % inline <ident> ... ;                   // non-terminals whose rules are substituted into their uses
+// % token <ident> <string> ;               // token def, whose text is matched by the generated lexer
+// % skip <string> ;                        // text that the generated lexer skips, e.g. white space
+// % lexer-state <ident> ... ;              // states of the generated lexer, besides INITIAL
+// % token < <ident> , ... > <ident> <string> [% begin <ident>] ;  // pattern active in some states (also %skip)
+// <ident> : <ident> ... % begin <ident> ;  // rule whose reduction puts the lexer in a state
+// % keywords [contextual] <ident> : <ident> <string> , ... ;   // keywords, scanned as the first token
+// % trivia [newline] <ident> ... ;         // tokens which a tree keeps beside the others, e.g. comments
+// % external <ident> = <n> , ... ;         // the numbers of tokens in an external lexer, see read_external
+// % parse-param <ident> : <type> ;         // a parameter of parse(), bound in every action
+// % include <ident> ;                      // the items of a grammar_fragment! (or "%include <string>;", of a file)
+// % export <ident> , ... ;                 // in an included piece, the non-terminals that it exports
+// % expr <ident> : <ident> => <ident> [, <ident>] { % left <ident> ... ; ... }   // expression, see read_expr
+// # [ cfg ( <condition> ) ]                // before an item or an alternative, which is skipped if false
+// <ident> < <ident> , ... > : ... ;         // parameterized rule, e.g. "List<T> : | List<T> T ;"
+//
+// A parameterized rule is instantiated for each list of arguments that it is used with, e.g.
+// "List<Expr>", after the rest of the grammar has been read.  Each instance is a non-terminal,
+// whose rules are the rules of the parameterized rule, with the arguments in place of the
+// parameters.
 
 //
 // This code reads a sequence of input tokens, and builds a simple yacc grammar.
-// It builds a symbol table (names), which name both tokens and variables of the
-// defined grammar.  It also builds the rules of the grammar.  Rules are represented
-// as a table of left-hand side symbols (the plhs vector) and a table of right-hand
-// side symbols (the ritem vector).  The rhs symbols for all rules are packed into
-// a single array, the ritem vector.  Each sequence of rhs symbols (for a single rule)
-// are stored sequentially, and each rule is terminated by a NO_ITEM symbol.
+// The grammar itself is constructed by a GrammarBuilder (see builder.rs); this module
+// parses the tokens, keeps track of where each name was defined (so that errors can
+// be reported at the right span), and collects the actions and rhs bindings
+// for each rule.
+//
+// Rules are numbered in the order in which they are read.  The rule_actions and rhs_binding
+// tables are indexed by that rule number, so the entries for the three predefined rules are
+// always empty.  The rules are kept in the reader until the whole grammar has been read, so that
+// the rules of %inline non-terminals can be substituted into the rules which use them, and then
+// they are handed to the builder, in order.
+//
+// The action of a rule is either a block of code, or "=> name", which names a method of
+// the Actions trait that is generated for the grammar (see output.rs).
+//
+// A rule is added only when it ends (at | or ;).  This allows us to handle actions which
+// appear in the middle of a rule: the action becomes the action of a new, anonymous, empty
+// rule, and the symbol for that rule is added to the rhs of the rule that we are reading.
+// Because the anonymous rule is added first, it gets the lower rule number, just as in
+// Berkeley YACC.
+//
+// "%type <T> A B ...;" gives the symbols A, B, ... values of type T, rather than the value type
+// that is given at the start of the grammar.  The symbols of each %type share a variant of the
+// generated value enum, named after the first of them (see output.rs).
 //
-// As the grammar is parsed, we build a name table.  The name table associates an
-// integer with each name, which is an index into the ReaderState.symbols table.
-// Other tables, such as plhs and ritem, contain indices into the symbols table.
+// The EBNF operators are desugared into generated non-terminals, such as "Expr*", with rules
+// whose actions are built in (Action::Builtin).  The generated non-terminals are given types
+// (Vec<T> or Option<T>, where T is the type of the operand), so a grammar which uses them is
+// typed, as if it used %type.  Each generated non-terminal is created once, however many times
+// it is used.
 //
-// Because indices are added in the order that they are found in the grammar,
-// tokens and variables are mixed together in this table.  After the grammar is
-// parsed, it is "packed".  This produces a new table, which lists all tokens,
-// then all variables.  The plhs and ritem tables are read, and are used to
-// produce several new tables.  
+// "%token NAME \"text\";" and "%token NAME r\"regex\";" define a token (or give a pattern to a
+// token that has already been defined) and give the generated lexer a pattern for it: a cooked
+// string is matched literally, and a raw string is a regular expression.  The patterns are
+// compiled into a DFA by racc_core::lexgen, after the grammar has been read (see lib.rs).
+// The text of a literal token is also an alias for it: rules can say "+" rather than PLUS, and
+// reports and error messages print "+".
+//
+// "%lexer-state STRING;" declares a state of the generated lexer, besides INITIAL.  A pattern is
+// active in INITIAL, unless it lists its states, as in "%token <STRING> CHARS r\"[^\"]+\";", and
+// "%begin STRING" after a pattern puts the lexer in that state when the pattern matches.  A rule
+// may end with "%begin STATE" too, which puts the lexer in STATE when the parser reduces by it;
+// lib.rs checks that the parser does so before it reads the next token.  The states of each
+// pattern are kept in lex_modes, beside lex_rules.  A literal that several tokens are declared
+// with, in different states, is an alias only of the first of them.
 
-use std::rc::Rc;
 use std::collections::HashMap;
+use std::io::File;
+use std::mem;
 
 use syntax::ast;
 use syntax::ast::Block;
 use syntax::ptr::P;
-use syntax::parse::token::{Token,Ident,BinOp,BinOpToken};
+use syntax::parse::token;
+use syntax::parse::token::{Token,BinOpToken};
+use syntax::parse::token::keywords;
 use syntax::parse::parser::Parser;
+use syntax::parse;
+use syntax::attr;
+use syntax::attr::AttrMetaMethods;
 use syntax::codemap;
 use syntax::codemap::Span;
-
-
-use grammar::{TOKEN,UNDEFINED};
-use grammar::Grammar;
-
-const NO_SYMBOL: uint = !0u;
-const NO_ITEM: uint = !0u;
+use syntax::ext::base::ExtCtxt;
+use syntax::ext::build::AstBuilder;
+use syntax::ext::quote::rt::ExtParseUtils;
+use syntax::print::pprust;
+
+use racc_core::{LrType, Assoc, Resolution};
+use racc_core::ids::{SymbolId, RuleId};
+use racc_core::builder::{GrammarBuilder,PREDEFINED_RULES};
+use racc_core::grammar::Grammar;
+use racc_core::lexgen::Pattern;
+
+use token_sets;
+use output;
+use output::CodegenOptions;
+use fragments;
 
 // symbol classes
 #[deriving(Copy,PartialEq,Show)]
@@ -51,14 +132,62 @@ enum SymClass {
     NonTerminal = 2
 }
 
+// The action of a rule.
+#[deriving(Clone)]
+pub enum Action {
+    // A block of code, which is inlined into the generated reduce() function.
+    Block(P<Block>),
+    // A block of code which evaluates to a Result, given by "{ ... }?".  An error ends the parse.
+    Fallible(P<Block>),
+    // A method of the generated Actions trait, given by "=> name".
+    Method(Span, ast::Ident),
+    // An action of a rule that was generated for an EBNF operator.
+    Builtin(Builtin),
+    // The action of a rule into which the rules of %inline non-terminals have been substituted:
+    // the actions of the substituted rules, which run first, in order, and then the action of
+    // the rule itself, if it has one.
+    Inlined(Vec<InlinedAction>, Option<Box<Action>>),
+}
+
+// The action of a rule of an %inline non-terminal, which has been substituted into a rule.
+#[deriving(Clone)]
+pub struct InlinedAction {
+    pub binding: Option<ast::Ident>,    // the name that the rule gave to the non-terminal's value
+    pub ty: Option<P<ast::Ty>>,         // the type of the value, if it was given by %type
+    pub action: Option<Action>,
+}
+
+// The actions of the rules generated for EBNF operators.  The values that they use are bound
+// to "list" and "item".
+#[deriving(Copy,Clone)]
+pub enum Builtin {
+    EmptyList,              // Vec::new()
+    SingletonList,          // vec![item]
+    Append,                 // list, with item pushed onto it
+    NoValue,                // None
+    SomeValue,              // Some(item)
+    TreeNode,               // a racc_runtime::Node, whose children are the values of the rhs
+    StartRule,              // the value of the start symbol, after its start token (see add_start_rules)
+    ExprList,               // list, the operands and operators of an expression (see read_expr)
+    ExprOperand,            // list (or an empty list), with item pushed onto it as an operand
+    ExprPrefix,             // list (or an empty list), with the last token of the rule pushed onto it
+    ExprBinary,             // list, with the middle token of the rule and then the items of item
+    ExprClimb(uint),        // the value of list, arranged by the operators of reader.exprs[i]
+}
+
+// An EBNF operator.
+#[deriving(Copy,PartialEq)]
+enum Ebnf {
+    Optional,               // X?
+    Many,                   // X*
+    Many1,                  // X+
+    Separated(uint),        // X % SEP; the operand is the separator symbol
+}
+
 struct Bucket
 {
     name: String,
-    tag: Option<Rc<String>>,
-    value: i16,
-    prec: i16,
     class: SymClass,
-    assoc: u8,
     span: Span,     // code span which defined this name
 }
 
@@ -66,19 +195,51 @@ fn make_bucket(name: &str, span: Span) -> Bucket
 {
     Bucket {
         name: name.to_string(),
-        tag: None,
-        value: UNDEFINED,
-        prec: 0,
         class: SymClass::Unknown,
-        assoc: TOKEN,
         span: span
     }
 }
 
+// A rule, which has been read but not yet handed to the builder.
+struct RuleDef
+{
+    lhs: uint,
+    rhs: Vec<uint>,
+    prec: Option<uint>,     // the symbol given by "%prec", if any
+    resolve: Vec<(uint, Resolution)>,   // the tokens given by "%resolve"
+    begin: Option<uint>,    // the lexer state given by "%begin", if any
+    span: Span,             // where the rule begins
+}
+
+// An expression declared by %expr (see read_expr).
+pub struct ExprDef
+{
+    pub lhs: String,
+    // The binary operators: (token, precedence, associativity).  Precedence levels are numbered
+    // from 1, loosest first, and are shared with the prefix operators.
+    pub binary: Vec<(String, uint, Assoc)>,
+    // The prefix operators: (token, precedence).
+    pub prefix: Vec<(String, uint)>,
+    // The methods of the Actions trait which combine operands: one for the binary operators, and
+    // one for the prefix operators, if there are any.
+    pub binary_method: (Span, ast::Ident),
+    pub prefix_method: Option<(Span, ast::Ident)>,
+    lhs_sym: uint,
+    operand: (uint, Span),
+    lists: Vec<uint>,       // the non-terminals generated for the list, which have its type
+    span: Span,
+}
+
+// A parameterized rule, such as "List<T> : | List<T> T ;".
+struct Template
+{
+    params: Vec<String>,
+    body: Vec<ast::TokenTree>,      // the tokens after the colon, up to and including the semicolon
+}
+
 struct ReaderState
 {
-    pitem: Vec<uint>,       // contains indices that point into symbols
-    plhs: Vec<uint>,        // contains indices that point into symbols
+    builder: GrammarBuilder,
 
     // All of the symbols, in the order that they are first encountered.
     symbols: Vec<Bucket>,
@@ -90,30 +251,129 @@ struct ReaderState
 
     last_was_action: bool,
 
-    gram: Grammar,         // the grammar we are building
+    // The rule that is currently being read.
+    lhs: uint,
+    rule_span: Span,
+    rhs: Vec<uint>,
+    rhs_idents: Vec<Option<ast::Ident>>,
+    action: Option<Action>,
+    prec: Option<uint>,     // the symbol given by "%prec", if any
+    resolve: Vec<(uint, Resolution)>,   // the tokens given by "%resolve"
+    begin: Option<uint>,    // the lexer state given by "%begin", if any
+
+    // The type of each symbol which has been given one by %type, and the name of the variant
+    // of the value enum for it.  Indexed by symbol.
+    types: Vec<Option<(ast::Ident, P<ast::Ty>)>>,
+
+    // The non-terminals declared by %inline.
+    inline: Vec<uint>,
+
+    // The parameterized rules, by name.
+    templates: HashMap<String, Template>,
 
-    // The actions (code blocks) provided by the grammar author.
-    rule_blocks: Vec<Option<P<Block>>>,
+    // The instances of parameterized rules that are used: (symbol, rule name, arguments, span
+    // of the first use).  They are read after the rest of the grammar.
+    instances: Vec<(uint, String, Vec<String>, Span)>,
 
-    // identifier used by grammar for a RHS value, given by =foo
-    // indices are same as rrhs
-    rhs_binding: Vec<Option<ast::Ident>>,      
+    // The non-terminals generated for EBNF operators: (symbol, operator, operand).  Their types
+    // are assigned after all of the %type directives have been read.
+    ebnf: Vec<(uint, Ebnf, uint)>,
+
+    // The rules that have been read, in order, not including the predefined rules.
+    rules: Vec<RuleDef>,
+
+    // The patterns of the generated lexer, given by %token and %skip: (token, pattern, span).
+    // The token is None for text that the lexer skips.
+    lex_rules: Vec<(Option<uint>, Pattern, Span)>,
+
+    // The states of the generated lexer, by name, and where each was declared.  State 0 is
+    // INITIAL, which is always declared.
+    lexer_states: Vec<(String, Span)>,
+
+    // The lexer states of each pattern: (the states in which it is active, the state that
+    // matching it enters).  Parallel to lex_rules.
+    lex_modes: Vec<(Vec<uint>, Option<uint>)>,
+
+    // The tokens which were declared with a literal string, by that string.  The string can be
+    // used in rules in place of the name of the token.
+    aliases: HashMap<String, uint>,
+
+    // The start symbols given by %start, and where they were given.
+    starts: Vec<(uint, Span)>,
+
+    // The preferences given by %prefer: (winner, loser, span), where None is "shift".
+    preferences: Vec<(Option<uint>, Option<uint>, Span)>,
+
+    // The tokens which were given values, as in "NUM = 300;", by value.
+    token_values: HashMap<i16, uint>,
+
+    // The keywords given by %keywords: (token, text, contextual), and the token which they are
+    // scanned as.
+    keywords: Vec<(uint, String, bool)>,
+    keyword_ident: Option<uint>,
+
+    // The tokens declared by %trivia: (token, ends a line, span).
+    trivia: Vec<(uint, bool, Span)>,
+
+    // The numbers of tokens in an external lexer, given by %external: (number, token).
+    externals: Vec<(u32, uint)>,
+
+    // The parameters given by %parse-param: (name, type).
+    parse_params: Vec<(ast::Ident, P<ast::Ty>)>,
+
+    // The goal symbol that was generated for several start symbols, if there are several.
+    start_goal: Option<uint>,
+
+    // The expressions declared by %expr.
+    exprs: Vec<ExprDef>,
+
+    // The actions provided by the grammar author, indexed by rule.
+    rule_actions: Vec<Option<Action>>,
+
+    // identifiers used by grammar for RHS values, given by =foo.
+    // indexed by rule, then by position within the rhs of the rule.
+    rhs_binding: Vec<Vec<Option<ast::Ident>>>,
 }
 
 impl ReaderState
 {
     pub fn new() -> ReaderState {
-        let gram = Grammar::new();
         ReaderState {
-            pitem: Vec::from_elem(gram.nitems, NO_ITEM),
-            plhs: Vec::from_elem(gram.nrules, NO_ITEM),
-            rule_blocks: Vec::from_elem(gram.nrules, None),
-            rhs_binding: Vec::from_elem(gram.nitems, None),
+            builder: GrammarBuilder::new(),
+            rules: Vec::new(),
+            rule_actions: Vec::from_fn(PREDEFINED_RULES, |_| None),
+            rhs_binding: Vec::from_fn(PREDEFINED_RULES, |_| Vec::new()),
             symbols: Vec::new(),
             symbol_table: HashMap::new(),
             gensym: 1,
             last_was_action: false,
-            gram: gram,
+            lhs: 0,
+            rule_span: codemap::DUMMY_SP,
+            rhs: Vec::new(),
+            rhs_idents: Vec::new(),
+            action: None,
+            prec: None,
+            resolve: Vec::new(),
+            begin: None,
+            types: Vec::new(),
+            ebnf: Vec::new(),
+            inline: Vec::new(),
+            templates: HashMap::new(),
+            instances: Vec::new(),
+            lex_rules: Vec::new(),
+            lexer_states: vec![("INITIAL".to_string(), codemap::DUMMY_SP)],
+            lex_modes: Vec::new(),
+            aliases: HashMap::new(),
+            starts: Vec::new(),
+            preferences: Vec::new(),
+            token_values: HashMap::new(),
+            keywords: Vec::new(),
+            keyword_ident: None,
+            trivia: Vec::new(),
+            externals: Vec::new(),
+            parse_params: Vec::new(),
+            start_goal: None,
+            exprs: Vec::new(),
         }
     }
 
@@ -123,7 +383,6 @@ impl ReaderState
     pub fn lookup(&mut self, name: &str, span: Span) -> uint
     {
         if let Some(ii) = self.symbol_table.get(name) {
-            // debug!("found {}_{} already in table", name, *ii);
             return *ii;
         }
 
@@ -131,7 +390,6 @@ impl ReaderState
         let bp = make_bucket(name, span);
         self.symbols.push(bp);
         self.symbol_table.insert(name.to_string(), index);
-        // debug!("added {}_{} to table", name, index);
         return index;
     }
 
@@ -141,417 +399,168 @@ impl ReaderState
         (index, &mut self.symbols[index])
     }
 
-    pub fn start_rule(&mut self, lhs: uint, span: Span)
+    pub fn define_token(&mut self, token: uint)
     {
-        assert!(self.symbols[lhs].class == SymClass::NonTerminal);
-        assert!(self.plhs.len() == self.gram.nrules);
-        assert!(self.gram.rprec.len() == self.gram.nrules);
-        assert!(self.gram.rassoc.len() == self.gram.nrules);
+        assert!(self.symbols[token].class == SymClass::Terminal);
+        self.builder.token(self.symbols[token].name.as_slice());
+    }
 
-        // debug!("  start_rule: r{}: {}_{} -> (at item {}) ...", self.gram.nrules, self.symbols[lhs].name, lhs, self.pitem.len());
+    pub fn start_rule(&mut self, lhs: uint, span: Span)
+    {
+        assert!(self.rhs.len() == 0);
+        assert!(self.action.is_none());
 
-        self.plhs.push(lhs);
-        self.gram.rprec.push(UNDEFINED);
-        self.gram.rassoc.push(TOKEN);
+        self.lhs = lhs;
+        self.rule_span = span;
+        self.last_was_action = false;
+    }
 
-        // nrules is not yet advanced; that happens in end_rule
-        drop(span); // will use later
+    // Adds a rule, with its action and the bindings of its rhs.
+    pub fn add_rule(&mut self, rule: RuleDef, action: Option<Action>, binding: Vec<Option<ast::Ident>>)
+    {
+        self.rules.push(rule);
+        self.rule_actions.push(action);
+        self.rhs_binding.push(binding);
+        assert!(self.rule_actions.len() == PREDEFINED_RULES + self.rules.len());
+        assert!(self.rhs_binding.len() == self.rule_actions.len());
     }
-    
+
     pub fn end_rule(&mut self)
     {
-        let rule = self.gram.nrules;
+        let rule = RuleDef { lhs: self.lhs, rhs: self.rhs.clone(), prec: self.prec.take(), resolve: mem::replace(&mut self.resolve, Vec::new()), begin: self.begin.take(), span: self.rule_span };
+        let action = self.action.take();
+        let binding = self.rhs_idents.clone();
+        self.add_rule(rule, action, binding);
 
-        assert!(self.plhs.len() == rule + 1);
-        assert!(self.gram.rprec.len() == rule + 1);
-        assert!(self.gram.rassoc.len() == rule + 1);
-
-        if !self.last_was_action && { if let Some(_) = self.symbols[self.plhs[self.gram.nrules]].tag { true } else { false } } {
-            if self.pitem[self.pitem.len() - 1] != NO_ITEM {
-                let mut i: uint = self.pitem.len() - 1;
-                while (i > 0) && self.pitem[i] != NO_ITEM {
-                    i -= 1;
-                }
-                if self.pitem[i + 1] == NO_ITEM || self.symbols[self.pitem[i + 1]].tag != self.symbols[self.plhs[self.gram.nrules]].tag {
-                    warn!("default_action_warning();")
-                    // default_action_warning();
-                }
-            }
-        }
-
-        assert!(self.pitem.len() == self.gram.nitems);
-        assert!(self.rule_blocks.len() == self.gram.nrules + (if self.last_was_action { 1 } else { 0 }));
-
-        // Insert a None entry into rule_blocks, if the current rule did not declare an action.
-        if !self.last_was_action {
-            self.rule_blocks.push(None);
-        }
+        self.rhs.clear();
+        self.rhs_idents.clear();
         self.last_was_action = false;
-
-        self.pitem.push(NO_ITEM);
-        self.rhs_binding.push(None);
-
-        self.gram.nitems += 1;
-        self.gram.nrules += 1;
-
-        assert!(self.rule_blocks.len() == self.gram.nrules);
-
-        // debug!("  end_rule: r{}, lhs={} nitems={}", rule, self.plhs[rule], self.pitem.len());
     }
 
+    // The action that we have just read was not at the end of the rule.  Move it
+    // to a new, empty rule, whose lhs is an anonymous non-terminal, and add that
+    // non-terminal to the rhs of the current rule.
     pub fn insert_empty_rule(&mut self, span: Span)
     {
-        let nrules = self.gram.nrules;
-
         self.gensym += 1;
         let symname = format!("$${}", self.gensym);
 
         debug!("insert_empty_rule: added symbol {}", symname);
 
-        let tag = self.symbols[self.plhs[nrules]].tag.clone();
         let bp = {
             let (bp_index, sym) = self.lookup_ref_mut(symname.as_slice(), span);
-            sym.tag = tag;
             sym.class = SymClass::NonTerminal;
             bp_index
         };
 
-        assert!(self.pitem.len() == self.gram.nitems);
-        self.gram.nitems += 2;
-        self.pitem.push(NO_ITEM);
-        self.pitem.push(NO_ITEM);
-
-        let mut bpp = self.gram.nitems - 1;
-        self.pitem[bpp] = bp;
-        bpp -= 1;
-        loop {
-            let b = self.pitem[bpp - 1];
-            self.pitem[bpp] = b;
-            if b == NO_ITEM {
-                break;
-            }
-            bpp -= 1;
-        }
+        let action = self.action.take();
+        self.add_rule(RuleDef { lhs: bp, rhs: Vec::new(), prec: None, resolve: Vec::new(), begin: None, span: span }, action, Vec::new());
 
-        // Insert the generated rule right before the current rule, which was
-        // written to self.{plhs,rprec,rassoc}[nrules].
-        self.plhs.insert(nrules, bp);        
-        self.gram.rprec.insert(nrules, 0);        
-        self.gram.rassoc.insert(nrules, TOKEN);
+        self.rhs.push(bp);
+        self.rhs_idents.push(None);
+        self.last_was_action = false;
+    }
 
-        self.gram.nrules += 1;
+    pub fn add_action(&mut self, span: Span, action: Action)
+    {
+        if self.last_was_action {
+            self.insert_empty_rule(span);
+        }
+        self.action = Some(action);
+        self.last_was_action = true;
     }
 
     pub fn add_symbol(&mut self, bp: uint, span: Span, ident: Option<ast::Ident>)
     {
-        assert!(self.pitem.len() == self.gram.nitems);
-        assert!(self.rhs_binding.len() == self.gram.nitems);
-
         if self.last_was_action {
             // If we have encountered a new rhs symbol for the current rule, and immediately
-            // prior to this we encountered an action (a code block), then we need to finish
-            // this rule, and rewrite the lhs of this rule to produce a new, anonymous lhs
-            // non-terminal.  Then we will start a new rule, using the lhs that we were
-            // previously using, with one rhs symbol, which is the generated symbol.
+            // prior to this we encountered an action (a code block), then the action belongs
+            // to an anonymous rule, which is inserted before the current rule.
             self.insert_empty_rule(span);
-            self.last_was_action = false;
         }
 
-        assert!(self.pitem.len() == self.gram.nitems);
-
-        // debug!("    add_symbol: pitem[{}] = {}_{}", self.pitem.len(), self.symbols[bp].name, bp);
-
-        self.gram.nitems += 1;
-        self.pitem.push(bp);
-        self.rhs_binding.push(ident);
+        self.rhs.push(bp);
+        self.rhs_idents.push(ident);
     }
 
-    // TODO: Modify this so that it takes &self, and produces a new set of tables
-    // which contain the packed symbols.  This should produce 
-    // struct SymbolTable {
-    //      names: Vec<String>,
-    //      values: Vec<i16>,
-    //      prec: Vec<u8>,
-    //      assoc: Vec<u8>,
-    //      start: uint,
-    // }
-    //
-    // As well as a vector, which allows mapping from old indices to new indices.
-    //
-    // Returns a vector which maps from old (unpacked) symbol indices to packed
-    // symbol indices.  This replaces the bucket.index field.
-    pub fn pack_symbols(&mut self, goal_symbol: uint) -> Vec<i16>
+    // Returns the non-terminal for an instance of a parameterized rule, e.g. "List<Expr>",
+    // creating it if this is the first use.  Its rules are read later.
+    pub fn instance_symbol(&mut self, name: &str, args: Vec<String>, span: Span) -> uint
     {
-        debug!("");
-        debug!("pack_symbols");
-        debug!("");
-
-        // output/mutated: m_goal
-
-        assert!(self.gram.name.len() == 0);
-        assert!(self.gram.value.len() == 0);
-        assert!(self.gram.pname.len() == 0);
-        assert!(self.gram.prec.len() == 0);
-        assert!(self.gram.assoc.len() == 0);        
-
-        assert!(goal_symbol < self.symbols.len());
-        assert!(self.symbols[goal_symbol].class == SymClass::NonTerminal);
-
-        for i in range(0, self.symbols.len()) {
-            debug!("    [{}] = {} value {}", i, self.symbols[i].name, self.symbols[i].value);
-        }
-
-        let nsyms: uint = 2 + self.symbols.len(); // $end and $accept
-        let mut ntokens: uint = 1; // $end
-
-        // Count the number of tokens.        
-        for sym in self.symbols.iter() {
-            if sym.class == SymClass::Terminal {
-                ntokens += 1;
-            }
+        let instance_name = format!("{}<{}>", name, args.connect(","));
+        if let Some(&sym) = self.symbol_table.get(instance_name.as_slice()) {
+            return sym;
         }
+        let sym = self.lookup(instance_name.as_slice(), span);
+        self.symbols[sym].class = SymClass::NonTerminal;
+        self.instances.push((sym, name.to_string(), args, span));
+        sym
+    }
 
-        let start_symbol = ntokens;
-        let nvars = nsyms - ntokens;
-
-        debug!("ntokens={} nvars={} nsyms={}", ntokens, nvars, nsyms);
-
-        self.gram.name.grow(nsyms, "".to_string());
-        self.gram.value.grow(nsyms, 0);
-        self.gram.prec.grow(nsyms, 0);
-        self.gram.assoc.grow(nsyms, 0);
-
-        // debug!("building v table");
-
-        // Build the 'v' table, which maps from packed symbols to packed symbols.  The size of the
-        // v table is the number of packed symbols (nsyms), which is not the same as the number of
-        // unpacked symbols.  This is because the $accept and error symbols are not symbols in the
-        // unpacked view.  In u = v[p], p is a packed symbol index (a number in the nsyms space),
-        // while u is the unpacked symbol.
-        let v = {
-            let mut v: Vec<uint> = Vec::from_elem(nsyms, NO_SYMBOL); // symbol indices, which point into reader.symbols[]
-            v[0] = NO_SYMBOL; // $end
-            v[start_symbol] = NO_SYMBOL; // $accept
-
-            let mut i: uint = 1;                      // packed index for assigning tokens
-            let mut j: uint = start_symbol + 1;       // packed index for assigning vars
-        
-            for s in range(0, self.symbols.len()) {
-                match self.symbols[s].class {
-                    SymClass::Terminal => { v[i] = s; i += 1; }
-                    SymClass::NonTerminal => {v[j] = s; j += 1; }
-                    SymClass::Unknown => { panic!("symbol {} has no defined class", self.symbols[s].name); }
-                }
-            }
-
-            assert!(i == ntokens);
-            assert!(j == nsyms);
-            for s in range(1, v.len()) {
-                assert!(s == start_symbol || v[s] != NO_SYMBOL);
-            }
-            v
+    // Returns the non-terminal for an EBNF operator applied to a symbol, generating it and its
+    // rules if this is the first use.
+    pub fn ebnf_symbol(&mut self, elem: uint, op: Ebnf, span: Span) -> uint
+    {
+        let elem_name = self.symbols[elem].name.clone();
+        let name = match op {
+            Ebnf::Optional => format!("{}?", elem_name),
+            Ebnf::Many => format!("{}*", elem_name),
+            Ebnf::Many1 => format!("{}+", elem_name),
+            Ebnf::Separated(sep) => format!("{}%{}", elem_name, self.symbols[sep].name),
         };
-
-        /*
-        debug!("v table (maps packed to unpacked):");
-        for i in range(0, v.len()) { 
-            if v[i] == NO_SYMBOL {
-                debug!("    packed {:3} --> NO_SYMBOL", i);
-            }
-            else {
-                debug!("    packed {:3} --> {}_{}", i, self.symbols[v[i]].name, v[i]);
-            }
-        }*/
-
-        // Build the remap table.  map_to_packed[old] gives the index of the packed location.
-        // This replaces the bucket::index field, from C.  This is the inverse of v.  The "error"
-        // symbol is always at packed index 1.
-        let mut map_to_packed: Vec<i16> = Vec::from_elem(nsyms, -1);
-        map_to_packed[0] = 1; // The 'error' symbol
-
-        for i in range(1, ntokens) {
-            map_to_packed[v[i]] = i as i16;
-        }
-
-        map_to_packed[goal_symbol] = (start_symbol + 1) as i16;
-        let mut k = start_symbol + 2;
-        for i in range(ntokens + 1, nsyms) {
-            if v[i] != goal_symbol {
-                map_to_packed[v[i]] = k as i16;
-                k += 1;
-            }
-        }
-
-        self.symbols[goal_symbol].value = 0;
-        let mut k: uint = 1;
-        for i in range(start_symbol + 1, nsyms) {
-            if v[i] != goal_symbol {
-                self.symbols[v[i]].value = k as i16;
-                k += 1;
-            }
-        }
-
-        // Assign token values
-        let mut k = 0;
-        for i in range(1, ntokens) {
-            let n = self.symbols[v[i]].value;
-            if n > 256 {
-                let mut j = k;
-                k += 1;
-                while j > 0 && self.gram.value[j - 1] > n {
-                    self.gram.value[j] = self.gram.value[j - 1];
-                    j -= 1;
-                }
-                self.gram.value[j] = n as i16;
-            }
-        }
-
-        if self.symbols[v[1]].value == UNDEFINED {
-            self.symbols[v[1]].value = 256;
-        }
-
-        let mut j = 0;
-        let mut n = 257;
-        for i in range(2, ntokens) {
-            if self.symbols[v[i]].value == UNDEFINED {
-                while j < k && n == self.gram.value[j] {
-                    while { j += 1; j < k && n == self.gram.value[j] } {
-                        continue;
-                    }
-                    n += 1;
-                }
-                self.symbols[v[i]].value = n as i16;
-                n += 1;
-            }
-        }
-
-        // Propagate $end token
-        self.gram.name[0] = "$end".to_string();
-        self.gram.value[0] = 0;
-        self.gram.prec[0] = 0;
-        self.gram.assoc[0] = TOKEN;
-
-        // Propagate token symbols
-        for i in range(1, ntokens) {
-            let from = &self.symbols[v[i]];
-            self.gram.name[i] = from.name.to_string();
-            self.gram.value[i] = from.value;
-            self.gram.prec[i] = from.prec;
-            self.gram.assoc[i] = from.assoc;
+        if let Some(&sym) = self.symbol_table.get(name.as_slice()) {
+            return sym;
         }
 
-        // Set up the start (accept) symbol
-        assert!(start_symbol == ntokens);
-        self.gram.name[start_symbol] = "$accept".to_string();
-        self.gram.value[start_symbol] = -1;
-        self.gram.prec[start_symbol] = 0;
-        self.gram.assoc[start_symbol] = TOKEN;
-
-        // Propagate non-terminal symbols
-        for i in range(start_symbol + 1, nsyms) {
-            let k = map_to_packed[v[i]] as uint;
-            assert!(k != NO_SYMBOL);
-            let from = &self.symbols[v[i]];
-            self.gram.name[k] = from.name.to_string();
-            self.gram.value[k] = from.value;
-            self.gram.prec[k] = from.prec;
-            self.gram.assoc[k] = from.assoc;
-        }
-
-/* todo
-        let symbol_pname: Vec<String> = Vec::new();
-        if gflag {
-            for i in range(0, nsyms) {
-                let pname = protect_string(reader.gram.name[i]);
-                symbol_pname.push(pname);
-            }
-        }
-*/
-
-        self.gram.nsyms = nsyms;
-        self.gram.ntokens = ntokens;
-        self.gram.nvars = nvars;
-        self.gram.start_symbol = start_symbol;
-
-        debug!("packed symbol table:");
-        for i in range(0, nsyms) {
-            debug!("    {:3} {} {:20} value {:3} prec {:2} assoc {:2}", i,
-            if i < ntokens { "token" } else { "var  " },
-            self.gram.name[i],
-            self.gram.value[i],
-            self.gram.prec[i],
-            self.gram.assoc[i]);
+        let sym = self.lookup(name.as_slice(), span);
+        self.symbols[sym].class = SymClass::NonTerminal;
+        self.ebnf.push((sym, op, elem));
+
+        let list = Some(token::str_to_ident("list"));
+        let item = Some(token::str_to_ident("item"));
+        let rules: Vec<(Vec<uint>, Vec<Option<ast::Ident>>, Builtin)> = match op {
+            Ebnf::Optional => vec![
+                (vec![], vec![], Builtin::NoValue),
+                (vec![elem], vec![item], Builtin::SomeValue)],
+            Ebnf::Many => vec![
+                (vec![], vec![], Builtin::EmptyList),
+                (vec![sym, elem], vec![list, item], Builtin::Append)],
+            Ebnf::Many1 => vec![
+                (vec![elem], vec![item], Builtin::SingletonList),
+                (vec![sym, elem], vec![list, item], Builtin::Append)],
+            Ebnf::Separated(sep) => vec![
+                (vec![elem], vec![item], Builtin::SingletonList),
+                (vec![sym, sep, elem], vec![list, None, item], Builtin::Append)],
+        };
+        for (rhs, binding, action) in rules.into_iter() {
+            self.add_rule(RuleDef { lhs: sym, rhs: rhs, prec: None, resolve: Vec::new(), begin: None, span: span }, Some(Action::Builtin(action)), binding);
         }
-    
-        map_to_packed
+        sym
     }
 
-    pub fn pack_grammar(&mut self, map_to_packed: &[i16], goal_var: uint)
+    // Hands the rules to the builder, in order.
+    pub fn build_rules(&mut self)
     {
-        let nitems = self.gram.nitems;
-        let nrules = self.gram.nrules;
-
-        // there are three pre-defined rules:
-        //      -1 -> (nothing)
-        //      -1 -> 
-        //      $accept -> start_symbol
-
-        let mut ritem: Vec<i16> = Vec::from_elem(nitems, 0);
-        ritem[0] = -1;
-        ritem[1] = map_to_packed[goal_var];
-        ritem[2] = 0;
-        ritem[3] = -2;
-
-        let mut rlhs: Vec<i16> = Vec::from_elem(nrules, 0);
-        rlhs[0] = 0;
-        rlhs[1] = 0;
-        rlhs[2] = self.gram.start_symbol as i16;
-
-        let mut rrhs: Vec<i16> = Vec::from_elem(nrules + 1, 0);
-        rrhs[0] = 0;
-        rrhs[1] = 0;
-        rrhs[2] = 1;
-
-        let plhs = &self.plhs;
-        let pitem = &self.pitem;
         let symbols = &self.symbols;
-
-        let gram = &mut self.gram;
-
-        let mut j = 4; // index of next item to process
-        for i in range(3, nrules) {
-            rlhs[i] = map_to_packed[plhs[i]] as i16;
-            rrhs[i] = j as i16;
-            let mut assoc = TOKEN;
-            let mut prec2 = 0u8;
-            while self.pitem[j] != NO_ITEM {
-                ritem[j] = map_to_packed[pitem[j]];
-                if symbols[pitem[j]].class == SymClass::Terminal {
-                    prec2 = symbols[pitem[j]].prec as u8;
-                    assoc = symbols[pitem[j]].assoc;
-                }
-                j += 1;
+        let builder = &mut self.builder;
+        for rule in self.rules.iter() {
+            let names: Vec<&str> = rule.rhs.iter().map(|&s| symbols[s].name.as_slice()).collect();
+            builder.rule(symbols[rule.lhs].name.as_slice(), names.as_slice());
+            if let Some(prec) = rule.prec {
+                builder.prec(symbols[prec].name.as_slice());
             }
-
-            // Terminate the item list with the negative of the rule index.
-            // This is important; it is used by the code in lr0::save_reductions()
-            // in order to realize when we've reached the end of a rule, and so can
-            // emit a reduction in a particular state.
-            ritem[j] = -(i as i16);
-            j += 1;
-            if gram.rprec[i] == UNDEFINED {
-                gram.rprec[i] = prec2 as i16;
-                gram.rassoc[i] = assoc;
+            for &(token, resolution) in rule.resolve.iter() {
+                builder.resolve(resolution, symbols[token].name.as_slice());
             }
         }
-
-        // Terminate the rrhs list
-        rrhs[nrules] = j as i16;
-
-        // Store results
-        gram.rlhs = rlhs;
-        gram.rrhs = rrhs;    
-        gram.ritem = ritem;
+        for &(winner, loser, _) in self.preferences.iter() {
+            match (winner, loser) {
+                (Some(w), Some(l)) => builder.prefer(symbols[w].name.as_slice(), symbols[l].name.as_slice()),
+                (None, Some(l)) => builder.prefer_shift(symbols[l].name.as_slice()),
+                (Some(w), None) => builder.prefer_reduce(symbols[w].name.as_slice()),
+                (None, None) => continue
+            };
+        }
     }
 
     pub fn print_grammar(gram: &Grammar)
@@ -604,69 +613,1886 @@ impl ReaderState
     }
 }
 
-// Reads the input of the macro invocation, parses and builds a grammar.
-pub fn read_grammar<'a>(grammar_sp: codemap::Span, parser: &mut Parser /* , tokens_enum: &'a P<ast::Item>, token_variants: &'a [P<ast::Variant>] */ )
-    -> (Grammar, Vec<Option<P<Block>>>, Vec<Option<ast::Ident>>) {
+// Everything that read_grammar() reads from the input of the macro invocation.
+pub struct GrammarDef {
+    pub gram: Grammar,
+    pub actions: Vec<Option<Action>>,
+    pub rhs_binding: Vec<Vec<Option<ast::Ident>>>,
+    // True if the grammar uses a shared token set, in which case the token constants have
+    // already been emitted, by grammar_tokens!.
+    pub shared_tokens: bool,
+    pub options: GrammarOptions,
+    // The variant of the value enum, and the type, of each symbol which was given a type by
+    // %type.  Indexed by grammar symbol.  Empty if the grammar does not use %type.
+    pub symbol_types: Vec<Option<(ast::Ident, P<ast::Ty>)>>,
+    // The enums of the AST, if the parser builds one (see make_ast).
+    pub ast_items: Vec<P<ast::Item>>,
+    // Where each symbol, rule, and action is in the grammar, for diagnostics.
+    pub spans: GrammarSpans,
+    // The entry points of a grammar with several start symbols: (start token, function name).
+    // Empty if the grammar has only one start symbol.
+    pub starts: Vec<(String, String)>,
+    // The patterns of the generated lexer: (token name, pattern, span), in the order that they
+    // were given.  The name is None for text that the lexer skips.  Empty if the grammar does
+    // not use %token or %skip.
+    pub lex_rules: Vec<(Option<String>, Pattern, Span)>,
+    // The states of the generated lexer, and which patterns and rules use them.
+    pub lexer_states: LexerStates,
+    // The keywords given by %keywords: the token that they are scanned as, and (token name,
+    // text, contextual) for each keyword.  None if the grammar does not use %keywords.
+    pub keywords: Option<(String, Vec<(String, String, bool)>)>,
+    // The tokens declared by %trivia: (token name, ends a line).  Empty if there are none.
+    pub trivia: Vec<(String, bool)>,
+    // The numbers of tokens in an external lexer, given by %external: (number, token name),
+    // sorted by number.  Empty if there are none.
+    pub externals: Vec<(u32, String)>,
+    // The parameters given by %parse-param: (name, type), in order.  Empty if there are none.
+    pub parse_params: Vec<(ast::Ident, P<ast::Ty>)>,
+    // The expressions declared by %expr, in order.  Builtin::ExprClimb indexes them.
+    pub exprs: Vec<ExprDef>,
+}
 
-    let mut reader: ReaderState = ReaderState::new();
+// The states of the generated lexer, given by %lexer-state and %begin.  A grammar which does not
+// declare any has only INITIAL, and every pattern is active in it.
+pub struct LexerStates {
+    // The names of the states; state 0 is INITIAL.
+    pub names: Vec<String>,
+    // For each pattern of GrammarDef.lex_rules: the states in which it is active, and the state
+    // that matching it enters, if any.
+    pub patterns: Vec<(Vec<uint>, Option<uint>)>,
+    // The rules given %begin: (grammar rule, state).
+    pub rules: Vec<(uint, uint)>,
+}
 
-        /*
-    // Parse the variants of the "tokens" enum.
-    for tv in token_variants.iter() {
-        debug!("    token (from enum): {}", tv);
+// Where the parts of a grammar are in the source, so that diagnostics about a symbol or a rule
+// point at it rather than at the whole grammar.
+pub struct GrammarSpans {
+    // Where each symbol was declared (or first used).  Indexed by grammar symbol; symbols which
+    // the reader made up, such as $accept, have the span of the grammar.
+    pub symbols: Vec<Span>,
+    // Where each rule begins.  Indexed by rule; the predefined rules have the span of the grammar.
+    pub rules: Vec<Span>,
+    // The action that the author wrote for each rule, if any.  Indexed by rule.
+    pub actions: Vec<Option<Span>>,
+}
+
+impl GrammarSpans {
+    pub fn symbol(&self, sym: SymbolId) -> Span {
+        self.symbols[sym.index()]
     }
-    */
 
-    // create_symbol_table()
+    pub fn rule(&self, rule: RuleId) -> Span {
+        self.rules[rule.index()]
+    }
 
-    // Add the well-known "error" symbol to the table.
-    {
-        let (_, bp) = reader.lookup_ref_mut("error", grammar_sp);
-        bp.class = SymClass::Terminal;
+    // The span of the action of a rule, or of the rule itself if it has no action.
+    pub fn action(&self, rule: RuleId) -> Span {
+        self.actions[rule.index()].unwrap_or(self.rules[rule.index()])
     }
+}
 
-    // read_declarations();
+// The options that are set by directives, and which affect how the parser is generated rather
+// than the grammar itself.
+pub struct GrammarOptions {
+    // The kind of automaton to build; set by "%define lr.type".
+    pub lr_type: LrType,
+    // The number of shift/reduce conflicts that the grammar is expected to have; set by "%expect".
+    pub expect_sr: Option<uint>,
+    // The number of reduce/reduce conflicts that the grammar is expected to have; set by
+    // "%expect-rr".
+    pub expect_rr: Option<uint>,
+    // The file that a report of the automaton is written to; set by "%define report.file".
+    pub report_file: Option<String>,
+    // The file that the automaton is written to, as a DOT graph; set by "%define report.dot".
+    pub dot_file: Option<String>,
+    // The file that an HTML report of the grammar is written to; set by "%define report.html".
+    pub html_file: Option<String>,
+    // The file that the grammar, automaton, and tables are written to, as JSON; set by
+    // "%define report.json".
+    pub json_file: Option<String>,
+    // What the parser builds; set by "%define tree".
+    pub tree: TreeKind,
+    // The %parse-param which holds the arena that the nodes of an AST are allocated in, if they
+    // are not boxed; set by "%define tree.arena".
+    pub arena: Option<String>,
+    // The names, visibility, and derives of the generated code; set by "%define api.*" and
+    // "%define debug.tables".
+    pub codegen: CodegenOptions,
+}
 
-    // now begins what was read_grammar();
+// What a parser builds.
+#[deriving(Copy,PartialEq)]
+pub enum TreeKind {
+    Actions,    // whatever the actions of the rules return (the default)
+    Cst,        // a concrete syntax tree, of racc_runtime::Node
+    Ast,        // an abstract syntax tree, of an enum generated for each non-terminal
+}
 
+// Reads "shift <token>" or "reduce <token>", after "%resolve" in a rule.  The conflicts between
+// reducing by the rule and another action on the token are then settled, silently, in favor of
+// shifting or of reducing by this rule (see GrammarBuilder::resolve).
+fn read_resolve(parser: &mut Parser, reader: &mut ReaderState, directive_span: Span) {
+    let kind_span = parser.span;
+    let kind = parser.parse_ident();
+    let resolution = match kind.as_str() {
+        "shift" => Resolution::Shift,
+        "reduce" => Resolution::Reduce,
+        other => {
+            parser.span_err(kind_span, format!("unknown resolution '{}'; expected 'shift' or 'reduce'", other).as_slice());
+            return;
+        }
+    };
+    let name_span = parser.span;
+    let name = parser.parse_ident().as_str().to_string();
+    let sym = reader.lookup(name.as_slice(), name_span);
+    if reader.symbols[sym].class == SymClass::NonTerminal {
+        parser.span_err(name_span, "%resolve must name a token, not a non-terminal");
+    }
+    else if reader.resolve.iter().any(|&(t, _)| t == sym) {
+        parser.span_err(directive_span, "rule has more than one %resolve for this token");
+    }
+    else {
+        reader.resolve.push((sym, resolution));
+    }
+}
 
-    // first, parse all tokens.
+// Reads "<choice> over <choice> ;", after "%prefer".  A choice is a non-terminal, which stands
+// for reducing by any of its rules, or "shift".  A conflict between the two choices which neither
+// %resolve nor precedence settles is settled, silently, in favor of the first (see
+// GrammarBuilder::prefer).
+fn read_prefer(parser: &mut Parser, reader: &mut ReaderState, directive_span: Span) {
+    let winner = read_preference_choice(parser, reader);
+    let over_span = parser.span;
+    if parser.parse_ident().as_str() != "over" {
+        parser.span_err(over_span, "expected 'over', as in \"%prefer Stmt over Expr;\"");
+    }
+    let loser = read_preference_choice(parser, reader);
+    parser.expect(&Token::Semi);
+    if winner == loser {
+        parser.span_err(directive_span, "%prefer must name two different choices");
+    }
+    else {
+        reader.preferences.push((winner, loser, directive_span));
+    }
+}
 
-    // let t_token = Token::Ident(Ident("token"), IdentStyle::Plain);
+// Reads one side of a %prefer: None for "shift", or a non-terminal.
+fn read_preference_choice(parser: &mut Parser, reader: &mut ReaderState) -> Option<uint> {
+    let span = parser.span;
+    let name = parser.parse_ident().as_str().to_string();
+    if name.as_slice() == "shift" {
+        return None;
+    }
+    let sym = reader.lookup(name.as_slice(), span);
+    if reader.symbols[sym].class == SymClass::Terminal {
+        parser.span_err(span, "%prefer must name non-terminals, or 'shift', not tokens");
+    }
+    Some(sym)
+}
 
-    let mut goal_symbol: Option<uint> = None;
+// Reads a directive, after the %.
+fn read_directive(parser: &mut Parser, reader: &mut ReaderState, token_set: &Option<String>, options: &mut GrammarOptions) {
+    let directive_span = parser.span;
+    let directive = parser.parse_ident();
+    match directive.as_str() {
+        "define" => read_define(parser, options),
+        "expect" => read_expect(parser, options),
+        "left" => read_precedence(parser, reader, token_set, Assoc::Left),
+        "right" => read_precedence(parser, reader, token_set, Assoc::Right),
+        "nonassoc" => read_precedence(parser, reader, token_set, Assoc::NonAssoc),
+        "type" => read_type(parser, reader),
+        "inline" => read_inline(parser, reader),
+        "token" => read_lex_token(parser, reader, token_set),
+        "keywords" => read_keywords(parser, reader, token_set),
+        "trivia" => read_trivia(parser, reader, token_set),
+        "external" => read_external(parser, reader, token_set),
+        "start" => read_start(parser, reader),
+        "parse" => read_parse_param(parser, reader),
+        "expr" => read_expr(parser, reader, token_set),
+        "prefer" => read_prefer(parser, reader, directive_span),
+        "lexer" => read_lexer_state(parser, reader),
+        "skip" => {
+            let states = read_state_list(parser, reader);
+            let pattern_span = parser.span;
+            let pattern = read_pattern(parser);
+            let begin = read_pattern_begin(parser, reader);
+            parser.expect(&Token::Semi);
+            if let Some(pattern) = pattern {
+                reader.lex_rules.push((None, pattern, pattern_span));
+                reader.lex_modes.push((states, begin));
+            }
+        }
+        _ => {
+            parser.span_err(directive_span, format!("unknown directive '%{}'", directive.as_str()).as_slice());
+            while parser.token != Token::Semi && parser.token != Token::Eof {
+                parser.bump();
+            }
+            parser.expect(&Token::Semi);
+        }
+    }
+}
 
-    // debug!("parsing token definitions");
-    loop {
-        // debug!("");
-        // debug!("token: {}", parser.token);
+// Reads "%left <token> ... ;", "%right <token> ... ;", or "%nonassoc <token> ... ;".  Each of
+// these declares a new precedence level, higher than the ones before it.  The tokens need not
+// have been defined already, as in YACC.
+fn read_precedence(parser: &mut Parser, reader: &mut ReaderState, token_set: &Option<String>, assoc: Assoc) {
+    let mut names: Vec<String> = Vec::new();
+    while !parser.eat(&Token::Semi) {
+        let span = parser.span;
+        let name = parser.parse_ident().as_str().to_string();
+        let (_, sym) = reader.lookup_ref_mut(name.as_slice(), span);
+        match sym.class {
+            SymClass::NonTerminal => {
+                parser.span_err(span, "only tokens can be given a precedence");
+                continue;
+            }
+            SymClass::Unknown => {
+                if let Some(ref set_name) = *token_set {
+                    parser.span_err(span, format!("token is not in token set '{}'; a grammar which uses a token set cannot define other tokens", set_name).as_slice());
+                    continue;
+                }
+                sym.class = SymClass::Terminal;
+            }
+            SymClass::Terminal => {}
+        }
+        names.push(name);
+    }
+    let names: Vec<&str> = names.iter().map(|n| n.as_slice()).collect();
+    reader.builder.precedence(assoc, names.as_slice());
+}
 
-        match parser.token {
-            Token::Eof => {
-                debug!("eof");
-                break;
+// Reads "%expr <symbol> : <operand> => <method> [, <method>] { <levels> }", where each level
+// is "%left <token> ... ;", "%right", "%nonassoc", or "%prefix", loosest first, as with the
+// precedence declarations of the grammar.  The non-terminal is parsed as a flat list of
+// operands and operators, which racc_runtime::expr::climb arranges by precedence, calling the
+// methods to combine operands.  Its rules are generated here:
+//
+//      E : E$list
+//      E$list : E$operand | E$list B E$operand         (for each binary operator B)
+//      E$operand : <operand> | E$prefix <operand>
+//      E$prefix : P | E$prefix P                       (for each prefix operator P)
+//
+// All of them are left recursive, and none of them conflict, however many operators there are.
+// The operators do not get a precedence in the grammar, so they can also be used elsewhere.
+fn read_expr(parser: &mut Parser, reader: &mut ReaderState, token_set: &Option<String>) {
+    let lhs_span = parser.span;
+    let lhs_name = parser.parse_ident().as_str().to_string();
+    parser.expect(&Token::Colon);
+    let operand_span = parser.span;
+    let operand_name = parser.parse_ident().as_str().to_string();
+    parser.expect(&Token::FatArrow);
+    let binary_span = parser.span;
+    let binary_method = parser.parse_ident();
+    let prefix_method = if parser.eat(&Token::Comma) {
+        let span = parser.span;
+        Some((span, parser.parse_ident()))
+    }
+    else {
+        None
+    };
+
+    let mut binary: Vec<(uint, uint, Assoc)> = Vec::new();
+    let mut prefix: Vec<(uint, uint)> = Vec::new();
+    let mut level = 0u;
+    parser.expect(&Token::OpenDelim(token::Brace));
+    while !parser.eat(&Token::CloseDelim(token::Brace)) {
+        parser.expect(&Token::BinOp(BinOpToken::Percent));
+        let kind_span = parser.span;
+        let kind = parser.parse_ident();
+        let assoc = match kind.as_str() {
+            "left" => Some(Assoc::Left),
+            "right" => Some(Assoc::Right),
+            "nonassoc" => Some(Assoc::NonAssoc),
+            "prefix" => None,
+            other => {
+                parser.span_err(kind_span, format!("unknown operator declaration '%{}'; expected '%left', '%right', '%nonassoc', or '%prefix'", other).as_slice());
+                skip_until(parser, false);
+                parser.expect(&Token::Semi);
+                continue;
+            }
+        };
+        level += 1;
+        while !parser.eat(&Token::Semi) {
+            let span = parser.span;
+            let name = parser.parse_ident().as_str().to_string();
+            let sym = reader.lookup(name.as_slice(), span);
+            match reader.symbols[sym].class {
+                SymClass::NonTerminal => {
+                    parser.span_err(span, "an operator must be a token, not a non-terminal");
+                    continue;
+                }
+                SymClass::Unknown => {
+                    if let Some(ref set_name) = *token_set {
+                        parser.span_err(span, format!("token is not in token set '{}'; a grammar which uses a token set cannot define other tokens", set_name).as_slice());
+                        continue;
+                    }
+                    reader.symbols[sym].class = SymClass::Terminal;
+                    reader.define_token(sym);
+                }
+                SymClass::Terminal => {}
+            }
+            match assoc {
+                Some(assoc) => {
+                    if binary.iter().any(|&(t, _, _)| t == sym) {
+                        parser.span_err(span, "token is declared more than once as a binary operator of this expression");
+                        continue;
+                    }
+                    binary.push((sym, level, assoc));
+                }
+                None => {
+                    if prefix.iter().any(|&(t, _)| t == sym) {
+                        parser.span_err(span, "token is declared more than once as a prefix operator of this expression");
+                        continue;
+                    }
+                    prefix.push((sym, level));
+                }
             }
+        }
+    }
+    if binary.len() == 0 {
+        parser.span_err(lhs_span, "%expr must declare at least one binary operator");
+        return;
+    }
+    match (prefix.len() != 0, prefix_method) {
+        (true, None) => {
+            parser.span_err(binary_span, "this expression has prefix operators, so it must name a second method, for them");
+            return;
+        }
+        (false, Some((span, _))) => {
+            parser.span_err(span, "this expression has no prefix operators, so it cannot name a method for them");
+            return;
+        }
+        _ => {}
+    }
 
-            Token::Ident(id, _) => {
-                // An identifier can start either a token definition or a rule definition.
-                // The next character will tell us whether this is a token definition or a
-                // rule definition.
-                //      : (begins a rule)
-                //      = (defines a token with a specific value)
-                //      ; (defines a token with an automatically-assigned value)
+    let lhs = reader.lookup(lhs_name.as_slice(), lhs_span);
+    match reader.symbols[lhs].class {
+        SymClass::Terminal => {
+            parser.span_err(lhs_span, "name has been defined as a token, and so cannot be declared by %expr");
+            return;
+        }
+        _ => reader.symbols[lhs].class = SymClass::NonTerminal
+    }
+    if reader.exprs.iter().any(|e| e.lhs_sym == lhs) {
+        parser.span_err(lhs_span, "non-terminal is declared by %expr more than once");
+        return;
+    }
+    let operand = reader.lookup(operand_name.as_slice(), operand_span);
 
-                // debug!("ident: id='{}'", id.as_str());
-                let name_def_str = id.as_str();
-                let name_def_span = parser.span;
-                let lhs = reader.lookup(name_def_str.as_slice(), name_def_span);
+    let mut lists: Vec<uint> = Vec::new();
+    for suffix in ["list", "operand", "prefix"].iter() {
+        if *suffix == "prefix" && prefix.len() == 0 {
+            break;
+        }
+        let sym = reader.lookup(format!("{}${}", lhs_name, suffix).as_slice(), lhs_span);
+        reader.symbols[sym].class = SymClass::NonTerminal;
+        lists.push(sym);
+    }
 
-                parser.bump();
-            
-                match parser.token {
-                    Token::Colon => {
-                        // debug!("found ':', beginning a rule");
-                        parser.bump();
-                        match reader.symbols[lhs].class {
+    let list = Some(token::str_to_ident("list"));
+    let item = Some(token::str_to_ident("item"));
+    let mut rules: Vec<(uint, Vec<uint>, Vec<Option<ast::Ident>>, Builtin)> = vec![
+        (lhs, vec![lists[0]], vec![list], Builtin::ExprClimb(reader.exprs.len())),
+        (lists[0], vec![lists[1]], vec![list], Builtin::ExprList)];
+    for &(op, _, _) in binary.iter() {
+        rules.push((lists[0], vec![lists[0], op, lists[1]], vec![list, None, item], Builtin::ExprBinary));
+    }
+    rules.push((lists[1], vec![operand], vec![item], Builtin::ExprOperand));
+    if prefix.len() != 0 {
+        rules.push((lists[1], vec![lists[2], operand], vec![list, item], Builtin::ExprOperand));
+        for &(op, _) in prefix.iter() {
+            rules.push((lists[2], vec![op], vec![None], Builtin::ExprPrefix));
+            rules.push((lists[2], vec![lists[2], op], vec![list, None], Builtin::ExprPrefix));
+        }
+    }
+    for (lhs, rhs, binding, action) in rules.into_iter() {
+        reader.add_rule(RuleDef { lhs: lhs, rhs: rhs, prec: None, resolve: Vec::new(), begin: None, span: lhs_span }, Some(Action::Builtin(action)), binding);
+    }
+
+    let names = &reader.symbols;
+    reader.exprs.push(ExprDef {
+        lhs: lhs_name.clone(),
+        binary: binary.iter().map(|&(t, level, assoc)| (names[t].name.clone(), level, assoc)).collect(),
+        prefix: prefix.iter().map(|&(t, level)| (names[t].name.clone(), level)).collect(),
+        binary_method: (binary_span, binary_method),
+        prefix_method: prefix_method,
+        lhs_sym: lhs,
+        operand: (operand, operand_span),
+        lists: lists,
+        span: lhs_span,
+    });
+}
+
+// Reads "%type <T> <symbol> ... ;".  The symbols can be tokens or non-terminals, and need not
+// have been defined already.
+fn read_type(parser: &mut Parser, reader: &mut ReaderState) {
+    parser.expect(&Token::Lt);
+    let ty = parser.parse_ty();
+    parser.expect_gt();
+
+    let mut variant: Option<ast::Ident> = None;
+    while !parser.eat(&Token::Semi) {
+        let span = parser.span;
+        let ident = parser.parse_ident();
+        let sym = reader.lookup(ident.as_str(), span);
+        if variant.is_none() {
+            variant = Some(ident);
+        }
+        while reader.types.len() <= sym {
+            reader.types.push(None);
+        }
+        if reader.types[sym].is_some() {
+            parser.span_err(span, "symbol has been given a type more than once");
+            continue;
+        }
+        reader.types[sym] = Some((variant.unwrap(), ty.clone()));
+    }
+    if variant.is_none() {
+        parser.span_err(parser.last_span, "%type must name at least one symbol");
+    }
+}
+
+// Generates the enums of an AST, and the actions which build it (see "%define tree ast").  Each
+// non-terminal gets an enum of the same name, with a variant for each of its rules.  A variant
+// is named by "=> Name" after its rule, or after the non-terminal if it has only one rule.  The
+// fields of a variant are the bound symbols of its rule, or all of its symbols if none are
+// bound.  Tokens have the value type of the grammar, non-terminals are boxed, and the symbols of
+// EBNF operators are lists or options.  The non-terminals are given their enums as their types,
+// as by %type.  The Visit, VisitMut, and Fold traits, which walk the AST, are generated too.
+fn make_ast(cx: &ExtCtxt, parser: &mut Parser, reader: &mut ReaderState, symbol_value_ty: &P<ast::Ty>, arena: Option<&str>, sp: Span) -> Vec<P<ast::Item>> {
+    let value_ty = pprust::ty_to_string(&**symbol_value_ty);
+    if let Some(arena) = arena {
+        if !reader.parse_params.iter().any(|&(name, _)| name.as_str() == arena) {
+            parser.span_err(sp, format!("%define tree.arena names '{}', which must be declared with %parse-param {}: &Arena;", arena, arena).as_slice());
+        }
+    }
+    let in_arena = arena.is_some();
+
+    // The enums take the lifetimes of the value type, such as the 'input of tokens which borrow
+    // from the input, and in an arena the lifetime of the arena, 'a.
+    let mut lifetimes = output::ty_lifetimes(&**symbol_value_ty);
+    if in_arena && !lifetimes.iter().any(|l| token::get_name(l.name).get() == "'a") {
+        lifetimes.push(cx.lifetime(sp, token::intern("'a")));
+    }
+    let lts = output::lifetime_params(lifetimes.as_slice());
+    let ebnf_syms: Vec<uint> = reader.ebnf.iter().map(|&(sym, _, _)| sym).collect();
+
+    // The non-terminals, in the order of their first rules.
+    let mut nonterminals: Vec<uint> = Vec::new();
+    for rule in reader.rules.iter() {
+        if !ebnf_syms.contains(&rule.lhs) && !nonterminals.contains(&rule.lhs) && Some(rule.lhs) != reader.start_goal {
+            nonterminals.push(rule.lhs);
+        }
+    }
+    for &nt in nonterminals.iter() {
+        while reader.types.len() <= nt {
+            reader.types.push(None);
+        }
+        if reader.types[nt].is_some() {
+            parser.span_err(reader.symbols[nt].span, "a parser which builds an AST (%define tree ast) cannot use %type");
+        }
+        let type_name = ast_type_name(reader.symbols[nt].name.as_slice());
+        let name = token::str_to_ident(type_name.as_slice());
+        reader.types[nt] = Some((name, output::ty_with_lifetimes(cx, sp, type_name.as_slice(), lifetimes.as_slice())));
+    }
+
+    // The variants of each non-terminal's enum, and the kinds of their fields.
+    let mut variants: Vec<Vec<(String, Vec<AstField>)>> = Vec::from_fn(nonterminals.len(), |_| Vec::new());
+    for i in range(0, reader.rules.len()) {
+        let lhs = reader.rules[i].lhs;
+        if ebnf_syms.contains(&lhs) || Some(lhs) == reader.start_goal {
+            continue;
+        }
+        let r = PREDEFINED_RULES + i;
+        let type_name = ast_type_name(reader.symbols[lhs].name.as_slice());
+        let variant = match reader.rule_actions[r] {
+            Some(Action::Method(_, name)) => name.as_str().to_string(),
+            None if reader.rules.iter().filter(|rule| rule.lhs == lhs).count() == 1 => type_name.clone(),
+            None => {
+                parser.span_err(reader.symbols[lhs].span, format!("'{}' has more than one rule, so each must name its variant, with => Name", reader.symbols[lhs].name).as_slice());
+                continue;
+            }
+            Some(ref action) => {
+                let span = user_action_span(action).unwrap_or(reader.symbols[lhs].span);
+                parser.span_err(span, "a parser which builds an AST (%define tree ast) cannot have actions or use %inline");
+                continue;
+            }
+        };
+
+        let rhs = reader.rules[i].rhs.clone();
+        let any_bound = reader.rhs_binding[r].iter().any(|b| b.is_some());
+        let mut fields: Vec<AstField> = Vec::new();
+        let mut args: Vec<String> = Vec::new();
+        for (pos, &sym) in rhs.iter().enumerate() {
+            if any_bound && reader.rhs_binding[r][pos].is_none() {
+                continue;
+            }
+            let binding = match reader.rhs_binding[r][pos] {
+                Some(b) => b,
+                None => {
+                    let b = token::str_to_ident(format!("yy{}", pos).as_slice());
+                    reader.rhs_binding[r][pos] = Some(b);
+                    b
+                }
+            };
+            let field = match ast_field(reader, sym) {
+                AstField::Node(name) => AstField::Boxed(name),
+                field => field
+            };
+            args.push(match (&field, arena) {
+                (&AstField::Boxed(_), Some(arena)) => format!("&*{}.alloc({})", arena, binding.as_str()),
+                (&AstField::Boxed(_), None) => format!("box {}", binding.as_str()),
+                _ => binding.as_str().to_string()
+            });
+            fields.push(field);
+        }
+
+        let value = if args.len() == 0 {
+            format!("{}::{}", type_name, variant)
+        }
+        else {
+            format!("{}::{}({})", type_name, variant, args.connect(", "))
+        };
+        reader.rule_actions[r] = Some(Action::Block(cx.block_expr(cx.parse_expr(value))));
+        let k = nonterminals.iter().position(|&nt| nt == lhs).unwrap();
+        variants[k].push((variant, fields));
+    }
+
+    // In an arena, each enum refers to its children with &'a references.  There is no VisitMut or
+    // Fold, since the children cannot be borrowed mutably or moved out of the arena.
+    let mut items: Vec<P<ast::Item>> = Vec::new();
+    let mut visit = format!("pub trait Visit{} {{\n", lts);
+    let mut visit_mut = format!("pub trait VisitMut{} {{\n", lts);
+    let mut fold = format!("pub trait Fold{} {{\n", lts);
+    for (&nt, variants) in nonterminals.iter().zip(variants.iter()) {
+        let type_name = ast_type_name(reader.symbols[nt].name.as_slice());
+        let method = ast_method_name(type_name.as_slice());
+        let node_ty = format!("{}{}", type_name, lts);
+
+        let defs: Vec<String> = variants.iter().map(|&(ref variant, ref fields)| {
+            if fields.len() == 0 {
+                variant.clone()
+            }
+            else {
+                let tys: Vec<String> = fields.iter().map(|f| f.ty(value_ty.as_slice(), lts.as_slice(), in_arena)).collect();
+                format!("{}({})", variant, tys.connect(", "))
+            }
+        }).collect();
+        items.push(cx.parse_item(format!("#[deriving(Show,Clone)] #[allow(non_camel_case_types)] pub enum {} {{ {} }}",
+            node_ty, defs.connect(", "))));
+
+        // Visit: visit_x calls walk_x, which visits the children of a node.  VisitMut is the same,
+        // with mutable references.
+        for &mutable in [false, true].iter() {
+            let (out, node_ref, binding) = if mutable { (&mut visit_mut, "&mut ", "ref mut") } else { (&mut visit, "&", "ref") };
+            out.push_str(format!("    fn visit_{0}(&mut self, node: {1}{2}) {{ self.walk_{0}(node) }}\n", method, node_ref, node_ty).as_slice());
+            out.push_str(format!("    fn walk_{}(&mut self, node: {}{}) {{\n        match *node {{\n", method, node_ref, node_ty).as_slice());
+            for &(ref variant, ref fields) in variants.iter() {
+                let mut pats: Vec<String> = Vec::new();
+                let mut body = String::new();
+                for (j, field) in fields.iter().enumerate() {
+                    match field.visit(format!("f{}", j).as_slice(), mutable) {
+                        Some(code) => {
+                            pats.push(format!("{} f{}", binding, j));
+                            body.push_str(code.as_slice());
+                            body.push(' ');
+                        }
+                        None => pats.push("_".to_string())
+                    }
+                }
+                out.push_str(format!("            {}::{} => {{ {}}}\n", type_name, ast_pattern(variant.as_slice(), pats.as_slice()), body).as_slice());
+            }
+            out.push_str("        }\n    }\n");
+        }
+
+        // Fold: fold_x calls fold_x_children, which rebuilds a node from its folded children.
+        fold.push_str(format!("    fn fold_{0}(&mut self, node: {1}) -> {1} {{ self.fold_{0}_children(node) }}\n", method, node_ty).as_slice());
+        fold.push_str(format!("    fn fold_{0}_children(&mut self, node: {1}) -> {1} {{\n        match node {{\n", method, node_ty).as_slice());
+        for &(ref variant, ref fields) in variants.iter() {
+            let pats: Vec<String> = range(0, fields.len()).map(|j| format!("f{}", j)).collect();
+            let values: Vec<String> = fields.iter().enumerate().map(|(j, field)| field.fold(format!("f{}", j).as_slice())).collect();
+            fold.push_str(format!("            {0}::{1} => {0}::{2},\n", type_name,
+                ast_pattern(variant.as_slice(), pats.as_slice()), ast_pattern(variant.as_slice(), values.as_slice())).as_slice());
+        }
+        fold.push_str("        }\n    }\n");
+    }
+    visit.push_str("}\n");
+    visit_mut.push_str("}\n");
+    fold.push_str("}\n");
+    debug!("{}", visit);
+    debug!("{}", visit_mut);
+    debug!("{}", fold);
+    items.push(cx.parse_item(visit));
+    if !in_arena {
+        items.push(cx.parse_item(visit_mut));
+        items.push(cx.parse_item(fold));
+    }
+    items
+}
+
+// The kind of a field of a variant of an AST enum.
+enum AstField {
+    Token,                  // the value of a token
+    Node(String),           // an AST enum, by value (in a list or an option)
+    Boxed(String),          // an AST enum, boxed
+    List(Box<AstField>),    // the symbol of a *, +, or % operator
+    Opt(Box<AstField>),     // the symbol of a ? operator
+}
+
+impl AstField {
+    // The type of the field, as source text.  Nodes have the lifetimes `lts` of the enums; in an
+    // arena, boxed nodes are references into the arena.
+    fn ty(&self, value_ty: &str, lts: &str, in_arena: bool) -> String {
+        match *self {
+            AstField::Token => value_ty.to_string(),
+            AstField::Node(ref name) => format!("{}{}", name, lts),
+            AstField::Boxed(ref name) if in_arena => format!("&'a {}{}", name, lts),
+            AstField::Boxed(ref name) => format!("Box<{}{}>", name, lts),
+            AstField::List(ref elem) => format!("Vec<{}>", elem.ty(value_ty, lts, in_arena)),
+            AstField::Opt(ref elem) => format!("Option<{}>", elem.ty(value_ty, lts, in_arena)),
+        }
+    }
+
+    fn has_nodes(&self) -> bool {
+        match *self {
+            AstField::Token => false,
+            AstField::Node(_) | AstField::Boxed(_) => true,
+            AstField::List(ref elem) | AstField::Opt(ref elem) => elem.has_nodes(),
+        }
+    }
+
+    // Code which visits the nodes in the field, given a reference to it (a mutable one if
+    // `mutable` is set, for VisitMut), or None if it has no nodes.
+    fn visit(&self, field: &str, mutable: bool) -> Option<String> {
+        if !self.has_nodes() {
+            return None;
+        }
+        let (node_ref, iter, binding) = if mutable { ("&mut ", "iter_mut", "ref mut") } else { ("&", "iter", "ref") };
+        Some(match *self {
+            AstField::Token => unreachable!(),
+            AstField::Node(ref name) => format!("self.visit_{}({});", ast_method_name(name.as_slice()), field),
+            AstField::Boxed(ref name) => format!("self.visit_{}({}**{});", ast_method_name(name.as_slice()), node_ref, field),
+            AstField::List(ref elem) => format!("for x in {}.{}() {{ {} }}", field, iter, elem.visit("x", mutable).unwrap()),
+            AstField::Opt(ref elem) => format!("if let Some({} x) = *{} {{ {} }}", binding, field, elem.visit("x", mutable).unwrap()),
+        })
+    }
+
+    // An expression which folds the field, given the field by value.
+    fn fold(&self, field: &str) -> String {
+        if !self.has_nodes() {
+            return field.to_string();
+        }
+        match *self {
+            AstField::Token => unreachable!(),
+            AstField::Node(ref name) => format!("self.fold_{}({})", ast_method_name(name.as_slice()), field),
+            AstField::Boxed(ref name) => format!("box self.fold_{}(*{})", ast_method_name(name.as_slice()), field),
+            AstField::List(ref elem) => format!("{}.into_iter().map(|x| {}).collect()", field, elem.fold("x")),
+            AstField::Opt(ref elem) => format!("{}.map(|x| {})", field, elem.fold("x")),
+        }
+    }
+}
+
+// A variant with the given fields, as a pattern or an expression.
+fn ast_pattern(variant: &str, fields: &[String]) -> String {
+    if fields.len() == 0 {
+        variant.to_string()
+    }
+    else {
+        format!("{}({})", variant, fields.connect(", "))
+    }
+}
+
+// The name of the AST enum of a non-terminal.  The names of instances of parameterized rules,
+// such as "Pair<A,B>", are made into identifiers ("Pair_A_B").
+fn ast_type_name(name: &str) -> String {
+    let mut out = String::new();
+    for c in name.chars() {
+        out.push(if c.is_alphanumeric() || c == '_' { c } else { '_' });
+    }
+    out.as_slice().trim_right_chars('_').to_string()
+}
+
+// The suffix of the Visit, VisitMut, and Fold methods of an AST enum: its name in snake case, e.g.
+// "bin_op" for "BinOp".
+fn ast_method_name(type_name: &str) -> String {
+    let mut out = String::new();
+    let mut prev_lower = false;
+    for c in type_name.chars() {
+        if c.is_uppercase() {
+            if prev_lower {
+                out.push('_');
+            }
+            out.push(c.to_lowercase());
+            prev_lower = false;
+        }
+        else {
+            out.push(c);
+            prev_lower = c.is_lowercase() || c.is_digit(10);
+        }
+    }
+    out
+}
+
+// The kind of the value of a symbol in an AST.
+fn ast_field(reader: &ReaderState, sym: uint) -> AstField {
+    for &(s, op, elem) in reader.ebnf.iter() {
+        if s == sym {
+            let elem = box ast_field(reader, elem);
+            return match op {
+                Ebnf::Optional => AstField::Opt(elem),
+                _ => AstField::List(elem)
+            };
+        }
+    }
+    if reader.symbols[sym].class == SymClass::NonTerminal {
+        AstField::Node(ast_type_name(reader.symbols[sym].name.as_slice()))
+    }
+    else {
+        AstField::Token
+    }
+}
+
+// Returns the span of an action that the grammar's author wrote, if there is one in `action`.
+fn user_action_span(action: &Action) -> Option<Span> {
+    match *action {
+        Action::Block(ref block) | Action::Fallible(ref block) => Some(block.span),
+        Action::Method(span, _) => Some(span),
+        Action::Builtin(_) => None,
+        Action::Inlined(ref inlined, ref outer) => {
+            inlined.iter()
+                .filter_map(|entry| entry.action.as_ref().and_then(|a| user_action_span(a)))
+                .next()
+                .or_else(|| outer.as_ref().and_then(|a| user_action_span(&**a)))
+        }
+    }
+}
+
+// Reads "%inline <symbol> ... ;".  The symbols must be non-terminals; their rules are
+// substituted into the rules which use them (see inline_rules).
+fn read_inline(parser: &mut Parser, reader: &mut ReaderState) {
+    while !parser.eat(&Token::Semi) {
+        let span = parser.span;
+        let name = parser.parse_ident().as_str().to_string();
+        let (sym, bp) = reader.lookup_ref_mut(name.as_slice(), span);
+        match bp.class {
+            SymClass::Terminal => {
+                parser.span_err(span, "only non-terminals can be %inline");
+                continue;
+            }
+            _ => bp.class = SymClass::NonTerminal
+        }
+        if !reader.inline.contains(&sym) {
+            reader.inline.push(sym);
+        }
+    }
+}
+
+// Reads "%start <symbol> ... ;".  A single symbol replaces the default start symbol, which is the
+// lhs of the first rule; each of several symbols is an entry point of the parser (see
+// add_start_rules).
+fn read_start(parser: &mut Parser, reader: &mut ReaderState) {
+    while !parser.eat(&Token::Semi) {
+        let span = parser.span;
+        let name = parser.parse_ident().as_str().to_string();
+        let sym = reader.lookup(name.as_slice(), span);
+        if reader.symbols[sym].class == SymClass::Terminal {
+            parser.span_err(span, "a start symbol must be a non-terminal, not a token");
+        }
+        else if reader.starts.iter().any(|&(s, _)| s == sym) {
+            parser.span_err(span, "start symbol is given more than once");
+        }
+        else {
+            reader.starts.push((sym, span));
+        }
+    }
+}
+
+// Makes the start symbols given by %start the start of the grammar.  When there are several, the
+// goal symbol is a new non-terminal, "$start", with a rule "$start : YYSTART_A A" for each start
+// symbol A.  A parse of A begins with the token YYSTART_A, which selects that rule; its value is
+// the value of A.  Returns the start tokens and the names of the entry points which push them.
+fn add_start_rules(parser: &mut Parser, reader: &mut ReaderState, goal: &mut Option<uint>) -> Vec<(String, String)> {
+    if reader.starts.len() == 0 {
+        return Vec::new();
+    }
+    for &(sym, span) in reader.starts.iter() {
+        if reader.inline.contains(&sym) {
+            parser.span_err(span, "a start symbol cannot be %inline");
+        }
+    }
+    if reader.starts.len() == 1 {
+        let (sym, _) = reader.starts[0];
+        *goal = Some(sym);
+        reader.builder.start(reader.symbols[sym].name.as_slice());
+        return Vec::new();
+    }
+
+    let (_, first_span) = reader.starts[0];
+    let start_goal = reader.lookup("$start", first_span);
+    reader.symbols[start_goal].class = SymClass::NonTerminal;
+    let mut entries: Vec<(String, String)> = Vec::new();
+    for &(sym, span) in reader.starts.clone().iter() {
+        let name = reader.symbols[sym].name.clone();
+        let token_name = format!("YYSTART_{}", name);
+        let token = reader.lookup(token_name.as_slice(), span);
+        if reader.symbols[token].class != SymClass::Unknown {
+            parser.span_err(span, format!("the start token for this symbol, '{}', is already defined", token_name).as_slice());
+            continue;
+        }
+        reader.symbols[token].class = SymClass::Terminal;
+        reader.define_token(token);
+        reader.add_rule(RuleDef { lhs: start_goal, rhs: vec![token, sym], prec: None, resolve: Vec::new(), begin: None, span: span },
+            Some(Action::Builtin(Builtin::StartRule)), vec![None, None]);
+        entries.push((token_name, format!("parse_{}", ast_method_name(name.as_slice()))));
+    }
+    *goal = Some(start_goal);
+    reader.builder.start("$start");
+    reader.start_goal = Some(start_goal);
+    entries
+}
+
+// Reads "%token [<states>] <ident> <string> [%begin <state>] ;".  The token is defined, if it has
+// not been already, and the string is its pattern in the generated lexer.
+fn read_lex_token(parser: &mut Parser, reader: &mut ReaderState, token_set: &Option<String>) {
+    let states = read_state_list(parser, reader);
+    let name_span = parser.span;
+    let name = parser.parse_ident().as_str().to_string();
+    let pattern_span = parser.span;
+    let pattern = read_pattern(parser);
+    let begin = read_pattern_begin(parser, reader);
+    parser.expect(&Token::Semi);
+
+    let sym = reader.lookup(name.as_slice(), name_span);
+    if reader.symbols[sym].class == SymClass::Terminal && reader.lex_rules.iter().any(|&(t, _, _)| t == Some(sym)) {
+        parser.span_err(pattern_span, "token is given more than one pattern");
+        return;
+    }
+    if !declare_token(parser, reader, token_set, sym, name_span) {
+        return;
+    }
+    if let Some(pattern) = pattern {
+        if let Pattern::Literal(ref text) = pattern {
+            // The same text may be the pattern of different tokens in different states, such as
+            // the quotes which open and close a string; it is an alias of the first of them.
+            let other_states = reader.aliases.get(text.as_slice())
+                .and_then(|&other| reader.lex_rules.iter().position(|&(t, _, _)| t == Some(other)))
+                .map(|i| reader.lex_modes[i].0.clone());
+            let disjoint = match other_states {
+                Some(ref other_states) => !other_states.iter().any(|s| states.contains(s)),
+                None => false
+            };
+            if !disjoint && !add_alias(parser, reader, sym, text.as_slice(), pattern_span) {
+                return;
+            }
+        }
+        reader.lex_rules.push((Some(sym), pattern, pattern_span));
+        reader.lex_modes.push((states, begin));
+    }
+}
+
+// Reads the pattern of a token: a string, which is matched literally, or a raw string, which is a
+// regular expression.
+fn read_pattern(parser: &mut Parser) -> Option<Pattern> {
+    let span = parser.span;
+    match parser.parse_lit().node {
+        ast::LitStr(ref text, ast::CookedStr) => Some(Pattern::Literal(text.get().to_string())),
+        ast::LitStr(ref text, ast::RawStr(_)) => Some(Pattern::Regex(text.get().to_string())),
+        _ => {
+            parser.span_err(span, "expected a string (matched literally) or a raw string (a regular expression)");
+            None
+        }
+    }
+}
+
+// Reads "%lexer-state <ident> ... ;", after "lexer".  The states are numbered in order, from 1;
+// INITIAL is state 0.
+fn read_lexer_state(parser: &mut Parser, reader: &mut ReaderState) {
+    parser.expect(&Token::BinOp(BinOpToken::Minus));
+    let suffix_span = parser.span;
+    let suffix = parser.parse_ident();
+    if suffix.as_str() != "state" {
+        parser.span_err(suffix_span, format!("unknown directive '%lexer-{}'", suffix.as_str()).as_slice());
+    }
+    while parser.token != Token::Semi && parser.token != Token::Eof {
+        let span = parser.span;
+        let name = parser.parse_ident().as_str().to_string();
+        if reader.lexer_states.iter().any(|&(ref n, _)| *n == name) {
+            parser.span_err(span, format!("lexer state '{}' is already declared", name).as_slice());
+        }
+        else {
+            reader.lexer_states.push((name, span));
+        }
+    }
+    parser.expect(&Token::Semi);
+}
+
+// Reads the name of a lexer state, which must have been declared by %lexer-state.
+fn read_state_name(parser: &mut Parser, reader: &ReaderState) -> Option<uint> {
+    let span = parser.span;
+    let name = parser.parse_ident();
+    match reader.lexer_states.iter().position(|&(ref n, _)| n.as_slice() == name.as_str()) {
+        Some(state) => Some(state),
+        None => {
+            parser.span_err(span, format!("unknown lexer state '{}'; lexer states are declared by %lexer-state", name.as_str()).as_slice());
+            None
+        }
+    }
+}
+
+// Reads the states that a pattern is active in, "<A, B>", before the pattern of %token or %skip.
+// A pattern without them is active in INITIAL.
+fn read_state_list(parser: &mut Parser, reader: &ReaderState) -> Vec<uint> {
+    if !parser.eat(&Token::Lt) {
+        return vec![0];
+    }
+    let mut states = Vec::new();
+    loop {
+        if let Some(state) = read_state_name(parser, reader) {
+            if !states.contains(&state) {
+                states.push(state);
+            }
+        }
+        if !parser.eat(&Token::Comma) {
+            break;
+        }
+    }
+    parser.expect_gt();
+    states
+}
+
+// Reads "%begin <state>" after a pattern, if it is there.
+fn read_pattern_begin(parser: &mut Parser, reader: &ReaderState) -> Option<uint> {
+    if !parser.eat(&Token::BinOp(BinOpToken::Percent)) {
+        return None;
+    }
+    let span = parser.span;
+    let directive = parser.parse_ident();
+    if directive.as_str() != "begin" {
+        parser.span_err(span, format!("unknown directive '%{}' after a pattern; only %begin can be used here", directive.as_str()).as_slice());
+        return None;
+    }
+    read_state_name(parser, reader)
+}
+
+// Defines a token which is named by a directive (%token or %keywords), if it has not been
+// defined already.  Returns false, having reported why, if the name is a non-terminal.
+fn declare_token(parser: &mut Parser, reader: &mut ReaderState, token_set: &Option<String>, sym: uint, name_span: Span) -> bool {
+    match reader.symbols[sym].class {
+        SymClass::Terminal => true,
+        SymClass::NonTerminal => {
+            parser.span_err(name_span, "token was previously used as a variable");
+            parser.span_err(reader.symbols[sym].span, "location of previous definition");
+            false
+        }
+        SymClass::Unknown => {
+            if let Some(ref set_name) = *token_set {
+                parser.span_err(name_span, format!("token is not in token set '{}'; a grammar which uses a token set cannot define other tokens", set_name).as_slice());
+            }
+            reader.symbols[sym].class = SymClass::Terminal;
+            reader.symbols[sym].span = name_span;
+            reader.define_token(sym);
+            true
+        }
+    }
+}
+
+// Makes a string an alias of a token, so that rules can use it in place of the token's name.
+// Returns false, having reported why, if the string is already an alias.
+fn add_alias(parser: &mut Parser, reader: &mut ReaderState, sym: uint, text: &str, span: Span) -> bool {
+    if let Some(&other) = reader.aliases.get(text) {
+        parser.span_err(span, format!("the text \"{}\" is already declared for token '{}'", text, reader.symbols[other].name).as_slice());
+        return false;
+    }
+    reader.aliases.insert(text.to_string(), sym);
+    reader.builder.alias(reader.symbols[sym].name.as_slice(), text);
+    true
+}
+
+// Reads "%trivia [newline] <ident> ... ;".  Each token is defined, if it has not been already.  A
+// parser which builds a tree attaches the trivia tokens to the tokens around them, rather than
+// parsing them; "newline" marks trivia which ends a line, and so ends the trailing trivia of the
+// token before it (see ParserState::push_token_at in the runtime).
+fn read_trivia(parser: &mut Parser, reader: &mut ReaderState, token_set: &Option<String>) {
+    let newline = match parser.token {
+        Token::Ident(id, _) if id.as_str() == "newline" => parser.look_ahead(1, |t| *t != Token::Semi),
+        _ => false
+    };
+    if newline {
+        parser.bump();
+    }
+    while parser.token != Token::Semi && parser.token != Token::Eof {
+        let span = parser.span;
+        let name = parser.parse_ident().as_str().to_string();
+        let sym = reader.lookup(name.as_slice(), span);
+        if !declare_token(parser, reader, token_set, sym, span) {
+            continue;
+        }
+        if reader.trivia.iter().any(|&(t, _, _)| t == sym) {
+            parser.span_err(span, "token is already declared as trivia");
+        }
+        else {
+            reader.trivia.push((sym, newline, span));
+        }
+    }
+    parser.expect(&Token::Semi);
+}
+
+// Reads "%external <ident> = <n> , ... ;".  Each token is defined, if it has not been already,
+// and <n> is its number in a lexer which was not generated from the grammar.  The generated
+// EXTERNAL_TOKENS translates those numbers into token values (see remap.rs in the runtime).
+// Several numbers may stand for the same token, but each number stands for one token.
+fn read_external(parser: &mut Parser, reader: &mut ReaderState, token_set: &Option<String>) {
+    loop {
+        let name_span = parser.span;
+        let name = parser.parse_ident().as_str().to_string();
+        parser.expect(&Token::Eq);
+        let number_span = parser.span;
+        let number = match parser.parse_lit().node {
+            ast::LitInt(n, _) if n <= 0xffff_ffff => Some(n as u32),
+            _ => {
+                parser.span_err(number_span, "expected the number of the token in the external lexer, an integer");
+                None
+            }
+        };
+
+        let sym = reader.lookup(name.as_slice(), name_span);
+        if let Some(number) = number {
+            if let Some(&(_, other)) = reader.externals.iter().find(|&&(n, _)| n == number) {
+                parser.span_err(number_span, format!("{} is already the external number of token '{}'", number, reader.symbols[other].name).as_slice());
+            }
+            else if declare_token(parser, reader, token_set, sym, name_span) {
+                reader.externals.push((number, sym));
+            }
+        }
+
+        if !parser.eat(&Token::Comma) {
+            break;
+        }
+    }
+    parser.expect(&Token::Semi);
+}
+
+// Reads "%keywords [contextual] <ident> : <token> <string> , ... ;".  <ident> is the token that
+// the keywords are scanned as, such as IDENT; it must be the same for every %keywords.  Each
+// keyword is defined as a token, and its string is its text, and an alias for it.
+fn read_keywords(parser: &mut Parser, reader: &mut ReaderState, token_set: &Option<String>) {
+    let contextual = match parser.token {
+        Token::Ident(id, _) if id.as_str() == "contextual" => parser.look_ahead(1, |t| *t != Token::Colon),
+        _ => false
+    };
+    if contextual {
+        parser.bump();
+    }
+
+    let ident_span = parser.span;
+    let ident_name = parser.parse_ident().as_str().to_string();
+    let ident = reader.lookup(ident_name.as_slice(), ident_span);
+    let ident_ok = declare_token(parser, reader, token_set, ident, ident_span);
+    match reader.keyword_ident {
+        Some(other) if other != ident => {
+            parser.span_err(ident_span, format!("keywords are already scanned as token '{}'; all keywords must be scanned as the same token", reader.symbols[other].name).as_slice());
+        }
+        _ => if ident_ok { reader.keyword_ident = Some(ident); }
+    }
+    parser.expect(&Token::Colon);
+
+    loop {
+        let name_span = parser.span;
+        let name = parser.parse_ident().as_str().to_string();
+        let text_span = parser.span;
+        let text = match parser.parse_lit().node {
+            ast::LitStr(ref text, _) if text.get().len() != 0 => Some(text.get().to_string()),
+            _ => {
+                parser.span_err(text_span, "expected the text of the keyword, a string");
+                None
+            }
+        };
+
+        let sym = reader.lookup(name.as_slice(), name_span);
+        if reader.keywords.iter().any(|&(k, _, _)| k == sym) {
+            parser.span_err(name_span, "token is declared as a keyword more than once");
+        }
+        else if declare_token(parser, reader, token_set, sym, name_span) {
+            if let Some(text) = text {
+                if add_alias(parser, reader, sym, text.as_slice(), text_span) {
+                    reader.keywords.push((sym, text, contextual));
+                }
+            }
+        }
+
+        if !parser.eat(&Token::Comma) {
+            break;
+        }
+    }
+    parser.expect(&Token::Semi);
+}
+
+// Reads the value of a token, in "NAME = <n> ;", after the "=".  The value is what the lexer
+// returns for the token, and what the token's constant and its variant of the Token enum are.
+fn read_token_value(parser: &mut Parser, reader: &mut ReaderState, token: uint) {
+    let value_span = parser.span;
+    let value = match parser.parse_lit().node {
+        ast::LitInt(n, _) if n > 0 && n < 0x7fff && n != 256 => Some(n as i16),
+        ast::LitInt(..) => {
+            parser.span_err(value_span, "the value of a token must be between 1 and 32766, and cannot be 256, which is the value of error");
+            None
+        }
+        _ => {
+            parser.span_err(value_span, "expected the value of the token, an integer");
+            None
+        }
+    };
+    parser.expect(&Token::Semi);
+
+    if let Some(value) = value {
+        if let Some(&other) = reader.token_values.get(&value) {
+            parser.span_err(value_span, format!("{} is already the value of token '{}'", value, reader.symbols[other].name).as_slice());
+            parser.span_err(reader.symbols[other].span, "see definition of token");
+            return;
+        }
+        reader.token_values.insert(value, token);
+        reader.builder.token_value(reader.symbols[token].name.as_slice(), value);
+    }
+}
+
+// Reads "%expect <n> ;" or "%expect-rr <n> ;".
+// Reads "%parse-param <ident> : <type> ;", after "parse".  The parameters become the fields of
+// the generated ParseParams struct, which is the context of the grammar, and are bound by name
+// in every action (see output.rs).
+fn read_parse_param(parser: &mut Parser, reader: &mut ReaderState) {
+    parser.expect(&Token::BinOp(BinOpToken::Minus));
+    let suffix_span = parser.span;
+    let suffix = parser.parse_ident();
+    if suffix.as_str() != "param" {
+        parser.span_err(suffix_span, format!("unknown directive '%parse-{}'", suffix.as_str()).as_slice());
+    }
+    let name_span = parser.span;
+    let name = parser.parse_ident();
+    parser.expect(&Token::Colon);
+    let ty = parser.parse_ty();
+    parser.expect(&Token::Semi);
+    if reader.parse_params.iter().any(|&(n, _)| n == name) {
+        parser.span_err(name_span, format!("'{}' is declared by %parse-param more than once", name.as_str()).as_slice());
+        return;
+    }
+    reader.parse_params.push((name, ty));
+}
+
+fn read_expect(parser: &mut Parser, options: &mut GrammarOptions) {
+    let rr = if parser.eat(&Token::BinOp(BinOpToken::Minus)) {
+        let suffix_span = parser.span;
+        let suffix = parser.parse_ident();
+        if suffix.as_str() != "rr" {
+            parser.span_err(suffix_span, format!("unknown directive '%expect-{}'", suffix.as_str()).as_slice());
+        }
+        true
+    }
+    else {
+        false
+    };
+
+    let count_span = parser.span;
+    let count = match parser.parse_lit().node {
+        ast::LitInt(n, _) => Some(n as uint),
+        _ => {
+            parser.span_err(count_span, "expected the number of conflicts");
+            None
+        }
+    };
+    parser.expect(&Token::Semi);
+
+    let expect = if rr { &mut options.expect_rr } else { &mut options.expect_sr };
+    if expect.is_some() {
+        parser.span_err(count_span, "the expected number of conflicts is given more than once");
+    }
+    *expect = count;
+}
+
+// Reads "%define <key> <value> ;".  The key is a sequence of identifiers separated by dots, as in
+// Bison; the value is the text of all of the tokens up to the semicolon.
+fn read_define(parser: &mut Parser, options: &mut GrammarOptions) {
+    let key_span = parser.span;
+    let mut key = parser.parse_ident().as_str().to_string();
+    while parser.eat(&Token::Dot) {
+        key.push('.');
+        key.push_str(parser.parse_ident().as_str());
+    }
+
+    let value_span = parser.span;
+    let mut value = String::new();
+    while parser.token != Token::Semi && parser.token != Token::Eof {
+        value.push_str(pprust::token_to_string(&parser.token).as_slice());
+        parser.bump();
+    }
+    parser.expect(&Token::Semi);
+
+    match key.as_slice() {
+        "lr.type" => {
+            match value.as_slice() {
+                "lalr" => options.lr_type = LrType::Lalr,
+                "ielr" => options.lr_type = LrType::Ielr,
+                "canonical-lr" => options.lr_type = LrType::CanonicalLr,
+                "slr" => options.lr_type = LrType::Slr,
+                _ => parser.span_err(value_span, format!("unknown value '{}' for lr.type; expected 'lalr', 'ielr', 'canonical-lr', or 'slr'", value).as_slice())
+            }
+        }
+        "report.file" => options.report_file = read_path(parser, value_span, key.as_slice(), value.as_slice()),
+        "report.dot" => options.dot_file = read_path(parser, value_span, key.as_slice(), value.as_slice()),
+        "report.html" => options.html_file = read_path(parser, value_span, key.as_slice(), value.as_slice()),
+        "report.json" => options.json_file = read_path(parser, value_span, key.as_slice(), value.as_slice()),
+        "tree" => {
+            match value.as_slice() {
+                "cst" => options.tree = TreeKind::Cst,
+                "ast" => options.tree = TreeKind::Ast,
+                "none" => options.tree = TreeKind::Actions,
+                _ => parser.span_err(value_span, format!("unknown value '{}' for tree; expected 'cst', 'ast', or 'none'", value).as_slice())
+            }
+        }
+        "tree.arena" => options.arena = Some(read_name(parser, value_span, key.as_slice(), value.as_slice())),
+        "api.token.enum" => options.codegen.token_enum = read_name(parser, value_span, key.as_slice(), value.as_slice()),
+        "api.parser.type" => options.codegen.parser_type = Some(read_name(parser, value_span, key.as_slice(), value.as_slice())),
+        "api.visibility" => {
+            match value.as_slice() {
+                "pub" => options.codegen.public = true,
+                "private" => options.codegen.public = false,
+                _ => parser.span_err(value_span, format!("unknown value '{}' for api.visibility; expected 'pub' or 'private'", value).as_slice())
+            }
+        }
+        "api.derive" => {
+            for name in value.as_slice().split(',') {
+                let name = read_name(parser, value_span, key.as_slice(), name);
+                if !options.codegen.derives.contains(&name) {
+                    options.codegen.derives.push(name);
+                }
+            }
+        }
+        "api.error.type" => options.codegen.error_type = Some(value),
+        "debug.tables" => {
+            match value.as_slice() {
+                "true" => options.codegen.debug_tables = true,
+                "false" => options.codegen.debug_tables = false,
+                _ => parser.span_err(value_span, format!("unknown value '{}' for debug.tables; expected 'true' or 'false'", value).as_slice())
+            }
+        }
+        _ => parser.span_err(key_span, format!("unknown option '{}'", key).as_slice())
+    }
+}
+
+// Returns the identifier in the value of a %define.
+fn read_name(parser: &mut Parser, value_span: Span, key: &str, value: &str) -> String {
+    let is_ident = value.len() != 0 && value.chars().all(|c| c == '_' || c.is_alphanumeric())
+        && !value.char_at(0).is_digit(10);
+    if !is_ident {
+        parser.span_err(value_span, format!("the value of {} must be an identifier", key).as_slice());
+    }
+    value.to_string()
+}
+
+// Returns the path in the value of a %define, which must be a string literal.
+fn read_path(parser: &mut Parser, value_span: Span, key: &str, value: &str) -> Option<String> {
+    if value.len() >= 2 && value.starts_with("\"") && value.ends_with("\"") {
+        Some(value.slice(1, value.len() - 1).to_string())
+    }
+    else {
+        parser.span_err(value_span, format!("the value of {} must be a string literal", key).as_slice());
+        None
+    }
+}
+
+// Reads "<A, B, ...>", the parameters of a parameterized rule or the arguments of an instance
+// of one.
+fn read_template_params(parser: &mut Parser) -> Vec<String> {
+    parser.expect(&Token::Lt);
+    let mut params: Vec<String> = Vec::new();
+    loop {
+        params.push(parser.parse_ident().as_str().to_string());
+        if !parser.eat(&Token::Comma) {
+            break;
+        }
+    }
+    parser.expect_gt();
+    params
+}
+
+// Reads the definition of a parameterized rule, "Name<A, ...> : ... ;", after the name.  The
+// body is kept as tokens, and is read once for each instance (see instantiate_templates).
+fn read_template(parser: &mut Parser, reader: &mut ReaderState, name: &str, span: Span) {
+    let params = read_template_params(parser);
+    parser.expect(&Token::Colon);
+    let mut body: Vec<ast::TokenTree> = Vec::new();
+    while parser.token != Token::Semi && parser.token != Token::Eof {
+        body.push(parser.parse_token_tree());
+    }
+    body.push(ast::TtToken(parser.span, Token::Semi));
+    parser.expect(&Token::Semi);
+
+    if reader.templates.contains_key(name) {
+        parser.span_err(span, "parameterized rule is defined more than once");
+        return;
+    }
+    reader.templates.insert(name.to_string(), Template { params: params, body: body });
+}
+
+// Reads the rules of each instance of a parameterized rule.  Reading an instance can create
+// more instances, which are read in turn.
+fn instantiate_templates(cx: &ExtCtxt, parser: &mut Parser, reader: &mut ReaderState) {
+    let mut next = 0;
+    while next < reader.instances.len() {
+        let (sym, name, args, span) = reader.instances[next].clone();
+        next += 1;
+
+        let body: Vec<ast::TokenTree> = match reader.templates.get(name.as_slice()) {
+            Some(template) if template.params.len() == args.len() => {
+                // Replace the parameters with the arguments.  Only the symbols of the rule are
+                // replaced, not the names within actions.
+                template.body.iter().map(|tt| match *tt {
+                    ast::TtToken(sp, Token::Ident(id, style)) => {
+                        match template.params.iter().position(|p| p.as_slice() == id.as_str()) {
+                            Some(i) => ast::TtToken(sp, Token::Ident(token::str_to_ident(args[i].as_slice()), style)),
+                            None => tt.clone()
+                        }
+                    }
+                    _ => tt.clone()
+                }).collect()
+            }
+            Some(template) => {
+                parser.span_err(span, format!("'{}' has {} parameter(s), but is given {}", name, template.params.len(), args.len()).as_slice());
+                continue;
+            }
+            None => {
+                parser.span_err(span, format!("there is no parameterized rule named '{}'", name).as_slice());
+                continue;
+            }
+        };
+
+        let mut body_parser = cx.new_parser_from_tts(body.as_slice());
+        read_rule_body(&mut body_parser, reader, sym);
+    }
+}
+
+// Substitutes the rules of each %inline non-terminal into the rules which use it, and then
+// removes its rules.  A rule "A : B X=x C" becomes "A : B p q C", for each rule "X : p q" of X.
+// The action of the rule of X runs first, and its value is bound to x; then the action of the
+// rule for A runs.  The bindings of the rules of X become bindings of the new rules, so they
+// must not clash with the bindings of the rules which use X.
+fn inline_rules(parser: &mut Parser, reader: &mut ReaderState, goal: Option<uint>) {
+    if reader.inline.len() == 0 {
+        return;
+    }
+    let nsyms = reader.symbols.len();
+    let mut inline: Vec<bool> = Vec::from_elem(nsyms, false);
+    for &x in reader.inline.iter() {
+        inline[x] = true;
+    }
+
+    // Check the %inline non-terminals.  They must not use themselves, directly or through other
+    // %inline non-terminals, or the substitution would never end.
+    let mut ok = true;
+    let mut uses: Vec<Vec<bool>> = Vec::from_elem(nsyms, Vec::new());
+    for &x in reader.inline.iter() {
+        uses[x] = Vec::from_elem(nsyms, false);
+        let mut any = false;
+        for (i, rule) in reader.rules.iter().enumerate() {
+            if rule.lhs != x {
+                continue;
+            }
+            any = true;
+            for &s in rule.rhs.iter() {
+                uses[x][s] = true;
+            }
+            if let Some(Action::Method(method_span, _)) = reader.rule_actions[PREDEFINED_RULES + i] {
+                parser.span_err(method_span, "the rules of an %inline non-terminal cannot use action methods");
+                ok = false;
+            }
+            if rule.begin.is_some() {
+                parser.span_err(rule.span, "the rules of an %inline non-terminal cannot use %begin");
+                ok = false;
+            }
+        }
+        if !any {
+            parser.span_err(reader.symbols[x].span, "%inline non-terminal has no rules");
+            ok = false;
+        }
+        if goal == Some(x) {
+            parser.span_err(reader.symbols[x].span, "the start symbol cannot be %inline");
+            ok = false;
+        }
+    }
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for &x in reader.inline.iter() {
+            for &y in reader.inline.iter() {
+                if uses[x][y] {
+                    for z in range(0, nsyms) {
+                        if uses[y][z] && !uses[x][z] {
+                            uses[x][z] = true;
+                            changed = true;
+                        }
+                    }
+                }
+            }
+        }
+    }
+    for &x in reader.inline.iter() {
+        if uses[x][x] {
+            parser.span_err(reader.symbols[x].span, "%inline non-terminal uses itself, so it cannot be substituted");
+            ok = false;
+        }
+    }
+    if !ok {
+        return;
+    }
+
+    let mut rules = mem::replace(&mut reader.rules, Vec::new());
+    let mut actions: Vec<Option<Action>> = mem::replace(&mut reader.rule_actions, Vec::new()).into_iter().skip(PREDEFINED_RULES).collect();
+    let mut bindings: Vec<Vec<Option<ast::Ident>>> = mem::replace(&mut reader.rhs_binding, Vec::new()).into_iter().skip(PREDEFINED_RULES).collect();
+
+    // Each round substitutes for the first %inline symbol of each rule.
+    loop {
+        let mut new_rules: Vec<RuleDef> = Vec::new();
+        let mut new_actions: Vec<Option<Action>> = Vec::new();
+        let mut new_bindings: Vec<Vec<Option<ast::Ident>>> = Vec::new();
+        let mut any = false;
+        for i in range(0, rules.len()) {
+            let pos = if inline[rules[i].lhs] { None } else { rules[i].rhs.iter().position(|&s| inline[s]) };
+            let pos = match pos {
+                Some(pos) => pos,
+                None => {
+                    new_rules.push(RuleDef { lhs: rules[i].lhs, rhs: rules[i].rhs.clone(), prec: rules[i].prec, resolve: rules[i].resolve.clone(), begin: rules[i].begin, span: rules[i].span });
+                    new_actions.push(actions[i].clone());
+                    new_bindings.push(bindings[i].clone());
+                    continue;
+                }
+            };
+            any = true;
+            let x = rules[i].rhs[pos];
+            let ty = match reader.types.get(x) {
+                Some(&Some((_, ref ty))) => Some(ty.clone()),
+                _ => None
+            };
+            if let Some(Action::Method(method_span, _)) = actions[i] {
+                parser.span_err(method_span, "a rule which uses an %inline non-terminal cannot use an action method");
+            }
+            for j in range(0, rules.len()) {
+                if rules[j].lhs != x {
+                    continue;
+                }
+                let mut rhs: Vec<uint> = rules[i].rhs.slice_to(pos).to_vec();
+                rhs.push_all(rules[j].rhs.as_slice());
+                rhs.push_all(rules[i].rhs.slice_from(pos + 1));
+                let mut binding: Vec<Option<ast::Ident>> = bindings[i].slice_to(pos).to_vec();
+                binding.push_all(bindings[j].as_slice());
+                binding.push_all(bindings[i].slice_from(pos + 1));
+                for (k, b) in binding.iter().enumerate() {
+                    if let Some(name) = *b {
+                        if binding.slice_to(k).contains(b) {
+                            parser.span_err(reader.symbols[x].span, format!("'{}' is bound both in a rule of this %inline non-terminal and in a rule which uses it", name.as_str()).as_slice());
+                        }
+                    }
+                }
+
+                // The actions of the rule of X (including any that were substituted into it) run
+                // before the actions that were substituted into this rule, and then this rule's.
+                let (mut inlined, inner) = match actions[j].clone() {
+                    Some(Action::Inlined(inlined, inner)) => (inlined, inner.map(|a| *a)),
+                    action => (Vec::new(), action)
+                };
+                inlined.push(InlinedAction { binding: bindings[i][pos], ty: ty.clone(), action: inner });
+                let outer = match actions[i].clone() {
+                    Some(Action::Inlined(rest, outer)) => {
+                        inlined.extend(rest.into_iter());
+                        outer
+                    }
+                    action => action.map(|a| box a)
+                };
+
+                new_rules.push(RuleDef { lhs: rules[i].lhs, rhs: rhs, prec: rules[i].prec, resolve: rules[i].resolve.clone(), begin: rules[i].begin, span: rules[i].span });
+                new_actions.push(Some(Action::Inlined(inlined, outer)));
+                new_bindings.push(binding);
+            }
+        }
+        rules = new_rules;
+        actions = new_actions;
+        bindings = new_bindings;
+        if !any {
+            break;
+        }
+    }
+
+    // Remove the rules of the %inline non-terminals.
+    reader.rule_actions = Vec::from_fn(PREDEFINED_RULES, |_| None);
+    reader.rhs_binding = Vec::from_fn(PREDEFINED_RULES, |_| Vec::new());
+    for ((rule, action), binding) in rules.into_iter().zip(actions.into_iter()).zip(bindings.into_iter()) {
+        if !inline[rule.lhs] {
+            reader.add_rule(rule, action, binding);
+        }
+    }
+}
+
+// Reads the alternatives of a rule, after the colon, up to and including the semicolon.
+fn read_rule_body(parser: &mut Parser, reader: &mut ReaderState, lhs: uint) {
+    reader.start_rule(lhs, parser.span);
+
+    // In this loop, we process the symbols on the right-hand side of the rule.
+    // If we encounter a symbol (whether token or variable), we add a reference
+    // (a symbol index) to it to the current rule.  If we encounter an action definition
+    // (code) then we hold on to it, until we see whether it is followed by more symbols
+    // (in which case it belongs to an unnamed variable for the rule prefix) or by the
+    // end of the rule (in which case it is the action for this rule).
+    //
+    // If we encounter a | or ;, then the current rule ends.  If we encounter a |, then
+    // we start a new rule, with the same left-hand symbol.
+    loop {
+        match parser.token {
+            Token::Ident(rhs_ident, _) => {
+                let rhs_name = rhs_ident.as_str();
+                // debug!("rule: found token/symbol ref '{}'", rhs_name);                                    
+                let rhs_span = parser.span;
+                parser.bump();
+                let mut rhs = if parser.token == Token::Lt {
+                    // An instance of a parameterized rule, e.g. "List<Expr>".
+                    let args = read_template_params(parser);
+                    reader.instance_symbol(rhs_name.as_slice(), args, rhs_span)
+                }
+                else {
+                    reader.lookup(rhs_name.as_slice(), rhs_span)
+                };
+
+                // see if the symbol is followed by an EBNF operator.  "X*=list"
+                // and "X+=list" are lexed with the "=" as part of the operator.
+                let mut eq_eaten = false;
+                let is_separator = parser.token == Token::BinOp(BinOpToken::Percent)
+                    && parser.look_ahead(1, |t| match *t {
+                        Token::Ident(id, _) => id.as_str() != "prec" && id.as_str() != "resolve" && id.as_str() != "begin",
+                        _ => false
+                    });
+                if is_separator {
+                    parser.bump();
+                    let sep_span = parser.span;
+                    let sep_name = parser.parse_ident().as_str().to_string();
+                    let sep = reader.lookup(sep_name.as_slice(), sep_span);
+                    rhs = reader.ebnf_symbol(rhs, Ebnf::Separated(sep), rhs_span);
+                }
+                else {
+                    let op = match parser.token {
+                        Token::Question => Some(Ebnf::Optional),
+                        Token::BinOp(BinOpToken::Star) => Some(Ebnf::Many),
+                        Token::BinOp(BinOpToken::Plus) => Some(Ebnf::Many1),
+                        Token::BinOpEq(BinOpToken::Star) => { eq_eaten = true; Some(Ebnf::Many) }
+                        Token::BinOpEq(BinOpToken::Plus) => { eq_eaten = true; Some(Ebnf::Many1) }
+                        _ => None
+                    };
+                    if let Some(op) = op {
+                        parser.bump();
+                        rhs = reader.ebnf_symbol(rhs, op, rhs_span);
+                    }
+                }
+
+                let rbind = read_binding(parser, reader, eq_eaten);
+                reader.add_symbol(rhs, parser.span, rbind);
+            }
+            Token::Literal(token::Lit::Str_(_), _) => {
+                // A string names the token which was declared with it, e.g. "+" for
+                // "%token PLUS \"+\";".
+                let lit_span = parser.span;
+                let text = match parser.parse_lit().node {
+                    ast::LitStr(ref text, _) => text.get().to_string(),
+                    _ => unreachable!()
+                };
+                let rhs = match reader.aliases.get(text.as_slice()) {
+                    Some(&sym) => sym,
+                    None => {
+                        parser.span_err(lit_span, format!("no token has been declared with the text \"{}\"; declare one with %token NAME \"{}\";", text, text).as_slice());
+                        0
+                    }
+                };
+                let rbind = read_binding(parser, reader, false);
+                reader.add_symbol(rhs, parser.span, rbind);
+            }
+            Token::BinOp(BinOpToken::Or) => {
+                parser.bump();
+                reader.end_rule();
+                reader.start_rule(lhs, parser.span);
+            }
+            Token::Pound => {
+                // "#[cfg(...)]" before an alternative; if it is false, the alternative is
+                // skipped, as if it had not been written.
+                let cfg_span = parser.span;
+                let enabled = read_cfg(parser);
+                if reader.rhs.len() != 0 || reader.action.is_some() {
+                    parser.span_err(cfg_span, "#[cfg] must come at the start of an alternative");
+                }
+                else if !enabled {
+                    skip_until(parser, true);
+                    if parser.eat(&Token::BinOp(BinOpToken::Or)) {
+                        reader.start_rule(lhs, parser.span);
+                    }
+                    else {
+                        parser.expect(&Token::Semi);
+                        break;
+                    }
+                }
+            }
+            Token::OpenDelim(_) => {
+                // Parse an action (a code block).  Parsing it is actually very easy, thanks to Rust!
+                let block_span = parser.span;
+                let block = parser.parse_block();
+                let action = if parser.eat(&Token::Question) { Action::Fallible(block) } else { Action::Block(block) };
+                reader.add_action(block_span, action);
+            }
+            Token::FatArrow => {
+                // "=> name" names a method of the Actions trait.
+                parser.bump();
+                let method_span = parser.span;
+                let method = parser.parse_ident();
+                reader.add_action(method_span, Action::Method(method_span, method));
+            }
+            Token::BinOp(BinOpToken::Percent) => {
+                // "%prec NAME" gives the rule the precedence of NAME, "%resolve shift NAME"
+                // settles its conflicts on NAME, and "%begin STATE" puts the lexer in STATE.
+                parser.bump();
+                let prec_span = parser.span;
+                let directive = parser.parse_ident();
+                if directive.as_str() == "resolve" {
+                    read_resolve(parser, reader, prec_span);
+                    continue;
+                }
+                if directive.as_str() == "begin" {
+                    let state = read_state_name(parser, reader);
+                    if reader.begin.is_some() {
+                        parser.span_err(prec_span, "rule has more than one %begin");
+                    }
+                    else {
+                        reader.begin = state;
+                    }
+                    continue;
+                }
+                if directive.as_str() != "prec" {
+                    parser.span_err(prec_span, format!("unknown directive '%{}' in rule; only %prec, %resolve and %begin can be used in a rule", directive.as_str()).as_slice());
+                }
+                let name_span = parser.span;
+                let name = parser.parse_ident().as_str().to_string();
+                let sym = reader.lookup(name.as_slice(), name_span);
+                if reader.symbols[sym].class == SymClass::NonTerminal {
+                    parser.span_err(name_span, "%prec must name a token, not a non-terminal");
+                }
+                else if reader.prec.is_some() {
+                    parser.span_err(prec_span, "rule has more than one %prec");
+                }
+                else {
+                    reader.prec = Some(sym);
+                }
+            }
+            Token::Semi => {
+                parser.bump();
+                reader.end_rule();
+                break;
+            }
+            _ => {
+                parser.unexpected();
+            }
+        }
+    }
+}
+
+// Reads the attributes before an item or an alternative, and returns true if the conditions of
+// all of them are true.  Only #[cfg(...)] is allowed; its condition is evaluated now, against
+// the configuration of the crate being compiled, so that a grammar can have rules which are
+// used only when a cargo feature is enabled, e.g. #[cfg(feature = "extensions")].
+fn read_cfg(parser: &mut Parser) -> bool {
+    let mut enabled = true;
+    for a in parser.parse_outer_attributes().iter() {
+        if !a.check_name("cfg") {
+            parser.span_err(a.span, "only #[cfg(...)] can be used in a grammar");
+            continue;
+        }
+        match a.meta_item_list() {
+            Some(items) if items.len() == 1 => {
+                if !attr::cfg_matches(&parser.sess.span_diagnostic, parser.cfg.as_slice(), &*items[0]) {
+                    enabled = false;
+                }
+            }
+            _ => parser.span_err(a.span, "expected #[cfg(<condition>)]")
+        }
+    }
+    enabled
+}
+
+// Skips the tokens of an item which is disabled by #[cfg], up to the ";" which ends it, or also
+// up to a "|" if `alternative` is true.  Actions are skipped whole, since they are single token
+// trees.
+fn skip_until(parser: &mut Parser, alternative: bool) {
+    loop {
+        match parser.token {
+            Token::Semi | Token::Eof => break,
+            Token::BinOp(BinOpToken::Or) if alternative => break,
+            _ => { parser.parse_token_tree(); }
+        }
+    }
+}
+
+// Reads the binding of a symbol in the rhs of a rule, "= binding" or "(binding)", if there is
+// one.  eq_eaten is true if the "=" was part of the token before it, as in "X*=list".
+fn read_binding(parser: &mut Parser, reader: &ReaderState, eq_eaten: bool) -> Option<ast::Ident> {
+    let mut rbind: Option<ast::Ident> = None;
+    let bind_span = parser.span;
+    if eq_eaten {
+        rbind = Some(parser.parse_ident());
+    }
+    else if parser.eat(&Token::Eq) {
+        rbind = Some(parser.parse_ident());
+    }
+    else if parser.eat(&Token::OpenDelim(token::Paren)) {
+        rbind = Some(parser.parse_ident());
+        parser.expect(&Token::CloseDelim(token::Paren));
+    }
+    if let Some(name) = rbind {
+        if reader.rhs_idents.iter().any(|b| *b == Some(name)) {
+            parser.span_err(bind_span, format!("'{}' is bound more than once in this rule", name.as_str()).as_slice());
+        }
+    }
+    rbind
+}
+
+// Reads "%include <name> ;" or "%include <string> ;", after "include", and then the items of
+// the fragment or file that it names (see fragments.rs).  A file is found relative to the file
+// which includes it.
+fn read_include(cx: &ExtCtxt, parser: &mut Parser, reader: &mut ReaderState, token_set: &mut Option<String>,
+                options: &mut GrammarOptions, goal_symbol: &mut Option<uint>, including: &mut Vec<String>) {
+    let span = parser.span;
+    let (key, prefix, tts) = match parser.token {
+        Token::Literal(token::Lit::Str_(_), _) => {
+            let name = match parser.parse_lit().node {
+                ast::LitStr(ref text, _) => text.get().to_string(),
+                _ => unreachable!()
+            };
+            parser.expect(&Token::Semi);
+            let path = Path::new(cx.codemap().span_to_filename(span)).dir_path().join(name.as_slice());
+            let text = match File::open(&path).read_to_string() {
+                Ok(text) => text,
+                Err(e) => {
+                    parser.span_err(span, format!("cannot read {}: {}", path.display(), e).as_slice());
+                    return;
+                }
+            };
+            let key = path.display().to_string();
+            let prefix = path.filestem_str().unwrap_or("").to_string();
+            let tts = parse::parse_tts_from_source_str(key.clone(), text, cx.cfg(), cx.parse_sess());
+            (key, prefix, tts)
+        }
+        _ => {
+            let name = parser.parse_ident().as_str().to_string();
+            parser.expect(&Token::Semi);
+            match fragments::find_fragment(name.as_slice()) {
+                Some(tts) => (name.clone(), name, tts),
+                None => {
+                    parser.span_err(span, format!("grammar fragment '{}' has not been defined; use grammar_fragment! to define it, before this grammar", name).as_slice());
+                    return;
+                }
+            }
+        }
+    };
+
+    if including.contains(&key) {
+        parser.span_err(span, format!("'{}' includes itself", key).as_slice());
+        return;
+    }
+    including.push(key);
+    let tts = fragments::namespace(cx, prefix.as_slice(), tts);
+    let mut fragment_parser = cx.new_parser_from_tts(tts.as_slice());
+    read_items(cx, &mut fragment_parser, reader, token_set, options, goal_symbol, including);
+    including.pop();
+}
+
+// Reads the items of a grammar (token definitions, directives, and rules) until the end of the
+// input of `parser`.  This is called for the body of the grammar! invocation, and again for each
+// fragment that it includes (see read_include); `including` holds the fragments which are being
+// read, innermost last.
+fn read_items(cx: &ExtCtxt, parser: &mut Parser, reader: &mut ReaderState, token_set: &mut Option<String>,
+              options: &mut GrammarOptions, goal_symbol: &mut Option<uint>, including: &mut Vec<String>) {
+    // debug!("parsing token definitions");
+    loop {
+        // debug!("");
+        // debug!("token: {}", parser.token);
+
+        if parser.token == Token::Pound {
+            // "#[cfg(...)]" before an item; if it is false, the item is skipped.
+            if !read_cfg(parser) {
+                skip_until(parser, false);
+                parser.expect(&Token::Semi);
+            }
+            continue;
+        }
+
+        if parser.eat_keyword(keywords::Use) {
+            // "use <set> ;" defines all of the tokens of a shared token set.
+            let set_span = parser.span;
+            let set_name = parser.parse_ident().as_str().to_string();
+            parser.expect(&Token::Semi);
+
+            if token_set.is_some() || reader.symbols.len() != 1 {
+                parser.span_err(set_span, "a token set must be used before any other token definitions, and only one token set may be used");
+                continue;
+            }
+
+            match token_sets::find_token_set(set_name.as_slice()) {
+                Some(tokens) => {
+                    for name in tokens.iter() {
+                        let (index, _) = reader.lookup_ref_mut(name.as_slice(), set_span);
+                        reader.symbols[index].class = SymClass::Terminal;
+                        reader.define_token(index);
+                    }
+                }
+                None => {
+                    parser.span_err(set_span, format!("token set '{}' has not been defined; use grammar_tokens! to define it, before this grammar", set_name).as_slice());
+                }
+            }
+            *token_set = Some(set_name);
+            continue;
+        }
+
+        if parser.eat(&Token::BinOp(BinOpToken::Percent)) {
+            match parser.token {
+                Token::Ident(id, _) if id.as_str() == "include" => {
+                    parser.bump();
+                    read_include(cx, parser, reader, token_set, options, goal_symbol, including);
+                }
+                _ => read_directive(parser, reader, token_set, options)
+            }
+            continue;
+        }
+
+        match parser.token {
+            Token::Eof => {
+                debug!("eof");
+                break;
+            }
+
+            Token::Ident(id, _) => {
+                // An identifier can start either a token definition or a rule definition.
+                // The next character will tell us whether this is a token definition or a
+                // rule definition.
+                //      : (begins a rule)
+                //      = (defines a token with a specific value)
+                //      ; (defines a token with an automatically-assigned value)
+
+                // debug!("ident: id='{}'", id.as_str());
+                let name_def_str = id.as_str();
+                let name_def_span = parser.span;
+                parser.bump();
+
+                if parser.token == Token::Lt {
+                    read_template(parser, reader, name_def_str.as_slice(), name_def_span);
+                    continue;
+                }
+                let lhs = reader.lookup(name_def_str.as_slice(), name_def_span);
+
+                match parser.token {
+                    Token::Colon => {
+                        // debug!("found ':', beginning a rule");
+                        parser.bump();
+                        match reader.symbols[lhs].class {
                             SymClass::Terminal => {
                                 parser.span_err(name_def_span, "name has been defined as a token, and so cannot be on the left-hand side of a rule");
                                 parser.span_err(reader.symbols[lhs].span, "see definition of token");
@@ -681,73 +2507,13 @@ pub fn read_grammar<'a>(grammar_sp: codemap::Span, parser: &mut Parser /* , toke
                             }
                         }
 
-                        reader.start_rule(lhs, parser.span);
-
-                        if goal_symbol == None {
+                        if *goal_symbol == None {
                             debug!("using '{}' as start symbol", name_def_str);
-                            goal_symbol = Some(lhs);
+                            *goal_symbol = Some(lhs);
+                            reader.builder.start(name_def_str.as_slice());
                         }
 
-                        // In this loop, we process the symbols on the right-hand side of the rule.
-                        // If we encounter a symbol (whether token or variable), we add a reference
-                        // (a symbol index) to it to prhs.  If we encounter an action definition (code)
-                        // then we close the current rule, then peek ahead to see whether we need to
-                        // define an unnamed variable for the rule prefix, or whether this is a normal
-                        // rule for name_def_str.
-                        //
-                        // If we encounter a | or ;, then the current rule ends.  If we encounter a |, then
-                        // we start a new rule, with the same left-hand symbol.
-                        loop {
-                            match parser.token {
-                                Token::Ident(rhs_ident, _) => {
-                                    let rhs_name = rhs_ident.as_str();
-                                    // debug!("rule: found token/symbol ref '{}'", rhs_name);                                    
-                                    let rhs = reader.lookup(rhs_name.as_slice(), parser.span);
-                                    parser.bump();
-
-                                    // see if the symbol is followed by "= binding".
-                                    let mut rbind: Option<ast::Ident> = None;
-                                    if parser.token == Token::Eq {
-                                        parser.bump();
-                                        match parser.token {
-                                            Token::Ident(rhs_bind_ident, _) => {
-                                                // debug!("found rhs binding");
-                                                rbind = Some(rhs_bind_ident);
-                                                parser.bump();
-                                            }
-                                            _ => {
-                                                parser.unexpected();
-                                            }
-                                        }
-                                    }
-
-                                    reader.add_symbol(rhs, parser.span, rbind);
-                                }
-                                Token::BinOp(BinOpToken::Or) => {
-                                    parser.bump();
-                                    reader.end_rule();
-                                    reader.start_rule(lhs, name_def_span);
-                                }
-                                Token::OpenDelim(_) => {
-                                    // Parse an action (a code block).  Parsing it is actually very easy, thanks to Rust!
-                                    assert!(reader.rule_blocks.len() == reader.gram.nrules);
-                                    if reader.last_was_action {
-                                        reader.insert_empty_rule(parser.span);
-                                    }
-                                    let block = parser.parse_block();
-                                    reader.rule_blocks.push(Some(block));
-                                    reader.last_was_action = true;
-                                }
-                                Token::Semi => {
-                                    parser.bump();
-                                    reader.end_rule();
-                                    break;
-                                }
-                                _ => {
-                                    parser.unexpected();
-                                }
-                            }
-                        }
+                        read_rule_body(parser, reader, lhs);
                     }
                     Token::Eq | Token::Semi => {
                         // = or ; defines a token
@@ -767,21 +2533,20 @@ pub fn read_grammar<'a>(grammar_sp: codemap::Span, parser: &mut Parser /* , toke
                             }
                             SymClass::Unknown => {
                                 // debug!("resolving forward ref of a token '{}'", name_def_str);
+                                if let Some(ref set_name) = *token_set {
+                                    // Tokens defined here would not have the values that the
+                                    // other grammars which use the set agree on.
+                                    parser.span_err(name_def_span, format!("token is not in token set '{}'; a grammar which uses a token set cannot define other tokens", set_name).as_slice());
+                                }
                                 reader.symbols[lhs].class = SymClass::Terminal;
+                                reader.define_token(lhs);
                             }
                         }
 
                         // debug!("defining token '{}' at unpacked symbol index {}", name_def_str, lhs);
 
                         if has_value {
-                            match parser.token {
-                                Token::Literal(_,_) => {
-                                    debug!("token has value: {}", parser.token);
-                                    parser.bump();
-                                    parser.expect(&Token::Semi);
-                                }
-                                _ => parser.unexpected()
-                            }                                                        
+                            read_token_value(parser, reader, lhs);
                         }
                     }
                     _ => {
@@ -795,8 +2560,73 @@ pub fn read_grammar<'a>(grammar_sp: codemap::Span, parser: &mut Parser /* , toke
         }
     }
 
+}
+
+// Returns the type of the values of a symbol: the type given by %type, or else the value type of
+// the grammar.
+fn reader_symbol_ty(reader: &ReaderState, sym: uint, symbol_value_ty: &P<ast::Ty>) -> P<ast::Ty> {
+    match reader.types.get(sym) {
+        Some(&Some((_, ref ty))) => ty.clone(),
+        _ => symbol_value_ty.clone()
+    }
+}
+
+// Reads the input of the macro invocation, parses and builds a grammar.
+pub fn read_grammar<'a>(cx: &ExtCtxt, grammar_sp: codemap::Span, parser: &mut Parser, symbol_value_ty: &P<ast::Ty>
+    /* , tokens_enum: &'a P<ast::Item>, token_variants: &'a [P<ast::Variant>] */ ) -> GrammarDef {
+
+    let mut reader: ReaderState = ReaderState::new();
+
+        /*
+    // Parse the variants of the "tokens" enum.
+    for tv in token_variants.iter() {
+        debug!("    token (from enum): {}", tv);
+    }
+    */
+
+    // create_symbol_table()
+
+    // Add the well-known "error" symbol to the table.
+    {
+        let (_, bp) = reader.lookup_ref_mut("error", grammar_sp);
+        bp.class = SymClass::Terminal;
+    }
+
+    // read_declarations();
+
+    // now begins what was read_grammar();
+
+
+    // first, parse all tokens.
+
+    // let t_token = Token::Ident(Ident("token"), IdentStyle::Plain);
+
+    let mut goal_symbol: Option<uint> = None;
+
+    // The name of the shared token set that this grammar uses, if any.
+    let mut token_set: Option<String> = None;
+
+    let mut options = GrammarOptions {
+        lr_type: LrType::Lalr,
+        expect_sr: None,
+        expect_rr: None,
+        report_file: None,
+        dot_file: None,
+        html_file: None,
+        json_file: None,
+        tree: TreeKind::Actions,
+        arena: None,
+        codegen: CodegenOptions::new(),
+    };
+
+    read_items(cx, parser, &mut reader, &mut token_set, &mut options, &mut goal_symbol, &mut Vec::new());
+
     debug!("");
 
+    instantiate_templates(cx, parser, &mut reader);
+    let starts = add_start_rules(parser, &mut reader, &mut goal_symbol);
+    inline_rules(parser, &mut reader, goal_symbol);
+
     // Check that we found a goal state.  This check was in check_symbols().
     // Rebind 'goal_symbol' now that we know it exists.
     let goal_symbol = if let Some(goal) = goal_symbol { goal } else {
@@ -812,12 +2642,217 @@ pub fn read_grammar<'a>(grammar_sp: codemap::Span, parser: &mut Parser /* , toke
             parser.span_err(sym.span, "symbol was used but never defined");
         }
     }
+    // The rules of an expression build its list, and arrange it when it is complete, so they
+    // cannot be substituted into other rules, or make the nodes of a tree.
+    for expr in reader.exprs.iter() {
+        if reader.inline.contains(&expr.lhs_sym) {
+            parser.span_err(expr.span, "a non-terminal declared by %expr cannot be %inline");
+        }
+        if options.tree != TreeKind::Actions {
+            parser.span_err(expr.span, "a parser which builds a tree (%define tree) cannot use %expr");
+        }
+    }
+    // An inlined non-terminal has no rules left to reduce by.
+    for &(winner, loser, span) in reader.preferences.iter() {
+        if winner.iter().chain(loser.iter()).any(|s| reader.inline.contains(s)) {
+            parser.span_err(span, "a non-terminal named by %prefer cannot be %inline");
+        }
+    }
+    // A token without an external number is one that the external lexer never produces.
+    if reader.externals.len() != 0 {
+        for sym in range(0, reader.symbols.len()) {
+            if reader.symbols[sym].class == SymClass::Terminal && reader.symbols[sym].name.as_slice() != "error"
+                && !reader.externals.iter().any(|&(_, t)| t == sym) {
+                parser.span_warn(reader.symbols[sym].span, format!("token '{}' has no number in %external, so the external lexer cannot produce it", reader.symbols[sym].name).as_slice());
+            }
+        }
+    }
+    // Trivia is kept in the tree, beside the tokens that the rules use.
+    for &(sym, _, span) in reader.trivia.iter() {
+        if options.tree != TreeKind::Cst {
+            parser.span_err(span, "only a parser which builds a tree (%define tree cst) can use %trivia");
+        }
+        for rule in reader.rules.iter().filter(|rule| rule.rhs.contains(&sym)) {
+            parser.span_err(rule.span, format!("rule uses '{}', which is declared as trivia", reader.symbols[sym].name).as_slice());
+        }
+    }
+    parser.abort_if_errors();
+
+    // All of the errors that the builder can find have already been reported, with
+    // better spans, so any error here is a bug in the reader.
+    reader.build_rules();
+    let gram = match reader.builder.build() {
+        Ok(gram) => gram,
+        Err(errors) => {
+            for e in errors.iter() {
+                parser.span_err(grammar_sp, format!("{}", e).as_slice());
+            }
+            parser.abort_if_errors();
+            panic!();
+        }
+    };
+    ReaderState::print_grammar(&gram);
+
+    // A parser which builds a tree has no actions of its own; every rule makes a node.
+    if options.tree == TreeKind::Cst {
+        for action in reader.rule_actions.iter_mut().skip(PREDEFINED_RULES) {
+            if let Some(Action::Builtin(Builtin::StartRule)) = *action {
+                continue;
+            }
+            if let Some(span) = action.as_ref().and_then(|a| user_action_span(a)) {
+                parser.span_err(span, "a parser which builds a tree (%define tree cst) cannot have actions");
+            }
+            *action = Some(Action::Builtin(Builtin::TreeNode));
+        }
+        for ty in reader.types.iter() {
+            if let Some((variant, _)) = *ty {
+                parser.span_err(grammar_sp, format!("a parser which builds a tree (%define tree cst) cannot use %type (given for {})", variant.as_str()).as_slice());
+            }
+        }
+        reader.ebnf.clear();
+        parser.abort_if_errors();
+    }
+
+    // A parser which builds an AST has an enum for each non-terminal, and each rule makes a
+    // variant of it.
+    let ast_items = if options.tree == TreeKind::Ast {
+        let items = make_ast(cx, parser, &mut reader, symbol_value_ty, options.arena.as_ref().map(|a| a.as_slice()), grammar_sp);
+        parser.abort_if_errors();
+        items
+    }
+    else {
+        Vec::new()
+    };
+
+    // Give the non-terminals that were generated for EBNF operators their types.  Their variants
+    // of the value enum are named after them, e.g. "Expr_many" for "Expr*".
+    for &(sym, op, elem) in reader.ebnf.iter() {
+        let elem_ty = match reader.types.get(elem) {
+            Some(&Some((_, ref ty))) => ty.clone(),
+            _ => symbol_value_ty.clone()
+        };
+        let (ty, suffix) = match op {
+            Ebnf::Optional => (quote_ty!(cx, Option<$elem_ty>), "opt".to_string()),
+            Ebnf::Many => (quote_ty!(cx, Vec<$elem_ty>), "many".to_string()),
+            Ebnf::Many1 => (quote_ty!(cx, Vec<$elem_ty>), "many1".to_string()),
+            Ebnf::Separated(sep) => (quote_ty!(cx, Vec<$elem_ty>), format!("sep_{}", reader.symbols[sep].name)),
+        };
+        let variant = token::str_to_ident(format!("{}_{}", reader.symbols[elem].name, suffix).as_slice());
+        while reader.types.len() <= sym {
+            reader.types.push(None);
+        }
+        reader.types[sym] = Some((variant, ty));
+    }
+
+    // Each declared lexer state must have patterns, and should be entered by something.
+    for state in range(1, reader.lexer_states.len()) {
+        let (ref name, span) = reader.lexer_states[state];
+        if !reader.lex_modes.iter().any(|&(ref states, _)| states.contains(&state)) {
+            parser.span_err(span, format!("lexer state '{}' has no patterns", name).as_slice());
+        }
+        else if !reader.lex_modes.iter().any(|&(_, begin)| begin == Some(state))
+            && !reader.rules.iter().any(|rule| rule.begin == Some(state)) {
+            parser.span_warn(span, format!("nothing puts the lexer in state '{}'; use %begin after a pattern or a rule", name).as_slice());
+        }
+    }
+
+    // Give the lists of each expression their type, Vec<ExprItem<T>>, where T is the type of the
+    // expression.  Its operands must have the same type, since the value of an operand alone is
+    // the value of the expression.  The variant is named after the expression, e.g. "Expr_expr".
+    for i in range(0, reader.exprs.len()) {
+        let ty = reader_symbol_ty(&reader, reader.exprs[i].lhs_sym, symbol_value_ty);
+        let (operand, operand_span) = reader.exprs[i].operand;
+        let operand_ty = reader_symbol_ty(&reader, operand, symbol_value_ty);
+        if pprust::ty_to_string(&*ty) != pprust::ty_to_string(&*operand_ty) {
+            parser.span_err(operand_span, format!("the operands of '{}' must have its type, {}, but '{}' has the type {}",
+                reader.exprs[i].lhs, pprust::ty_to_string(&*ty), reader.symbols[operand].name, pprust::ty_to_string(&*operand_ty)).as_slice());
+        }
+        let list_ty = quote_ty!(cx, Vec<::racc_runtime::expr::ExprItem<$ty>>);
+        let variant = token::str_to_ident(format!("{}_expr", reader.exprs[i].lhs).as_slice());
+        for &sym in reader.exprs[i].lists.clone().iter() {
+            while reader.types.len() <= sym {
+                reader.types.push(None);
+            }
+            reader.types[sym] = Some((variant, list_ty.clone()));
+        }
+    }
     parser.abort_if_errors();
 
-    let map_to_packed = reader.pack_symbols(goal_symbol);
-    reader.pack_grammar(map_to_packed.as_slice(), goal_symbol);
-    ReaderState::print_grammar(&reader.gram);
+    // Map the types from reader symbols to grammar symbols.
+    let symbol_types: Vec<Option<(ast::Ident, P<ast::Ty>)>> = if reader.types.len() == 0 {
+        Vec::new()
+    }
+    else {
+        range(0, gram.nsyms).map(|s| {
+            match reader.symbol_table.get(gram.name[s].as_slice()) {
+                Some(&sym) if sym < reader.types.len() => reader.types[sym].clone(),
+                _ => None
+            }
+        }).collect()
+    };
+
+    let symbol_spans: Vec<Span> = range(0, gram.nsyms).map(|s| {
+        match reader.symbol_table.get(gram.name[s].as_slice()) {
+            Some(&sym) => reader.symbols[sym].span,
+            None => grammar_sp
+        }
+    }).collect();
+
+    let rule_spans: Vec<Span> = range(0, PREDEFINED_RULES).map(|_| grammar_sp)
+        .chain(reader.rules.iter().map(|rule| rule.span))
+        .collect();
+
+    let action_spans: Vec<Option<Span>> = reader.rule_actions.iter()
+        .map(|action| action.as_ref().and_then(|a| user_action_span(a)))
+        .collect();
+
+    let lex_rules = {
+        let symbols = &reader.symbols;
+        reader.lex_rules.iter().map(|&(sym, ref pattern, span)|
+            (sym.map(|sym| symbols[sym].name.clone()), pattern.clone(), span)).collect()
+    };
+
+    let lexer_states = LexerStates {
+        names: reader.lexer_states.iter().map(|&(ref name, _)| name.clone()).collect(),
+        patterns: reader.lex_modes.clone(),
+        rules: reader.rules.iter().enumerate()
+            .filter_map(|(i, rule)| rule.begin.map(|state| (PREDEFINED_RULES + i, state)))
+            .collect(),
+    };
 
-    (reader.gram, reader.rule_blocks, reader.rhs_binding)
+    let mut externals: Vec<(u32, String)> = reader.externals.iter().map(|&(n, sym)| (n, reader.symbols[sym].name.clone())).collect();
+    externals.sort_by(|&(a, _), &(b, _)| a.cmp(&b));
+
+    let trivia = reader.trivia.iter().map(|&(sym, newline, _)| (reader.symbols[sym].name.clone(), newline)).collect();
+
+    let keywords = reader.keyword_ident.map(|ident| {
+        let symbols = &reader.symbols;
+        (symbols[ident].name.clone(),
+         reader.keywords.iter().map(|&(sym, ref text, contextual)| (symbols[sym].name.clone(), text.clone(), contextual)).collect())
+    });
+
+    assert!(reader.rule_actions.len() == gram.nrules);
+    GrammarDef {
+        gram: gram,
+        actions: reader.rule_actions,
+        rhs_binding: reader.rhs_binding,
+        shared_tokens: token_set.is_some(),
+        options: options,
+        symbol_types: symbol_types,
+        ast_items: ast_items,
+        spans: GrammarSpans {
+            symbols: symbol_spans,
+            rules: rule_spans,
+            actions: action_spans,
+        },
+        starts: starts,
+        lex_rules: lex_rules,
+        lexer_states: lexer_states,
+        keywords: keywords,
+        trivia: trivia,
+        externals: externals,
+        parse_params: reader.parse_params,
+        exprs: reader.exprs,
+    }
 }
 
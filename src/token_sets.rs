@@ -0,0 +1,78 @@
+// Shared token declarations.
+//
+// A token set is declared once, with the grammar_tokens! macro:
+//
+//      grammar_tokens! { ExprTokens; NUM; PLUS; MINUS; LPAREN; RPAREN; }
+//
+// This emits a constant for each token, and an enum of the tokens named after the set (as a
+// grammar emits its Token enum), and records the set.  Any number of grammars may then
+// use the set, by writing "use ExprTokens;" in place of their own token declarations.  All of
+// those grammars assign the same values to the same tokens, so their parsers can be driven by
+// the same lexer.
+//
+// Token values are assigned exactly as GrammarBuilder assigns them: error is 256, and the
+// tokens of the set are numbered from 257, in order.  A grammar which uses a set defines the
+// tokens of the set first, in the same order, so it gets the same values.
+//
+// The sets are recorded in the compiler process, so a set must be declared (in source order)
+// before any grammar that uses it, within the same crate.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use syntax::ast;
+use syntax::ext::base::{ExtCtxt, MacResult, MacItems, DummyResult};
+use syntax::codemap::Span;
+use syntax::parse::token::Token;
+
+use output;
+
+// The value of the first token in a set.  (error is 256.)
+pub const FIRST_TOKEN_VALUE: u32 = 257;
+
+thread_local!(static TOKEN_SETS: RefCell<HashMap<String, Vec<String>>> = RefCell::new(HashMap::new()));
+
+// Returns the tokens of a set, in order, or None if no set has that name.
+pub fn find_token_set(name: &str) -> Option<Vec<String>> {
+    TOKEN_SETS.with(|sets| sets.borrow().get(name).map(|tokens| tokens.clone()))
+}
+
+// Expands grammar_tokens! { <set-name> ; <token> ; ... }
+pub fn expand_grammar_tokens(cx: &mut ExtCtxt, sp: Span, tts: &[ast::TokenTree]) -> Box<MacResult+'static> {
+    let mut parser = cx.new_parser_from_tts(tts);
+
+    let set_name = parser.parse_ident();
+    parser.expect(&Token::Semi);
+
+    let mut tokens: Vec<String> = Vec::new();
+    while parser.token != Token::Eof {
+        let token_span = parser.span;
+        let token = parser.parse_ident().as_str().to_string();
+        parser.expect(&Token::Semi);
+        if token.as_slice() == "error" || tokens.contains(&token) {
+            cx.span_err(token_span, "token is defined more than once");
+        }
+        tokens.push(token);
+    }
+
+    let set_name = set_name.as_str().to_string();
+    let already_defined = TOKEN_SETS.with(|sets| {
+        let mut sets = sets.borrow_mut();
+        if sets.contains_key(&set_name) {
+            true
+        }
+        else {
+            sets.insert(set_name.clone(), tokens.clone());
+            false
+        }
+    });
+    if already_defined {
+        cx.span_err(sp, format!("token set '{}' is defined more than once", set_name).as_slice());
+        return DummyResult::any(sp);
+    }
+
+    let values: Vec<(String, u32)> = tokens.into_iter().enumerate().map(|(i, t)| (t, FIRST_TOKEN_VALUE + i as u32)).collect();
+    let mut items = output::make_token_consts(cx, sp, values.as_slice());
+    items.extend(output::make_token_enum(cx, set_name.as_slice(), values.as_slice()).into_iter());
+    MacItems::new(items.into_iter())
+}
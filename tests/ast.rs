@@ -0,0 +1,76 @@
+// Tests of the traits generated for an AST (see "%define tree ast"), on trees built by hand.
+
+#![feature(phase)]
+
+#[phase(plugin, link)] extern crate racc;
+
+use std::any::Any;
+use racc::runtime::{ParserTables, Location};
+
+grammar! {
+    uint ctx;
+    i64;
+
+    %define tree ast;
+    %left PLUS;
+
+    NUM; PLUS; LPAREN; RPAREN;
+
+    Exprs : Expr*=items;
+
+    Expr : Expr=left PLUS Expr=right => Add
+         | NUM=value => Num
+         | LPAREN Expr=inner RPAREN => Paren;
+}
+
+// Doubles every number, in place.
+struct Doubler;
+
+impl VisitMut for Doubler {
+    fn visit_expr(&mut self, node: &mut Expr) {
+        if let Expr::Num(ref mut value) = *node {
+            *value *= 2;
+        }
+        self.walk_expr(node);
+    }
+}
+
+// Collects the numbers, in order.
+struct Numbers {
+    values: Vec<i64>,
+}
+
+impl Visit for Numbers {
+    fn visit_expr(&mut self, node: &Expr) {
+        if let Expr::Num(value) = *node {
+            self.values.push(value);
+        }
+        self.walk_expr(node);
+    }
+}
+
+// "1 + (2) 3"
+fn tree() -> Exprs {
+    Exprs::Exprs(vec![
+        Expr::Add(box Expr::Num(1), box Expr::Paren(box Expr::Num(2))),
+        Expr::Num(3),
+    ])
+}
+
+#[test]
+fn visit() {
+    let mut numbers = Numbers { values: Vec::new() };
+    numbers.visit_exprs(&tree());
+    assert_eq!(numbers.values, vec![1, 2, 3]);
+}
+
+#[test]
+fn visit_mut_changes_nodes_in_place() {
+    // Doubler reaches the numbers in the list, in the boxed children of Add, and in Paren.
+    let mut exprs = tree();
+    let mut doubler = Doubler;
+    doubler.visit_exprs(&mut exprs);
+    let mut numbers = Numbers { values: Vec::new() };
+    numbers.visit_exprs(&exprs);
+    assert_eq!(numbers.values, vec![2, 4, 6]);
+}